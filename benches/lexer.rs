@@ -0,0 +1,110 @@
+//! Measures the throughput of the lexer on generated English text, against
+//! a baseline stemming every word with a stemmer created for it, as the
+//! lexer used to.
+//!
+//! Run with `cargo bench --bench lexer`.
+
+use indexer::lexer::Lexer;
+use rust_stemmers::{Algorithm, Stemmer};
+
+use std::collections::HashSet;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// The size of the generated text, in bytes.
+const TEXT_BYTES: usize = 8 * 1024 * 1024;
+
+/// How many times each variant is run; the fastest run is reported.
+const RUNS: usize = 5;
+
+/// The words the text is made of, inflected so that stemming has work to do.
+const WORDS: [&str; 24] = [
+    "indexing",
+    "documents",
+    "searches",
+    "running",
+    "quickly",
+    "the",
+    "engines",
+    "stemmed",
+    "tokenizer",
+    "relational",
+    "conditional",
+    "happiness",
+    "generously",
+    "connection",
+    "connected",
+    "files",
+    "parsed",
+    "of",
+    "queries",
+    "ranking",
+    "postings",
+    "segments",
+    "merged",
+    "2024",
+];
+
+/// Generates `bytes` bytes of text from `WORDS`, in a fixed pseudo-random
+/// order.
+fn generate_text(bytes: usize) -> String {
+    let mut text = String::with_capacity(bytes + 16);
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    while text.len() < bytes {
+        // xorshift64
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        text.push_str(WORDS[(state % WORDS.len() as u64) as usize]);
+        text.push(if state.is_multiple_of(11) { '\n' } else { ' ' });
+    }
+    text
+}
+
+/// Runs `tokenize` `RUNS` times over `chars` and prints the throughput of
+/// the fastest run.
+///
+/// # Returns
+/// The duration of the fastest run.
+fn bench(name: &str, chars: &[char], tokenize: impl Fn(&[char]) -> Vec<String>) -> Duration {
+    let mut best = Duration::MAX;
+    let mut tokens = 0;
+    for _ in 0..RUNS {
+        let started = Instant::now();
+        tokens = black_box(tokenize(black_box(chars))).len();
+        best = best.min(started.elapsed());
+    }
+    let seconds = best.as_secs_f64();
+    println!(
+        "{name:<28} {:>8.1} ms {:>8.1} MB/s {:>8.2} M tokens/s",
+        seconds * 1000.0,
+        TEXT_BYTES as f64 / seconds / 1_000_000.0,
+        tokens as f64 / seconds / 1_000_000.0
+    );
+    best
+}
+
+fn main() {
+    let text = generate_text(TEXT_BYTES);
+    let chars = text.chars().collect::<Vec<char>>();
+    let stop_words = HashSet::new();
+
+    let baseline = bench("stemmer per token", &chars, |chars| {
+        Lexer::new(chars)
+            .with_stemmer(None)
+            .get_tokens(&stop_words)
+            .into_iter()
+            .map(|token| Stemmer::create(Algorithm::English).stem(&token).to_string())
+            .collect()
+    });
+    let shared = bench("shared stemmer", &chars, |chars| {
+        Lexer::new(chars).get_tokens(&stop_words)
+    });
+    bench("shared stemmer, index terms", &chars, |chars| {
+        Lexer::new(chars).get_index_tokens(&stop_words)
+    });
+    println!(
+        "shared stemmer speedup: {:.2}x",
+        baseline.as_secs_f64() / shared.as_secs_f64()
+    );
+}
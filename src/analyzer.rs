@@ -0,0 +1,712 @@
+use rust_stemmers::Algorithm;
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+use crate::code::split_identifiers;
+use crate::langdetect::detect_language;
+use crate::lexer::{Lexer, TokenFilter};
+use crate::placeholder::{collapse_placeholders, restore_placeholders};
+use crate::query::{BoolExpr, Clause, Query, phrase_term};
+use crate::stopwords::{StopWords, language_stop_words, no_stop_words};
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Prefix marking the character n-grams of a word in the index.
+pub const NGRAM_PREFIX: &str = "~";
+
+/// Prefix marking the words of a document's file name and directories in
+/// the index, kept apart from the words of its content.
+pub const PATH_PREFIX: &str = "@";
+
+/// Prefix marking the words of a document's title in the index.
+pub const TITLE_PREFIX: &str = "^";
+
+/// Prefix marking the words of a document's headings in the index.
+pub const HEADING_PREFIX: &str = "#";
+
+/// The prefixes of the fields other than the content of documents, whose
+/// terms plain query words also match.
+const FIELD_PREFIXES: [&str; 3] = [PATH_PREFIX, TITLE_PREFIX, HEADING_PREFIX];
+
+/// Returns `true` if `term` is a term of the path, title or heading field of
+/// documents rather than of their content.
+pub fn is_field_term(term: &str) -> bool {
+    FIELD_PREFIXES.iter().any(|prefix| term.starts_with(prefix))
+}
+
+/// The languages words can be stemmed and stop words filtered in.
+#[derive(
+    clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    Danish,
+    Dutch,
+    #[default]
+    English,
+    Finnish,
+    French,
+    German,
+    Hungarian,
+    Italian,
+    Norwegian,
+    Portuguese,
+    Romanian,
+    Russian,
+    Spanish,
+    Swedish,
+    Turkish,
+}
+
+impl Language {
+    /// Returns the Snowball stemming algorithm of the language.
+    pub fn stemmer(self) -> Algorithm {
+        match self {
+            Self::Danish => Algorithm::Danish,
+            Self::Dutch => Algorithm::Dutch,
+            Self::English => Algorithm::English,
+            Self::Finnish => Algorithm::Finnish,
+            Self::French => Algorithm::French,
+            Self::German => Algorithm::German,
+            Self::Hungarian => Algorithm::Hungarian,
+            Self::Italian => Algorithm::Italian,
+            Self::Norwegian => Algorithm::Norwegian,
+            Self::Portuguese => Algorithm::Portuguese,
+            Self::Romanian => Algorithm::Romanian,
+            Self::Russian => Algorithm::Russian,
+            Self::Spanish => Algorithm::Spanish,
+            Self::Swedish => Algorithm::Swedish,
+            Self::Turkish => Algorithm::Turkish,
+        }
+    }
+
+    /// Returns the ISO 639-1 code of the language, e.g. `de` for German.
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::Danish => "da",
+            Self::Dutch => "nl",
+            Self::English => "en",
+            Self::Finnish => "fi",
+            Self::French => "fr",
+            Self::German => "de",
+            Self::Hungarian => "hu",
+            Self::Italian => "it",
+            Self::Norwegian => "no",
+            Self::Portuguese => "pt",
+            Self::Romanian => "ro",
+            Self::Russian => "ru",
+            Self::Spanish => "es",
+            Self::Swedish => "sv",
+            Self::Turkish => "tr",
+        }
+    }
+
+    /// Returns the language of an ISO 639-1 code, ignoring case, or `None`
+    /// if it is not one words can be stemmed in.
+    pub fn from_code(code: &str) -> Option<Self> {
+        <Self as clap::ValueEnum>::value_variants()
+            .iter()
+            .copied()
+            .find(|language| language.code().eq_ignore_ascii_case(code))
+    }
+
+    /// Reads the stop word list of the language compiled into the
+    /// `stop_words` crate. Use `stopwords::language_stop_words` to get it as
+    /// a set read only once.
+    pub(crate) fn stop_word_list(self) -> Vec<String> {
+        use stop_words::LANGUAGE;
+        stop_words::get(match self {
+            Self::Danish => LANGUAGE::Danish,
+            Self::Dutch => LANGUAGE::Dutch,
+            Self::English => LANGUAGE::English,
+            Self::Finnish => LANGUAGE::Finnish,
+            Self::French => LANGUAGE::French,
+            Self::German => LANGUAGE::German,
+            Self::Hungarian => LANGUAGE::Hungarian,
+            Self::Italian => LANGUAGE::Italian,
+            Self::Norwegian => LANGUAGE::Norwegian,
+            Self::Portuguese => LANGUAGE::Portuguese,
+            Self::Romanian => LANGUAGE::Romanian,
+            Self::Russian => LANGUAGE::Russian,
+            Self::Spanish => LANGUAGE::Spanish,
+            Self::Swedish => LANGUAGE::Swedish,
+            Self::Turkish => LANGUAGE::Turkish,
+        })
+    }
+}
+
+/// The Unicode normalization form text is brought to before it is
+/// tokenized, so that the same word written with composed or decomposed
+/// code points is indexed once.
+#[derive(
+    clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Normalization {
+    /// Canonical composition: `e` followed by a combining acute accent
+    /// becomes `é`.
+    #[default]
+    Nfc,
+    /// Compatibility composition, which also folds ligatures, full-width
+    /// forms and the like into their plain equivalent: `ﬁ` becomes `fi`,
+    /// `Ａ` becomes `A`.
+    Nfkc,
+}
+
+impl Normalization {
+    /// Brings `text` to the normalization form. ASCII text is in every form
+    /// already.
+    pub fn apply(self, text: String) -> String {
+        if text.is_ascii() {
+            return text;
+        }
+        match self {
+            Self::Nfc => text.nfc().collect(),
+            Self::Nfkc => text.nfkc().collect(),
+        }
+    }
+}
+
+/// Tokenizer options of an index, persisted in its manifest so that queries
+/// are analyzed the same way as the indexed documents.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct AnalyzerSettings {
+    /// The Unicode normalization form of document and query text.
+    pub normalization: Normalization,
+    /// Strip diacritics so that `café` and `cafe` match each other.
+    pub fold_accents: bool,
+    /// Remember the original casing of words (e.g. `NASA`) for display,
+    /// while matching stays case-insensitive.
+    pub preserve_case: bool,
+    /// The language words are stemmed and stop words filtered in.
+    pub language: Language,
+    /// Reduce words to their stem so that `indexing` matches `indexed`.
+    pub stemming: bool,
+    /// Leave the stop words of `language` out of the index.
+    pub stop_words: bool,
+    /// Detect the language of every document and stem it and filter its
+    /// stop words in that language rather than `language`, which remains
+    /// the language of queries and of documents whose language is unclear.
+    pub detect_language: bool,
+    /// Also index the character n-grams of this length of every word, so
+    /// that parts of words match; `0` disables n-grams.
+    pub ngrams: usize,
+    /// Index punctuation characters as tokens of their own, as indexes
+    /// built before they were dropped did.
+    pub punctuation: bool,
+    /// Leave out the words and numbers shorter than this many characters;
+    /// `0` keeps them all.
+    pub min_token_len: usize,
+    /// Collapse timestamps, UUIDs and long hex strings into the `<ts>`,
+    /// `<uuid>` and `<hex>` placeholders, so that log lines don't fill the
+    /// index with unique tokens.
+    pub placeholders: bool,
+}
+
+impl Default for AnalyzerSettings {
+    fn default() -> Self {
+        Self {
+            normalization: Normalization::default(),
+            fold_accents: false,
+            preserve_case: false,
+            language: Language::default(),
+            stemming: true,
+            stop_words: true,
+            detect_language: false,
+            ngrams: 0,
+            punctuation: false,
+            min_token_len: 0,
+            placeholders: false,
+        }
+    }
+}
+
+impl AnalyzerSettings {
+    /// Returns the stemming algorithm to analyze with, if stemming is enabled.
+    pub fn stemmer(&self) -> Option<Algorithm> {
+        self.stemming.then(|| self.language.stemmer())
+    }
+
+    /// Returns the filter of the tokens left out of documents and queries.
+    pub fn token_filter(&self) -> TokenFilter {
+        TokenFilter {
+            punctuation: !self.punctuation,
+            min_len: self.min_token_len,
+        }
+    }
+
+    /// Returns the stop words to filter out, empty if disabled.
+    pub fn stop_words(&self) -> StopWords {
+        if self.stop_words {
+            language_stop_words(self.language)
+        } else {
+            no_stop_words()
+        }
+    }
+}
+
+/// Turns document and query text into index terms: case folding, optional
+/// accent folding, tokenizing, stemming and stop word removal.
+pub struct Analyzer {
+    /// The tokenizer options.
+    pub settings: AnalyzerSettings,
+    /// Words filtered out of documents and queries.
+    pub stop_words: StopWords,
+    /// The size of the chunks text documents are read and analyzed in, or
+    /// `None` to analyze them whole.
+    pub stream_chunk: Option<usize>,
+    /// The words abbreviations stand for, keyed by the normalized
+    /// abbreviation, e.g. `k8s` for `kubernetes`.
+    pub expansions: HashMap<String, String>,
+    /// The stems given to words instead of those of the stemmer, keyed by
+    /// the normalized word, e.g. `news` for `news` rather than `new`.
+    pub stem_exceptions: HashMap<String, String>,
+}
+
+impl Analyzer {
+    /// Creates a new `Analyzer`.
+    ///
+    /// # Arguments
+    /// * `settings` - The tokenizer options.
+    /// * `stop_words` - Words filtered out of documents and queries.
+    pub fn new(settings: AnalyzerSettings, stop_words: StopWords) -> Self {
+        Self {
+            settings,
+            stop_words,
+            stream_chunk: None,
+            expansions: HashMap::new(),
+            stem_exceptions: HashMap::new(),
+        }
+    }
+
+    /// Makes the analyzer add the terms of the expansion of every
+    /// abbreviation it meets, in documents and queries alike, so that `k8s`
+    /// and `kubernetes` find each other.
+    ///
+    /// # Arguments
+    /// * `expansions` - The expansions keyed by their abbreviation.
+    pub fn with_expansions(mut self, expansions: &BTreeMap<String, String>) -> Self {
+        self.expansions = expansions
+            .iter()
+            .map(|(abbreviation, expansion)| (self.normalize(abbreviation), expansion.clone()))
+            .collect();
+        self
+    }
+
+    /// Makes the analyzer stem the words of `exceptions` as they say
+    /// instead of with the stemmer, in documents and queries alike, to keep
+    /// apart words the stemmer conflates or join words it cannot.
+    ///
+    /// # Arguments
+    /// * `exceptions` - The stems keyed by their word.
+    pub fn with_stem_exceptions(mut self, exceptions: &BTreeMap<String, String>) -> Self {
+        self.stem_exceptions = exceptions
+            .iter()
+            .map(|(word, stem)| (self.normalize(word), self.normalize(stem)))
+            .collect();
+        self
+    }
+
+    /// Makes the parsers read and analyze text documents in chunks of about
+    /// `chunk_bytes` bytes instead of whole, to bound memory use.
+    pub fn with_stream_chunk(mut self, chunk_bytes: Option<usize>) -> Self {
+        self.stream_chunk = chunk_bytes;
+        self
+    }
+
+    /// Creates an analyzer with the same options, stop words and chunk size
+    /// but without stemming, for the documents indexed with their words as
+    /// written.
+    pub fn without_stemming(&self) -> Self {
+        let settings = AnalyzerSettings {
+            stemming: false,
+            ..self.settings
+        };
+        let mut analyzer =
+            Self::new(settings, self.stop_words.clone()).with_stream_chunk(self.stream_chunk);
+        analyzer.expansions = self.expansions.clone();
+        analyzer.stem_exceptions = self.stem_exceptions.clone();
+        analyzer
+    }
+
+    /// Creates an analyzer stemming words and filtering stop words in
+    /// `language` instead, for the documents detected to be written in it
+    /// and the queries restricted to them. Stop words overriding those of
+    /// the index language are kept, and display forms are recorded with
+    /// those of this analyzer.
+    ///
+    /// # Arguments
+    /// * `language` - The language to analyze text in.
+    pub fn in_language(&self, language: Language) -> Self {
+        let settings = AnalyzerSettings {
+            language,
+            ..self.settings
+        };
+        let stop_words = if Arc::ptr_eq(&self.stop_words, &self.settings.stop_words()) {
+            settings.stop_words()
+        } else {
+            Arc::clone(&self.stop_words)
+        };
+        Self {
+            settings,
+            stop_words,
+            stream_chunk: self.stream_chunk,
+            expansions: self.expansions.clone(),
+            stem_exceptions: self.stem_exceptions.clone(),
+        }
+    }
+
+    /// Detects the language of document text if the index detects
+    /// languages.
+    ///
+    /// # Arguments
+    /// * `text` - The document text, or its start.
+    ///
+    /// # Returns
+    /// The detected `Language`, or `None` if detection is disabled or the
+    /// language is unclear.
+    pub fn detect_language(&self, text: &str) -> Option<Language> {
+        if !self.settings.detect_language {
+            return None;
+        }
+        detect_language(text, self.settings.language)
+    }
+
+    /// Folds the case of `text`, brings it to the Unicode normalization
+    /// form of the index and folds its accents if enabled. Documents,
+    /// queries, abbreviations and display forms all go through here, so
+    /// that they are matched alike.
+    ///
+    /// # Arguments
+    /// * `text` - The text to normalize.
+    ///
+    /// # Returns
+    /// The normalized text.
+    pub fn normalize(&self, text: &str) -> String {
+        let folded = self.settings.normalization.apply(fold_case(text));
+        if self.settings.fold_accents {
+            fold_accents(&folded)
+        } else {
+            folded
+        }
+    }
+
+    /// Analyzes document text into index terms, emitting both the stemmed
+    /// and the exact form of every word, and the terms of the expansion of
+    /// every abbreviation.
+    ///
+    /// # Arguments
+    /// * `text` - The document text.
+    ///
+    /// # Returns
+    /// A `Vec` of index terms.
+    pub fn analyze(&self, text: &str) -> Vec<String> {
+        let normalized = self.normalize_tokens(text);
+        let chars = normalized.chars().collect::<Vec<char>>();
+        let mut tokens = self.lexer(&chars).get_index_tokens(&self.stop_words);
+        self.restore(&mut tokens);
+        tokens.append(&mut self.ngrams(&normalized));
+        tokens.append(&mut self.expansion_terms(&normalized));
+        tokens
+    }
+
+    /// Analyzes source code into index terms: every identifier as written
+    /// and, for `camelCase` ones, each of the words it is made of. Code is
+    /// never stemmed, so that identifiers are indexed as they are written
+    /// rather than mangled into stems.
+    ///
+    /// # Arguments
+    /// * `text` - The source code.
+    ///
+    /// # Returns
+    /// A `Vec` of index terms.
+    pub fn analyze_code(&self, text: &str) -> Vec<String> {
+        let normalized = self.normalize_tokens(&split_identifiers(text));
+        let chars = normalized.chars().collect::<Vec<char>>();
+        let mut tokens = Lexer::new(&chars)
+            .with_stemmer(None)
+            .with_filter(self.settings.token_filter())
+            .get_index_tokens(&self.stop_words);
+        self.restore(&mut tokens);
+        tokens.append(&mut self.ngrams(&normalized));
+        tokens.append(&mut self.expansion_terms(&normalized));
+        tokens
+    }
+
+    /// Normalizes `text` and, if enabled, collapses its timestamps, UUIDs
+    /// and long hex strings for `restore` to turn into placeholders once
+    /// tokenized.
+    fn normalize_tokens(&self, text: &str) -> String {
+        let normalized = self.normalize(text);
+        if self.settings.placeholders {
+            collapse_placeholders(&normalized)
+        } else {
+            normalized
+        }
+    }
+
+    /// Turns the tokens of text collapsed by `normalize_tokens` into
+    /// placeholders, e.g. `<uuid>`.
+    fn restore(&self, tokens: &mut [String]) {
+        if self.settings.placeholders {
+            restore_placeholders(tokens);
+        }
+    }
+
+    /// Returns the terms of the expansions of the abbreviations in
+    /// normalized `text`, stemmed like the words of a query.
+    ///
+    /// # Arguments
+    /// * `text` - The normalized text.
+    ///
+    /// # Returns
+    /// A `Vec` of terms, empty if the text holds no known abbreviation.
+    fn expansion_terms(&self, text: &str) -> Vec<String> {
+        if self.expansions.is_empty() {
+            return Vec::new();
+        }
+        let mut terms = Vec::new();
+        for word in text.split(|c: char| !c.is_alphanumeric()) {
+            if let Some(expansion) = self.expansions.get(word) {
+                let chars = self.normalize(expansion).chars().collect::<Vec<char>>();
+                terms.append(&mut self.lexer(&chars).get_tokens(&self.stop_words));
+            }
+        }
+        terms
+    }
+
+    /// Creates a lexer stemming words as configured.
+    fn lexer<'a>(&'a self, chars: &'a [char]) -> Lexer<'a> {
+        Lexer::new(chars)
+            .with_stemmer(self.settings.stemmer())
+            .with_stem_exceptions(&self.stem_exceptions)
+            .with_filter(self.settings.token_filter())
+    }
+
+    /// Returns the character n-grams of the words of normalized `text`,
+    /// prefixed with `NGRAM_PREFIX`, or nothing if n-grams are disabled.
+    /// Words shorter than the n-gram length are left out.
+    ///
+    /// # Arguments
+    /// * `text` - The normalized text.
+    ///
+    /// # Returns
+    /// A `Vec` of n-gram terms.
+    fn ngrams(&self, text: &str) -> Vec<String> {
+        let n = self.settings.ngrams;
+        if n == 0 {
+            return Vec::new();
+        }
+        let mut ngrams = Vec::new();
+        for word in text.split(|c: char| !c.is_alphanumeric()) {
+            if self.stop_words.contains(word) {
+                continue;
+            }
+            let chars = word.chars().collect::<Vec<char>>();
+            for window in chars.windows(n) {
+                ngrams.push(format!(
+                    "{NGRAM_PREFIX}{}",
+                    window.iter().collect::<String>()
+                ));
+            }
+        }
+        ngrams
+    }
+
+    /// Analyzes the path of a document into the terms of its path field: the
+    /// stemmed words of its file name and directories, prefixed with
+    /// `PATH_PREFIX`, each once.
+    ///
+    /// # Arguments
+    /// * `path` - The URI of the document.
+    ///
+    /// # Returns
+    /// A `Vec` of path terms.
+    pub fn path_terms(&self, path: &Path) -> Vec<String> {
+        let mut terms = Vec::new();
+        self.field_terms(PATH_PREFIX, &path.to_string_lossy(), &mut terms);
+        terms
+    }
+
+    /// Analyzes the title and the headings of a document into the terms of
+    /// its title and heading fields, prefixed with `TITLE_PREFIX` and
+    /// `HEADING_PREFIX`, each once.
+    ///
+    /// # Arguments
+    /// * `title` - The title of the document, if it has one.
+    /// * `headings` - The headings of the document.
+    ///
+    /// # Returns
+    /// A `Vec` of title and heading terms.
+    pub fn structure_terms(&self, title: Option<&str>, headings: &[String]) -> Vec<String> {
+        let mut terms = Vec::new();
+        if let Some(title) = title {
+            self.field_terms(TITLE_PREFIX, title, &mut terms);
+        }
+        for heading in headings {
+            self.field_terms(HEADING_PREFIX, heading, &mut terms);
+        }
+        terms
+    }
+
+    /// Adds the stemmed words of `text` to `terms` as terms of the field of
+    /// `prefix`, skipping those already there.
+    fn field_terms(&self, prefix: &str, text: &str, terms: &mut Vec<String>) {
+        let normalized = self.normalize(text);
+        let chars = normalized.chars().collect::<Vec<char>>();
+        for token in self.lexer(&chars).get_tokens(&self.stop_words) {
+            if !token.chars().any(char::is_alphanumeric) {
+                continue;
+            }
+            let term = format!("{prefix}{token}");
+            if !terms.contains(&term) {
+                terms.push(term);
+            }
+        }
+    }
+
+    /// Analyzes a parsed query into index terms. Phrases and `=` words are
+    /// matched exactly, bypassing stemming; filters produce no terms. A
+    /// phrase of several words becomes a single phrase term, matching only
+    /// where the words follow each other. Plain words also match the path,
+    /// title and heading fields of documents, and abbreviations their
+    /// expansion.
+    ///
+    /// # Arguments
+    /// * `query` - The parsed query.
+    ///
+    /// # Returns
+    /// A `Vec` of query terms.
+    pub fn analyze_query(&self, query: &Query) -> Vec<String> {
+        query
+            .clauses
+            .iter()
+            .flat_map(|clause| self.analyze_clause(clause))
+            .collect()
+    }
+
+    /// Analyzes the boolean filter of a parsed query, replacing each clause
+    /// by its index terms. Clauses without terms, e.g. stop words, are
+    /// dropped.
+    ///
+    /// # Arguments
+    /// * `query` - The parsed query.
+    ///
+    /// # Returns
+    /// The analyzed filter, or `None` if the query has none.
+    pub fn analyze_filter(&self, query: &Query) -> Option<BoolExpr<Vec<String>>> {
+        query.filter.as_ref()?.filter_map(&|clause| {
+            let terms = self.analyze_clause(clause);
+            (!terms.is_empty()).then_some(terms)
+        })
+    }
+
+    /// Analyzes a single clause of a query into index terms.
+    pub fn analyze_clause(&self, clause: &Clause) -> Vec<String> {
+        match clause {
+            Clause::Word(word) => {
+                let normalized = self.normalize_tokens(word);
+                let chars = normalized.chars().collect::<Vec<char>>();
+                let mut tokens = self.lexer(&chars).get_tokens(&self.stop_words);
+                self.restore(&mut tokens);
+                let mut field_terms = FIELD_PREFIXES
+                    .iter()
+                    .flat_map(|prefix| {
+                        tokens
+                            .iter()
+                            .filter(|token| token.chars().any(char::is_alphanumeric))
+                            .map(move |token| format!("{prefix}{token}"))
+                    })
+                    .collect();
+                tokens.append(&mut self.ngrams(&normalized));
+                tokens.append(&mut self.expansion_terms(&normalized));
+                tokens.append(&mut field_terms);
+                tokens
+            }
+            Clause::Exact(text) => {
+                let chars = self.normalize_tokens(text).chars().collect::<Vec<char>>();
+                let mut tokens = self.lexer(&chars).get_exact_tokens(&self.stop_words);
+                self.restore(&mut tokens);
+                tokens
+            }
+            Clause::Phrase(text) => {
+                let chars = self.normalize_tokens(text).chars().collect::<Vec<char>>();
+                let mut words = self.lexer(&chars).get_exact_tokens(&self.stop_words);
+                self.restore(&mut words);
+                if words.len() > 1 {
+                    vec![phrase_term(&words)]
+                } else {
+                    words
+                }
+            }
+            Clause::Language(_) | Clause::Tag(_) => Vec::new(),
+        }
+    }
+
+    /// Returns the index terms of a single word, its stem and its exact
+    /// form, without recording its casing.
+    ///
+    /// # Arguments
+    /// * `word` - The word.
+    ///
+    /// # Returns
+    /// A `Vec` of index terms, empty for stop words.
+    pub fn word_terms(&self, word: &str) -> Vec<String> {
+        let chars = self.normalize(word).chars().collect::<Vec<char>>();
+        self.lexer(&chars).get_index_tokens(&self.stop_words)
+    }
+
+    /// Remembers the original casing of every word of `text` containing an
+    /// uppercase character if the index preserves case. The first casing
+    /// seen for a word wins.
+    ///
+    /// # Arguments
+    /// * `text` - The document text.
+    /// * `forms` - The display forms of the document, keyed by the
+    ///   normalized word.
+    pub fn record_display_forms(&self, text: &str, forms: &mut HashMap<String, String>) {
+        if !self.settings.preserve_case {
+            return;
+        }
+        for word in text.split(|c: char| !c.is_alphanumeric()) {
+            if word.chars().any(|c| c.is_uppercase()) {
+                forms
+                    .entry(self.normalize(word))
+                    .or_insert_with(|| word.to_string());
+            }
+        }
+    }
+}
+
+/// The query prefix restricting a search to documents in one language,
+/// e.g. `lang:de`.
+pub const LANGUAGE_FILTER: &str = "lang:";
+
+/// The query prefix restricting a search to documents carrying a tag, e.g.
+/// `tag:project=alpha`.
+pub const TAG_FILTER: &str = "tag:";
+
+/// Folds the case of `text` with the full Unicode case folding rather than
+/// lowercasing, so that words differing only in case are equal where
+/// lowercasing keeps them apart: `STRASSE` and `Straße` both fold to
+/// `strasse`, and the final sigma of `ΟΔΟΣ` and `οδός` to `σ`.
+pub fn fold_case(text: &str) -> String {
+    if text.is_ascii() {
+        text.to_ascii_lowercase()
+    } else {
+        caseless::default_case_fold_str(text)
+    }
+}
+
+/// Removes diacritics from `text` by decomposing it and dropping the
+/// combining marks, e.g. `café` becomes `cafe`.
+pub fn fold_accents(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .nfc()
+        .collect()
+}
@@ -0,0 +1,403 @@
+use anyhow::Context;
+
+use crate::crypto::EncryptionError;
+use crate::logger::Logger;
+use crate::observer::IndexingObserver;
+use crate::offload::{Offloaded, offload};
+use crate::query::{EmptyQueryError, QuerySyntaxError};
+use crate::scoring::Ranker;
+use crate::skip::SkipRules;
+use crate::store::is_index;
+use crate::tree::{CompactSummary, CorruptIndexError};
+use crate::{
+    Config, ErrorHandler, SearchOptions, SearchResults, compact_segments, count_term,
+    delete_documents, index_documents, search_term,
+};
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The errors of the library API.
+#[derive(Debug)]
+pub enum Error {
+    /// The builder was not given the directory of the index.
+    MissingIndexDir,
+    /// No index was committed in the directory yet.
+    IndexNotFound(PathBuf),
+    /// The file or directory to index doesn't exist.
+    PathNotFound(PathBuf),
+    /// The query is malformed.
+    QuerySyntax(QuerySyntaxError),
+    /// The query leaves nothing to search for, e.g. only stop words.
+    EmptyQuery(EmptyQueryError),
+    /// The index files cannot be read back, e.g. a truncated postings list.
+    CorruptIndex(anyhow::Error),
+    /// Reading or writing the index or the indexed documents failed.
+    Io(anyhow::Error),
+    /// The index cannot be encrypted or decrypted, e.g. without its key
+    /// file or with another one.
+    Encryption(anyhow::Error),
+    /// Indexing or searching failed for another reason. Like the other
+    /// failures, it displays its own message and keeps its causes as the
+    /// `source` chain.
+    Index(anyhow::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingIndexDir => write!(f, "no index directory was given"),
+            Self::IndexNotFound(dir) => write!(f, "no index was found in {dir:?}"),
+            Self::PathNotFound(path) => write!(f, "{path:?} does not exist"),
+            Self::QuerySyntax(err) => write!(f, "{err}"),
+            Self::EmptyQuery(err) => write!(f, "{err}"),
+            Self::CorruptIndex(err) | Self::Io(err) | Self::Encryption(err) | Self::Index(err) => {
+                write!(f, "{err}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::QuerySyntax(err) => Some(err),
+            Self::EmptyQuery(err) => Some(err),
+            Self::CorruptIndex(err) | Self::Io(err) | Self::Encryption(err) | Self::Index(err) => {
+                err.source()
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    /// Keeps the query errors apart from the failures of the index, which
+    /// are told apart by the first of their causes that is typed.
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<QuerySyntaxError>() {
+            Ok(err) => return Self::QuerySyntax(err),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<EmptyQueryError>() {
+            Ok(err) => return Self::EmptyQuery(err),
+            Err(err) => err,
+        };
+        if caused_by::<EncryptionError>(&err) {
+            Self::Encryption(err)
+        } else if caused_by::<CorruptIndexError>(&err) || caused_by::<bincode2::Error>(&err) {
+            Self::CorruptIndex(err)
+        } else if caused_by::<io::Error>(&err) {
+            Self::Io(err)
+        } else {
+            Self::Index(err)
+        }
+    }
+}
+
+/// Returns whether `err` or one of its causes is a `T`.
+fn caused_by<T: std::error::Error + Send + Sync + 'static>(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<T>().is_some() || err.chain().any(|cause| cause.is::<T>())
+}
+
+/// The result of the operations of the library API.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// An index opened through the library API, handing out the `IndexWriter`
+/// and `IndexReader` of the index:
+///
+/// ```no_run
+/// use indexer::api::Indexer;
+///
+/// let indexer = Indexer::builder().index_dir("notes-index").hidden(true).build()?;
+/// indexer.writer()?.index("notes")?;
+/// for (path, score) in indexer.reader()?.search("rust AND lifetimes")?.hits {
+///     println!("{score:.2}: {path:?}");
+/// }
+/// # Ok::<(), indexer::api::Error>(())
+/// ```
+#[derive(Clone)]
+pub struct Indexer {
+    /// The directory containing the index files.
+    index_dir: PathBuf,
+    /// Index hidden files and directories.
+    hidden: bool,
+    /// Follow symbolic links while discovering documents.
+    follow_symlinks: bool,
+    /// The rules excluding paths from indexing.
+    skip: SkipRules,
+    /// The ranker persisted as the index default, if any.
+    ranker: Option<Ranker>,
+    /// The key file of an encrypted index, if any.
+    key_file: Option<PathBuf>,
+    /// Where the messages of indexing runs are written.
+    log: ErrorHandler,
+    /// Told about indexing runs as they go, if any.
+    observer: Option<Arc<dyn IndexingObserver>>,
+}
+
+impl Indexer {
+    /// Returns a builder of an `Indexer`, given the index directory at
+    /// least.
+    pub fn builder() -> IndexerBuilder {
+        IndexerBuilder::default()
+    }
+
+    /// Returns the directory containing the index files.
+    pub fn index_dir(&self) -> &Path {
+        &self.index_dir
+    }
+
+    /// Returns a handle adding documents to the index and removing them,
+    /// creating the index at its first run. Only one writer may use an index
+    /// at a time.
+    ///
+    /// # Returns
+    /// The `IndexWriter`; building it doesn't fail yet.
+    pub fn writer(&self) -> Result<IndexWriter> {
+        Ok(IndexWriter {
+            indexer: self.clone(),
+        })
+    }
+
+    /// Returns a handle searching the last commit of the index, with the
+    /// default `SearchOptions`.
+    ///
+    /// # Returns
+    /// The `IndexReader`, or `Error::IndexNotFound` if nothing was committed
+    /// to the index yet.
+    pub fn reader(&self) -> Result<IndexReader> {
+        if !is_index(&self.index_dir) {
+            return Err(Error::IndexNotFound(self.index_dir.clone()));
+        }
+        Ok(IndexReader {
+            index_dir: self.index_dir.clone(),
+            options: SearchOptions {
+                key_file: self.key_file.clone(),
+                ..SearchOptions::default()
+            },
+        })
+    }
+}
+
+/// Builds an `Indexer`, see `Indexer::builder`.
+#[derive(Clone, Default)]
+pub struct IndexerBuilder {
+    index_dir: Option<PathBuf>,
+    hidden: bool,
+    follow_symlinks: bool,
+    skip: SkipRules,
+    ranker: Option<Ranker>,
+    key_file: Option<PathBuf>,
+    log: Option<ErrorHandler>,
+    observer: Option<Arc<dyn IndexingObserver>>,
+}
+
+impl IndexerBuilder {
+    /// Sets the directory containing the index files, created by the first
+    /// indexing run.
+    pub fn index_dir(mut self, index_dir: impl Into<PathBuf>) -> Self {
+        self.index_dir = Some(index_dir.into());
+        self
+    }
+
+    /// Indexes hidden files and directories if `true`.
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Follows symbolic links while discovering documents if `true`,
+    /// rather than skip them.
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Sets the rules excluding paths from indexing.
+    pub fn skip(mut self, skip: SkipRules) -> Self {
+        self.skip = skip;
+        self
+    }
+
+    /// Sets the ranker persisted as the default of the index.
+    pub fn ranker(mut self, ranker: Ranker) -> Self {
+        self.ranker = Some(ranker);
+        self
+    }
+
+    /// Sets the key file encrypting the index.
+    pub fn key_file(mut self, key_file: impl Into<PathBuf>) -> Self {
+        self.key_file = Some(key_file.into());
+        self
+    }
+
+    /// Sets where the messages of indexing runs, e.g. the documents that
+    /// failed to parse, are written: to `stderr` unless set.
+    pub fn log(mut self, log: ErrorHandler) -> Self {
+        self.log = Some(log);
+        self
+    }
+
+    /// Sets the observer told about indexing runs as they go, e.g. to show
+    /// their progress. Runs are silent without one.
+    pub fn observer(mut self, observer: Arc<dyn IndexingObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Builds the `Indexer`.
+    ///
+    /// # Returns
+    /// The `Indexer`, or `Error::MissingIndexDir` if no index directory was
+    /// given.
+    pub fn build(self) -> Result<Indexer> {
+        Ok(Indexer {
+            index_dir: self.index_dir.ok_or(Error::MissingIndexDir)?,
+            hidden: self.hidden,
+            follow_symlinks: self.follow_symlinks,
+            skip: self.skip,
+            ranker: self.ranker,
+            key_file: self.key_file,
+            log: self.log.unwrap_or(ErrorHandler::Stderr),
+            observer: self.observer,
+        })
+    }
+}
+
+/// Adds documents to an index and removes them, see `Indexer::writer`.
+/// Every operation is committed before returning.
+pub struct IndexWriter {
+    indexer: Indexer,
+}
+
+impl IndexWriter {
+    /// Indexes the documents at `path`, a file or a directory: new and
+    /// modified documents are indexed and those removed since the last run
+    /// dropped from the index.
+    ///
+    /// # Arguments
+    /// * `path` - The file or directory to index.
+    ///
+    /// # Returns
+    /// `Ok(())` once committed, `Error::PathNotFound` if `path` doesn't
+    /// exist, or one of the failures of the index, e.g. `Error::Io`, if the
+    /// run failed.
+    pub fn index(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Err(Error::PathNotFound(path.to_path_buf()));
+        }
+        let indexer = &self.indexer;
+        fs::create_dir_all(&indexer.index_dir)
+            .with_context(|| format!("create index dir {:?}", indexer.index_dir))?;
+        let (logger, writer) = Logger::spawn(indexer.log.clone());
+        let mut cfg = Config::new(path.to_path_buf(), indexer.index_dir.clone(), logger);
+        cfg.hidden = indexer.hidden;
+        cfg.error_handler = indexer.log.clone();
+        cfg.follow_symlinks = indexer.follow_symlinks;
+        cfg.skip = indexer.skip.clone();
+        cfg.ranker = indexer.ranker;
+        cfg.key_file = indexer.key_file.clone();
+        cfg.observer = indexer.observer.clone();
+
+        let result = index_documents(&cfg);
+        cfg.logger.stop();
+        let _ = writer.join();
+        Ok(result?)
+    }
+
+    /// Indexes the documents at `path` like `index`, without blocking the
+    /// async task awaiting the run.
+    ///
+    /// # Returns
+    /// The `Offloaded` future resolving to the outcome of `index`.
+    pub fn index_async(&self, path: impl Into<PathBuf>) -> Offloaded<Result<()>> {
+        let writer = IndexWriter {
+            indexer: self.indexer.clone(),
+        };
+        let path = path.into();
+        offload(move || writer.index(path))
+    }
+
+    /// Removes the documents at `path`, a file or a directory, from the
+    /// index, whether or not they still exist.
+    ///
+    /// # Returns
+    /// The paths of the documents removed, or one of the failures of the
+    /// index, e.g. `Error::Io`.
+    pub fn delete(&self, path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let indexer = &self.indexer;
+        Ok(delete_documents(
+            &indexer.index_dir,
+            path.as_ref(),
+            indexer.key_file.as_deref(),
+        )?)
+    }
+
+    /// Merges the small segments of the index, or all of them if `all`, and
+    /// drops the documents left without postings.
+    ///
+    /// # Returns
+    /// The `CompactSummary`, or one of the failures of the index, e.g.
+    /// `Error::CorruptIndex`.
+    pub fn compact(&self, all: bool) -> Result<CompactSummary> {
+        let indexer = &self.indexer;
+        Ok(compact_segments(
+            &indexer.index_dir,
+            all,
+            indexer.key_file.as_deref(),
+        )?)
+    }
+}
+
+/// Searches the last commit of an index, see `Indexer::reader`.
+#[derive(Clone)]
+pub struct IndexReader {
+    index_dir: PathBuf,
+    options: SearchOptions,
+}
+
+impl IndexReader {
+    /// Searches with `options`, e.g. a ranker, boosts or filters, rather
+    /// than the default ones. The key file of the `Indexer` is kept unless
+    /// `options` has one.
+    pub fn with_options(mut self, options: SearchOptions) -> Self {
+        let key_file = self.options.key_file.take();
+        self.options = options;
+        self.options.key_file = self.options.key_file.take().or(key_file);
+        self
+    }
+
+    /// Searches the index for `query`, e.g. `rust AND "memory safety"`.
+    ///
+    /// # Returns
+    /// The `SearchResults`, `Error::QuerySyntax` or `Error::EmptyQuery` if
+    /// the query cannot be searched, or one of the failures of the index,
+    /// e.g. `Error::Encryption`.
+    pub fn search(&self, query: &str) -> Result<SearchResults> {
+        Ok(search_term(query, &self.index_dir, &self.options)?)
+    }
+
+    /// Searches the index for `query` like `search`, without blocking the
+    /// async task awaiting the results.
+    ///
+    /// # Returns
+    /// The `Offloaded` future resolving to the outcome of `search`.
+    pub fn search_async(&self, query: impl Into<String>) -> Offloaded<Result<SearchResults>> {
+        let reader = self.clone();
+        let query = query.into();
+        offload(move || reader.search(&query))
+    }
+
+    /// Counts the documents matching `query` without ranking them.
+    ///
+    /// # Returns
+    /// The number of matches, or the errors of `search`.
+    pub fn count(&self, query: &str) -> Result<u64> {
+        Ok(count_term(query, &self.index_dir, &self.options)?)
+    }
+}
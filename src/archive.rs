@@ -0,0 +1,299 @@
+use anyhow::{Context, anyhow};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::backup::{BackupObject, IndexFiles, hash_file, hex_digest, with_committed_files};
+use crate::manifest::MANIFEST_FILE;
+use crate::publish::CURRENT_LINK;
+use crate::tar::{ArchiveContent, TarWriter, for_each_entry, open_archive};
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Component, Path};
+use std::time::SystemTime;
+
+/// The first entry of an index archive, describing it.
+pub const ARCHIVE_INFO_FILE: &str = "indexer-archive.json";
+
+/// The version of the layout of index archives, raised when archives made
+/// by a newer indexer can no longer be imported by older ones.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// The description of an index archive, written as its first entry.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ArchiveInfo {
+    /// The version of the layout of the archive.
+    pub version: u32,
+    /// The version of the indexer that made the archive.
+    pub indexer_version: String,
+    /// The generation of the archived index.
+    pub generation: u64,
+    /// When the archive was made, in seconds since the Unix epoch.
+    pub created: u64,
+    /// The files of the index directory, keyed by their path within it.
+    pub files: BTreeMap<String, BackupObject>,
+}
+
+/// What an archive holds.
+#[derive(Debug, Default)]
+pub struct ArchiveSummary {
+    /// The generation of the archived index.
+    pub generation: u64,
+    /// The number of files of the index.
+    pub files: usize,
+    /// The total size of the files of the index, before compression.
+    pub bytes: u64,
+}
+
+/// Packages the last committed generation of the index in `index_dir`, or
+/// of the index published in it, into one gzip compressed tar archive, to
+/// be copied to another machine and unpacked with `import_archive`.
+/// Indexing may go on meanwhile; the archive starts over when a commit
+/// changes the index.
+///
+/// Encrypted indexes stay encrypted: their key file is not archived.
+///
+/// # Arguments
+/// * `index_dir` - The directory containing the index files.
+/// * `output` - The archive file to write, e.g. `dump.tar.gz`.
+///
+/// # Returns
+/// The `ArchiveSummary`, or an `anyhow::Result` error if the index cannot
+/// be read or the archive cannot be written.
+pub fn archive_index(index_dir: &Path, output: &Path) -> anyhow::Result<ArchiveSummary> {
+    with_committed_files(index_dir, |index_dir, files| {
+        write_archive(index_dir, files, output)
+    })
+}
+
+/// Writes the files of one generation of an index into an archive: its
+/// description first, then the segments, then the other files with the
+/// manifest last.
+fn write_archive(
+    index_dir: &Path,
+    files: &IndexFiles,
+    output: &Path,
+) -> anyhow::Result<ArchiveSummary> {
+    let mut info = ArchiveInfo {
+        version: ARCHIVE_VERSION,
+        indexer_version: env!("CARGO_PKG_VERSION").to_string(),
+        generation: files.generation,
+        created: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        files: BTreeMap::new(),
+    };
+    for name in &files.segments {
+        let path = index_dir.join(name);
+        let (hash, size) = hash_file(&path).with_context(|| format!("read {path:?}"))?;
+        info.files.insert(name.clone(), BackupObject { hash, size });
+    }
+    for (name, content) in &files.metadata {
+        let object = BackupObject {
+            hash: hex_digest(Sha256::digest(content).as_slice()),
+            size: content.len() as u64,
+        };
+        info.files.insert(name.clone(), object);
+    }
+
+    let file = File::create(output).with_context(|| format!("create {output:?}"))?;
+    let mut tar = TarWriter::new(GzEncoder::new(BufWriter::new(file), Compression::default()));
+    let content = serde_json::to_vec_pretty(&info).context("serialize archive info")?;
+    tar.append(ARCHIVE_INFO_FILE, content.len() as u64, content.as_slice())?;
+    for name in &files.segments {
+        let path = index_dir.join(name);
+        let file = File::open(&path).with_context(|| format!("open {path:?}"))?;
+        tar.append(name, info.files[name].size, file)
+            .with_context(|| format!("archive {path:?}"))?;
+    }
+    // The manifest is the last of the metadata files
+    for (name, content) in &files.metadata {
+        tar.append(name, content.len() as u64, content.as_slice())?;
+    }
+    tar.finish()?
+        .finish()
+        .context("compress archive")?
+        .flush()
+        .context("write archive")?;
+
+    Ok(ArchiveSummary {
+        generation: info.generation,
+        files: info.files.len(),
+        bytes: info.files.values().map(|object| object.size).sum(),
+    })
+}
+
+/// Returns `true` if `path` is an index archive made by `archive_index`,
+/// rather than a document dump of another search engine.
+pub fn is_index_archive(path: &Path) -> bool {
+    let Ok(ArchiveContent::Tar(reader)) = open_archive(path) else {
+        return false;
+    };
+    let mut first = None;
+    let _ = for_each_entry(reader, |name, _, _| {
+        first = Some(name.to_string());
+        // Stops at the first entry
+        Err(anyhow!("done"))
+    });
+    first.as_deref() == Some(ARCHIVE_INFO_FILE)
+}
+
+/// Unpacks an index archive made by `archive_index` into `index_dir`, which
+/// must not hold an index yet: import into a new directory and
+/// `indexer publish` it to replace a live index. Every file is checked
+/// against the hash recorded in the archive, and the manifest is written
+/// last.
+///
+/// # Arguments
+/// * `input` - The archive file.
+/// * `index_dir` - The directory to unpack the index into.
+///
+/// # Returns
+/// The `ArchiveSummary`, or an `anyhow::Result` error if the archive is
+/// corrupt, incomplete or made by a newer indexer.
+pub fn import_archive(input: &Path, index_dir: &Path) -> anyhow::Result<ArchiveSummary> {
+    if index_dir.join(MANIFEST_FILE).exists()
+        || fs::symlink_metadata(index_dir.join(CURRENT_LINK)).is_ok()
+    {
+        return Err(anyhow!(
+            "{index_dir:?} already holds an index; import into another directory and publish it"
+        ));
+    }
+    let ArchiveContent::Tar(reader) = open_archive(input)? else {
+        return Err(anyhow!("{input:?} is not an index archive"));
+    };
+    fs::create_dir_all(index_dir).with_context(|| format!("create {index_dir:?}"))?;
+
+    let mut info: Option<ArchiveInfo> = None;
+    let mut summary = ArchiveSummary::default();
+    // Without its manifest, a partially imported index is not opened
+    let mut manifest = None;
+    for_each_entry(reader, |name, size, content| {
+        let Some(info) = &info else {
+            if name != ARCHIVE_INFO_FILE {
+                return Err(anyhow!("{input:?} is not an index archive"));
+            }
+            let read: ArchiveInfo =
+                serde_json::from_reader(content).context("parse archive info")?;
+            if read.version > ARCHIVE_VERSION {
+                return Err(anyhow!(
+                    "the archive was made by indexer {} in a newer format (version {}); \
+                     upgrade to import it",
+                    read.indexer_version,
+                    read.version
+                ));
+            }
+            summary.generation = read.generation;
+            info = Some(read);
+            return Ok(());
+        };
+        let object = info
+            .files
+            .get(name)
+            .ok_or_else(|| anyhow!("archive holds the unlisted file {name:?}"))?;
+        let relative = Path::new(name);
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(anyhow!("archive holds the unsafe path {name:?}"));
+        }
+        if size != object.size {
+            return Err(anyhow!(
+                "{name:?} is {size} bytes long, expected {}",
+                object.size
+            ));
+        }
+        if name == MANIFEST_FILE {
+            let mut content_bytes = Vec::new();
+            content
+                .read_to_end(&mut content_bytes)
+                .context("read manifest")?;
+            check_hash(name, object, Sha256::digest(&content_bytes).as_slice())?;
+            manifest = Some(content_bytes);
+        } else {
+            let path = index_dir.join(relative);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).with_context(|| format!("create {parent:?}"))?;
+            }
+            unpack(content, name, object, &path)?;
+        }
+        summary.files += 1;
+        summary.bytes += object.size;
+        Ok(())
+    })
+    .with_context(|| format!("import {input:?}"))?;
+
+    let info = info.ok_or_else(|| anyhow!("{input:?} is not an index archive"))?;
+    if summary.files != info.files.len() {
+        return Err(anyhow!(
+            "{input:?} is incomplete: {} of {} files",
+            summary.files,
+            info.files.len()
+        ));
+    }
+    let manifest = manifest.ok_or_else(|| anyhow!("{input:?} holds no manifest"))?;
+    fs::write(index_dir.join(MANIFEST_FILE), manifest).context("write manifest")?;
+    Ok(summary)
+}
+
+/// Writes the content of an archived file into `path`, checking its hash
+/// before renaming it into place.
+fn unpack(
+    content: &mut dyn Read,
+    name: &str,
+    object: &BackupObject,
+    path: &Path,
+) -> anyhow::Result<()> {
+    let mut staged = path.to_path_buf();
+    staged.as_mut_os_string().push(".tmp");
+    let file = File::create(&staged).with_context(|| format!("create {staged:?}"))?;
+    let mut writer = HashingWriter {
+        inner: BufWriter::new(file),
+        hasher: Sha256::new(),
+    };
+    io::copy(content, &mut writer).with_context(|| format!("unpack {name:?}"))?;
+    let hash = writer.hasher.finalize();
+    if let Err(err) = check_hash(name, object, hash.as_slice()) {
+        let _ = fs::remove_file(&staged);
+        return Err(err);
+    }
+    writer
+        .inner
+        .into_inner()
+        .map_err(|err| err.into_error())
+        .and_then(|file| file.sync_all())
+        .with_context(|| format!("write {staged:?}"))?;
+    fs::rename(&staged, path).with_context(|| format!("replace {path:?}"))
+}
+
+/// Checks the hash of an archived file against the one recorded for it.
+fn check_hash(name: &str, object: &BackupObject, digest: &[u8]) -> anyhow::Result<()> {
+    let hash = hex_digest(digest);
+    if hash != object.hash {
+        return Err(anyhow!("{name:?} is corrupt: it hashes to {hash}"));
+    }
+    Ok(())
+}
+
+/// A writer hashing what it writes.
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
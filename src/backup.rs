@@ -0,0 +1,551 @@
+use anyhow::{Context, anyhow};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::manifest::{MANIFEST_FILE, Manifest};
+use crate::publish::{CURRENT_LINK, resolve_index};
+#[cfg(feature = "s3")]
+use crate::s3::S3Bucket;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// The files of an index directory, besides its manifest and segments, that
+/// are part of a backup. Reports, logs and checkpoints of unfinished runs
+/// are left out.
+const INDEX_FILES: [&str; 5] = [
+    "docstore.bin",
+    "display.bin",
+    "stats.bin",
+    "matched.bin",
+    "evicted.bin",
+];
+
+/// How many times a backup starts over while commits change the index, and
+/// how long it waits between tries.
+const BACKUP_RETRIES: u32 = 20;
+const BACKUP_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// The size of the chunks objects are copied in.
+const COPY_BUFFER_SIZE: usize = 256 * 1024;
+
+/// Where the backups of an index are stored: a directory, e.g. on a NAS
+/// mount, or a bucket of an S3 compatible object store. Both hold the same
+/// layout: the files of the index as `objects/<sha256>`, each backed up
+/// generation as `snapshots/<generation>.json` listing its files, and the
+/// latest generation in `latest`. Files already uploaded by a previous
+/// backup are not uploaded again.
+pub enum BackupTarget {
+    /// A local directory.
+    Directory(PathBuf),
+    /// A bucket, and the prefix of the objects within it.
+    #[cfg(feature = "s3")]
+    S3 { bucket: S3Bucket, prefix: String },
+}
+
+impl BackupTarget {
+    /// Parses a backup location: `s3://<bucket>[/<prefix>]`, available when
+    /// built with the `s3` feature, or a directory, optionally as a
+    /// `file://` URL.
+    ///
+    /// # Arguments
+    /// * `spec` - The location given by the user.
+    ///
+    /// # Returns
+    /// The `BackupTarget`, or an `anyhow::Result` error if the location is
+    /// not supported.
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        if let Some(rest) = spec.strip_prefix("s3://") {
+            #[cfg(feature = "s3")]
+            {
+                let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+                if bucket.is_empty() {
+                    return Err(anyhow!("{spec:?} names no bucket"));
+                }
+                return Ok(Self::S3 {
+                    bucket: S3Bucket::from_env(bucket)?,
+                    prefix: prefix.trim_matches('/').to_string(),
+                });
+            }
+            #[cfg(not(feature = "s3"))]
+            {
+                let _ = rest;
+                return Err(anyhow!(
+                    "S3 backups need indexer to be built with `--features s3`"
+                ));
+            }
+        }
+        let path = spec.strip_prefix("file://").unwrap_or(spec);
+        if path.contains("://") {
+            return Err(anyhow!("unsupported backup location {spec:?}"));
+        }
+        Ok(Self::Directory(PathBuf::from(path)))
+    }
+
+    /// Returns `true` if the target holds the object `name`.
+    fn exists(&self, name: &str) -> anyhow::Result<bool> {
+        match self {
+            Self::Directory(dir) => Ok(dir.join(name).exists()),
+            #[cfg(feature = "s3")]
+            Self::S3 { bucket, prefix } => bucket.exists(&object_key(prefix, name)),
+        }
+    }
+
+    /// Streams the content of the object `name`.
+    fn get(&self, name: &str) -> anyhow::Result<Box<dyn Read + Send>> {
+        match self {
+            Self::Directory(dir) => {
+                let path = dir.join(name);
+                let file = File::open(&path).with_context(|| format!("open {path:?}"))?;
+                Ok(Box::new(file))
+            }
+            #[cfg(feature = "s3")]
+            Self::S3 { bucket, prefix } => bucket.get(&object_key(prefix, name)),
+        }
+    }
+
+    /// Stores `body` as the object `name`, replacing any previous one.
+    /// Objects of a directory are written to a temporary file renamed into
+    /// place, so that an interrupted backup leaves no partial object.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the object.
+    /// * `body` - The content of the object.
+    /// * `size` - The length of the content in bytes.
+    fn put(&self, name: &str, mut body: impl Read, size: u64) -> anyhow::Result<()> {
+        match self {
+            Self::Directory(dir) => {
+                let path = dir.join(name);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).with_context(|| format!("create {parent:?}"))?;
+                }
+                let mut staged = path.clone();
+                staged.as_mut_os_string().push(".tmp");
+                let mut file =
+                    File::create(&staged).with_context(|| format!("create {staged:?}"))?;
+                let copied =
+                    io::copy(&mut body, &mut file).with_context(|| format!("write {staged:?}"))?;
+                if copied != size {
+                    let _ = fs::remove_file(&staged);
+                    return Err(anyhow!("{name} changed while it was copied"));
+                }
+                file.sync_all()
+                    .with_context(|| format!("write {staged:?}"))?;
+                fs::rename(&staged, &path).with_context(|| format!("replace {path:?}"))
+            }
+            #[cfg(feature = "s3")]
+            Self::S3 { bucket, prefix } => bucket.put(&object_key(prefix, name), body, size),
+        }
+    }
+}
+
+impl fmt::Display for BackupTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Directory(dir) => write!(f, "{}", dir.display()),
+            #[cfg(feature = "s3")]
+            Self::S3 { bucket, prefix } => write!(f, "s3://{}/{prefix}", bucket.name()),
+        }
+    }
+}
+
+/// Returns the key of the object `name` under `prefix` in a bucket.
+#[cfg(feature = "s3")]
+fn object_key(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{prefix}/{name}")
+    }
+}
+
+/// The files of one backed up generation of an index.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BackupSnapshot {
+    /// The generation of the index.
+    pub generation: u64,
+    /// When the backup was made, in seconds since the Unix epoch.
+    pub created: u64,
+    /// The files of the index directory, keyed by their path within it.
+    pub files: BTreeMap<String, BackupObject>,
+}
+
+/// A file of a backed up index.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackupObject {
+    /// The hex encoded SHA-256 hash of the file, naming its object.
+    pub hash: String,
+    /// The size of the file in bytes.
+    pub size: u64,
+}
+
+/// What a backup uploaded.
+#[derive(Debug, Default)]
+pub struct BackupSummary {
+    /// The generation of the index backed up.
+    pub generation: u64,
+    /// The number of files of the index.
+    pub files: usize,
+    /// The total size of the files of the index.
+    pub bytes: u64,
+    /// The number of files uploaded, the others being already stored.
+    pub uploaded: usize,
+    /// The size of the files uploaded.
+    pub uploaded_bytes: u64,
+}
+
+impl BackupSummary {
+    /// Counts a file of the index, and its upload if it was uploaded.
+    fn record(&mut self, object: &BackupObject, uploaded: bool) {
+        self.files += 1;
+        self.bytes += object.size;
+        if uploaded {
+            self.uploaded += 1;
+            self.uploaded_bytes += object.size;
+        }
+    }
+}
+
+/// What a restore wrote.
+#[derive(Debug, Default)]
+pub struct RestoreSummary {
+    /// The generation of the index restored.
+    pub generation: u64,
+    /// The number of files restored.
+    pub files: usize,
+    /// The total size of the files restored.
+    pub bytes: u64,
+}
+
+/// The files of one committed generation of an index: its manifest and the
+/// files it was read with, and the files of its committed segments, which
+/// are never changed once written.
+pub(crate) struct IndexFiles {
+    pub(crate) generation: u64,
+    /// The manifest and the other files of `INDEX_FILES`, read into memory.
+    pub(crate) metadata: Vec<(String, Vec<u8>)>,
+    /// The paths of the segment files within the index directory.
+    pub(crate) segments: Vec<String>,
+}
+
+impl IndexFiles {
+    /// Reads the files of the index in `index_dir`.
+    ///
+    /// # Returns
+    /// The `IndexFiles`, `None` if a commit ran meanwhile, or an
+    /// `anyhow::Result` error if the index cannot be read.
+    fn read(index_dir: &Path) -> anyhow::Result<Option<Self>> {
+        let manifest_path = index_dir.join(MANIFEST_FILE);
+        let raw = fs::read(&manifest_path).with_context(|| format!("read {manifest_path:?}"))?;
+        let manifest: Manifest = serde_json::from_slice(&raw).context("parse manifest")?;
+        if manifest.committing {
+            return Ok(None);
+        }
+        let mut metadata = Vec::new();
+        for name in INDEX_FILES {
+            match fs::read(index_dir.join(name)) {
+                Ok(content) => metadata.push((name.to_string(), content)),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err).with_context(|| format!("read {name}")),
+            }
+        }
+        let segment_dirs = match &manifest.segments {
+            Some(segments) => segments.iter().map(|id| format!("segment_{id}")).collect(),
+            // Indexes committed before the segments were listed search them all
+            None => fs::read_dir(index_dir)
+                .context("list index directory")?
+                .filter_map(Result::ok)
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .filter(|name| name.starts_with("segment_"))
+                .collect::<Vec<String>>(),
+        };
+        let mut segments = Vec::new();
+        for dir in segment_dirs {
+            let entries = match fs::read_dir(index_dir.join(&dir)) {
+                Ok(entries) => entries,
+                // Retired by a merge committed meanwhile
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(err) => return Err(err).with_context(|| format!("list {dir}")),
+            };
+            for entry in entries.filter_map(Result::ok) {
+                if entry.file_type().is_ok_and(|kind| kind.is_file()) {
+                    segments.push(format!("{dir}/{}", entry.file_name().to_string_lossy()));
+                }
+            }
+        }
+        segments.sort();
+        // Nothing read belongs to another generation if the manifest is unchanged
+        if fs::read(&manifest_path).ok().as_ref() != Some(&raw) {
+            return Ok(None);
+        }
+        metadata.push((MANIFEST_FILE.to_string(), raw));
+        Ok(Some(Self {
+            generation: manifest.generation,
+            metadata,
+            segments,
+        }))
+    }
+}
+
+/// Backs up a consistent snapshot of the index in `index_dir`, or of the
+/// index published in it: the files of its last committed generation. Only
+/// the files not stored by a previous backup are uploaded. Indexing may go
+/// on meanwhile; the backup starts over when a commit changes the index.
+///
+/// Encrypted indexes stay encrypted: their key file is not backed up.
+///
+/// # Arguments
+/// * `index_dir` - The directory containing the index files.
+/// * `target` - Where the backup is stored.
+///
+/// # Returns
+/// The `BackupSummary`, or an `anyhow::Result` error if the index cannot
+/// be read or the target cannot be written.
+pub fn backup_index(index_dir: &Path, target: &BackupTarget) -> anyhow::Result<BackupSummary> {
+    with_committed_files(index_dir, |index_dir, files| {
+        upload_files(index_dir, files, target)
+    })
+}
+
+/// Runs `copy` on the files of the last committed generation of the index
+/// in `index_dir`, or of the index published in it, starting over when a
+/// commit changes the index meanwhile.
+///
+/// # Arguments
+/// * `index_dir` - The directory containing the index files.
+/// * `copy` - Copies the files, given the resolved index directory.
+///
+/// # Returns
+/// The result of `copy`, or an `anyhow::Result` error if the index cannot
+/// be read, `copy` fails on an unchanged index, or the index keeps changing.
+pub(crate) fn with_committed_files<T>(
+    index_dir: &Path,
+    mut copy: impl FnMut(&Path, &IndexFiles) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let index_dir = resolve_index(index_dir);
+    for _ in 0..BACKUP_RETRIES {
+        let Some(files) = IndexFiles::read(&index_dir)? else {
+            thread::sleep(BACKUP_RETRY_DELAY);
+            continue;
+        };
+        match copy(&index_dir, &files) {
+            Ok(copied) => return Ok(copied),
+            Err(err) => {
+                // A segment may have been retired by a merge committed
+                // during the copy
+                let changed = IndexFiles::read(&index_dir)?
+                    .is_none_or(|now| now.generation != files.generation);
+                if !changed {
+                    return Err(err);
+                }
+                thread::sleep(BACKUP_RETRY_DELAY);
+            }
+        }
+    }
+    Err(anyhow!(
+        "{index_dir:?} kept changing while copying it; try again once indexing is done"
+    ))
+}
+
+/// Uploads the files of one generation of an index, then the snapshot
+/// listing them, then marks it as the latest.
+fn upload_files(
+    index_dir: &Path,
+    files: &IndexFiles,
+    target: &BackupTarget,
+) -> anyhow::Result<BackupSummary> {
+    let mut summary = BackupSummary {
+        generation: files.generation,
+        ..Default::default()
+    };
+    let mut snapshot = BackupSnapshot {
+        generation: files.generation,
+        created: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        files: BTreeMap::new(),
+    };
+    for name in &files.segments {
+        let path = index_dir.join(name);
+        let (hash, size) = hash_file(&path).with_context(|| format!("read {path:?}"))?;
+        let object = BackupObject { hash, size };
+        let uploaded = upload_object(target, &object, || {
+            File::open(&path).with_context(|| format!("open {path:?}"))
+        })?;
+        summary.record(&object, uploaded);
+        snapshot.files.insert(name.clone(), object);
+    }
+    for (name, content) in &files.metadata {
+        let object = BackupObject {
+            hash: hex_digest(Sha256::digest(content).as_slice()),
+            size: content.len() as u64,
+        };
+        let uploaded = upload_object(target, &object, || Ok(content.as_slice()))?;
+        summary.record(&object, uploaded);
+        snapshot.files.insert(name.clone(), object);
+    }
+
+    let content = serde_json::to_vec_pretty(&snapshot).context("serialize backup snapshot")?;
+    target.put(
+        &format!("snapshots/{}.json", snapshot.generation),
+        content.as_slice(),
+        content.len() as u64,
+    )?;
+    let latest = snapshot.generation.to_string();
+    target.put("latest", latest.as_bytes(), latest.len() as u64)?;
+    Ok(summary)
+}
+
+/// Uploads the object of a file unless the target already holds it.
+///
+/// # Arguments
+/// * `target` - Where the backup is stored.
+/// * `object` - The hash and size of the file.
+/// * `open` - Opens the content of the file.
+///
+/// # Returns
+/// `true` if the object was uploaded, or an `anyhow::Result` error.
+fn upload_object<R: Read>(
+    target: &BackupTarget,
+    object: &BackupObject,
+    open: impl FnOnce() -> anyhow::Result<R>,
+) -> anyhow::Result<bool> {
+    let key = format!("objects/{}", object.hash);
+    if target.exists(&key)? {
+        return Ok(false);
+    }
+    target.put(&key, open()?, object.size)?;
+    Ok(true)
+}
+
+/// Restores a backed up index into `index_dir`, which must not hold an
+/// index yet: restore into a new directory and `indexer publish` it to
+/// replace a live index. Every file is checked against its hash, and the
+/// manifest is written last.
+///
+/// # Arguments
+/// * `source` - Where the backup is stored.
+/// * `index_dir` - The directory to restore the index into.
+/// * `generation` - The generation to restore, or `None` for the latest.
+///
+/// # Returns
+/// The `RestoreSummary`, or an `anyhow::Result` error if the backup cannot
+/// be read or is corrupt.
+pub fn restore_index(
+    source: &BackupTarget,
+    index_dir: &Path,
+    generation: Option<u64>,
+) -> anyhow::Result<RestoreSummary> {
+    if index_dir.join(MANIFEST_FILE).exists()
+        || fs::symlink_metadata(index_dir.join(CURRENT_LINK)).is_ok()
+    {
+        return Err(anyhow!(
+            "{index_dir:?} already holds an index; restore into another directory and publish it"
+        ));
+    }
+    let generation = match generation {
+        Some(generation) => generation,
+        None => {
+            let mut latest = String::new();
+            source
+                .get("latest")
+                .with_context(|| format!("find the latest backup in {source}"))?
+                .read_to_string(&mut latest)
+                .context("read latest backup")?;
+            latest
+                .trim()
+                .parse()
+                .with_context(|| format!("parse latest backup {latest:?}"))?
+        }
+    };
+    let snapshot: BackupSnapshot = serde_json::from_reader(
+        source
+            .get(&format!("snapshots/{generation}.json"))
+            .with_context(|| format!("find generation {generation} in {source}"))?,
+    )
+    .context("parse backup snapshot")?;
+
+    fs::create_dir_all(index_dir).with_context(|| format!("create {index_dir:?}"))?;
+    let mut summary = RestoreSummary {
+        generation: snapshot.generation,
+        ..Default::default()
+    };
+    // Without its manifest, a partially restored index is not opened
+    let (manifest, files): (Vec<_>, Vec<_>) = snapshot
+        .files
+        .iter()
+        .partition(|(name, _)| name.as_str() == MANIFEST_FILE);
+    for (name, object) in files.into_iter().chain(manifest) {
+        let relative = Path::new(name);
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(anyhow!("backup holds the unsafe path {name:?}"));
+        }
+        let path = index_dir.join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("create {parent:?}"))?;
+        }
+        download(source, object, &path)?;
+        summary.files += 1;
+        summary.bytes += object.size;
+    }
+    Ok(summary)
+}
+
+/// Downloads an object into `path`, checking its size and hash before
+/// renaming it into place.
+fn download(source: &BackupTarget, object: &BackupObject, path: &Path) -> anyhow::Result<()> {
+    let mut reader = source.get(&format!("objects/{}", object.hash))?;
+    let mut staged = path.to_path_buf();
+    staged.as_mut_os_string().push(".tmp");
+    let mut file = File::create(&staged).with_context(|| format!("create {staged:?}"))?;
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    let mut buffer = vec![0; COPY_BUFFER_SIZE];
+    loop {
+        let read = reader
+            .read(&mut buffer)
+            .with_context(|| format!("download object {}", object.hash))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        file.write_all(&buffer[..read])
+            .with_context(|| format!("write {staged:?}"))?;
+        size += read as u64;
+    }
+    let hash = hex_digest(hasher.finalize().as_slice());
+    if hash != object.hash || size != object.size {
+        let _ = fs::remove_file(&staged);
+        return Err(anyhow!(
+            "object {} is corrupt: got {size} bytes hashing to {hash}",
+            object.hash
+        ));
+    }
+    file.sync_all()
+        .with_context(|| format!("write {staged:?}"))?;
+    fs::rename(&staged, path).with_context(|| format!("replace {path:?}"))
+}
+
+/// Hashes a file in chunks rather than whole.
+///
+/// # Returns
+/// The hex encoded SHA-256 hash of the file and its size in bytes.
+pub(crate) fn hash_file(path: &Path) -> io::Result<(String, u64)> {
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok((hex_digest(hasher.finalize().as_slice()), size))
+}
+
+/// Formats a digest as lowercase hex.
+pub(crate) fn hex_digest(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
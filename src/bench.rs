@@ -0,0 +1,368 @@
+use anyhow::{Context, anyhow};
+use serde::Serialize;
+
+use crate::analyzer::is_field_term;
+use crate::lexer::exact_word;
+use crate::observer::{IndexingObserver, RunStats, SilentObserver};
+use crate::sample::XorShift;
+use crate::temp::temp_path;
+use crate::tree::MainIndex;
+use crate::{Config, SearchOptions, index_documents, search_term_shared};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The number of distinct words of a synthetic corpus.
+const SYNTHETIC_VOCABULARY: usize = 50_000;
+
+/// The number of documents per directory of a synthetic corpus.
+const SYNTHETIC_DIR_DOCS: usize = 1000;
+
+/// The syllables the words of a synthetic corpus are made of. They all end
+/// with a vowel the stemmer leaves alone, so that the words are indexed as
+/// they are written.
+const SYLLABLES: [&str; 8] = ["ka", "lo", "mi", "nu", "ra", "so", "ti", "vu"];
+
+/// The number of queries picked from the index when none are given.
+const DEFAULT_QUERY_COUNT: usize = 10;
+
+/// A corpus of generated plain text documents in the system temp directory,
+/// removed with everything in it when dropped. The words of the documents
+/// follow Zipf's law, like those of natural language: a few words are very
+/// frequent and most of them are rare.
+pub struct SyntheticCorpus {
+    /// The directory holding the documents.
+    path: PathBuf,
+}
+
+impl SyntheticCorpus {
+    /// Generates a corpus of `docs` documents of `words` words each.
+    ///
+    /// # Arguments
+    /// * `docs` - The number of documents.
+    /// * `words` - The number of words of each document.
+    /// * `seed` - The seed of the random words; the same seed generates the
+    ///   same corpus.
+    ///
+    /// # Returns
+    /// The `SyntheticCorpus`, or an `anyhow::Result` error if the documents
+    /// cannot be written.
+    pub fn generate(docs: usize, words: usize, seed: u64) -> anyhow::Result<Self> {
+        let corpus = Self { path: temp_path() };
+        fs::create_dir_all(&corpus.path).context("create synthetic corpus dir")?;
+        let mut rng = XorShift::new(seed);
+        for doc in 0..docs {
+            let dir = corpus.path.join(format!("{:04}", doc / SYNTHETIC_DIR_DOCS));
+            if doc % SYNTHETIC_DIR_DOCS == 0 {
+                fs::create_dir_all(&dir).context("create synthetic corpus dir")?;
+            }
+            let mut text = String::with_capacity(words * 8);
+            for i in 0..words {
+                if i > 0 {
+                    text.push(if i % 12 == 0 { '\n' } else { ' ' });
+                }
+                text.push_str(&synthetic_word(zipf_rank(&mut rng)));
+            }
+            text.push('\n');
+            let path = dir.join(format!("doc-{doc:07}.txt"));
+            fs::write(&path, &text).with_context(|| format!("write {path:?}"))?;
+        }
+        Ok(corpus)
+    }
+
+    /// Returns the directory holding the documents.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for SyntheticCorpus {
+    /// Removes the directory of the corpus.
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Returns a random rank of the synthetic vocabulary, rank `k` being drawn
+/// about `1/k` as often as the first one.
+fn zipf_rank(rng: &mut XorShift) -> usize {
+    let uniform = (rng.next() >> 11) as f64 / (1u64 << 53) as f64;
+    ((SYNTHETIC_VOCABULARY as f64).powf(uniform) as usize).saturating_sub(1)
+}
+
+/// Returns the synthetic word of `rank`, at least two syllables long so
+/// that no word is a stop word.
+fn synthetic_word(rank: usize) -> String {
+    let mut n = rank + SYLLABLES.len();
+    let mut word = String::new();
+    while n > 0 {
+        word.push_str(SYLLABLES[n % SYLLABLES.len()]);
+        n /= SYLLABLES.len();
+    }
+    word
+}
+
+/// The latency of the sample queries of a benchmark.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct QueryLatency {
+    /// The number of searches timed.
+    pub searches: u64,
+    /// The average number of matches of a search.
+    pub mean_matches: f64,
+    /// The average latency, in microseconds.
+    pub mean_us: u64,
+    /// The median latency, in microseconds.
+    pub p50_us: u64,
+    /// The latency 95% of the searches were faster than, in microseconds.
+    pub p95_us: u64,
+    /// The latency 99% of the searches were faster than, in microseconds.
+    pub p99_us: u64,
+    /// The latency of the slowest search, in microseconds.
+    pub max_us: u64,
+}
+
+impl QueryLatency {
+    /// Summarizes the latencies of the searches.
+    ///
+    /// # Arguments
+    /// * `latencies` - How long each search took.
+    /// * `matches` - The number of matches of every search together.
+    fn new(mut latencies: Vec<Duration>, matches: u64) -> Self {
+        if latencies.is_empty() {
+            return Self::default();
+        }
+        latencies.sort();
+        let percentile = |p: f64| {
+            let rank = ((latencies.len() as f64 * p).ceil() as usize).max(1);
+            latencies[rank.min(latencies.len()) - 1].as_micros() as u64
+        };
+        let searches = latencies.len() as u64;
+        Self {
+            searches,
+            mean_matches: matches as f64 / searches as f64,
+            mean_us: (latencies.iter().sum::<Duration>() / searches as u32).as_micros() as u64,
+            p50_us: percentile(0.50),
+            p95_us: percentile(0.95),
+            p99_us: percentile(0.99),
+            max_us: percentile(1.0),
+        }
+    }
+}
+
+/// The outcome of a benchmark: how fast a corpus was indexed and how fast
+/// the index answers queries.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct BenchReport {
+    /// The file or directory indexed.
+    pub source: PathBuf,
+    /// The number of documents indexed.
+    pub docs: u64,
+    /// The size of the documents indexed, in bytes.
+    pub bytes: u64,
+    /// How long indexing took, in milliseconds.
+    pub indexing_ms: u64,
+    /// The number of segments written to disk while indexing.
+    pub flushes: u64,
+    /// The peak resident memory of the process by the end of indexing, in
+    /// bytes, if the platform tells it.
+    pub peak_memory: Option<u64>,
+    /// The sample queries searched.
+    pub queries: Vec<String>,
+    /// The latency of the sample queries.
+    pub latency: QueryLatency,
+}
+
+impl BenchReport {
+    /// Returns the indexing throughput in megabytes per second.
+    pub fn megabytes_per_sec(&self) -> f64 {
+        self.bytes as f64 / 1_000.0 / self.indexing_ms.max(1) as f64
+    }
+
+    /// Returns the indexing throughput in documents per second.
+    pub fn docs_per_sec(&self) -> f64 {
+        self.docs as f64 * 1_000.0 / self.indexing_ms.max(1) as f64
+    }
+}
+
+/// Counts the segments flushed by an indexing run and keeps its
+/// `RunStats`, telling the observer of the run about it as well.
+struct BenchObserver {
+    /// The observer of the run, if any.
+    inner: Option<Arc<dyn IndexingObserver>>,
+    /// The number of segments flushed.
+    flushes: AtomicU64,
+    /// The stats of the run once finished.
+    stats: Mutex<Option<RunStats>>,
+}
+
+impl BenchObserver {
+    fn inner(&self) -> &dyn IndexingObserver {
+        match &self.inner {
+            Some(observer) => observer.as_ref(),
+            None => &SilentObserver,
+        }
+    }
+}
+
+impl IndexingObserver for BenchObserver {
+    fn on_notice(&self, message: &str) {
+        self.inner().on_notice(message);
+    }
+
+    fn on_warning(&self, message: &str) {
+        self.inner().on_warning(message);
+    }
+
+    fn on_start(&self, total: u64, discovered: u64) {
+        self.inner().on_start(total, discovered);
+    }
+
+    fn on_file_start(&self, path: &Path) {
+        self.inner().on_file_start(path);
+    }
+
+    fn on_file_done(&self, path: &Path) {
+        self.inner().on_file_done(path);
+    }
+
+    fn on_flush(&self, segment_id: u64) {
+        self.flushes.fetch_add(1, Ordering::Relaxed);
+        self.inner().on_flush(segment_id);
+    }
+
+    fn on_finish(&self, stats: &RunStats) {
+        *self.stats.lock().unwrap() = Some(stats.clone());
+        self.inner().on_finish(stats);
+    }
+}
+
+/// Indexes `cfg.filepath` into the index at `cfg.index_path`, normally a
+/// new temporary one, then searches the index for each query `rounds`
+/// times, measuring the indexing throughput, the segments flushed, the peak
+/// memory and the latency of the queries.
+///
+/// # Arguments
+/// * `cfg` - The indexing `Config`. Its observer is still told about the
+///   run.
+/// * `queries` - The sample queries, or none to pick words of the index
+///   across the range of their document frequencies.
+/// * `options` - The `SearchOptions` of the queries.
+/// * `rounds` - How many times each query is searched.
+///
+/// # Returns
+/// The `BenchReport`, or an `anyhow::Result` error if indexing or a search
+/// fails.
+pub fn run_bench(
+    cfg: &mut Config,
+    queries: Vec<String>,
+    options: &SearchOptions,
+    rounds: usize,
+) -> anyhow::Result<BenchReport> {
+    let observer = Arc::new(BenchObserver {
+        inner: cfg.observer.take(),
+        flushes: AtomicU64::new(0),
+        stats: Mutex::new(None),
+    });
+    cfg.observer = Some(observer.clone());
+    let started = Instant::now();
+    let indexed = index_documents(cfg);
+    let indexing = started.elapsed();
+    cfg.observer = observer.inner.clone();
+    indexed.context("index corpus")?;
+    let peak_memory = peak_memory();
+    let stats = observer.stats.lock().unwrap().take().unwrap_or_default();
+
+    let mut main_index = MainIndex::open_read_only(&cfg.index_path, cfg.key_file.as_deref())
+        .context("open main index")?;
+    options.apply(&mut main_index)?;
+    let queries = if queries.is_empty() {
+        sample_queries(&main_index)?
+    } else {
+        queries
+    };
+    if queries.is_empty() {
+        return Err(anyhow!("the corpus has no words to search for"));
+    }
+
+    // A first round warms the caches up, untimed
+    for query in &queries {
+        search_term_shared(&main_index, query, options)
+            .with_context(|| format!("search {query:?}"))?;
+    }
+    let mut latencies = Vec::with_capacity(queries.len() * rounds);
+    let mut matches = 0;
+    for _ in 0..rounds {
+        for query in &queries {
+            let started = Instant::now();
+            let results = search_term_shared(&main_index, query, options)?;
+            latencies.push(started.elapsed());
+            matches += results.total;
+        }
+    }
+
+    Ok(BenchReport {
+        source: cfg.filepath.clone(),
+        docs: stats.indexed,
+        bytes: stats.bytes,
+        indexing_ms: indexing.as_millis() as u64,
+        flushes: observer.flushes.load(Ordering::Relaxed),
+        peak_memory,
+        queries,
+        latency: QueryLatency::new(latencies, matches),
+    })
+}
+
+/// Picks words of the content of the documents across the range of their
+/// document frequencies, from the most frequent to rare ones, and pairs of
+/// them, so that the queries match many documents as well as few. The
+/// ranks picked grow geometrically, as most words of a corpus are rare.
+///
+/// # Returns
+/// The queries, or an `anyhow::Result` error if the index cannot be read.
+fn sample_queries(main_index: &MainIndex) -> anyhow::Result<Vec<String>> {
+    let mut terms = main_index
+        .global_document_frequencies()
+        .context("document frequencies")?
+        .into_iter()
+        .filter(|(term, _)| {
+            !is_field_term(term)
+                && exact_word(term).is_none()
+                && term.chars().all(char::is_alphanumeric)
+        })
+        .collect::<Vec<(String, u64)>>();
+    terms.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    let count = DEFAULT_QUERY_COUNT.min(terms.len());
+    let mut ranks = (0..count)
+        .map(|i| ((terms.len() as f64).powf(i as f64 / count as f64) as usize).saturating_sub(1))
+        .collect::<Vec<usize>>();
+    ranks.dedup();
+    let words = ranks
+        .into_iter()
+        .map(|rank| terms[rank].0.clone())
+        .collect::<Vec<String>>();
+    let pairs = words
+        .iter()
+        .zip(words.iter().rev())
+        .take(words.len() / 2)
+        .map(|(frequent, rare)| format!("{frequent} {rare}"));
+    Ok(words.iter().cloned().chain(pairs).collect())
+}
+
+/// Returns the peak resident memory of the process so far, in bytes, read
+/// from `/proc/self/status`; `None` on platforms without it.
+fn peak_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes = line
+        .trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
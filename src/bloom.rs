@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+
+/// The file of a segment holding the bloom filter of its terms.
+pub const BLOOM_FILE: &str = "terms.bloom";
+
+/// The bits of a filter per term it holds, giving about 1% of false
+/// positives with `HASHES` hashes.
+const BITS_PER_TERM: usize = 10;
+
+/// The number of bits set per term.
+const HASHES: u32 = 7;
+
+/// A bloom filter of the terms of a segment, kept next to its dictionary so
+/// that searches skip the segments that cannot hold any of the query terms
+/// without loading their dictionary. Terms are hashed with FNV-1a, which
+/// unlike the hasher of the standard library is stable across builds.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BloomFilter {
+    /// The number of bits set per term.
+    hashes: u32,
+    /// The bits of the filter.
+    bits: Vec<u64>,
+}
+
+impl BloomFilter {
+    /// Creates an empty filter sized for `terms` terms.
+    pub fn with_capacity(terms: usize) -> Self {
+        let words = (terms * BITS_PER_TERM).div_ceil(64).max(1);
+        Self {
+            hashes: HASHES,
+            bits: vec![0; words],
+        }
+    }
+
+    /// Adds `term` to the filter.
+    pub fn insert(&mut self, term: &str) {
+        for bit in self.bit_indexes(term) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Returns `false` if `term` was definitely not added to the filter, and
+    /// `true` if it may have been.
+    pub fn may_contain(&self, term: &str) -> bool {
+        self.bit_indexes(term)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Returns the bits of `term`, derived from two hashes by double hashing.
+    fn bit_indexes(&self, term: &str) -> impl Iterator<Item = usize> + use<> {
+        let len = (self.bits.len() * 64) as u64;
+        let first = fnv1a(term.as_bytes(), FNV_OFFSET);
+        // Odd, so that the bits of a term never collapse into one
+        let second = fnv1a(term.as_bytes(), FNV_OFFSET ^ 0x9e37_79b9_7f4a_7c15) | 1;
+        (0..u64::from(self.hashes))
+            .map(move |i| (first.wrapping_add(i.wrapping_mul(second)) % len) as usize)
+    }
+}
+
+/// The offset basis of the 64-bit FNV-1a hash.
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Hashes `bytes` with the 64-bit FNV-1a hash, starting from `offset`.
+fn fnv1a(bytes: &[u8], offset: u64) -> u64 {
+    bytes.iter().fold(offset, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
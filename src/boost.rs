@@ -0,0 +1,61 @@
+use crate::tree::DocInfo;
+
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// A hook multiplying the score of every search result by a factor of its
+/// own, derived from what the index knows about the document, e.g. its
+/// modification time or the depth of its path. Hooks are set in
+/// `SearchOptions::score_boosts` and applied after the boost rules of the
+/// configuration file.
+pub trait ScoreBoost: Send + Sync {
+    /// Returns the factor the score of a result is multiplied by.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the result, which may be an alias of the
+    ///   document sharing its content.
+    /// * `info` - The `DocInfo` of the document.
+    fn factor(&self, path: &Path, info: &DocInfo) -> f64;
+
+    /// Returns the lowest and highest factors the hook can return, if they
+    /// are known. Searches for a few best results only skip the documents
+    /// that cannot rank among them when every hook bounds its factors; none
+    /// can be negative.
+    fn range(&self) -> Option<(f64, f64)> {
+        None
+    }
+}
+
+/// Ranks recently modified documents higher: the score of a document
+/// modified just now is doubled, and the bonus halves every `half_life`, so
+/// that a match from last week outranks the same match from years ago.
+/// Documents with no known modification time count as modified when they
+/// were indexed.
+#[derive(Clone, Copy, Debug)]
+pub struct RecencyBoost {
+    /// How long it takes the bonus of a document to halve.
+    pub half_life: Duration,
+}
+
+impl RecencyBoost {
+    /// Creates a boost whose bonus halves every `half_life`.
+    pub fn new(half_life: Duration) -> Self {
+        Self { half_life }
+    }
+}
+
+impl ScoreBoost for RecencyBoost {
+    fn factor(&self, _path: &Path, info: &DocInfo) -> f64 {
+        let modified = info.modified.unwrap_or(info.indexed_at);
+        // Modification times in the future count as now
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        let half_lives = age.as_secs_f64() / self.half_life.as_secs_f64().max(1.0);
+        1.0 + 0.5f64.powf(half_lives)
+    }
+
+    fn range(&self) -> Option<(f64, f64)> {
+        Some((1.0, 2.0))
+    }
+}
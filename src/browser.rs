@@ -0,0 +1,327 @@
+use anyhow::{Context, anyhow};
+use rusqlite::{Connection, OpenFlags};
+use scraper::Html;
+
+use crate::Config;
+use crate::parsers::visible_text;
+use crate::report::{RunReport, SkipKind};
+use crate::temp::TempIndex;
+use crate::walker::ModifiedTimes;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long fetching one page may take.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Words starting a segment of the path or a query value of a URL, hinting
+/// that visiting it does more than show a page, e.g. logs out or confirms a
+/// subscription.
+const ACTION_WORDS: [&str; 14] = [
+    "logout",
+    "signout",
+    "delete",
+    "remove",
+    "unsubscribe",
+    "subscribe",
+    "confirm",
+    "verify",
+    "activate",
+    "approve",
+    "reset",
+    "revoke",
+    "cancel",
+    "checkout",
+];
+
+/// Endings of the query parameters carrying a credential or a one-time
+/// code, e.g. `access_token`.
+const SECRET_PARAMS: [&str; 10] = [
+    "token",
+    "code",
+    "key",
+    "otp",
+    "auth",
+    "session",
+    "sig",
+    "signature",
+    "nonce",
+    "ticket",
+];
+
+/// Pages of a Firefox `places.sqlite` database, with the titles they were
+/// bookmarked under.
+const FIREFOX_PAGES: &str = "SELECT p.url, COALESCE(p.title, ''),
+        (SELECT GROUP_CONCAT(b.title, ' ') FROM moz_bookmarks b WHERE b.fk = p.id)
+    FROM moz_places p
+    WHERE p.url LIKE 'http%'";
+
+/// Pages of a Chromium `History` database.
+const CHROMIUM_PAGES: &str = "SELECT url, COALESCE(title, ''), NULL
+    FROM urls
+    WHERE url LIKE 'http%'";
+
+/// A page from the history or bookmarks of a browser.
+pub struct Page {
+    /// The URL of the page.
+    pub url: String,
+    /// The title of the page when it was last visited.
+    pub title: String,
+    /// The titles the page was bookmarked under, if it was bookmarked.
+    pub bookmark_title: Option<String>,
+}
+
+impl Page {
+    /// Returns the text indexed for the page: its titles and its URL.
+    pub fn text(&self) -> String {
+        format!(
+            "{}\n{}\n{}",
+            self.title,
+            self.bookmark_title.as_deref().unwrap_or_default(),
+            self.url
+        )
+    }
+}
+
+/// Returns whether the database has a table called `name`.
+fn has_table(conn: &Connection, name: &str) -> anyhow::Result<bool> {
+    let count: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [name],
+            |row| row.get(0),
+        )
+        .context("query tables")?;
+    Ok(count > 0)
+}
+
+/// Reads the pages of a Firefox `places.sqlite` or Chromium `History`
+/// database. The database is copied first, as a running browser keeps it
+/// locked.
+///
+/// # Arguments
+/// * `db` - The path of the database.
+///
+/// # Returns
+/// The pages, or an `anyhow::Result` error if the database cannot be read or
+/// is not a browser history database.
+pub fn read_pages(db: &Path) -> anyhow::Result<Vec<Page>> {
+    let copy_dir = TempIndex::new()?;
+    let copy = copy_dir.path().join("history.sqlite");
+    fs::copy(db, &copy).with_context(|| format!("copy {db:?}"))?;
+    // Recent visits may still be in the write-ahead log
+    let wal = PathBuf::from(format!("{}-wal", db.display()));
+    if wal.exists() {
+        fs::copy(&wal, copy_dir.path().join("history.sqlite-wal")).context("copy WAL")?;
+    }
+
+    let conn = Connection::open_with_flags(&copy, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .context("open history database")?;
+    let query = if has_table(&conn, "moz_places")? {
+        FIREFOX_PAGES
+    } else if has_table(&conn, "urls")? {
+        CHROMIUM_PAGES
+    } else {
+        return Err(anyhow!(
+            "{db:?} is neither a Firefox nor a Chromium history database"
+        ));
+    };
+
+    let mut statement = conn.prepare(query).context("prepare pages query")?;
+    let pages = statement
+        .query_map([], |row| {
+            Ok(Page {
+                url: row.get(0)?,
+                title: row.get(1)?,
+                bookmark_title: row.get(2)?,
+            })
+        })
+        .context("query pages")?
+        .collect::<Result<Vec<Page>, _>>()
+        .context("read pages")?;
+    Ok(pages)
+}
+
+/// Which pages of a browser history are fetched with `--fetch-pages`. Only
+/// http(s) pages that look safe to visit again are: none whose path or
+/// query names an action, such as `/logout` or `?action=unsubscribe`, or
+/// whose query carries a token or a one-time code.
+#[derive(Clone, Debug, Default)]
+pub struct FetchPolicy {
+    /// Only fetch the pages of these hosts and their subdomains, if any
+    /// are given.
+    pub hosts: Vec<String>,
+}
+
+impl FetchPolicy {
+    /// Returns whether the page at `url` may be fetched.
+    ///
+    /// # Arguments
+    /// * `url` - The parsed URL of the page.
+    pub fn allows(&self, url: &ureq::RequestUrl) -> bool {
+        if !matches!(url.scheme(), "http" | "https") {
+            return false;
+        }
+        let host = url.host().to_lowercase();
+        if !self.hosts.is_empty()
+            && !self.hosts.iter().any(|allowed| {
+                let allowed = allowed.to_lowercase();
+                host == allowed || host.ends_with(&format!(".{allowed}"))
+            })
+        {
+            return false;
+        }
+        let normalize = |text: &str| text.to_lowercase().replace(['-', '_'], "");
+        let names_action = |text: &str| {
+            text.split('/').any(|segment| {
+                let segment = normalize(segment);
+                ACTION_WORDS.iter().any(|word| segment.starts_with(word))
+            })
+        };
+        !names_action(url.path())
+            && url.query_pairs().iter().all(|(name, value)| {
+                let name = normalize(name);
+                !SECRET_PARAMS.iter().any(|secret| name.ends_with(secret))
+                    && !names_action(&name)
+                    && !names_action(value)
+            })
+    }
+}
+
+/// Fetches a page and extracts its visible text.
+///
+/// # Arguments
+/// * `agent` - The HTTP agent to fetch with.
+/// * `url` - The URL of the page.
+///
+/// # Returns
+/// The text of the page, or an `anyhow::Result` error if it cannot be
+/// fetched.
+fn fetch_page_text(agent: &ureq::Agent, url: &str) -> anyhow::Result<String> {
+    let body = agent
+        .get(url)
+        .call()
+        .context("fetch page")?
+        .into_string()
+        .context("read page")?;
+    Ok(visible_text(&Html::parse_document(&body)))
+}
+
+/// Indexes the pages of a browser history database as virtual documents
+/// named after their URL. Pages already in the index are skipped. A page
+/// that cannot be fetched is reported and left for the next run, rather
+/// than indexed without its content.
+///
+/// # Arguments
+/// * `db` - The path of the `places.sqlite` or `History` database.
+/// * `fetch` - Also fetch and index the content of the new pages it
+///   allows, if given.
+/// * `cfg` - The indexing `Config`; its index path, key file and index wide
+///   settings are used.
+///
+/// # Returns
+/// The number of newly indexed pages, or an `anyhow::Result` error.
+pub fn index_browser_history(
+    db: &Path,
+    fetch: Option<&FetchPolicy>,
+    cfg: &Config,
+) -> anyhow::Result<u64> {
+    let pages = read_pages(db)?;
+    let mut report = RunReport::new(db);
+    report.discovered = pages.len() as u64;
+
+    let agent = ureq::AgentBuilder::new().timeout(FETCH_TIMEOUT).build();
+    let mut main_index = crate::open_for_indexing(cfg)?;
+    let analyzer = main_index.analyzer();
+    let mut display_forms = HashMap::new();
+    let mut indexed = 0;
+    for page in &pages {
+        let uri = PathBuf::from(&page.url);
+        if !main_index
+            .doc_store
+            .needs_indexing(&uri, &ModifiedTimes::new())
+        {
+            report.skip(&uri, SkipKind::UpToDate, "already indexed");
+            continue;
+        }
+
+        let mut text = page.text();
+        let fetched = fetch.is_some_and(|policy| {
+            agent
+                .get(&page.url)
+                .request_url()
+                .is_ok_and(|url| policy.allows(&url))
+        });
+        if fetched {
+            match fetch_page_text(&agent, &page.url) {
+                Ok(content) => {
+                    text.push('\n');
+                    text.push_str(&content);
+                }
+                Err(err) => {
+                    report.fail(&uri, format!("{err:#}"));
+                    continue;
+                }
+            }
+        }
+        analyzer.record_display_forms(&text, &mut display_forms);
+        let tokens = analyzer.analyze(&text);
+        match main_index.add_document(&uri, &tokens) {
+            Ok(()) => {
+                report.indexed.push(uri);
+                indexed += 1;
+            }
+            Err(err) => report.fail(&uri, format!("add to index: {err}")),
+        }
+    }
+
+    main_index.add_display_forms(display_forms);
+    main_index.commit().context("commit model")?;
+    report
+        .save(&main_index.index_dir, main_index.cipher())
+        .context("save run report")?;
+    Ok(indexed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns whether `policy` allows fetching `url`.
+    fn allows(policy: &FetchPolicy, url: &str) -> bool {
+        let agent = ureq::Agent::new();
+        policy.allows(&agent.get(url).request_url().unwrap())
+    }
+
+    #[test]
+    fn only_pages_safe_to_visit_again_are_fetched() {
+        let policy = FetchPolicy::default();
+        assert!(allows(&policy, "https://docs.rs/serde/latest/serde/"));
+        assert!(allows(
+            &policy,
+            "http://example.com/search?q=rust&keywords=x"
+        ));
+        assert!(!allows(&policy, "ftp://example.com/file.txt"));
+        assert!(!allows(&policy, "https://example.com/account/logout"));
+        assert!(!allows(&policy, "https://example.com/sign-out.php"));
+        assert!(!allows(
+            &policy,
+            "https://example.com/list?action=unsubscribe"
+        ));
+        assert!(!allows(
+            &policy,
+            "https://example.com/login?access_token=abc"
+        ));
+        assert!(!allows(&policy, "https://example.com/callback?code=123"));
+
+        let policy = FetchPolicy {
+            hosts: vec!["docs.rs".to_string()],
+        };
+        assert!(allows(&policy, "https://docs.rs/serde"));
+        assert!(allows(&policy, "https://www.docs.rs/serde"));
+        assert!(!allows(&policy, "https://example.com/serde"));
+        assert!(!allows(&policy, "https://notdocs.rs/serde"));
+    }
+}
@@ -0,0 +1,305 @@
+use anyhow::{Context, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::{self, Cipher};
+use crate::manifest::write_atomic;
+use crate::source::scheme_of;
+use crate::tree::{DocInfo, DocumentStore};
+use crate::walker::ModifiedTimes;
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// The file within an index directory recording when documents last matched
+/// a search.
+const MATCHES_FILE: &str = "matched.bin";
+
+/// The file within an index directory recording when documents were evicted.
+const EVICTIONS_FILE: &str = "evicted.bin";
+
+/// Evictions shrink the index below its budget by this ratio, so that the
+/// next few runs don't evict again straight away.
+const BUDGET_TARGET_RATIO: f64 = 0.9;
+
+/// Which documents are evicted first when the index outgrows its budget.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EvictionPolicy {
+    /// The documents indexed the longest time ago.
+    #[default]
+    Oldest,
+    /// The documents that matched a search the longest time ago, or never.
+    LeastMatched,
+    /// The documents with the most indexed tokens.
+    Largest,
+}
+
+/// The maximum size of an index and how to get back under it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SizeBudget {
+    /// The maximum size of the index directory in bytes.
+    pub max_bytes: u64,
+    /// Which documents to evict when the index is larger.
+    pub policy: EvictionPolicy,
+}
+
+/// Parses a size in bytes, optionally followed by a `K`, `M` or `G` binary
+/// unit, e.g. `512M`.
+///
+/// # Arguments
+/// * `size` - The size to parse.
+///
+/// # Returns
+/// The size in bytes, or an `anyhow::Result` error if it is not a number.
+pub fn parse_size(size: &str) -> anyhow::Result<u64> {
+    let size = size.trim();
+    let (number, unit) = match size.char_indices().find(|(_, c)| c.is_ascii_alphabetic()) {
+        Some((at, _)) => size.split_at(at),
+        None => (size, ""),
+    };
+    let multiplier: u64 = match unit.to_ascii_uppercase().trim_end_matches(['B', 'I']) {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return Err(anyhow!("invalid size unit {unit:?} in {size:?}")),
+    };
+    let number = number
+        .trim()
+        .parse::<u64>()
+        .map_err(|err| anyhow!("invalid size {size:?}: {err}"))?;
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| anyhow!("size {size:?} is too large"))
+}
+
+/// Parses a duration in seconds, optionally followed by an `s`, `m`, `h` or
+/// `d` unit, e.g. `5m`.
+///
+/// # Arguments
+/// * `duration` - The duration to parse.
+///
+/// # Returns
+/// The `Duration`, or an `anyhow::Result` error if it is not a positive
+/// number.
+pub fn parse_duration(duration: &str) -> anyhow::Result<Duration> {
+    let duration = duration.trim();
+    let (number, unit) = match duration
+        .char_indices()
+        .find(|(_, c)| c.is_ascii_alphabetic())
+    {
+        Some((at, _)) => duration.split_at(at),
+        None => (duration, ""),
+    };
+    let multiplier: u64 = match unit.to_ascii_lowercase().as_str() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(anyhow!("invalid duration unit {unit:?} in {duration:?}")),
+    };
+    let number = number
+        .trim()
+        .parse::<u64>()
+        .map_err(|err| anyhow!("invalid duration {duration:?}: {err}"))?;
+    if number == 0 {
+        return Err(anyhow!("duration {duration:?} must be positive"));
+    }
+    number
+        .checked_mul(multiplier)
+        .map(Duration::from_secs)
+        .ok_or_else(|| anyhow!("duration {duration:?} is too large"))
+}
+
+/// Returns the total size in bytes of the files in `index_dir` and its
+/// subdirectories.
+pub fn index_size(index_dir: &Path) -> anyhow::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(index_dir).with_context(|| format!("read {index_dir:?}"))? {
+        let entry = entry.context("read index entry")?;
+        let metadata = entry.metadata().context("read index entry metadata")?;
+        if metadata.is_dir() {
+            size += index_size(&entry.path())?;
+        } else {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
+/// Loads when the documents of the index in `index_dir` last matched a
+/// search, keyed by URI. Documents that never matched are absent.
+///
+/// # Arguments
+/// * `index_dir` - The directory containing the index files.
+/// * `cipher` - The cipher of an encrypted index, if any.
+pub fn load_matches(index_dir: &Path, cipher: Option<&Cipher>) -> HashMap<PathBuf, SystemTime> {
+    load_times(&index_dir.join(MATCHES_FILE), cipher)
+}
+
+/// Records that the documents at `paths` matched a search now, for the
+/// `LeastMatched` eviction policy. The record lives beside the index, so
+/// searches only write it when asked to, see `SearchOptions::record_matches`.
+///
+/// # Arguments
+/// * `index_dir` - The directory containing the index files.
+/// * `cipher` - The cipher of an encrypted index, if any.
+/// * `paths` - The URIs of the matching documents.
+///
+/// # Returns
+/// `Ok(())` once the record is written, otherwise an `anyhow::Result` error.
+pub fn record_matches<'a>(
+    index_dir: &Path,
+    cipher: Option<&Cipher>,
+    paths: impl IntoIterator<Item = &'a Path>,
+) -> anyhow::Result<()> {
+    record_times(&index_dir.join(MATCHES_FILE), cipher, paths)
+}
+
+/// Loads when documents were evicted from the index in `index_dir`, keyed by
+/// URI.
+///
+/// # Arguments
+/// * `index_dir` - The directory containing the index files.
+/// * `cipher` - The cipher of an encrypted index, if any.
+pub fn load_evictions(index_dir: &Path, cipher: Option<&Cipher>) -> HashMap<PathBuf, SystemTime> {
+    load_times(&index_dir.join(EVICTIONS_FILE), cipher)
+}
+
+/// Records that the documents at `paths` were evicted now, so that indexing
+/// runs leave them out until they are modified.
+///
+/// # Arguments
+/// * `index_dir` - The directory containing the index files.
+/// * `cipher` - The cipher of an encrypted index, if any.
+/// * `paths` - The URIs of the evicted documents.
+///
+/// # Returns
+/// `Ok(())` once the record is written, otherwise an `anyhow::Result` error.
+pub fn record_evictions(
+    index_dir: &Path,
+    cipher: Option<&Cipher>,
+    paths: &[PathBuf],
+) -> anyhow::Result<()> {
+    record_times(
+        &index_dir.join(EVICTIONS_FILE),
+        cipher,
+        paths.iter().map(PathBuf::as_path),
+    )
+}
+
+/// Loads a record of times keyed by URI, empty if there is none or it
+/// cannot be read.
+fn load_times(path: &Path, cipher: Option<&Cipher>) -> HashMap<PathBuf, SystemTime> {
+    fs::read(path)
+        .ok()
+        .and_then(|buf| crypto::open(cipher, buf).ok())
+        .and_then(|buf| bincode2::deserialize(&buf).ok())
+        .unwrap_or_default()
+}
+
+/// Sets the time of `paths` in a record of times to now. The record is
+/// locked meanwhile, so that the searches of several threads or processes
+/// don't lose each other's updates, and encrypted like the index.
+fn record_times<'a>(
+    path: &Path,
+    cipher: Option<&Cipher>,
+    paths: impl IntoIterator<Item = &'a Path>,
+) -> anyhow::Result<()> {
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.with_extension("lock"))
+        .context("open record lock")?;
+    lock.lock().context("lock record")?;
+
+    let mut times = load_times(path, cipher);
+    let now = SystemTime::now();
+    for path in paths {
+        times.insert(path.to_path_buf(), now);
+    }
+    let serialised = bincode2::serialize(&times).context("serialize record")?;
+    let serialised = crypto::seal(cipher, serialised).context("encrypt record")?;
+    write_atomic(path, &serialised)
+}
+
+/// Returns `true` if the document at `uri` was evicted and not modified
+/// since, so it should not be indexed again.
+///
+/// # Arguments
+/// * `evictions` - When documents were evicted, from `load_evictions`.
+/// * `uri` - The URI of the document.
+/// * `modified` - The modification times read during discovery.
+pub fn is_evicted(
+    evictions: &HashMap<PathBuf, SystemTime>,
+    uri: &Path,
+    modified: &ModifiedTimes,
+) -> bool {
+    let Some(&evicted_at) = evictions.get(uri) else {
+        return false;
+    };
+    let info = DocInfo {
+        path: uri.to_path_buf(),
+        indexed_at: evicted_at,
+        ..DocInfo::default()
+    };
+    !scheme_of(uri).is_expired(uri, &info, modified)
+}
+
+/// Picks the documents to evict so that an index of `size` bytes shrinks
+/// below `BUDGET_TARGET_RATIO` of its budget. The space a document takes is
+/// estimated from its share of the indexed tokens.
+///
+/// # Arguments
+/// * `doc_store` - The documents of the index.
+/// * `budget` - The size budget of the index.
+/// * `size` - The current size of the index in bytes.
+/// * `matches` - When documents last matched, from `load_matches`.
+///
+/// # Returns
+/// The ids of the documents to evict, empty if the index fits its budget.
+pub fn select_evictions(
+    doc_store: &DocumentStore,
+    budget: &SizeBudget,
+    size: u64,
+    matches: &HashMap<PathBuf, SystemTime>,
+) -> Vec<u64> {
+    if size <= budget.max_bytes {
+        return Vec::new();
+    }
+    let total_tokens: u64 = doc_store
+        .id_to_doc_info
+        .values()
+        .map(|info| info.token_count)
+        .sum();
+    if total_tokens == 0 {
+        return Vec::new();
+    }
+    let bytes_per_token = size as f64 / total_tokens as f64;
+    let excess = size as f64 - budget.max_bytes as f64 * BUDGET_TARGET_RATIO;
+
+    let mut docs: Vec<_> = doc_store.id_to_doc_info.iter().collect();
+    match budget.policy {
+        EvictionPolicy::Oldest => docs.sort_by_key(|(_, info)| info.indexed_at),
+        EvictionPolicy::LeastMatched => {
+            docs.sort_by_key(|(_, info)| (matches.get(&info.path).copied(), info.indexed_at))
+        }
+        EvictionPolicy::Largest => {
+            docs.sort_by_key(|(_, info)| std::cmp::Reverse(info.token_count))
+        }
+    }
+
+    let mut freed = 0.0;
+    let mut evicted = Vec::new();
+    for (&id, info) in docs {
+        if freed >= excess {
+            break;
+        }
+        freed += info.token_count as f64 * bytes_per_token;
+        evicted.push(id);
+    }
+    evicted
+}
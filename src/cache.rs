@@ -0,0 +1,428 @@
+use std::collections::{BTreeMap, HashMap};
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::SearchResults;
+use crate::tree::{Posting, SegmentTermInfo};
+
+/// The default number of bytes of decoded postings the server keeps cached.
+pub const DEFAULT_POSTINGS_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+/// The number of bytes of segment dictionaries, as stored on disk, an index
+/// keeps loaded.
+pub(crate) const DICT_CACHE_BYTES: usize = 32 * 1024 * 1024;
+
+/// The location of a postings list: the id of its segment and its offset in
+/// the postings file of the segment.
+type ListKey = (u64, u64);
+
+/// A cached postings list.
+struct CachedList {
+    postings: Vec<Posting>,
+    /// The estimated memory used by the postings.
+    bytes: usize,
+    /// The tick of the last access, the key of the list in `CacheState::lru`.
+    tick: u64,
+}
+
+/// The contents of a `PostingsCache`.
+#[derive(Default)]
+struct CacheState {
+    /// The directory of the index the cached lists were read from.
+    index_dir: PathBuf,
+    /// The index generation the cached lists were read from.
+    generation: u64,
+    lists: HashMap<ListKey, CachedList>,
+    /// The cached lists by tick of their last access, least recent first.
+    lru: BTreeMap<u64, ListKey>,
+    /// The sum of the bytes of the cached lists.
+    bytes: usize,
+    /// The tick given to the next access.
+    next_tick: u64,
+}
+
+impl CacheState {
+    /// Drops every cached list if they were read from another index than
+    /// `index_dir`, e.g. before a new index was published, or from another
+    /// generation of it than `generation`.
+    fn sync_generation(&mut self, index_dir: &Path, generation: u64) {
+        if self.generation != generation || self.index_dir != index_dir {
+            *self = Self {
+                index_dir: index_dir.to_path_buf(),
+                generation,
+                next_tick: self.next_tick,
+                ..Self::default()
+            };
+        }
+    }
+
+    /// Returns a new access tick.
+    fn tick(&mut self) -> u64 {
+        self.next_tick += 1;
+        self.next_tick
+    }
+}
+
+/// A cache of decoded postings lists, shared by the searches of a
+/// long-running server so that the lists of popular terms are not read and
+/// decoded again for every request. The least recently used lists are
+/// evicted once the cached postings take more than the capacity, and the
+/// whole cache is dropped when searches see a new generation of the index or
+/// a newly published index.
+pub struct PostingsCache {
+    /// The number of bytes of postings kept at most.
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+impl PostingsCache {
+    /// Creates an empty cache.
+    ///
+    /// # Arguments
+    /// * `capacity` - The number of bytes of decoded postings kept at most.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /// Returns a copy of a cached postings list, marking it as recently used.
+    ///
+    /// # Arguments
+    /// * `index_dir` - The directory of the index being searched.
+    /// * `generation` - The generation of the index being searched.
+    /// * `seg_id` - The id of the segment holding the list.
+    /// * `offset` - The offset of the list in the postings file.
+    ///
+    /// # Returns
+    /// The postings, or `None` if the list isn't cached for this generation.
+    pub fn get(
+        &self,
+        index_dir: &Path,
+        generation: u64,
+        seg_id: u64,
+        offset: u64,
+    ) -> Option<Vec<Posting>> {
+        let mut state = self.state.lock().unwrap();
+        state.sync_generation(index_dir, generation);
+        let tick = state.tick();
+        let list = state.lists.get_mut(&(seg_id, offset))?;
+        let last = std::mem::replace(&mut list.tick, tick);
+        let postings = list.postings.clone();
+        state.lru.remove(&last);
+        state.lru.insert(tick, (seg_id, offset));
+        Some(postings)
+    }
+
+    /// Caches a postings list read from the index, evicting the least
+    /// recently used lists to stay within the capacity. Lists larger than
+    /// the whole capacity are not cached.
+    ///
+    /// # Arguments
+    /// * `index_dir` - The directory of the index the list was read from.
+    /// * `generation` - The generation of the index the list was read from.
+    /// * `seg_id` - The id of the segment holding the list.
+    /// * `offset` - The offset of the list in the postings file.
+    /// * `postings` - The decoded postings.
+    pub fn insert(
+        &self,
+        index_dir: &Path,
+        generation: u64,
+        seg_id: u64,
+        offset: u64,
+        postings: &[Posting],
+    ) {
+        let bytes = postings_bytes(postings);
+        if bytes > self.capacity {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.sync_generation(index_dir, generation);
+        if state.lists.contains_key(&(seg_id, offset)) {
+            return;
+        }
+        while state.bytes + bytes > self.capacity {
+            let Some((_, key)) = state.lru.pop_first() else {
+                break;
+            };
+            if let Some(evicted) = state.lists.remove(&key) {
+                state.bytes -= evicted.bytes;
+            }
+        }
+        let tick = state.tick();
+        state.lru.insert(tick, (seg_id, offset));
+        state.lists.insert(
+            (seg_id, offset),
+            CachedList {
+                postings: postings.to_vec(),
+                bytes,
+                tick,
+            },
+        );
+        state.bytes += bytes;
+    }
+}
+
+/// Returns the estimated memory used by decoded postings.
+fn postings_bytes(postings: &[Posting]) -> usize {
+    postings
+        .iter()
+        .map(|posting| size_of::<Posting>() + posting.positions.len() * size_of::<u32>())
+        .sum()
+}
+
+/// A loaded segment dictionary.
+struct CachedDict {
+    dict: Arc<SegmentTermInfo>,
+    /// The size of the dictionary file.
+    bytes: usize,
+    /// The tick of the last access, the key of the dictionary in
+    /// `DictCacheState::lru`.
+    tick: u64,
+}
+
+/// The contents of a `DictCache`.
+#[derive(Default)]
+struct DictCacheState {
+    dicts: HashMap<u64, CachedDict>,
+    /// The segment ids of the cached dictionaries by tick of their last
+    /// access, least recent first.
+    lru: BTreeMap<u64, u64>,
+    /// The sum of the bytes of the cached dictionaries.
+    bytes: usize,
+    /// The tick given to the next access.
+    next_tick: u64,
+}
+
+/// A cache of the term dictionaries of the segments of an index, so that
+/// the searches run on an open index don't read and deserialize the same
+/// dictionaries again. Segments never change once written, so dictionaries
+/// stay valid until evicted; the least recently used ones are evicted once
+/// the cached dictionary files take more than the capacity.
+pub(crate) struct DictCache {
+    /// The number of bytes of dictionary files kept at most.
+    capacity: usize,
+    state: Mutex<DictCacheState>,
+}
+
+impl DictCache {
+    /// Creates an empty cache.
+    ///
+    /// # Arguments
+    /// * `capacity` - The number of bytes of dictionary files kept at most.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(DictCacheState::default()),
+        }
+    }
+
+    /// Returns a cached dictionary, marking it as recently used.
+    ///
+    /// # Arguments
+    /// * `seg_id` - The id of the segment of the dictionary.
+    ///
+    /// # Returns
+    /// The dictionary, or `None` if it isn't cached.
+    pub fn get(&self, seg_id: u64) -> Option<Arc<SegmentTermInfo>> {
+        let mut state = self.state.lock().unwrap();
+        state.next_tick += 1;
+        let tick = state.next_tick;
+        let cached = state.dicts.get_mut(&seg_id)?;
+        let last = std::mem::replace(&mut cached.tick, tick);
+        let dict = Arc::clone(&cached.dict);
+        state.lru.remove(&last);
+        state.lru.insert(tick, seg_id);
+        Some(dict)
+    }
+
+    /// Caches a dictionary read from the index, evicting the least recently
+    /// used dictionaries to stay within the capacity. Dictionaries larger
+    /// than the whole capacity are not cached.
+    ///
+    /// # Arguments
+    /// * `seg_id` - The id of the segment of the dictionary.
+    /// * `dict` - The deserialised dictionary.
+    /// * `bytes` - The size of the dictionary file.
+    pub fn insert(&self, seg_id: u64, dict: &Arc<SegmentTermInfo>, bytes: usize) {
+        if bytes > self.capacity {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.dicts.contains_key(&seg_id) {
+            return;
+        }
+        while state.bytes + bytes > self.capacity {
+            let Some((_, evicted)) = state.lru.pop_first() else {
+                break;
+            };
+            if let Some(evicted) = state.dicts.remove(&evicted) {
+                state.bytes -= evicted.bytes;
+            }
+        }
+        state.next_tick += 1;
+        let tick = state.next_tick;
+        state.lru.insert(tick, seg_id);
+        state.dicts.insert(
+            seg_id,
+            CachedDict {
+                dict: Arc::clone(dict),
+                bytes,
+                tick,
+            },
+        );
+        state.bytes += bytes;
+    }
+
+    /// Drops the dictionary of a segment removed from the index.
+    pub fn remove(&self, seg_id: u64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(removed) = state.dicts.remove(&seg_id) {
+            state.lru.remove(&removed.tick);
+            state.bytes -= removed.bytes;
+        }
+    }
+}
+
+/// The default number of search results a server keeps cached per index.
+pub const DEFAULT_RESULTS_CACHE_ENTRIES: usize = 256;
+
+/// Cached search results.
+struct CachedResults {
+    results: Arc<SearchResults>,
+    /// The tick of the last access, the key of the results in
+    /// `ResultsCacheState::lru`.
+    tick: u64,
+}
+
+/// The contents of a `ResultsCache`.
+#[derive(Default)]
+struct ResultsCacheState {
+    /// The directory of the index the cached results were searched in.
+    index_dir: PathBuf,
+    /// The index generation the cached results were searched in.
+    generation: u64,
+    results: HashMap<String, CachedResults>,
+    /// The keys of the cached results by tick of their last access, least
+    /// recent first.
+    lru: BTreeMap<u64, String>,
+    /// The tick given to the next access.
+    next_tick: u64,
+}
+
+impl ResultsCacheState {
+    /// Drops every cached result if they were searched in another index than
+    /// `index_dir` or another generation of it than `generation`.
+    fn sync_generation(&mut self, index_dir: &Path, generation: u64) {
+        if self.generation != generation || self.index_dir != index_dir {
+            *self = Self {
+                index_dir: index_dir.to_path_buf(),
+                generation,
+                next_tick: self.next_tick,
+                ..Self::default()
+            };
+        }
+    }
+
+    /// Returns a new access tick.
+    fn tick(&mut self) -> u64 {
+        self.next_tick += 1;
+        self.next_tick
+    }
+}
+
+/// A cache of the results of the latest searches of a server, so that the
+/// same query sent again, e.g. by a typeahead or a dashboard, is answered
+/// without reading and scoring postings. Results are keyed by their query,
+/// with its whitespace normalized, and their options. The least recently
+/// used are evicted beyond the capacity, and the whole cache is dropped when
+/// searches see a new generation of the index or a newly published index.
+pub struct ResultsCache {
+    /// The number of results kept at most.
+    capacity: usize,
+    state: Mutex<ResultsCacheState>,
+}
+
+impl ResultsCache {
+    /// Creates an empty cache.
+    ///
+    /// # Arguments
+    /// * `capacity` - The number of results kept at most.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(ResultsCacheState::default()),
+        }
+    }
+
+    /// Returns the key of the results of `query` searched with `options`:
+    /// the query with runs of whitespace collapsed, and the options.
+    ///
+    /// # Arguments
+    /// * `query` - The query as sent.
+    /// * `options` - The options of the search changing its results, e.g.
+    ///   field weights or a sort order, written out.
+    pub fn key(query: &str, options: &str) -> String {
+        let query = query.split_whitespace().collect::<Vec<_>>().join(" ");
+        format!("{query}\0{options}")
+    }
+
+    /// Returns cached results, marking them as recently used.
+    ///
+    /// # Arguments
+    /// * `index_dir` - The directory of the index being searched.
+    /// * `generation` - The current generation of the index.
+    /// * `key` - The key of the search, from `ResultsCache::key`.
+    ///
+    /// # Returns
+    /// The results, or `None` if they aren't cached for this generation.
+    pub fn get(&self, index_dir: &Path, generation: u64, key: &str) -> Option<Arc<SearchResults>> {
+        let mut state = self.state.lock().unwrap();
+        state.sync_generation(index_dir, generation);
+        let tick = state.tick();
+        let cached = state.results.get_mut(key)?;
+        let last = std::mem::replace(&mut cached.tick, tick);
+        let results = Arc::clone(&cached.results);
+        state.lru.remove(&last);
+        state.lru.insert(tick, key.to_string());
+        Some(results)
+    }
+
+    /// Caches the results of a search, evicting the least recently used
+    /// results to stay within the capacity. Results of another generation
+    /// than the cached ones are not cached.
+    ///
+    /// # Arguments
+    /// * `index_dir` - The directory of the index searched.
+    /// * `key` - The key of the search, from `ResultsCache::key`.
+    /// * `results` - The results of the search.
+    pub fn insert(&self, index_dir: &Path, key: &str, results: &Arc<SearchResults>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.generation != results.generation || state.index_dir != index_dir {
+            return;
+        }
+        if state.results.contains_key(key) {
+            return;
+        }
+        while state.results.len() >= self.capacity {
+            let Some((_, evicted)) = state.lru.pop_first() else {
+                break;
+            };
+            state.results.remove(&evicted);
+        }
+        let tick = state.tick();
+        state.lru.insert(tick, key.to_string());
+        state.results.insert(
+            key.to_string(),
+            CachedResults {
+                results: Arc::clone(results),
+                tick,
+            },
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::{self, Cipher};
+use crate::manifest::write_atomic;
+use crate::publish::resolve_index;
+use crate::walker::ModifiedTimes;
+
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// The file within an index directory holding the checkpoint of an
+/// unfinished indexing run.
+const CHECKPOINT_FILE: &str = "checkpoint.bin";
+
+/// The file within an index directory the documents completed since the
+/// checkpoint was saved are appended to.
+const JOURNAL_FILE: &str = "checkpoint.journal";
+
+/// The time between two checkpoints. Each checkpoint commits the index, so
+/// this trades the work lost on an interruption against the time spent
+/// committing.
+pub const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The number of document bytes processed after which a checkpoint is due
+/// even if `CHECKPOINT_INTERVAL` has not elapsed.
+pub const CHECKPOINT_BYTES: u64 = 256 * 1024 * 1024;
+
+/// The progress of an indexing run, saved periodically so that an
+/// interrupted run can resume without discovering and checking every
+/// document again. The discovered documents are saved once; the documents
+/// completed at each checkpoint are appended to a journal.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Checkpoint {
+    /// The path the run indexes.
+    pub root: PathBuf,
+    /// The documents discovered under `root`.
+    pub docs: Vec<PathBuf>,
+    /// The modification times read during discovery.
+    pub modified: ModifiedTimes,
+    /// The documents processed and committed to the index.
+    pub completed: HashSet<PathBuf>,
+    /// The documents processed since the last checkpoint, not committed yet.
+    #[serde(skip)]
+    pending: Vec<PathBuf>,
+    /// When the last checkpoint was saved.
+    #[serde(skip, default = "Instant::now")]
+    saved_at: Instant,
+    /// The bytes processed by the run when the last checkpoint was saved.
+    #[serde(skip)]
+    saved_bytes: u64,
+}
+
+impl Checkpoint {
+    /// Creates the checkpoint of a run that has discovered its documents and
+    /// processed none yet.
+    pub fn new(root: &Path, docs: Vec<PathBuf>, modified: ModifiedTimes) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            docs,
+            modified,
+            completed: HashSet::new(),
+            pending: Vec::new(),
+            saved_at: Instant::now(),
+            saved_bytes: 0,
+        }
+    }
+
+    /// Loads the checkpoint of the unfinished run on the index in
+    /// `index_dir` with the documents completed in its journal, decrypting
+    /// them with `cipher` if the index is encrypted. A journal entry cut
+    /// short by an interruption is ignored.
+    ///
+    /// # Returns
+    /// The `Checkpoint`, `None` if the last run finished, or an
+    /// `anyhow::Result` error if it cannot be read.
+    pub fn load(index_dir: &Path, cipher: Option<&Cipher>) -> anyhow::Result<Option<Self>> {
+        let buf = match fs::read(resolve_index(index_dir).join(CHECKPOINT_FILE)) {
+            Ok(buf) => buf,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err).context("read checkpoint"),
+        };
+        let buf = crypto::open(cipher, buf).context("decrypt checkpoint")?;
+        let mut checkpoint: Self = bincode2::deserialize(&buf).context("deserialize checkpoint")?;
+
+        let journal = match fs::read(resolve_index(index_dir).join(JOURNAL_FILE)) {
+            Ok(journal) => journal,
+            Err(err) if err.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err).context("read checkpoint journal"),
+        };
+        let mut rest = journal.as_slice();
+        while let Some((len, tail)) = rest.split_first_chunk::<4>() {
+            let len = u32::from_le_bytes(*len) as usize;
+            let Some(entry) = tail.get(..len) else {
+                break;
+            };
+            let entry = crypto::open(cipher, entry.to_vec()).context("decrypt checkpoint")?;
+            let completed: Vec<PathBuf> =
+                bincode2::deserialize(&entry).context("deserialize checkpoint")?;
+            checkpoint.completed.extend(completed);
+            rest = &tail[len..];
+        }
+        Ok(Some(checkpoint))
+    }
+
+    /// Returns `true` if the index in `index_dir` has the checkpoint of an
+    /// unfinished run, without reading it.
+    pub fn exists(index_dir: &Path) -> bool {
+        resolve_index(index_dir).join(CHECKPOINT_FILE).exists()
+    }
+
+    /// Saves the checkpoint in `index_dir` when a run starts, replacing the
+    /// previous one and its journal, and encrypts it with `cipher` if the
+    /// index is encrypted.
+    ///
+    /// # Returns
+    /// `Ok(())` once it is written, otherwise an `anyhow::Result` error.
+    pub fn save(&self, index_dir: &Path, cipher: Option<&Cipher>) -> anyhow::Result<()> {
+        remove_file(&resolve_index(index_dir).join(JOURNAL_FILE))?;
+        let serialised = bincode2::serialize(self).context("serialize checkpoint")?;
+        let serialised = crypto::seal(cipher, serialised).context("encrypt checkpoint")?;
+        write_atomic(&resolve_index(index_dir).join(CHECKPOINT_FILE), &serialised)
+    }
+
+    /// Appends the documents processed since the last checkpoint to the
+    /// journal in `index_dir`, once the index holding them was committed,
+    /// and marks them as completed.
+    ///
+    /// # Arguments
+    /// * `index_dir` - The index directory holding the checkpoint.
+    /// * `cipher` - The cipher encrypting the journal, if the index is
+    ///   encrypted.
+    ///
+    /// # Returns
+    /// `Ok(())` once the entry is on disk, otherwise an `anyhow::Result`
+    /// error.
+    pub fn append_pending(
+        &mut self,
+        index_dir: &Path,
+        cipher: Option<&Cipher>,
+    ) -> anyhow::Result<()> {
+        let serialised = bincode2::serialize(&self.pending).context("serialize checkpoint")?;
+        let serialised = crypto::seal(cipher, serialised).context("encrypt checkpoint")?;
+        let len = u32::try_from(serialised.len()).context("checkpoint journal entry too large")?;
+        let mut entry = len.to_le_bytes().to_vec();
+        entry.extend(serialised);
+        let path = resolve_index(index_dir).join(JOURNAL_FILE);
+        let mut journal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("open {path:?}"))?;
+        journal
+            .write_all(&entry)
+            .and_then(|_| journal.sync_data())
+            .with_context(|| format!("write {path:?}"))?;
+        self.completed.extend(self.pending.drain(..));
+        Ok(())
+    }
+
+    /// Removes the checkpoint from `index_dir` once the run has finished.
+    ///
+    /// # Returns
+    /// `Ok(())` if it is removed or there was none, otherwise an
+    /// `anyhow::Result` error.
+    pub fn remove(index_dir: &Path) -> anyhow::Result<()> {
+        let index_dir = resolve_index(index_dir);
+        remove_file(&index_dir.join(CHECKPOINT_FILE))?;
+        remove_file(&index_dir.join(JOURNAL_FILE))
+    }
+
+    /// Records that `doc` was processed.
+    ///
+    /// # Arguments
+    /// * `doc` - The processed document.
+    /// * `bytes` - The bytes processed by the run so far.
+    ///
+    /// # Returns
+    /// `true` if `CHECKPOINT_INTERVAL` elapsed or `CHECKPOINT_BYTES` were
+    /// processed since the last checkpoint, so a new one is due.
+    pub fn processed(&mut self, doc: &Path, bytes: u64) -> bool {
+        self.pending.push(doc.to_path_buf());
+        if self.saved_at.elapsed() < CHECKPOINT_INTERVAL
+            && bytes.saturating_sub(self.saved_bytes) < CHECKPOINT_BYTES
+        {
+            return false;
+        }
+        self.saved_at = Instant::now();
+        self.saved_bytes = bytes;
+        true
+    }
+
+    /// Returns the discovered documents that were not processed yet.
+    pub fn remaining(&self) -> Vec<PathBuf> {
+        self.docs
+            .iter()
+            .filter(|doc| !self.completed.contains(*doc))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Removes `path`, if it exists.
+///
+/// # Returns
+/// `Ok(())` if it is removed or there was none, otherwise an `anyhow::Result`
+/// error.
+fn remove_file(path: &Path) -> anyhow::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => {
+            Err(err).with_context(|| format!("remove {path:?}"))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempIndex;
+
+    #[test]
+    fn loads_the_completed_documents_from_the_journal() {
+        let index_dir = TempIndex::new().unwrap();
+        let docs: Vec<PathBuf> = ["/a", "/b", "/c"].into_iter().map(PathBuf::from).collect();
+        let mut checkpoint = Checkpoint::new(Path::new("/"), docs, ModifiedTimes::default());
+        checkpoint.save(index_dir.path(), None).unwrap();
+        checkpoint.processed(Path::new("/a"), 0);
+        checkpoint.append_pending(index_dir.path(), None).unwrap();
+        checkpoint.processed(Path::new("/b"), 0);
+        checkpoint.append_pending(index_dir.path(), None).unwrap();
+        // An entry cut short by an interruption
+        let journal = index_dir.path().join(JOURNAL_FILE);
+        let mut file = OpenOptions::new().append(true).open(&journal).unwrap();
+        file.write_all(&[200, 0, 0, 0, 1]).unwrap();
+
+        let loaded = Checkpoint::load(index_dir.path(), None).unwrap().unwrap();
+        assert_eq!(loaded.remaining(), vec![PathBuf::from("/c")]);
+
+        // A new run starts with an empty journal
+        checkpoint.save(index_dir.path(), None).unwrap();
+        assert!(!journal.exists());
+        Checkpoint::remove(index_dir.path()).unwrap();
+        assert!(!Checkpoint::exists(index_dir.path()));
+    }
+
+    #[test]
+    fn is_due_after_enough_bytes() {
+        let mut checkpoint = Checkpoint::new(Path::new("/"), Vec::new(), ModifiedTimes::default());
+        assert!(!checkpoint.processed(Path::new("/a"), 1024));
+        assert!(checkpoint.processed(Path::new("/b"), CHECKPOINT_BYTES));
+        assert!(!checkpoint.processed(Path::new("/c"), CHECKPOINT_BYTES + 1));
+    }
+}
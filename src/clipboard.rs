@@ -0,0 +1,166 @@
+use anyhow::Context;
+use arboard::Clipboard;
+
+use crate::tree::MainIndex;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// How often the clipboard is polled in watch mode.
+pub const CLIPBOARD_POLL: Duration = Duration::from_millis(500);
+
+/// The URI prefix of the captured snippets, which are virtual documents.
+pub const CLIP_URI_PREFIX: &str = "clip:";
+
+/// Merge the small segments of the clipboard index once there are more than
+/// this many, as every snippet is committed on its own.
+const MAX_SMALL_SEGMENTS: usize = 16;
+
+/// Indexes a snippet as a virtual document named after the time it was
+/// captured, `clip:<time>`. Its text is kept in the document store, so that
+/// it is encrypted along with the index rather than written out in
+/// plaintext.
+///
+/// # Arguments
+/// * `main_index` - The clipboard index.
+/// * `text` - The captured text.
+///
+/// # Returns
+/// The URI of the saved snippet, or an `anyhow::Result` error.
+pub fn save_clip(main_index: &mut MainIndex, text: &str) -> anyhow::Result<PathBuf> {
+    let name = chrono::Local::now().format("%Y-%m-%dT%H-%M-%S%.3f");
+    let path = PathBuf::from(format!("{CLIP_URI_PREFIX}{name}"));
+
+    let analyzer = main_index.analyzer();
+    let tokens = analyzer.analyze(text);
+    let mut display_forms = HashMap::new();
+    analyzer.record_display_forms(text, &mut display_forms);
+    main_index
+        .add_document(&path, &tokens)
+        .context("add clip to index")?;
+    let doc_id = main_index.doc_store.get_id(&path);
+    main_index
+        .doc_store
+        .set_text(doc_id, Some(text.to_string()));
+    main_index.add_display_forms(display_forms);
+    main_index.commit().context("commit clip")?;
+    // Merging only once the clip is flushed keeps its document in the merge
+    let segments_before = main_index.active_segments.clone();
+    main_index
+        .compact_if_needed(MAX_SMALL_SEGMENTS)
+        .context("compact clipboard index")?;
+    if main_index.active_segments != segments_before {
+        main_index.commit().context("commit compaction")?;
+    }
+    Ok(path)
+}
+
+/// Reads the text currently on the clipboard.
+fn clipboard_text(clipboard: &mut Clipboard) -> Option<String> {
+    clipboard
+        .get_text()
+        .ok()
+        .filter(|text| !text.trim().is_empty())
+}
+
+/// Captures the text currently on the clipboard into the clipboard index.
+///
+/// # Arguments
+/// * `index_dir` - The directory of the clipboard index.
+/// * `key_file` - The key file of an encrypted index, if any.
+///
+/// # Returns
+/// The URI of the saved snippet, `None` if the clipboard holds no text, or
+/// an `anyhow::Result` error.
+pub fn capture_clipboard(
+    index_dir: &Path,
+    key_file: Option<&Path>,
+) -> anyhow::Result<Option<PathBuf>> {
+    let mut clipboard = Clipboard::new().context("access clipboard")?;
+    let Some(text) = clipboard_text(&mut clipboard) else {
+        return Ok(None);
+    };
+    let mut main_index = MainIndex::open(index_dir, key_file).context("open clipboard index")?;
+    save_clip(&mut main_index, &text).map(Some)
+}
+
+/// Watches the clipboard and captures every new text copied to it into the
+/// clipboard index, until the process is stopped. The text on the clipboard
+/// when watching starts is not captured.
+///
+/// # Arguments
+/// * `index_dir` - The directory of the clipboard index.
+/// * `key_file` - The key file of an encrypted index, if any.
+/// * `on_capture` - Called with the URI of each saved snippet.
+///
+/// # Returns
+/// An `anyhow::Result` error if the clipboard or the index cannot be
+/// accessed.
+pub fn watch_clipboard(
+    index_dir: &Path,
+    key_file: Option<&Path>,
+    mut on_capture: impl FnMut(&Path),
+) -> anyhow::Result<()> {
+    let mut clipboard = Clipboard::new().context("access clipboard")?;
+    let mut main_index = MainIndex::open(index_dir, key_file).context("open clipboard index")?;
+    let mut last = clipboard_text(&mut clipboard);
+
+    loop {
+        thread::sleep(CLIPBOARD_POLL);
+        let text = clipboard_text(&mut clipboard);
+        if text.is_none() || text == last {
+            continue;
+        }
+        if let Some(text) = &text {
+            let path = save_clip(&mut main_index, text)?;
+            on_capture(&path);
+        }
+        last = text;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempIndex;
+
+    use std::fs;
+
+    /// Returns the content of every file under `dir`.
+    fn file_contents(dir: &Path) -> Vec<Vec<u8>> {
+        let mut contents = Vec::new();
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                contents.extend(file_contents(&path));
+            } else {
+                contents.push(fs::read(&path).unwrap());
+            }
+        }
+        contents
+    }
+
+    #[test]
+    fn clips_are_kept_encrypted_in_the_index() {
+        let temp = TempIndex::new().unwrap();
+        let key_file = temp.path().join("key");
+        fs::write(&key_file, "passphrase").unwrap();
+        let index_dir = temp.path().join("clipboard");
+        let text = "docker system prune --volumes";
+
+        let mut main_index = MainIndex::open(&index_dir, Some(&key_file)).unwrap();
+        let uri = save_clip(&mut main_index, text).unwrap();
+        assert!(uri.to_string_lossy().starts_with(CLIP_URI_PREFIX));
+
+        let plaintext = text.as_bytes();
+        assert!(
+            file_contents(&index_dir)
+                .iter()
+                .all(|content| !content.windows(plaintext.len()).any(|w| w == plaintext))
+        );
+        let reopened = MainIndex::open_read_only(&index_dir, Some(&key_file)).unwrap();
+        assert_eq!(reopened.doc_store.text(&uri), Some(text));
+    }
+}
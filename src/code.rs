@@ -0,0 +1,68 @@
+/// The extensions of the source code files indexed with the code analyzer.
+pub const CODE_EXTENSIONS: [&str; 22] = [
+    "rs", "py", "js", "jsx", "ts", "tsx", "go", "c", "h", "cc", "cpp", "hpp", "java", "kt", "cs",
+    "swift", "rb", "php", "lua", "scala", "sh", "zig",
+];
+
+/// Returns `true` if `ext` is the extension of a source code file.
+pub fn is_code(ext: &str) -> bool {
+    CODE_EXTENSIONS.contains(&ext)
+}
+
+/// Follows every `camelCase` or `PascalCase` identifier of `text` with the
+/// words it is made of, so that `parseDocument` is found by `parse` and
+/// `document` as well as by the whole identifier. Acronyms stay whole:
+/// `HTTPServer` is followed by `HTTP Server`. The words of `snake_case`
+/// identifiers need no splitting, as the lexer already ends words at `_`.
+///
+/// # Arguments
+/// * `text` - The source code, before it is lowercased.
+///
+/// # Returns
+/// The text with the words of identifiers inserted after them.
+pub fn split_identifiers(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + text.len() / 4);
+    let mut rest = text;
+    while let Some(start) = rest.find(|c: char| c.is_alphanumeric()) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| !c.is_alphanumeric())
+            .unwrap_or(rest.len());
+        let word = &rest[..end];
+        out.push_str(word);
+        let parts = camel_case_parts(word);
+        if parts.len() > 1 {
+            for part in parts {
+                out.push(' ');
+                out.push_str(part);
+            }
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Cuts an alphanumeric word where its case changes: before an uppercase
+/// letter following a lowercase one or a digit, and before the last letter
+/// of a run of uppercase ones followed by a lowercase one.
+fn camel_case_parts(word: &str) -> Vec<&str> {
+    let chars = word.char_indices().collect::<Vec<(usize, char)>>();
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (i, &(at, c)) in chars.iter().enumerate().skip(1) {
+        let prev = chars[i - 1].1;
+        let next_lower = chars
+            .get(i + 1)
+            .is_some_and(|(_, next)| next.is_lowercase());
+        let boundary = c.is_uppercase()
+            && (prev.is_lowercase() || prev.is_numeric() || (prev.is_uppercase() && next_lower));
+        if boundary {
+            parts.push(&word[start..at]);
+            start = at;
+        }
+    }
+    parts.push(&word[start..]);
+    parts
+}
@@ -0,0 +1,370 @@
+use anyhow::Context;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::backup::hex_digest;
+use crate::manifest::Manifest;
+use crate::publish::resolve_index;
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// The number of bytes of the nonce prepended to every sealed blob.
+const NONCE_LEN: usize = 12;
+
+/// The number of bytes of a ChaCha20-Poly1305 key, and of a raw key file.
+pub const KEY_LEN: usize = 32;
+
+/// The number of bytes of the random salt of a new encrypted index.
+const SALT_LEN: usize = 16;
+
+/// Why the files of an index cannot be encrypted or decrypted, e.g. a
+/// missing or wrong key file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncryptionError(pub String);
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for EncryptionError {}
+
+/// Returns an `anyhow::Error` holding an `EncryptionError` with `message`.
+pub(crate) fn encryption_error(message: impl Into<String>) -> anyhow::Error {
+    anyhow::Error::new(EncryptionError(message.into()))
+}
+
+/// How the key of an encrypted index is derived from its key file,
+/// recorded in the manifest of the index when it is created.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum KeyDerivation {
+    /// The key is the Argon2id hash of the key file content, so that the
+    /// file may hold a passphrase.
+    Argon2id {
+        /// The random salt of the index, hex encoded.
+        salt: String,
+        /// The memory cost in KiB.
+        memory_kib: u32,
+        /// The number of passes over the memory.
+        iterations: u32,
+        /// The number of lanes.
+        parallelism: u32,
+    },
+    /// The key file holds the `KEY_LEN` bytes of the key itself.
+    Raw,
+}
+
+impl KeyDerivation {
+    /// Returns the key derivation of a new encrypted index: Argon2id with
+    /// the default costs and a fresh random salt, or the raw key file.
+    ///
+    /// # Arguments
+    /// * `raw` - Whether the key file holds the key itself.
+    pub fn generate(raw: bool) -> Self {
+        if raw {
+            return Self::Raw;
+        }
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self::Argon2id {
+            salt: hex_digest(&salt),
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+
+    /// Derives the key from the content of a key file.
+    ///
+    /// # Arguments
+    /// * `content` - The content of the key file.
+    ///
+    /// # Returns
+    /// The key, or an `anyhow::Result` error if a raw key file doesn't hold
+    /// exactly `KEY_LEN` bytes or the recorded parameters are invalid.
+    fn derive(&self, content: &[u8]) -> anyhow::Result<[u8; KEY_LEN]> {
+        match self {
+            Self::Argon2id {
+                salt,
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let salt = decode_hex(salt)
+                    .ok_or_else(|| encryption_error("the key salt of the index is not hex"))?;
+                let params = Params::new(*memory_kib, *iterations, *parallelism, Some(KEY_LEN))
+                    .map_err(|err| encryption_error(format!("invalid key derivation: {err}")))?;
+                let mut key = [0u8; KEY_LEN];
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(content, &salt, &mut key)
+                    .map_err(|err| encryption_error(format!("derive the index key: {err}")))?;
+                Ok(key)
+            }
+            Self::Raw => content.try_into().map_err(|_| {
+                encryption_error(format!(
+                    "a raw key file must hold exactly {KEY_LEN} bytes, not {}",
+                    content.len()
+                ))
+            }),
+        }
+    }
+}
+
+/// Decodes a lowercase or uppercase hex string.
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Encrypts and decrypts index files with ChaCha20-Poly1305.
+pub struct Cipher {
+    /// The AEAD instance keyed with the index key.
+    aead: ChaCha20Poly1305,
+}
+
+impl Cipher {
+    /// Creates a `Cipher` from a key file, deriving the key as the index
+    /// records. Indexes encrypted before the derivation was recorded use the
+    /// SHA-256 hash of the file content.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the key file.
+    /// * `derivation` - The key derivation of the index, if recorded.
+    ///
+    /// # Returns
+    /// The `Cipher`, or an `anyhow::Result` error if the file cannot be read,
+    /// is empty or doesn't suit the derivation.
+    pub fn from_key_file(path: &Path, derivation: Option<&KeyDerivation>) -> anyhow::Result<Self> {
+        let content = fs::read(path).with_context(|| format!("read key file {path:?}"))?;
+        if content.is_empty() {
+            return Err(encryption_error(format!("key file {path:?} is empty")));
+        }
+        let key: [u8; KEY_LEN] = match derivation {
+            Some(derivation) => derivation
+                .derive(&content)
+                .with_context(|| format!("key file {path:?}"))?,
+            None => Sha256::digest(&content).into(),
+        };
+        Ok(Self {
+            aead: ChaCha20Poly1305::new(Key::from_slice(&key)),
+        })
+    }
+
+    /// Encrypts `plaintext` under a fresh random nonce.
+    ///
+    /// # Arguments
+    /// * `plaintext` - The bytes to encrypt.
+    ///
+    /// # Returns
+    /// The nonce followed by the ciphertext, or an `anyhow::Result` error.
+    pub fn seal(&self, plaintext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .aead
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| encryption_error("encrypt index data"))?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypts bytes produced by `seal`.
+    ///
+    /// # Arguments
+    /// * `sealed` - The nonce followed by the ciphertext.
+    ///
+    /// # Returns
+    /// The plaintext, or an `anyhow::Result` error if the data was tampered
+    /// with or encrypted with another key.
+    pub fn open(&self, sealed: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return Err(encryption_error("encrypted index data is truncated"));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.aead
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| encryption_error("decrypt index data: wrong key or corrupted file"))
+    }
+}
+
+/// Encrypts `data` if a cipher is given, otherwise returns it unchanged.
+pub fn seal(cipher: Option<&Cipher>, data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    match cipher {
+        Some(cipher) => cipher.seal(&data),
+        None => Ok(data),
+    }
+}
+
+/// Decrypts `data` if a cipher is given, otherwise returns it unchanged.
+pub fn open(cipher: Option<&Cipher>, data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    match cipher {
+        Some(cipher) => cipher.open(&data),
+        None => Ok(data),
+    }
+}
+
+/// Returns the cipher of the index in `index_dir` for the files read
+/// without opening the index, e.g. its run reports.
+///
+/// # Arguments
+/// * `index_dir` - The directory containing the index files.
+/// * `key_file` - The key file of an encrypted index, if any.
+///
+/// # Returns
+/// The `Cipher`, `None` if the index is not encrypted, or an
+/// `anyhow::Result` error if the index is encrypted but no key file was
+/// given, or the other way round.
+pub fn index_cipher(index_dir: &Path, key_file: Option<&Path>) -> anyhow::Result<Option<Cipher>> {
+    let manifest = Manifest::load(index_dir).context("load manifest")?;
+    match key_file {
+        Some(_) if !manifest.encrypted => Err(encryption_error(format!(
+            "index {index_dir:?} is not encrypted; open it without a key file"
+        ))),
+        Some(key_file) => {
+            Cipher::from_key_file(key_file, manifest.key_derivation.as_ref()).map(Some)
+        }
+        None if manifest.encrypted => Err(encryption_error(format!(
+            "index {index_dir:?} is encrypted; a key file is required"
+        ))),
+        None => Ok(None),
+    }
+}
+
+/// Returns the cipher of an index opened for writing with a key file. An
+/// index without documents yet becomes encrypted, its key derivation
+/// generated and recorded in `manifest`.
+///
+/// # Arguments
+/// * `index_dir` - The directory containing the index files, for the error
+///   message.
+/// * `manifest` - The manifest of the index.
+/// * `has_documents` - Whether the index already has a document store.
+/// * `key_file` - The key file of the index.
+/// * `raw` - Whether the key file of a new index holds the key itself.
+///
+/// # Returns
+/// The `Cipher`, or an `anyhow::Result` error if the index has plaintext
+/// documents or the key file cannot be used.
+pub(crate) fn writer_cipher(
+    index_dir: &Path,
+    manifest: &mut Manifest,
+    has_documents: bool,
+    key_file: &Path,
+    raw: bool,
+) -> anyhow::Result<Cipher> {
+    if !manifest.encrypted {
+        if has_documents {
+            return Err(encryption_error(format!(
+                "index {index_dir:?} is not encrypted; open it without a key file"
+            )));
+        }
+        manifest.encrypted = true;
+        manifest.key_derivation = Some(KeyDerivation::generate(raw));
+    }
+    Cipher::from_key_file(key_file, manifest.key_derivation.as_ref())
+}
+
+/// Returns the cipher of the index in `index_dir` for an indexing run
+/// before the index is opened, e.g. to encrypt the checkpoint of the run. A
+/// new index is marked encrypted right away, so that the run opens it with
+/// the same key.
+///
+/// # Arguments
+/// * `index_dir` - The directory containing the index files.
+/// * `key_file` - The key file of the index.
+/// * `raw` - Whether the key file of a new index holds the key itself.
+///
+/// # Returns
+/// The `Cipher`, or an `anyhow::Result` error if the index has plaintext
+/// documents or the key file cannot be used.
+pub(crate) fn indexing_cipher(
+    index_dir: &Path,
+    key_file: &Path,
+    raw: bool,
+) -> anyhow::Result<Cipher> {
+    let index_dir = resolve_index(index_dir);
+    let mut manifest = Manifest::load(&index_dir).context("load manifest")?;
+    if manifest.encrypted {
+        return Cipher::from_key_file(key_file, manifest.key_derivation.as_ref());
+    }
+    let has_documents = index_dir.join("docstore.bin").exists();
+    let cipher = writer_cipher(&index_dir, &mut manifest, has_documents, key_file, raw)?;
+    fs::create_dir_all(&index_dir).context("create index directory")?;
+    manifest.save(&index_dir)?;
+    Ok(cipher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempIndex;
+
+    fn key_file(dir: &TempIndex, content: &[u8]) -> std::path::PathBuf {
+        let path = dir.path().join("key");
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn argon2id_keys_depend_on_the_salt() {
+        let dir = TempIndex::new().unwrap();
+        let path = key_file(&dir, b"correct horse battery staple");
+        let derivation = KeyDerivation::generate(false);
+        let cipher = Cipher::from_key_file(&path, Some(&derivation)).unwrap();
+        let sealed = cipher.seal(b"secret").unwrap();
+
+        let same = Cipher::from_key_file(&path, Some(&derivation)).unwrap();
+        assert_eq!(same.open(&sealed).unwrap(), b"secret");
+        let other = Cipher::from_key_file(&path, Some(&KeyDerivation::generate(false))).unwrap();
+        assert!(other.open(&sealed).is_err());
+        let legacy = Cipher::from_key_file(&path, None).unwrap();
+        assert!(legacy.open(&sealed).is_err());
+    }
+
+    #[test]
+    fn raw_keys_must_be_32_bytes() {
+        let dir = TempIndex::new().unwrap();
+        let derivation = KeyDerivation::generate(true);
+        let path = key_file(&dir, &[7; KEY_LEN]);
+        let cipher = Cipher::from_key_file(&path, Some(&derivation)).unwrap();
+        let sealed = cipher.seal(b"secret").unwrap();
+        assert_eq!(cipher.open(&sealed).unwrap(), b"secret");
+
+        let path = key_file(&dir, b"a passphrase");
+        let err = Cipher::from_key_file(&path, Some(&derivation))
+            .err()
+            .unwrap();
+        assert!(err.downcast_ref::<EncryptionError>().is_some());
+    }
+
+    #[test]
+    fn new_indexes_record_their_key_derivation() {
+        let dir = TempIndex::new().unwrap();
+        let path = key_file(&dir, b"passphrase");
+        let index_dir = dir.path().join("index");
+        let cipher = indexing_cipher(&index_dir, &path, false).unwrap();
+        let manifest = Manifest::load(&index_dir).unwrap();
+        assert!(manifest.encrypted);
+        assert!(matches!(
+            manifest.key_derivation,
+            Some(KeyDerivation::Argon2id { .. })
+        ));
+
+        let sealed = cipher.seal(b"secret").unwrap();
+        let reopened = index_cipher(&index_dir, Some(&path)).unwrap().unwrap();
+        assert_eq!(reopened.open(&sealed).unwrap(), b"secret");
+    }
+}
@@ -0,0 +1,117 @@
+use anyhow::Context;
+use serde::Serialize;
+
+use crate::tree::{DocInfo, MainIndex};
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// A document found in only one of the compared indexes.
+#[derive(Serialize, Debug)]
+pub struct DiffDocument {
+    /// The URI of the document.
+    pub path: PathBuf,
+    /// The number of tokens indexed for it.
+    pub tokens: u64,
+}
+
+/// A document indexed again between the compared indexes.
+#[derive(Serialize, Debug)]
+pub struct ReindexedDocument {
+    /// The URI of the document.
+    pub path: PathBuf,
+    /// The number of tokens indexed for it in the older index.
+    pub tokens_before: u64,
+    /// The number of tokens indexed for it in the newer index.
+    pub tokens_after: u64,
+    /// Whether its content changed, rather than only being indexed again.
+    pub content_changed: bool,
+}
+
+/// What `indexer diff` reports about two snapshots of an index.
+#[derive(Serialize, Debug)]
+pub struct IndexDiff {
+    /// The generations of the older and the newer index.
+    pub generations: (u64, u64),
+    /// The documents only in the newer index, by path.
+    pub added: Vec<DiffDocument>,
+    /// The documents only in the older index, by path.
+    pub removed: Vec<DiffDocument>,
+    /// The documents indexed again in the newer index, by path.
+    pub reindexed: Vec<ReindexedDocument>,
+    /// The number of distinct terms of the older and the newer index.
+    pub terms: (u64, u64),
+    /// The number of tokens of the older and the newer index.
+    pub tokens: (u64, u64),
+}
+
+/// Compares two snapshots of an index, e.g. copies taken before and after a
+/// scheduled run or two published builds, to audit what changed between
+/// them.
+///
+/// # Arguments
+/// * `before` - The directory of the older index.
+/// * `after` - The directory of the newer index.
+/// * `key_file` - The key file of the indexes, if they are encrypted.
+///
+/// # Returns
+/// The `IndexDiff`, or an `anyhow::Result` error if either index cannot be
+/// opened.
+pub fn diff_indexes(
+    before: &Path,
+    after: &Path,
+    key_file: Option<&Path>,
+) -> anyhow::Result<IndexDiff> {
+    let old = MainIndex::open_read_only(before, key_file)
+        .with_context(|| format!("open index {before:?}"))?;
+    let new = MainIndex::open_read_only(after, key_file)
+        .with_context(|| format!("open index {after:?}"))?;
+    let old_docs = live_documents(&old);
+    let new_docs = live_documents(&new);
+
+    let mut diff = IndexDiff {
+        generations: (old.manifest.generation, new.manifest.generation),
+        added: Vec::new(),
+        removed: Vec::new(),
+        reindexed: Vec::new(),
+        terms: (old.stats.term_count, new.stats.term_count),
+        tokens: (old.stats.total_tokens, new.stats.total_tokens),
+    };
+    for (path, info) in &new_docs {
+        match old_docs.get(path) {
+            None => diff.added.push(DiffDocument {
+                path: path.to_path_buf(),
+                tokens: info.token_count,
+            }),
+            Some(old_info) if old_info.indexed_at != info.indexed_at => {
+                diff.reindexed.push(ReindexedDocument {
+                    path: path.to_path_buf(),
+                    tokens_before: old_info.token_count,
+                    tokens_after: info.token_count,
+                    content_changed: old_info.content_hash != info.content_hash,
+                });
+            }
+            Some(_) => {}
+        }
+    }
+    for (path, info) in &old_docs {
+        if !new_docs.contains_key(path) {
+            diff.removed.push(DiffDocument {
+                path: path.to_path_buf(),
+                tokens: info.token_count,
+            });
+        }
+    }
+    Ok(diff)
+}
+
+/// Returns the documents of an index that are not deleted, by path.
+fn live_documents(main_index: &MainIndex) -> BTreeMap<&Path, &DocInfo> {
+    main_index
+        .doc_store
+        .id_to_doc_info
+        .iter()
+        .filter(|(id, _)| !main_index.doc_store.tombstones.contains(*id))
+        .map(|(_, info)| (info.path.as_path(), info))
+        .collect()
+}
@@ -0,0 +1,151 @@
+use anyhow::{Context, anyhow};
+use serde::Serialize;
+
+use crate::budget::load_evictions;
+use crate::policy::policy_for;
+use crate::report::{RenamedFile, RunReport, SkipKind, SkippedFile};
+use crate::sniff::sniff_type;
+use crate::store::is_index;
+use crate::tree::{DocumentStore, MainIndex};
+use crate::{Config, content_guard, get_docs, get_extensions_map, stale_documents};
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A file an indexing run would parse and index.
+#[derive(Serialize, Clone, Debug)]
+pub struct PlannedFile {
+    /// The path of the file.
+    pub path: PathBuf,
+    /// The parser that would handle the file, named by the extension of its
+    /// format, e.g. `pdf`, or `name` if only its name would be indexed.
+    pub parser: String,
+    /// Whether the format was told by the content of the file, as its
+    /// extension does not tell it.
+    pub sniffed: bool,
+}
+
+/// What an indexing run would do, found by `plan_index` without changing the
+/// index.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct DryRun {
+    /// The file or directory the run would index.
+    pub source: PathBuf,
+    /// The number of files found while walking `source`.
+    pub discovered: u64,
+    /// The files that would be (re-)indexed.
+    pub files: Vec<PlannedFile>,
+    /// The indexed files found at a new path, which would move without being
+    /// indexed again.
+    pub moved: Vec<RenamedFile>,
+    /// The files that would be left out, and why.
+    pub skipped: Vec<SkippedFile>,
+}
+
+/// Finds what indexing `cfg.filepath` into `cfg.index_path` would do,
+/// without writing anything: the files are discovered with the hidden,
+/// skip, symbolic link and extension rules of the run and checked against
+/// the index, if it exists, for moved and unchanged ones. Duplicates, parse
+/// errors and the budgets of the run are only found while indexing.
+///
+/// # Arguments
+/// * `cfg` - The `Config` of the run.
+///
+/// # Returns
+/// The `DryRun`, or an `anyhow::Result` error if the path cannot be walked
+/// or the index cannot be read.
+pub fn plan_index(cfg: &Config) -> anyhow::Result<DryRun> {
+    if !cfg.filepath.exists() {
+        return Err(anyhow!("{:?} does not exist", cfg.filepath));
+    }
+    let mut report = RunReport::new(&cfg.filepath);
+    let (docs, modified) = get_docs(
+        cfg.filepath.clone(),
+        cfg.hidden,
+        cfg.follow_symlinks,
+        &cfg.skip,
+        cfg.discovery,
+        &mut report,
+    )?;
+    let discovered = docs.len() as u64;
+
+    // The document store is only changed in memory
+    let (mut doc_store, evictions) = if is_index(&cfg.index_path) {
+        let main_index = MainIndex::open_read_only(&cfg.index_path, cfg.key_file.as_deref())
+            .context("open main index")?;
+        let evictions = load_evictions(&cfg.index_path, main_index.cipher());
+        (main_index.doc_store, evictions)
+    } else {
+        (DocumentStore::default(), HashMap::new())
+    };
+    let moved = doc_store
+        .detect_renames(&docs)
+        .into_iter()
+        .map(|(from, to)| RenamedFile { from, to })
+        .collect();
+    let docs = stale_documents(
+        docs,
+        &cfg.policies,
+        &doc_store,
+        &modified,
+        &evictions,
+        cfg.sniffing,
+        &mut report,
+    );
+
+    let mut files = Vec::new();
+    for doc in docs {
+        match plan_file(&doc, cfg) {
+            Ok(file) => files.push(file),
+            Err((kind, reason)) => report.skip(&doc, kind, reason),
+        }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut skipped = report.skipped;
+    skipped.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(DryRun {
+        source: cfg.filepath.clone(),
+        discovered,
+        files,
+        moved,
+        skipped,
+    })
+}
+
+/// Tells which parser would handle a document to index, the way the run
+/// types it.
+///
+/// # Returns
+/// The `PlannedFile`, or the kind and reason of the document being left
+/// out.
+fn plan_file(doc: &Path, cfg: &Config) -> Result<PlannedFile, (SkipKind, String)> {
+    let policy = policy_for(&cfg.policies, doc);
+    let planned = |parser: &str, sniffed| PlannedFile {
+        path: doc.to_path_buf(),
+        parser: parser.to_string(),
+        sniffed,
+    };
+    if policy.filenames_only {
+        return Ok(planned("name", false));
+    }
+    let ext = doc
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let (ext, sniffed) = if get_extensions_map().contains_key(&ext) {
+        (ext, false)
+    } else if let Some(sniffed) = sniff_type(doc, cfg.sniffing) {
+        (sniffed.to_string(), true)
+    } else if ext.is_empty() {
+        return Err((SkipKind::NoExtension, "no extension".to_string()));
+    } else {
+        return Err((
+            SkipKind::UnsupportedExtension,
+            format!("unsupported extension .{ext}"),
+        ));
+    };
+    if let Some(skipped) = content_guard(doc, &ext, cfg.max_file_size) {
+        return Err(skipped);
+    }
+    Ok(planned(&ext, sniffed))
+}
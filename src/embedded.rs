@@ -0,0 +1,343 @@
+use anyhow::{Context, anyhow};
+use mailparse::{DispositionType, ParsedMail};
+
+use crate::parsers::mbox_messages;
+use crate::tar::{ArchiveContent, for_each_entry, open_archive};
+use crate::temp::temp_path;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// The prefix of the URIs of documents embedded in another one, e.g.
+/// `embedded:/mail/a.eml!/report.pdf`.
+pub const EMBEDDED_URI_PREFIX: &str = "embedded:";
+
+/// Separates a container from the name of a document embedded in it.
+pub const EMBEDDED_SEPARATOR: &str = "!/";
+
+/// How deep embedded documents are followed, e.g. a zip attached to a mail
+/// is depth 2.
+pub const MAX_EMBEDDED_DEPTH: usize = 3;
+
+/// Embedded documents larger than this many bytes are left out.
+pub const MAX_EMBEDDED_BYTES: u64 = 64 * 1024 * 1024;
+
+/// A document found inside a container.
+pub struct Embedded {
+    /// The name of the document within its container, e.g. the file name of
+    /// an attachment or the path of a zip entry.
+    pub name: String,
+    /// The content of the document.
+    pub content: Vec<u8>,
+}
+
+impl Embedded {
+    /// Returns the extension of the document name, if any.
+    pub fn extension(&self) -> Option<String> {
+        Path::new(&self.name)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+    }
+}
+
+/// A temporary file holding an embedded document so that the file parsers
+/// can read it, removed with its directory when dropped.
+pub struct ExtractedFile {
+    /// The private directory created for the file.
+    dir: PathBuf,
+    /// The file within `dir`.
+    path: PathBuf,
+}
+
+impl ExtractedFile {
+    /// Writes `embedded` into a new temporary file keeping its extension.
+    /// The file is created in a directory of its own, only accessible to
+    /// the current user, and neither is ever opened if it already exists,
+    /// so that another user cannot plant a file or a symlink at its
+    /// predictable path.
+    ///
+    /// # Returns
+    /// The `ExtractedFile`, or an `anyhow::Result` error if it cannot be
+    /// written.
+    pub fn new(embedded: &Embedded) -> anyhow::Result<Self> {
+        let dir = temp_path();
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&dir).context("create extraction dir")?;
+        let mut name = String::from("embedded");
+        if let Some(ext) = embedded.extension() {
+            name.push('.');
+            name.push_str(&ext);
+        }
+        let extracted = Self {
+            path: dir.join(name),
+            dir,
+        };
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&extracted.path)
+            .context("create embedded document file")?;
+        file.write_all(&embedded.content)
+            .context("write embedded document")?;
+        Ok(extracted)
+    }
+
+    /// Returns the path of the temporary file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ExtractedFile {
+    /// Removes the temporary file and its directory.
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Returns `true` if documents with the extension `ext` can embed other
+/// documents.
+pub fn is_container(ext: &str) -> bool {
+    matches!(
+        ext,
+        "eml" | "mbox" | "zip" | "tar" | "tgz" | "gz" | "docx" | "pptx"
+    )
+}
+
+/// Returns the URI of the document `name` embedded in the document at
+/// `parent`, itself possibly embedded.
+pub fn child_uri(parent: &Path, name: &str) -> PathBuf {
+    let parent = parent.to_string_lossy();
+    if parent.starts_with(EMBEDDED_URI_PREFIX) {
+        PathBuf::from(format!("{parent}{EMBEDDED_SEPARATOR}{name}"))
+    } else {
+        PathBuf::from(format!(
+            "{EMBEDDED_URI_PREFIX}{parent}{EMBEDDED_SEPARATOR}{name}"
+        ))
+    }
+}
+
+/// Returns the file holding the outermost container of an embedded
+/// document, or `None` if `uri` is not an embedded document URI.
+pub fn container_file(uri: &Path) -> Option<PathBuf> {
+    let uri = uri.to_string_lossy();
+    let rest = uri.strip_prefix(EMBEDDED_URI_PREFIX)?;
+    let (file, _) = rest.split_once(EMBEDDED_SEPARATOR)?;
+    Some(PathBuf::from(file))
+}
+
+/// Extracts the documents embedded in a container one at a time: the
+/// attachments of a mail, the messages of an mbox archive, named `<n>.eml`
+/// after their position in it, the entries of a zip or tar archive or of a
+/// docx or pptx document, or the file compressed in a gzip file. Documents
+/// larger than `MAX_EMBEDDED_BYTES` are left out, whatever size the
+/// container claims for them.
+///
+/// # Arguments
+/// * `path` - The file holding the container.
+/// * `ext` - The extension of the container.
+/// * `visit` - Called with each embedded document, before the next one is
+///   read.
+///
+/// # Returns
+/// An `anyhow::Result` error if the container cannot be read or `visit`
+/// fails.
+pub fn for_each_embedded(
+    path: &Path,
+    ext: &str,
+    mut visit: impl FnMut(Embedded) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    match ext {
+        "eml" => {
+            let raw = fs::read(path).context("read mail")?;
+            let mail = mailparse::parse_mail(&raw).context("parse mail")?;
+            let mut attachments = Vec::new();
+            mail_attachments(&mail, &mut attachments);
+            attachments.into_iter().try_for_each(visit)
+        }
+        "mbox" => {
+            let raw = fs::read(path).context("read mbox")?;
+            mbox_messages(&raw)
+                .into_iter()
+                .enumerate()
+                .filter(|(_, content)| content.len() as u64 <= MAX_EMBEDDED_BYTES)
+                .try_for_each(|(i, content)| {
+                    visit(Embedded {
+                        name: format!("{}.eml", i + 1),
+                        content,
+                    })
+                })
+        }
+        "zip" | "docx" | "pptx" => zip_entries(
+            path,
+            |name| {
+                ext == "zip"
+                    || name.starts_with("word/embeddings/")
+                    || name.starts_with("ppt/embeddings/")
+            },
+            visit,
+        ),
+        "tar" | "tgz" | "gz" => archive_entries(path, visit),
+        _ => Err(anyhow!("{ext} documents don't embed other documents")),
+    }
+}
+
+/// Reads `content` up to `MAX_EMBEDDED_BYTES`, reserving at most `size`
+/// bytes up front.
+///
+/// # Returns
+/// The content, `None` if it is larger than `MAX_EMBEDDED_BYTES`, or an
+/// `io::Error`.
+fn read_bounded(content: impl Read, size: u64) -> std::io::Result<Option<Vec<u8>>> {
+    let mut buf = Vec::with_capacity(size.min(MAX_EMBEDDED_BYTES) as usize);
+    content.take(MAX_EMBEDDED_BYTES + 1).read_to_end(&mut buf)?;
+    Ok((buf.len() as u64 <= MAX_EMBEDDED_BYTES).then_some(buf))
+}
+
+/// Collects the attachments of a MIME part and its subparts.
+fn mail_attachments(part: &ParsedMail, attachments: &mut Vec<Embedded>) {
+    for sub in &part.subparts {
+        mail_attachments(sub, attachments);
+    }
+    let disposition = part.get_content_disposition();
+    let name = disposition
+        .params
+        .get("filename")
+        .or_else(|| part.ctype.params.get("name"));
+    let Some(name) = name else {
+        return;
+    };
+    if disposition.disposition != DispositionType::Attachment
+        && part.ctype.mimetype.starts_with("text/")
+    {
+        return;
+    }
+    if let Ok(content) = part.get_body_raw()
+        && content.len() as u64 <= MAX_EMBEDDED_BYTES
+    {
+        attachments.push(Embedded {
+            name: name.clone(),
+            content,
+        });
+    }
+}
+
+/// Visits the file entries of a zip archive whose name is accepted by
+/// `keep`. The sizes recorded in the archive are not trusted: entries are
+/// decompressed up to `MAX_EMBEDDED_BYTES` and left out past it.
+fn zip_entries(
+    path: &Path,
+    keep: impl Fn(&str) -> bool,
+    mut visit: impl FnMut(Embedded) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let file = File::open(path).context("open archive")?;
+    let mut archive = zip::ZipArchive::new(file).context("read archive")?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).context("read archive entry")?;
+        if entry.is_dir() || entry.size() > MAX_EMBEDDED_BYTES || !keep(entry.name()) {
+            continue;
+        }
+        let name = entry.name().to_string();
+        let size = entry.size();
+        let Some(content) =
+            read_bounded(&mut entry, size).with_context(|| format!("extract {name}"))?
+        else {
+            continue;
+        };
+        visit(Embedded { name, content })?;
+    }
+    Ok(())
+}
+
+/// Visits the file entries of a tar archive, compressed or not, or the file
+/// compressed in a gzip file, named after the archive without its `.gz`
+/// extension.
+fn archive_entries(
+    path: &Path,
+    mut visit: impl FnMut(Embedded) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    match open_archive(path)? {
+        ArchiveContent::Tar(reader) => for_each_entry(reader, |name, size, content| {
+            if size > MAX_EMBEDDED_BYTES {
+                return Ok(());
+            }
+            match read_bounded(content, size).with_context(|| format!("extract {name}"))? {
+                Some(content) => visit(Embedded {
+                    name: name.to_string(),
+                    content,
+                }),
+                None => Ok(()),
+            }
+        }),
+        ArchiveContent::File { name, reader } => {
+            match read_bounded(reader, 0).context("decompress file")? {
+                Some(content) => visit(Embedded { name, content }),
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempIndex;
+
+    #[test]
+    fn zip_entries_are_visited_in_order() {
+        let temp = TempIndex::new().unwrap();
+        let path = temp.path().join("docs.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        for (name, content) in [("a.txt", "first"), ("dir/b.md", "second")] {
+            zip.start_file(name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let mut visited = Vec::new();
+        for_each_embedded(&path, "zip", |embedded| {
+            visited.push((embedded.name, String::from_utf8(embedded.content)?));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            visited,
+            [
+                ("a.txt".to_string(), "first".to_string()),
+                ("dir/b.md".to_string(), "second".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn oversized_content_is_left_out() {
+        let small = read_bounded(&b"text"[..], u64::MAX).unwrap();
+        assert_eq!(small.as_deref(), Some(&b"text"[..]));
+        let large = std::io::repeat(0).take(MAX_EMBEDDED_BYTES + 1);
+        assert_eq!(read_bounded(large, 1).unwrap(), None);
+    }
+
+    #[test]
+    fn extracted_files_live_in_a_private_directory() {
+        let embedded = Embedded {
+            name: "report.PDF".to_string(),
+            content: b"%PDF".to_vec(),
+        };
+        let extracted = ExtractedFile::new(&embedded).unwrap();
+        let path = extracted.path().to_path_buf();
+        assert_eq!(fs::read(&path).unwrap(), b"%PDF");
+        assert_eq!(path.extension().unwrap(), "pdf");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let dir = path.parent().unwrap();
+            assert_eq!(dir.metadata().unwrap().permissions().mode() & 0o777, 0o700);
+        }
+        drop(extracted);
+        assert!(!path.parent().unwrap().exists());
+    }
+}
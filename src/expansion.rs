@@ -0,0 +1,64 @@
+use anyhow::{Context, anyhow};
+
+use crate::analyzer::fold_case;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Reads an expansion dictionary: one abbreviation per line followed by `=`
+/// and the words it stands for, e.g. `k8s = kubernetes`. Empty lines and
+/// lines starting with `#` are ignored.
+///
+/// # Arguments
+/// * `path` - The dictionary file.
+///
+/// # Returns
+/// The expansions keyed by their case folded abbreviation, or an
+/// `anyhow::Result` error naming the offending line.
+pub fn load_expansions(path: &Path) -> anyhow::Result<BTreeMap<String, String>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("read expansions file {path:?}"))?;
+    parse_expansions(&content).with_context(|| format!("parse expansions file {path:?}"))
+}
+
+/// Parses the content of an expansion dictionary as described in
+/// `load_expansions`.
+///
+/// # Arguments
+/// * `content` - The dictionary content.
+///
+/// # Returns
+/// The expansions keyed by their case folded abbreviation, or an
+/// `anyhow::Result` error.
+pub fn parse_expansions(content: &str) -> anyhow::Result<BTreeMap<String, String>> {
+    let mut expansions = BTreeMap::new();
+    for (line_no, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((abbreviation, expansion)) = line.split_once('=') else {
+            return Err(anyhow!(
+                "line {}: expected `<abbreviation> = <expansion>`, got {line:?}",
+                line_no + 1
+            ));
+        };
+        let abbreviation = fold_case(abbreviation.trim());
+        let expansion = expansion.trim();
+        if abbreviation.is_empty() || !abbreviation.chars().all(char::is_alphanumeric) {
+            return Err(anyhow!(
+                "line {}: the abbreviation {abbreviation:?} must be a single word",
+                line_no + 1
+            ));
+        }
+        if !expansion.chars().any(char::is_alphanumeric) {
+            return Err(anyhow!(
+                "line {}: {abbreviation:?} expands to no words",
+                line_no + 1
+            ));
+        }
+        expansions.insert(abbreviation, expansion.to_string());
+    }
+    Ok(expansions)
+}
@@ -0,0 +1,242 @@
+use anyhow::Context;
+use serde_json::{Map, Value, json};
+
+use crate::lexer::exact_word;
+use crate::tree::MainIndex;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// The on-disk formats an index can be exported to.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum ExportFormat {
+    /// One JSON document per line as consumed by `tantivy index`.
+    Tantivy,
+    /// Elasticsearch/OpenSearch `_bulk` API action and document lines.
+    EsBulk,
+}
+
+/// The name of the Elasticsearch index targeted by the bulk action lines.
+const ES_INDEX_NAME: &str = "indexer";
+
+/// The tantivy schema matching the documents emitted by `export_tantivy`.
+/// It is written next to the exported documents so that a tantivy index can
+/// be created with `tantivy new` before ingesting them.
+const TANTIVY_SCHEMA: &str = r#"[
+  {
+    "name": "path",
+    "type": "text",
+    "options": {
+      "indexing": { "record": "basic", "fieldnorms": false, "tokenizer": "raw" },
+      "stored": true
+    }
+  },
+  {
+    "name": "body",
+    "type": "text",
+    "options": {
+      "indexing": { "record": "freq", "fieldnorms": true, "tokenizer": "default" },
+      "stored": false
+    }
+  }
+]
+"#;
+
+/// Exports every document of the index in `index_dir` to `output` using the
+/// given format.
+///
+/// # Arguments
+/// * `index_dir` - The directory containing the index files.
+/// * `output` - The file the exported documents are written to.
+/// * `format` - The `ExportFormat` to write.
+/// * `key_file` - The key file of an encrypted index, if any.
+///
+/// # Returns
+/// The number of exported documents, or an `anyhow::Result` error.
+pub fn export_index(
+    index_dir: &Path,
+    output: &Path,
+    format: ExportFormat,
+    key_file: Option<&Path>,
+) -> anyhow::Result<u64> {
+    let main_index = MainIndex::open_read_only(index_dir, key_file).context("open main index")?;
+    let mut writer = BufWriter::new(File::create(output).context("create export file")?);
+
+    let count = match format {
+        ExportFormat::Tantivy => {
+            let count = export_tantivy(&main_index, &mut writer)?;
+            let schema_path = output.with_extension("schema.json");
+            std::fs::write(&schema_path, TANTIVY_SCHEMA).context("write tantivy schema")?;
+            count
+        }
+        ExportFormat::EsBulk => export_es_bulk(&main_index, &mut writer)?,
+    };
+
+    writer.flush().context("flush export writer")?;
+    Ok(count)
+}
+
+/// Writes one tantivy JSON document per indexed document. The `body` field
+/// repeats every stored term as many times as it occurs in the document so
+/// that tantivy reconstructs the same term frequencies.
+///
+/// # Arguments
+/// * `main_index` - The index to export.
+/// * `writer` - The destination of the JSON lines.
+///
+/// # Returns
+/// The number of exported documents, or an `anyhow::Result` error.
+fn export_tantivy(main_index: &MainIndex, writer: &mut impl Write) -> anyhow::Result<u64> {
+    let mut count = 0;
+    for (path, terms) in main_index.document_terms()? {
+        let body = terms
+            .iter()
+            .filter(|(term, _)| exact_word(term).is_none())
+            .flat_map(|(term, tf)| std::iter::repeat_n(term.as_str(), *tf as usize))
+            .collect::<Vec<&str>>()
+            .join(" ");
+
+        let doc = json!({
+            "path": path.to_string_lossy(),
+            "body": body,
+        });
+        writeln!(writer, "{doc}").context("write tantivy document")?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Writes an `{index}` action line followed by a
+/// `{path, content_terms, metadata}` document for every indexed document, in
+/// the newline delimited format accepted by the Elasticsearch `_bulk` API.
+///
+/// # Arguments
+/// * `main_index` - The index to export.
+/// * `writer` - The destination of the bulk lines.
+///
+/// # Returns
+/// The number of exported documents, or an `anyhow::Result` error.
+fn export_es_bulk(main_index: &MainIndex, writer: &mut impl Write) -> anyhow::Result<u64> {
+    let mut count = 0;
+    for (path, terms) in main_index.document_terms()? {
+        let path_str = path.to_string_lossy();
+        let content_terms = terms
+            .iter()
+            .filter(|(term, _)| exact_word(term).is_none())
+            .flat_map(|(term, tf)| std::iter::repeat_n(term.as_str(), *tf as usize))
+            .collect::<Vec<&str>>();
+
+        let indexed_at = main_index
+            .doc_store
+            .doc_to_id
+            .get(&path)
+            .and_then(|id| main_index.doc_store.id_to_doc_info.get(id))
+            .and_then(|info| info.indexed_at.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let action = json!({ "index": { "_index": ES_INDEX_NAME, "_id": path_str } });
+        let doc = json!({
+            "path": path_str,
+            "content_terms": content_terms,
+            "metadata": {
+                "indexed_at": indexed_at,
+                "extension": path.extension().map(|e| e.to_string_lossy()),
+                "unique_terms": terms.len(),
+            },
+        });
+        writeln!(writer, "{action}").context("write bulk action")?;
+        writeln!(writer, "{doc}").context("write bulk document")?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Imports a tantivy document dump (one JSON object per line, as produced by
+/// `tantivy search` or `export_tantivy`) into the index in `index_dir`.
+/// The `path` field (or `path_field` if given) names the document and every
+/// other string field is tokenized as its content.
+///
+/// # Arguments
+/// * `input` - The JSON lines file to import.
+/// * `index_dir` - The directory of the index the documents are added to.
+/// * `path_field` - The field holding the document path.
+/// * `key_file` - The key file of an encrypted index, if any.
+///
+/// # Returns
+/// The number of imported documents, or an `anyhow::Result` error.
+pub fn import_tantivy(
+    input: &Path,
+    index_dir: &Path,
+    path_field: &str,
+    key_file: Option<&Path>,
+) -> anyhow::Result<u64> {
+    let reader = BufReader::new(File::open(input).context("open import file")?);
+    let mut main_index = MainIndex::open(index_dir, key_file).context("open main index")?;
+    let analyzer = main_index.analyzer();
+    let mut display_forms = HashMap::new();
+    let mut count = 0;
+
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line.context("read import line")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let doc: Map<String, Value> = serde_json::from_str(&line)
+            .with_context(|| format!("parse document on line {}", line_no + 1))?;
+
+        let path = match doc.get(path_field).and_then(first_string) {
+            Some(p) => PathBuf::from(p),
+            None => continue,
+        };
+
+        let mut content = String::new();
+        for (field, value) in &doc {
+            if field == path_field {
+                continue;
+            }
+            collect_strings(value, &mut content);
+        }
+
+        let tokens = analyzer.analyze(&content);
+        if tokens.is_empty() {
+            continue;
+        }
+        analyzer.record_display_forms(&content, &mut display_forms);
+        main_index
+            .add_document(&path, &tokens)
+            .context("add imported document")?;
+        count += 1;
+    }
+
+    main_index.add_display_forms(display_forms);
+    main_index.commit().context("commit imported documents")?;
+    Ok(count)
+}
+
+/// Returns the first string of a tantivy field value, which is either a plain
+/// string or an array of values.
+fn first_string(value: &Value) -> Option<&str> {
+    match value {
+        Value::String(s) => Some(s),
+        Value::Array(values) => values.iter().find_map(first_string),
+        _ => None,
+    }
+}
+
+/// Appends every string contained in `value` to `content`, separated by
+/// whitespace.
+fn collect_strings(value: &Value, content: &mut String) {
+    match value {
+        Value::String(s) => {
+            content.push_str(s);
+            content.push(' ');
+        }
+        Value::Array(values) => values.iter().for_each(|v| collect_strings(v, content)),
+        Value::Object(fields) => fields.values().for_each(|v| collect_strings(v, content)),
+        _ => {}
+    }
+}
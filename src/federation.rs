@@ -0,0 +1,183 @@
+use anyhow::{Context, anyhow};
+use serde::Deserialize;
+use serde_json::json;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+/// How long a remote server may take to answer.
+const REMOTE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The number of results requested from each remote server per query.
+pub const DEFAULT_REMOTE_RESULTS: usize = 50;
+
+/// One hit of a remote `POST /api/search/batch` response.
+#[derive(Deserialize)]
+struct RemoteHit {
+    path: PathBuf,
+    score: f64,
+}
+
+/// The results of one query of a remote `POST /api/search/batch` response.
+#[derive(Deserialize)]
+struct RemoteResults {
+    #[serde(default)]
+    results: Vec<RemoteHit>,
+    #[serde(default)]
+    partial: bool,
+    /// Why the server could not search the query, e.g. it was reduced to
+    /// nothing by analysis.
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// A search result of a federated search.
+#[derive(Debug, Clone)]
+pub struct FederatedHit {
+    /// The base URL of the server that returned the document.
+    pub remote: String,
+    /// The path of the document on that server.
+    pub path: PathBuf,
+    /// The score of the document divided by the best score of its server,
+    /// so that the scores of different indexes can be compared.
+    pub score: f64,
+}
+
+/// The results of a federated search.
+#[derive(Debug, Default)]
+pub struct FederatedResults {
+    /// The hits of every server, in descending order of normalized score.
+    pub hits: Vec<FederatedHit>,
+    /// The servers that could not be searched, with the error.
+    pub failed: Vec<(String, String)>,
+    /// The servers whose results are partial because of broken segments.
+    pub partial: Vec<String>,
+}
+
+/// Searches several running `indexer serve` instances at once through their
+/// JSON API and merges their results.
+pub struct FederatedSearcher {
+    /// The base URLs of the servers, e.g. `http://host:8765`.
+    remotes: Vec<String>,
+    /// The number of results requested from each server.
+    k: usize,
+    /// The bearer tokens sent to the servers that require one, keyed by
+    /// their base URL.
+    tokens: HashMap<String, String>,
+    /// The HTTP agent shared by the requests.
+    agent: ureq::Agent,
+}
+
+impl FederatedSearcher {
+    /// Creates a `FederatedSearcher` over the servers at `remotes`.
+    ///
+    /// # Arguments
+    /// * `remotes` - The base URLs of the servers.
+    /// * `k` - The number of results requested from each server.
+    pub fn new(remotes: Vec<String>, k: usize) -> Self {
+        let remotes = remotes
+            .into_iter()
+            .map(|remote| remote.trim_end_matches('/').to_string())
+            .collect();
+        Self {
+            remotes,
+            k,
+            tokens: HashMap::new(),
+            agent: ureq::AgentBuilder::new().timeout(REMOTE_TIMEOUT).build(),
+        }
+    }
+
+    /// Sends `token` as `Authorization: Bearer <token>` to the server at
+    /// `remote`, for servers started with `--auth-token`.
+    ///
+    /// # Arguments
+    /// * `remote` - The base URL of the server, as given to `new`.
+    /// * `token` - The token the server requires.
+    pub fn with_token(mut self, remote: &str, token: String) -> Self {
+        self.tokens
+            .insert(remote.trim_end_matches('/').to_string(), token);
+        self
+    }
+
+    /// Sends a query to one server.
+    fn search_remote(&self, remote: &str, query: &str) -> anyhow::Result<RemoteResults> {
+        let body = json!({ "queries": [query], "k": self.k }).to_string();
+        let mut request = self
+            .agent
+            .post(&format!("{remote}/api/search/batch"))
+            .set("Content-Type", "application/json");
+        if let Some(token) = self.tokens.get(remote) {
+            request = request.set("Authorization", &format!("Bearer {token}"));
+        }
+        let response = request
+            .send_string(&body)
+            .context("send query")?
+            .into_string()
+            .context("read response")?;
+        let mut results: Vec<RemoteResults> =
+            serde_json::from_str(&response).context("parse response")?;
+        let results = results.pop().ok_or_else(|| anyhow!("empty response"))?;
+        match results.error {
+            Some(err) => Err(anyhow!(err)),
+            None => Ok(results),
+        }
+    }
+
+    /// Sends `query` to every server in parallel and merges their results.
+    /// The scores of every server are divided by its best score before
+    /// merging, as raw scores depend on the statistics of each index. Servers
+    /// that fail are reported instead of failing the search.
+    ///
+    /// # Arguments
+    /// * `query` - The query, sent as is to every server.
+    ///
+    /// # Returns
+    /// The merged `FederatedResults`.
+    pub fn search(&self, query: &str) -> FederatedResults {
+        let responses = thread::scope(|scope| {
+            let handles = self
+                .remotes
+                .iter()
+                .map(|remote| scope.spawn(move || (remote, self.search_remote(remote, query))))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        let mut merged = FederatedResults::default();
+        for (remote, response) in responses {
+            let response = match response {
+                Ok(response) => response,
+                Err(err) => {
+                    merged.failed.push((remote.clone(), format!("{err:#}")));
+                    continue;
+                }
+            };
+            if response.partial {
+                merged.partial.push(remote.clone());
+            }
+            let best = response
+                .results
+                .iter()
+                .map(|hit| hit.score)
+                .fold(0.0, f64::max);
+            merged
+                .hits
+                .extend(response.results.into_iter().map(|hit| FederatedHit {
+                    remote: remote.clone(),
+                    path: hit.path,
+                    score: if best > 0.0 { hit.score / best } else { 0.0 },
+                }));
+        }
+        merged.hits.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        merged
+    }
+}
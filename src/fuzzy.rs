@@ -0,0 +1,52 @@
+/// The largest edit distance a fuzzy search accepts.
+pub const MAX_FUZZY_DISTANCE: usize = 2;
+
+/// The number of index terms a query term is expanded to at most, the
+/// closest ones first.
+pub const MAX_FUZZY_EXPANSIONS: usize = 50;
+
+/// Returns `true` if `term` is expanded by a fuzzy search allowing
+/// `max_distance` edits: a plain word term, not an exact, n-gram or phrase
+/// term, and long enough that the edits leave most of it unchanged.
+///
+/// # Arguments
+/// * `term` - The analyzed query term.
+/// * `max_distance` - The largest edit distance accepted.
+pub fn is_fuzzy_candidate(term: &str, max_distance: usize) -> bool {
+    term.chars().all(char::is_alphabetic) && term.chars().count() > 2 * max_distance
+}
+
+/// Computes the Levenshtein distance between `a` and `b`, giving up as soon
+/// as it exceeds `max_distance`.
+///
+/// # Arguments
+/// * `a` - The first term.
+/// * `b` - The second term.
+/// * `max_distance` - The largest distance of interest.
+///
+/// # Returns
+/// The number of single character insertions, deletions and substitutions
+/// turning `a` into `b`, or `None` if it is above `max_distance`.
+pub fn edit_distance(a: &str, b: &str, max_distance: usize) -> Option<usize> {
+    let a = a.chars().collect::<Vec<char>>();
+    let b = b.chars().collect::<Vec<char>>();
+    if a.len().abs_diff(b.len()) > max_distance {
+        return None;
+    }
+
+    // Only the previous row of the distance matrix is kept
+    let mut prev = (0..=b.len()).collect::<Vec<usize>>();
+    for (i, ca) in a.iter().enumerate() {
+        let mut row = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != cb);
+            row[j + 1] = substitution.min(prev[j + 1] + 1).min(row[j] + 1);
+        }
+        if row.iter().all(|&distance| distance > max_distance) {
+            return None;
+        }
+        prev = row;
+    }
+    let distance = prev[b.len()];
+    (distance <= max_distance).then_some(distance)
+}
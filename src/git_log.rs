@@ -0,0 +1,150 @@
+use anyhow::{Context, anyhow};
+
+use crate::Config;
+use crate::report::{RunReport, SkipKind};
+use crate::walker::ModifiedTimes;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Separates the commits in the `git log` output.
+const RECORD_SEPARATOR: char = '\x1e';
+/// Separates the fields of a commit in the `git log` output.
+const FIELD_SEPARATOR: char = '\x1f';
+
+/// A commit read from the history of a git repository.
+pub struct Commit {
+    /// The full hash of the commit.
+    pub sha: String,
+    /// The author, as `Name <email>`.
+    pub author: String,
+    /// The author date, in ISO 8601 format.
+    pub date: String,
+    /// The full commit message.
+    pub message: String,
+    /// The paths touched by the commit.
+    pub paths: Vec<String>,
+}
+
+impl Commit {
+    /// Returns the text indexed for the commit: its message, author and
+    /// touched paths.
+    pub fn text(&self) -> String {
+        format!(
+            "{}\n{}\n{}",
+            self.message,
+            self.author,
+            self.paths.join("\n")
+        )
+    }
+}
+
+/// Returns the virtual path under which a commit is indexed,
+/// `git:<repository>@<sha>`.
+///
+/// # Arguments
+/// * `repo` - The path of the repository.
+/// * `sha` - The hash of the commit.
+pub fn commit_uri(repo: &Path, sha: &str) -> PathBuf {
+    PathBuf::from(format!("git:{}@{sha}", repo.display()))
+}
+
+/// Reads the history of a git repository with `git log`.
+///
+/// # Arguments
+/// * `repo` - The path of the repository, or of any directory within it.
+///
+/// # Returns
+/// The commits, newest first, or an `anyhow::Result` error if `git` fails.
+pub fn read_git_log(repo: &Path) -> anyhow::Result<Vec<Commit>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args([
+            "log",
+            "--name-only",
+            "--format=%x1e%H%x1f%an <%ae>%x1f%aI%x1f%B%x1f",
+        ])
+        .output()
+        .context("run git log")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git log failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let log = String::from_utf8_lossy(&output.stdout);
+    let commits = log
+        .split(RECORD_SEPARATOR)
+        .filter(|record| !record.trim().is_empty())
+        .filter_map(|record| {
+            let mut fields = record.splitn(5, FIELD_SEPARATOR);
+            Some(Commit {
+                sha: fields.next()?.trim().to_string(),
+                author: fields.next()?.to_string(),
+                date: fields.next()?.to_string(),
+                message: fields.next()?.trim().to_string(),
+                paths: fields
+                    .next()
+                    .unwrap_or_default()
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            })
+        })
+        .collect();
+    Ok(commits)
+}
+
+/// Indexes every commit of a git repository as a virtual document named
+/// after `commit_uri`, so that searches hit the history next to the code.
+/// Commits never change, so those already in the index are skipped.
+///
+/// # Arguments
+/// * `repo` - The path of the repository.
+/// * `cfg` - The indexing `Config`; its index path, key file and index wide
+///   settings are used.
+///
+/// # Returns
+/// The number of newly indexed commits, or an `anyhow::Result` error.
+pub fn index_git_log(repo: &Path, cfg: &Config) -> anyhow::Result<u64> {
+    let repo = repo.canonicalize().context("resolve repository path")?;
+    let commits = read_git_log(&repo)?;
+    let mut report = RunReport::new(&repo);
+    report.discovered = commits.len() as u64;
+
+    let mut main_index = crate::open_for_indexing(cfg)?;
+    let analyzer = main_index.analyzer();
+    let mut display_forms = HashMap::new();
+    let mut indexed = 0;
+    for commit in &commits {
+        let uri = commit_uri(&repo, &commit.sha);
+        if !main_index
+            .doc_store
+            .needs_indexing(&uri, &ModifiedTimes::new())
+        {
+            report.skip(&uri, SkipKind::UpToDate, "already indexed");
+            continue;
+        }
+        let text = commit.text();
+        analyzer.record_display_forms(&text, &mut display_forms);
+        let tokens = analyzer.analyze(&text);
+        match main_index.add_document(&uri, &tokens) {
+            Ok(()) => {
+                report.indexed.push(uri);
+                indexed += 1;
+            }
+            Err(err) => report.fail(&uri, format!("add to index: {err}")),
+        }
+    }
+
+    main_index.add_display_forms(display_forms);
+    main_index.commit().context("commit model")?;
+    report
+        .save(&main_index.index_dir, main_index.cipher())
+        .context("save run report")?;
+    Ok(indexed)
+}
@@ -0,0 +1,63 @@
+use std::path::Path;
+
+/// Matches a path against a glob pattern.
+///
+/// Supported syntax:
+/// * `?` matches any single character except `/`.
+/// * `*` matches any sequence of characters except `/`.
+/// * `**` matches any sequence of characters including `/`; `**/` also
+///   matches zero directories.
+///
+/// Patterns without a `/` are matched against the file name only, so that
+/// `*.min.js` matches the file wherever it lives.
+///
+/// # Arguments
+/// * `pattern` - The glob pattern.
+/// * `path` - The path to match.
+///
+/// # Returns
+/// `true` if the path matches the pattern, `false` otherwise.
+pub fn glob_match_path(pattern: &str, path: &Path) -> bool {
+    if !pattern.contains('/') {
+        let basename = path
+            .file_name()
+            .map(|v| v.to_string_lossy().to_string())
+            .unwrap_or_default();
+        return glob_match(pattern, &basename);
+    }
+    glob_match(pattern, &path.to_string_lossy())
+}
+
+/// Matches `text` against the glob `pattern` as described in
+/// `glob_match_path`, without the file name special case.
+///
+/// # Arguments
+/// * `pattern` - The glob pattern.
+/// * `text` - The text to match.
+///
+/// # Returns
+/// `true` if the text matches the pattern, `false` otherwise.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let text = text.chars().collect::<Vec<char>>();
+    matches(&pattern, &text)
+}
+
+/// Recursively matches the pattern characters against the text characters.
+fn matches(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            let rest = &pattern[2..];
+            if rest.first() == Some(&'/') && matches(&rest[1..], text) {
+                return true;
+            }
+            (0..=text.len()).any(|i| matches(rest, &text[i..]))
+        }
+        Some('*') => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| matches(&pattern[1..], &text[i..])),
+        Some('?') => !text.is_empty() && text[0] != '/' && matches(&pattern[1..], &text[1..]),
+        Some(c) => text.first() == Some(c) && matches(&pattern[1..], &text[1..]),
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::HashSet;
+use std::ops::Range;
+
+use crate::analyzer::Analyzer;
+use crate::lexer::{is_cjk, is_word_char};
+use crate::query::term_parts;
+
+/// The number of characters of context kept on each side of a match.
+const FRAGMENT_CONTEXT: usize = 60;
+
+/// The maximum number of fragments returned per document.
+const MAX_FRAGMENTS: usize = 5;
+
+/// A piece of a document around one or more query matches.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fragment {
+    /// The text of the fragment, with line breaks replaced by spaces.
+    pub text: String,
+    /// The 1-based line of the document the first match is on.
+    pub line: usize,
+    /// The 1-based column, in characters, of the first match on its line.
+    pub column: usize,
+    /// The byte ranges of the matches within `text`.
+    pub matches: Vec<Range<usize>>,
+    /// Whether the document goes on before the fragment.
+    pub cut_before: bool,
+    /// Whether the document goes on after the fragment.
+    pub cut_after: bool,
+}
+
+impl Fragment {
+    /// Returns the fragment text with every match wrapped in `open` and
+    /// `close`, e.g. `<mark>` and `</mark>`.
+    ///
+    /// # Arguments
+    /// * `open` - The text inserted before each match.
+    /// * `close` - The text inserted after each match.
+    pub fn marked(&self, open: &str, close: &str) -> String {
+        let mut out = String::with_capacity(self.text.len());
+        let mut last = 0;
+        for range in &self.matches {
+            out.push_str(&self.text[last..range.start]);
+            out.push_str(open);
+            out.push_str(&self.text[range.clone()]);
+            out.push_str(close);
+            last = range.end;
+        }
+        out.push_str(&self.text[last..]);
+        out
+    }
+
+    /// Returns the fragment as an excerpt to show next to a search result:
+    /// its text trimmed, with every match wrapped in `open` and `close` and
+    /// an ellipsis where the document goes on, e.g. `…the quick **brown**
+    /// fox…`.
+    ///
+    /// # Arguments
+    /// * `open` - The text inserted before each match.
+    /// * `close` - The text inserted after each match.
+    pub fn excerpt(&self, open: &str, close: &str) -> String {
+        let marked = self.marked(open, close);
+        format!(
+            "{}{}{}",
+            if self.cut_before { "…" } else { "" },
+            marked.trim(),
+            if self.cut_after { "…" } else { "" }
+        )
+    }
+}
+
+/// Finds the words of `text` matching the query terms and cuts the text into
+/// fragments around them. A word matches if its stem or its exact form is one
+/// of `tokens`, or a word of one of their phrases; in runs of CJK
+/// characters, the bigrams that are query terms match. Matches close to each
+/// other share a fragment.
+///
+/// # Arguments
+/// * `text` - The document text.
+/// * `tokens` - The analyzed query terms.
+/// * `analyzer` - The `Analyzer` of the index, turning words into terms.
+///
+/// # Returns
+/// The fragments, in document order, at most `MAX_FRAGMENTS` of them.
+pub fn highlight_text(text: &str, tokens: &[String], analyzer: &Analyzer) -> Vec<Fragment> {
+    let wanted: HashSet<&str> = tokens.iter().flat_map(|t| term_parts(t)).collect();
+
+    // Byte ranges of the matching words
+    let mut matches: Vec<Range<usize>> = Vec::new();
+    let mut word_start = None;
+    let mut cjk_start = None;
+    for (i, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+    {
+        let cjk = is_cjk(c);
+        match (cjk, cjk_start) {
+            (true, None) => cjk_start = Some(i),
+            (false, Some(start)) => {
+                cjk_start = None;
+                matches.append(&mut cjk_matches(&text[start..i], start, &wanted));
+            }
+            _ => {}
+        }
+        let word_char = c.is_alphanumeric() || (word_start.is_some() && is_word_char(c));
+        match (word_char && !cjk, word_start) {
+            (true, None) => word_start = Some(i),
+            (false, Some(start)) => {
+                word_start = None;
+                if analyzer
+                    .word_terms(&text[start..i])
+                    .iter()
+                    .any(|term| wanted.contains(term.as_str()))
+                {
+                    matches.push(start..i);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut fragments: Vec<Fragment> = Vec::new();
+    let mut current: Option<(Range<usize>, Vec<Range<usize>>)> = None;
+    for m in matches {
+        let start = floor_char_boundary(text, m.start.saturating_sub(FRAGMENT_CONTEXT));
+        let end = ceil_char_boundary(text, (m.end + FRAGMENT_CONTEXT).min(text.len()));
+        match &mut current {
+            Some((span, ms)) if start <= span.end => {
+                span.end = end;
+                ms.push(m);
+            }
+            _ => {
+                if let Some((span, ms)) = current.take() {
+                    fragments.push(fragment(text, span, ms));
+                    if fragments.len() == MAX_FRAGMENTS {
+                        return fragments;
+                    }
+                }
+                current = Some((start..end, vec![m]));
+            }
+        }
+    }
+    if let Some((span, ms)) = current {
+        fragments.push(fragment(text, span, ms));
+    }
+    fragments
+}
+
+/// Finds the bigrams of a run of CJK characters that are query terms, or
+/// the run itself if it is a single character, merging overlapping ones.
+///
+/// # Arguments
+/// * `run` - The run of CJK characters.
+/// * `offset` - The byte offset of the run in the document text.
+/// * `wanted` - The query terms.
+///
+/// # Returns
+/// The byte ranges of the matches in the document text, in order.
+fn cjk_matches(run: &str, offset: usize, wanted: &HashSet<&str>) -> Vec<Range<usize>> {
+    let mut matches: Vec<Range<usize>> = Vec::new();
+    let bounds = run
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(run.len()))
+        .collect::<Vec<usize>>();
+    let width = if bounds.len() == 2 { 1 } else { 2 };
+    for window in bounds.windows(width + 1) {
+        let (start, end) = (window[0], window[width]);
+        if !wanted.contains(&run[start..end]) {
+            continue;
+        }
+        match matches.last_mut() {
+            Some(last) if last.end > offset + start => last.end = offset + end,
+            _ => matches.push(offset + start..offset + end),
+        }
+    }
+    matches
+}
+
+/// Builds the fragment of `text` spanning `span` with the given matches.
+fn fragment(text: &str, span: Range<usize>, matches: Vec<Range<usize>>) -> Fragment {
+    let first = matches[0].start;
+    let before = &text[..first];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let column = text[line_start..first].chars().count() + 1;
+
+    Fragment {
+        text: text[span.clone()].replace(['\n', '\r'], " "),
+        line,
+        column,
+        matches: matches
+            .into_iter()
+            .map(|m| m.start - span.start..m.end - span.start)
+            .collect(),
+        cut_before: span.start > 0,
+        cut_after: span.end < text.len(),
+    }
+}
+
+/// Returns the largest char boundary of `text` not after `index`.
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// Returns the smallest char boundary of `text` not before `index`.
+fn ceil_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
@@ -9,21 +9,103 @@ pub const HTML_DEFAULT: &str = r"
   </head>
   <body>
     <h1>Type a query to search</h1>
-    <input type='text' id='query' value='' />
+    <input type='text' id='query' value='' list='suggestions' autocomplete='off' />
+    <datalist id='suggestions'></datalist>
     <ul id='results'></ul>
 
     <script>
-      document.getElementById('query').addEventListener('change', (e) => {
-        fetch('/query', {
-          method: 'POST',
-          headers: {
-            'Content-Type': 'text/plain',
-          },
-          body: e.currentTarget.value,
+      // The token of an index protected by one, asked for once per session
+      let token = sessionStorage.getItem('indexer-token');
+
+      function search(query) {
+        const headers = { 'Content-Type': 'text/plain' };
+        if (token) {
+          headers['Authorization'] = `Bearer ${token}`;
+        }
+        return fetch('query', { method: 'POST', headers, body: query }).then(
+          (response) => {
+            if (response.status === 401) {
+              token = prompt('This index requires a token');
+              if (token === null) {
+                throw new Error('Missing token');
+              }
+              sessionStorage.setItem('indexer-token', token);
+              return search(query);
+            }
+            return response.text();
+          }
+        );
+      }
+
+      // Opens the file of a document. Links cannot present the token, so
+      // with one the file is fetched and opened from memory, as text if it
+      // is markup that would otherwise run with the origin of this page
+      function openDocument(e) {
+        if (!token) {
+          return;
+        }
+        e.preventDefault();
+        const view = window.open('', '_blank');
+        fetch(e.currentTarget.href, {
+          headers: { Authorization: `Bearer ${token}` },
         })
-          .then((response) => response.text())
+          .then((response) => {
+            if (!response.ok) {
+              throw new Error(`Failed to open document: ${response.status}`);
+            }
+            return response.blob();
+          })
+          .then((blob) => {
+            const markup = /html|xml/.test(blob.type);
+            const file = markup ? new Blob([blob], { type: 'text/plain' }) : blob;
+            view.location = URL.createObjectURL(file);
+          })
+          .catch((err) => {
+            view.close();
+            console.error(err);
+          });
+      }
+
+      // Returns the link to a result: the page itself for web pages, the
+      // file served by the index for files, none for embedded documents
+      function documentLink(path) {
+        if (/^https?:/.test(path)) {
+          return path;
+        }
+        if (path.startsWith('embedded:')) {
+          return null;
+        }
+        return `api/doc?id=${encodeURIComponent(path)}`;
+      }
+
+      // Completes the last word of the query as it is typed, once typing
+      // pauses, without searching until the query is submitted
+      let suggestTimer = null;
+      document.getElementById('query').addEventListener('input', (e) => {
+        const query = e.currentTarget.value;
+        clearTimeout(suggestTimer);
+        suggestTimer = setTimeout(() => {
+          const headers = token ? { Authorization: `Bearer ${token}` } : {};
+          fetch(`api/suggest?q=${encodeURIComponent(query)}`, { headers })
+            .then((response) => (response.ok ? response.json() : null))
+            .then((body) => {
+              const list = document.getElementById('suggestions');
+              list.innerHTML = '';
+              (body ? body.suggestions : []).forEach((suggestion) => {
+                const option = document.createElement('option');
+                option.value = suggestion.text;
+                list.appendChild(option);
+              });
+            })
+            .catch((err) => console.error(err));
+        }, 150);
+      });
+
+      document.getElementById('query').addEventListener('change', (e) => {
+        search(e.currentTarget.value)
           .then((result) => {
-            // result is a string of strings separated by newline
+            // result is a string of strings separated by newline, each a
+            // path optionally followed by a tab and the document title
             const list_items = result.split('\n');
             let results = document.getElementById('results');
 
@@ -33,7 +115,22 @@ pub const HTML_DEFAULT: &str = r"
             list_items.forEach((item) => {
               if (item.trim() !== '') {
                 const li = document.createElement('li');
-                li.textContent = item;
+                const [path, title] = item.split('\t');
+                const text = title ? `${title} (${path})` : path;
+                const href = documentLink(path);
+                if (href) {
+                  const a = document.createElement('a');
+                  a.href = href;
+                  a.target = '_blank';
+                  a.rel = 'noopener';
+                  a.textContent = text;
+                  if (!/^https?:/.test(path)) {
+                    a.addEventListener('click', openDocument);
+                  }
+                  li.appendChild(a);
+                } else {
+                  li.textContent = text;
+                }
                 results.appendChild(li);
               }
             });
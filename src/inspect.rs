@@ -0,0 +1,180 @@
+use anyhow::Context;
+use serde::Serialize;
+
+use crate::budget::index_size;
+use crate::checkpoint::Checkpoint;
+use crate::manifest::Manifest;
+use crate::postings::VARBYTE_POSTINGS_FILE;
+use crate::publish::resolve_index;
+use crate::tree::MainIndex;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where a segment directory stands in the index.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SegmentState {
+    /// Committed and searched.
+    Active,
+    /// Committed but left out of searches until it is repaired.
+    Quarantined,
+    /// Not committed: written by an unfinished run, or replaced by a merge
+    /// and not removed yet.
+    Uncommitted,
+}
+
+/// How the postings of a segment are encoded.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PostingsFormat {
+    /// Delta and variable-byte encoded lists.
+    Varbyte,
+    /// The segment has no postings file.
+    Missing,
+}
+
+/// Whether a writer holds the index.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LockStatus {
+    /// No commit is in progress.
+    Unlocked,
+    /// A commit is rewriting the document store, or died doing so.
+    Committing,
+}
+
+/// A segment directory of the index.
+#[derive(Serialize, Debug)]
+pub struct SegmentInfo {
+    /// The id of the segment.
+    pub id: u64,
+    /// Whether the segment is searched.
+    pub state: SegmentState,
+    /// The encoding of its postings.
+    pub format: PostingsFormat,
+    /// The size of the segment directory.
+    pub bytes: u64,
+    /// The number of terms in its dictionary, if it could be read.
+    pub terms: Option<u64>,
+    /// The number of documents with postings in it, if it could be read.
+    pub documents: Option<u64>,
+    /// Why the segment could not be read, if it couldn't.
+    pub error: Option<String>,
+}
+
+/// What `indexer inspect` reports about an index directory. Meant to
+/// diagnose broken indexes, so every part that can be read is reported even
+/// if others can't.
+#[derive(Serialize)]
+pub struct Inspection {
+    /// The inspected directory.
+    pub index_dir: PathBuf,
+    /// The manifest, `None` if it cannot be parsed.
+    pub manifest: Option<Manifest>,
+    /// Whether a commit is in progress.
+    pub lock: LockStatus,
+    /// Whether an indexing run was interrupted and left a checkpoint to
+    /// resume from.
+    pub interrupted_run: bool,
+    /// Every segment directory, ordered by id.
+    pub segments: Vec<SegmentInfo>,
+    /// The size of the whole index directory.
+    pub bytes: u64,
+    /// Why the manifest or the index could not be read, if they couldn't.
+    pub errors: Vec<String>,
+}
+
+/// Inspects the index in `index_dir`, or the one published in it: its
+/// manifest, the state, encoding, size and counts of every segment
+/// directory, and whether a writer holds it.
+///
+/// # Arguments
+/// * `index_dir` - The directory containing the index files.
+/// * `key_file` - The key file of an encrypted index, if any.
+///
+/// # Returns
+/// The `Inspection`, or an `anyhow::Result` error if `index_dir` cannot be
+/// listed.
+pub fn inspect_index(index_dir: &Path, key_file: Option<&Path>) -> anyhow::Result<Inspection> {
+    let index_dir = &resolve_index(index_dir);
+    let mut errors = Vec::new();
+    let manifest = Manifest::load(index_dir)
+        .map_err(|err| errors.push(format!("manifest: {err:#}")))
+        .ok();
+    let main_index = match &manifest {
+        Some(_) => MainIndex::open_read_only(index_dir, key_file)
+            .map_err(|err| errors.push(format!("open index: {err:#}")))
+            .ok(),
+        None => None,
+    };
+
+    let mut segments = Vec::new();
+    for seg_id in segment_ids(index_dir)? {
+        let seg_dir = index_dir.join(format!("segment_{seg_id}"));
+        let committed = manifest
+            .as_ref()
+            .and_then(|manifest| manifest.segments.as_ref())
+            .is_none_or(|segments| segments.contains(&seg_id));
+        let state = match &manifest {
+            Some(manifest) if manifest.quarantined.contains(&seg_id) => SegmentState::Quarantined,
+            _ if committed => SegmentState::Active,
+            _ => SegmentState::Uncommitted,
+        };
+        let format = if seg_dir.join(VARBYTE_POSTINGS_FILE).exists() {
+            PostingsFormat::Varbyte
+        } else {
+            PostingsFormat::Missing
+        };
+        let counts = main_index
+            .as_ref()
+            .map(|main_index| main_index.segment_counts(seg_id));
+        let (terms, documents, error) = match counts {
+            Some(Ok((terms, documents))) => (Some(terms), Some(documents), None),
+            Some(Err(err)) => (None, None, Some(format!("{err:#}"))),
+            None => (None, None, None),
+        };
+        segments.push(SegmentInfo {
+            id: seg_id,
+            state,
+            format,
+            bytes: index_size(&seg_dir).unwrap_or_default(),
+            terms,
+            documents,
+            error,
+        });
+    }
+
+    Ok(Inspection {
+        index_dir: index_dir.to_path_buf(),
+        lock: match &manifest {
+            Some(manifest) if manifest.committing => LockStatus::Committing,
+            _ => LockStatus::Unlocked,
+        },
+        manifest,
+        interrupted_run: Checkpoint::exists(index_dir),
+        segments,
+        bytes: index_size(index_dir).context("measure index size")?,
+        errors,
+    })
+}
+
+/// Returns the ids of the segment directories in `index_dir`, in ascending
+/// order.
+fn segment_ids(index_dir: &Path) -> anyhow::Result<Vec<u64>> {
+    let mut seg_ids = Vec::new();
+    for entry in fs::read_dir(index_dir).with_context(|| format!("read {index_dir:?}"))? {
+        let entry = entry.context("read index entry")?;
+        if let Some(seg_id) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_prefix("segment_"))
+            .and_then(|id| id.parse::<u64>().ok())
+            && entry.path().is_dir()
+        {
+            seg_ids.push(seg_id);
+        }
+    }
+    seg_ids.sort_unstable();
+    Ok(seg_ids)
+}
@@ -0,0 +1,249 @@
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::logger::Logger;
+use crate::manifest::Manifest;
+use crate::observer::LoggingObserver;
+use crate::report::IndexProgress;
+use crate::settings::Settings;
+use crate::skip::SkipRules;
+use crate::sniff::Sniffing;
+use crate::walker::{Discovery, IndexOrder};
+use crate::{Config, ErrorHandler, index_documents};
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// The body of a `POST /api/index` request.
+#[derive(Deserialize)]
+pub struct IndexRequest {
+    /// The file or directory to index, within the roots the index was built
+    /// from.
+    pub path: PathBuf,
+    /// Also index hidden files and directories.
+    #[serde(default)]
+    pub hidden: bool,
+    /// The paths to leave out, as `--skip-paths` takes them.
+    #[serde(default)]
+    pub skip_paths: Vec<PathBuf>,
+    /// The only files to index, as `--include` takes them.
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Skip the files with an execute bit set, as `--skip-executables` does.
+    #[serde(default)]
+    pub skip_executables: bool,
+}
+
+/// An indexing run started through `POST /api/index`.
+struct IndexJob {
+    /// The indexed file or directory.
+    path: PathBuf,
+    /// When the run started, in RFC 3339 format.
+    started_at: String,
+    /// When the run ended, in RFC 3339 format, `None` while it runs.
+    finished_at: Option<String>,
+    /// The error the run failed with, if it did.
+    error: Option<String>,
+    progress: Arc<IndexProgress>,
+}
+
+impl IndexJob {
+    /// Returns the state of the run as JSON, for `GET /api/index/status`.
+    fn status(&self) -> Value {
+        let state = match (&self.finished_at, &self.error) {
+            (None, _) => "running",
+            (Some(_), None) => "completed",
+            (Some(_), Some(_)) => "failed",
+        };
+        let progress = &self.progress;
+        json!({
+            "state": state,
+            "path": self.path,
+            "started_at": self.started_at,
+            "finished_at": self.finished_at,
+            "total": progress.total.load(Ordering::Relaxed),
+            "processed": progress.processed.load(Ordering::Relaxed),
+            "indexed": progress.indexed.load(Ordering::Relaxed),
+            "bytes": progress.bytes.load(Ordering::Relaxed),
+            "errors": progress.errors.load(Ordering::Relaxed),
+            "error": self.error,
+        })
+    }
+}
+
+/// The indexing runs of a hosted index started through `POST /api/index`,
+/// run in the background one at a time.
+pub(crate) struct IndexJobs {
+    /// The path to the directory containing the index files.
+    index_file: PathBuf,
+    /// The key file of an encrypted index, if any.
+    key_file: Option<PathBuf>,
+    /// The settings the runs are configured with, as `indexer index` is.
+    settings: Settings,
+    /// The log of the server, which the runs log to.
+    logger: Logger,
+    /// The latest run, if any.
+    latest: Arc<Mutex<Option<IndexJob>>>,
+    /// The thread of the latest run, waited for when the server stops.
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl IndexJobs {
+    /// Creates the runs of an index, none started yet.
+    ///
+    /// # Arguments
+    /// * `index_file` - The path to the directory containing the index files.
+    /// * `key_file` - The key file of an encrypted index, if any.
+    /// * `settings` - The settings the runs are configured with.
+    /// * `logger` - The log of the server, which the runs log to.
+    pub(crate) fn new(
+        index_file: PathBuf,
+        key_file: Option<PathBuf>,
+        settings: Settings,
+        logger: Logger,
+    ) -> Self {
+        Self {
+            index_file,
+            key_file,
+            settings,
+            logger,
+            latest: Arc::new(Mutex::new(None)),
+            thread: Mutex::new(None),
+        }
+    }
+
+    /// Starts indexing `request.path` in the background. Only paths within
+    /// the roots of the index are accepted, as the server serves the files
+    /// of the indexed documents.
+    ///
+    /// # Arguments
+    /// * `request` - The path to index and the options of the run.
+    ///
+    /// # Returns
+    /// The status of the started run, or the HTTP status and message to
+    /// refuse the request with: `400` for a path that cannot be read, `403`
+    /// for one outside the roots of the index and `409` while another run
+    /// is in progress.
+    pub(crate) fn start(&self, request: IndexRequest) -> Result<Value, (u16, String)> {
+        // Documents keep the path they were indexed under, so the path is
+        // indexed as given and only resolved to check where it leads
+        let path = request.path;
+        let real = fs::canonicalize(&path)
+            .map_err(|err| (400, format!("Cannot index {path:?}: {err}")))?;
+        let manifest = Manifest::load(&self.index_file)
+            .map_err(|err| (500, format!("Failed to read index manifest: {err:#}")))?;
+        let inside = manifest
+            .roots
+            .iter()
+            .filter_map(|root| fs::canonicalize(root).ok())
+            .any(|root| real.starts_with(root));
+        if !inside {
+            return Err((
+                403,
+                format!(
+                    "{path:?} is outside the roots of the index; index it once with `indexer index`"
+                ),
+            ));
+        }
+
+        let mut latest = self.latest.lock().unwrap();
+        if latest.as_ref().is_some_and(|job| job.finished_at.is_none()) {
+            return Err((409, "An indexing run is already in progress".to_string()));
+        }
+        let progress = Arc::new(IndexProgress::default());
+        let job = IndexJob {
+            path: path.clone(),
+            started_at: chrono::Local::now().to_rfc3339(),
+            finished_at: None,
+            error: None,
+            progress: Arc::clone(&progress),
+        };
+        let status = job.status();
+        *latest = Some(job);
+        drop(latest);
+
+        let mut skip = SkipRules::default()
+            .with_include(request.include)
+            .with_executables(request.skip_executables);
+        skip.add_legacy(request.skip_paths);
+        let cfg = Config {
+            hidden: request.hidden,
+            // The messages of the run go to the log of the server
+            error_handler: ErrorHandler::Stderr,
+            filepath: path.clone(),
+            index_path: self.index_file.clone(),
+            logger: self.logger.clone(),
+            skip,
+            ranker: None,
+            auto_compact: self.settings.auto_compact,
+            analyzer: None,
+            key_file: self.key_file.clone(),
+            raw_key: false,
+            throttle: false,
+            discovery: Discovery::default(),
+            keep_history: false,
+            keep_duplicates: false,
+            budget: None,
+            quota: None,
+            profile: None,
+            segment_budget: None,
+            sandbox: None,
+            resume: false,
+            policies: self.settings.policies.clone(),
+            tags: Vec::new(),
+            tag_rules: self.settings.tags.clone(),
+            expansions: None,
+            stem_exceptions: None,
+            stop_words: None,
+            noise: None,
+            time_budget: None,
+            order: IndexOrder::default(),
+            priorities: self.settings.priorities.clone(),
+            sniffing: Sniffing::default(),
+            max_file_size: None,
+            follow_symlinks: false,
+            progress: Some(progress),
+            observer: Some(Arc::new(LoggingObserver::new(self.logger.clone()))),
+        };
+        let latest = Arc::clone(&self.latest);
+        let logger = self.logger.clone();
+        let thread = thread::spawn(move || {
+            let error = index_documents(&cfg).err().map(|err| format!("{err:#}"));
+            match &error {
+                None => logger.info(format!("Indexing run of {path:?} completed")),
+                Some(err) => logger.error(format!("Indexing run of {path:?} failed: {err}")),
+            }
+            if let Some(job) = latest.lock().unwrap().as_mut() {
+                job.error = error;
+                job.finished_at = Some(chrono::Local::now().to_rfc3339());
+            }
+        });
+        *self.thread.lock().unwrap() = Some(thread);
+        Ok(status)
+    }
+
+    /// Waits for the run in progress, if any, e.g. before the server stops
+    /// so that its documents are committed.
+    pub(crate) fn wait(&self) {
+        let Some(thread) = self.thread.lock().unwrap().take() else {
+            return;
+        };
+        if !thread.is_finished() {
+            self.logger
+                .info("Waiting for the indexing run in progress to finish");
+        }
+        let _ = thread.join();
+    }
+
+    /// Returns the state and progress of the latest run as JSON, for
+    /// `GET /api/index/status`.
+    pub(crate) fn status(&self) -> Value {
+        match self.latest.lock().unwrap().as_ref() {
+            Some(job) => job.status(),
+            None => json!({ "state": "idle" }),
+        }
+    }
+}
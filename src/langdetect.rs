@@ -0,0 +1,64 @@
+use clap::ValueEnum;
+
+use crate::analyzer::{Language, fold_case};
+use crate::stopwords::language_stop_words;
+
+/// The number of words at the start of a document its language is told
+/// from.
+const SAMPLE_WORDS: usize = 1000;
+
+/// The fewest stop words of the detected language a sample must hold, so
+/// that a title or a list of names is not assigned a language.
+const MIN_STOP_WORDS: usize = 5;
+
+/// The smallest share of the words of a sample that must be stop words of
+/// the detected language. Function words make up a third or more of prose,
+/// far less of code or tables.
+const MIN_STOP_WORD_SHARE: f64 = 0.1;
+
+/// Detects the language of a text by counting the stop words of each
+/// language among its first words: the language whose stop words are the
+/// most frequent wins.
+///
+/// # Arguments
+/// * `text` - The text, e.g. a document or the start of it.
+/// * `preferred` - The language winning ties, usually that of the index,
+///   e.g. between Danish and Norwegian which share most of their stop
+///   words.
+///
+/// # Returns
+/// The detected `Language`, or `None` if the text holds too few stop words
+/// to tell.
+pub fn detect_language(text: &str, preferred: Language) -> Option<Language> {
+    let words = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .take(SAMPLE_WORDS)
+        .map(fold_case)
+        .collect::<Vec<String>>();
+    if words.is_empty() {
+        return None;
+    }
+
+    let mut best: Option<(Language, usize)> = None;
+    for &language in Language::value_variants() {
+        let stop_words = language_stop_words(language);
+        let count = words
+            .iter()
+            .filter(|word| stop_words.contains(*word))
+            .count();
+        let better = match best {
+            None => true,
+            Some((_, best_count)) => {
+                count > best_count || (count == best_count && language == preferred)
+            }
+        };
+        if better {
+            best = Some((language, count));
+        }
+    }
+
+    let (language, count) = best?;
+    let share = count as f64 / words.len() as f64;
+    (count >= MIN_STOP_WORDS && share >= MIN_STOP_WORD_SHARE).then_some(language)
+}
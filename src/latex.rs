@@ -0,0 +1,366 @@
+use unicode_normalization::UnicodeNormalization;
+
+/// The environments holding no prose: math, pictures, code listings and
+/// comments. They are skipped up to their `\end`.
+const SKIPPED_ENVIRONMENTS: [&str; 16] = [
+    "equation",
+    "equation*",
+    "align",
+    "align*",
+    "gather",
+    "gather*",
+    "multline",
+    "multline*",
+    "eqnarray",
+    "math",
+    "displaymath",
+    "tikzpicture",
+    "verbatim",
+    "lstlisting",
+    "minted",
+    "comment",
+];
+
+/// The commands whose arguments are not prose, e.g. labels, references,
+/// file names and definitions. Their arguments are dropped, while those of
+/// other commands (`\section`, `\emph`, `\caption`, ...) are kept.
+const DROPPED_COMMANDS: [&str; 34] = [
+    "label",
+    "ref",
+    "eqref",
+    "pageref",
+    "autoref",
+    "cref",
+    "Cref",
+    "cite",
+    "citep",
+    "citet",
+    "nocite",
+    "includegraphics",
+    "input",
+    "include",
+    "documentclass",
+    "usepackage",
+    "bibliography",
+    "bibliographystyle",
+    "newcommand",
+    "renewcommand",
+    "providecommand",
+    "newenvironment",
+    "renewenvironment",
+    "setlength",
+    "addtolength",
+    "setcounter",
+    "vspace",
+    "hspace",
+    "url",
+    "color",
+    "definecolor",
+    "hypersetup",
+    "pagestyle",
+    "thispagestyle",
+];
+
+/// The number of arguments of an environment that are not prose, e.g. the
+/// column specification of a `tabular`.
+fn environment_args(environment: &str) -> usize {
+    match environment {
+        "tabular" | "array" | "minipage" | "wrapfigure" => 1,
+        "tabularx" | "tabular*" => 2,
+        _ => 0,
+    }
+}
+
+/// The combining mark of an accent command, e.g. `\'` for an acute accent.
+fn accent_mark(accent: &str) -> Option<char> {
+    Some(match accent {
+        "'" => '\u{301}',
+        "`" => '\u{300}',
+        "^" => '\u{302}',
+        "\"" => '\u{308}',
+        "~" => '\u{303}',
+        "=" => '\u{304}',
+        "." => '\u{307}',
+        "c" => '\u{327}',
+        "v" => '\u{30c}',
+        "u" => '\u{306}',
+        "H" => '\u{30b}',
+        "k" => '\u{328}',
+        "r" => '\u{30a}',
+        _ => return None,
+    })
+}
+
+/// The prose of a LaTeX document.
+#[derive(Debug, Default)]
+pub struct LatexText {
+    /// The text of the body, with the titles of its sections.
+    pub text: String,
+    /// The argument of `\title`, if any.
+    pub title: Option<String>,
+}
+
+/// Extracts the prose of a LaTeX document: commands are stripped, keeping
+/// the text of their arguments (section titles, emphasis, captions,
+/// footnotes, ...) except for labels, references, citations, file names and
+/// definitions, and comments, math and code listings are left out. The
+/// preamble is skipped, its `\title` aside. Accented letters (`\'e`,
+/// `\"{o}`) are composed.
+///
+/// # Arguments
+/// * `source` - The LaTeX source.
+///
+/// # Returns
+/// The `LatexText` of the document.
+pub fn latex_to_text(source: &str) -> LatexText {
+    let (preamble, body) = match source.find("\\begin{document}") {
+        Some(start) => {
+            let body = &source[start + "\\begin{document}".len()..];
+            let end = body.find("\\end{document}").unwrap_or(body.len());
+            (&source[..start], &body[..end])
+        }
+        None => ("", source),
+    };
+    let mut preamble_reader = Reader::new(preamble);
+    preamble_reader.read_until(None);
+    let mut reader = Reader::new(body);
+    reader.title = preamble_reader.title;
+    let text = reader.read_until(None);
+    LatexText {
+        text: text.nfc().collect(),
+        title: reader.title.map(|title| {
+            title
+                .nfc()
+                .collect::<String>()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        }),
+    }
+}
+
+/// A cursor over LaTeX source.
+struct Reader {
+    chars: Vec<char>,
+    pos: usize,
+    /// The argument of the first `\title` read.
+    title: Option<String>,
+}
+
+impl Reader {
+    fn new(source: &str) -> Self {
+        Self {
+            chars: source.chars().collect(),
+            pos: 0,
+            title: None,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    /// Returns `true` and moves past `s` if the source continues with it.
+    fn eat(&mut self, s: &str) -> bool {
+        let matches = s
+            .chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.pos + i) == Some(&c));
+        if matches {
+            self.pos += s.chars().count();
+        }
+        matches
+    }
+
+    /// Moves past the next occurrence of `end`, or to the end of the source.
+    fn skip_past(&mut self, end: &str) {
+        while self.pos < self.chars.len() && !self.eat(end) {
+            self.pos += 1;
+        }
+    }
+
+    /// Reads the prose up to the unescaped `end` character, or to the end of
+    /// the source, and moves past it.
+    fn read_until(&mut self, end: Option<char>) -> String {
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                c if Some(c) == end => break,
+                '%' => self.skip_past("\n"),
+                '{' => text.push_str(&self.read_until(Some('}'))),
+                '}' => {}
+                '~' => text.push(' '),
+                '$' => {
+                    if self.eat("$") {
+                        self.skip_past("$$");
+                    } else {
+                        self.skip_past("$");
+                    }
+                    text.push(' ');
+                }
+                '\\' => self.read_command(&mut text),
+                c => text.push(c),
+            }
+        }
+        text
+    }
+
+    /// Reads the name of a command, after its backslash: a run of letters
+    /// or a single other character.
+    fn command_name(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+        if self.pos == start && self.pos < self.chars.len() {
+            self.pos += 1;
+        }
+        let mut name = self.chars[start..self.pos].iter().collect::<String>();
+        if name.chars().all(|c| c.is_ascii_alphabetic()) && self.eat("*") {
+            name.push('*');
+        }
+        name
+    }
+
+    /// Skips the arguments following a command: optional ones in brackets
+    /// and mandatory ones in braces, at most `max` of the latter.
+    fn skip_args(&mut self, max: usize) {
+        let mut skipped = 0;
+        loop {
+            match self.peek() {
+                Some('[') => {
+                    self.pos += 1;
+                    self.skip_group('[', ']');
+                }
+                Some('{') if skipped < max => {
+                    self.pos += 1;
+                    self.skip_group('{', '}');
+                    skipped += 1;
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Moves past the `close` character ending the group just opened.
+    fn skip_group(&mut self, open: char, close: char) {
+        let mut depth = 1;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '\\' => self.pos += 1,
+                c if c == open => depth += 1,
+                c if c == close => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Reads the argument of a command as prose, braced or a single
+    /// character.
+    fn read_arg(&mut self) -> String {
+        while self.peek() == Some(' ') {
+            self.pos += 1;
+        }
+        match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                self.read_until(Some('}'))
+            }
+            Some(c) => {
+                self.pos += 1;
+                c.to_string()
+            }
+            None => String::new(),
+        }
+    }
+
+    /// Reads a command, after its backslash, writing the prose it stands for
+    /// to `text`.
+    fn read_command(&mut self, text: &mut String) {
+        let name = self.command_name();
+        match name.as_str() {
+            "\\" => {
+                text.push('\n');
+                self.skip_args(0);
+            }
+            "&" | "%" | "$" | "#" | "_" | "{" | "}" => text.push_str(&name),
+            " " | "," | ";" | ":" | "!" => text.push(' '),
+            "(" => self.skip_past("\\)"),
+            "[" => self.skip_past("\\]"),
+            "begin" => {
+                let environment = self.read_arg();
+                if SKIPPED_ENVIRONMENTS.contains(&environment.as_str()) {
+                    self.skip_past(&format!("\\end{{{environment}}}"));
+                } else {
+                    self.skip_args(environment_args(&environment));
+                }
+                text.push('\n');
+            }
+            "end" => {
+                self.read_arg();
+                text.push('\n');
+            }
+            "item" => {
+                text.push('\n');
+                if self.eat("[") {
+                    text.push_str(&self.read_until(Some(']')));
+                    text.push(' ');
+                }
+            }
+            "par" | "newline" | "linebreak" | "maketitle" => text.push('\n'),
+            "ldots" | "dots" => text.push_str("..."),
+            "i" => text.push('i'),
+            "j" => text.push('j'),
+            "ss" => text.push('ß'),
+            "ae" => text.push('æ'),
+            "AE" => text.push('Æ'),
+            "oe" => text.push('œ'),
+            "OE" => text.push('Œ'),
+            "o" => text.push('ø'),
+            "O" => text.push('Ø'),
+            "aa" => text.push('å'),
+            "AA" => text.push('Å'),
+            "l" => text.push('ł'),
+            "L" => text.push('Ł'),
+            "LaTeX" | "TeX" => text.push_str(&name),
+            "verb" => {
+                if let Some(delimiter) = self.peek() {
+                    self.pos += 1;
+                    let start = self.pos;
+                    self.skip_past(&delimiter.to_string());
+                    let end = self.pos.saturating_sub(1).max(start);
+                    text.extend(&self.chars[start..end]);
+                }
+            }
+            "href" => {
+                self.skip_args(1);
+            }
+            "title" => {
+                self.skip_args(0);
+                let title = self.read_arg();
+                text.push_str(&title);
+                text.push('\n');
+                if self.title.is_none() {
+                    self.title = Some(title);
+                }
+            }
+            name if DROPPED_COMMANDS.contains(&name.trim_end_matches('*')) => {
+                self.skip_args(usize::MAX);
+            }
+            name => match accent_mark(name) {
+                Some(mark) => {
+                    text.push_str(&self.read_arg());
+                    text.push(mark);
+                }
+                None => self.skip_args(0),
+            },
+        }
+    }
+}
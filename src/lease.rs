@@ -0,0 +1,117 @@
+use anyhow::Context;
+
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+/// The directory of an index holding one lease file per generation read.
+pub const READERS_DIR: &str = "readers";
+
+/// A shared lock on the lease file of the generation a read-only index
+/// searches, held for as long as the index is open so that writers keep the
+/// segments of that generation on disk. The lock goes away with the reader,
+/// even if its process dies.
+pub struct ReaderLease {
+    _file: File,
+}
+
+impl ReaderLease {
+    /// Takes a lease on `generation` of the index in `index_dir`, waiting
+    /// for a writer checking the leases to be done.
+    ///
+    /// # Arguments
+    /// * `index_dir` - The directory containing the index files.
+    /// * `generation` - The generation of the index read.
+    ///
+    /// # Returns
+    /// The `ReaderLease`, or an `anyhow::Result` error if the lease file
+    /// cannot be created, e.g. on read-only media.
+    pub fn acquire(index_dir: &Path, generation: u64) -> anyhow::Result<Self> {
+        let dir = index_dir.join(READERS_DIR);
+        fs::create_dir_all(&dir).context("create readers directory")?;
+        let path = dir.join(generation.to_string());
+        loop {
+            let file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&path)
+                .context("open reader lease")?;
+            file.lock_shared().context("lock reader lease")?;
+            // A writer removes the lease files nobody holds: the lock only
+            // counts if the file was not removed before it was taken
+            let held = file.metadata().context("read reader lease")?;
+            if fs::metadata(&path).is_ok_and(|current| current.ino() == held.ino()) {
+                return Ok(Self { _file: file });
+            }
+        }
+    }
+}
+
+/// Returns the oldest generation of the index in `index_dir` still held by
+/// a `ReaderLease`. The lease files nobody holds anymore are removed.
+///
+/// # Arguments
+/// * `index_dir` - The directory containing the index files.
+///
+/// # Returns
+/// The oldest generation read, `None` if no reader holds a lease, or an
+/// `anyhow::Result` error if the leases cannot be checked.
+pub fn oldest_read_generation(index_dir: &Path) -> anyhow::Result<Option<u64>> {
+    let entries = match fs::read_dir(index_dir.join(READERS_DIR)) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).context("list reader leases"),
+    };
+    let mut oldest: Option<u64> = None;
+    for entry in entries {
+        let entry = entry.context("read reader lease")?;
+        let Some(generation) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<u64>().ok())
+        else {
+            continue;
+        };
+        let file = match File::open(entry.path()) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err).context("open reader lease"),
+        };
+        match file.try_lock() {
+            Ok(()) => match fs::remove_file(entry.path()) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err).context("remove reader lease"),
+            },
+            Err(TryLockError::WouldBlock) => {
+                oldest = Some(oldest.map_or(generation, |oldest| oldest.min(generation)));
+            }
+            Err(TryLockError::Error(err)) => return Err(err).context("check reader lease"),
+        }
+    }
+    Ok(oldest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempIndex;
+
+    #[test]
+    fn only_held_leases_count() {
+        let index_dir = TempIndex::new().unwrap();
+        assert_eq!(oldest_read_generation(index_dir.path()).unwrap(), None);
+
+        let old = ReaderLease::acquire(index_dir.path(), 3).unwrap();
+        let new = ReaderLease::acquire(index_dir.path(), 5).unwrap();
+        assert_eq!(oldest_read_generation(index_dir.path()).unwrap(), Some(3));
+
+        drop(old);
+        assert_eq!(oldest_read_generation(index_dir.path()).unwrap(), Some(5));
+        assert!(!index_dir.path().join(READERS_DIR).join("3").exists());
+        drop(new);
+        assert_eq!(oldest_read_generation(index_dir.path()).unwrap(), None);
+    }
+}
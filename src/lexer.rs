@@ -1,10 +1,114 @@
 use rust_stemmers::{Algorithm, Stemmer};
+use unicode_normalization::char::is_combining_mark;
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+/// Prefix marking the exact (unstemmed) surface form of a word in the index,
+/// so that `=poses` only matches documents containing `poses` itself. The
+/// `=` punctuation token is the prefix alone, so a term is only an exact
+/// form if a word follows it: see `exact_word`.
+pub const EXACT_PREFIX: &str = "=";
+
+/// Returns the word of an exact index term, or `None` if `term` is not
+/// one, e.g. a stem or the `=` punctuation token of `a=b`.
+pub fn exact_word(term: &str) -> Option<&str> {
+    term.strip_prefix(EXACT_PREFIX)
+        .filter(|word| !word.is_empty())
+}
+
+/// Returns `true` for the characters of the scripts written without spaces
+/// between words: Chinese ideographs, Japanese kana and Korean hangul.
+pub fn is_cjk(c: char) -> bool {
+    matches!(
+        c,
+        '\u{1100}'..='\u{11FF}' // Hangul Jamo
+            | '\u{3040}'..='\u{30FF}' // Hiragana and Katakana
+            | '\u{31F0}'..='\u{31FF}' // Katakana phonetic extensions
+            | '\u{3400}'..='\u{4DBF}' // CJK Unified Ideographs Extension A
+            | '\u{4E00}'..='\u{9FFF}' // CJK Unified Ideographs
+            | '\u{AC00}'..='\u{D7AF}' // Hangul Syllables
+            | '\u{F900}'..='\u{FAFF}' // CJK Compatibility Ideographs
+            | '\u{FF66}'..='\u{FF9F}' // Halfwidth Katakana
+            | '\u{20000}'..='\u{2FA1F}' // CJK Unified Ideographs Extensions B to F
+    )
+}
+
+/// Returns `true` for the characters words are made of: letters, digits
+/// and the combining marks left on them by text that is not fully composed,
+/// e.g. the accent of `q̃`, which has no precomposed form.
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || is_combining_mark(c)
+}
+
+/// Returns `true` for punctuation characters: ASCII punctuation and that of
+/// the Latin-1 supplement, general, CJK and full-width punctuation, e.g.
+/// `.`, `«`, `—` or `。`.
+pub fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation()
+        || matches!(
+            c,
+            '¡' | '§' | '«' | '¶' | '·' | '»' | '¿'
+                | '\u{2010}'..='\u{2027}' // General Punctuation: dashes, quotes, bullets
+                | '\u{2030}'..='\u{205E}' // General Punctuation: primes, brackets
+                | '\u{3001}'..='\u{3003}' // Ideographic comma and full stop
+                | '\u{3008}'..='\u{3011}' // CJK brackets
+                | '\u{3014}'..='\u{301F}' // CJK brackets and quotes
+                | '\u{FF01}'..='\u{FF0F}' // Full-width punctuation
+                | '\u{FF1A}'..='\u{FF20}'
+                | '\u{FF3B}'..='\u{FF40}'
+                | '\u{FF5B}'..='\u{FF65}'
+        )
+}
+
+/// The tokens the lexer leaves out, in documents and queries alike. They
+/// are dropped before positions are counted, so a phrase matches across
+/// them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TokenFilter {
+    /// Drop the punctuation characters the lexer otherwise emits as tokens
+    /// of their own, e.g. `.` or `(`.
+    pub punctuation: bool,
+    /// Drop the words and numbers of fewer characters than this; `0` and
+    /// `1` keep them all. CJK characters and placeholders are always kept.
+    pub min_len: usize,
+}
+
+impl TokenFilter {
+    /// Returns `true` if the lexer emits `token`, before stemming.
+    pub fn keeps(&self, token: &str) -> bool {
+        let Some(first) = token.chars().next() else {
+            return false;
+        };
+        if self.punctuation && token.chars().all(is_punctuation) {
+            return false;
+        }
+        if self.min_len > 1 && first.is_alphanumeric() && !is_cjk(first) {
+            return token.chars().nth(self.min_len - 1).is_some();
+        }
+        true
+    }
+}
 
 /// A simple lexer for tokenizing text. It supports numeric, alphabetic, and
-/// other characters, and applies English stemming to alphabetic tokens.
+/// other characters, and stems alphabetic tokens (in English unless
+/// configured otherwise). Runs of CJK characters, which have no spaces to
+/// split words at, are cut into overlapping bigrams: `東京都` becomes `東京`
+/// and `京都`.
 pub struct Lexer<'a> {
     /// The input text as a slice of characters.
     pub input: &'a [char],
+    /// The stemmer of the algorithm applied to words, created once per
+    /// lexer rather than per word, or `None` to keep them as is.
+    stemmer: Option<Stemmer>,
+    /// The stems given to words instead of those of the stemmer, keyed by
+    /// the word, if any.
+    pub stem_exceptions: Option<&'a HashMap<String, String>>,
+    /// The tokens left out.
+    pub filter: TokenFilter,
+    /// Whether the last token was a bigram of a CJK run, whose last
+    /// character is then not a token of its own.
+    in_cjk_run: bool,
 }
 
 impl<'a> Lexer<'a> {
@@ -13,7 +117,42 @@ impl<'a> Lexer<'a> {
     /// # Arguments
     /// * `input` - The input text as a slice of characters.
     pub fn new(input: &'a [char]) -> Self {
-        Self { input }
+        Self {
+            input,
+            stemmer: Some(Stemmer::create(Algorithm::English)),
+            stem_exceptions: None,
+            filter: TokenFilter::default(),
+            in_cjk_run: false,
+        }
+    }
+
+    /// Sets the stemming algorithm applied to words.
+    ///
+    /// # Arguments
+    /// * `stemmer` - The algorithm, or `None` to disable stemming.
+    pub fn with_stemmer(mut self, stemmer: Option<Algorithm>) -> Self {
+        self.stemmer = stemmer.map(Stemmer::create);
+        self
+    }
+
+    /// Sets the stems given to words instead of those of the stemmer, e.g.
+    /// `news` for `news` rather than `new`. They are only consulted when
+    /// stemming is enabled.
+    ///
+    /// # Arguments
+    /// * `exceptions` - The stems keyed by the word.
+    pub fn with_stem_exceptions(mut self, exceptions: &'a HashMap<String, String>) -> Self {
+        self.stem_exceptions = Some(exceptions);
+        self
+    }
+
+    /// Sets the tokens left out, e.g. punctuation.
+    ///
+    /// # Arguments
+    /// * `filter` - The `TokenFilter`.
+    pub fn with_filter(mut self, filter: TokenFilter) -> Self {
+        self.filter = filter;
+        self
     }
 
     /// Trims whitespace from the left side of the input.
@@ -57,54 +196,120 @@ impl<'a> Lexer<'a> {
         self.chop(n)
     }
 
-    /// Extracts the next token from the input. It handles numeric tokens,
-    /// alphabetic tokens (with stemming), and single-character tokens.
+    /// Extracts the next token kept by the filter from the input without
+    /// stemming it.
     ///
     /// # Returns
-    /// An `Option` containing the next token as a `String`, or `None` if no
-    /// more tokens are available.
-    fn next_token(&mut self) -> Option<String> {
+    /// An `Option` containing the next token and whether it is a word that
+    /// can be stemmed, or `None` if no more tokens are available.
+    fn next_surface_token(&mut self) -> Option<(String, bool)> {
+        loop {
+            let (token, is_word) = self.next_raw_token()?;
+            if self.filter.keeps(&token) {
+                return Some((token, is_word));
+            }
+        }
+    }
+
+    /// Extracts the next token from the input without stemming it. It
+    /// handles numeric tokens, alphabetic tokens, CJK bigrams and
+    /// single-character tokens.
+    ///
+    /// # Returns
+    /// An `Option` containing the next token and whether it is a word that
+    /// can be stemmed, or `None` if no more tokens are available.
+    fn next_raw_token(&mut self) -> Option<(String, bool)> {
         self.trim_left();
 
         if self.input.is_empty() {
             return None;
         }
 
+        if is_cjk(self.input[0]) {
+            return self.next_cjk_token();
+        }
+        self.in_cjk_run = false;
+
         if self.input[0].is_numeric() {
-            return Some(self.chop_while(|x| x.is_numeric()).iter().collect());
+            return Some((self.chop_while(|x| x.is_numeric()).iter().collect(), false));
         }
 
         if self.input[0].is_alphabetic() {
-            let term: String = self.chop_while(|x| x.is_alphanumeric()).iter().collect();
+            let term: String = self
+                .chop_while(|x| is_word_char(*x) && !is_cjk(*x))
+                .iter()
+                .collect();
+            return Some((term, true));
+        }
+        Some((self.chop(1).iter().collect(), false))
+    }
 
-            let stemmed_token = self.stem_token(&term);
-            return Some(stemmed_token);
+    /// Extracts the next bigram of a run of CJK characters: the character
+    /// at the start of the input and the one after it. A run of a single
+    /// character is a token of its own. Bigrams are not stemmed.
+    ///
+    /// # Returns
+    /// The next token, or the token after the run if the input starts with
+    /// the last character of a run already covered by a bigram.
+    fn next_cjk_token(&mut self) -> Option<(String, bool)> {
+        let pairs = self.input.get(1).is_some_and(|c| is_cjk(*c));
+        let covered = std::mem::replace(&mut self.in_cjk_run, pairs);
+        let first = self.chop(1)[0];
+        if pairs {
+            return Some(([first, self.input[0]].iter().collect(), false));
+        }
+        if covered {
+            return self.next_raw_token();
         }
-        Some(self.chop(1).iter().collect())
+        Some((first.to_string(), false))
     }
 
-    /// Stems a given token using the English Porter2 stemming algorithm.
+    /// Extracts the next token from the input. It handles numeric tokens,
+    /// alphabetic tokens (with stemming), and single-character tokens.
+    ///
+    /// # Returns
+    /// An `Option` containing the next token as a `String`, or `None` if no
+    /// more tokens are available.
+    fn next_token(&mut self) -> Option<String> {
+        let (token, is_word) = self.next_surface_token()?;
+        if is_word {
+            return Some(self.stem_token(token));
+        }
+        Some(token)
+    }
+
+    /// Stems a given token using the configured Snowball algorithm, unless
+    /// the stem exceptions give it another stem.
     ///
     /// # Arguments
     /// * `token` - The token to stem.
     ///
     /// # Returns
-    /// The stemmed version of the token as a `String`.
-    fn stem_token(&self, token: &str) -> String {
-        let stemmer = Stemmer::create(Algorithm::English);
-        stemmer.stem(token).to_string()
+    /// The stemmed version of the token as a `String`, or the token itself if
+    /// stemming is disabled or leaves it unchanged.
+    fn stem_token(&self, token: String) -> String {
+        let Some(stemmer) = &self.stemmer else {
+            return token;
+        };
+        if let Some(stem) = self.stem_exceptions.and_then(|stems| stems.get(&token)) {
+            return stem.clone();
+        }
+        // The stemmer only allocates for words it changes
+        if let Cow::Owned(stem) = stemmer.stem(&token) {
+            return stem;
+        }
+        token
     }
 
     /// Retrieves all tokens from the input, applying stemming and removing
     /// specified stop words.
     ///
     /// # Arguments
-    /// * `stop_words` - A slice of `String`s representing words to be filtered
-    ///   out.
+    /// * `stop_words` - The set of words to be filtered out.
     ///
     /// # Returns
     /// A `Vec` of processed tokens as `String`s.
-    pub fn get_tokens(&mut self, stop_words: &[String]) -> Vec<String> {
+    pub fn get_tokens(&mut self, stop_words: &HashSet<String>) -> Vec<String> {
         let mut tokens = Vec::new();
         for token in self.by_ref() {
             tokens.push(token);
@@ -114,17 +319,61 @@ impl<'a> Lexer<'a> {
         tokens
     }
 
+    /// Retrieves all tokens from the input for indexing. Besides the stemmed
+    /// form, every word is also emitted in its exact surface form prefixed
+    /// with `EXACT_PREFIX`, so that exact queries can bypass stemming.
+    ///
+    /// # Arguments
+    /// * `stop_words` - The set of words to be filtered out.
+    ///
+    /// # Returns
+    /// A `Vec` of processed tokens as `String`s.
+    pub fn get_index_tokens(&mut self, stop_words: &HashSet<String>) -> Vec<String> {
+        let mut tokens = Vec::new();
+        while let Some((token, is_word)) = self.next_surface_token() {
+            if is_word {
+                if !stop_words.contains(&token) {
+                    tokens.push(format!("{EXACT_PREFIX}{token}"));
+                }
+                tokens.push(self.stem_token(token));
+            } else {
+                tokens.push(token);
+            }
+        }
+
+        self.remove_stop_words(&mut tokens, stop_words);
+        tokens
+    }
+
+    /// Retrieves all tokens from the input without stemming, marking words
+    /// with `EXACT_PREFIX` so they only match their exact indexed form.
+    ///
+    /// # Arguments
+    /// * `stop_words` - The set of words to be filtered out.
+    ///
+    /// # Returns
+    /// A `Vec` of processed tokens as `String`s.
+    pub fn get_exact_tokens(&mut self, stop_words: &HashSet<String>) -> Vec<String> {
+        let mut tokens = Vec::new();
+        while let Some((token, is_word)) = self.next_surface_token() {
+            if is_word && !stop_words.contains(&token) {
+                tokens.push(format!("{EXACT_PREFIX}{token}"));
+            } else if !is_word {
+                tokens.push(token);
+            }
+        }
+
+        self.remove_stop_words(&mut tokens, stop_words);
+        tokens
+    }
+
     /// Removes specified stop words from a mutable vector of tokens.
     ///
     /// # Arguments
     /// * `tokens` - A mutable reference to the `Vec<String>` of tokens.
-    /// * `stop_words` - A slice of `String`s representing stop words.
-    fn remove_stop_words(&self, tokens: &mut Vec<String>, stop_words: &[String]) {
-        *tokens = tokens
-            .iter()
-            .filter(|t| !stop_words.contains(t))
-            .map(|t| t.to_string())
-            .collect::<Vec<String>>();
+    /// * `stop_words` - The set of stop words.
+    fn remove_stop_words(&self, tokens: &mut Vec<String>, stop_words: &HashSet<String>) {
+        tokens.retain(|t| !stop_words.contains(t));
     }
 }
 
@@ -1,24 +1,127 @@
+pub mod analyzer;
+pub mod api;
+pub mod archive;
+pub mod backup;
+pub mod bench;
+pub mod bloom;
+pub mod boost;
+pub mod browser;
+pub mod budget;
+pub mod cache;
+pub mod checkpoint;
+pub mod clipboard;
+pub mod code;
+pub mod crypto;
+pub mod diff;
+pub mod dry_run;
+pub mod embedded;
+pub mod expansion;
+pub mod export;
+pub mod federation;
+pub mod fuzzy;
+pub mod git_log;
+pub mod glob;
+pub mod highlight;
 pub mod html;
+pub mod inspect;
+pub mod jobs;
+pub mod langdetect;
+pub mod latex;
+pub mod lease;
 pub mod lexer;
+pub mod logger;
+pub mod maildir;
+pub mod manifest;
+pub mod metadata_filter;
+pub mod noise;
+pub mod observer;
+#[cfg(feature = "ocr")]
+pub mod ocr;
+pub mod offload;
+pub mod org;
+pub mod output;
 pub mod parsers;
+pub mod placeholder;
+pub mod policy;
+pub mod postings;
+pub mod privacy;
+pub mod profile;
+pub mod publish;
+pub mod query;
+pub mod query_debug;
+pub mod quota;
+pub mod recovery;
+pub mod remote;
+pub mod report;
+pub mod rtf;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod sample;
+pub mod sandbox;
+pub mod scoring;
 pub mod server;
+pub mod service;
+pub mod settings;
+pub mod skip;
+pub mod sniff;
+pub mod source;
+pub mod stats;
+pub mod stem_exceptions;
+pub mod stopwords;
+pub mod store;
+pub mod tags;
+pub mod tar;
+pub mod temp;
+pub mod template;
+pub mod testing;
+pub mod throttle;
 pub mod tree;
+pub mod update;
+pub mod walker;
 
+use analyzer::{Analyzer, AnalyzerSettings, Language};
 use anyhow::Context;
-use indicatif::ProgressBar;
+use boost::ScoreBoost;
+use budget::{EvictionPolicy, SizeBudget, is_evicted, load_evictions, record_matches};
+use cache::PostingsCache;
+use checkpoint::Checkpoint;
+use code::CODE_EXTENSIONS;
+use crypto::Cipher;
+use highlight::Fragment;
+use logger::Logger;
+use metadata_filter::MetadataFilter;
+use noise::NoiseFilter;
+use observer::{IndexingObserver, RunStats, SilentObserver};
+use offload::{Offloaded, offload};
 use parsers::*;
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
-use stop_words::LANGUAGE;
-use tree::{DocumentStore, MainIndex};
+use policy::{PolicyRule, policy_for};
+use privacy::log_path;
+use profile::Profile;
+use query::{BoolExpr, EmptyQueryError, Query};
+use query_debug::QueryDebug;
+use quota::{Quota, QuotaAction};
+use rayon::iter::{IntoParallelRefIterator, ParallelBridge, ParallelIterator};
+use report::{IndexProgress, RenamedFile, RunReport, SkipKind};
+use sandbox::SandboxLimits;
+use scoring::{Bm25, FieldWeights, Ranker, SortOrder};
+use serde_json::{Value, json};
+use settings::BoostRule;
+use sha2::{Digest, Sha256};
+use skip::SkipRules;
+use sniff::{Sniffing, is_binary, sniff_type};
+use tags::{TagRule, tags_for};
+use tree::{
+    CompactSummary, DocumentStore, IndexSummary, MainIndex, RepairSummary, SegmentCheck, TopResults,
+};
+use walker::{Discovery, IndexOrder, ModifiedTimes, Symlinks, order_documents};
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fs,
-    io::{Write, stderr},
-    os::unix::fs::PermissionsExt,
+    io::{self, BufRead, Read, Write, stderr},
     path::{Path, PathBuf},
-    sync::{Arc, RwLock, atomic::AtomicU64, mpsc},
-    time::{Duration, SystemTime},
+    sync::{Arc, Mutex, RwLock, atomic::AtomicU64, mpsc},
+    time::{Duration, Instant, SystemTime},
 };
 
 /// Configuration for the indexing process.
@@ -31,14 +134,297 @@ pub struct Config {
     pub filepath: PathBuf,
     /// The path to the directory where index files will be stored.
     pub index_path: PathBuf,
-    /// A sender channel for sending messages (errors, info, debug).
-    pub sender: Arc<RwLock<mpsc::Sender<Message>>>,
-    /// A list of paths to skip during indexing.
-    pub skip_paths: Vec<PathBuf>,
+    /// The log the messages (errors, info, debug) of the run are sent to.
+    pub logger: Logger,
+    /// The rules excluding paths from indexing.
+    pub skip: SkipRules,
+    /// The ranker to persist as the index default, if any.
+    pub ranker: Option<Ranker>,
+    /// Merge small segments before indexing once there are more than this
+    /// many of them, or never if `None`.
+    pub auto_compact: Option<usize>,
+    /// The tokenizer options to persist in the index manifest, if any.
+    pub analyzer: Option<AnalyzerSettings>,
+    /// The key file encrypting the index, if any.
+    pub key_file: Option<PathBuf>,
+    /// Whether the key file of a new encrypted index holds the key itself,
+    /// rather than a secret the key is derived from.
+    pub raw_key: bool,
+    /// Run with low priority, few threads and a pause between documents.
+    pub throttle: bool,
+    /// How documents are discovered under `filepath`.
+    pub discovery: Discovery,
+    /// Start keeping the previous versions of re-indexed documents.
+    pub keep_history: bool,
+    /// Start indexing files with identical content on their own.
+    pub keep_duplicates: bool,
+    /// The size budget to persist in the index manifest, if any.
+    pub budget: Option<SizeBudget>,
+    /// The quotas to persist in the index manifest, if any.
+    pub quota: Option<Quota>,
+    /// The resource profile to persist in the index manifest, if any.
+    pub profile: Option<Profile>,
+    /// The segment memory budget to persist in the index manifest, if any.
+    pub segment_budget: Option<u64>,
+    /// Run the risky parsers in child processes with these limits, if any.
+    pub sandbox: Option<SandboxLimits>,
+    /// Resume the interrupted run on the index from its checkpoint instead
+    /// of discovering the documents again.
+    pub resume: bool,
+    /// Rules changing how the documents under some directories are indexed.
+    pub policies: Vec<PolicyRule>,
+    /// The tags attached to every indexed document, e.g. `project=alpha`.
+    pub tags: Vec<String>,
+    /// Rules tagging the documents under some directories.
+    pub tag_rules: Vec<TagRule>,
+    /// The abbreviation expansions to persist in the index manifest, if any.
+    pub expansions: Option<BTreeMap<String, String>>,
+    /// The stemming exceptions to persist in the index manifest, if any.
+    pub stem_exceptions: Option<BTreeMap<String, String>>,
+    /// The stop words replacing those of the analyzer language, to persist
+    /// in the index manifest, if any.
+    pub stop_words: Option<BTreeSet<String>>,
+    /// The noise pruning of rare terms to persist in the index manifest, if
+    /// any.
+    pub noise: Option<NoiseFilter>,
+    /// Stop indexing once this much time has passed, committing the
+    /// documents indexed so far. The most stale documents go first.
+    pub time_budget: Option<Duration>,
+    /// The order in which the documents are indexed.
+    pub order: IndexOrder,
+    /// The glob patterns of the documents indexed first with
+    /// `IndexOrder::Priority`, most important first.
+    pub priorities: Vec<String>,
+    /// How documents with no extension, or an unknown one, are typed.
+    pub sniffing: Sniffing,
+    /// Follow symbolic links while discovering documents, rather than
+    /// skip them.
+    pub follow_symlinks: bool,
+    /// The size in bytes past which documents are skipped, if any.
+    pub max_file_size: Option<u64>,
+    /// Counts the documents of the run as they are processed, for another
+    /// thread to follow.
+    pub progress: Option<Arc<IndexProgress>>,
+    /// Told about the run as it goes, e.g. to drive a progress bar. Nothing
+    /// about the run is printed without one.
+    pub observer: Option<Arc<dyn IndexingObserver>>,
+}
+
+impl Config {
+    /// Creates the configuration of a run indexing `filepath` into
+    /// `index_path` with the default settings: hidden files and symbolic
+    /// links skipped, the settings persisted in the index manifest left as
+    /// they are, and the documents discovered by the builtin walker.
+    ///
+    /// # Arguments
+    /// * `filepath` - The file or directory to index.
+    /// * `index_path` - The directory where the index files are stored.
+    /// * `logger` - The log the messages of the run are sent to.
+    pub fn new(filepath: PathBuf, index_path: PathBuf, logger: Logger) -> Self {
+        Self {
+            hidden: false,
+            error_handler: ErrorHandler::Stderr,
+            filepath,
+            index_path,
+            logger,
+            skip: SkipRules::default(),
+            ranker: None,
+            auto_compact: None,
+            analyzer: None,
+            key_file: None,
+            raw_key: false,
+            throttle: false,
+            discovery: Discovery::default(),
+            keep_history: false,
+            keep_duplicates: false,
+            budget: None,
+            quota: None,
+            profile: None,
+            segment_budget: None,
+            sandbox: None,
+            resume: false,
+            policies: Vec::new(),
+            tags: Vec::new(),
+            tag_rules: Vec::new(),
+            expansions: None,
+            stem_exceptions: None,
+            stop_words: None,
+            noise: None,
+            time_budget: None,
+            order: IndexOrder::default(),
+            priorities: Vec::new(),
+            sniffing: Sniffing::default(),
+            follow_symlinks: false,
+            max_file_size: None,
+            progress: None,
+            observer: None,
+        }
+    }
+
+    /// Returns the observer of the run, or one ignoring it if none is set.
+    fn observer(&self) -> &dyn IndexingObserver {
+        match &self.observer {
+            Some(observer) => observer.as_ref(),
+            None => &SilentObserver,
+        }
+    }
+}
+
+/// Options controlling how search results are ranked.
+#[derive(Clone, Default)]
+pub struct SearchOptions {
+    /// The ranking algorithm to use, or `None` for the index default.
+    pub ranker: Option<Ranker>,
+    /// Rules multiplying the scores of documents with matching paths.
+    pub boosts: Vec<BoostRule>,
+    /// Hooks multiplying the score of each document by a factor of its
+    /// own, e.g. a `RecencyBoost`.
+    pub score_boosts: Vec<Arc<dyn ScoreBoost>>,
+    /// The parameters of the BM25 ranker.
+    pub bm25: Bm25,
+    /// The weights of the fields matched by query terms.
+    pub field_weights: FieldWeights,
+    /// The key file of an encrypted index, if any.
+    pub key_file: Option<PathBuf>,
+    /// Only return documents in this language (ISO 639-1 code), unless the
+    /// query has its own `lang:` filter.
+    pub language: Option<String>,
+    /// Only return documents carrying all of these tags, as well as those
+    /// of the `tag:` filters of the query.
+    pub tags: Vec<String>,
+    /// Only return documents whose modification time, extension and
+    /// directory match this filter.
+    pub metadata: MetadataFilter,
+    /// The order of the results.
+    pub sort: SortOrder,
+    /// Search the index as it was at this time. Requires an index keeping
+    /// history.
+    pub as_of: Option<SystemTime>,
+    /// Also match the index terms within this many edits of the query
+    /// terms, at most `MAX_FUZZY_DISTANCE`.
+    pub fuzzy: Option<usize>,
+    /// The cache of decoded postings shared by the searches, e.g. of a
+    /// long-running server.
+    pub postings_cache: Option<Arc<PostingsCache>>,
+    /// Only this many best results are wanted, so that documents that
+    /// cannot rank among them are not scored. Every match is ranked when
+    /// the results are filtered or sorted other than by score.
+    pub limit: Option<usize>,
+    /// Record when the hits matched, for the least-matched eviction policy
+    /// of an index with a size budget. The record is written beside the
+    /// index, even one opened read-only, so searches only write it when
+    /// asked to.
+    pub record_matches: bool,
+}
+
+impl SearchOptions {
+    /// Applies the options that are set on the index itself. Once applied,
+    /// the index can be searched through a shared reference with
+    /// `search_term_shared`, e.g. by several threads at once.
+    ///
+    /// # Arguments
+    /// * `main_index` - The index about to be searched.
+    ///
+    /// # Returns
+    /// `Ok(())`, or an `anyhow::Error` if a point in time is requested from
+    /// an index that does not keep history.
+    pub fn apply(&self, main_index: &mut MainIndex) -> anyhow::Result<()> {
+        if self.as_of.is_some() && !main_index.manifest.keep_history {
+            return Err(anyhow::anyhow!(
+                "the index does not keep history; re-index it with --keep-history"
+            ));
+        }
+        main_index.boosts = self.boosts.clone();
+        main_index.score_boosts = self.score_boosts.clone();
+        main_index.bm25 = self.bm25;
+        main_index.field_weights = self.field_weights;
+        main_index.as_of = self.as_of;
+        main_index.postings_cache = self.postings_cache.clone();
+        Ok(())
+    }
+
+    /// Returns `true` if the searches record when their hits matched: they
+    /// are asked to and the index evicts the least matched documents first.
+    fn records_matches(&self, main_index: &MainIndex) -> bool {
+        self.record_matches
+            && main_index
+                .manifest
+                .budget
+                .as_ref()
+                .is_some_and(|budget| budget.policy == EvictionPolicy::LeastMatched)
+    }
+
+    /// Returns the number of best results a search for `query` may stop at:
+    /// `limit`, unless the results are filtered, sorted other than by score
+    /// or recorded for the least-matched eviction, which need every match.
+    ///
+    /// # Arguments
+    /// * `main_index` - The index about to be searched.
+    /// * `query` - The parsed query.
+    /// * `has_filter` - Whether the query has a boolean filter.
+    /// * `limit` - The number of results wanted, if limited.
+    fn result_limit(
+        &self,
+        main_index: &MainIndex,
+        query: &Query,
+        has_filter: bool,
+        limit: Option<usize>,
+    ) -> Option<usize> {
+        let records_matches = self.records_matches(main_index);
+        let filtered = has_filter
+            || query.language().or(self.language.as_deref()).is_some()
+            || !self.tags(query).is_empty()
+            || !self.metadata.is_empty();
+        limit.filter(|_| !filtered && !records_matches && self.sort == SortOrder::Score)
+    }
+
+    /// Analyzes a parsed query into the terms searched for and its boolean
+    /// filter, both expanded to the similar index terms if fuzzy matching
+    /// is enabled. Queries restricted to a language are stemmed in it if
+    /// the index detects the language of its documents.
+    ///
+    /// # Arguments
+    /// * `main_index` - The index about to be searched.
+    /// * `query` - The parsed query.
+    ///
+    /// # Returns
+    /// The query terms and the analyzed filter, if the query has one.
+    fn analyze(
+        &self,
+        main_index: &MainIndex,
+        query: &Query,
+    ) -> (Vec<String>, Option<BoolExpr<Vec<String>>>) {
+        let mut analyzer = main_index.analyzer();
+        if let Some(language) = query
+            .language()
+            .or(self.language.as_deref())
+            .and_then(Language::from_code)
+            && analyzer.settings.detect_language
+        {
+            analyzer = analyzer.in_language(language);
+        }
+        let expand = |terms: Vec<String>| match self.fuzzy {
+            Some(max_distance) => main_index.fuzzy_expand(&terms, max_distance),
+            None => terms,
+        };
+        let tokens = expand(analyzer.analyze_query(query));
+        let filter = analyzer
+            .analyze_filter(query)
+            .and_then(|filter| filter.filter_map(&|terms| Some(expand(terms.clone()))));
+        (tokens, filter)
+    }
+
+    /// Returns the tags the results of a query must carry: those of its
+    /// `tag:` filters and those of the options.
+    fn tags<'a>(&'a self, query: &'a Query) -> Vec<&'a str> {
+        let mut tags = query.tags();
+        tags.extend(self.tags.iter().map(String::as_str));
+        tags
+    }
 }
 
 /// Defines where error and informational messages should be output.
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum ErrorHandler {
     /// Messages are printed to `stderr`.
     Stderr,
@@ -59,13 +445,15 @@ pub enum Message {
     Debug(String),
 }
 
+/// Type alias for a parser function. It takes a `Path`, the `Logger` of
+/// the run and an `Analyzer`, returning the document terms and parse errors
+/// as a `ParseOutcome`.
+type Parser = fn(&Path, &Logger, &Analyzer) -> ParseOutcome;
+
 /// Type alias for a `HashMap` mapping file extensions (as `String`) to parser functions.
-/// Each parser function takes a `Path`, an `Arc<RwLock<mpsc::Sender<Message>>>`,
-/// and a slice of `String` (stop words), returning an `anyhow::Result<Vec<String>>`.
-type ExtensionToParser =
-    HashMap<String, fn(&Path, Arc<RwLock<mpsc::Sender<Message>>>, &[String]) -> Vec<String>>;
+type ExtensionToParser = HashMap<String, Parser>;
 
-fn get_extensions_map() -> ExtensionToParser {
+pub(crate) fn get_extensions_map() -> ExtensionToParser {
     let mut extensions_map: ExtensionToParser = HashMap::new();
 
     extensions_map.insert("csv".to_string(), parse_csv_document);
@@ -75,78 +463,827 @@ fn get_extensions_map() -> ExtensionToParser {
     extensions_map.insert("xhtml".to_string(), parse_xml_document);
     extensions_map.insert("txt".to_string(), parse_txt_document);
     extensions_map.insert("md".to_string(), parse_txt_document);
+    extensions_map.insert("tex".to_string(), parse_latex_document);
+    extensions_map.insert("org".to_string(), parse_org_document);
+    extensions_map.insert("srt".to_string(), parse_subtitle_document);
+    extensions_map.insert("vtt".to_string(), parse_subtitle_document);
+    extensions_map.insert("eml".to_string(), parse_email_document);
+    extensions_map.insert("mbox".to_string(), parse_mbox_document);
+    extensions_map.insert("rtf".to_string(), parse_rtf_document);
+    extensions_map.insert("docx".to_string(), parse_docx_document);
+    extensions_map.insert("pptx".to_string(), parse_pptx_document);
+    extensions_map.insert("zip".to_string(), parse_zip_document);
+    extensions_map.insert("tar".to_string(), parse_archive_document);
+    extensions_map.insert("tgz".to_string(), parse_archive_document);
+    extensions_map.insert("gz".to_string(), parse_archive_document);
+    for ext in CODE_EXTENSIONS {
+        extensions_map.insert(ext.to_string(), parse_code_document);
+    }
+    #[cfg(feature = "ocr")]
+    for ext in ocr::IMAGE_EXTENSIONS {
+        extensions_map.insert(ext.to_string(), parse_image_document);
+    }
     extensions_map.shrink_to_fit();
     extensions_map
 }
 
+/// The results of a search.
+pub struct SearchResults {
+    /// The matching documents and their scores, in descending order of score.
+    pub hits: Vec<(PathBuf, f64)>,
+    /// The broken segments left out of the search. The hits are partial if
+    /// there are any.
+    pub skipped_segments: Vec<u64>,
+    /// The other paths with the same content as a hit, keyed by the path of
+    /// the hit. Only the best ranked path of identical documents is a hit.
+    pub duplicates: HashMap<PathBuf, Vec<PathBuf>>,
+    /// The titles of the hits that have one, keyed by the path of the hit.
+    pub titles: HashMap<PathBuf, String>,
+    /// The generation of the index the search read. Only committed segments
+    /// of that generation are searched.
+    pub generation: u64,
+    /// The number of matches. If the search stopped at the best results
+    /// asked for, it is the number of matching documents, counted without
+    /// ranking them, and `hits` only hold the best ones.
+    pub total: u64,
+}
+
+impl SearchResults {
+    /// Returns `true` if broken segments were left out of the search.
+    pub fn is_partial(&self) -> bool {
+        !self.skipped_segments.is_empty()
+    }
+}
+
 /// Searches the index for a given term. It tokenizes the term,
 /// loads the main index, and performs the search.
 ///
 /// # Arguments
 /// * `term` - The search query string.
 /// * `index_file` - The path to the directory containing the index files.
+/// * `options` - The `SearchOptions` controlling the ranking.
+///
+/// # Returns
+/// The `SearchResults`, or an `anyhow::Error` on failure.
+pub fn search_term(
+    term: &str,
+    index_file: &Path,
+    options: &SearchOptions,
+) -> anyhow::Result<SearchResults> {
+    let mut main_index = MainIndex::open_read_only(index_file, options.key_file.as_deref())
+        .context("open main index")?;
+    search_term_in(&mut main_index, term, options)
+}
+
+/// Searches the index like `search_term` without blocking the async task
+/// awaiting the results: the index is read on a thread of the offload pool.
+///
+/// # Arguments
+/// * `term` - The search query string.
+/// * `index_file` - The path to the directory containing the index files.
+/// * `options` - The `SearchOptions` controlling the ranking.
 ///
 /// # Returns
-/// A `Result` containing a `Vec` of tuples, where each tuple is a `PathBuf`
-/// of a matching document and its TF-IDF score, or an `anyhow::Error` on failure.
-pub fn search_term(term: &str, index_file: &Path) -> anyhow::Result<Vec<(PathBuf, f64)>> {
-    let text_chars = term.to_lowercase().chars().collect::<Vec<char>>();
-    let mut lex = lexer::Lexer::new(&text_chars);
-    let stop_words = stop_words::get(LANGUAGE::English);
-    let tokens = lex.get_tokens(&stop_words);
-    let main_index = MainIndex::new(index_file).context("new main index")?;
-    let results = main_index.search(&tokens).context("query results")?;
-    Ok(results)
-}
-
-fn get_docs(
+/// The `Offloaded` future resolving to the `SearchResults`, or an
+/// `anyhow::Error` on failure.
+pub fn search_term_async(
+    term: String,
+    index_file: PathBuf,
+    options: SearchOptions,
+) -> Offloaded<anyhow::Result<SearchResults>> {
+    offload(move || search_term(&term, &index_file, &options))
+}
+
+/// Searches an already opened index for a given term, e.g. one kept
+/// preloaded by the server.
+///
+/// # Arguments
+/// * `main_index` - The opened `MainIndex`.
+/// * `term` - The search query string.
+/// * `options` - The `SearchOptions` controlling the ranking.
+///
+/// # Returns
+/// The `SearchResults`, or an `anyhow::Error` on failure.
+pub fn search_term_in(
+    main_index: &mut MainIndex,
+    term: &str,
+    options: &SearchOptions,
+) -> anyhow::Result<SearchResults> {
+    options.apply(main_index)?;
+    search_term_shared(main_index, term, options)
+}
+
+/// Searches an index `options` were already applied to with
+/// `SearchOptions::apply`, through a shared reference so that several
+/// threads can search it at once, e.g. the index kept in memory by the
+/// server.
+///
+/// # Arguments
+/// * `main_index` - The opened `MainIndex`, with `options` applied.
+/// * `term` - The search query string.
+/// * `options` - The `SearchOptions` controlling the ranking.
+///
+/// # Returns
+/// The `SearchResults`, or an `anyhow::Error` on failure.
+pub fn search_term_shared(
+    main_index: &MainIndex,
+    term: &str,
+    options: &SearchOptions,
+) -> anyhow::Result<SearchResults> {
+    let query = Query::parse(term)?;
+    let (tokens, filter) = options.analyze(main_index, &query);
+    EmptyQueryError::check(term, &tokens)?;
+    let limit = options.result_limit(main_index, &query, filter.is_some(), options.limit);
+    let top = match limit {
+        Some(limit) => main_index.search_top(&tokens, options.ranker, limit),
+        None => main_index
+            .search_ranked(&tokens, options.ranker)
+            .map(|results| TopResults {
+                results,
+                pruned: false,
+            }),
+    }
+    .context("query results")?;
+    let mut results = top.results;
+    if let Some(filter) = filter {
+        main_index
+            .retain_matching(&mut results, &filter)
+            .context("filter query results")?;
+    }
+    if let Some(language) = query.language().or(options.language.as_deref()) {
+        main_index.retain_language(&mut results, language);
+    }
+    main_index.retain_tags(&mut results, &options.tags(&query));
+    main_index.retain_metadata(&mut results, &options.metadata);
+    let duplicates = main_index.collapse_duplicates(&mut results);
+    let mut total = results.len() as u64;
+    if let Some(limit) = limit
+        && top.pruned
+    {
+        if results.len() < limit {
+            // Collapsing duplicates left fewer than the best results asked
+            // for, which the skipped documents may make up for
+            let options = SearchOptions {
+                limit: None,
+                ..options.clone()
+            };
+            return search_term_shared(main_index, term, &options);
+        }
+        results.truncate(limit);
+        total = main_index.search_count(&tokens);
+    }
+    main_index.sort_results(&mut results, options.sort);
+    record_budget_matches(main_index, options, &results);
+    Ok(SearchResults {
+        titles: hit_titles(main_index, &results),
+        hits: results,
+        skipped_segments: main_index.skipped_segments(),
+        duplicates,
+        generation: main_index.manifest.generation,
+        total,
+    })
+}
+
+/// Counts the documents matching a query without ranking them, which is
+/// much cheaper than `search_term` when only the number of hits is needed.
+/// Queries with a boolean filter, a language, tags or a metadata filter are
+/// searched in full, as their filters apply to the ranked results.
+///
+/// # Arguments
+/// * `term` - The search query string.
+/// * `index_file` - The path to the directory containing the index files.
+/// * `options` - The `SearchOptions` of the search.
+///
+/// # Returns
+/// The number of matching documents, or an `anyhow::Error` on failure.
+pub fn count_term(term: &str, index_file: &Path, options: &SearchOptions) -> anyhow::Result<u64> {
+    let mut main_index = MainIndex::open_read_only(index_file, options.key_file.as_deref())
+        .context("open main index")?;
+    let query = Query::parse(term)?;
+    let (tokens, filter) = options.analyze(&main_index, &query);
+    EmptyQueryError::check(term, &tokens)?;
+    if filter.is_some()
+        || query.language().or(options.language.as_deref()).is_some()
+        || !options.tags(&query).is_empty()
+        || !options.metadata.is_empty()
+    {
+        let results = search_term_in(&mut main_index, term, options)?;
+        return Ok(results.hits.len() as u64);
+    }
+    options.apply(&mut main_index)?;
+    Ok(main_index.search_count(&tokens))
+}
+
+/// Reads the text around the first match of a query in each of `paths`,
+/// e.g. to show it next to the search results.
+///
+/// # Arguments
+/// * `term` - The search query string.
+/// * `index_file` - The path to the directory containing the index files.
+/// * `options` - The `SearchOptions` of the search.
+/// * `paths` - The documents found by the search.
+///
+/// # Returns
+/// A `HashMap` mapping each document to its snippet, without the documents
+/// that cannot be read back or have no match, or an `anyhow::Error` if the
+/// index cannot be opened.
+pub fn hit_snippets(
+    term: &str,
+    index_file: &Path,
+    options: &SearchOptions,
+    paths: &[&Path],
+) -> anyhow::Result<HashMap<PathBuf, Fragment>> {
+    let main_index = MainIndex::open_read_only(index_file, options.key_file.as_deref())
+        .context("open main index")?;
+    hit_snippets_in(&main_index, term, options, paths)
+}
+
+/// Reads the text around the first match of a query in each of `paths` as
+/// `hit_snippets` does, in an already opened index.
+///
+/// # Arguments
+/// * `main_index` - The index the documents were found in.
+/// * `term` - The search query string.
+/// * `options` - The `SearchOptions` of the search.
+/// * `paths` - The documents found by the search.
+///
+/// # Returns
+/// A `HashMap` mapping each document to its snippet, or an `anyhow::Error`
+/// if the query cannot be parsed.
+pub fn hit_snippets_in(
+    main_index: &MainIndex,
+    term: &str,
+    options: &SearchOptions,
+    paths: &[&Path],
+) -> anyhow::Result<HashMap<PathBuf, Fragment>> {
+    let (tokens, _) = options.analyze(main_index, &Query::parse(term)?);
+    Ok(paths
+        .iter()
+        .filter_map(|&path| {
+            let fragment = main_index
+                .highlight(path, &tokens)
+                .ok()?
+                .into_iter()
+                .next()?;
+            Some((path.to_path_buf(), fragment))
+        })
+        .collect())
+}
+
+/// Explains how a query is analyzed and matched against the index: the
+/// terms of each clause, the stop words and stemming applied, and how many
+/// segments and documents contain each term.
+///
+/// # Arguments
+/// * `term` - The search query string.
+/// * `index_file` - The path to the directory containing the index files.
+/// * `options` - The `SearchOptions` controlling the ranking.
+///
+/// # Returns
+/// The `QueryDebug` of the query, or an `anyhow::Error` on failure.
+pub fn debug_query(
+    term: &str,
+    index_file: &Path,
+    options: &SearchOptions,
+) -> anyhow::Result<QueryDebug> {
+    let mut main_index = MainIndex::open_read_only(index_file, options.key_file.as_deref())
+        .context("open main index")?;
+    options.apply(&mut main_index)?;
+    let query = Query::parse(term)?;
+    let ranker = options.ranker.unwrap_or(main_index.manifest.ranker);
+    Ok(QueryDebug::new(&main_index, term, &query, ranker))
+}
+
+/// Searches the index for several queries at once, sharing the segment
+/// dictionary and postings reads between them.
+///
+/// # Arguments
+/// * `queries` - The search query strings.
+/// * `index_file` - The path to the directory containing the index files.
+/// * `options` - The `SearchOptions` controlling the ranking.
+///
+/// # Returns
+/// The `SearchResults` of each query, or the error that query alone failed
+/// with, in the order of `queries`, or an `anyhow::Error` if the index could
+/// not be searched.
+pub fn search_terms(
+    queries: &[String],
+    index_file: &Path,
+    options: &SearchOptions,
+) -> anyhow::Result<Vec<anyhow::Result<SearchResults>>> {
+    let mut main_index = MainIndex::open_read_only(index_file, options.key_file.as_deref())
+        .context("open main index")?;
+    search_terms_in(&mut main_index, queries, options)
+}
+
+/// Searches an already opened index for several queries at once, e.g. one
+/// kept preloaded by the server.
+///
+/// # Arguments
+/// * `main_index` - The opened `MainIndex`.
+/// * `queries` - The search query strings.
+/// * `options` - The `SearchOptions` controlling the ranking.
+///
+/// # Returns
+/// The `SearchResults` of each query, or the error that query alone failed
+/// with, in the order of `queries`, or an `anyhow::Error` if the index could
+/// not be searched.
+pub fn search_terms_in(
+    main_index: &mut MainIndex,
+    queries: &[String],
+    options: &SearchOptions,
+) -> anyhow::Result<Vec<anyhow::Result<SearchResults>>> {
+    options.apply(main_index)?;
+    search_terms_shared(main_index, queries, options)
+}
+
+/// Searches an index `options` were already applied to for several queries
+/// at once, through a shared reference as `search_term_shared` does. A query
+/// that cannot be parsed or is reduced to nothing by analysis only fails its
+/// own entry, the others are still searched.
+///
+/// # Arguments
+/// * `main_index` - The opened `MainIndex`, with `options` applied.
+/// * `queries` - The search query strings.
+/// * `options` - The `SearchOptions` controlling the ranking.
+///
+/// # Returns
+/// The `SearchResults` of each query, or the error that query alone failed
+/// with, in the order of `queries`, or an `anyhow::Error` if the index could
+/// not be searched.
+pub fn search_terms_shared(
+    main_index: &MainIndex,
+    queries: &[String],
+    options: &SearchOptions,
+) -> anyhow::Result<Vec<anyhow::Result<SearchResults>>> {
+    let prepared = queries
+        .iter()
+        .map(|query| {
+            let parsed = Query::parse(query)?;
+            let (tokens, filter) = options.analyze(main_index, &parsed);
+            EmptyQueryError::check(query, &tokens)?;
+            Ok((parsed, tokens, filter))
+        })
+        .collect::<Vec<anyhow::Result<_>>>();
+    let analyzed = prepared
+        .iter()
+        .filter_map(|prepared| prepared.as_ref().ok())
+        .map(|(_, tokens, _)| tokens.clone())
+        .collect::<Vec<_>>();
+    let mut batch = main_index
+        .search_batch(&analyzed, options.ranker)
+        .context("batch query results")?
+        .into_iter();
+    let skipped_segments = main_index.skipped_segments();
+    prepared
+        .into_iter()
+        .map(|prepared| {
+            let (query, _, filter) = match prepared {
+                Ok(prepared) => prepared,
+                Err(err) => return Ok(Err(err)),
+            };
+            let mut hits = batch.next().context("batch query results")?;
+            if let Some(filter) = filter {
+                main_index
+                    .retain_matching(&mut hits, &filter)
+                    .context("filter query results")?;
+            }
+            if let Some(language) = query.language().or(options.language.as_deref()) {
+                main_index.retain_language(&mut hits, language);
+            }
+            main_index.retain_tags(&mut hits, &options.tags(&query));
+            main_index.retain_metadata(&mut hits, &options.metadata);
+            record_budget_matches(main_index, options, &hits);
+            let duplicates = main_index.collapse_duplicates(&mut hits);
+            main_index.sort_results(&mut hits, options.sort);
+            Ok(Ok(SearchResults {
+                titles: hit_titles(main_index, &hits),
+                total: hits.len() as u64,
+                hits,
+                skipped_segments: skipped_segments.clone(),
+                duplicates,
+                generation: main_index.manifest.generation,
+            }))
+        })
+        .collect()
+}
+
+/// Returns the titles of the hits that have one, keyed by their path.
+fn hit_titles(main_index: &MainIndex, hits: &[(PathBuf, f64)]) -> HashMap<PathBuf, String> {
+    hits.iter()
+        .filter_map(|(path, _)| {
+            let title = main_index.doc_store.title(path)?;
+            Some((path.clone(), title.to_string()))
+        })
+        .collect()
+}
+
+/// Records when the hits of a search matched, if asked to and the index
+/// evicts the least recently matched documents first. Failing to record them
+/// doesn't fail the search.
+fn record_budget_matches(main_index: &MainIndex, options: &SearchOptions, hits: &[(PathBuf, f64)]) {
+    if options.records_matches(main_index) && !hits.is_empty() {
+        let _ = record_matches(
+            &main_index.index_dir,
+            main_index.cipher(),
+            hits.iter().map(|(path, _)| path.as_path()),
+        );
+    }
+}
+
+/// Returns the JSON object of a search hit: `{"path", "score"}`, with the
+/// `title` and the `duplicates` of the hit if it has any.
+///
+/// # Arguments
+/// * `path` - The path of the hit.
+/// * `score` - The score of the hit.
+/// * `duplicates` - The duplicates of the hits, from `SearchResults`.
+/// * `titles` - The titles of the hits, from `SearchResults`.
+pub fn hit_json(
+    path: &Path,
+    score: f64,
+    duplicates: &HashMap<PathBuf, Vec<PathBuf>>,
+    titles: &HashMap<PathBuf, String>,
+) -> Value {
+    let mut hit = json!({ "path": path.to_string_lossy(), "score": score });
+    if let Some(title) = titles.get(path) {
+        hit["title"] = Value::from(title.as_str());
+    }
+    if let Some(others) = duplicates.get(path) {
+        hit["duplicates"] = others
+            .iter()
+            .map(|other| Value::from(other.to_string_lossy()))
+            .collect();
+    }
+    hit
+}
+
+/// Returns the JSON object of a query that failed: `{"query", "error"}`, with
+/// the `tokens` it was analyzed into if it was reduced to nothing.
+///
+/// # Arguments
+/// * `query` - The query as given.
+/// * `err` - The error the query failed with.
+pub fn query_error_json(query: &str, err: &anyhow::Error) -> Value {
+    match err.downcast_ref::<EmptyQueryError>() {
+        Some(empty) => json!({
+            "query": query,
+            "error": format!("{err:#}"),
+            "tokens": empty.tokens,
+        }),
+        None => json!({ "query": query, "error": format!("{err:#}") }),
+    }
+}
+
+/// Answers queries read one per line, writing one JSON line per query with
+/// its results (`{"query", "results": [{"path", "score", "title", "duplicates"}], "partial",
+/// "generation", "took_ms"}`) or its error (`{"query", "error"}`). The index is opened once for all of
+/// them. Blank lines are ignored.
+///
+/// # Arguments
+/// * `input` - The reader yielding the queries.
+/// * `output` - The writer receiving the result lines, flushed after each.
+/// * `index_file` - The path to the directory containing the index files.
+/// * `options` - The `SearchOptions` controlling the ranking.
+/// * `count` - The maximum number of results per query, if any.
+///
+/// # Returns
+/// `Ok(())` once `input` is exhausted, or an `anyhow::Error` if the index
+/// cannot be opened or reading or writing fails.
+pub fn search_lines(
+    input: impl BufRead,
+    mut output: impl Write,
+    index_file: &Path,
+    options: &SearchOptions,
+    count: Option<usize>,
+) -> anyhow::Result<()> {
+    let mut main_index = MainIndex::open_read_only(index_file, options.key_file.as_deref())
+        .context("open main index")?;
+    options.apply(&mut main_index)?;
+    let options = SearchOptions {
+        limit: count,
+        ..options.clone()
+    };
+
+    for line in input.lines() {
+        let query = line.context("read query")?;
+        if query.trim().is_empty() {
+            continue;
+        }
+        let started = Instant::now();
+        let line = match search_term_shared(&main_index, &query, &options) {
+            Ok(mut results) => {
+                if let Some(count) = count {
+                    results.hits.truncate(count);
+                }
+                let hits = results
+                    .hits
+                    .iter()
+                    .map(|(path, score)| {
+                        hit_json(path, *score, &results.duplicates, &results.titles)
+                    })
+                    .collect::<Vec<_>>();
+                json!({
+                    "query": query,
+                    "results": hits,
+                    "partial": results.is_partial(),
+                    "generation": results.generation,
+                    "took_ms": started.elapsed().as_secs_f64() * 1000.0,
+                })
+            }
+            Err(err) => query_error_json(&query, &err),
+        };
+        writeln!(output, "{line}").context("write results")?;
+        output.flush().context("flush results")?;
+    }
+    Ok(())
+}
+
+/// Lists the terms of the index with their document frequencies, most
+/// frequent first. Exact terms are shown in their original casing if the
+/// index preserves it.
+///
+/// # Arguments
+/// * `index_file` - The path to the directory containing the index files.
+/// * `prefix` - Only list terms starting with this prefix, if given.
+/// * `top` - Only list this many terms, if given.
+/// * `key_file` - The key file of an encrypted index, if any.
+///
+/// # Returns
+/// A `Vec` of `(term, document frequency)` tuples, or an `anyhow::Error` on
+/// failure.
+pub fn list_terms(
+    index_file: &Path,
+    prefix: Option<&str>,
+    top: Option<usize>,
+    key_file: Option<&Path>,
+) -> anyhow::Result<Vec<(String, u64)>> {
+    let main_index = MainIndex::open_read_only(index_file, key_file).context("open main index")?;
+    let mut terms = main_index
+        .global_document_frequencies()
+        .context("document frequencies")?
+        .into_iter()
+        .filter(|(term, _)| prefix.is_none_or(|p| term.starts_with(p)))
+        .collect::<Vec<(String, u64)>>();
+
+    terms.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    if let Some(top) = top {
+        terms.truncate(top);
+    }
+    for (term, _) in terms.iter_mut() {
+        *term = main_index.display_term(term);
+    }
+    Ok(terms)
+}
+
+/// Merges the small segments of the index in `index_file`, or all of them,
+/// and removes the merged segment directories.
+///
+/// # Arguments
+/// * `index_file` - The path to the directory containing the index files.
+/// * `all` - Merge every segment into one instead of only the small ones.
+/// * `key_file` - The key file of an encrypted index, if any.
+///
+/// # Returns
+/// The `CompactSummary` of the merge, or an `anyhow::Error` on failure.
+pub fn compact_segments(
+    index_file: &Path,
+    all: bool,
+    key_file: Option<&Path>,
+) -> anyhow::Result<CompactSummary> {
+    let mut main_index = MainIndex::open(index_file, key_file).context("open main index")?;
+    let summary = main_index.compact(all).context("merge segments")?;
+    if !summary.merged.is_empty() {
+        main_index.commit().context("commit compaction")?;
+    }
+    Ok(summary)
+}
+
+/// Summarizes what the index in `index_file` contains.
+///
+/// # Arguments
+/// * `index_file` - The path to the directory containing the index files.
+/// * `largest` - The number of largest documents to list.
+/// * `key_file` - The key file of an encrypted index, if any.
+///
+/// # Returns
+/// The `IndexSummary` of the index, or an `anyhow::Error` on failure.
+pub fn index_stats(
+    index_file: &Path,
+    largest: usize,
+    key_file: Option<&Path>,
+) -> anyhow::Result<IndexSummary> {
+    let main_index = MainIndex::open_read_only(index_file, key_file).context("open main index")?;
+    main_index.summary(largest).context("summarize index")
+}
+
+/// Moves the indexed documents at `from`, or under it if it is a directory,
+/// to `to` without parsing them again. The files are moved too unless they
+/// were already, i.e. `from` no longer exists and `to` does.
+///
+/// # Arguments
+/// * `index_file` - The path to the directory containing the index files.
+/// * `from` - The old path of the documents, as it was indexed.
+/// * `to` - The new path of the documents.
+/// * `key_file` - The key file of an encrypted index, if any.
+///
+/// # Returns
+/// The old and new URIs of the moved documents, or an `anyhow::Error` if no
+/// document is indexed at `from` or the move fails.
+pub fn move_documents(
+    index_file: &Path,
+    from: &Path,
+    to: &Path,
+    key_file: Option<&Path>,
+) -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
+    let mut main_index = MainIndex::open(index_file, key_file).context("open main index")?;
+    let moves = main_index
+        .doc_store
+        .rename(from, to)
+        .with_context(|| format!("move {from:?} to {to:?}"))?;
+    if moves.is_empty() {
+        return Err(anyhow::anyhow!("no indexed documents at {from:?}"));
+    }
+    if fs::symlink_metadata(from).is_ok() {
+        if fs::symlink_metadata(to).is_ok() {
+            return Err(anyhow::anyhow!("{to:?} already exists"));
+        }
+        fs::rename(from, to).with_context(|| format!("move {from:?} to {to:?}"))?;
+    } else if fs::symlink_metadata(to).is_err() {
+        return Err(anyhow::anyhow!("neither {from:?} nor {to:?} exist"));
+    }
+    main_index.commit().context("commit moved documents")?;
+    Ok(moves)
+}
+
+/// Detects the indexed files moved between any of `roots`, e.g. the paths
+/// watched by the service, and moves their documents without parsing them
+/// again. An indexing run only finds the files moved within its own path
+/// and deletes those moved out of it, so this runs before the runs of
+/// each root. The paths are only walked if an indexed file is missing.
+///
+/// # Arguments
+/// * `cfg` - The indexing `Config` of the runs, whose path is ignored.
+/// * `roots` - The paths the files may have moved between.
+///
+/// # Returns
+/// The old and new URIs of the moved documents, or an `anyhow::Result`
+/// error.
+pub fn detect_moves(cfg: &Config, roots: &[PathBuf]) -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
+    let mut main_index = open_for_indexing(cfg)?;
+    if !main_index.doc_store.has_missing_files() {
+        return Ok(Vec::new());
+    }
+    let mut discovered = Vec::new();
+    for root in roots {
+        let (docs, _) = get_docs(
+            root.clone(),
+            cfg.hidden,
+            cfg.follow_symlinks,
+            &cfg.skip,
+            cfg.discovery,
+            &mut RunReport::new(root),
+        )
+        .with_context(|| format!("discover {root:?}"))?;
+        discovered.extend(docs);
+    }
+    let moves = main_index.doc_store.detect_renames(&discovered);
+    if !moves.is_empty() {
+        main_index.commit().context("commit moved documents")?;
+    }
+    Ok(moves)
+}
+
+/// Deletes the indexed documents at `path`, or under it if it is a
+/// directory, from the index. The files themselves are left alone.
+///
+/// # Arguments
+/// * `index_file` - The path to the directory containing the index files.
+/// * `path` - The path of the documents to delete, as it was indexed.
+/// * `key_file` - The key file of an encrypted index, if any.
+///
+/// # Returns
+/// The URIs of the deleted documents, or an `anyhow::Error` if none is
+/// indexed at `path`.
+pub fn delete_documents(
+    index_file: &Path,
+    path: &Path,
+    key_file: Option<&Path>,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let mut main_index = MainIndex::open(index_file, key_file).context("open main index")?;
+    let deleted = main_index
+        .delete_document(path)
+        .with_context(|| format!("delete {path:?}"))?;
+    if deleted.is_empty() {
+        return Err(anyhow::anyhow!("no indexed documents at {path:?}"));
+    }
+    main_index.commit().context("commit deleted documents")?;
+    Ok(deleted)
+}
+
+/// Checks that every segment of the index in `index_file` can be read, and
+/// repairs the broken ones if asked to.
+///
+/// # Arguments
+/// * `index_file` - The path to the directory containing the index files.
+/// * `repair` - Rebuild the broken segments from their readable postings,
+///   or drop them, with `MainIndex::repair`.
+/// * `key_file` - The key file of an encrypted index, if any.
+///
+/// # Returns
+/// The `SegmentCheck` of every segment before any repair, the
+/// `RepairSummary` if a repair ran, or an `anyhow::Error` on failure.
+pub fn verify_index(
+    index_file: &Path,
+    repair: bool,
+    key_file: Option<&Path>,
+) -> anyhow::Result<(Vec<SegmentCheck>, Option<RepairSummary>)> {
+    if !repair {
+        let main_index =
+            MainIndex::open_read_only(index_file, key_file).context("open main index")?;
+        return Ok((main_index.verify(), None));
+    }
+    let mut main_index = MainIndex::open(index_file, key_file).context("open main index")?;
+    let checks = main_index.verify();
+    let summary = main_index.repair().context("repair segments")?;
+    Ok((checks, Some(summary)))
+}
+
+pub(crate) fn get_docs(
     filepath: PathBuf,
     handle_hidden: bool,
-    skip_paths: &[PathBuf],
-) -> Result<Vec<PathBuf>, String> {
+    follow_symlinks: bool,
+    skip: &SkipRules,
+    discovery: Discovery,
+    report: &mut RunReport,
+) -> api::Result<(Vec<PathBuf>, ModifiedTimes)> {
     if filepath.is_dir() {
         let basename = match filepath.file_name() {
             Some(v) => v.to_string_lossy().to_string(),
             None => "".to_string(),
         };
         if basename.starts_with(".") && !handle_hidden {
-            return Err("Provide the `hidden` flag to index hidden directories".to_string());
+            return Err(api::Error::Index(anyhow::anyhow!(
+                "Provide the `hidden` flag to index hidden directories"
+            )));
         }
 
-        if skip_paths.contains(&filepath)
-            || skip_paths.contains(&Path::new(&basename).to_path_buf())
-        {
-            return Err("Skipping and indexing the same path".to_string());
+        if let Some(reason) = skip.matches(&filepath) {
+            return Err(api::Error::Index(anyhow::anyhow!(
+                "The indexed path {filepath:?} is itself excluded by {reason}"
+            )));
         }
 
-        read_files_recursively(&filepath, handle_hidden, skip_paths)
+        match discovery {
+            Discovery::Builtin => {
+                let mut symlinks = Symlinks::new(follow_symlinks);
+                symlinks.admit(&filepath, false);
+                read_files_recursively(&filepath, handle_hidden, &mut symlinks, skip, report)
+                    .map(|files| (files, ModifiedTimes::new()))
+            }
+            Discovery::Ignore => Ok((
+                walker::walk_ignore(&filepath, handle_hidden, follow_symlinks, skip, report),
+                ModifiedTimes::new(),
+            )),
+            Discovery::Remote => Ok(walker::walk_remote(
+                &filepath,
+                handle_hidden,
+                follow_symlinks,
+                skip,
+                report,
+            )),
+        }
     } else {
-        Ok(Vec::from([filepath]))
+        Ok((Vec::from([filepath]), ModifiedTimes::new()))
     }
 }
 
-/// Recursively reads files from a directory, respecting hidden file settings
-/// and skip paths.
+/// Recursively reads files from a directory, respecting hidden file settings,
+/// skip paths and the symbolic link policy.
 ///
 /// # Arguments
 /// * `files_dir` - The directory to read files from.
 /// * `scan_hidden` - If `true`, hidden files and directories will be included.
-/// * `skip_paths` - A slice of paths to explicitly skip.
+/// * `symlinks` - Which symbolic links are followed, and what was reached.
+/// * `skip` - The `SkipRules` excluding paths.
+/// * `report` - The `RunReport` recording the skipped paths.
 ///
 /// # Returns
-/// A `Result` containing a `Vec<PathBuf>` of discovered files, or an
-/// `anyhow::Result` error.
+/// A `Result` containing a `Vec<PathBuf>` of discovered files, or the
+/// `Error::Io` that stopped the discovery, keeping its `io::Error` as source.
 fn read_files_recursively(
     files_dir: &Path,
     scan_hidden: bool,
-    skip_paths: &[PathBuf],
-) -> anyhow::Result<Vec<PathBuf>, String> {
+    symlinks: &mut Symlinks,
+    skip: &SkipRules,
+    report: &mut RunReport,
+) -> api::Result<Vec<PathBuf>> {
     let mut files = Vec::new();
 
     // Skip invalid filepaths
     // Skip hidden files if the scan_hidden flag is not set
-    // Skip filepaths and basenames specified in the `skip_paths` list
-    // Skip files whose executable bits have been set
+    // Skip paths matched by the skip rules
+    // Skip files whose executable bits have been set, if asked to
     if !files_dir.exists() {
         return Ok(files);
     }
@@ -155,40 +1292,48 @@ fn read_files_recursively(
         .file_name()
         .map(|v| v.to_string_lossy().to_string())
         .unwrap_or_default();
-    if (basename.starts_with(".") && !scan_hidden)
-        || skip_paths.contains(&files_dir.to_path_buf())
-        || skip_paths.contains(&Path::new(&basename).to_path_buf())
-    {
+    if let Some((kind, reason)) = skip_reason(files_dir, &basename, scan_hidden, skip) {
+        report.skip(files_dir, kind, reason);
         return Ok(files);
     }
 
     if files_dir.is_dir() {
-        for entry in fs::read_dir(files_dir).map_err(|err| err.to_string())? {
-            let entry = entry.map_err(|err| err.to_string())?;
+        let read_error = |err: io::Error| {
+            api::Error::Io(anyhow::Error::new(err).context(format!("read directory {files_dir:?}")))
+        };
+        for entry in fs::read_dir(files_dir).map_err(read_error)? {
+            let entry = entry.map_err(read_error)?;
             let path = entry.path();
 
             let basename = path
                 .file_name()
                 .map(|v| v.to_string_lossy().to_string())
                 .unwrap_or_default();
-            if (basename.starts_with(".") && !scan_hidden)
-                || skip_paths.contains(&path.to_path_buf())
-                || skip_paths.contains(&Path::new(&basename).to_path_buf())
-            {
+            if let Some((kind, reason)) = skip_reason(&path, &basename, scan_hidden, skip) {
+                report.skip(&path, kind, reason);
+                continue;
+            }
+            let is_link = entry.file_type().is_ok_and(|t| t.is_symlink());
+            if let Some((kind, reason)) = symlinks.admit(&path, is_link) {
+                report.skip(&path, kind, reason);
                 continue;
             }
             if path.is_dir() {
-                let mut subdir_files = read_files_recursively(&path, scan_hidden, skip_paths)?;
+                let mut subdir_files =
+                    read_files_recursively(&path, scan_hidden, symlinks, skip, report)?;
                 files.append(&mut subdir_files);
+            } else if skip.executables
+                && fs::metadata(&path).is_ok_and(|data| skip.skips_executable(&data))
+            {
+                report.skip(&path, SkipKind::Executable, "executable");
             } else {
                 files.push(path);
             }
         }
     } else if let Ok(data) = fs::metadata(files_dir) {
-        let mode = data.permissions().mode();
-        // check execute bits set
-        // (not set && push to files)
-        if mode & 0o111 == 0 {
+        if skip.skips_executable(&data) {
+            report.skip(files_dir, SkipKind::Executable, "executable");
+        } else {
             files.push(files_dir.to_path_buf());
         }
     }
@@ -196,87 +1341,628 @@ fn read_files_recursively(
     Ok(files)
 }
 
-/// Checks if a document's index entry is expired, meaning the original file
-/// has been modified more recently than it was indexed.
+/// Returns why a discovered path is left out, if it is: hidden entries
+/// unless `scan_hidden` is set, and entries matched by the skip rules.
+pub(crate) fn skip_reason(
+    path: &Path,
+    basename: &str,
+    scan_hidden: bool,
+    skip: &SkipRules,
+) -> Option<(SkipKind, String)> {
+    if basename.starts_with(".") && !scan_hidden {
+        return Some((SkipKind::Hidden, "hidden".to_string()));
+    }
+    skip.matches(path)
+        .map(|reason| (SkipKind::Excluded, format!("excluded by {reason}")))
+}
+
+/// Selects the documents that need to be (re-)indexed: those with a supported
+/// extension, or whose policy only indexes their name, that are new or
+/// modified since they were last indexed. The other ones are recorded as
+/// skipped.
 ///
 /// # Arguments
-/// * `doc_id` - The ID of the document to check.
-/// * `doc_store` - A reference to the `DocumentStore` containing document
-///   metadata.
+/// * `docs` - The discovered documents.
+/// * `policies` - The indexing policy rules.
+/// * `doc_store` - The `DocumentStore` of the index.
+/// * `modified` - The modification times already read during discovery.
+/// * `evictions` - When documents were evicted to keep the index within its
+///   size budget; they are left out until modified.
+/// * `sniffing` - Whether documents of unknown types are kept, to be typed
+///   by their content.
+/// * `report` - The `RunReport` recording the skipped documents.
 ///
 /// # Returns
-/// `Some(true)` if the index is expired, `Some(false)` if not expired,
-/// and `None` if the document ID is not found in the `doc_store`.
-fn doc_index_is_expired(doc_id: u64, doc_store: &DocumentStore) -> bool {
-    if let Some(doc_info) = doc_store.id_to_doc_info.get(&doc_id) {
-        let now = SystemTime::now();
-        let modified_at = Path::new(&doc_info.path)
-            .metadata()
-            .unwrap()
-            .modified()
-            .unwrap();
-        let elapsed_since_modified = now.duration_since(modified_at).unwrap();
-        let elapsed_since_indexed = now.duration_since(doc_info.indexed_at).unwrap();
+/// The documents to process.
+pub(crate) fn stale_documents(
+    docs: Vec<PathBuf>,
+    policies: &[PolicyRule],
+    doc_store: &DocumentStore,
+    modified: &ModifiedTimes,
+    evictions: &HashMap<PathBuf, SystemTime>,
+    sniffing: Sniffing,
+    report: &mut RunReport,
+) -> Vec<PathBuf> {
+    let extensions_map = get_extensions_map();
+    docs.into_iter()
+        .filter(|doc| {
+            match doc.extension() {
+                _ if policy_for(policies, doc).filenames_only => {}
+                // Typed by `process_doc`
+                _ if sniffing != Sniffing::Off => {}
+                Some(ext) => {
+                    let ext = ext.to_string_lossy();
+                    if !extensions_map.contains_key(ext.as_ref()) {
+                        report.skip(
+                            doc,
+                            SkipKind::UnsupportedExtension,
+                            format!("unsupported extension .{ext}"),
+                        );
+                        return false;
+                    }
+                }
+                None => {
+                    report.skip(doc, SkipKind::NoExtension, "no extension");
+                    return false;
+                }
+            }
+            if !doc_store.needs_indexing(doc, modified) {
+                report.skip(doc, SkipKind::UpToDate, "unchanged since last run");
+                return false;
+            }
+            if !doc_store.doc_to_id.contains_key(doc) && is_evicted(evictions, doc, modified) {
+                report.skip(
+                    doc,
+                    SkipKind::Evicted,
+                    "evicted to stay within the index size budget",
+                );
+                return false;
+            }
+            true
+        })
+        .collect()
+}
+
+/// Formats a number with thousands separators, e.g. `98,000`.
+pub fn thousands(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// The content of a document read to hash it.
+struct DocumentContent {
+    /// The hex encoded SHA-256 hash of the content.
+    hash: String,
+    /// The size of the content in bytes.
+    size: u64,
+    /// The content itself, if it was read whole to be handed to the parser.
+    content: Option<Vec<u8>>,
+}
 
-        return elapsed_since_indexed > elapsed_since_modified;
+/// Computes the hex encoded SHA-256 hash of a document's content. Documents
+/// up to `STREAM_THRESHOLD_BYTES` are read whole if `keep` is set, so that
+/// their parser is given the bytes hashed rather than reading the file
+/// again; larger ones are read in chunks.
+///
+/// # Arguments
+/// * `doc` - The document.
+/// * `keep` - Whether the content is parsed in this process.
+///
+/// # Returns
+/// The `DocumentContent`, or an `io::Error` if the file cannot be read.
+fn read_content(doc: &Path, keep: bool) -> std::io::Result<DocumentContent> {
+    let mut file = fs::File::open(doc)?;
+    let (digest, size, content) = if keep && file.metadata()?.len() <= STREAM_THRESHOLD_BYTES {
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        (
+            Sha256::digest(&content),
+            content.len() as u64,
+            Some(content),
+        )
+    } else {
+        let mut hasher = Sha256::new();
+        let size = std::io::copy(&mut file, &mut hasher)?;
+        (hasher.finalize(), size, None)
     };
-    true
+    Ok(DocumentContent {
+        hash: digest.iter().map(|b| format!("{b:02x}")).collect(),
+        size,
+        content,
+    })
+}
+
+/// Folds a document into the indexed one with identical content, if there
+/// is one (see `DocumentStore::dedup`). In indexes keeping duplicates, a
+/// document folded by an earlier run is given its own document instead.
+///
+/// # Arguments
+/// * `model` - The index the document is about to be indexed in.
+/// * `uri` - The URI of the document.
+/// * `hash` - The content hash of the document.
+///
+/// # Returns
+/// `true` if the document shares the postings of another one and doesn't
+/// need to be indexed, `false` otherwise.
+fn dedup(model: &RwLock<MainIndex>, uri: &Path, hash: &str) -> bool {
+    let mut model = model.write().unwrap();
+    if model.manifest.keep_duplicates {
+        model.doc_store.unalias(uri);
+        return false;
+    }
+    model.doc_store.dedup(uri, hash)
 }
 
+/// The extensions of the formats whose parsers expect binary content.
+const BINARY_FORMATS: [&str; 7] = ["pdf", "docx", "pptx", "zip", "tar", "tgz", "gz"];
+
+/// Returns why a document is not parsed, if it is not: it is larger than
+/// `max_file_size`, or holds binary content while its parser expects text.
+///
+/// # Arguments
+/// * `doc` - The document.
+/// * `ext` - The extension of the parser of the document.
+/// * `max_file_size` - The size in bytes past which documents are skipped.
+///
+/// # Returns
+/// The kind of reason and the reason, or `None` if the document is parsed.
+pub(crate) fn content_guard(
+    doc: &Path,
+    ext: &str,
+    max_file_size: Option<u64>,
+) -> Option<(SkipKind, String)> {
+    if let Some(max) = max_file_size
+        && let Ok(metadata) = fs::metadata(doc)
+        && metadata.len() > max
+    {
+        return Some((
+            SkipKind::TooLarge,
+            format!("{} bytes, over --max-file-size {max}", metadata.len()),
+        ));
+    }
+    #[cfg(feature = "ocr")]
+    if ocr::IMAGE_EXTENSIONS.contains(&ext) {
+        return None;
+    }
+    (!BINARY_FORMATS.contains(&ext) && is_binary(doc))
+        .then(|| (SkipKind::Binary, "binary content".to_string()))
+}
+
+#[allow(clippy::too_many_arguments)]
 fn process_doc(
-    doc: &PathBuf,
+    doc: &Path,
     model: Arc<RwLock<MainIndex>>,
-    err_sender: Arc<RwLock<mpsc::Sender<Message>>>,
-    indexed_files: Arc<AtomicU64>,
-    kilobytes: Arc<AtomicU64>,
-    stop_words: &[String],
+    logger: Logger,
+    progress: &IndexProgress,
+    analyzer: &Analyzer,
+    unstemmed: &Analyzer,
+    policies: &[PolicyRule],
+    tags: BTreeSet<String>,
+    sandbox: Option<&SandboxLimits>,
+    sniffing: Sniffing,
+    max_file_size: Option<u64>,
+    report: &Mutex<RunReport>,
 ) {
-    // check if document index exists in the doc_store;
-    // if it exists, check whether the file has been modified
-    // since the last time is was indexed
-    // if yes then reindex the file
-    // if no then skip the file
+    let policy = policy_for(policies, doc);
+    // Unchanged documents were already left out by `stale_documents`
     let extensions_map = get_extensions_map();
-    let ext = match doc.extension() {
-        Some(v) => {
-            let v = v.to_string_lossy().to_string();
-            if !extensions_map.contains_key(&v) {
-                return;
-            }
-            v
-        }
-        None => return,
+    let ext = doc
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_string())
+        .unwrap_or_default();
+    // Documents whose extension does not tell their type are typed by
+    // their content
+    let ext = if policy.filenames_only || extensions_map.contains_key(&ext) {
+        ext
+    } else if let Some(sniffed) = sniff_type(doc, sniffing) {
+        sniffed.to_string()
+    } else if ext.is_empty() {
+        report
+            .lock()
+            .unwrap()
+            .skip(doc, SkipKind::NoExtension, "no extension");
+        return;
+    } else {
+        report.lock().unwrap().skip(
+            doc,
+            SkipKind::UnsupportedExtension,
+            format!("unsupported extension .{ext}"),
+        );
+        return;
+    };
+    let doc_analyzer = if policy.no_stemming {
+        unstemmed
+    } else {
+        analyzer
     };
 
+    // Parsers read whole files, so huge and binary ones are left out before
+    if !policy.filenames_only
+        && let Some((kind, reason)) = content_guard(doc, &ext, max_file_size)
     {
-        let doc_id = model.write().unwrap().doc_store.get_id(doc);
-        if !doc_index_is_expired(doc_id, &model.read().unwrap().doc_store) {
+        logger.info(format!("Skipped {}: {reason}", log_path(doc)));
+        report.lock().unwrap().skip(doc, kind, reason);
+        return;
+    }
+
+    // Index identical content only once, whatever the number of copies.
+    // Documents parsed in this process are given the bytes hashed
+    let sandboxed = sandbox.is_some() && sandbox::is_risky(&ext);
+    let keep = !policy.filenames_only && !sandboxed && doc_analyzer.stream_chunk.is_none();
+    let DocumentContent {
+        hash,
+        size: file_size,
+        content,
+    } = match remote::retry(|| read_content(doc, keep)) {
+        Ok(read) => read,
+        Err(err) => {
+            report.lock().unwrap().fail(doc, format!("read: {err}"));
+            logger.error(format!("Failed to read document {}: {err}", log_path(doc)));
             return;
         }
+    };
+    // Documents indexed by name share no terms with their content
+    if !policy.filenames_only && dedup(&model, doc, &hash) {
+        report.lock().unwrap().skip(
+            doc,
+            SkipKind::Duplicate,
+            "identical content already indexed",
+        );
+        return;
     }
 
-    if let Some(parser) = extensions_map.get(&ext) {
-        let tokens = parser(doc, Arc::clone(&err_sender), stop_words);
-        if tokens.is_empty() {
+    let parser = extensions_map.get(&ext);
+    if policy.filenames_only || parser.is_some() {
+        let mut outcome = match parser {
+            Some(parser) if !policy.filenames_only => {
+                let parse = || parse_document(*parser, doc, &ext, &logger, doc_analyzer, sandbox);
+                match content {
+                    Some(content) => parsers::with_content(doc, content, parse),
+                    None => parse(),
+                }
+            }
+            _ => {
+                let name = doc
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let mut outcome = ParseOutcome {
+                    terms: doc_analyzer.analyze(&name).into_iter().collect(),
+                    ..Default::default()
+                };
+                doc_analyzer.record_display_forms(&name, &mut outcome.display_forms);
+                outcome
+            }
+        };
+        report.lock().unwrap().parse_outcome(doc, &outcome);
+        if outcome.terms.is_empty() {
+            let error = match outcome.errors.first() {
+                Some(err) if outcome.class() == Some(ParseErrorClass::Fatal) => {
+                    format!("parse: {}", err.message)
+                }
+                _ => "no terms extracted".to_string(),
+            };
+            if !policy.filenames_only {
+                model.write().unwrap().doc_store.release_content(doc);
+            }
+            report.lock().unwrap().fail(doc, error);
             return;
         }
-        let file_size = doc.metadata().unwrap().len();
-        // do the division here to prevent u64 overflow on large directories
-        kilobytes.fetch_add(file_size / 1024, std::sync::atomic::Ordering::Relaxed);
-        indexed_files.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        outcome.terms.extend(doc_analyzer.path_terms(doc));
+        // The text is parsed again as the parsers only keep the terms;
+        // risky formats are not parsed outside of the sandbox
+        let text = (policy.store_text && !policy.filenames_only && !sandboxed)
+            .then(|| extract_text_as(doc, &ext))
+            .and_then(|text| match text {
+                Ok(text) => Some(text),
+                Err(err) => {
+                    logger.error(format!(
+                        "Failed to keep the text of {}: {err:#}",
+                        log_path(doc)
+                    ));
+                    None
+                }
+            });
 
-        if let Err(err) = model.write().unwrap().add_document(doc, &tokens) {
-            let _ = err_sender.read().unwrap().send(Message::Error(format!(
-                "Error adding document to model: {err}"
-            )));
+        progress
+            .bytes
+            .fetch_add(file_size, std::sync::atomic::Ordering::Relaxed);
+        progress
+            .indexed
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+        let model_handle = model;
+        let mut model = model_handle.write().unwrap();
+        match model.add_document_positions(doc, outcome.terms) {
+            Ok(()) => {
+                let doc_id = model.doc_store.get_id(doc);
+                model.doc_store.set_content_hash(doc_id, &hash);
+                model.doc_store.set_title(doc_id, outcome.title);
+                model.doc_store.set_language(doc_id, outcome.language);
+                model.doc_store.set_tags(doc_id, tags);
+                model.doc_store.set_text(doc_id, text);
+                model.doc_store.set_file_metadata(doc_id, doc);
+                model.add_display_forms(outcome.display_forms);
+                report.lock().unwrap().indexed.push(doc.to_path_buf());
+            }
+            Err(err) => {
+                if !policy.filenames_only {
+                    model.doc_store.release_content(doc);
+                }
+                report
+                    .lock()
+                    .unwrap()
+                    .fail(doc, format!("add to index: {err}"));
+                logger.error(format!("Error adding document to model: {err}"));
+                return;
+            }
+        }
+        drop(model);
+        if !policy.filenames_only && embedded::is_container(&ext) {
+            index_embedded(
+                doc,
+                doc,
+                &ext,
+                1,
+                &model_handle,
+                &logger,
+                doc_analyzer,
+                sandbox,
+                report,
+            );
         }
         return;
     }
 
-    let _ = err_sender
-        .read()
-        .unwrap()
-        .send(Message::Error(format!("Failed to parse document: {doc:?}")));
+    logger.error(format!("Failed to parse document: {}", log_path(doc)));
+}
+
+/// Parses a document, in a sandboxed child process if `sandbox` is set and
+/// its parser is a risky one.
+///
+/// # Arguments
+/// * `parser` - The parser of the document's extension.
+/// * `doc` - The file to parse.
+/// * `ext` - The extension of the document.
+/// * `logger` - Where the parser reports its messages.
+/// * `analyzer` - The analyzer the document is tokenized with.
+/// * `sandbox` - The limits of sandboxed parsers, if sandboxing.
+///
+/// # Returns
+/// The `ParseOutcome` of the document.
+fn parse_document(
+    parser: Parser,
+    doc: &Path,
+    ext: &str,
+    logger: &Logger,
+    analyzer: &Analyzer,
+    sandbox: Option<&SandboxLimits>,
+) -> ParseOutcome {
+    match sandbox {
+        Some(limits) if sandbox::is_risky(ext) => {
+            sandbox::parse_sandboxed(doc, ext, analyzer, limits)
+        }
+        _ => parser(doc, logger, analyzer),
+    }
+}
+
+/// Indexes the documents embedded in a container as children of it, e.g.
+/// the attachments of a mail, following nested containers up to
+/// `MAX_EMBEDDED_DEPTH`. Embedded documents of unsupported types are left
+/// out.
+///
+/// # Arguments
+/// * `uri` - The URI of the container in the `DocumentStore`.
+/// * `path` - The file holding the container.
+/// * `ext` - The extension of the container.
+/// * `depth` - How deep the container is embedded, 1 for a file.
+/// * `model` - The index receiving the embedded documents.
+/// * `logger` - Where failures are reported.
+/// * `analyzer` - The analyzer the documents are tokenized with.
+/// * `sandbox` - The limits of sandboxed parsers, if sandboxing.
+/// * `report` - The report of the indexing run.
+#[allow(clippy::too_many_arguments)]
+fn index_embedded(
+    uri: &Path,
+    path: &Path,
+    ext: &str,
+    depth: usize,
+    model: &Arc<RwLock<MainIndex>>,
+    logger: &Logger,
+    analyzer: &Analyzer,
+    sandbox: Option<&SandboxLimits>,
+    report: &Mutex<RunReport>,
+) {
+    let extensions_map = get_extensions_map();
+    let extracted = embedded::for_each_embedded(path, ext, |child| {
+        let Some(child_ext) = child.extension() else {
+            return Ok(());
+        };
+        let Some(parser) = extensions_map.get(&child_ext) else {
+            return Ok(());
+        };
+        let child_uri = embedded::child_uri(uri, &child.name);
+        let hash: String = Sha256::digest(&child.content)
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        if dedup(model, &child_uri, &hash) {
+            report.lock().unwrap().skip(
+                &child_uri,
+                SkipKind::Duplicate,
+                "identical content already indexed",
+            );
+            return Ok(());
+        }
+        let extracted = match embedded::ExtractedFile::new(&child) {
+            Ok(extracted) => extracted,
+            Err(err) => {
+                model.write().unwrap().doc_store.release_content(&child_uri);
+                report.lock().unwrap().fail(&child_uri, format!("{err:#}"));
+                return Ok(());
+            }
+        };
+
+        let mut outcome = parse_document(
+            *parser,
+            extracted.path(),
+            &child_ext,
+            logger,
+            analyzer,
+            sandbox,
+        );
+        report.lock().unwrap().parse_outcome(&child_uri, &outcome);
+        if outcome.terms.is_empty() {
+            model.write().unwrap().doc_store.release_content(&child_uri);
+            report
+                .lock()
+                .unwrap()
+                .fail(&child_uri, "no terms extracted");
+            return Ok(());
+        }
+        outcome.terms.extend(analyzer.path_terms(&child_uri));
+        {
+            let mut model = model.write().unwrap();
+            if let Err(err) = model.add_document_positions(&child_uri, outcome.terms) {
+                model.doc_store.release_content(&child_uri);
+                report
+                    .lock()
+                    .unwrap()
+                    .fail(&child_uri, format!("add to index: {err}"));
+                return Ok(());
+            }
+            let doc_id = model.doc_store.get_id(&child_uri);
+            model.doc_store.set_content_hash(doc_id, &hash);
+            model
+                .doc_store
+                .set_parent(doc_id, uri, child.content.len() as u64);
+            model.doc_store.set_title(doc_id, outcome.title);
+            model.doc_store.set_language(doc_id, outcome.language);
+            model.add_display_forms(outcome.display_forms);
+        }
+        report.lock().unwrap().indexed.push(child_uri.clone());
+
+        if depth < embedded::MAX_EMBEDDED_DEPTH && embedded::is_container(&child_ext) {
+            index_embedded(
+                &child_uri,
+                extracted.path(),
+                &child_ext,
+                depth + 1,
+                model,
+                logger,
+                analyzer,
+                sandbox,
+                report,
+            );
+        }
+        Ok(())
+    });
+    if let Err(err) = extracted {
+        logger.error(format!(
+            "Failed to extract the documents embedded in {}: {err:#}",
+            log_path(uri)
+        ));
+    }
+}
+
+/// Opens the index of `cfg` for writing and applies the index wide settings
+/// requested by `cfg` to its manifest.
+///
+/// # Arguments
+/// * `cfg` - The indexing `Config`.
+///
+/// # Returns
+/// The opened `MainIndex`, or an `anyhow::Result` error if the requested
+/// analyzer settings differ from those the indexed documents were built with.
+pub(crate) fn open_for_indexing(cfg: &Config) -> anyhow::Result<MainIndex> {
+    let mut main_index =
+        MainIndex::open(&cfg.index_path, cfg.key_file.as_deref()).context("open main index")?;
+    main_index.observer = cfg.observer.clone();
+    for message in main_index.recovery.messages(&main_index.index_dir) {
+        cfg.observer().on_warning(&message);
+    }
+    match cfg.ranker {
+        // A custom scorer lives in the process that registered it, so other
+        // processes opening the index could not rank with it
+        Some(Ranker::Custom) => {
+            anyhow::bail!("the custom ranker cannot be the default ranker of an index")
+        }
+        Some(ranker) => main_index.manifest.ranker = ranker,
+        None => {}
+    }
+    // Tokenizer options are fixed once documents were indexed with them, as
+    // queries could no longer match both the old and the new documents
+    if let Some(settings) = cfg.analyzer
+        && settings != main_index.manifest.analyzer
+    {
+        if !main_index.doc_store.doc_to_id.is_empty() {
+            return Err(anyhow::anyhow!(
+                "index {:?} was built with the analyzer settings {:?}, not {settings:?}; \
+                 rebuild it in a new directory to change them",
+                cfg.index_path,
+                main_index.manifest.analyzer
+            ));
+        }
+        main_index.manifest.analyzer = settings;
+    }
+    if let Some(stop_words) = &cfg.stop_words
+        && main_index.manifest.stop_words.as_ref() != Some(stop_words)
+    {
+        if !main_index.doc_store.doc_to_id.is_empty() {
+            return Err(anyhow::anyhow!(
+                "index {:?} was built with other stop words; rebuild it in a new directory \
+                 to change them",
+                cfg.index_path
+            ));
+        }
+        main_index.manifest.stop_words = Some(stop_words.clone());
+    }
+    if let Some(exceptions) = &cfg.stem_exceptions
+        && *exceptions != main_index.manifest.stem_exceptions
+    {
+        if !main_index.doc_store.doc_to_id.is_empty() {
+            return Err(anyhow::anyhow!(
+                "index {:?} was built with other stem exceptions; rebuild it in a new \
+                 directory to change them",
+                cfg.index_path
+            ));
+        }
+        main_index.manifest.stem_exceptions = exceptions.clone();
+    }
+    if cfg.keep_history {
+        main_index.manifest.keep_history = true;
+    }
+    if cfg.keep_duplicates {
+        main_index.manifest.keep_duplicates = true;
+    }
+    if let Some(budget) = cfg.budget {
+        main_index.manifest.budget = Some(budget);
+    }
+    if let Some(quota) = cfg.quota {
+        main_index.manifest.quota = Some(quota);
+    }
+    if let Some(expansions) = &cfg.expansions {
+        main_index.manifest.expansions = expansions.clone();
+    }
+    if let Some(noise) = &cfg.noise {
+        main_index.manifest.noise = Some(noise.clone());
+    }
+    if let Some(profile) = cfg.profile {
+        main_index.manifest.profile = profile;
+        main_index.max_segment_docs = profile.segment_docs().unwrap_or(tree::MAX_SEGMENT_DOCS);
+    }
+    if let Some(budget) = cfg.segment_budget {
+        main_index.manifest.segment_budget = Some(budget);
+        main_index.segment_budget = Some(budget);
+    }
+    Ok(main_index)
 }
 
 /// Indexes documents located at `cfg.filepath`. It reads files recursively
@@ -290,57 +1976,383 @@ fn process_doc(
 /// # Returns
 /// `Ok(())` if indexing completes successfully, otherwise an `anyhow::Result` error.
 pub fn index_documents(cfg: &Config) -> anyhow::Result<()> {
-    println!("Indexing documents...");
+    let observer = cfg.observer();
+    observer.on_notice("Indexing documents...");
+    let started = Instant::now();
     let filepath = PathBuf::from(&cfg.filepath);
     if !filepath.exists() {
-        eprintln!("Provided an invalid filepath");
+        observer.on_warning("Provided an invalid filepath");
         return Ok(());
     }
-    let docs =
-        get_docs(filepath, cfg.hidden, &cfg.skip_paths).map_err(|err| anyhow::anyhow!(err))?;
+    let mut report = RunReport::new(&filepath);
+    let cipher = cfg
+        .key_file
+        .as_deref()
+        .map(|key_file| crypto::indexing_cipher(&cfg.index_path, key_file, cfg.raw_key))
+        .transpose()?;
+    let resumed = if cfg.resume {
+        match Checkpoint::load(&cfg.index_path, cipher.as_ref())? {
+            Some(checkpoint) if checkpoint.root == filepath => Some(checkpoint),
+            Some(checkpoint) => {
+                observer.on_notice(&format!(
+                    "The interrupted run indexed {:?}, not {filepath:?}; starting over",
+                    checkpoint.root
+                ));
+                None
+            }
+            None => {
+                observer.on_notice("No interrupted run to resume; starting over");
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let (docs, modified, checkpoint) = match resumed {
+        Some(checkpoint) => {
+            observer.on_notice(&format!(
+                "Resuming: {} of {} documents were already processed",
+                thousands(checkpoint.completed.len() as u64),
+                thousands(checkpoint.docs.len() as u64)
+            ));
+            report.discovered = checkpoint.docs.len() as u64;
+            (
+                checkpoint.remaining(),
+                checkpoint.modified.clone(),
+                checkpoint,
+            )
+        }
+        None => {
+            let (docs, modified) = get_docs(
+                filepath.clone(),
+                cfg.hidden,
+                cfg.follow_symlinks,
+                &cfg.skip,
+                cfg.discovery,
+                &mut report,
+            )?;
+            report.discovered = docs.len() as u64;
+            let checkpoint = Checkpoint::new(&filepath, docs.clone(), modified.clone());
+            checkpoint.save(&cfg.index_path, cipher.as_ref())?;
+            (docs, modified, checkpoint)
+        }
+    };
+    report.discovery_ms = started.elapsed().as_millis() as u64;
 
-    let bar = ProgressBar::new_spinner();
-    bar.enable_steady_tick(Duration::from_millis(100));
+    let checkpoint = Some((checkpoint, cipher));
+    index_paths(cfg, docs, &modified, checkpoint, report, started)?;
+    Checkpoint::remove(&cfg.index_path)?;
+    Ok(())
+}
 
+/// Indexes documents like `index_documents` without blocking the async task
+/// awaiting the run: the run is driven from a thread of the offload pool.
+///
+/// # Arguments
+/// * `cfg` - The `Config` containing indexing parameters.
+///
+/// # Returns
+/// The `Offloaded` future resolving to `Ok(())` once the run is committed,
+/// otherwise to an `anyhow::Result` error.
+pub fn index_documents_async(cfg: Config) -> Offloaded<anyhow::Result<()>> {
+    offload(move || index_documents(&cfg))
+}
+
+/// Indexes the given documents, already discovered under `cfg.filepath`,
+/// and saves the run report.
+///
+/// # Arguments
+/// * `cfg` - The indexing `Config`.
+/// * `docs` - The documents to index if they are new or modified.
+/// * `modified` - The modification times read during discovery.
+/// * `checkpoint` - The checkpoint of the run, saved every
+///   `CHECKPOINT_INTERVAL` or `CHECKPOINT_BYTES`, and the cipher encrypting
+///   it, if any.
+/// * `report` - The `RunReport` of the run, holding the discovery results.
+/// * `started` - When the run started.
+///
+/// # Returns
+/// `Ok(())` if indexing completes successfully, otherwise an `anyhow::Result`
+/// error.
+pub(crate) fn index_paths(
+    cfg: &Config,
+    docs: Vec<PathBuf>,
+    modified: &ModifiedTimes,
+    checkpoint: Option<(Checkpoint, Option<Cipher>)>,
+    mut report: RunReport,
+    started: Instant,
+) -> anyhow::Result<()> {
+    let observer = cfg.observer();
     // process the documents in parallel
-    let model = Arc::new(RwLock::new(
-        MainIndex::new(&cfg.index_path).context("new main index")?,
-    ));
-    let indexed_files = Arc::new(AtomicU64::new(0));
-    let stop_words = stop_words::get(LANGUAGE::English);
-    let err_sender = Arc::clone(&cfg.sender);
-    let kilobytes = Arc::new(AtomicU64::new(0));
-
-    docs.par_iter().for_each(|doc| {
+    let mut main_index = open_for_indexing(cfg)?;
+    if let Ok(root) = fs::canonicalize(&cfg.filepath)
+        && !main_index.manifest.roots.contains(&root)
+    {
+        main_index.manifest.roots.push(root);
+    }
+    // Documents cannot be taken back once indexed, so runs on an index
+    // already over its quota are refused up front
+    if let Some(usage) = main_index.quota_usage()?
+        && usage.exceeded()
+    {
+        let message = format!(
+            "index {:?} is over its quota: {}",
+            cfg.index_path,
+            usage.describe(|bytes| format!("{} bytes", thousands(bytes)))
+        );
+        match main_index.manifest.quota.map(|quota| quota.action) {
+            Some(QuotaAction::Refuse) => {
+                return Err(anyhow::anyhow!(
+                    "{message}; raise the quota or delete documents"
+                ));
+            }
+            _ => observer.on_warning(&message),
+        }
+    }
+    let segments_before = main_index.active_segments.clone();
+    if let Some(max_small_segments) = cfg.auto_compact {
+        main_index
+            .compact_if_needed(max_small_segments)
+            .context("compact small segments")?;
+    }
+    let analyzer = main_index.analyzer();
+    let unstemmed = analyzer.without_stemming();
+    let profile = main_index.manifest.profile;
+
+    // Moved files keep their postings instead of being indexed again
+    let renamed = main_index.doc_store.detect_renames(&docs);
+    if !renamed.is_empty() {
+        observer.on_notice(&format!(
+            "{} documents moved",
+            thousands(renamed.len() as u64)
+        ));
+    }
+    report.renamed.extend(
+        renamed
+            .into_iter()
+            .map(|(from, to)| RenamedFile { from, to }),
+    );
+
+    // Files that are gone and were not moved are dropped from the index
+    let deleted = main_index.doc_store.purge_missing(Path::new(&cfg.filepath));
+    if !deleted.is_empty() {
+        observer.on_notice(&format!(
+            "{} documents removed",
+            thousands(deleted.len() as u64)
+        ));
+    }
+    report.deleted = deleted;
+
+    // Only the documents modified since the last run are processed, so the
+    // progress reflects the actual amount of work
+    let evictions = load_evictions(&main_index.index_dir, main_index.cipher());
+    let docs = stale_documents(
+        docs,
+        &cfg.policies,
+        &main_index.doc_store,
+        modified,
+        &evictions,
+        cfg.sniffing,
+        &mut report,
+    );
+    // Within a time budget, the documents left stale the longest go first:
+    // new ones, then the ones indexed the longest ago, each in `cfg.order`
+    let mut docs = docs;
+    order_documents(&mut docs, cfg.order, &cfg.priorities, modified);
+    let deadline = cfg.time_budget.map(|budget| started + budget);
+    if deadline.is_some() {
+        docs.sort_by_cached_key(|doc| main_index.doc_store.indexed_at(doc));
+    }
+    // Documents left out as unchanged need no checking on resume either
+    let checkpoint = checkpoint.map(|(mut checkpoint, cipher)| {
+        let stale: HashSet<&PathBuf> = docs.iter().collect();
+        let unchanged = checkpoint
+            .docs
+            .iter()
+            .filter(|doc| !stale.contains(doc))
+            .cloned()
+            .collect::<Vec<_>>();
+        checkpoint.completed.extend(unchanged);
+        (Mutex::new(checkpoint), cipher)
+    });
+    observer.on_start(docs.len() as u64, report.discovered);
+
+    let indexing_started = Instant::now();
+    let report = Mutex::new(report);
+    let model = Arc::new(RwLock::new(main_index));
+    let logger = cfg.logger.clone();
+    let progress = cfg.progress.clone().unwrap_or_default();
+    progress
+        .total
+        .store(docs.len() as u64, std::sync::atomic::Ordering::Relaxed);
+    // Counts a document as done, for the observer and in the progress of
+    // the run
+    let advance = |doc: &Path| {
+        observer.on_file_done(doc);
+        progress
+            .processed
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let failures = report.lock().unwrap().failures.len();
+        progress
+            .errors
+            .store(failures as u64, std::sync::atomic::Ordering::Relaxed);
+    };
+
+    let out_of_time = AtomicU64::new(0);
+    let over_quota = AtomicU64::new(0);
+    // The documents admitted so far, counting those still being indexed
+    let admissions = model
+        .read()
+        .unwrap()
+        .manifest
+        .quota
+        .filter(|quota| quota.action == QuotaAction::Refuse)
+        .and_then(|quota| quota.max_documents)
+        .map(|max| (max, AtomicU64::new(model.read().unwrap().doc_count())));
+    let process = |doc: &PathBuf| {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            out_of_time.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            report.lock().unwrap().skip(
+                doc,
+                SkipKind::TimeBudget,
+                "time budget of the run expired",
+            );
+            advance(doc);
+            return;
+        }
+        // Documents already indexed are updated whatever the quota
+        if let Some((max, admitted)) = &admissions
+            && !model.read().unwrap().doc_store.doc_to_id.contains_key(doc)
+            && admitted.fetch_add(1, std::sync::atomic::Ordering::SeqCst) >= *max
+        {
+            over_quota.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            report.lock().unwrap().skip(
+                doc,
+                SkipKind::Quota,
+                "document quota of the index reached",
+            );
+            advance(doc);
+            return;
+        }
+        observer.on_file_start(doc);
         process_doc(
             doc,
             Arc::clone(&model),
-            Arc::clone(&err_sender),
-            Arc::clone(&indexed_files),
-            Arc::clone(&kilobytes),
-            &stop_words,
+            logger.clone(),
+            &progress,
+            &analyzer,
+            &unstemmed,
+            &cfg.policies,
+            tags_for(&cfg.tags, &cfg.tag_rules, doc),
+            cfg.sandbox.as_ref(),
+            cfg.sniffing,
+            cfg.max_file_size,
+            &report,
         );
-    });
-
-    bar.finish();
-    model.write().unwrap().commit().context("commit model")?;
-    println!("Completed Indexing documents...");
-    let indexed_files = indexed_files.load(std::sync::atomic::Ordering::SeqCst);
-    println!(
-        "Indexed {} file{}",
-        indexed_files,
-        if indexed_files == 1 { "" } else { "s" }
-    );
+        let due = checkpoint.as_ref().is_some_and(|(checkpoint, _)| {
+            let bytes = progress.bytes.load(std::sync::atomic::Ordering::SeqCst);
+            checkpoint.lock().unwrap().processed(doc, bytes)
+        });
+        if due
+            && let Some((checkpoint, cipher)) = &checkpoint
+            && let Err(err) = save_checkpoint(&model, checkpoint, cipher.as_ref())
+        {
+            logger.error(format!("Failed to save checkpoint: {err:#}"));
+        }
+        advance(doc);
+        if cfg.throttle {
+            std::thread::sleep(throttle::THROTTLE_PAUSE);
+        }
+    };
+    // A parallel slice iterator splits the documents between the threads up
+    // front, which would index the end of the list within the first seconds
+    let process_all = || {
+        if deadline.is_some() || cfg.order != IndexOrder::Walk {
+            docs.iter().par_bridge().for_each(process);
+        } else {
+            docs.par_iter().for_each(process);
+        }
+    };
+    if cfg.throttle {
+        if let Err(err) = throttle::lower_priority() {
+            logger.error(format!("Failed to lower process priority: {err:#}"));
+        }
+        throttle::thread_pool()?.install(process_all);
+    } else if let Some(pool) = profile.thread_pool()? {
+        pool.install(process_all);
+    } else {
+        process_all();
+    }
 
-    let kbs = kilobytes.load(std::sync::atomic::Ordering::SeqCst);
-    let (mbs, kbs) = ((kbs / 1024), (kbs % 1024));
-    println!("Total files size: {mbs} Mbs {kbs} Kbs");
+    let mut report = report.into_inner().unwrap();
+    report.indexing_ms = indexing_started.elapsed().as_millis() as u64;
+    {
+        let mut model = model.write().unwrap();
+        model.commit().context("commit model")?;
+        if let Some(budget) = model.manifest.budget {
+            report.evicted = model
+                .enforce_budget(&budget)
+                .context("enforce size budget")?;
+            if !report.evicted.is_empty() {
+                model.commit().context("commit evictions")?;
+            }
+        }
 
-    // Close the message handler
-    let _ = Arc::clone(&cfg.sender).read().unwrap().send(Message::Break);
+        report.segments_created = model
+            .active_segments
+            .iter()
+            .filter(|id| !segments_before.contains(id))
+            .copied()
+            .collect();
+        report.segments_created.sort_unstable();
+        report.total_ms = started.elapsed().as_millis() as u64;
+        report
+            .save(&model.index_dir, model.cipher())
+            .context("save run report")?;
+        if let Some(usage) = model.quota_usage()?
+            && usage.exceeded()
+        {
+            observer.on_warning(&format!(
+                "index {:?} is now over its quota: {}",
+                cfg.index_path,
+                usage.describe(|bytes| format!("{} bytes", thousands(bytes)))
+            ));
+        }
+        observer.on_finish(&RunStats {
+            indexed: progress.indexed.load(std::sync::atomic::Ordering::SeqCst),
+            bytes: progress.bytes.load(std::sync::atomic::Ordering::SeqCst),
+            out_of_time: out_of_time.into_inner(),
+            over_quota: over_quota.into_inner(),
+            evicted: report.evicted.len() as u64,
+            elapsed: started.elapsed(),
+        });
+    }
     Ok(())
 }
 
+/// Commits the index and appends the documents processed since the last
+/// checkpoint to the journal of the run, marking them as completed.
+///
+/// # Arguments
+/// * `model` - The index being built.
+/// * `checkpoint` - The checkpoint of the run.
+/// * `cipher` - The cipher encrypting the checkpoint, if the index is
+///   encrypted.
+///
+/// # Returns
+/// `Ok(())` once the checkpoint is saved, otherwise an `anyhow::Result`
+/// error.
+fn save_checkpoint(
+    model: &RwLock<MainIndex>,
+    checkpoint: &Mutex<Checkpoint>,
+    cipher: Option<&Cipher>,
+) -> anyhow::Result<()> {
+    let mut model = model.write().unwrap();
+    let mut checkpoint = checkpoint.lock().unwrap();
+    model.commit().context("commit checkpoint")?;
+    checkpoint.append_pending(&model.index_dir, cipher)
+}
+
 /// Handles messages received from the indexing process, directing them to the
 /// specified error handler.
 /// Messages can be errors, informational, or debug messages.
@@ -362,15 +2374,15 @@ pub fn handle_messages(
 
         let message = match message {
             Message::Break => return Ok(()),
-            Message::Error(err) => format!("{now} INFO: {err}"),
+            Message::Error(err) => format!("{now} ERROR: {err}"),
             Message::Info(info) => format!("{now} INFO: {info}"),
-            Message::Debug(deb) => format!("{now} INFO: {deb}"),
+            Message::Debug(deb) => format!("{now} DEBUG: {deb}"),
         };
 
         match error_handler {
             ErrorHandler::Stderr => {
                 let mut stderr = stderr().lock();
-                let _ = stderr.write_all(message.to_string().as_bytes());
+                let _ = writeln!(stderr, "{message}");
             }
             ErrorHandler::File(ref f) => {
                 let mut file = fs::OpenOptions::new()
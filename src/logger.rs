@@ -0,0 +1,65 @@
+use crate::{ErrorHandler, Message, handle_messages};
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+/// A cloneable handle on the log, through which indexing runs, parsers and
+/// the server send their messages, so that they are all written to the same
+/// place by one `handle_messages` thread. Messages sent once that thread
+/// stopped are dropped.
+#[derive(Clone)]
+pub struct Logger {
+    sender: Sender<Message>,
+}
+
+impl Logger {
+    /// Creates a logger along with the receiving end of its messages, to
+    /// be read by `handle_messages`.
+    pub fn channel() -> (Self, Receiver<Message>) {
+        let (sender, receiver) = mpsc::channel();
+        (Self { sender }, receiver)
+    }
+
+    /// Creates a logger whose messages are written to `handler` by a
+    /// background thread, until `stop` is called.
+    ///
+    /// # Arguments
+    /// * `handler` - Where the messages are written.
+    ///
+    /// # Returns
+    /// The logger and the thread writing its messages, to be joined after
+    /// `stop` so that every message is written before exiting.
+    pub fn spawn(handler: ErrorHandler) -> (Self, JoinHandle<()>) {
+        let (logger, receiver) = Self::channel();
+        let writer = thread::spawn(move || {
+            let _ = handle_messages(&receiver, handler);
+        });
+        (logger, writer)
+    }
+
+    /// Sends a message to the log.
+    pub fn send(&self, message: Message) {
+        let _ = self.sender.send(message);
+    }
+
+    /// Logs an error.
+    pub fn error(&self, message: impl Into<String>) {
+        self.send(Message::Error(message.into()));
+    }
+
+    /// Logs an informational message.
+    pub fn info(&self, message: impl Into<String>) {
+        self.send(Message::Info(message.into()));
+    }
+
+    /// Logs a debug message.
+    pub fn debug(&self, message: impl Into<String>) {
+        self.send(Message::Debug(message.into()));
+    }
+
+    /// Tells the thread writing the messages to stop once it has written
+    /// those sent before.
+    pub fn stop(&self) {
+        self.send(Message::Break);
+    }
+}
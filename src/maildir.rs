@@ -0,0 +1,147 @@
+use anyhow::Context;
+use mailparse::MailHeaderMap;
+
+use crate::Config;
+use crate::parsers::{ParseOutcome, email_text};
+use crate::report::{RunReport, SkipKind};
+use crate::walker::ModifiedTimes;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The subdirectories of a Maildir folder holding delivered messages.
+const MESSAGE_DIRS: [&str; 2] = ["cur", "new"];
+
+/// Returns the virtual path under which a message is indexed: `mid:` followed
+/// by its Message-ID, so that the flag changes renaming the file in `cur/` do
+/// not make it a new document. Messages without a Message-ID are named after
+/// their file, without the flags suffix.
+///
+/// # Arguments
+/// * `raw` - The raw message.
+/// * `path` - The path of the message file.
+pub fn message_uri(raw: &[u8], path: &Path) -> PathBuf {
+    let message_id = mailparse::parse_headers(raw)
+        .ok()
+        .and_then(|(headers, _)| headers.get_first_value("Message-ID"));
+    match message_id {
+        Some(id) if !id.trim().is_empty() => {
+            PathBuf::from(format!("mid:{}", id.trim().trim_matches(['<', '>'])))
+        }
+        _ => {
+            let path = path.to_string_lossy();
+            let unique = path
+                .split_once(":2,")
+                .map_or(path.as_ref(), |(name, _)| name);
+            PathBuf::from(format!("maildir:{unique}"))
+        }
+    }
+}
+
+/// Finds the message files of a Maildir, including the folders nested in it
+/// (e.g. the `.Sent` folder of a Maildir++ tree).
+///
+/// # Arguments
+/// * `dir` - The Maildir root.
+///
+/// # Returns
+/// The paths of the message files in every `cur/` and `new/` directory.
+pub fn find_messages(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut messages = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir).with_context(|| format!("read dir {dir:?}"))? {
+            let entry = entry.context("read dir entry")?;
+            if !entry.file_type().is_ok_and(|t| t.is_dir()) {
+                continue;
+            }
+            let path = entry.path();
+            let name = entry.file_name();
+            if MESSAGE_DIRS.iter().any(|d| name == *d) {
+                for message in fs::read_dir(&path).with_context(|| format!("read dir {path:?}"))? {
+                    let message = message.context("read dir entry")?;
+                    if message.file_type().is_ok_and(|t| t.is_file()) {
+                        messages.push(message.path());
+                    }
+                }
+            } else if name != "tmp" {
+                dirs.push(path);
+            }
+        }
+    }
+    messages.sort_unstable();
+    Ok(messages)
+}
+
+/// Indexes the messages of a Maildir as virtual documents named after
+/// `message_uri`. Messages already in the index are skipped, so running it
+/// again only indexes the mail that arrived since.
+///
+/// # Arguments
+/// * `dir` - The Maildir root.
+/// * `cfg` - The indexing `Config`; its index path, key file and index wide
+///   settings are used.
+///
+/// # Returns
+/// The number of newly indexed messages, or an `anyhow::Result` error.
+pub fn index_maildir(dir: &Path, cfg: &Config) -> anyhow::Result<u64> {
+    let messages = find_messages(dir)?;
+    let mut report = RunReport::new(dir);
+    report.discovered = messages.len() as u64;
+
+    let mut main_index = crate::open_for_indexing(cfg)?;
+    let analyzer = main_index.analyzer();
+    let mut indexed = 0;
+    for path in &messages {
+        let raw = match fs::read(path) {
+            Ok(raw) => raw,
+            Err(err) => {
+                report.fail(path, format!("read: {err}"));
+                continue;
+            }
+        };
+        let uri = message_uri(&raw, path);
+        if !main_index
+            .doc_store
+            .needs_indexing(&uri, &ModifiedTimes::new())
+        {
+            report.skip(path, SkipKind::UpToDate, "already indexed");
+            continue;
+        }
+
+        let mut outcome = ParseOutcome::default();
+        match email_text(&raw, &mut outcome) {
+            Ok(text) => {
+                outcome.language = analyzer.detect_language(&text);
+                analyzer.record_display_forms(&text, &mut outcome.display_forms);
+                let terms = match outcome.language {
+                    Some(language) => analyzer.in_language(language).analyze(&text),
+                    None => analyzer.analyze(&text),
+                };
+                outcome.terms = terms.into_iter().collect();
+            }
+            Err(err) => outcome = ParseOutcome::fatal(format!("{err:#}")),
+        }
+        report.parse_outcome(path, &outcome);
+        if outcome.terms.is_empty() {
+            report.fail(path, "no terms extracted");
+            continue;
+        }
+        match main_index.add_document_positions(&uri, outcome.terms) {
+            Ok(()) => {
+                let doc_id = main_index.doc_store.get_id(&uri);
+                main_index.doc_store.set_language(doc_id, outcome.language);
+                main_index.add_display_forms(outcome.display_forms);
+                report.indexed.push(uri);
+                indexed += 1;
+            }
+            Err(err) => report.fail(path, format!("add to index: {err}")),
+        }
+    }
+
+    main_index.commit().context("commit model")?;
+    report
+        .save(&main_index.index_dir, main_index.cipher())
+        .context("save run report")?;
+    Ok(indexed)
+}
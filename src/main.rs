@@ -1,12 +1,67 @@
 use anyhow::{Context, anyhow};
-use indexer::{Config, ErrorHandler, Message, handle_messages, index_documents, search_term};
+use indexer::settings::{IndexRoute, Settings};
+use indexer::skip::SkipRules;
+use indexer::{
+    Config, ErrorHandler, Message, SearchOptions, compact_segments, count_term, debug_query,
+    delete_documents, handle_messages, hit_snippets, index_documents, index_stats, list_terms,
+    move_documents, search_lines, search_term, thousands, verify_index,
+};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, RwLock, mpsc};
+use std::sync::{Arc, OnceLock, mpsc};
+use std::time::{Duration, SystemTime};
 use std::{fs, thread};
 
-use clap::Parser;
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{Parser, ValueEnum};
+use indicatif::{ProgressBar, ProgressStyle};
 
-use indexer::server::run_server;
+use indexer::analyzer::{AnalyzerSettings, Language, Normalization};
+use indexer::archive::{archive_index, import_archive, is_index_archive};
+use indexer::backup::{BackupTarget, backup_index, restore_index};
+use indexer::bench::{BenchReport, SyntheticCorpus, run_bench};
+use indexer::boost::{RecencyBoost, ScoreBoost};
+use indexer::browser::{FetchPolicy, index_browser_history};
+use indexer::budget::{EvictionPolicy, SizeBudget, parse_duration, parse_size};
+use indexer::cache::{DEFAULT_POSTINGS_CACHE_BYTES, DEFAULT_RESULTS_CACHE_ENTRIES, PostingsCache};
+use indexer::clipboard::{capture_clipboard, watch_clipboard};
+use indexer::diff::diff_indexes;
+use indexer::dry_run::{DryRun, plan_index};
+use indexer::expansion::load_expansions;
+use indexer::export::{ExportFormat, export_index, import_tantivy};
+use indexer::federation::{DEFAULT_REMOTE_RESULTS, FederatedSearcher};
+use indexer::fuzzy::MAX_FUZZY_DISTANCE;
+use indexer::git_log::index_git_log;
+use indexer::inspect::inspect_index;
+use indexer::logger::Logger;
+use indexer::maildir::index_maildir;
+use indexer::manifest::Manifest;
+use indexer::metadata_filter::{MetadataFilter, parse_timestamp};
+use indexer::noise::{DEFAULT_NOISE_MAX_LEN, NoiseFilter, NoiseHeuristic};
+use indexer::observer::{IndexingObserver, RunStats};
+use indexer::output::{OutputFormat, SearchOutput, format_results, write_results};
+use indexer::privacy::{Redaction, set_redaction};
+use indexer::profile::Profile;
+use indexer::publish::publish_index;
+use indexer::quota::{Quota, QuotaAction};
+use indexer::report::{RunReport, SkipKind, list_reports, skipped_files};
+use indexer::sample::{SampleReport, sample_corpus};
+use indexer::sandbox::{SandboxLimits, parse_worker};
+use indexer::scoring::{Bm25, FieldWeights, Ranker, SortOrder};
+use indexer::server::{
+    AUTH_TOKEN_VAR, DEFAULT_BIND_ADDRESS, DEFAULT_PINNED_TERMS, ServedIndex, TlsFiles,
+    default_workers, is_loopback, run_server,
+};
+use indexer::service::{ServiceOptions, run_service};
+use indexer::sniff::Sniffing;
+use indexer::stem_exceptions::load_stem_exceptions;
+use indexer::stopwords::load_stop_words;
+use indexer::store::IndexStore;
+use indexer::tags::parse_tag;
+use indexer::temp::{TempIndex, is_temp_index};
+use indexer::template::{Template, TemplateHit};
+use indexer::update::{UpdateStatus, self_update};
+use indexer::walker::{Discovery, IndexOrder};
 
 /// Represents the command-line arguments for the Indexer application.
 #[derive(Parser, Debug)]
@@ -32,10 +87,41 @@ struct Args {
     /// Display logs in the stdout
     #[arg(short = 's', long = "stdout", help = "Display logs to the stdout")]
     stdout: bool,
+
+    /// Path to the configuration file, defaults to `~/.indexer/config`.
+    #[arg(long = "config", help = "Path to the configuration file")]
+    config_file: Option<PathBuf>,
+
+    /// Key file encrypting the index at rest. New indexes built with a key
+    /// file are encrypted, and encrypted indexes can only be opened with it.
+    #[arg(long = "key-file", help = "Key file of an encrypted index")]
+    key_file: Option<PathBuf>,
+
+    /// The named index to use instead of the default one, kept in
+    /// `~/.indexer/<name>` and created by indexing into it. `--index` takes
+    /// precedence.
+    #[arg(
+        short = 'n',
+        long = "name",
+        global = true,
+        help = "Use the named index ~/.indexer/<NAME>"
+    )]
+    name: Option<String>,
+
+    /// Hash or truncate document paths in logs, for deployments where the
+    /// log file must not reveal which documents exist.
+    #[arg(
+        long = "redact-logs",
+        value_enum,
+        help = "Hash or truncate document paths in logs"
+    )]
+    redact_logs: Option<Redaction>,
 }
 
 /// Defines the available subcommands for the Indexer application.
 #[derive(Parser, Debug)]
+// Parsed once at startup, so the size of the largest variant doesn't matter
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     /// Build an index for a directory.
     Index {
@@ -52,30 +138,624 @@ enum Commands {
             help = "Index hidden files and directories"
         )]
         hidden: bool,
+        /// Follow symbolic links, skipping those leading to a file or
+        /// directory already reached, e.g. in a cycle.
+        #[clap(long = "follow-symlinks", help = "Follow symbolic links")]
+        follow_symlinks: bool,
         /// Skip paths with specified basename.
         /// To skip `target` directories:
         /// `indexer index --path . --skip-paths target`
+        /// Entries with several components are matched as exact paths, and
+        /// entries with `*` or `?` as glob patterns, e.g. `**/*.min.js`.
         #[clap(
             short = 's',
             long = "skip-paths",
+            num_args = 1..,
             help = "Skip specific entries: directories and files"
         )]
         skip_paths: Option<Vec<PathBuf>>,
+        /// Skip files and directories with this base name, wherever they are.
+        #[clap(long = "skip-name", help = "Skip entries with this base name")]
+        skip_name: Vec<String>,
+        /// Skip this exact file or directory.
+        #[clap(long = "skip-path", help = "Skip this exact file or directory")]
+        skip_path: Vec<PathBuf>,
+        /// Skip entries matching a glob pattern, e.g. `**/generated/**`.
+        #[clap(long = "skip-glob", help = "Skip entries matching a glob pattern")]
+        skip_glob: Vec<String>,
+        /// Only index the files matching one of these glob patterns or
+        /// extensions, e.g. `--include rs,md --include 'docs/**'`.
+        #[clap(
+            long = "include",
+            value_delimiter = ',',
+            help = "Only index files matching these globs or extensions"
+        )]
+        include: Vec<String>,
+        /// Skip the files with an execute bit set. Off by default, as
+        /// scripts are worth indexing and compiled programs are left out by
+        /// their content anyway.
+        #[clap(long = "skip-executables", help = "Skip files with an execute bit set")]
+        skip_executables: bool,
+        /// Default ranking algorithm persisted in the index manifest.
+        #[clap(
+            short = 'r',
+            long = "ranker",
+            value_parser = ranker_parser(),
+            help = "Default ranking algorithm of the index"
+        )]
+        ranker: Option<Ranker>,
+        /// Skip the automatic merge of small segments.
+        #[clap(long = "no-compact", help = "Skip automatic segment compaction")]
+        no_compact: bool,
+        /// Bring text to this Unicode normalization form before tokenizing
+        /// it.
+        #[clap(
+            long = "normalization",
+            value_enum,
+            help = "Unicode normalization form of the text (default: nfc)"
+        )]
+        normalization: Option<Normalization>,
+        /// Match words regardless of their accents.
+        #[clap(long = "fold-accents", help = "Make the index accent-insensitive")]
+        fold_accents: bool,
+        /// Remember the original casing of words for display.
+        #[clap(
+            long = "preserve-case",
+            help = "Keep the original casing of words for display"
+        )]
+        preserve_case: bool,
+        /// Language words are stemmed and stop words filtered in.
+        #[clap(
+            long = "language",
+            value_enum,
+            help = "Language of the stemmer and stop words"
+        )]
+        language: Option<Language>,
+        /// Detect the language of every document and stem it and filter its
+        /// stop words in that language, falling back to `--language`.
+        #[clap(
+            long = "detect-language",
+            help = "Stem each document in its detected language"
+        )]
+        detect_language: bool,
+        /// Index words as they are, without reducing them to their stem.
+        #[clap(long = "no-stemming", help = "Disable stemming")]
+        no_stemming: bool,
+        /// Keep stop words in the index.
+        #[clap(long = "no-stop-words", help = "Keep stop words in the index")]
+        no_stop_words: bool,
+        /// Also index the character n-grams of this length of every word.
+        #[clap(
+            long = "ngrams",
+            help = "Index character n-grams of this length for partial matches"
+        )]
+        ngrams: Option<usize>,
+        /// Index punctuation characters as tokens of their own, as indexes
+        /// built before they were dropped did.
+        #[clap(
+            long = "keep-punctuation",
+            help = "Index punctuation characters as tokens"
+        )]
+        keep_punctuation: bool,
+        /// Leave out the words and numbers shorter than this many
+        /// characters.
+        #[clap(
+            long = "min-token-len",
+            help = "Drop words and numbers shorter than this many characters"
+        )]
+        min_token_len: Option<usize>,
+        /// Collapse timestamps, UUIDs and long hex strings into `<ts>`,
+        /// `<uuid>` and `<hex>` placeholder tokens, for log corpora.
+        #[clap(
+            long = "placeholders",
+            help = "Index timestamps, UUIDs and hex strings as placeholders"
+        )]
+        placeholders: bool,
+        /// Expand abbreviations listed in this file, one `k8s = kubernetes`
+        /// per line, in documents and queries alike.
+        #[clap(
+            long = "expansions",
+            help = "File of abbreviation expansions, e.g. `k8s = kubernetes`"
+        )]
+        expansions: Option<PathBuf>,
+        /// Stem the words listed in this file as it says instead of with the
+        /// stemmer, one `word` kept as is or `word = stem` per line.
+        #[clap(
+            long = "stem-exceptions",
+            conflicts_with = "no_stemming",
+            help = "File of stemming exceptions, e.g. `news` or `mice = mouse`"
+        )]
+        stem_exceptions: Option<PathBuf>,
+        /// Filter the words listed in this file, one per line, instead of
+        /// the stop words of the language.
+        #[clap(
+            long = "stop-words",
+            conflicts_with = "no_stop_words",
+            help = "File of stop words replacing those of the language"
+        )]
+        stop_words: Option<PathBuf>,
+        /// Tag every indexed document, e.g. `--tag project=alpha`, for
+        /// `tag:` filters. Repeatable; `tag` directives of the configuration
+        /// file tag the documents of some directories only.
+        #[clap(
+            long = "tag",
+            value_parser = parse_tag,
+            help = "Tag the indexed documents, e.g. project=alpha"
+        )]
+        tags: Vec<String>,
+        /// Drop the terms found in a single document that look like noise
+        /// when segments are merged: `long` terms, `hex` strings or `base64`
+        /// blobs. Stored in the manifest for later merges.
+        #[clap(
+            long = "prune-noise",
+            value_enum,
+            value_delimiter = ',',
+            help = "Prune rare noise terms at merge time (long,hex,base64)"
+        )]
+        prune_noise: Vec<NoiseHeuristic>,
+        /// The length beyond which a term is noise for `--prune-noise long`.
+        #[clap(
+            long = "noise-max-len",
+            requires = "prune_noise",
+            help = "Length beyond which terms are noise (default 40)"
+        )]
+        noise_max_len: Option<usize>,
+        /// Stop after this long, e.g. `5m` or `2h`, committing what was
+        /// indexed; the documents left stale the longest are indexed first,
+        /// so that repeated runs keep a large corpus roughly fresh.
+        #[clap(
+            long = "budget",
+            value_parser = parse_duration,
+            help = "Time budget of the run (e.g. 30s, 5m, 2h)"
+        )]
+        time_budget: Option<Duration>,
+        /// The order in which documents are indexed: `newest` modified first,
+        /// `smallest` first, `priority` to follow the `priority` directives
+        /// of the configuration file, or `walk` for the order of discovery.
+        #[clap(
+            long = "order",
+            value_enum,
+            default_value = "newest",
+            help = "Order in which documents are indexed"
+        )]
+        order: IndexOrder,
+        /// How documents with no extension, or an unknown one, are typed:
+        /// `all` tells PDF, Office, RTF, markup and mail files from their
+        /// content and indexes other text files as code or plain text,
+        /// `text` only indexes text files, and `off` skips them.
+        #[clap(
+            long = "sniff",
+            value_enum,
+            default_value = "all",
+            help = "Type documents without a known extension by their content"
+        )]
+        sniffing: Sniffing,
+        /// Skip documents larger than this, e.g. `100M`, rather than read
+        /// them whole into memory.
+        #[clap(
+            long = "max-file-size",
+            value_parser = parse_size,
+            help = "Skip documents larger than this (e.g. 100M)"
+        )]
+        max_file_size: Option<u64>,
+        /// Index in the background: low CPU and IO priority, few threads and
+        /// a pause between documents.
+        #[clap(
+            long = "throttle",
+            help = "Index with low priority to stay out of the way"
+        )]
+        throttle: bool,
+        /// How to discover documents: `builtin`, `ignore` to honour
+        /// `.gitignore` and `.ignore` files with a parallel walk, or `remote`
+        /// for network mounts.
+        #[clap(
+            long = "discovery",
+            value_enum,
+            default_value = "builtin",
+            help = "Document discovery backend"
+        )]
+        discovery: Discovery,
+        /// Keep the previous versions of re-indexed documents, so that the
+        /// index can be searched as it was at an earlier time (`--as-of`).
+        #[clap(
+            long = "keep-history",
+            help = "Keep previous versions of re-indexed documents"
+        )]
+        keep_history: bool,
+        /// Index files with identical content on their own and list each of
+        /// them in results, instead of folding copies into one document.
+        #[clap(
+            long = "keep-duplicates",
+            help = "Index identical files on their own instead of folding them"
+        )]
+        keep_duplicates: bool,
+        /// Maximum size of the index, e.g. `2G`; documents are evicted after
+        /// a run leaving the index larger.
+        #[clap(
+            long = "max-size",
+            value_parser = parse_size,
+            help = "Maximum index size (e.g. 512M, 2G)"
+        )]
+        max_size: Option<u64>,
+        /// Which documents to evict first when the index outgrows `--max-size`.
+        #[clap(
+            long = "evict",
+            value_enum,
+            requires = "max_size",
+            default_value = "oldest",
+            help = "Eviction policy when the index exceeds its maximum size"
+        )]
+        evict: EvictionPolicy,
+        /// Maximum number of documents of the index. Stored in the manifest
+        /// for later runs.
+        #[clap(long = "max-docs", help = "Maximum number of documents of the index")]
+        max_docs: Option<u64>,
+        /// Maximum size of the index directory, e.g. `1G`. Unlike
+        /// `--max-size`, nothing is evicted to stay within it.
+        #[clap(
+            long = "max-disk",
+            value_parser = parse_size,
+            help = "Maximum disk usage of the index (e.g. 512M, 2G)"
+        )]
+        max_disk: Option<u64>,
+        /// What runs do once a quota is exceeded: refuse to index more, or
+        /// only warn.
+        #[clap(
+            long = "quota-action",
+            value_enum,
+            default_value = "refuse",
+            help = "Action when the index exceeds a quota (refuse, warn)"
+        )]
+        quota_action: QuotaAction,
+        /// Resource profile, e.g. `low-memory` for single board computers.
+        /// Stored in the manifest for later runs.
+        #[clap(
+            long = "profile",
+            value_enum,
+            help = "Resource profile of the index (default, low-memory)"
+        )]
+        profile: Option<Profile>,
+        /// Approximate memory an in-memory segment may hold before it is
+        /// flushed, e.g. `64M`, instead of flushing every 100 documents.
+        /// Stored in the manifest for later runs.
+        #[clap(
+            long = "segment-budget",
+            value_parser = parse_size,
+            help = "Flush segments once they hold this much memory (e.g. 64M)"
+        )]
+        segment_budget: Option<u64>,
+        /// Run the PDF and HTML parsers in child processes with memory and
+        /// CPU time limits, so that a crashing or looping parser only fails
+        /// one document.
+        #[clap(
+            long = "sandbox",
+            help = "Run risky parsers in limited child processes"
+        )]
+        sandbox: bool,
+        /// Memory limit of a sandboxed parser, e.g. `256M`.
+        #[clap(
+            long = "sandbox-memory",
+            value_parser = parse_size,
+            requires = "sandbox",
+            help = "Memory limit of sandboxed parsers (default 512M)"
+        )]
+        sandbox_memory: Option<u64>,
+        /// CPU time limit of a sandboxed parser in seconds.
+        #[clap(
+            long = "sandbox-cpu",
+            requires = "sandbox",
+            help = "CPU time limit of sandboxed parsers in seconds (default 30)"
+        )]
+        sandbox_cpu: Option<u64>,
+        /// Resume the interrupted run on the index from its checkpoint
+        /// instead of discovering and checking every document again.
+        #[clap(long = "resume", help = "Resume an interrupted indexing run")]
+        resume: bool,
+        /// Use the 32 bytes of the key file as the key of a new encrypted
+        /// index, rather than derive the key from the file with Argon2id.
+        #[clap(
+            long = "raw-key",
+            help = "The key file of a new encrypted index holds a raw 32-byte key"
+        )]
+        raw_key: bool,
+        /// Index the commits of this git repository (messages, authors and
+        /// touched paths) instead of files. Each commit becomes a document
+        /// named `git:<repository>@<sha>`.
+        #[clap(long = "git-log", help = "Index the history of a git repository")]
+        git_log: Option<PathBuf>,
+        /// Index the messages of this Maildir (its `cur/` and `new/`
+        /// directories, nested folders included) instead of files. Each
+        /// message becomes a document named `mid:<message-id>`.
+        #[clap(
+            long = "maildir",
+            conflicts_with = "git_log",
+            help = "Index the messages of a Maildir"
+        )]
+        maildir: Option<PathBuf>,
+        /// Index the pages of a Firefox `places.sqlite` or Chromium `History`
+        /// database (titles, bookmark titles and URLs) instead of files.
+        #[clap(
+            long = "browser-history",
+            conflicts_with_all = ["git_log", "maildir"],
+            help = "Index a browser history database"
+        )]
+        browser_history: Option<PathBuf>,
+        /// Also fetch and index the content of the pages of
+        /// `--browser-history`.
+        #[clap(
+            long = "fetch-pages",
+            requires = "browser_history",
+            help = "Fetch the content of history pages"
+        )]
+        fetch_pages: bool,
+        /// Only fetch the pages of this host and its subdomains with
+        /// `--fetch-pages`, e.g. `docs.rs`. Repeatable.
+        #[clap(
+            long = "fetch-host",
+            requires = "fetch_pages",
+            help = "Only fetch the pages of this host (repeatable)"
+        )]
+        fetch_hosts: Vec<String>,
+        /// List the files the run would index, with the parser of each, and
+        /// the files it would leave out and why, without indexing anything.
+        #[clap(
+            long = "dry-run",
+            conflicts_with_all = ["resume", "git_log", "maildir", "browser_history"],
+            help = "Show what would be indexed without indexing"
+        )]
+        dry_run: bool,
+        /// Print the dry run as JSON.
+        #[clap(
+            long = "json",
+            requires = "dry_run",
+            help = "Print the dry run as JSON"
+        )]
+        json: bool,
     },
     /// Query some search term using the index.
     Search {
-        /// Path to index files directory.
+        /// Path to index files directory, or `tmp:` to index `--path` into
+        /// a temporary index removed after the search.
         #[arg(short = 'i', long = "index", help = "Path to index files directory")]
         index_directory: Option<PathBuf>,
+        /// Path to index into the temporary index selected with `--index tmp:`.
+        #[arg(short = 'p', long = "path", help = "Path to index for a `tmp:` index")]
+        path: Option<PathBuf>,
         /// Query to search.
-        #[arg(short = 'q', long = "query", help = "Query to search")]
-        query: String,
-        /// Write result to file.
+        #[arg(
+            short = 'q',
+            long = "query",
+            required_unless_present = "batch",
+            help = "Query to search"
+        )]
+        query: Option<String>,
+        /// Read one query per line from stdin and print one JSON line of
+        /// results per query, keeping the index open between them.
+        #[arg(
+            long = "batch",
+            conflicts_with = "query",
+            help = "Answer queries read from stdin as JSON lines"
+        )]
+        batch: bool,
+        /// Write result to file, formatted after its extension: `.csv`,
+        /// `.jsonl`, `.md` or plain text.
         #[arg(short = 'o', long = "output", help = "Write result to file")]
         output_file: Option<PathBuf>,
+        /// Print or write the results as a table, JSON lines or Markdown
+        /// instead of the plain text lines, e.g. to pipe them into `jq`.
+        #[arg(
+            long = "format",
+            value_enum,
+            conflicts_with_all = ["batch", "remotes", "template", "snippets"],
+            help = "Format of the results (default: text, or after the --output extension)"
+        )]
+        format: Option<OutputFormat>,
         /// Number of results to return.
         #[arg(short = 'c', long = "count", help = "Number of results")]
         result_count: Option<usize>,
+        /// Ranking algorithm, overriding the index default.
+        #[arg(
+            short = 'r',
+            long = "ranker",
+            visible_alias = "ranking",
+            value_parser = ranker_parser(),
+            help = "Ranking algorithm"
+        )]
+        ranker: Option<Ranker>,
+        /// BM25 term frequency saturation, overriding the `bm25` setting.
+        #[arg(
+            long = "bm25-k1",
+            help = "BM25 term frequency saturation k1 (default 1.2)"
+        )]
+        bm25_k1: Option<f64>,
+        /// BM25 document length normalization, overriding the `bm25` setting.
+        #[arg(
+            long = "bm25-b",
+            help = "BM25 document length normalization b, 0 to 1 (default 0.75)"
+        )]
+        bm25_b: Option<f64>,
+        /// Rank recently modified documents higher: a document modified
+        /// now scores up to twice as much, a bonus halving every
+        /// `HALF_LIFE`, e.g. `30d`.
+        #[arg(
+            long = "boost-recent",
+            value_name = "HALF_LIFE",
+            value_parser = parse_duration,
+            help = "Rank recently modified documents higher (e.g. 30d)"
+        )]
+        boost_recent: Option<Duration>,
+        /// Record when the results matched, for an index evicting the least
+        /// matched documents first (`--evict least-matched`).
+        #[arg(
+            long = "record-matches",
+            help = "Record when results matched, for --evict least-matched"
+        )]
+        record_matches: bool,
+        /// Weights of the fields matched by query terms, e.g. `path^3`.
+        #[arg(
+            long = "weights",
+            value_parser = FieldWeights::parse,
+            help = "Field weights, e.g. \"body^1 title^8\" (default body^1 title^5 heading^2 path^2)"
+        )]
+        weights: Option<FieldWeights>,
+        /// Only return documents in this language (ISO 639-1 code, e.g. `de`).
+        /// A `lang:` filter in the query takes precedence.
+        #[arg(long = "lang", help = "Only return documents in this language")]
+        language: Option<String>,
+        /// Only return documents carrying this tag, e.g. `project=alpha`, or
+        /// any value of it, e.g. `project`. Repeatable; all must match.
+        #[arg(
+            long = "tag",
+            value_parser = parse_tag,
+            help = "Only return documents carrying this tag"
+        )]
+        tags: Vec<String>,
+        /// Sort the results by score, by modification time (newest first),
+        /// by path or by size (largest first), by score among equals.
+        #[arg(
+            long = "sort",
+            value_enum,
+            default_value_t,
+            help = "Order of the results"
+        )]
+        sort: SortOrder,
+        /// Search the index as it was at this time, e.g. `2026-01-31` or
+        /// `2026-01-31T12:00:00Z`. Requires an index built with
+        /// `--keep-history`.
+        #[arg(
+            long = "as-of",
+            value_parser = parse_timestamp,
+            help = "Search the index as it was at this time"
+        )]
+        as_of: Option<SystemTime>,
+        /// Only return documents modified at or after this time, e.g.
+        /// `2024-01-01`.
+        #[arg(
+            long = "after",
+            value_parser = parse_timestamp,
+            help = "Only return documents modified at or after this time"
+        )]
+        after: Option<SystemTime>,
+        /// Only return documents modified before this time.
+        #[arg(
+            long = "before",
+            value_parser = parse_timestamp,
+            help = "Only return documents modified before this time"
+        )]
+        before: Option<SystemTime>,
+        /// Only return documents with one of these extensions, e.g.
+        /// `--ext pdf,md`. Repeatable.
+        #[arg(
+            long = "ext",
+            value_delimiter = ',',
+            help = "Only return documents with these extensions"
+        )]
+        extensions: Vec<String>,
+        /// Only return documents under this directory, e.g. `~/projects`,
+        /// whether they were indexed through a relative or absolute path.
+        #[arg(long = "under", help = "Only return documents under this directory")]
+        under: Option<PathBuf>,
+        /// Search these running servers instead of a local index and merge
+        /// their results, e.g. `--remote http://host:8765`. Repeatable.
+        #[arg(
+            long = "remote",
+            conflicts_with_all = [
+                "index_directory",
+                "batch",
+                "ranker",
+                "bm25_k1",
+                "bm25_b",
+                "language",
+                "tags",
+                "sort",
+                "as_of",
+                "after",
+                "before",
+                "extensions",
+                "under",
+                "fuzzy"
+            ],
+            help = "Search a running server instead of a local index"
+        )]
+        remotes: Vec<String>,
+        /// The token a server given with `--remote` requires as
+        /// `Authorization: Bearer <token>`, e.g.
+        /// `--remote-token http://host:8765=s3cret`. Repeatable.
+        #[arg(
+            long = "remote-token",
+            requires = "remotes",
+            value_parser = parse_remote_token,
+            help = "Token to send to a remote server, as <URL>=<TOKEN> (repeatable)"
+        )]
+        remote_tokens: Vec<(String, String)>,
+        /// Also match index terms within this many edits of the query terms,
+        /// so that misspelled queries still find results.
+        #[arg(
+            long = "fuzzy",
+            value_parser = parse_fuzzy,
+            help = "Match terms within this edit distance (0 to 2)"
+        )]
+        fuzzy: Option<usize>,
+        /// Shape each result line with a template of `{rank}`, `{score}`,
+        /// `{score:.N}`, `{path}`, `{title}` and `{snippet}` fields.
+        #[arg(
+            long = "template",
+            value_parser = Template::parse,
+            conflicts_with_all = ["batch", "remotes"],
+            help = "Template of each result line, e.g. '{score:.2}\\t{path}\\t{snippet}'"
+        )]
+        template: Option<Template>,
+        /// Show the text around the first match of each result, with the
+        /// matched words in bold, e.g. `…the quick **brown** fox…`.
+        #[arg(
+            long = "snippets",
+            conflicts_with_all = ["batch", "remotes", "template"],
+            help = "Show an excerpt around the matches of each result"
+        )]
+        snippets: bool,
+        /// Print how the query is analyzed and how often each of its terms
+        /// occurs in the index before the results.
+        #[arg(
+            long = "debug-query",
+            conflicts_with_all = ["batch", "remotes"],
+            help = "Show the analyzed query and per-term statistics"
+        )]
+        debug_query: bool,
+    },
+    /// Print the number of documents matching a query, without ranking them.
+    Count {
+        /// Path to index files directory.
+        #[arg(short = 'i', long = "index", help = "Path to index files directory")]
+        index_directory: Option<PathBuf>,
+        /// Query to count the matches of.
+        #[arg(short = 'q', long = "query", help = "Query to count the matches of")]
+        query: String,
+        /// Only count documents in this language (ISO 639-1 code, e.g. `de`).
+        /// A `lang:` filter in the query takes precedence.
+        #[arg(long = "lang", help = "Only count documents in this language")]
+        language: Option<String>,
+        /// Only count documents carrying this tag. Repeatable.
+        #[arg(
+            long = "tag",
+            value_parser = parse_tag,
+            help = "Only count documents carrying this tag"
+        )]
+        tags: Vec<String>,
+        /// Count the matches in the index as it was at this time. Requires an
+        /// index built with `--keep-history`.
+        #[arg(
+            long = "as-of",
+            value_parser = parse_timestamp,
+            help = "Count the matches in the index as it was at this time"
+        )]
+        as_of: Option<SystemTime>,
+        /// Also match index terms within this many edits of the query terms.
+        #[arg(
+            long = "fuzzy",
+            value_parser = parse_fuzzy,
+            help = "Match terms within this edit distance (0 to 2)"
+        )]
+        fuzzy: Option<usize>,
     },
     /// Serve the search engine via HTTP.
     Serve {
@@ -85,24 +765,871 @@ enum Commands {
         /// Port number to listen on.
         #[arg(short = 'p', long = "port", help = "Port number")]
         port: Option<u16>,
+        /// Host name or IP address to bind to, e.g. `0.0.0.0` to be
+        /// reachable from the other machines of the network.
+        #[arg(
+            long = "bind",
+            help = "Address to bind to (default localhost, 0.0.0.0 for all interfaces)"
+        )]
+        bind: Option<String>,
+        /// Ranking algorithm, overriding the index default.
+        #[arg(
+            short = 'r',
+            long = "ranker",
+            visible_alias = "ranking",
+            value_parser = ranker_parser(),
+            help = "Ranking algorithm"
+        )]
+        ranker: Option<Ranker>,
+        /// BM25 term frequency saturation, overriding the `bm25` setting.
+        #[arg(
+            long = "bm25-k1",
+            help = "BM25 term frequency saturation k1 (default 1.2)"
+        )]
+        bm25_k1: Option<f64>,
+        /// BM25 document length normalization, overriding the `bm25` setting.
+        #[arg(
+            long = "bm25-b",
+            help = "BM25 document length normalization b, 0 to 1 (default 0.75)"
+        )]
+        bm25_b: Option<f64>,
+        /// Rank recently modified documents higher: a document modified
+        /// now scores up to twice as much, a bonus halving every
+        /// `HALF_LIFE`, e.g. `30d`.
+        #[arg(
+            long = "boost-recent",
+            value_name = "HALF_LIFE",
+            value_parser = parse_duration,
+            help = "Rank recently modified documents higher (e.g. 30d)"
+        )]
+        boost_recent: Option<Duration>,
+        /// Record when the results matched, for an index evicting the least
+        /// matched documents first (`--evict least-matched`).
+        #[arg(
+            long = "record-matches",
+            help = "Record when results matched, for --evict least-matched"
+        )]
+        record_matches: bool,
+        /// Weights of the fields matched by query terms, e.g. `path^3`.
+        #[arg(
+            long = "weights",
+            value_parser = FieldWeights::parse,
+            help = "Field weights, e.g. \"body^1 title^8\" (default body^1 title^5 heading^2 path^2)"
+        )]
+        weights: Option<FieldWeights>,
+        /// Merge the small segments of the indexes before serving them. The
+        /// server otherwise never writes to the indexes it serves.
+        #[arg(long = "compact", help = "Merge small segments before serving")]
+        compact: bool,
+        /// Also pin the postings of the most frequent terms in memory.
+        #[arg(
+            long = "preload",
+            help = "Also load the postings of the most frequent terms at startup"
+        )]
+        preload: bool,
+        /// Number of most frequent terms whose postings are kept in memory.
+        #[arg(
+            long = "pin-terms",
+            requires = "preload",
+            help = "Number of most frequent terms to pin in memory when preloading"
+        )]
+        pin_terms: Option<usize>,
+        /// Read the index from disk for every query instead of keeping it
+        /// in memory, as servers did by default before.
+        #[arg(
+            long = "no-preload",
+            conflicts_with = "preload",
+            help = "Read the index from disk for every query instead of keeping its dictionaries in memory"
+        )]
+        no_preload: bool,
+        /// Megabytes of decoded postings of recently queried terms kept in
+        /// memory between requests; 0 disables the cache.
+        #[arg(
+            long = "postings-cache",
+            help = "Megabytes of recently queried postings to cache (default 64, 0 disables)"
+        )]
+        postings_cache: Option<usize>,
+        /// Number of search results kept in memory, so that repeated
+        /// queries are answered without searching; 0 disables the cache.
+        #[arg(
+            long = "results-cache",
+            help = "Number of search results to cache (default 256, 0 disables)"
+        )]
+        results_cache: Option<usize>,
+        /// Number of requests answered at the same time.
+        #[arg(
+            long = "workers",
+            help = "Number of requests answered in parallel (default: one per CPU)"
+        )]
+        workers: Option<usize>,
+        /// Accept `POST /api/index` requests re-indexing paths within the
+        /// roots of the served indexes.
+        #[arg(
+            long = "allow-indexing",
+            help = "Allow indexing runs to be started with POST /api/index"
+        )]
+        allow_indexing: bool,
+        /// Accept `POST /api/shutdown` requests stopping the server.
+        #[arg(
+            long = "allow-shutdown",
+            help = "Allow the server to be stopped with POST /api/shutdown"
+        )]
+        allow_shutdown: bool,
+        /// Origin of the pages allowed to call the API from a browser, e.g.
+        /// `http://192.168.1.20:3000`, or `*` for any page; repeatable.
+        #[arg(
+            long = "cors-origin",
+            help = "Origin allowed to make cross-origin API requests, or * for any (repeatable)"
+        )]
+        cors_origins: Vec<String>,
+        /// Token the API requests must present as `Authorization: Bearer
+        /// <token>`, for the indexes without one of their own. Read from
+        /// `INDEXER_AUTH_TOKEN` if not given.
+        #[arg(
+            long = "auth-token",
+            help = "Token required as `Authorization: Bearer <token>` by API requests (or INDEXER_AUTH_TOKEN)"
+        )]
+        auth_token: Option<String>,
+        /// PEM certificate chain to answer HTTPS requests with, available
+        /// when built with the `tls` feature.
+        #[arg(
+            long = "tls-cert",
+            requires = "tls_key",
+            help = "PEM certificate chain to serve HTTPS with (needs --tls-key)"
+        )]
+        tls_cert: Option<PathBuf>,
+        /// PEM private key of the certificate given with `--tls-cert`.
+        #[arg(
+            long = "tls-key",
+            requires = "tls_cert",
+            help = "PEM private key of the HTTPS certificate (needs --tls-cert)"
+        )]
+        tls_key: Option<PathBuf>,
+    },
+    /// List the indexed terms and their document frequencies.
+    Terms {
+        /// Path to index files directory.
+        #[arg(short = 'i', long = "index", help = "Path to index files directory")]
+        index_directory: Option<PathBuf>,
+        /// Number of most frequent terms to list.
+        #[arg(short = 't', long = "top", help = "Number of most frequent terms")]
+        top: Option<usize>,
+        /// Only list terms starting with this prefix.
+        #[arg(short = 'p', long = "prefix", help = "Only list terms with this prefix")]
+        prefix: Option<String>,
+    },
+    /// Index a random sample of a corpus into a temporary index and project
+    /// the size and duration of indexing all of it.
+    Sample {
+        /// Path to the corpus to sample.
+        #[arg(short = 'p', long = "path", help = "Path to the corpus to sample")]
+        path: Option<PathBuf>,
+        /// Share of the files of each extension to index, in percent.
+        #[arg(
+            long = "percent",
+            default_value_t = 5.0,
+            help = "Percentage of the files to sample"
+        )]
+        percent: f64,
+        /// Seed of the random selection, to sample the same files again.
+        #[arg(long = "seed", help = "Seed of the random selection")]
+        seed: Option<u64>,
+        /// Include hidden files and directories.
+        #[arg(long = "hidden", help = "Include hidden files and directories")]
+        hidden: bool,
     },
+    /// Index a corpus into a temporary index and measure the indexing
+    /// throughput, the peak memory, the segments flushed and the latency of
+    /// sample queries.
+    Bench {
+        /// Path to the corpus to index.
+        #[arg(
+            short = 'p',
+            long = "path",
+            conflicts_with = "synthetic",
+            help = "Path to the corpus to index"
+        )]
+        path: Option<PathBuf>,
+        /// Index this many generated documents rather than a corpus.
+        #[arg(long = "synthetic", help = "Number of generated documents to index")]
+        synthetic: Option<usize>,
+        /// Number of words of each generated document.
+        #[arg(
+            long = "words",
+            default_value_t = 500,
+            requires = "synthetic",
+            help = "Number of words of each generated document"
+        )]
+        words: usize,
+        /// Seed of the generated documents, the same seed generating the
+        /// same corpus.
+        #[arg(
+            long = "seed",
+            default_value_t = 1,
+            help = "Seed of the generated documents"
+        )]
+        seed: u64,
+        /// A sample query; may be repeated. Words of the index are picked
+        /// when no query is given.
+        #[arg(short = 'q', long = "query", help = "Sample query (repeatable)")]
+        query: Vec<String>,
+        /// File of sample queries, one per line.
+        #[arg(long = "queries", help = "File of sample queries, one per line")]
+        queries: Option<PathBuf>,
+        /// Number of times each query is searched.
+        #[arg(
+            long = "rounds",
+            default_value_t = 10,
+            help = "Number of times each query is searched"
+        )]
+        rounds: usize,
+        /// Number of best results each query asks for.
+        #[arg(
+            short = 'c',
+            long = "count",
+            default_value_t = 10,
+            help = "Number of results per query"
+        )]
+        result_count: usize,
+        /// Include hidden files and directories.
+        #[arg(long = "hidden", help = "Include hidden files and directories")]
+        hidden: bool,
+        /// Print the report as JSON.
+        #[arg(long = "json", help = "Print the report as JSON")]
+        json: bool,
+    },
+    /// Check that every segment of the index can be read.
+    Verify {
+        /// Path to index files directory.
+        #[arg(short = 'i', long = "index", help = "Path to index files directory")]
+        index_directory: Option<PathBuf>,
+        /// Rebuild the broken segments from their readable postings, or drop
+        /// them.
+        #[arg(long = "repair", help = "Rebuild or drop broken segments")]
+        repair: bool,
+    },
+    /// Show what the index contains: documents, terms, segments, sizes,
+    /// the largest documents and how old the index is.
+    Stats {
+        /// Path to index files directory.
+        #[arg(short = 'i', long = "index", help = "Path to index files directory")]
+        index_directory: Option<PathBuf>,
+        /// The number of largest documents to list.
+        #[arg(
+            long = "largest",
+            default_value_t = 10,
+            help = "Number of largest documents to list"
+        )]
+        largest: usize,
+    },
+    /// Show the manifest, the segment directories and the lock status of an
+    /// index, e.g. to diagnose a broken one.
+    Inspect {
+        /// Path to index files directory.
+        #[arg(short = 'i', long = "index", help = "Path to index files directory")]
+        index_directory: Option<PathBuf>,
+        /// Print the inspection as JSON.
+        #[arg(long = "json", help = "Print the inspection as JSON")]
+        json: bool,
+    },
+    /// Merge the segments of the index to speed up searches after many
+    /// incremental runs.
+    Compact {
+        /// Path to index files directory.
+        #[arg(short = 'i', long = "index", help = "Path to index files directory")]
+        index_directory: Option<PathBuf>,
+        /// Merge every segment into one instead of only the small ones.
+        #[arg(long = "all", help = "Merge every segment, not only the small ones")]
+        all: bool,
+    },
+    /// Move indexed files, or a directory of them, to a new path without
+    /// re-indexing them. Files already moved are only updated in the index.
+    Mv {
+        /// Path to index files directory.
+        #[arg(short = 'i', long = "index", help = "Path to index files directory")]
+        index_directory: Option<PathBuf>,
+        /// The path the documents were indexed at.
+        #[arg(help = "Old path of the files or directory")]
+        from: PathBuf,
+        /// The new path of the documents.
+        #[arg(help = "New path of the files or directory")]
+        to: PathBuf,
+    },
+    /// Make an index built in its own directory the one searched through the
+    /// index directory, switching running servers to it atomically.
+    Publish {
+        /// Path to index files directory.
+        #[arg(short = 'i', long = "index", help = "Path to index files directory")]
+        index_directory: Option<PathBuf>,
+        /// The directory of the complete index to publish.
+        #[arg(help = "Directory of the rebuilt index")]
+        build: PathBuf,
+    },
+    /// Compare two snapshots of an index, listing the documents added,
+    /// removed and re-indexed between them.
+    Diff {
+        /// The directory of the older index.
+        #[arg(help = "Directory of the older index")]
+        before: PathBuf,
+        /// The directory of the newer index.
+        #[arg(help = "Directory of the newer index")]
+        after: PathBuf,
+        /// Print the differences as JSON.
+        #[arg(long = "json", help = "Print the differences as JSON")]
+        json: bool,
+    },
+    /// Remove indexed files, or a directory of them, from the index. The
+    /// files themselves are kept.
+    Delete {
+        /// Path to index files directory.
+        #[arg(short = 'i', long = "index", help = "Path to index files directory")]
+        index_directory: Option<PathBuf>,
+        /// The path the documents were indexed at.
+        #[arg(
+            short = 'p',
+            long = "path",
+            help = "Path of the files or directory to remove"
+        )]
+        path: PathBuf,
+    },
+    /// Package the index into a portable archive, or export its documents
+    /// for consumption by another search engine.
+    Export {
+        /// Path to index files directory.
+        #[arg(short = 'i', long = "index", help = "Path to index files directory")]
+        index_directory: Option<PathBuf>,
+        /// File to write the archive or the exported documents to.
+        #[arg(short = 'o', long = "output", help = "File to write the export to")]
+        output_file: PathBuf,
+        /// Format of the exported documents. Without it, the index files
+        /// are packaged into a `.tar.gz` archive for `indexer import`.
+        #[arg(
+            short = 'f',
+            long = "format",
+            value_enum,
+            help = "Export the documents for another search engine"
+        )]
+        format: Option<ExportFormat>,
+    },
+    /// Unpack an index archive made by `indexer export`, or import a
+    /// document dump produced by another search engine.
+    Import {
+        /// Path to index files directory.
+        #[arg(short = 'o', long = "output", help = "Path to index files directory")]
+        output_directory: Option<PathBuf>,
+        /// Index archive or document dump to import.
+        #[arg(
+            short = 'p',
+            long = "path",
+            help = "Index archive or document dump to import"
+        )]
+        input_file: PathBuf,
+        /// Field holding the document path.
+        #[arg(
+            long = "path-field",
+            default_value = "path",
+            help = "Field holding the document path"
+        )]
+        path_field: String,
+    },
+    /// Back up the last committed generation of the index to a directory
+    /// or an S3 bucket. Files stored by previous backups are not uploaded
+    /// again.
+    Backup {
+        /// Path to index files directory.
+        #[arg(short = 'i', long = "index", help = "Path to index files directory")]
+        index_directory: Option<PathBuf>,
+        /// Where to store the backup: a directory, or `s3://bucket/prefix`
+        /// when built with the `s3` feature.
+        #[arg(
+            long = "to",
+            value_name = "LOCATION",
+            help = "Directory or s3://bucket/prefix to back up to"
+        )]
+        target: String,
+    },
+    /// Restore a backed up index into a directory holding no index.
+    Restore {
+        /// Path to index files directory.
+        #[arg(short = 'i', long = "index", help = "Path to index files directory")]
+        index_directory: Option<PathBuf>,
+        /// Where the backup is stored.
+        #[arg(
+            long = "from",
+            value_name = "LOCATION",
+            help = "Directory or s3://bucket/prefix to restore from"
+        )]
+        source: String,
+        /// The generation to restore instead of the latest backed up.
+        #[arg(long = "generation", help = "Generation to restore")]
+        generation: Option<u64>,
+    },
+    /// Show the reports of past indexing runs.
+    Report {
+        /// Path to index files directory.
+        #[arg(short = 'i', long = "index", help = "Path to index files directory")]
+        index_directory: Option<PathBuf>,
+        /// Display the report of the latest run instead of listing them.
+        #[arg(long = "last", help = "Display the report of the latest run")]
+        last: bool,
+    },
+    /// List the files the indexing runs left out, and why.
+    Skipped {
+        /// Path to index files directory.
+        #[arg(short = 'i', long = "index", help = "Path to index files directory")]
+        index_directory: Option<PathBuf>,
+        /// Only look at the latest run instead of every kept report.
+        #[arg(long = "last-run", help = "Only list the files of the latest run")]
+        last_run: bool,
+        /// Only list the files left out for this kind of reason.
+        #[arg(long = "kind", value_enum, help = "Only list this kind of reason")]
+        kind: Option<SkipKind>,
+        /// Only list these files, or the files under these directories.
+        #[arg(help = "Files or directories to explain")]
+        paths: Vec<PathBuf>,
+    },
+    /// Capture the text on the clipboard into a dedicated index.
+    Clip {
+        /// Path to the clipboard index directory (default: `~/.indexer/clipboard`).
+        #[arg(short = 'i', long = "index", help = "Path to the clipboard index")]
+        index_directory: Option<PathBuf>,
+        /// Keep watching the clipboard and capture every text copied to it.
+        #[arg(short = 'w', long = "watch", help = "Capture every copied text")]
+        watch: bool,
+    },
+    /// Serve the index and keep the paths of the configuration file indexed,
+    /// e.g. under `systemd --user`.
+    /// List the indexes of the index store, the default one marked with `*`.
+    ListIndexes,
+    /// Delete a named index with all its files. The indexed files are kept.
+    DropIndex {
+        /// The name of the index.
+        #[arg(value_name = "NAME", help = "Name of the index to delete")]
+        index_name: String,
+    },
+    /// Make a named index the one used by commands given neither `--index`
+    /// nor `--name`, or print the current default without a name.
+    DefaultIndex {
+        /// The name of the index.
+        #[arg(
+            value_name = "NAME",
+            conflicts_with = "clear",
+            help = "Name of the index to use by default"
+        )]
+        index_name: Option<String>,
+        /// Use the unnamed index of the store by default again.
+        #[arg(long = "clear", help = "Use the unnamed index by default again")]
+        clear: bool,
+    },
+    Service {
+        /// Path to index files directory.
+        #[arg(short = 'i', long = "index", help = "Path to index files directory")]
+        index_directory: Option<PathBuf>,
+        /// Port number to listen on.
+        #[arg(short = 'p', long = "port", help = "Port number")]
+        port: Option<u16>,
+        /// Skip files and directories with this base name, wherever they are.
+        #[arg(long = "skip-name", help = "Skip entries with this base name")]
+        skip_name: Vec<String>,
+        /// Skip this exact file or directory.
+        #[arg(long = "skip-path", help = "Skip this exact file or directory")]
+        skip_path: Vec<PathBuf>,
+        /// Skip entries matching a glob pattern, e.g. `**/generated/**`.
+        #[arg(long = "skip-glob", help = "Skip entries matching a glob pattern")]
+        skip_glob: Vec<String>,
+        /// Only index the files matching one of these glob patterns or
+        /// extensions.
+        #[arg(
+            long = "include",
+            value_delimiter = ',',
+            help = "Only index files matching these globs or extensions"
+        )]
+        include: Vec<String>,
+        /// Skip the files with an execute bit set.
+        #[arg(long = "skip-executables", help = "Skip files with an execute bit set")]
+        skip_executables: bool,
+    },
+    /// Parse one document and print its terms as JSON. Used by `--sandbox`
+    /// to run parsers in a child process.
+    #[command(hide = true)]
+    ParseWorker {
+        /// The extension picking the parser.
+        #[arg(long = "ext")]
+        ext: String,
+        /// The analyzer settings of the index, as JSON.
+        #[arg(long = "analyzer")]
+        analyzer: String,
+        /// The size of the chunks text is analyzed in.
+        #[arg(long = "stream-chunk")]
+        stream_chunk: Option<usize>,
+        /// The document to parse.
+        path: PathBuf,
+    },
+    /// Update the indexer to the latest release.
+    SelfUpdate {
+        /// Only check whether an update is available.
+        #[arg(long = "check", help = "Only check for an update")]
+        check: bool,
+        /// Install into this directory instead of replacing the running binary.
+        #[arg(
+            long = "install-dir",
+            help = "Install into this directory (e.g. ~/.local/bin)"
+        )]
+        install_dir: Option<PathBuf>,
+    },
+}
+
+/// Determines and returns the default storage directory for the indexer.
+/// This will typically be `~/.indexer`. If the directory does not exist, it
+/// attempts to create it.
+///
+/// # Returns
+/// A `PathBuf` representing the storage directory.
+fn get_storage() -> PathBuf {
+    let mut index_dir = home::home_dir().unwrap_or(Path::new(".").to_path_buf());
+    index_dir.push(".indexer");
+    if !index_dir.exists() {
+        fs::create_dir(&index_dir)
+            .map_err(|err| eprintln!("Create .indexer dir: {err}"))
+            .unwrap();
+    }
+    index_dir
+}
+
+/// Indexes the documents described by `cfg`, handling the messages of the
+/// indexing process on a separate thread.
+///
+/// # Arguments
+/// * `cfg` - The indexing `Config`.
+/// * `receiver` - The receiving end of `cfg.logger`.
+///
+/// # Returns
+/// `Ok(())` if indexing completed, otherwise an `anyhow::Result` error.
+fn run_indexing(cfg: &Config, receiver: mpsc::Receiver<Message>) -> anyhow::Result<()> {
+    // Spawns a new thread to handle messages (errors/info) from the
+    // indexing process.
+    let err_handler = cfg.error_handler.clone();
+    let logs_handler = thread::spawn(move || {
+        let _ = handle_messages(&receiver, err_handler.clone());
+    });
+
+    let result = index_documents(cfg);
+    // Close the message handler incase index_documents exited early
+    cfg.logger.stop();
+    logs_handler.join().unwrap(); // Wait for compeletion
+    result
+}
+
+/// Follows indexing runs on the terminal: prints their notices and summary
+/// and shows a bar of the documents processed.
+#[derive(Default)]
+struct ProgressObserver {
+    /// The bar of the documents processed, shown once the run starts.
+    bar: OnceLock<ProgressBar>,
+}
+
+impl ProgressObserver {
+    /// Prints `message` to stdout, above the bar if it is shown.
+    fn print(&self, message: &str) {
+        match self.bar.get() {
+            Some(bar) => bar.suspend(|| println!("{message}")),
+            None => println!("{message}"),
+        }
+    }
+}
+
+impl IndexingObserver for ProgressObserver {
+    fn on_notice(&self, message: &str) {
+        self.print(message);
+    }
+
+    fn on_warning(&self, message: &str) {
+        match self.bar.get() {
+            Some(bar) => bar.suspend(|| eprintln!("Warning: {message}")),
+            None => eprintln!("Warning: {message}"),
+        }
+    }
+
+    fn on_start(&self, total: u64, discovered: u64) {
+        println!(
+            "{} of {} documents need re-indexing",
+            thousands(total),
+            thousands(discovered)
+        );
+        let bar = self.bar.get_or_init(|| ProgressBar::new(total));
+        bar.set_style(
+            ProgressStyle::with_template("{wide_bar} {pos}/{len} ({eta})")
+                .unwrap_or_else(|_| ProgressStyle::default_bar()),
+        );
+        bar.enable_steady_tick(Duration::from_millis(100));
+    }
+
+    fn on_file_done(&self, _path: &Path) {
+        if let Some(bar) = self.bar.get() {
+            bar.inc(1);
+        }
+    }
+
+    fn on_flush(&self, segment_id: u64) {
+        self.print(&format!("Flushed segment_{segment_id}"));
+    }
+
+    fn on_finish(&self, stats: &RunStats) {
+        if let Some(bar) = self.bar.get() {
+            bar.finish();
+        }
+        if stats.out_of_time > 0 {
+            println!(
+                "Time budget expired, {} documents left for the next run",
+                thousands(stats.out_of_time)
+            );
+        }
+        if stats.over_quota > 0 {
+            println!(
+                "Document quota reached, {} new documents were not indexed",
+                thousands(stats.over_quota)
+            );
+        }
+        if stats.evicted > 0 {
+            println!(
+                "Evicted {} documents to stay within the index size budget",
+                thousands(stats.evicted)
+            );
+        }
+        println!("Completed Indexing documents...");
+        println!(
+            "Indexed {} file{}",
+            stats.indexed,
+            if stats.indexed == 1 { "" } else { "s" }
+        );
+        let kbs = stats.bytes / 1024;
+        let (mbs, kbs) = ((kbs / 1024), (kbs % 1024));
+        println!("Total files size: {mbs} Mbs {kbs} Kbs");
+    }
+}
+
+/// Prints a human readable summary of an indexing run report.
+///
+/// # Arguments
+/// * `report` - The `RunReport` to print.
+fn print_report(report: &RunReport) {
+    println!("Run started {} on {:?}", report.started_at, report.source);
+    println!(
+        "Discovered {}, indexed {}, skipped {}, failed {}",
+        report.discovered,
+        report.indexed.len(),
+        report.skipped.len(),
+        report.failures.len()
+    );
+    println!("Segments created: {:?}", report.segments_created);
+    println!(
+        "Durations: discovery {} ms, indexing {} ms, total {} ms",
+        report.discovery_ms, report.indexing_ms, report.total_ms
+    );
+    if !report.failures.is_empty() {
+        println!("\nFailures:");
+        for failure in &report.failures {
+            println!("  {}: {}", failure.path.display(), failure.error);
+        }
+    }
+    if !report.parse_errors.is_empty() {
+        println!("\nParse errors:");
+        for doc in &report.parse_errors {
+            println!("  {} ({:?}):", doc.path.display(), doc.class);
+            for error in &doc.errors {
+                println!("    {}", error.message);
+            }
+            if doc.suppressed > 0 {
+                println!("    ... and {} more", doc.suppressed);
+            }
+        }
+    }
+    if !report.skipped.is_empty() {
+        println!("\nSkipped:");
+        for skipped in &report.skipped {
+            println!("  {}: {}", skipped.path.display(), skipped.reason);
+        }
+    }
+}
+
+/// Formats a number of bytes with a binary unit, e.g. `1.5 MiB`.
+fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Prints the outcome of indexing a sample of a corpus and the projected
+/// cost of indexing all of it.
+///
+/// # Arguments
+/// * `sample` - The `SampleReport` to print.
+fn print_sample(sample: &SampleReport) {
+    println!(
+        "Sampled {} of {} files: index {}, {} terms, {} tokens",
+        sample.sampled,
+        sample.files,
+        human_bytes(sample.index_bytes),
+        sample.term_count,
+        sample.total_tokens
+    );
+    println!(
+        "Projected full index: {}, ~{} terms, {:.0?} to index",
+        human_bytes(sample.projected_index_bytes()),
+        sample.projected_term_count(),
+        sample.projected_duration()
+    );
+    println!(
+        "\n{:<8} {:>8} {:>8} {:>11} {:>11} {:>10} {:>11} {:>10}",
+        "ext", "files", "sampled", "input", "index", "time", "proj. index", "proj. time"
+    );
+    for ext in &sample.extensions {
+        println!(
+            "{:<8} {:>8} {:>8} {:>11} {:>11} {:>10} {:>11} {:>10}",
+            ext.extension,
+            ext.files,
+            ext.sampled,
+            human_bytes(ext.input_bytes),
+            human_bytes(ext.index_bytes),
+            format!("{:.1?}", ext.duration),
+            human_bytes(ext.projected_index_bytes()),
+            format!("{:.1?}", ext.projected_duration()),
+        );
+    }
+}
+
+/// Prints the indexing throughput, peak memory, segment flushes and query
+/// latency measured by a benchmark.
+///
+/// # Arguments
+/// * `report` - The `BenchReport` to print.
+fn print_bench(report: &BenchReport) {
+    let micros = Duration::from_micros;
+    println!(
+        "Indexed {} documents ({}) in {:.1?}: {:.1} MB/s, {:.0} docs/s",
+        report.docs,
+        human_bytes(report.bytes),
+        Duration::from_millis(report.indexing_ms),
+        report.megabytes_per_sec(),
+        report.docs_per_sec()
+    );
+    println!("Segments flushed: {}", report.flushes);
+    match report.peak_memory {
+        Some(bytes) => println!("Peak memory: {}", human_bytes(bytes)),
+        None => println!("Peak memory: unknown"),
+    }
+    let latency = &report.latency;
+    println!(
+        "Queried {} times ({} queries, {:.1} matches on average): mean {:.2?}, p50 {:.2?}, p95 {:.2?}, p99 {:.2?}, max {:.2?}",
+        latency.searches,
+        report.queries.len(),
+        latency.mean_matches,
+        micros(latency.mean_us),
+        micros(latency.p50_us),
+        micros(latency.p95_us),
+        micros(latency.p99_us),
+        micros(latency.max_us)
+    );
+}
+
+/// Prints what an indexing run would do: the files it would index with
+/// their parser, the moved files and the files it would leave out.
+///
+/// # Arguments
+/// * `plan` - The `DryRun` to print.
+fn print_dry_run(plan: &DryRun) {
+    for file in &plan.files {
+        let sniffed = if file.sniffed { ", sniffed" } else { "" };
+        println!("index: {} ({}{sniffed})", file.path.display(), file.parser);
+    }
+    for file in &plan.moved {
+        println!("move: {} -> {}", file.from.display(), file.to.display());
+    }
+    for file in &plan.skipped {
+        let kind = file
+            .kind
+            .to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default();
+        println!("skip: {}: {kind}: {}", file.path.display(), file.reason);
+    }
+    println!(
+        "Discovered {}, would index {}, move {}, skip {}",
+        plan.discovered,
+        plan.files.len(),
+        plan.moved.len(),
+        plan.skipped.len()
+    );
+}
+
+/// Returns the boost hooks of a search ranking recent documents higher
+/// with `--boost-recent`.
+///
+/// # Arguments
+/// * `half_life` - The half-life of the bonus of recent documents, if any.
+fn recency_boost(half_life: Option<Duration>) -> Vec<Arc<dyn ScoreBoost>> {
+    half_life
+        .map(|half_life| Arc::new(RecencyBoost::new(half_life)) as Arc<dyn ScoreBoost>)
+        .into_iter()
+        .collect()
 }
 
-/// Determines and returns the default storage directory for the indexer.
-/// This will typically be `~/.indexer`. If the directory does not exist, it
-/// attempts to create it.
+/// Parses the token of a remote server given on the command line as
+/// `<URL>=<TOKEN>`.
+///
+/// # Arguments
+/// * `value` - The URL and token to parse.
 ///
 /// # Returns
-/// A `PathBuf` representing the storage directory.
-fn get_storage() -> PathBuf {
-    let mut index_dir = home::home_dir().unwrap_or(Path::new(".").to_path_buf());
-    index_dir.push(".indexer");
-    if !index_dir.exists() {
-        fs::create_dir(&index_dir)
-            .map_err(|err| eprintln!("Create .indexer dir: {err}"))
-            .unwrap();
+/// The URL and the token, or an error message if either is missing.
+fn parse_remote_token(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((remote, token)) if !remote.is_empty() && !token.is_empty() => {
+            Ok((remote.to_string(), token.to_string()))
+        }
+        _ => Err("expected <URL>=<TOKEN>".to_string()),
     }
-    index_dir
+}
+
+/// Parses the edit distance of a fuzzy search given on the command line.
+///
+/// # Arguments
+/// * `value` - The distance to parse.
+///
+/// # Returns
+/// The distance, or an error message if it is not a number up to
+/// `MAX_FUZZY_DISTANCE`.
+fn parse_fuzzy(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(distance) if distance <= MAX_FUZZY_DISTANCE => Ok(distance),
+        _ => Err(format!(
+            "expected an edit distance from 0 to {MAX_FUZZY_DISTANCE}"
+        )),
+    }
+}
+
+/// Returns the parser of the ranking algorithms accepted on the command
+/// line: every `Ranker` but `custom`, which needs a scorer registered by a
+/// library user and so cannot be used from here.
+///
+/// # Returns
+/// The parser, listing the accepted rankers in the help.
+fn ranker_parser() -> impl TypedValueParser<Value = Ranker> {
+    let rankers = Ranker::value_variants()
+        .iter()
+        .filter(|ranker| **ranker != Ranker::Custom)
+        .filter_map(ValueEnum::to_possible_value);
+    // Only the names of the listed rankers get through
+    PossibleValuesParser::new(rankers).map(|name| Ranker::from_str(&name, true).unwrap_or_default())
 }
 
 /// The main entry point of the Indexer application.
@@ -113,22 +1640,47 @@ fn get_storage() -> PathBuf {
 /// `Ok(())` if the operation was successful, otherwise an `anyhow::Result` error.
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
+    if let Some(redaction) = args.redact_logs {
+        set_redaction(redaction);
+    }
+
+    // Workers answer on stdout, so they skip the log setup below
+    if let Commands::ParseWorker {
+        ext,
+        analyzer,
+        stream_chunk,
+        path,
+    } = &args.command
+    {
+        return parse_worker(path, ext, analyzer, *stream_chunk);
+    }
 
     let error_handler = if args.stdout {
         ErrorHandler::Stderr
     } else {
-        let mut log_file = get_storage();
-        log_file.push("logs");
-        println!("Logs saved to: {log_file:?}");
-        match args.log_file {
-            Some(file) => ErrorHandler::File(file),
-            None => ErrorHandler::File(log_file.clone()),
-        }
+        let log_file = args.log_file.unwrap_or_else(|| get_storage().join("logs"));
+        // On stderr, to keep stdout to the results of the command
+        eprintln!("Logs saved to: {log_file:?}");
+        ErrorHandler::File(log_file)
+    };
+
+    let config_file = match args.config_file {
+        Some(ref f) => f.clone(),
+        None => get_storage().join("config"),
+    };
+    let settings = Settings::load(&config_file)?;
+
+    // Commands without `--index` use the index selected with `--name`,
+    // otherwise the default one
+    let store = IndexStore::new(get_storage());
+    let named = args.name.is_some();
+    let default_index = match &args.name {
+        Some(name) => store.index_path(name)?,
+        None => store.default_path()?,
     };
 
-    // Error messages channel
-    let (sender, receiver) = mpsc::channel();
-    let sender = Arc::new(RwLock::new(sender));
+    // The log of every command, written by the thread reading `receiver`
+    let (logger, receiver) = Logger::channel();
 
     match args.command {
         Commands::Index {
@@ -136,6 +1688,58 @@ fn main() -> anyhow::Result<()> {
             output_directory,
             hidden,
             skip_paths,
+            skip_name,
+            skip_path,
+            skip_glob,
+            include,
+            skip_executables,
+            ranker,
+            no_compact,
+            normalization,
+            fold_accents,
+            preserve_case,
+            language,
+            detect_language,
+            no_stemming,
+            no_stop_words,
+            ngrams,
+            keep_punctuation,
+            min_token_len,
+            placeholders,
+            expansions,
+            stem_exceptions,
+            stop_words,
+            tags,
+            prune_noise,
+            noise_max_len,
+            time_budget,
+            order,
+            sniffing,
+            max_file_size,
+            follow_symlinks,
+            throttle,
+            discovery,
+            keep_history,
+            keep_duplicates,
+            max_size,
+            evict,
+            max_docs,
+            max_disk,
+            quota_action,
+            profile,
+            segment_budget,
+            sandbox,
+            sandbox_memory,
+            sandbox_cpu,
+            resume,
+            raw_key,
+            git_log,
+            maildir,
+            browser_history,
+            fetch_pages,
+            fetch_hosts,
+            dry_run,
+            json,
         } => {
             let filepath = match path {
                 Some(p) => p,
@@ -143,85 +1747,1167 @@ fn main() -> anyhow::Result<()> {
             };
 
             let index_path = {
-                if let Some(path) = output_directory {
+                // A dry run leaves the disk untouched
+                if dry_run {
+                    output_directory.unwrap_or(default_index)
+                } else if let Some(path) = output_directory {
                     if let Err(err) = fs::create_dir_all(&path) {
                         return Err(anyhow!("ERROR: create ouput dir: {err}"));
                     }
                     path
                 } else {
-                    get_storage()
+                    fs::create_dir_all(&default_index).context("create index dir")?;
+                    default_index
                 }
             };
 
+            let mut skip = SkipRules::new(skip_path, skip_name, skip_glob)
+                .with_include(include)
+                .with_executables(skip_executables);
+            skip.add_legacy(skip_paths.unwrap_or_default());
+
             let cfg = Config {
                 filepath,
                 index_path,
                 error_handler,
-                sender,
+                logger,
                 hidden,
-                skip_paths: skip_paths.unwrap_or_default(),
+                skip,
+                ranker,
+                auto_compact: if no_compact {
+                    None
+                } else {
+                    settings.auto_compact
+                },
+                analyzer: (normalization.is_some()
+                    || fold_accents
+                    || preserve_case
+                    || language.is_some()
+                    || detect_language
+                    || no_stemming
+                    || no_stop_words
+                    || ngrams.is_some()
+                    || keep_punctuation
+                    || min_token_len.is_some()
+                    || placeholders)
+                    .then_some(AnalyzerSettings {
+                        normalization: normalization.unwrap_or_default(),
+                        fold_accents,
+                        preserve_case,
+                        language: language.unwrap_or_default(),
+                        stemming: !no_stemming,
+                        stop_words: !no_stop_words,
+                        detect_language,
+                        ngrams: ngrams.unwrap_or_default(),
+                        punctuation: keep_punctuation,
+                        min_token_len: min_token_len.unwrap_or_default(),
+                        placeholders,
+                    }),
+                key_file: args.key_file,
+                raw_key,
+                throttle,
+                discovery,
+                keep_history,
+                keep_duplicates,
+                budget: max_size.map(|max_bytes| SizeBudget {
+                    max_bytes,
+                    policy: evict,
+                }),
+                quota: (max_docs.is_some() || max_disk.is_some()).then_some(Quota {
+                    max_documents: max_docs,
+                    max_bytes: max_disk,
+                    action: quota_action,
+                }),
+                profile,
+                segment_budget,
+                sandbox: sandbox.then(|| {
+                    let defaults = SandboxLimits::default();
+                    SandboxLimits {
+                        memory_bytes: sandbox_memory.unwrap_or(defaults.memory_bytes),
+                        cpu_seconds: sandbox_cpu.unwrap_or(defaults.cpu_seconds),
+                    }
+                }),
+                resume,
+                policies: settings.policies,
+                tags,
+                tag_rules: settings.tags,
+                expansions: expansions.as_deref().map(load_expansions).transpose()?,
+                stem_exceptions: stem_exceptions
+                    .as_deref()
+                    .map(load_stem_exceptions)
+                    .transpose()?,
+                stop_words: stop_words.as_deref().map(load_stop_words).transpose()?,
+                noise: (!prune_noise.is_empty()).then(|| NoiseFilter {
+                    heuristics: prune_noise,
+                    max_len: noise_max_len.unwrap_or(DEFAULT_NOISE_MAX_LEN),
+                }),
+                time_budget,
+                order,
+                priorities: settings.priorities,
+                sniffing,
+                max_file_size,
+                follow_symlinks,
+                progress: None,
+                observer: Some(Arc::new(ProgressObserver::default())),
             };
-
-            // Spawns a new thread to handle messages (errors/info) from the
-            // indexing process.
-            let err_handler = cfg.error_handler.clone();
-            let logs_handler = thread::spawn(move || {
-                let _ = handle_messages(&receiver, err_handler.clone());
-            });
-
-            index_documents(&cfg)?;
-            // Close the message handler incase index_documents exited early
-            let _ = Arc::clone(&cfg.sender).read().unwrap().send(Message::Break);
-            logs_handler.join().unwrap(); // Wait for compeletion
+            if dry_run {
+                let plan = plan_index(&cfg)?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&plan)?);
+                } else {
+                    print_dry_run(&plan);
+                }
+            } else if let Some(repo) = git_log {
+                let indexed = index_git_log(&repo, &cfg)?;
+                println!("Indexed {indexed} new commits from {repo:?}");
+            } else if let Some(dir) = maildir {
+                let indexed = index_maildir(&dir, &cfg)?;
+                println!("Indexed {indexed} new messages from {dir:?}");
+            } else if let Some(db) = browser_history {
+                let policy = FetchPolicy { hosts: fetch_hosts };
+                let indexed = index_browser_history(&db, fetch_pages.then_some(&policy), &cfg)?;
+                println!("Indexed {indexed} new pages from {db:?}");
+            } else {
+                run_indexing(&cfg, receiver)?;
+            }
         }
         Commands::Search {
             index_directory,
+            path,
             query,
+            batch,
             output_file,
+            format,
             result_count,
+            ranker,
+            bm25_k1,
+            bm25_b,
+            boost_recent,
+            record_matches,
+            weights,
+            language,
+            tags,
+            sort,
+            as_of,
+            after,
+            before,
+            extensions,
+            under,
+            remotes,
+            remote_tokens,
+            debug_query: explain_query,
+            fuzzy,
+            template,
+            snippets,
         } => {
+            if !remotes.is_empty() {
+                let searcher = remote_tokens.into_iter().fold(
+                    FederatedSearcher::new(remotes, result_count.unwrap_or(DEFAULT_REMOTE_RESULTS)),
+                    |searcher, (remote, token)| searcher.with_token(&remote, token),
+                );
+                let mut results = searcher.search(&query.unwrap_or_default());
+                for (remote, err) in &results.failed {
+                    eprintln!("Warning: could not search {remote}: {err}");
+                }
+                for remote in &results.partial {
+                    eprintln!("Warning: results of {remote} are partial");
+                }
+                if let Some(count) = result_count {
+                    results.hits.truncate(count);
+                }
+                let lines = results
+                    .hits
+                    .iter()
+                    .map(|hit| format!("{:.4}: {} {}", hit.score, hit.remote, hit.path.display()))
+                    .collect::<Vec<String>>();
+                match output_file {
+                    Some(f) => fs::write(f, lines.join("\n"))?,
+                    None => lines.iter().for_each(|line| println!("{line}")),
+                }
+                return Ok(());
+            }
+
+            // Kept alive until the end of the search, then removed
+            let mut temp_index = None;
             let index_files = match index_directory {
+                Some(p) if is_temp_index(&p) => {
+                    let filepath = match path {
+                        Some(p) => p,
+                        None => std::env::current_dir().context("get current directory")?,
+                    };
+                    let temp = TempIndex::new()?;
+                    let cfg = Config {
+                        filepath,
+                        index_path: temp.path().to_path_buf(),
+                        error_handler,
+                        logger,
+                        hidden: false,
+                        skip: SkipRules::default(),
+                        ranker: None,
+                        auto_compact: None,
+                        analyzer: None,
+                        key_file: args.key_file.clone(),
+                        raw_key: false,
+                        throttle: false,
+                        discovery: Discovery::default(),
+                        keep_history: false,
+                        keep_duplicates: false,
+                        budget: None,
+                        quota: None,
+                        profile: None,
+                        segment_budget: None,
+                        sandbox: None,
+                        resume: false,
+                        policies: Vec::new(),
+                        tags: Vec::new(),
+                        tag_rules: Vec::new(),
+                        expansions: None,
+                        stem_exceptions: None,
+                        stop_words: None,
+                        noise: None,
+                        time_budget: None,
+                        order: IndexOrder::default(),
+                        priorities: Vec::new(),
+                        sniffing: Sniffing::default(),
+                        max_file_size: None,
+                        follow_symlinks: false,
+                        progress: None,
+                        observer: Some(Arc::new(ProgressObserver::default())),
+                    };
+                    run_indexing(&cfg, receiver)?;
+                    temp_index.insert(temp).path().to_path_buf()
+                }
                 Some(p) => p,
-                None => get_storage(),
+                None => default_index,
             };
-            let mut result = search_term(&query, &index_files)?;
+            let options = SearchOptions {
+                ranker,
+                boosts: settings.boosts,
+                score_boosts: recency_boost(boost_recent),
+                bm25: Bm25::new(
+                    bm25_k1.unwrap_or(settings.bm25.k1),
+                    bm25_b.unwrap_or(settings.bm25.b),
+                )?,
+                field_weights: weights.unwrap_or_default(),
+                key_file: args.key_file,
+                language,
+                tags,
+                metadata: MetadataFilter::new(after, before, extensions, under),
+                sort,
+                as_of,
+                fuzzy,
+                postings_cache: None,
+                limit: result_count,
+                record_matches,
+            };
+            if batch {
+                let stdin = std::io::stdin().lock();
+                return match output_file {
+                    Some(f) => search_lines(
+                        stdin,
+                        fs::File::create(f).context("create output file")?,
+                        &index_files,
+                        &options,
+                        result_count,
+                    ),
+                    None => search_lines(
+                        stdin,
+                        std::io::stdout().lock(),
+                        &index_files,
+                        &options,
+                        result_count,
+                    ),
+                };
+            }
+            let query = query.unwrap_or_default();
+            if explain_query {
+                print!("{}", debug_query(&query, &index_files, &options)?);
+            }
+            let results = search_term(&query, &index_files, &options)?;
+            if results.is_partial() {
+                eprintln!(
+                    "Warning: results are partial, broken segments {:?} were skipped; \
+                     run `indexer verify --repair`",
+                    results.skipped_segments
+                );
+            }
+            let duplicates = results.duplicates;
+            let titles = results.titles;
+            let mut hits = results.hits;
 
-            // Do nothing
-            if result.is_empty() {
+            // Do nothing, unless the empty results are written to a file or
+            // as a table
+            if hits.is_empty() && output_file.is_none() && format.is_none() {
                 return Ok(());
             }
 
             if let Some(count) = result_count
-                && result.len() > count
+                && hits.len() > count
             {
-                result.truncate(count);
+                hits.truncate(count);
             }
 
-            let result = result
-                .iter()
-                .map(|(path, score)| {
-                    let path = path.to_string_lossy().to_string();
-                    format!("{score}: {path}")
-                })
-                .collect::<Vec<String>>();
+            let lines = match &template {
+                Some(template) => {
+                    let snippets = if template.needs_snippets() {
+                        let paths = hits
+                            .iter()
+                            .map(|(path, _)| path.as_path())
+                            .collect::<Vec<_>>();
+                        hit_snippets(&query, &index_files, &options, &paths)?
+                    } else {
+                        HashMap::new()
+                    };
+                    let snippets: HashMap<PathBuf, String> = snippets
+                        .into_iter()
+                        .map(|(path, fragment)| (path, fragment.text))
+                        .collect();
+                    hits.iter()
+                        .enumerate()
+                        .map(|(i, (path, score))| {
+                            template.render(&TemplateHit {
+                                rank: i + 1,
+                                score: *score,
+                                path,
+                                title: titles.get(path).map(String::as_str),
+                                snippet: snippets.get(path).map(String::as_str),
+                            })
+                        })
+                        .collect::<Vec<String>>()
+                }
+                None => {
+                    let excerpts = if snippets {
+                        let paths = hits
+                            .iter()
+                            .map(|(path, _)| path.as_path())
+                            .collect::<Vec<_>>();
+                        hit_snippets(&query, &index_files, &options, &paths)?
+                    } else {
+                        HashMap::new()
+                    };
+                    hits.iter()
+                        .map(|(path, score)| {
+                            let mut line = match titles.get(path) {
+                                Some(title) => {
+                                    format!("{score}: {title} ({})", path.to_string_lossy())
+                                }
+                                None => format!("{score}: {}", path.to_string_lossy()),
+                            };
+                            if let Some(others) = duplicates.get(path) {
+                                let others = others
+                                    .iter()
+                                    .map(|other| other.to_string_lossy())
+                                    .collect::<Vec<_>>();
+                                line = format!("{line} (also {})", others.join(", "));
+                            }
+                            match excerpts.get(path) {
+                                Some(fragment) => {
+                                    format!("{line}\n    {}", fragment.excerpt("**", "**"))
+                                }
+                                None => line,
+                            }
+                        })
+                        .collect::<Vec<String>>()
+                }
+            };
 
-            if let Some(ref f) = output_file {
-                fs::write(f, result.join(""))?;
-            } else {
-                result.iter().for_each(|r| println!("{r}"));
+            let output = SearchOutput {
+                query: &query,
+                hits: &hits,
+                titles: &titles,
+                duplicates: &duplicates,
+                lines: &lines,
+            };
+            match output_file {
+                Some(f) => write_results(&f, format, &output)?,
+                None => print!("{}", format_results(format.unwrap_or_default(), &output)?),
             }
         }
+        Commands::Count {
+            index_directory,
+            query,
+            language,
+            tags,
+            as_of,
+            fuzzy,
+        } => {
+            let index_files = match index_directory {
+                Some(p) => p,
+                None => default_index,
+            };
+            let options = SearchOptions {
+                key_file: args.key_file,
+                language,
+                tags,
+                as_of,
+                fuzzy,
+                ..Default::default()
+            };
+            println!("{}", count_term(&query, &index_files, &options)?);
+        }
         Commands::Serve {
             index_directory,
             port,
+            bind,
+            ranker,
+            bm25_k1,
+            bm25_b,
+            boost_recent,
+            record_matches,
+            weights,
+            compact,
+            preload,
+            pin_terms,
+            no_preload,
+            postings_cache,
+            results_cache,
+            workers,
+            allow_indexing,
+            allow_shutdown,
+            cors_origins,
+            auth_token,
+            tls_cert,
+            tls_key,
         } => {
             let port = port.unwrap_or(8765);
+            let bind = bind.unwrap_or_else(|| DEFAULT_BIND_ADDRESS.to_string());
+            // The variable keeps the token out of the process list
+            let auth_token = auth_token
+                .or_else(|| std::env::var(AUTH_TOKEN_VAR).ok())
+                .filter(|token| !token.is_empty());
+            // Without `--index` or `--name`, the indexes of the `serve`
+            // directives are hosted under their prefixes, if there are any
+            let routes = match index_directory {
+                Some(p) => vec![IndexRoute {
+                    prefix: String::new(),
+                    path: p,
+                    token: None,
+                }],
+                None if !named && !settings.routes.is_empty() => settings.routes.clone(),
+                None => vec![IndexRoute {
+                    prefix: String::new(),
+                    path: default_index,
+                    token: None,
+                }],
+            };
+            let bm25 = Bm25::new(
+                bm25_k1.unwrap_or(settings.bm25.k1),
+                bm25_b.unwrap_or(settings.bm25.b),
+            )?;
+            let cache_bytes = postings_cache
+                .map(|mb| mb * 1024 * 1024)
+                .unwrap_or(DEFAULT_POSTINGS_CACHE_BYTES);
+
+            let mut indexes = Vec::with_capacity(routes.len());
+            for route in routes {
+                let index_files = route.path;
+                if compact {
+                    compact_segments(&index_files, false, args.key_file.as_deref())?;
+                }
+
+                // The low-memory profile keeps no index in memory
+                let profile = Manifest::load(&index_files)?.profile;
+                let options = SearchOptions {
+                    ranker,
+                    boosts: settings.boosts.clone(),
+                    score_boosts: recency_boost(boost_recent),
+                    bm25,
+                    field_weights: weights.unwrap_or_default(),
+                    key_file: args.key_file.clone(),
+                    language: None,
+                    tags: Vec::new(),
+                    metadata: MetadataFilter::default(),
+                    sort: SortOrder::default(),
+                    as_of: None,
+                    fuzzy: None,
+                    postings_cache: (cache_bytes > 0 && profile.allows_preload())
+                        .then(|| Arc::new(PostingsCache::new(cache_bytes))),
+                    limit: None,
+                    record_matches,
+                };
+                if preload && !profile.allows_preload() {
+                    eprintln!(
+                        "Warning: --preload is disabled by the low-memory profile of the index {index_files:?}"
+                    );
+                }
+                indexes.push(ServedIndex {
+                    prefix: route.prefix,
+                    index_file: index_files,
+                    options,
+                    // The dictionaries are loaded once unless told otherwise;
+                    // `--preload` pins postings as well
+                    preload: (!no_preload && profile.allows_preload()).then(|| {
+                        if preload {
+                            pin_terms.unwrap_or(DEFAULT_PINNED_TERMS)
+                        } else {
+                            0
+                        }
+                    }),
+                    results_cache: results_cache.unwrap_or(DEFAULT_RESULTS_CACHE_ENTRIES),
+                    token: route.token.or_else(|| auth_token.clone()),
+                    indexing: allow_indexing.then(|| settings.clone()),
+                    allow_shutdown,
+                    cors_origins: cors_origins.clone(),
+                });
+            }
+            if !is_loopback(&bind) && indexes.iter().any(|index| index.token.is_none()) {
+                eprintln!(
+                    "Warning: the server is reachable from other machines and serves indexes without a token"
+                );
+            }
+            let logs_handler = thread::spawn(move || {
+                let _ = handle_messages(&receiver, error_handler);
+            });
+            let result = run_server(
+                indexes,
+                &bind,
+                port,
+                tls_cert
+                    .zip(tls_key)
+                    .map(|(cert, key)| TlsFiles { cert, key }),
+                workers.unwrap_or_else(default_workers),
+                logger.clone(),
+                |url| println!("Server listening on {url}"),
+            );
+            // Write the messages of the server before exiting
+            logger.stop();
+            logs_handler.join().unwrap();
+            result?;
+            println!("Server stopped");
+        }
+        Commands::Terms {
+            index_directory,
+            top,
+            prefix,
+        } => {
             let index_files = match index_directory {
                 Some(p) => p,
-                None => get_storage(),
+                None => default_index,
             };
-
-            run_server(&index_files, port, sender)?;
+            let terms = list_terms(
+                &index_files,
+                prefix.as_deref(),
+                top,
+                args.key_file.as_deref(),
+            )?;
+            terms.iter().for_each(|(term, df)| println!("{df}\t{term}"));
+        }
+        Commands::Sample {
+            path,
+            percent,
+            seed,
+            hidden,
+        } => {
+            let filepath = match path {
+                Some(p) => p,
+                None => std::env::current_dir().context("get current directory")?,
+            };
+            let temp = TempIndex::new()?;
+            let cfg = Config {
+                filepath,
+                index_path: temp.path().to_path_buf(),
+                error_handler: error_handler.clone(),
+                logger,
+                hidden,
+                skip: SkipRules::default(),
+                ranker: None,
+                auto_compact: None,
+                analyzer: None,
+                key_file: None,
+                raw_key: false,
+                throttle: false,
+                discovery: Discovery::default(),
+                keep_history: false,
+                keep_duplicates: false,
+                budget: None,
+                quota: None,
+                profile: None,
+                segment_budget: None,
+                sandbox: None,
+                resume: false,
+                policies: Vec::new(),
+                tags: Vec::new(),
+                tag_rules: Vec::new(),
+                expansions: None,
+                stem_exceptions: None,
+                stop_words: None,
+                noise: None,
+                time_budget: None,
+                order: IndexOrder::default(),
+                priorities: Vec::new(),
+                sniffing: Sniffing::default(),
+                max_file_size: None,
+                follow_symlinks: false,
+                progress: None,
+                observer: Some(Arc::new(ProgressObserver::default())),
+            };
+            let logs_handler = thread::spawn(move || {
+                let _ = handle_messages(&receiver, error_handler);
+            });
+            let sample = sample_corpus(&cfg, percent, seed);
+            cfg.logger.stop();
+            logs_handler.join().unwrap();
+            print_sample(&sample?);
+        }
+        Commands::Bench {
+            path,
+            synthetic,
+            words,
+            seed,
+            mut query,
+            queries,
+            rounds,
+            result_count,
+            hidden,
+            json,
+        } => {
+            if let Some(file) = queries {
+                let lines = fs::read_to_string(&file)
+                    .with_context(|| format!("read queries from {file:?}"))?;
+                query.extend(
+                    lines
+                        .lines()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty())
+                        .map(String::from),
+                );
+            }
+            let corpus = match synthetic {
+                Some(docs) => Some(SyntheticCorpus::generate(docs, words, seed)?),
+                None => None,
+            };
+            let filepath = match (&corpus, path) {
+                (Some(corpus), _) => corpus.path().to_path_buf(),
+                (None, Some(p)) => p,
+                (None, None) => std::env::current_dir().context("get current directory")?,
+            };
+            let temp = TempIndex::new()?;
+            let mut cfg = Config {
+                filepath,
+                index_path: temp.path().to_path_buf(),
+                error_handler: error_handler.clone(),
+                logger,
+                hidden,
+                skip: SkipRules::default(),
+                ranker: None,
+                auto_compact: None,
+                analyzer: None,
+                key_file: None,
+                raw_key: false,
+                throttle: false,
+                discovery: Discovery::default(),
+                keep_history: false,
+                keep_duplicates: false,
+                budget: None,
+                quota: None,
+                profile: None,
+                segment_budget: None,
+                sandbox: None,
+                resume: false,
+                policies: Vec::new(),
+                tags: Vec::new(),
+                tag_rules: Vec::new(),
+                expansions: None,
+                stem_exceptions: None,
+                stop_words: None,
+                noise: None,
+                time_budget: None,
+                order: IndexOrder::default(),
+                priorities: Vec::new(),
+                sniffing: Sniffing::default(),
+                max_file_size: None,
+                follow_symlinks: false,
+                progress: None,
+                observer: (!json)
+                    .then(|| Arc::new(ProgressObserver::default()) as Arc<dyn IndexingObserver>),
+            };
+            let logs_handler = thread::spawn(move || {
+                let _ = handle_messages(&receiver, error_handler);
+            });
+            let options = SearchOptions {
+                limit: Some(result_count),
+                ..SearchOptions::default()
+            };
+            let report = run_bench(&mut cfg, query, &options, rounds);
+            cfg.logger.stop();
+            logs_handler.join().unwrap();
+            let report = report?;
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&report).context("serialize bench report")?
+                );
+            } else {
+                print_bench(&report);
+            }
+        }
+        Commands::Verify {
+            index_directory,
+            repair,
+        } => {
+            let index_files = match index_directory {
+                Some(p) => p,
+                None => default_index,
+            };
+            let (checks, summary) = verify_index(&index_files, repair, args.key_file.as_deref())?;
+            for check in &checks {
+                let state = if check.quarantined {
+                    " (quarantined)"
+                } else {
+                    ""
+                };
+                match &check.error {
+                    Some(err) => println!("segment {}{state}: BROKEN: {err}", check.seg_id),
+                    None => println!("segment {}{state}: ok", check.seg_id),
+                }
+            }
+            let broken = checks.iter().filter(|c| c.error.is_some()).count();
+            match summary {
+                Some(summary) => println!(
+                    "Rebuilt {:?}, dropped {:?}, removed {} documents to re-index",
+                    summary.rebuilt, summary.dropped, summary.removed_docs
+                ),
+                None if broken > 0 => {
+                    return Err(anyhow!(
+                        "{broken} broken segments; run `indexer verify --repair`"
+                    ));
+                }
+                None => println!("All {} segments are healthy", checks.len()),
+            }
+        }
+        Commands::Stats {
+            index_directory,
+            largest,
+        } => {
+            let index_files = match index_directory {
+                Some(p) => p,
+                None => default_index,
+            };
+            let summary = index_stats(&index_files, largest, args.key_file.as_deref())?;
+            println!("Documents: {}", summary.documents);
+            println!("Unique terms: {}", summary.terms);
+            println!("Segments: {}", summary.segments);
+            println!("Postings size: {}", human_bytes(summary.postings_bytes));
+            println!("Index size: {}", human_bytes(summary.index_bytes));
+            if let Some(usage) = &summary.quota {
+                let flag = if usage.exceeded() { " (exceeded)" } else { "" };
+                println!("Quota: {}{flag}", usage.describe(human_bytes));
+            }
+            if let (Some(oldest), Some(newest)) = (summary.oldest, summary.newest) {
+                let format = |time: SystemTime| {
+                    chrono::DateTime::<chrono::Local>::from(time)
+                        .format("%Y-%m-%d %H:%M")
+                        .to_string()
+                };
+                let days = SystemTime::now()
+                    .duration_since(oldest)
+                    .unwrap_or_default()
+                    .as_secs()
+                    / (24 * 60 * 60);
+                println!(
+                    "Age: {days} days (first document indexed {}, last {})",
+                    format(oldest),
+                    format(newest)
+                );
+            }
+            if !summary.largest.is_empty() {
+                println!("\nLargest documents (tokens):");
+                for (path, tokens) in &summary.largest {
+                    println!("  {tokens:>10}  {}", path.display());
+                }
+            }
+        }
+        Commands::Inspect {
+            index_directory,
+            json,
+        } => {
+            let index_files = match index_directory {
+                Some(p) => p,
+                None => default_index,
+            };
+            let inspection = inspect_index(&index_files, args.key_file.as_deref())?;
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&inspection).context("serialize inspection")?
+                );
+                return Ok(());
+            }
+            println!("Index: {}", inspection.index_dir.display());
+            if let Some(manifest) = &inspection.manifest {
+                println!("Generation: {}", manifest.generation);
+                println!("Encrypted: {}", manifest.encrypted);
+                println!("Profile: {:?}", manifest.profile);
+                println!("Analyzer: {:?}", manifest.analyzer);
+            }
+            println!("Lock: {:?}", inspection.lock);
+            if inspection.interrupted_run {
+                println!("An interrupted indexing run can be resumed");
+            }
+            println!("Size: {}", human_bytes(inspection.bytes));
+            println!("\nSegments:");
+            for segment in &inspection.segments {
+                let counts = match (segment.documents, segment.terms) {
+                    (Some(documents), Some(terms)) => format!("{documents} docs, {terms} terms"),
+                    _ => "unreadable".to_string(),
+                };
+                println!(
+                    "  {:>6}  {:?}, {:?}, {}, {counts}",
+                    segment.id,
+                    segment.state,
+                    segment.format,
+                    human_bytes(segment.bytes)
+                );
+                if let Some(err) = &segment.error {
+                    println!("          {err}");
+                }
+            }
+            for err in &inspection.errors {
+                eprintln!("Error: {err}");
+            }
+        }
+        Commands::Compact {
+            index_directory,
+            all,
+        } => {
+            let index_files = match index_directory {
+                Some(p) => p,
+                None => default_index,
+            };
+            let summary = compact_segments(&index_files, all, args.key_file.as_deref())?;
+            if summary.merged.is_empty() {
+                println!("Nothing to compact: {} segments", summary.segments);
+            } else {
+                match summary.new_segment {
+                    Some(seg_id) => println!(
+                        "Merged {} segments into segment {seg_id}",
+                        summary.merged.len()
+                    ),
+                    None => println!(
+                        "Removed {} segments without live postings",
+                        summary.merged.len()
+                    ),
+                }
+                println!(
+                    "{} segments left, removed {} documents without postings",
+                    summary.segments, summary.removed_docs
+                );
+            }
+        }
+        Commands::Mv {
+            index_directory,
+            from,
+            to,
+        } => {
+            let index_files = match index_directory {
+                Some(p) => p,
+                None => default_index,
+            };
+            let moves = move_documents(&index_files, &from, &to, args.key_file.as_deref())?;
+            println!("Moved {} documents from {from:?} to {to:?}", moves.len());
+        }
+        Commands::Publish {
+            index_directory,
+            build,
+        } => {
+            let index_files = match index_directory {
+                Some(p) => p,
+                None => default_index,
+            };
+            match publish_index(&index_files, &build, args.key_file.as_deref())? {
+                Some(previous) => println!("Published {build:?}, replacing {previous:?}"),
+                None => println!("Published {build:?}"),
+            }
+        }
+        Commands::Diff {
+            before,
+            after,
+            json,
+        } => {
+            let diff = diff_indexes(&before, &after, args.key_file.as_deref())?;
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&diff).context("serialize diff")?
+                );
+                return Ok(());
+            }
+            let delta = |(before, after): (u64, u64)| after as i64 - before as i64;
+            println!(
+                "Generation {} -> {}",
+                diff.generations.0, diff.generations.1
+            );
+            for doc in &diff.added {
+                println!("+ {} ({} tokens)", doc.path.display(), doc.tokens);
+            }
+            for doc in &diff.removed {
+                println!("- {} ({} tokens)", doc.path.display(), doc.tokens);
+            }
+            for doc in &diff.reindexed {
+                println!(
+                    "~ {} ({} -> {} tokens{})",
+                    doc.path.display(),
+                    doc.tokens_before,
+                    doc.tokens_after,
+                    if doc.content_changed {
+                        ""
+                    } else {
+                        ", same content"
+                    }
+                );
+            }
+            println!(
+                "\n{} added, {} removed, {} re-indexed",
+                diff.added.len(),
+                diff.removed.len(),
+                diff.reindexed.len()
+            );
+            println!(
+                "Terms: {} -> {} ({:+})",
+                diff.terms.0,
+                diff.terms.1,
+                delta(diff.terms)
+            );
+            println!(
+                "Tokens: {} -> {} ({:+})",
+                diff.tokens.0,
+                diff.tokens.1,
+                delta(diff.tokens)
+            );
+        }
+        Commands::Delete {
+            index_directory,
+            path,
+        } => {
+            let index_files = match index_directory {
+                Some(p) => p,
+                None => default_index,
+            };
+            let deleted = delete_documents(&index_files, &path, args.key_file.as_deref())?;
+            println!("Deleted {} documents under {path:?}", deleted.len());
+        }
+        Commands::Export {
+            index_directory,
+            output_file,
+            format,
+        } => {
+            let index_files = match index_directory {
+                Some(p) => p,
+                None => default_index,
+            };
+            let Some(format) = format else {
+                let summary = archive_index(&index_files, &output_file)?;
+                println!(
+                    "Archived generation {} of the index, {} files ({} bytes), to {output_file:?}",
+                    summary.generation, summary.files, summary.bytes
+                );
+                return Ok(());
+            };
+            let count = export_index(&index_files, &output_file, format, args.key_file.as_deref())?;
+            println!("Exported {count} documents to {output_file:?}");
+        }
+        Commands::Import {
+            output_directory,
+            input_file,
+            path_field,
+        } => {
+            let index_path = match output_directory {
+                Some(path) => {
+                    fs::create_dir_all(&path).context("create output dir")?;
+                    path
+                }
+                None => {
+                    fs::create_dir_all(&default_index).context("create index dir")?;
+                    default_index
+                }
+            };
+            if is_index_archive(&input_file) {
+                let summary = import_archive(&input_file, &index_path)?;
+                println!(
+                    "Imported generation {} of the index, {} files ({} bytes), into {index_path:?}",
+                    summary.generation, summary.files, summary.bytes
+                );
+                return Ok(());
+            }
+            let count = import_tantivy(
+                &input_file,
+                &index_path,
+                &path_field,
+                args.key_file.as_deref(),
+            )?;
+            println!("Imported {count} documents from {input_file:?}");
+        }
+        Commands::Backup {
+            index_directory,
+            target,
+        } => {
+            let index_files = match index_directory {
+                Some(p) => p,
+                None => default_index,
+            };
+            let target = BackupTarget::parse(&target)?;
+            let summary = backup_index(&index_files, &target)?;
+            println!(
+                "Backed up generation {} to {target}: {} files ({}), {} uploaded ({})",
+                summary.generation,
+                summary.files,
+                human_bytes(summary.bytes),
+                summary.uploaded,
+                human_bytes(summary.uploaded_bytes)
+            );
+        }
+        Commands::Restore {
+            index_directory,
+            source,
+            generation,
+        } => {
+            let index_files = match index_directory {
+                Some(p) => p,
+                None => default_index,
+            };
+            let source = BackupTarget::parse(&source)?;
+            let summary = restore_index(&source, &index_files, generation)?;
+            println!(
+                "Restored generation {} into {index_files:?}: {} files ({})",
+                summary.generation,
+                summary.files,
+                human_bytes(summary.bytes)
+            );
+        }
+        Commands::Report {
+            index_directory,
+            last,
+        } => {
+            let index_files = match index_directory {
+                Some(p) => p,
+                None => default_index,
+            };
+            if !last {
+                list_reports(&index_files)?
+                    .iter()
+                    .for_each(|path| println!("{}", path.display()));
+                return Ok(());
+            }
+            match RunReport::load_last(&index_files, args.key_file.as_deref())? {
+                Some(report) => print_report(&report),
+                None => println!("No indexing runs recorded in {index_files:?}"),
+            }
+        }
+        Commands::Skipped {
+            index_directory,
+            last_run,
+            kind,
+            paths,
+        } => {
+            let index_files = match index_directory {
+                Some(p) => p,
+                None => default_index,
+            };
+            let skipped: Vec<_> = skipped_files(&index_files, last_run, args.key_file.as_deref())?
+                .into_iter()
+                .filter(|file| kind.is_none_or(|kind| file.kind == kind))
+                .filter(|file| paths.is_empty() || paths.iter().any(|p| file.path.starts_with(p)))
+                .collect();
+            if skipped.is_empty() {
+                println!("No files left out");
+            }
+            for file in &skipped {
+                let kind = file
+                    .kind
+                    .to_possible_value()
+                    .map(|value| value.get_name().to_string())
+                    .unwrap_or_default();
+                println!("{}: {kind}: {}", file.path.display(), file.reason);
+            }
+        }
+        Commands::Clip {
+            index_directory,
+            watch,
+        } => {
+            let index_files = match index_directory {
+                Some(p) => p,
+                None => get_storage().join("clipboard"),
+            };
+            fs::create_dir_all(&index_files).context("create clipboard index dir")?;
+            if watch {
+                println!("Watching the clipboard, press Ctrl-C to stop");
+                watch_clipboard(&index_files, args.key_file.as_deref(), |path| {
+                    println!("Captured {}", path.display())
+                })?;
+            }
+            match capture_clipboard(&index_files, args.key_file.as_deref())? {
+                Some(path) => println!("Captured {}", path.display()),
+                None => println!("The clipboard holds no text"),
+            }
+        }
+        Commands::ListIndexes => {
+            for index in store.list()? {
+                let marker = if index.is_default { "*" } else { " " };
+                let name = index.name.as_deref().unwrap_or("(unnamed)");
+                let roots = index
+                    .roots
+                    .iter()
+                    .map(|root| root.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                println!("{marker} {name}\t{}\t{roots}", index.path.display());
+            }
+        }
+        Commands::DropIndex { index_name } => {
+            let path = store.drop_index(&index_name)?;
+            println!("Deleted index {index_name:?} in {path:?}");
+        }
+        Commands::DefaultIndex { index_name, clear } => {
+            if clear {
+                store.set_default(None)?;
+                println!("Commands use the unnamed index by default");
+            } else if let Some(name) = index_name {
+                store.set_default(Some(&name))?;
+                println!("Commands use the index {name:?} by default");
+            } else {
+                match store.default_name()? {
+                    Some(name) => println!("{name}"),
+                    None => println!("(unnamed)"),
+                }
+            }
+        }
+        Commands::Service {
+            index_directory,
+            port,
+            skip_name,
+            skip_path,
+            skip_glob,
+            include,
+            skip_executables,
+        } => {
+            let index_files = match index_directory {
+                Some(p) => p,
+                None => default_index,
+            };
+            run_service(ServiceOptions {
+                index_dir: index_files,
+                port: port.unwrap_or(8765),
+                config_file,
+                error_handler,
+                key_file: args.key_file,
+                skip: SkipRules::new(skip_path, skip_name, skip_glob)
+                    .with_include(include)
+                    .with_executables(skip_executables),
+            })?;
+        }
+        Commands::ParseWorker { .. } => unreachable!("parse workers return early"),
+        Commands::SelfUpdate { check, install_dir } => {
+            let current = env!("CARGO_PKG_VERSION");
+            match self_update(install_dir.as_deref(), check)? {
+                UpdateStatus::UpToDate => println!("indexer {current} is up to date"),
+                UpdateStatus::Available(version) => {
+                    println!("indexer {version} is available (running {current})")
+                }
+                UpdateStatus::Installed { version, target } => println!(
+                    "Installed indexer {version} to {} (was running {current})",
+                    target.display()
+                ),
+            }
         }
     }
     Ok(())
@@ -0,0 +1,226 @@
+use anyhow::{Context, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::analyzer::AnalyzerSettings;
+use crate::budget::SizeBudget;
+use crate::crypto::KeyDerivation;
+use crate::noise::NoiseFilter;
+use crate::profile::Profile;
+use crate::publish::resolve_index;
+use crate::quota::Quota;
+use crate::scoring::Ranker;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The name of the manifest file within an index directory.
+pub(crate) const MANIFEST_FILE: &str = "manifest.json";
+
+/// The version of the on-disk layout of indexes, raised when the document
+/// store or the segments change in a way older indexers cannot read.
+pub const INDEX_FORMAT: u32 = 1;
+
+/// Index wide settings persisted next to the segments of an index.
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Manifest {
+    /// The ranking algorithm used when a search does not request one.
+    pub ranker: Ranker,
+    /// The tokenizer options documents were indexed with.
+    pub analyzer: AnalyzerSettings,
+    /// Incremented on every commit, so that readers can tell whether the
+    /// index changed since they loaded it.
+    pub generation: u64,
+    /// The `INDEX_FORMAT` the index was written in, `0` for indexes written
+    /// before the format was recorded.
+    pub format: u32,
+    /// Whether the segment files and the document store are encrypted.
+    pub encrypted: bool,
+    /// How the key of an encrypted index is derived from its key file.
+    /// `None` for indexes encrypted before it was recorded, whose key is the
+    /// SHA-256 hash of the key file.
+    pub key_derivation: Option<KeyDerivation>,
+    /// Whether previous versions of re-indexed documents are kept, so that
+    /// the index can be searched as it was at an earlier time.
+    pub keep_history: bool,
+    /// Whether files with identical content are indexed and listed in
+    /// results on their own, rather than folded into a single document.
+    pub keep_duplicates: bool,
+    /// The segments that failed to open or deserialize. They are skipped by
+    /// searches until `indexer verify --repair` rebuilds or drops them.
+    pub quarantined: Vec<u64>,
+    /// The committed segments. Readers only search these, so that segments
+    /// being written by an index run stay invisible until its commit. `None`
+    /// for indexes committed before the list was recorded, whose segment
+    /// directories are all searched.
+    pub segments: Option<Vec<u64>>,
+    /// The segments no longer committed whose directories are kept for the
+    /// readers of older generations, mapped to the generation of the commit
+    /// that retired them. They are removed at a later commit once no
+    /// `ReaderLease` holds a generation before it.
+    pub retired: BTreeMap<u64, u64>,
+    /// Set while a commit renames its staged document store, display forms
+    /// and statistics into place, so that readers wait for it to finish
+    /// instead of loading files that don't match the segments. A writer
+    /// opening the index finishes a commit that died with it set.
+    pub committing: bool,
+    /// The maximum size of the index, if any. Documents are evicted after
+    /// indexing runs leaving the index larger.
+    pub budget: Option<SizeBudget>,
+    /// The document and disk quotas of the index, if any.
+    pub quota: Option<Quota>,
+    /// The resource profile indexing runs and servers of the index use.
+    pub profile: Profile,
+    /// The approximate memory in bytes an in-memory segment holds before it
+    /// is flushed, if set. Otherwise segments are flushed every
+    /// `MAX_SEGMENT_DOCS` documents, or as the profile says.
+    pub segment_budget: Option<u64>,
+    /// The words abbreviations stand for, added to documents and queries
+    /// alike, keyed by the case folded abbreviation.
+    pub expansions: BTreeMap<String, String>,
+    /// The stems given to words instead of those of the stemmer, keyed by
+    /// the case folded word.
+    pub stem_exceptions: BTreeMap<String, String>,
+    /// The stop words filtered out instead of those of the analyzer
+    /// language, if overridden.
+    pub stop_words: Option<BTreeSet<String>>,
+    /// The terms found in a single document that are dropped when segments
+    /// are merged, if any.
+    pub noise: Option<NoiseFilter>,
+    /// The canonical paths indexing runs were given. Documents are only ever
+    /// served from within them.
+    pub roots: Vec<PathBuf>,
+}
+
+impl Manifest {
+    /// Loads the manifest of the index in `index_dir`, or of the index
+    /// published in it, falling back to the default manifest if the index
+    /// has none yet.
+    ///
+    /// # Arguments
+    /// * `index_dir` - The directory containing the index files.
+    ///
+    /// # Returns
+    /// The loaded `Manifest`, or an `anyhow::Result` error if the manifest
+    /// exists but cannot be parsed.
+    pub fn load(index_dir: &Path) -> anyhow::Result<Self> {
+        let path = resolve_index(index_dir).join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path).context("read manifest")?;
+        serde_json::from_str(&content).context("parse manifest")
+    }
+
+    /// Checks that the index was written in a format this indexer reads.
+    ///
+    /// # Arguments
+    /// * `index_dir` - The directory containing the index files, for the
+    ///   error message.
+    ///
+    /// # Returns
+    /// `Ok(())` if it was, otherwise an `anyhow::Result` error saying to
+    /// upgrade the indexer.
+    pub fn check_format(&self, index_dir: &Path) -> anyhow::Result<()> {
+        if self.format > INDEX_FORMAT {
+            return Err(anyhow!(
+                "index {index_dir:?} was written by a newer indexer in format {} \
+                 (this one reads up to format {INDEX_FORMAT}); upgrade to open it",
+                self.format
+            ));
+        }
+        Ok(())
+    }
+
+    /// Writes the manifest into `index_dir`.
+    ///
+    /// # Arguments
+    /// * `index_dir` - The directory containing the index files.
+    ///
+    /// # Returns
+    /// `Ok(())` if the manifest was written, otherwise an `anyhow::Result`
+    /// error.
+    pub fn save(&self, index_dir: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self).context("serialize manifest")?;
+        write_atomic(&index_dir.join(MANIFEST_FILE), content.as_bytes()).context("write manifest")
+    }
+}
+
+/// Writes `content` into `path` through a temporary file renamed over it, so
+/// that readers see either the previous content or the new one, never a
+/// partially written file. The new content is on disk before the rename, so
+/// that a crash doesn't leave an empty file behind either.
+///
+/// # Arguments
+/// * `path` - The file to write.
+/// * `content` - The new content of the file.
+///
+/// # Returns
+/// `Ok(())` once the file is replaced, otherwise an `anyhow::Result` error.
+pub(crate) fn write_atomic(path: &Path, content: &[u8]) -> anyhow::Result<()> {
+    stage_file(path, content)?;
+    install_file(path)
+}
+
+/// Returns the temporary file `content` meant for `path` is staged in,
+/// e.g. `docstore.bin.tmp` for `docstore.bin`.
+pub(crate) fn staged_path(path: &Path) -> PathBuf {
+    let mut staged = PathBuf::from(path);
+    staged.as_mut_os_string().push(".tmp");
+    staged
+}
+
+/// Writes `content` into the staged file of `path` and syncs it to disk,
+/// leaving `path` untouched until `install_file` renames it over.
+///
+/// # Arguments
+/// * `path` - The file to write.
+/// * `content` - The new content of the file.
+///
+/// # Returns
+/// `Ok(())` once the staged file is on disk, otherwise an `anyhow::Result`
+/// error.
+pub(crate) fn stage_file(path: &Path, content: &[u8]) -> anyhow::Result<()> {
+    let staged = staged_path(path);
+    let mut file = File::create(&staged).with_context(|| format!("create {staged:?}"))?;
+    file.write_all(content)
+        .and_then(|_| file.sync_all())
+        .with_context(|| format!("write {staged:?}"))
+}
+
+/// Renames the staged file of `path` over it and syncs the directory, so
+/// that the rename survives a crash.
+///
+/// # Arguments
+/// * `path` - The file replaced by its staged content.
+///
+/// # Returns
+/// `Ok(())` once the file is replaced, otherwise an `anyhow::Result` error.
+pub(crate) fn install_file(path: &Path) -> anyhow::Result<()> {
+    fs::rename(staged_path(path), path).with_context(|| format!("replace {path:?}"))?;
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    sync_dir(dir)
+}
+
+/// Syncs the entries of `dir` to disk, making the files created, renamed or
+/// removed in it durable.
+///
+/// # Arguments
+/// * `dir` - The directory to sync.
+///
+/// # Returns
+/// `Ok(())` once synced, otherwise an `anyhow::Result` error.
+pub(crate) fn sync_dir(dir: &Path) -> anyhow::Result<()> {
+    // Directories can't be opened for syncing on every platform
+    if cfg!(unix) {
+        File::open(dir)
+            .and_then(|dir| dir.sync_all())
+            .with_context(|| format!("sync {dir:?}"))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use crate::embedded::container_file;
+use crate::tree::DocInfo;
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Restricts search results by the metadata of their documents recorded at
+/// indexing time, e.g. `--after 2024-01-01 --ext pdf --under ~/projects`.
+/// Every restriction set must hold for a document to be kept.
+#[derive(Clone, Debug, Default)]
+pub struct MetadataFilter {
+    /// Only keep the documents modified at or after this time.
+    pub after: Option<SystemTime>,
+    /// Only keep the documents modified before this time.
+    pub before: Option<SystemTime>,
+    /// Only keep the documents with one of these extensions, lowercase and
+    /// without a leading dot.
+    pub extensions: Vec<String>,
+    /// Only keep the documents under this directory, made absolute.
+    pub under: Option<PathBuf>,
+}
+
+impl MetadataFilter {
+    /// Creates a filter, normalizing the extensions (`.PDF` is `pdf`) and
+    /// making `under` absolute against the current directory.
+    ///
+    /// # Arguments
+    /// * `after` - The earliest modification time kept.
+    /// * `before` - The modification time from which documents are left out.
+    /// * `extensions` - The extensions kept, e.g. `pdf` or `.md`; all if
+    ///   empty.
+    /// * `under` - The directory the documents kept are in.
+    pub fn new(
+        after: Option<SystemTime>,
+        before: Option<SystemTime>,
+        extensions: Vec<String>,
+        under: Option<PathBuf>,
+    ) -> Self {
+        let extensions = extensions
+            .iter()
+            .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+            .filter(|ext| !ext.is_empty())
+            .collect();
+        Self {
+            after,
+            before,
+            extensions,
+            under: under.map(|dir| absolute(&dir)),
+        }
+    }
+
+    /// Returns `true` if the filter keeps every document.
+    pub fn is_empty(&self) -> bool {
+        self.after.is_none()
+            && self.before.is_none()
+            && self.extensions.is_empty()
+            && self.under.is_none()
+    }
+
+    /// Returns `true` if the document described by `info` is kept. Documents
+    /// without a recorded modification time are dated by their indexing, and
+    /// embedded documents are under the directory of their container file.
+    pub fn matches(&self, info: &DocInfo) -> bool {
+        let modified = info.modified.unwrap_or(info.indexed_at);
+        if self.after.is_some_and(|after| modified < after)
+            || self.before.is_some_and(|before| modified >= before)
+        {
+            return false;
+        }
+        if !self.extensions.is_empty()
+            && !info
+                .extension
+                .as_ref()
+                .is_some_and(|ext| self.extensions.contains(ext))
+        {
+            return false;
+        }
+        self.under.as_ref().is_none_or(|dir| {
+            let file = container_file(&info.path).unwrap_or_else(|| info.path.clone());
+            absolute(&file).starts_with(dir)
+        })
+    }
+}
+
+/// Returns the extension recorded for the document at `path`, lowercase,
+/// e.g. `pdf` for `Report.PDF`.
+pub fn extension_of(path: &Path) -> Option<String> {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+}
+
+/// Makes `path` absolute against the current directory without touching
+/// the file system, so that documents indexed through a relative path are
+/// found under the absolute directory holding them.
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Parses a point in time given on the command line or to the server: an
+/// RFC 3339 timestamp, a local `YYYY-MM-DD HH:MM:SS` time, or a date alone,
+/// meaning midnight local time at the start of that day.
+///
+/// # Arguments
+/// * `value` - The timestamp to parse.
+///
+/// # Returns
+/// The `SystemTime`, or an error message if the format is not recognised.
+pub fn parse_timestamp(value: &str) -> Result<SystemTime, String> {
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(time.into());
+    }
+    let local = chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| {
+            chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map(|date| date.and_time(chrono::NaiveTime::MIN))
+        })
+        .map_err(|_| format!("invalid timestamp {value:?}"))?;
+    local
+        .and_local_timezone(chrono::Local)
+        .earliest()
+        .map(SystemTime::from)
+        .ok_or_else(|| format!("{value:?} does not exist in the local time zone"))
+}
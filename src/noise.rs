@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+
+/// The default length beyond which a term is considered noise by
+/// `NoiseHeuristic::Long`.
+pub const DEFAULT_NOISE_MAX_LEN: usize = 40;
+
+/// The minimum length of a hex or base64 blob; shorter terms such as
+/// `deadbeef` or `utf8` are too likely to be words.
+const MIN_BLOB_LEN: usize = 16;
+
+/// The kinds of terms considered noise when pruning rare terms at merge time.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NoiseHeuristic {
+    /// Terms longer than the maximum length of the filter.
+    Long,
+    /// Hex strings such as hashes and ids, e.g. `3f9a0c...`.
+    Hex,
+    /// Runs of letters and digits such as base64 encoded blobs and tokens.
+    Base64,
+}
+
+/// Which terms found in a single document are dropped when segments are
+/// merged, so that ids, hashes and encoded blobs of log-heavy corpora don't
+/// dominate the dictionary.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct NoiseFilter {
+    /// The heuristics a term is checked against; matching any makes it
+    /// noise.
+    pub heuristics: Vec<NoiseHeuristic>,
+    /// The length beyond which a term is noise with `NoiseHeuristic::Long`.
+    pub max_len: usize,
+}
+
+impl NoiseFilter {
+    /// Returns `true` if `term` looks like noise. Prefixed terms, e.g. exact
+    /// or path terms, are checked without their prefix.
+    ///
+    /// # Arguments
+    /// * `term` - The index term.
+    pub fn is_noise(&self, term: &str) -> bool {
+        let word = term.trim_start_matches(|c: char| !c.is_alphanumeric());
+        if word.is_empty() || word.contains(':') {
+            return false;
+        }
+        let len = word.chars().count();
+        self.heuristics.iter().any(|heuristic| match heuristic {
+            NoiseHeuristic::Long => len > self.max_len,
+            NoiseHeuristic::Hex => is_hex_blob(word, len),
+            NoiseHeuristic::Base64 => is_base64_blob(word, len),
+        })
+    }
+}
+
+/// Returns `true` if `word` is a long hex string holding at least one digit,
+/// so that long words made of the letters `a` to `f` are kept.
+fn is_hex_blob(word: &str, len: usize) -> bool {
+    len >= MIN_BLOB_LEN
+        && word.chars().all(|c| c.is_ascii_hexdigit())
+        && word.chars().any(|c| c.is_ascii_digit())
+}
+
+/// Returns `true` if `word` is a long run of ASCII letters and digits
+/// mixing both, with digits spread through it rather than only as a suffix
+/// (`version2024` is kept).
+fn is_base64_blob(word: &str, len: usize) -> bool {
+    if len < MIN_BLOB_LEN || !word.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return false;
+    }
+    // Count the switches between letters and digits: words with a number
+    // appended switch once, encoded data many times
+    let switches = word
+        .as_bytes()
+        .windows(2)
+        .filter(|pair| pair[0].is_ascii_digit() != pair[1].is_ascii_digit())
+        .count();
+    switches >= 4
+}
@@ -0,0 +1,103 @@
+use crate::logger::Logger;
+
+use std::path::Path;
+use std::time::Duration;
+
+/// What an indexing run did, given to `IndexingObserver::on_finish`.
+#[derive(Debug, Clone, Default)]
+pub struct RunStats {
+    /// The number of documents indexed.
+    pub indexed: u64,
+    /// The size of the documents indexed, in bytes.
+    pub bytes: u64,
+    /// The number of documents left for the next run once the time budget
+    /// of the run expired.
+    pub out_of_time: u64,
+    /// The number of new documents not indexed once the document quota of
+    /// the index was reached.
+    pub over_quota: u64,
+    /// The number of documents evicted to stay within the size budget of
+    /// the index.
+    pub evicted: u64,
+    /// How long the run took.
+    pub elapsed: Duration,
+}
+
+/// Follows an indexing run, e.g. to drive a progress bar. The library
+/// prints nothing about a run itself: an observer set in the `Config` of
+/// the run is told instead. Every method does nothing unless implemented.
+///
+/// Documents are processed by several threads at once, so the methods about
+/// them may be called concurrently.
+pub trait IndexingObserver: Send + Sync {
+    /// Called with a notice about the run, e.g. the number of documents
+    /// moved since the previous run.
+    fn on_notice(&self, _message: &str) {}
+
+    /// Called with a warning about the run or the index, e.g. an index over
+    /// its quota.
+    fn on_warning(&self, _message: &str) {}
+
+    /// Called once the documents to index are known, before any of them is
+    /// processed.
+    ///
+    /// # Arguments
+    /// * `total` - The number of documents the run (re-)indexes.
+    /// * `discovered` - The number of documents found, unchanged ones
+    ///   included.
+    fn on_start(&self, _total: u64, _discovered: u64) {}
+
+    /// Called when a document starts being parsed and indexed.
+    fn on_file_start(&self, _path: &Path) {}
+
+    /// Called once a document is processed, indexed or not.
+    fn on_file_done(&self, _path: &Path) {}
+
+    /// Called once a segment is written to disk.
+    fn on_flush(&self, _segment_id: u64) {}
+
+    /// Called once the run is committed.
+    fn on_finish(&self, _stats: &RunStats) {}
+}
+
+/// The observer of runs given none, ignoring them.
+pub(crate) struct SilentObserver;
+
+impl IndexingObserver for SilentObserver {}
+
+/// An observer writing the notices, warnings and summary of indexing runs
+/// to a log, e.g. that of the server or the service.
+pub struct LoggingObserver {
+    /// The log written to.
+    logger: Logger,
+}
+
+impl LoggingObserver {
+    /// Creates an observer writing to `logger`.
+    pub fn new(logger: Logger) -> Self {
+        Self { logger }
+    }
+}
+
+impl IndexingObserver for LoggingObserver {
+    fn on_notice(&self, message: &str) {
+        self.logger.info(message);
+    }
+
+    fn on_warning(&self, message: &str) {
+        self.logger.error(message);
+    }
+
+    fn on_flush(&self, segment_id: u64) {
+        self.logger.debug(format!("Flushed segment_{segment_id}"));
+    }
+
+    fn on_finish(&self, stats: &RunStats) {
+        self.logger.info(format!(
+            "Indexed {} documents ({} bytes) in {:.1}s",
+            stats.indexed,
+            stats.bytes,
+            stats.elapsed.as_secs_f64()
+        ));
+    }
+}
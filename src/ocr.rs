@@ -0,0 +1,101 @@
+use anyhow::{Context, anyhow};
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// The resolution, in dots per inch, PDF pages are rendered at for text
+/// recognition.
+const OCR_DPI: u32 = 300;
+
+/// PDF pages whose text layer holds fewer non-blank characters than this
+/// are taken for scans and recognized.
+pub const MIN_PAGE_TEXT: usize = 16;
+
+/// The extensions of the image files whose text is recognized.
+pub const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "tif", "tiff"];
+
+/// Returns `true` if the text extracted from a PDF page is too short for it
+/// to have a text layer, e.g. a page number stamped on a scan.
+pub fn needs_ocr(page_text: &str) -> bool {
+    page_text.chars().filter(|c| !c.is_whitespace()).count() < MIN_PAGE_TEXT
+}
+
+/// Recognizes the text of an image file with `tesseract`.
+///
+/// # Arguments
+/// * `path` - The image file.
+///
+/// # Returns
+/// The recognized text, or an `anyhow::Result` error if `tesseract` is not
+/// installed or fails.
+pub fn image_text(path: &Path) -> anyhow::Result<String> {
+    let output = run(Command::new("tesseract").arg(path).arg("stdout"), None)?;
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// Recognizes the text of a page of a PDF document: the page is rendered
+/// with `pdftoppm` and its image read by `tesseract`.
+///
+/// # Arguments
+/// * `path` - The PDF document.
+/// * `page` - The number of the page, starting at 1.
+///
+/// # Returns
+/// The recognized text, or an `anyhow::Result` error if `pdftoppm` or
+/// `tesseract` are not installed or fail.
+pub fn pdf_page_text(path: &Path, page: u32) -> anyhow::Result<String> {
+    let image = run(
+        Command::new("pdftoppm")
+            .args(["-png", "-singlefile", "-r", &OCR_DPI.to_string()])
+            .args(["-f", &page.to_string(), "-l", &page.to_string()])
+            .arg(path),
+        None,
+    )?;
+    let output = run(
+        Command::new("tesseract").args(["stdin", "stdout"]),
+        Some(image),
+    )?;
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// Runs an OCR tool, feeding it `input` if any, and returns what it wrote
+/// to its standard output.
+fn run(command: &mut Command, input: Option<Vec<u8>>) -> anyhow::Result<Vec<u8>> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => anyhow!("{program} is not installed"),
+            _ => anyhow!(err).context(format!("run {program}")),
+        })?;
+    // The input is written from another thread so that a tool writing its
+    // output before reading all of it doesn't block
+    let writer = input.zip(child.stdin.take()).map(|(input, mut stdin)| {
+        thread::spawn(move || {
+            // A tool exiting early closes the pipe; its status tells why
+            let _ = stdin.write_all(&input);
+        })
+    });
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("run {program}"))?;
+    if let Some(writer) = writer {
+        let _ = writer.join();
+    }
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{program} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
@@ -0,0 +1,160 @@
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+/// Blocking work waiting for a thread of the pool.
+type Job = Box<dyn FnOnce() + Send>;
+
+/// The queue of the threads running offloaded work, started on first use.
+static POOL: OnceLock<Sender<Job>> = OnceLock::new();
+
+/// The result of blocking work run on the offload pool by `offload`, to
+/// be awaited from async code. It works with any executor, e.g. tokio or
+/// async-std, as it only relies on the waker of the task awaiting it.
+pub struct Offloaded<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+/// The state shared by an `Offloaded` and the thread doing its work.
+struct Shared<T> {
+    /// The outcome of the work once done, or the panic it raised.
+    result: Option<thread::Result<T>>,
+    /// The waker of the task awaiting the work, woken once it is done.
+    waker: Option<Waker>,
+}
+
+impl<T> Future for Offloaded<T> {
+    type Output = T;
+
+    /// Resolves to the outcome of the work once done. A panic of the work
+    /// is raised again in the task awaiting it.
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut shared = self.shared.lock().unwrap();
+        match shared.result.take() {
+            Some(Ok(value)) => Poll::Ready(value),
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Returns the queue of the offload pool, starting its threads if needed:
+/// one per available CPU, so that a burst of calls queues up instead of
+/// starting a thread each.
+fn pool() -> &'static Sender<Job> {
+    POOL.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let threads = thread::available_parallelism().map_or(4, NonZeroUsize::get);
+        for number in 0..threads {
+            let receiver = Arc::clone(&receiver);
+            thread::Builder::new()
+                .name(format!("offload-{number}"))
+                .spawn(move || run_jobs(&receiver))
+                .expect("start offload thread");
+        }
+        sender
+    })
+}
+
+/// Runs the jobs of the offload pool one after the other, for as long as
+/// the process lives.
+///
+/// # Arguments
+/// * `receiver` - The queue shared by the threads of the pool.
+fn run_jobs(receiver: &Mutex<Receiver<Job>>) {
+    loop {
+        // The lock is released before running the job
+        let job = receiver.lock().unwrap().recv();
+        match job {
+            Ok(job) => job(),
+            Err(_) => return,
+        }
+    }
+}
+
+/// Runs blocking work, such as reading the index files or parsing
+/// documents, on a thread of the offload pool, so that the async runtime
+/// awaiting it keeps running its other tasks meanwhile.
+///
+/// # Arguments
+/// * `work` - The blocking work.
+///
+/// # Returns
+/// The `Offloaded` future resolving to the outcome of `work`.
+pub fn offload<T, F>(work: F) -> Offloaded<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let shared = Arc::new(Mutex::new(Shared {
+        result: None,
+        waker: None,
+    }));
+    let done = Arc::clone(&shared);
+    let job: Job = Box::new(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(work));
+        let mut done = done.lock().unwrap();
+        done.result = Some(result);
+        if let Some(waker) = done.waker.take() {
+            waker.wake();
+        }
+    });
+    // The receiving threads never exit, so the queue stays open
+    let _ = pool().send(job);
+    Offloaded { shared }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::Wake;
+    use std::thread::Thread;
+
+    /// Wakes the test thread parked while awaiting offloaded work.
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Awaits `future` on the current thread.
+    fn block_on<T>(future: Offloaded<T>) -> T {
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(value) => return value,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn bursts_of_work_share_the_pool_threads() {
+        let pending = (0..256)
+            .map(|number| offload(move || (number, thread::current().id())))
+            .collect::<Vec<_>>();
+        let mut threads = Vec::new();
+        for (expected, future) in pending.into_iter().enumerate() {
+            let (number, thread) = block_on(future);
+            assert_eq!(number, expected);
+            if !threads.contains(&thread) {
+                threads.push(thread);
+            }
+        }
+        let limit = thread::available_parallelism().map_or(4, NonZeroUsize::get);
+        assert!(threads.len() <= limit);
+    }
+}
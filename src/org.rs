@@ -0,0 +1,139 @@
+/// The keywords of Org headlines marking their state.
+const TODO_KEYWORDS: [&str; 2] = ["TODO", "DONE"];
+
+/// The `#+KEYWORD:` lines whose value is prose.
+const TEXT_KEYWORDS: [&str; 3] = ["TITLE", "SUBTITLE", "CAPTION"];
+
+/// The prose of an Org document.
+#[derive(Debug, Default)]
+pub struct OrgText {
+    /// The text of the headlines and of the body.
+    pub text: String,
+    /// The value of `#+TITLE:`, if any.
+    pub title: Option<String>,
+}
+
+/// Extracts the prose of an Org-mode document. Headlines are kept without
+/// their stars, `TODO`/`DONE` keyword and priority cookie, their tags as
+/// words. Links are replaced by their description, or their target when
+/// they have none. Comments, comment blocks, drawers (`:PROPERTIES:` ...
+/// `:END:`), planning lines (`SCHEDULED:`, `DEADLINE:`) and settings such
+/// as `#+OPTIONS:` are left out; the delimiters of other blocks are dropped
+/// and their content kept.
+///
+/// # Arguments
+/// * `source` - The Org source.
+///
+/// # Returns
+/// The `OrgText` of the document.
+pub fn org_to_text(source: &str) -> OrgText {
+    let mut out = OrgText::default();
+    let mut in_drawer = false;
+    let mut in_comment = false;
+    for line in source.lines() {
+        let trimmed = line.trim();
+        let upper = trimmed.to_ascii_uppercase();
+        if in_comment {
+            in_comment = !upper.starts_with("#+END_COMMENT");
+            continue;
+        }
+        if in_drawer {
+            in_drawer = upper != ":END:";
+            continue;
+        }
+        if is_drawer_start(trimmed) {
+            in_drawer = true;
+            continue;
+        }
+        if upper.starts_with("#+BEGIN_COMMENT") {
+            in_comment = true;
+            continue;
+        }
+        if let Some(keyword_line) = trimmed.strip_prefix("#+") {
+            let (keyword, value) = keyword_line.split_once(':').unwrap_or((keyword_line, ""));
+            let keyword = keyword.to_ascii_uppercase();
+            if TEXT_KEYWORDS.contains(&keyword.as_str()) {
+                let value = link_text(value.trim());
+                if keyword == "TITLE" && out.title.is_none() && !value.is_empty() {
+                    out.title = Some(value.clone());
+                }
+                out.text.push_str(&value);
+                out.text.push('\n');
+            }
+            continue;
+        }
+        // Comment lines start with `#` and a space
+        if trimmed == "#" || trimmed.starts_with("# ") {
+            continue;
+        }
+        if ["SCHEDULED:", "DEADLINE:", "CLOSED:"]
+            .iter()
+            .any(|planning| trimmed.starts_with(planning))
+        {
+            continue;
+        }
+        let text = match headline(line) {
+            Some(title) => title,
+            None => line,
+        };
+        out.text.push_str(&link_text(text));
+        out.text.push('\n');
+    }
+    out
+}
+
+/// Returns `true` if `line` opens a drawer, e.g. `:PROPERTIES:`.
+fn is_drawer_start(line: &str) -> bool {
+    line.len() > 2
+        && line != ":END:"
+        && line
+            .strip_prefix(':')
+            .and_then(|name| name.strip_suffix(':'))
+            .is_some_and(|name| {
+                name.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            })
+}
+
+/// Returns the title of a headline, with its tags, if `line` is one: stars
+/// followed by a space, then an optional state keyword and priority cookie.
+fn headline(line: &str) -> Option<&str> {
+    let rest = line.trim_start_matches('*');
+    if rest.len() == line.len() || !rest.starts_with(' ') {
+        return None;
+    }
+    let mut rest = rest.trim_start();
+    for keyword in TODO_KEYWORDS {
+        if let Some(after) = rest.strip_prefix(keyword)
+            && (after.is_empty() || after.starts_with(' '))
+        {
+            rest = after.trim_start();
+        }
+    }
+    if rest.starts_with("[#") && rest.get(3..4) == Some("]") {
+        rest = rest[4..].trim_start();
+    }
+    Some(rest)
+}
+
+/// Replaces the links of `text` by their description, e.g.
+/// `[[https://orgmode.org][Org]]` by `Org`, or by their target when they
+/// have none.
+fn link_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        let Some(end) = rest[start..].find("]]") else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let link = &rest[start + 2..start + end];
+        out.push_str(match link.split_once("][") {
+            Some((_, description)) => description,
+            None => link,
+        });
+        rest = &rest[start + end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,174 @@
+use anyhow::Context;
+use serde_json::Value;
+
+use crate::hit_json;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The format search results are printed in with `--format`, or written to
+/// an `--output` file in, chosen by the extension of the file by default.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The lines shown on the terminal, one per result.
+    #[default]
+    Text,
+    /// A CSV table with a header row.
+    Csv,
+    /// A tab separated table with a header row.
+    Tsv,
+    /// One JSON object per result and line.
+    #[value(name = "json", alias = "jsonl")]
+    Jsonl,
+    /// A Markdown table.
+    Markdown,
+}
+
+impl OutputFormat {
+    /// Picks the format of the file at `path` from its extension: `.csv`,
+    /// `.tsv`, `.jsonl` (or `.ndjson`), `.md` (or `.markdown`), and plain
+    /// text for any other.
+    pub fn from_path(path: &Path) -> Self {
+        let ext = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match ext.as_str() {
+            "csv" => Self::Csv,
+            "tsv" => Self::Tsv,
+            "jsonl" | "ndjson" => Self::Jsonl,
+            "md" | "markdown" => Self::Markdown,
+            _ => Self::Text,
+        }
+    }
+}
+
+/// Writes search results to a file in `format`, or in the format of its
+/// extension without one.
+///
+/// # Arguments
+/// * `path` - The file to write.
+/// * `format` - The format to write, if chosen with `--format`.
+/// * `results` - The results to write.
+///
+/// # Returns
+/// `Ok(())` once written, otherwise an `anyhow::Result` error.
+pub fn write_results(
+    path: &Path,
+    format: Option<OutputFormat>,
+    results: &SearchOutput,
+) -> anyhow::Result<()> {
+    let format = format.unwrap_or_else(|| OutputFormat::from_path(path));
+    let content = format_results(format, results)?;
+    fs::write(path, content).with_context(|| format!("write results to {path:?}"))
+}
+
+/// Search results along with what their output formats show of them.
+pub struct SearchOutput<'a> {
+    /// The query the results are for.
+    pub query: &'a str,
+    /// The results, in descending order of score.
+    pub hits: &'a [(PathBuf, f64)],
+    /// The titles of the documents that have one.
+    pub titles: &'a HashMap<PathBuf, String>,
+    /// The other paths of the results with duplicates.
+    pub duplicates: &'a HashMap<PathBuf, Vec<PathBuf>>,
+    /// The results as shown on the terminal, for the text format.
+    pub lines: &'a [String],
+}
+
+/// Formats search results. The structured formats hold the rank, score,
+/// path, title and duplicate paths of every result; the tables have a
+/// header row even without results.
+///
+/// # Arguments
+/// * `format` - The format to write.
+/// * `results` - The results to format.
+///
+/// # Returns
+/// The formatted results, one line per result, otherwise an
+/// `anyhow::Result` error.
+pub fn format_results(format: OutputFormat, results: &SearchOutput) -> anyhow::Result<String> {
+    let SearchOutput {
+        query,
+        hits,
+        titles,
+        duplicates,
+        lines,
+    } = results;
+    let others = |doc: &Path| {
+        duplicates
+            .get(doc)
+            .map(|others| {
+                others
+                    .iter()
+                    .map(|other| other.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(";")
+            })
+            .unwrap_or_default()
+    };
+
+    let content = match format {
+        OutputFormat::Text => lines.iter().map(|line| format!("{line}\n")).collect(),
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            let delimiter = if format == OutputFormat::Tsv {
+                b'\t'
+            } else {
+                b','
+            };
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(delimiter)
+                .from_writer(Vec::new());
+            writer
+                .write_record(["rank", "score", "path", "title", "duplicates"])
+                .context("write table header")?;
+            for (rank, (doc, score)) in hits.iter().enumerate() {
+                writer
+                    .write_record([
+                        (rank + 1).to_string(),
+                        score.to_string(),
+                        doc.to_string_lossy().to_string(),
+                        titles.get(doc).cloned().unwrap_or_default(),
+                        others(doc),
+                    ])
+                    .context("write table record")?;
+            }
+            let buf = writer.into_inner().context("flush table")?;
+            String::from_utf8(buf).context("table output")?
+        }
+        OutputFormat::Jsonl => hits
+            .iter()
+            .enumerate()
+            .map(|(rank, (doc, score))| {
+                let mut hit = hit_json(doc, *score, duplicates, titles);
+                hit["rank"] = Value::from(rank + 1);
+                hit["query"] = Value::from(*query);
+                format!("{hit}\n")
+            })
+            .collect(),
+        OutputFormat::Markdown => {
+            let mut table = format!("Results for `{query}`\n\n");
+            table.push_str("| Rank | Score | Title | Path | Duplicates |\n");
+            table.push_str("|---:|---:|---|---|---|\n");
+            for (rank, (doc, score)) in hits.iter().enumerate() {
+                let title = titles.get(doc).map(String::as_str).unwrap_or_default();
+                table.push_str(&format!(
+                    "| {} | {score:.4} | {} | {} | {} |\n",
+                    rank + 1,
+                    escape_cell(title),
+                    escape_cell(&doc.to_string_lossy()),
+                    escape_cell(&others(doc)),
+                ));
+            }
+            table
+        }
+    };
+    Ok(content)
+}
+
+/// Escapes the characters that would break a Markdown table cell.
+fn escape_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
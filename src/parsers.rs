@@ -1,258 +1,1419 @@
 use anyhow::Context;
 use html5ever::driver::{self, ParseOpts};
 use lopdf;
-use scraper::{Html, HtmlTreeSink};
+use mailparse::{MailHeaderMap, ParsedMail};
+use scraper::{Html, HtmlTreeSink, Selector};
+use serde::{Deserialize, Serialize};
 use tendril::TendrilSink;
 use xml::EventReader;
 use xml::reader::XmlEvent;
 
-use crate::Message;
-use crate::lexer::Lexer;
+use crate::analyzer::{Analyzer, Language};
+use crate::code::is_code;
+use crate::get_extensions_map;
+use crate::latex::latex_to_text;
+use crate::logger::Logger;
+#[cfg(feature = "ocr")]
+use crate::ocr;
+use crate::org::org_to_text;
+use crate::privacy::log_path;
+use crate::profile::STREAM_CHUNK_BYTES;
+use crate::rtf::{is_rtf, rtf_to_text};
+use crate::sniff::{Sniffing, sniff_type};
+use crate::tar::{ArchiveContent, for_each_entry, open_archive};
+use crate::tree::TermPositions;
 
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs::{self, File};
-use std::io::BufReader;
-use std::path::Path;
-use std::sync::{Arc, RwLock, mpsc};
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 
-/// Parses a CSV document, extracts text content from all fields, tokenizes it,
-/// and removes stop words.
+/// The number of recoverable errors reported per document; further ones are
+/// only counted.
+const MAX_REPORTED_ERRORS: usize = 10;
+
+/// The read buffer of the streaming parsers, large enough to keep the number
+/// of round trips low on network mounts.
+const READ_BUFFER_SIZE: usize = 256 * 1024;
+
+/// The size past which plain text documents are analyzed in chunks whatever
+/// the profile of the index, so that large logs and text dumps are indexed
+/// without holding copies of their whole text.
+pub(crate) const STREAM_THRESHOLD_BYTES: u64 = 16 * 1024 * 1024;
+
+thread_local! {
+    /// The content of the document parsed on this thread, already read by
+    /// the indexing run to hash it, see `with_content`.
+    static PRELOADED: RefCell<Option<(PathBuf, Vec<u8>)>> = const { RefCell::new(None) };
+}
+
+/// Runs `parse` with the content of the document at `path` already read, so
+/// that its parser takes it instead of reading the file again.
 ///
 /// # Arguments
-/// * `filepath` - The path to the CSV file.
-/// * `err_handler` - A sender for logging messages.
-/// * `stop_words` - A slice of stop words to filter out.
+/// * `path` - The document about to be parsed.
+/// * `content` - The content of the document.
+/// * `parse` - Parses the document.
 ///
 /// # Returns
-/// A `Result` containing a `Vec<String>` of processed tokens on success, or an
-/// `anyhow::Error` on failure.
-pub fn parse_csv_document(
-    filepath: &Path,
-    err_handler: Arc<RwLock<mpsc::Sender<Message>>>,
-    stop_words: &[String],
-) -> Vec<String> {
-    {
-        let _ = err_handler
-            .read()
-            .unwrap()
-            .send(Message::Info(format!("Indexing document: {filepath:?}")));
+/// What `parse` returns.
+pub(crate) fn with_content<T>(path: &Path, content: Vec<u8>, parse: impl FnOnce() -> T) -> T {
+    PRELOADED.with_borrow_mut(|preloaded| *preloaded = Some((path.to_path_buf(), content)));
+    let parsed = parse();
+    PRELOADED.with_borrow_mut(|preloaded| *preloaded = None);
+    parsed
+}
+
+/// Takes the content of the document at `path` if it was already read, see
+/// `with_content`.
+fn take_preloaded(path: &Path) -> Option<Vec<u8>> {
+    PRELOADED.with_borrow_mut(|preloaded| match preloaded {
+        Some((preloaded_path, _)) if preloaded_path == path => {
+            preloaded.take().map(|(_, content)| content)
+        }
+        _ => None,
+    })
+}
+
+/// Reads the whole content of the document at `path`, unless it was
+/// already read.
+fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    match take_preloaded(path) {
+        Some(content) => Ok(content),
+        None => fs::read(path),
+    }
+}
+
+/// The content of a document read by a streaming parser: the content
+/// already read, or the file.
+enum DocumentReader {
+    Preloaded(Cursor<Vec<u8>>),
+    File(File),
+}
+
+impl DocumentReader {
+    /// Opens the document at `path`, unless it was already read.
+    fn open(path: &Path) -> io::Result<Self> {
+        match take_preloaded(path) {
+            Some(content) => Ok(Self::Preloaded(Cursor::new(content))),
+            None => File::open(path).map(Self::File),
+        }
+    }
+}
+
+impl Read for DocumentReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Preloaded(content) => content.read(buf),
+            Self::File(file) => file.read(buf),
+        }
     }
+}
+
+impl Seek for DocumentReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::Preloaded(content) => content.seek(pos),
+            Self::File(file) => file.seek(pos),
+        }
+    }
+}
+
+/// How badly an error affected the parsing of a document.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum ParseErrorClass {
+    /// Part of the document was lost (e.g. a bad CSV row or a malformed XML
+    /// node), the rest was still indexed.
+    Recoverable,
+    /// Nothing could be extracted from the document.
+    Fatal,
+}
 
-    let f = match File::open(filepath).context("open filepath") {
-        Ok(f) => f,
+/// An error met while parsing a document.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ParseError {
+    /// How badly the error affected the document.
+    pub class: ParseErrorClass,
+    /// The error message.
+    pub message: String,
+}
+
+/// The result of parsing a document: the terms that could be extracted and
+/// the errors met on the way.
+#[derive(Default)]
+pub struct ParseOutcome {
+    /// The extracted terms with their positions.
+    pub terms: TermPositions,
+    /// The errors met while parsing.
+    pub errors: Vec<ParseError>,
+    /// The number of recoverable errors not kept in `errors`.
+    pub suppressed: usize,
+    /// The title of the document, shown in results instead of its path:
+    /// the PDF `Title`, the HTML `<title>` or the first Markdown heading.
+    pub title: Option<String>,
+    /// The headings of the document besides its title: the HTML `<h1>` to
+    /// `<h6>`, the Markdown `#` lines, the XML `<title>` of sections or the
+    /// PDF outline.
+    pub headings: Vec<String>,
+    /// The detected language of the document, whose stemmer and stop words
+    /// analyzed it, if the index detects languages.
+    pub language: Option<Language>,
+    /// The original casing of the words of the document, keyed by their
+    /// normalized form, if the index preserves case.
+    pub display_forms: HashMap<String, String>,
+}
+
+impl ParseOutcome {
+    /// Creates the outcome of a document nothing could be extracted from.
+    pub fn fatal(message: impl Into<String>) -> Self {
+        Self {
+            errors: vec![ParseError {
+                class: ParseErrorClass::Fatal,
+                message: message.into(),
+            }],
+            ..Default::default()
+        }
+    }
+
+    /// Records an error after which parsing went on.
+    pub fn recoverable(&mut self, message: impl Into<String>) {
+        if self.errors.len() >= MAX_REPORTED_ERRORS {
+            self.suppressed += 1;
+            return;
+        }
+        self.errors.push(ParseError {
+            class: ParseErrorClass::Recoverable,
+            message: message.into(),
+        });
+    }
+
+    /// Returns the worst class of the errors met, or `None` if the document
+    /// parsed cleanly.
+    pub fn class(&self) -> Option<ParseErrorClass> {
+        self.errors.iter().map(|e| e.class).max()
+    }
+
+    /// Logs the errors.
+    fn log(&self, filepath: &Path, logger: &Logger) {
+        for error in &self.errors {
+            logger.error(format!(
+                "{}: {:?} parse error: {}",
+                log_path(filepath),
+                error.class,
+                error.message
+            ));
+        }
+        if self.suppressed > 0 {
+            logger.error(format!(
+                "{}: {} more recoverable parse errors",
+                log_path(filepath),
+                self.suppressed
+            ));
+        }
+    }
+}
+
+/// Reads a text file, replacing invalid UTF-8 sequences instead of failing.
+///
+/// # Arguments
+/// * `filepath` - The path to the file.
+/// * `outcome` - The `ParseOutcome` recording the replacement, if any.
+///
+/// # Returns
+/// The file content, or an error if the file cannot be read.
+fn read_text_lossy(filepath: &Path, outcome: &mut ParseOutcome) -> anyhow::Result<String> {
+    let bytes = read_file(filepath).context("read file")?;
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok(text),
         Err(err) => {
-            let _ = err_handler
-                .read()
-                .unwrap()
-                .send(Message::Error(format!("{err}")));
-            return Vec::new();
+            outcome.recoverable("invalid UTF-8 replaced");
+            Ok(String::from_utf8_lossy(err.as_bytes()).into_owned())
         }
-    };
-    let reader = BufReader::new(f);
+    }
+}
+
+/// Reads the text of all fields of a CSV document, separated by spaces.
+/// Malformed rows are skipped and recorded as recoverable errors.
+///
+/// # Arguments
+/// * `filepath` - The path to the CSV file.
+/// * `outcome` - The `ParseOutcome` recording the errors met.
+///
+/// # Returns
+/// The extracted text, or an error if the file cannot be read.
+fn csv_text(filepath: &Path, outcome: &mut ParseOutcome) -> anyhow::Result<String> {
+    let f = DocumentReader::open(filepath).context("open filepath")?;
+    let reader = BufReader::with_capacity(READ_BUFFER_SIZE, f);
     let mut rdr = csv::Reader::from_reader(reader);
 
     let mut fields = String::new();
-
     for record in rdr.records() {
         // The iterator yields Result<StringRecord, Error>
         let record = match record {
             Ok(r) => r,
-            Err(_) => continue,
+            Err(err) => {
+                outcome.recoverable(format!("bad row: {err}"));
+                continue;
+            }
         };
         for field in record.iter() {
             fields.push_str(field);
+            fields.push(' ');
         }
     }
+    Ok(fields)
+}
+
+/// The elements whose content is never shown as text by browsers: scripts,
+/// style sheets, the fallbacks of scripts and templates.
+const HIDDEN_ELEMENTS: [&str; 4] = ["script", "style", "noscript", "template"];
 
-    let fields_chars = fields.to_lowercase().chars().collect::<Vec<char>>();
-    let mut lex = Lexer::new(&fields_chars);
-    let tokens = lex.get_tokens(stop_words);
-    tokens
+/// Collects the text nodes of an HTML document, separated by spaces, leaving
+/// out the markup, comments and the content of `HIDDEN_ELEMENTS`.
+///
+/// # Arguments
+/// * `html` - The parsed document.
+///
+/// # Returns
+/// The visible text of the document.
+pub(crate) fn visible_text(html: &Html) -> String {
+    let mut text = String::new();
+    for node in html.root_element().descendants() {
+        let Some(chunk) = node.value().as_text() else {
+            continue;
+        };
+        let hidden = node.ancestors().any(|ancestor| {
+            ancestor
+                .value()
+                .as_element()
+                .is_some_and(|element| HIDDEN_ELEMENTS.contains(&element.name()))
+        });
+        if !hidden && !chunk.trim().is_empty() {
+            text.push_str(chunk);
+            text.push(' ');
+        }
+    }
+    text
 }
 
-/// Parses an HTML document, extracts all visible text content, tokenizes it,
-/// and removes stop words.
+/// Reads the visible text of an HTML document, with its `<title>`, its
+/// `<h1>` to `<h6>` headings and its meta description, indexed along with
+/// the headings.
 ///
 /// # Arguments
 /// * `filepath` - The path to the HTML file.
-/// * `err_handler` - A sender for logging messages.
-/// * `stop_words` - A slice of stop words to filter out.
+/// * `outcome` - The `ParseOutcome` recording the title, the headings and
+///   the errors met.
 ///
 /// # Returns
-/// A `Result` containing a `Vec<String>` of processed tokens on success, or an
-/// `anyhow::Error` on failure.
-pub fn parse_html_document(
-    filepath: &Path,
-    err_handler: Arc<RwLock<mpsc::Sender<Message>>>,
-    stop_words: &[String],
-) -> Vec<String> {
-    {
-        let _ = err_handler
-            .read()
-            .unwrap()
-            .send(Message::Info(format!("Indexing document: {filepath:?}")));
-    }
-    let document = match fs::read_to_string(filepath) {
-        Ok(c) => c,
-        Err(err) => {
-            let _ = err_handler
-                .read()
-                .unwrap()
-                .send(Message::Error(format!("{err}")));
-            return Vec::new();
-        }
-    };
+/// The extracted text, or an error if the file cannot be read.
+fn html_text(filepath: &Path, outcome: &mut ParseOutcome) -> anyhow::Result<String> {
+    let document = read_text_lossy(filepath, outcome)?;
     let parser = driver::parse_document(
         HtmlTreeSink::new(Html::new_document()),
         ParseOpts::default(),
     );
     let html = parser.one(document);
-    let text = html.html();
+    if let Ok(selector) = Selector::parse("title") {
+        outcome.title = html
+            .select(&selector)
+            .next()
+            .map(|title| title.text().collect::<String>())
+            .and_then(|title| clean_title(&title));
+    }
+    if let Ok(selector) = Selector::parse("h1, h2, h3, h4, h5, h6") {
+        outcome.headings = html
+            .select(&selector)
+            .filter_map(|heading| clean_title(&heading.text().collect::<String>()))
+            .collect();
+    }
+    if let Ok(selector) = Selector::parse("meta[name][content]") {
+        let description = html
+            .select(&selector)
+            .find(|meta| {
+                meta.value()
+                    .attr("name")
+                    .is_some_and(|name| name.eq_ignore_ascii_case("description"))
+            })
+            .and_then(|meta| meta.value().attr("content"))
+            .and_then(clean_title);
+        outcome.headings.extend(description);
+    }
+    Ok(visible_text(&html).trim().to_string())
+}
 
-    let text_chars = text.trim().to_lowercase().chars().collect::<Vec<char>>();
-    let mut lex = Lexer::new(&text_chars);
-    let tokens = lex.get_tokens(stop_words);
-    tokens
+/// Collapses the whitespace of a title.
+///
+/// # Returns
+/// The title, or `None` if it is blank.
+fn clean_title(title: &str) -> Option<String> {
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!title.is_empty()).then_some(title)
 }
 
-/// Parses an XML document, extracts all character data (text content),
-/// tokenizes it, and removes stop words.
+/// Returns the texts of the headings of a Markdown document, `#` lines in
+/// fenced code blocks aside.
+fn markdown_headings(text: &str) -> Vec<String> {
+    let mut headings = Vec::new();
+    let mut in_fence = false;
+    for line in text.lines() {
+        let line = line.trim_start();
+        if line.starts_with("```") || line.starts_with("~~~") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        let heading = line.trim_start_matches('#');
+        let level = line.len() - heading.len();
+        if (1..=6).contains(&level)
+            && (heading.is_empty() || heading.starts_with(' '))
+            && let Some(heading) = clean_title(heading.trim_end_matches('#'))
+        {
+            headings.push(heading);
+        }
+    }
+    headings
+}
+
+/// Records the headings of Markdown text, the first one of the document
+/// becoming its title.
+fn add_markdown_headings(text: &str, outcome: &mut ParseOutcome) {
+    let mut headings = markdown_headings(text).into_iter();
+    if outcome.title.is_none() {
+        outcome.title = headings.next();
+    }
+    outcome.headings.extend(headings);
+}
+
+/// Reads the text of a Markdown document, taking its first heading as its
+/// title and the others as its headings.
 ///
 /// # Arguments
-/// * `filepath` - The path to the XML file.
-/// * `err_handler` - A sender for logging messages.
-/// * `stop_words` - A slice of stop words to filter out.
+/// * `filepath` - The path to the Markdown file.
+/// * `outcome` - The `ParseOutcome` recording the errors met.
 ///
 /// # Returns
-/// A `Result` containing a `Vec<String>` of processed tokens on success, or an
-/// `anyhow::Error` on failure.
-pub fn parse_xml_document(
-    filepath: &Path,
-    err_handler: Arc<RwLock<mpsc::Sender<Message>>>,
-    stop_words: &[String],
-) -> Vec<String> {
-    {
-        let _ = err_handler
-            .read()
-            .unwrap()
-            .send(Message::Info(format!("Indexing document: {filepath:?}")));
+/// The text of the document, or an error if the file cannot be read.
+fn markdown_text(filepath: &Path, outcome: &mut ParseOutcome) -> anyhow::Result<String> {
+    let text = read_text_lossy(filepath, outcome)?;
+    add_markdown_headings(&text, outcome);
+    Ok(text)
+}
+
+/// Reads the prose of a LaTeX document, taking its `\title` as its title.
+///
+/// # Arguments
+/// * `filepath` - The path to the LaTeX file.
+/// * `outcome` - The `ParseOutcome` recording the title and the errors met.
+///
+/// # Returns
+/// The extracted text, or an error if the file cannot be read.
+fn latex_text(filepath: &Path, outcome: &mut ParseOutcome) -> anyhow::Result<String> {
+    let source = read_text_lossy(filepath, outcome)?;
+    let document = latex_to_text(&source);
+    outcome.title = document.title.as_deref().and_then(clean_title);
+    Ok(document.text)
+}
+
+/// Reads the prose of an Org-mode document, taking its `#+TITLE:` as its
+/// title.
+///
+/// # Arguments
+/// * `filepath` - The path to the Org file.
+/// * `outcome` - The `ParseOutcome` recording the title and the errors met.
+///
+/// # Returns
+/// The extracted text, or an error if the file cannot be read.
+fn org_text(filepath: &Path, outcome: &mut ParseOutcome) -> anyhow::Result<String> {
+    let source = read_text_lossy(filepath, outcome)?;
+    let document = org_to_text(&source);
+    outcome.title = document.title.as_deref().and_then(clean_title);
+    Ok(document.text)
+}
+
+/// Reads the dialogue of a SubRip (`.srt`) or WebVTT (`.vtt`) subtitle
+/// file. Cues are blocks of lines separated by blank lines, and only the
+/// lines after the timing line (`00:01:02,500 --> 00:01:04,000`) of a cue
+/// are dialogue: cue numbers and identifiers, timings, and the header,
+/// `NOTE`, `STYLE` and `REGION` blocks of WebVTT files are left out. Markup
+/// such as `<i>`, `<v Speaker>` or `{\an8}` is stripped, and a line
+/// repeating the previous one, as in rolling captions, is indexed once.
+///
+/// # Arguments
+/// * `filepath` - The path to the subtitle file.
+/// * `outcome` - The `ParseOutcome` recording the errors met.
+///
+/// # Returns
+/// The dialogue, one line per line of a cue, or an error if the file cannot
+/// be read or has no cues.
+fn subtitle_text(filepath: &Path, outcome: &mut ParseOutcome) -> anyhow::Result<String> {
+    let content = read_text_lossy(filepath, outcome)?;
+    let mut text = String::new();
+    let mut cues = 0;
+    let mut previous = String::new();
+    // The blocks are separated by lines that are blank
+    let lines = content
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .collect::<Vec<_>>();
+    for block in lines.split(|line| line.trim().is_empty()) {
+        let Some(timing) = block.iter().position(|line| line.contains("-->")) else {
+            continue;
+        };
+        cues += 1;
+        for line in &block[timing + 1..] {
+            let line = strip_cue_markup(line);
+            if line.is_empty() || line == previous {
+                continue;
+            }
+            text.push_str(&line);
+            text.push('\n');
+            previous = line;
+        }
+    }
+    if cues == 0 {
+        return Err(anyhow::anyhow!("no subtitle cues"));
     }
+    Ok(text)
+}
 
-    let file = match File::open(filepath) {
-        Ok(f) => f,
-        Err(err) => {
-            let _ = err_handler
-                .read()
-                .unwrap()
-                .send(Message::Error(format!("{err}")));
-            return Vec::new();
+/// Strips the markup of a line of a subtitle cue: the `<...>` tags of
+/// SubRip and WebVTT, including the inline timestamps of WebVTT, and the
+/// `{...}` override codes of SubRip. The entities of WebVTT are decoded.
+fn strip_cue_markup(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut closing = None;
+    for c in line.chars() {
+        match (closing, c) {
+            (None, '<') => closing = Some('>'),
+            (None, '{') => closing = Some('}'),
+            (None, c) => stripped.push(c),
+            (Some(end), c) if c == end => closing = None,
+            (Some(_), _) => {}
         }
-    };
-    let file = BufReader::new(file);
+    }
+    stripped
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+/// The headers of an email whose values are indexed with its body.
+const EMAIL_HEADERS: [&str; 4] = ["Subject", "From", "To", "Cc"];
 
-    let parser = EventReader::new(file);
-    let mut tokens = Vec::new();
+/// Extracts the indexed text of an email: its main headers and its text
+/// parts. HTML parts are only used, stripped of their markup, when a part
+/// has no plain text alternative.
+///
+/// # Arguments
+/// * `raw` - The raw message, in RFC 5322 format.
+/// * `outcome` - The `ParseOutcome` recording the parts that failed to
+///   decode.
+///
+/// # Returns
+/// The extracted text, or an error if the message cannot be parsed.
+pub fn email_text(raw: &[u8], outcome: &mut ParseOutcome) -> anyhow::Result<String> {
+    let mail = mailparse::parse_mail(raw).context("parse email")?;
+    let mut text = String::new();
+    for header in EMAIL_HEADERS {
+        for value in mail.headers.get_all_values(header) {
+            text.push_str(&value);
+            text.push('\n');
+        }
+    }
+    mail_part_text(&mail, outcome, &mut text);
+    Ok(text)
+}
 
-    for e in parser {
+/// Appends the text of a MIME part and its subparts to `text`.
+fn mail_part_text(part: &ParsedMail, outcome: &mut ParseOutcome, text: &mut String) {
+    if !part.subparts.is_empty() {
+        let alternative = part.ctype.mimetype == "multipart/alternative";
+        let has_plain = part
+            .subparts
+            .iter()
+            .any(|p| p.ctype.mimetype == "text/plain");
+        for sub in &part.subparts {
+            if alternative && has_plain && sub.ctype.mimetype == "text/html" {
+                continue;
+            }
+            mail_part_text(sub, outcome, text);
+        }
+        return;
+    }
+
+    let is_html = part.ctype.mimetype == "text/html";
+    if part.ctype.mimetype != "text/plain" && !is_html {
+        return;
+    }
+    match part.get_body() {
+        Ok(body) if is_html => {
+            text.push_str(&visible_text(&Html::parse_document(&body)));
+        }
+        Ok(body) => text.push_str(&body),
+        Err(err) => outcome.recoverable(format!("{} part: {err}", part.ctype.mimetype)),
+    }
+    text.push('\n');
+}
+
+/// Reads the text of an email stored in a file, e.g. a `.eml` file.
+///
+/// # Arguments
+/// * `filepath` - The path to the email file.
+/// * `outcome` - The `ParseOutcome` recording the errors met.
+///
+/// # Returns
+/// The extracted text, or an error if the file cannot be read or parsed.
+fn eml_text(filepath: &Path, outcome: &mut ParseOutcome) -> anyhow::Result<String> {
+    let raw = read_file(filepath).context("read file")?;
+    email_text(&raw, outcome)
+}
+
+/// Splits an mbox archive into its raw messages. A message starts at a
+/// `From ` line at the start of the archive or after a blank line; the
+/// `>From ` lines escaping `From ` lines of message bodies are unescaped.
+/// Text before the first message is ignored.
+///
+/// # Arguments
+/// * `raw` - The content of the mbox archive.
+///
+/// # Returns
+/// The raw messages, in RFC 5322 format, in archive order.
+pub fn mbox_messages(raw: &[u8]) -> Vec<Vec<u8>> {
+    let mut messages = Vec::new();
+    let mut message: Option<Vec<u8>> = None;
+    let mut after_blank = true;
+    for line in raw.split_inclusive(|&b| b == b'\n') {
+        if after_blank && line.starts_with(b"From ") {
+            messages.extend(message.replace(Vec::new()));
+            after_blank = false;
+            continue;
+        }
+        after_blank = line == b"\n" || line == b"\r\n";
+        let Some(message) = message.as_mut() else {
+            continue;
+        };
+        let escaped = line
+            .iter()
+            .position(|&b| b != b'>')
+            .is_some_and(|start| start > 0 && line[start..].starts_with(b"From "));
+        message.extend_from_slice(if escaped { &line[1..] } else { line });
+    }
+    messages.extend(message);
+    messages
+}
+
+/// Reads the headers of the messages of an mbox archive. The messages
+/// themselves are indexed as documents embedded in the archive.
+///
+/// # Arguments
+/// * `filepath` - The path to the mbox file.
+/// * `outcome` - The `ParseOutcome` recording the messages whose headers
+///   cannot be parsed.
+///
+/// # Returns
+/// The extracted headers, or an error if the file cannot be read.
+fn mbox_text(filepath: &Path, outcome: &mut ParseOutcome) -> anyhow::Result<String> {
+    let raw = read_file(filepath).context("read file")?;
+    let mut text = String::new();
+    for (i, message) in mbox_messages(&raw).iter().enumerate() {
+        match mailparse::parse_headers(message) {
+            Ok((headers, _)) => {
+                for header in EMAIL_HEADERS {
+                    for value in headers.get_all_values(header) {
+                        text.push_str(&value);
+                        text.push('\n');
+                    }
+                }
+            }
+            Err(err) => outcome.recoverable(format!("message {}: {err}", i + 1)),
+        }
+    }
+    Ok(text)
+}
+
+/// Reads the character data of an XML document. The text read before a
+/// malformed node is kept. The first `<title>` element, as in XHTML, RSS or
+/// DocBook, is the title of the document, the others and the `<h1>` to
+/// `<h6>` elements its headings.
+///
+/// # Arguments
+/// * `filepath` - The path to the XML file.
+/// * `outcome` - The `ParseOutcome` recording the title, the headings and
+///   the errors met.
+///
+/// # Returns
+/// The extracted text, or an error if the file cannot be read or is
+/// malformed before any text.
+fn xml_text(filepath: &Path, outcome: &mut ParseOutcome) -> anyhow::Result<String> {
+    let file = DocumentReader::open(filepath)?;
+    xml_reader_text(BufReader::with_capacity(READ_BUFFER_SIZE, file), outcome)
+}
+
+/// Reads the character data of the XML document read from `reader`, like
+/// `xml_text`.
+fn xml_reader_text(reader: impl Read, outcome: &mut ParseOutcome) -> anyhow::Result<String> {
+    let mut text = String::new();
+    // The name and text of the title or heading element being read
+    let mut heading: Option<(String, String)> = None;
+    for e in EventReader::new(reader) {
         match e {
-            Ok(XmlEvent::Characters(text)) => {
-                let text_chars = text.to_lowercase().chars().collect::<Vec<char>>();
-                let mut lex = Lexer::new(&text_chars);
-                tokens.append(&mut lex.get_tokens(stop_words));
+            Ok(XmlEvent::StartElement { name, .. })
+                if heading.is_none() && is_heading_element(&name.local_name) =>
+            {
+                heading = Some((name.local_name, String::new()));
+            }
+            Ok(XmlEvent::EndElement { name }) => {
+                let Some((element, heading_text)) =
+                    heading.take_if(|(element, _)| *element == name.local_name)
+                else {
+                    continue;
+                };
+                let Some(heading_text) = clean_title(&heading_text) else {
+                    continue;
+                };
+                if element == "title" && outcome.title.is_none() {
+                    outcome.title = Some(heading_text);
+                } else {
+                    outcome.headings.push(heading_text);
+                }
+            }
+            Ok(XmlEvent::Characters(chars)) => {
+                if let Some((_, heading_text)) = &mut heading {
+                    heading_text.push_str(&chars);
+                    heading_text.push(' ');
+                }
+                text.push_str(&chars);
+                text.push(' ');
             }
             Err(err) => {
-                let _ = err_handler
-                    .read()
-                    .unwrap()
-                    .send(Message::Error(format!("{err}")));
+                if text.trim().is_empty() {
+                    return Err(err.into());
+                }
+                outcome.recoverable(format!("malformed node: {err}"));
                 continue;
             }
             _ => {}
         }
     }
-    tokens
+    Ok(text)
 }
 
-/// Parses a PDF document, extracts text from all pages, tokenizes it,
-/// and removes stop words.
+/// Returns `true` if the XML elements named `name` hold a title or a
+/// heading.
+fn is_heading_element(name: &str) -> bool {
+    matches!(name, "title" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6")
+}
+
+/// Reads the plain text of an RTF document, with its title from the
+/// document properties. The text read from a document whose groups are not
+/// balanced is kept.
 ///
 /// # Arguments
-/// * `filepath` - The path to the PDF file.
-/// * `err_handler` - A sender for logging messages.
-/// * `stop_words` - A slice of stop words to filter out.
+/// * `filepath` - The path to the RTF file.
+/// * `outcome` - The `ParseOutcome` recording the title and the errors met.
 ///
 /// # Returns
-/// A `Result` containing a `Vec<String>` of processed tokens on success, or an
-///  `anyhow::Error` on failure.
-pub fn parse_pdf_document(
-    filepath: &Path,
-    err_handler: Arc<RwLock<mpsc::Sender<Message>>>,
-    stop_words: &[String],
-) -> Vec<String> {
-    {
-        let _ = err_handler
-            .read()
-            .unwrap()
-            .send(Message::Info(format!("Indexing document: {filepath:?}")));
+/// The extracted text, or an error if the file cannot be read or is not
+/// an RTF document.
+fn rtf_text(filepath: &Path, outcome: &mut ParseOutcome) -> anyhow::Result<String> {
+    let raw = read_file(filepath).context("read file")?;
+    if !is_rtf(&raw) {
+        return Err(anyhow::anyhow!("not an RTF document"));
+    }
+    let document = rtf_to_text(&raw);
+    if document.unbalanced {
+        outcome.recoverable("unbalanced groups");
     }
+    outcome.title = document.title;
+    Ok(document.text)
+}
 
-    let mut tokens = Vec::new();
-    let doc = match lopdf::Document::load(filepath) {
-        Ok(doc) => doc,
-        Err(err) => {
-            let _ = err_handler
-                .read()
-                .unwrap()
-                .send(Message::Error(format!("{err}")));
-            return Vec::new();
+/// Reads the text of the body of a docx document, held in the
+/// `word/document.xml` entry of the archive.
+///
+/// # Arguments
+/// * `filepath` - The path to the docx file.
+/// * `outcome` - The `ParseOutcome` recording the errors met.
+///
+/// # Returns
+/// The extracted text, or an error if the document cannot be read.
+fn docx_text(filepath: &Path, outcome: &mut ParseOutcome) -> anyhow::Result<String> {
+    let file = DocumentReader::open(filepath).context("open file")?;
+    let mut archive = zip::ZipArchive::new(file).context("read docx archive")?;
+    let body = archive
+        .by_name("word/document.xml")
+        .context("read docx body")?;
+    xml_reader_text(BufReader::new(body), outcome)
+}
+
+/// Reads the text of the slides of a pptx presentation in slide order, each
+/// followed by its speaker notes. Slides are held in the
+/// `ppt/slides/slideN.xml` entries of the archive and their notes in
+/// `ppt/notesSlides/notesSlideN.xml`. Slides that cannot be read are
+/// skipped and recorded as recoverable errors.
+///
+/// # Arguments
+/// * `filepath` - The path to the pptx file.
+/// * `outcome` - The `ParseOutcome` recording the errors met.
+///
+/// # Returns
+/// The extracted text, or an error if the archive cannot be read.
+fn pptx_text(filepath: &Path, outcome: &mut ParseOutcome) -> anyhow::Result<String> {
+    let file = DocumentReader::open(filepath).context("open file")?;
+    let mut archive = zip::ZipArchive::new(file).context("read pptx archive")?;
+    let mut slides = archive
+        .file_names()
+        .filter_map(|name| {
+            name.strip_prefix("ppt/slides/slide")?
+                .strip_suffix(".xml")?
+                .parse::<u32>()
+                .ok()
+        })
+        .collect::<Vec<u32>>();
+    slides.sort_unstable();
+
+    let mut text = String::new();
+    for slide in slides {
+        let parts = [
+            format!("ppt/slides/slide{slide}.xml"),
+            format!("ppt/notesSlides/notesSlide{slide}.xml"),
+        ];
+        for name in parts {
+            let part = match archive.by_name(&name) {
+                Ok(part) => part,
+                // Slides without speaker notes
+                Err(zip::result::ZipError::FileNotFound) => continue,
+                Err(err) => {
+                    outcome.recoverable(format!("{name}: {err}"));
+                    continue;
+                }
+            };
+            match xml_reader_text(BufReader::new(part), outcome) {
+                Ok(part_text) => {
+                    text.push_str(&part_text);
+                    text.push('\n');
+                }
+                Err(err) => outcome.recoverable(format!("{name}: {err}")),
+            }
         }
-    };
+    }
+    Ok(text)
+}
+
+/// Reads the names of the entries of a zip archive, the text a zip is found
+/// by; the entries themselves are indexed as embedded documents.
+///
+/// # Arguments
+/// * `filepath` - The path to the zip file.
+/// * `_outcome` - The `ParseOutcome` recording the errors met.
+///
+/// # Returns
+/// The entry names, or an error if the archive cannot be read.
+fn zip_text(filepath: &Path, _outcome: &mut ParseOutcome) -> anyhow::Result<String> {
+    let file = DocumentReader::open(filepath).context("open file")?;
+    let archive = zip::ZipArchive::new(file).context("read zip archive")?;
+    Ok(archive.file_names().collect::<Vec<&str>>().join("\n"))
+}
+
+/// Reads the names of the files of a tar archive, compressed or not, or of
+/// the file compressed in a gzip file; the files themselves are indexed as
+/// embedded documents.
+///
+/// # Arguments
+/// * `filepath` - The path to the archive.
+/// * `_outcome` - The `ParseOutcome` recording the errors met.
+///
+/// # Returns
+/// The file names, or an error if the archive cannot be read.
+fn archive_text(filepath: &Path, _outcome: &mut ParseOutcome) -> anyhow::Result<String> {
+    match open_archive(filepath)? {
+        ArchiveContent::Tar(reader) => {
+            let mut names = Vec::new();
+            for_each_entry(reader, |name, _, _| {
+                names.push(name.to_string());
+                Ok(())
+            })?;
+            Ok(names.join("\n"))
+        }
+        ArchiveContent::File { name, .. } => Ok(name),
+    }
+}
+
+/// Reads the text of all pages of a PDF document. Pages whose text cannot
+/// be extracted are skipped and recorded as recoverable errors. With the
+/// `ocr` feature, the text of pages without a text layer is recognized.
+///
+/// # Arguments
+/// * `filepath` - The path to the PDF file.
+/// * `outcome` - The `ParseOutcome` recording the errors met.
+///
+/// # Returns
+/// The extracted text, or an error if the document cannot be loaded.
+fn pdf_text(filepath: &Path, outcome: &mut ParseOutcome) -> anyhow::Result<String> {
+    let doc = lopdf::Document::load_mem(&read_file(filepath).context("read file")?)?;
+    outcome.title = pdf_title(&doc);
+    // The outline (bookmarks) lists the sections of the document
+    if let Ok(toc) = doc.get_toc() {
+        outcome.headings = toc
+            .toc
+            .iter()
+            .filter_map(|entry| clean_title(&entry.title))
+            .collect();
+    }
 
+    let mut text = String::new();
     for (page_num, _) in doc.get_pages() {
-        if let Ok(text) = doc.extract_text(&[page_num]) {
-            let text_chars = text.to_lowercase().chars().collect::<Vec<char>>();
-            let mut lexer = Lexer::new(&text_chars);
-            tokens.append(&mut lexer.get_tokens(stop_words));
+        let page = doc.extract_text(&[page_num]).map_err(anyhow::Error::from);
+        #[cfg(feature = "ocr")]
+        let page = match page {
+            Ok(page) if !ocr::needs_ocr(&page) => Ok(page),
+            // Scanned pages have no text layer, at most a stamped page number
+            extracted => match ocr::pdf_page_text(filepath, page_num) {
+                Ok(recognized) => Ok(recognized),
+                Err(err) => {
+                    outcome.recoverable(format!("page {page_num}: {err}"));
+                    extracted
+                }
+            },
+        };
+        match page {
+            Ok(page) => {
+                text.push_str(&page);
+                text.push('\n');
+            }
+            Err(err) => outcome.recoverable(format!("page {page_num}: {err}")),
         }
     }
+    Ok(text)
+}
 
-    tokens
+/// Returns the `Title` entry of the information dictionary of a PDF
+/// document, decoded from UTF-16 or, lacking a byte order mark, from its
+/// single byte encoding.
+fn pdf_title(doc: &lopdf::Document) -> Option<String> {
+    let info = match doc.trailer.get(b"Info").ok()? {
+        lopdf::Object::Reference(id) => doc.get_object(*id).ok()?,
+        info => info,
+    };
+    let lopdf::Object::String(bytes, _) = info.as_dict().ok()?.get(b"Title").ok()? else {
+        return None;
+    };
+    let title = match bytes.strip_prefix(&[0xfe, 0xff]) {
+        Some(utf16) => String::from_utf16_lossy(
+            &utf16
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect::<Vec<_>>(),
+        ),
+        None => bytes.iter().map(|&b| b as char).collect(),
+    };
+    clean_title(&title)
 }
 
-/// Parses a plain text document, reads its content, tokenizes it,
+/// Recognizes the text of an image with OCR.
+///
+/// # Arguments
+/// * `filepath` - The path to the image file.
+/// * `_outcome` - The `ParseOutcome` recording the errors met.
+///
+/// # Returns
+/// The recognized text, or an error if the OCR tool fails.
+#[cfg(feature = "ocr")]
+fn image_text(filepath: &Path, _outcome: &mut ParseOutcome) -> anyhow::Result<String> {
+    ocr::image_text(filepath)
+}
+
+/// Extracts the text of a document the way it is read for indexing, e.g. to
+/// highlight matches in it. Documents whose extension does not tell their
+/// type are typed by their content.
+///
+/// # Arguments
+/// * `filepath` - The path to the document.
+///
+/// # Returns
+/// The extracted text, or an error if the document cannot be read or its
+/// type is not supported.
+pub fn extract_text(filepath: &Path) -> anyhow::Result<String> {
+    let ext = filepath
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if !get_extensions_map().contains_key(&ext)
+        && let Some(sniffed) = sniff_type(filepath, Sniffing::All)
+    {
+        return extract_text_as(filepath, sniffed);
+    }
+    extract_text_as(filepath, &ext)
+}
+
+/// Extracts the text of a document with the parser of another extension,
+/// e.g. the one its content was sniffed as.
+///
+/// # Arguments
+/// * `filepath` - The path to the document.
+/// * `ext` - The extension picking the parser.
+///
+/// # Returns
+/// The extracted text, or an error if the document cannot be read or the
+/// extension is not supported.
+pub fn extract_text_as(filepath: &Path, ext: &str) -> anyhow::Result<String> {
+    let mut outcome = ParseOutcome::default();
+    match ext {
+        "csv" => csv_text(filepath, &mut outcome),
+        "html" => html_text(filepath, &mut outcome),
+        "pdf" => pdf_text(filepath, &mut outcome),
+        "xml" | "xhtml" => xml_text(filepath, &mut outcome),
+        "txt" => read_text_lossy(filepath, &mut outcome),
+        "md" => markdown_text(filepath, &mut outcome),
+        "eml" => eml_text(filepath, &mut outcome),
+        "mbox" => mbox_text(filepath, &mut outcome),
+        "rtf" => rtf_text(filepath, &mut outcome),
+        "srt" | "vtt" => subtitle_text(filepath, &mut outcome),
+        "tex" => latex_text(filepath, &mut outcome),
+        "org" => org_text(filepath, &mut outcome),
+        "docx" => docx_text(filepath, &mut outcome),
+        "pptx" => pptx_text(filepath, &mut outcome),
+        "zip" => zip_text(filepath, &mut outcome),
+        "tar" | "tgz" | "gz" => archive_text(filepath, &mut outcome),
+        #[cfg(feature = "ocr")]
+        ext if ocr::IMAGE_EXTENSIONS.contains(&ext) => image_text(filepath, &mut outcome),
+        ext if is_code(ext) => read_text_lossy(filepath, &mut outcome),
+        _ => Err(anyhow::anyhow!("unsupported extension {ext:?}")),
+    }
+}
+
+/// Extracts the text of a document with `read_text`, then tokenizes it and
+/// removes stop words, in the language of the document if the index detects
+/// languages. The words of its title and headings are also added as terms
+/// of the title and heading fields.
+///
+/// # Arguments
+/// * `filepath` - The path to the document.
+/// * `logger` - The log the parser reports to.
+/// * `analyzer` - The `Analyzer` turning the extracted text into terms.
+/// * `read_text` - The text extractor of the document format.
+///
+/// # Returns
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+fn parse_document(
+    filepath: &Path,
+    logger: &Logger,
+    analyzer: &Analyzer,
+    read_text: fn(&Path, &mut ParseOutcome) -> anyhow::Result<String>,
+) -> ParseOutcome {
+    analyze_document(filepath, logger, read_text, |text, outcome| {
+        outcome.language = analyzer.detect_language(text);
+        let language_analyzer = outcome
+            .language
+            .map(|language| analyzer.in_language(language));
+        let analyzer = language_analyzer.as_ref().unwrap_or(analyzer);
+        analyzer.record_display_forms(text, &mut outcome.display_forms);
+        let mut tokens = analyzer.analyze(text);
+        tokens.extend(analyzer.structure_terms(outcome.title.as_deref(), &outcome.headings));
+        tokens
+    })
+}
+
+/// Extracts the text of a document with `read_text` and turns it into terms
+/// with `analyze`.
+///
+/// # Arguments
+/// * `filepath` - The path to the document.
+/// * `logger` - The log the parser reports to.
+/// * `read_text` - The text extractor of the document format.
+/// * `analyze` - Turns the extracted text into terms, recording what it
+///   finds about the document in the outcome.
+///
+/// # Returns
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+fn analyze_document(
+    filepath: &Path,
+    logger: &Logger,
+    read_text: fn(&Path, &mut ParseOutcome) -> anyhow::Result<String>,
+    analyze: impl Fn(&str, &mut ParseOutcome) -> Vec<String>,
+) -> ParseOutcome {
+    {
+        logger.info(format!("Indexing document: {}", log_path(filepath)));
+    }
+
+    let mut outcome = ParseOutcome::default();
+    match read_text(filepath, &mut outcome) {
+        Ok(text) => outcome.terms = analyze(&text, &mut outcome).into_iter().collect(),
+        Err(err) => outcome = ParseOutcome::fatal(format!("{err:#}")),
+    }
+    outcome.log(filepath, logger);
+    outcome
+}
+
+/// Parses a CSV document, extracts text content from all fields, tokenizes it,
+/// and removes stop words. Malformed rows are skipped.
+///
+/// # Arguments
+/// * `filepath` - The path to the CSV file.
+/// * `logger` - The log the parser reports to.
+/// * `analyzer` - The `Analyzer` turning the extracted text into terms.
+///
+/// # Returns
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+pub fn parse_csv_document(filepath: &Path, logger: &Logger, analyzer: &Analyzer) -> ParseOutcome {
+    parse_document(filepath, logger, analyzer, csv_text)
+}
+
+/// Parses an HTML document, extracts all visible text content, tokenizes it,
 /// and removes stop words.
 ///
 /// # Arguments
+/// * `filepath` - The path to the HTML file.
+/// * `logger` - The log the parser reports to.
+/// * `analyzer` - The `Analyzer` turning the extracted text into terms.
+///
+/// # Returns
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+pub fn parse_html_document(filepath: &Path, logger: &Logger, analyzer: &Analyzer) -> ParseOutcome {
+    parse_document(filepath, logger, analyzer, html_text)
+}
+
+/// Parses an XML document, extracts all character data (text content),
+/// tokenizes it, and removes stop words. The text read before a malformed
+/// node is kept.
+///
+/// # Arguments
+/// * `filepath` - The path to the XML file.
+/// * `logger` - The log the parser reports to.
+/// * `analyzer` - The `Analyzer` turning the extracted text into terms.
+///
+/// # Returns
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+pub fn parse_xml_document(filepath: &Path, logger: &Logger, analyzer: &Analyzer) -> ParseOutcome {
+    parse_document(filepath, logger, analyzer, xml_text)
+}
+
+/// Parses a PDF document, extracts text from all pages, tokenizes it,
+/// and removes stop words. Pages whose text cannot be extracted are skipped.
+///
+/// # Arguments
+/// * `filepath` - The path to the PDF file.
+/// * `logger` - The log the parser reports to.
+/// * `analyzer` - The `Analyzer` turning the extracted text into terms.
+///
+/// # Returns
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+pub fn parse_pdf_document(filepath: &Path, logger: &Logger, analyzer: &Analyzer) -> ParseOutcome {
+    parse_document(filepath, logger, analyzer, pdf_text)
+}
+
+/// Parses an email file, extracts its main headers and text parts, tokenizes
+/// them, and removes stop words.
+///
+/// # Arguments
+/// * `filepath` - The path to the email file.
+/// * `logger` - The log the parser reports to.
+/// * `analyzer` - The `Analyzer` turning the extracted text into terms.
+///
+/// # Returns
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+pub fn parse_email_document(filepath: &Path, logger: &Logger, analyzer: &Analyzer) -> ParseOutcome {
+    parse_document(filepath, logger, analyzer, eml_text)
+}
+
+/// Parses an mbox archive, extracts the headers of its messages, tokenizes
+/// them, and removes stop words. The messages themselves are indexed as
+/// documents embedded in the archive.
+///
+/// # Arguments
+/// * `filepath` - The path to the mbox file.
+/// * `logger` - The log the parser reports to.
+/// * `analyzer` - The `Analyzer` turning the extracted text into terms.
+///
+/// # Returns
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+pub fn parse_mbox_document(filepath: &Path, logger: &Logger, analyzer: &Analyzer) -> ParseOutcome {
+    parse_document(filepath, logger, analyzer, mbox_text)
+}
+
+/// Parses an RTF document, strips its control words, tokenizes the plain
+/// text left, and removes stop words.
+///
+/// # Arguments
+/// * `filepath` - The path to the RTF file.
+/// * `logger` - The log the parser reports to.
+/// * `analyzer` - The `Analyzer` turning the extracted text into terms.
+///
+/// # Returns
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+pub fn parse_rtf_document(filepath: &Path, logger: &Logger, analyzer: &Analyzer) -> ParseOutcome {
+    parse_document(filepath, logger, analyzer, rtf_text)
+}
+
+/// Parses a docx document, extracts the text of its body, tokenizes it, and
+/// removes stop words.
+///
+/// # Arguments
+/// * `filepath` - The path to the docx file.
+/// * `logger` - The log the parser reports to.
+/// * `analyzer` - The `Analyzer` turning the extracted text into terms.
+///
+/// # Returns
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+pub fn parse_docx_document(filepath: &Path, logger: &Logger, analyzer: &Analyzer) -> ParseOutcome {
+    parse_document(filepath, logger, analyzer, docx_text)
+}
+
+/// Parses a pptx presentation, reading the text and speaker notes of its
+/// slides, tokenizing them and removing stop words.
+///
+/// # Arguments
+/// * `filepath` - The path to the pptx file.
+/// * `logger` - The log the parser reports to.
+/// * `analyzer` - The `Analyzer` turning the extracted text into terms.
+///
+/// # Returns
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+pub fn parse_pptx_document(filepath: &Path, logger: &Logger, analyzer: &Analyzer) -> ParseOutcome {
+    parse_document(filepath, logger, analyzer, pptx_text)
+}
+
+/// Parses a zip archive into the names of its entries. The supported
+/// entries are indexed as documents of their own.
+///
+/// # Arguments
+/// * `filepath` - The path to the zip file.
+/// * `logger` - The log the parser reports to.
+/// * `analyzer` - The `Analyzer` turning the entry names into terms.
+///
+/// # Returns
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+pub fn parse_zip_document(filepath: &Path, logger: &Logger, analyzer: &Analyzer) -> ParseOutcome {
+    parse_document(filepath, logger, analyzer, zip_text)
+}
+
+/// Parses a tar archive, compressed or not, or a gzip compressed file into
+/// the names of the files it holds. The supported files are indexed as
+/// documents of their own.
+///
+/// # Arguments
+/// * `filepath` - The path to the archive.
+/// * `logger` - The log the parser reports to.
+/// * `analyzer` - The `Analyzer` turning the file names into terms.
+///
+/// # Returns
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+pub fn parse_archive_document(
+    filepath: &Path,
+    logger: &Logger,
+    analyzer: &Analyzer,
+) -> ParseOutcome {
+    parse_document(filepath, logger, analyzer, archive_text)
+}
+
+/// Parses an image by recognizing its text with OCR, e.g. a scanned page or
+/// a screenshot.
+///
+/// # Arguments
+/// * `filepath` - The path to the image file.
+/// * `logger` - The log the parser reports to.
+/// * `analyzer` - The `Analyzer` turning the recognized text into terms.
+///
+/// # Returns
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+#[cfg(feature = "ocr")]
+pub fn parse_image_document(filepath: &Path, logger: &Logger, analyzer: &Analyzer) -> ParseOutcome {
+    parse_document(filepath, logger, analyzer, image_text)
+}
+
+/// Parses a plain text document, reads its content, tokenizes it,
+/// and removes stop words. The document is read in chunks if the analyzer
+/// streams text documents or it is larger than `STREAM_THRESHOLD_BYTES`.
+///
+/// # Arguments
 /// * `filepath` - The path to the text file.
-/// * `err_handler` - A sender for logging messages.
-/// * `stop_words` - A slice of stop words to filter out.
+/// * `logger` - The log the parser reports to.
+/// * `analyzer` - The `Analyzer` turning the extracted text into terms.
 ///
 /// # Returns
-/// A `Result` containing a `Vec<String>` of processed tokens on success, or an
-/// `anyhow::Error` on failure.
-pub fn parse_txt_document(
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+pub fn parse_txt_document(filepath: &Path, logger: &Logger, analyzer: &Analyzer) -> ParseOutcome {
+    let large =
+        fs::metadata(filepath).is_ok_and(|metadata| metadata.len() > STREAM_THRESHOLD_BYTES);
+    let chunk_bytes = analyzer
+        .stream_chunk
+        .or(large.then_some(STREAM_CHUNK_BYTES));
+    if let Some(chunk_bytes) = chunk_bytes {
+        return parse_text_chunks(filepath, logger, analyzer, chunk_bytes);
+    }
+    let is_markdown = filepath.extension().is_some_and(|ext| ext == "md");
+    let read_text = if is_markdown {
+        markdown_text
+    } else {
+        read_text_lossy
+    };
+    parse_document(filepath, logger, analyzer, read_text)
+}
+
+/// Parses a LaTeX document, strips its commands, keeping its prose and
+/// section titles, tokenizes them and removes stop words.
+///
+/// # Arguments
+/// * `filepath` - The path to the LaTeX file.
+/// * `logger` - The log the parser reports to.
+/// * `analyzer` - The `Analyzer` turning the extracted text into terms.
+///
+/// # Returns
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+pub fn parse_latex_document(filepath: &Path, logger: &Logger, analyzer: &Analyzer) -> ParseOutcome {
+    parse_document(filepath, logger, analyzer, latex_text)
+}
+
+/// Parses an Org-mode document, keeping its headlines and prose without
+/// drawers, planning lines and settings, tokenizes them and removes stop
+/// words.
+///
+/// # Arguments
+/// * `filepath` - The path to the Org file.
+/// * `logger` - The log the parser reports to.
+/// * `analyzer` - The `Analyzer` turning the extracted text into terms.
+///
+/// # Returns
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+pub fn parse_org_document(filepath: &Path, logger: &Logger, analyzer: &Analyzer) -> ParseOutcome {
+    parse_document(filepath, logger, analyzer, org_text)
+}
+
+/// Parses a SubRip or WebVTT subtitle file, keeping only the dialogue of
+/// its cues, tokenizing it and removing stop words.
+///
+/// # Arguments
+/// * `filepath` - The path to the subtitle file.
+/// * `logger` - The log the parser reports to.
+/// * `analyzer` - The `Analyzer` turning the extracted text into terms.
+///
+/// # Returns
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+pub fn parse_subtitle_document(
+    filepath: &Path,
+    logger: &Logger,
+    analyzer: &Analyzer,
+) -> ParseOutcome {
+    parse_document(filepath, logger, analyzer, subtitle_text)
+}
+
+/// Parses a source code file, splitting `camelCase` identifiers into their
+/// words and leaving every token unstemmed. Invalid UTF-8 sequences are
+/// replaced.
+///
+/// # Arguments
+/// * `filepath` - The path to the source file.
+/// * `logger` - The log the parser reports to.
+/// * `analyzer` - The `Analyzer` turning the code into terms.
+///
+/// # Returns
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+pub fn parse_code_document(filepath: &Path, logger: &Logger, analyzer: &Analyzer) -> ParseOutcome {
+    analyze_document(filepath, logger, read_text_lossy, |text, outcome| {
+        analyzer.record_display_forms(text, &mut outcome.display_forms);
+        analyzer.analyze_code(text)
+    })
+}
+
+/// Parses a text document in chunks of about `chunk_bytes` bytes, cut at
+/// line ends, so that only one chunk of the document is held in memory at a
+/// time. Lines longer than a chunk, e.g. of minified dumps, are cut at a
+/// space, or between two characters if they have none. Invalid UTF-8
+/// sequences are replaced.
+///
+/// # Arguments
+/// * `filepath` - The path to the text file.
+/// * `logger` - The log the parser reports to.
+/// * `analyzer` - The `Analyzer` turning each chunk into terms.
+/// * `chunk_bytes` - The size of the chunks.
+///
+/// # Returns
+/// The `ParseOutcome` holding the processed tokens and the errors met.
+fn parse_text_chunks(
     filepath: &Path,
-    err_handler: Arc<RwLock<mpsc::Sender<Message>>>,
-    stop_words: &[String],
-) -> Vec<String> {
+    logger: &Logger,
+    analyzer: &Analyzer,
+    chunk_bytes: usize,
+) -> ParseOutcome {
     {
-        let _ = err_handler
-            .read()
-            .unwrap()
-            .send(Message::Info(format!("Indexing document: {filepath:?}")));
+        logger.info(format!("Indexing document: {}", log_path(filepath)));
     }
-    let content = match fs::read_to_string(filepath) {
-        Ok(val) => val,
-        Err(err) => {
-            let _ = err_handler
-                .read()
-                .unwrap()
-                .send(Message::Error(format!("{err}")));
-            return Vec::new();
+
+    let is_markdown = filepath.extension().is_some_and(|ext| ext == "md");
+    let mut outcome = ParseOutcome::default();
+    // The language is detected from the first chunk and used for the rest
+    let mut chunk_analyzer: Option<Option<Analyzer>> = None;
+    let mut read_chunks = || -> anyhow::Result<()> {
+        let file = File::open(filepath).context("open file")?;
+        let mut reader = BufReader::with_capacity(READ_BUFFER_SIZE, file);
+        let mut chunk = Vec::with_capacity(chunk_bytes);
+        loop {
+            // A chunk never grows past twice its size, whatever its lines
+            let limit = (2 * chunk_bytes - chunk.len()) as u64;
+            let read = (&mut reader)
+                .take(limit)
+                .read_until(b'\n', &mut chunk)
+                .context("read file")?;
+            if chunk.len() < chunk_bytes && read > 0 {
+                continue;
+            }
+            let rest = if read > 0 && chunk.last() != Some(&b'\n') {
+                let tail = &chunk[chunk_bytes..];
+                let cut = tail
+                    .iter()
+                    .rposition(u8::is_ascii_whitespace)
+                    .map(|at| at + 1)
+                    .or_else(|| tail.iter().rposition(|byte| byte & 0xC0 != 0x80))
+                    .unwrap_or(tail.len());
+                chunk.split_off(chunk_bytes + cut)
+            } else {
+                Vec::new()
+            };
+            let text = match std::str::from_utf8(&chunk) {
+                Ok(text) => std::borrow::Cow::Borrowed(text),
+                Err(_) => {
+                    outcome.recoverable("invalid UTF-8 replaced");
+                    String::from_utf8_lossy(&chunk)
+                }
+            };
+            if is_markdown {
+                add_markdown_headings(&text, &mut outcome);
+            }
+            let language_analyzer = chunk_analyzer.get_or_insert_with(|| {
+                outcome.language = analyzer.detect_language(&text);
+                outcome
+                    .language
+                    .map(|language| analyzer.in_language(language))
+            });
+            let analyzer = language_analyzer.as_ref().unwrap_or(analyzer);
+            analyzer.record_display_forms(&text, &mut outcome.display_forms);
+            // Folding each chunk keeps one entry per distinct term rather
+            // than one string per token of the whole document
+            outcome.terms.extend(analyzer.analyze(&text));
+            chunk.clear();
+            chunk.extend_from_slice(&rest);
+            if read == 0 {
+                return Ok(());
+            }
         }
     };
-
-    let content = content.to_lowercase().chars().collect::<Vec<char>>();
-    let mut lex = Lexer::new(&content);
-    let tokens = lex.get_tokens(stop_words);
-    tokens
+    match read_chunks() {
+        Ok(()) => {
+            let language_analyzer = chunk_analyzer.flatten();
+            let analyzer = language_analyzer.as_ref().unwrap_or(analyzer);
+            let terms = analyzer.structure_terms(outcome.title.as_deref(), &outcome.headings);
+            outcome.terms.extend(terms);
+        }
+        Err(err) => outcome = ParseOutcome::fatal(format!("{err:#}")),
+    }
+    outcome.log(filepath, logger);
+    outcome
 }
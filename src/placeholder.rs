@@ -0,0 +1,184 @@
+/// The minimum number of hex digits of a string collapsed into `<hex>`;
+/// shorter ones such as `deadbeef` are too likely to be words or short ids
+/// worth searching for.
+const MIN_HEX_LEN: usize = 16;
+
+/// The placeholders unique tokens are collapsed into, with the character
+/// standing for each of them while the text is tokenized. The characters
+/// are from the private use area, so they never occur in documents, and the
+/// lexer emits each of them as a token of its own.
+const PLACEHOLDERS: [(char, &str); 3] = [
+    ('\u{E000}', "<ts>"),
+    ('\u{E001}', "<uuid>"),
+    ('\u{E002}', "<hex>"),
+];
+
+/// Replaces the timestamps, UUIDs and long hex strings of normalized `text`
+/// by the characters standing for their placeholder, so that the text
+/// tokenizes into `<ts>`, `<uuid>` and `<hex>` once passed through
+/// `restore_placeholders`. Placeholders written out, e.g. `<uuid>` in a
+/// query, are replaced as well.
+///
+/// Timestamps are ISO 8601 dates, optionally followed by a time and a time
+/// zone (`2024-05-01t10:22:33.120z`), and times with seconds (`10:22:33`).
+///
+/// # Arguments
+/// * `text` - The lowercased text.
+///
+/// # Returns
+/// The text with every match replaced.
+pub fn collapse_placeholders(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let at_start = i == 0 || !is_word_byte(bytes[i - 1]);
+        if at_start
+            && let Some((len, placeholder)) = match_placeholder(&bytes[i..])
+            && bytes.get(i + len).is_none_or(|&b| !is_word_byte(b))
+        {
+            out.push_str(&text[copied..i]);
+            out.push(placeholder);
+            i += len;
+            copied = i;
+            continue;
+        }
+        i += 1;
+    }
+    out.push_str(&text[copied..]);
+    out
+}
+
+/// Replaces the tokens standing for a placeholder by the placeholder, e.g.
+/// `<uuid>`.
+///
+/// # Arguments
+/// * `tokens` - The tokens of text passed through `collapse_placeholders`.
+pub fn restore_placeholders(tokens: &mut [String]) {
+    for token in tokens.iter_mut() {
+        let mut chars = token.chars();
+        if let (Some(c), None) = (chars.next(), chars.next())
+            && let Some((_, placeholder)) = PLACEHOLDERS.iter().find(|(p, _)| *p == c)
+        {
+            *token = placeholder.to_string();
+        }
+    }
+}
+
+/// Returns the length of the timestamp, UUID, hex string or written out
+/// placeholder `bytes` start with, and the character standing for its
+/// placeholder.
+fn match_placeholder(bytes: &[u8]) -> Option<(usize, char)> {
+    for (c, placeholder) in PLACEHOLDERS {
+        if bytes.starts_with(placeholder.as_bytes()) {
+            return Some((placeholder.len(), c));
+        }
+    }
+    if let Some(len) = uuid_len(bytes) {
+        return Some((len, PLACEHOLDERS[1].0));
+    }
+    if let Some(len) = timestamp_len(bytes) {
+        return Some((len, PLACEHOLDERS[0].0));
+    }
+    hex_len(bytes).map(|len| (len, PLACEHOLDERS[2].0))
+}
+
+/// Returns `true` for the bytes that can be part of a word: ASCII letters
+/// and digits, and every byte of a non-ASCII character.
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || !b.is_ascii()
+}
+
+/// Returns `true` if `bytes` holds `count` ASCII digits at `at`.
+fn digits_at(bytes: &[u8], at: usize, count: usize) -> bool {
+    bytes
+        .get(at..at + count)
+        .is_some_and(|digits| digits.iter().all(u8::is_ascii_digit))
+}
+
+/// Returns the number of hex digits `bytes` starts with.
+fn hex_run(bytes: &[u8]) -> usize {
+    bytes.iter().take_while(|b| b.is_ascii_hexdigit()).count()
+}
+
+/// Returns the length of the UUID, e.g.
+/// `123e4567-e89b-12d3-a456-426614174000`, `bytes` starts with.
+fn uuid_len(bytes: &[u8]) -> Option<usize> {
+    let mut at = 0;
+    for (i, group) in [8, 4, 4, 4, 12].into_iter().enumerate() {
+        if i > 0 {
+            (bytes.get(at) == Some(&b'-')).then_some(())?;
+            at += 1;
+        }
+        (hex_run(&bytes[at..]) == group).then_some(())?;
+        at += group;
+    }
+    Some(at)
+}
+
+/// Returns the length of the time with seconds, e.g. `10:22:33.120`,
+/// `bytes` starts with.
+fn time_len(bytes: &[u8]) -> Option<usize> {
+    let valid = digits_at(bytes, 0, 2)
+        && bytes.get(2) == Some(&b':')
+        && digits_at(bytes, 3, 2)
+        && bytes.get(5) == Some(&b':')
+        && digits_at(bytes, 6, 2);
+    if !valid {
+        return None;
+    }
+    let mut len = 8;
+    if matches!(bytes.get(len), Some(b'.' | b',')) && digits_at(bytes, len + 1, 1) {
+        len += 1 + bytes[len + 1..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+    }
+    Some(len)
+}
+
+/// Returns the length of the timestamp `bytes` starts with: an ISO 8601
+/// date, optionally followed by a time and a time zone, or a time with
+/// seconds.
+fn timestamp_len(bytes: &[u8]) -> Option<usize> {
+    let is_date = digits_at(bytes, 0, 4)
+        && bytes.get(4) == Some(&b'-')
+        && digits_at(bytes, 5, 2)
+        && bytes.get(7) == Some(&b'-')
+        && digits_at(bytes, 8, 2);
+    if !is_date {
+        return time_len(bytes);
+    }
+    let mut len = 10;
+    if !matches!(bytes.get(len), Some(b't' | b' ')) {
+        return Some(len);
+    }
+    let Some(time) = time_len(&bytes[len + 1..]) else {
+        return Some(len);
+    };
+    len += 1 + time;
+    match bytes.get(len) {
+        Some(b'z') => len += 1,
+        Some(b'+' | b'-') if digits_at(bytes, len + 1, 2) => {
+            len += 3;
+            if bytes.get(len) == Some(&b':') && digits_at(bytes, len + 1, 2) {
+                len += 3;
+            } else if digits_at(bytes, len, 2) {
+                len += 2;
+            }
+        }
+        _ => {}
+    }
+    Some(len)
+}
+
+/// Returns the length of the long hex string, optionally prefixed with
+/// `0x`, `bytes` starts with. The string must hold a digit, so that long
+/// words made of the letters `a` to `f` are kept.
+fn hex_len(bytes: &[u8]) -> Option<usize> {
+    let prefix = if bytes.starts_with(b"0x") { 2 } else { 0 };
+    let run = hex_run(&bytes[prefix..]);
+    let has_digit = bytes[prefix..prefix + run].iter().any(u8::is_ascii_digit);
+    (run >= MIN_HEX_LEN && has_digit).then_some(prefix + run)
+}
@@ -0,0 +1,122 @@
+use anyhow::anyhow;
+
+use crate::glob::glob_match_path;
+
+use std::path::Path;
+
+/// How the documents matched by a `PolicyRule` are indexed. The default
+/// policy indexes the full text of supported documents with the analyzer
+/// settings of the index.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IndexPolicy {
+    /// Index only the file name, whatever the type of the file, without
+    /// reading its content.
+    pub filenames_only: bool,
+    /// Keep the extracted text in the index, so that snippets are shown
+    /// even once the file changed, moved or is out of reach.
+    pub store_text: bool,
+    /// Index the words as written, without stemming them.
+    pub no_stemming: bool,
+}
+
+impl IndexPolicy {
+    /// The option names accepted by a `policy` directive.
+    const OPTIONS: [&'static str; 3] = ["filenames", "store_text", "no_stemming"];
+
+    /// Parses the options of a `policy` directive, e.g. `filenames` or
+    /// `store_text no_stemming`.
+    ///
+    /// # Arguments
+    /// * `options` - The option names.
+    ///
+    /// # Returns
+    /// The `IndexPolicy` setting all of them, or an `anyhow::Result` error
+    /// naming the unknown option.
+    pub fn parse(options: &[String]) -> anyhow::Result<Self> {
+        if options.is_empty() {
+            return Err(anyhow!(
+                "policy needs at least one of {}",
+                Self::OPTIONS.join(", ")
+            ));
+        }
+        let mut policy = Self::default();
+        for option in options {
+            match option.as_str() {
+                "filenames" => policy.filenames_only = true,
+                "store_text" => policy.store_text = true,
+                "no_stemming" => policy.no_stemming = true,
+                _ => {
+                    return Err(anyhow!(
+                        "unknown policy option {option:?}, expected one of {}",
+                        Self::OPTIONS.join(", ")
+                    ));
+                }
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Combines two policies, setting the options set by either.
+    fn merge(self, other: Self) -> Self {
+        Self {
+            filenames_only: self.filenames_only || other.filenames_only,
+            store_text: self.store_text || other.store_text,
+            no_stemming: self.no_stemming || other.no_stemming,
+        }
+    }
+}
+
+/// A rule applying an `IndexPolicy` to the documents whose path matches
+/// `pattern`.
+///
+/// A pattern ending with `/` names a directory: `archive/` applies to
+/// everything under any directory called `archive`, `/home/me/archive/` to
+/// everything under that directory. Other patterns are glob patterns as
+/// supported by `glob_match_path`.
+#[derive(Clone, Debug)]
+pub struct PolicyRule {
+    /// The pattern as written in the configuration file.
+    pub pattern: String,
+    /// The policy applied to matching documents.
+    pub policy: IndexPolicy,
+}
+
+impl PolicyRule {
+    /// Returns `true` if the rule applies to the document at `path`.
+    pub fn matches(&self, path: &Path) -> bool {
+        rule_matches(&self.pattern, path)
+    }
+}
+
+/// Returns `true` if the pattern of a configuration rule applies to the
+/// document at `path`: a directory pattern ending with `/`, or a glob
+/// pattern as supported by `glob_match_path`.
+///
+/// # Arguments
+/// * `pattern` - The pattern as written in the configuration file.
+/// * `path` - The path of the document.
+pub fn rule_matches(pattern: &str, path: &Path) -> bool {
+    match pattern.strip_suffix('/') {
+        Some(dir) if dir.starts_with('/') => glob_match_path(&format!("{dir}/**"), path),
+        Some(dir) => glob_match_path(&format!("**/{dir}/**"), path),
+        None => glob_match_path(pattern, path),
+    }
+}
+
+/// Returns the policy of the document at `path`: the options of all the
+/// rules matching it combined, or the default policy if none does.
+///
+/// # Arguments
+/// * `rules` - The policy rules.
+/// * `path` - The path of the document.
+///
+/// # Returns
+/// The `IndexPolicy` the document is indexed with.
+pub fn policy_for(rules: &[PolicyRule], path: &Path) -> IndexPolicy {
+    rules
+        .iter()
+        .filter(|rule| rule.matches(path))
+        .fold(IndexPolicy::default(), |policy, rule| {
+            policy.merge(rule.policy)
+        })
+}
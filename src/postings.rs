@@ -0,0 +1,249 @@
+use anyhow::{Context, anyhow};
+
+use crate::tree::{CorruptIndexError, Posting};
+
+/// The postings file of segments written with delta and variable-byte
+/// encoding.
+pub const VARBYTE_POSTINGS_FILE: &str = "postings.vb";
+
+/// Appends `value` to `out` as a variable-byte integer: seven bits per byte,
+/// least significant first, the high bit set on every byte but the last.
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Reads a variable-byte integer from `buf` at `pos`, advancing `pos`.
+///
+/// # Returns
+/// The integer, or an `anyhow::Result` error if the buffer ends or the
+/// integer overflows 64 bits.
+fn read_varint(buf: &[u8], pos: &mut usize) -> anyhow::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *buf
+            .get(*pos)
+            .ok_or_else(|| anyhow!("truncated postings list"))?;
+        *pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(anyhow!("variable-byte integer overflows 64 bits"))
+}
+
+/// Encodes a postings list sorted by document id. Document ids and positions
+/// are stored as the difference to their predecessor, and every number as a
+/// variable-byte integer:
+///
+/// ```text
+/// count, then per posting: doc id delta, tf, generation,
+///                          position count, position deltas...
+/// ```
+///
+/// # Arguments
+/// * `postings` - The postings, in ascending order of document id.
+///
+/// # Returns
+/// The encoded list.
+pub fn encode_postings(postings: &[Posting]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_varint(&mut out, postings.len() as u64);
+    let mut prev_doc = 0;
+    for posting in postings {
+        write_varint(&mut out, posting.doc_id - prev_doc);
+        prev_doc = posting.doc_id;
+        write_varint(&mut out, u64::from(posting.tf));
+        write_varint(&mut out, posting.generation);
+        write_varint(&mut out, posting.positions.len() as u64);
+        let mut prev_pos = 0;
+        for &position in &posting.positions {
+            write_varint(&mut out, u64::from(position - prev_pos));
+            prev_pos = position;
+        }
+    }
+    out
+}
+
+/// An iterator decoding the postings of one list one at a time, in ascending
+/// order of document id. Searches still decode every list they read in
+/// full, as the bounds and document frequencies they score with need the
+/// whole list.
+pub struct PostingsIter {
+    /// The encoded list.
+    buf: Vec<u8>,
+    /// The offset of the next posting in `buf`.
+    pos: usize,
+    /// The number of postings left to decode.
+    remaining: u64,
+    /// The document id of the last decoded posting.
+    prev_doc: u64,
+}
+
+impl PostingsIter {
+    /// Creates an iterator over a list written by `encode_postings`.
+    ///
+    /// # Arguments
+    /// * `buf` - The encoded (and decrypted) list.
+    ///
+    /// # Returns
+    /// The `PostingsIter`, or an `anyhow::Result` error if the list header
+    /// is corrupt.
+    pub fn new(buf: Vec<u8>) -> anyhow::Result<Self> {
+        let mut pos = 0;
+        let remaining = read_varint(&buf, &mut pos).context("read postings count")?;
+        Ok(Self {
+            buf,
+            pos,
+            remaining,
+            prev_doc: 0,
+        })
+    }
+
+    /// Returns the number of postings left to decode.
+    pub fn remaining(&self) -> usize {
+        self.remaining as usize
+    }
+}
+
+/// Decodes the posting at `pos` of a variable-byte encoded list, advancing
+/// `pos`.
+///
+/// # Arguments
+/// * `buf` - The encoded list.
+/// * `pos` - The offset of the posting in `buf`.
+/// * `prev_doc` - The document id of the previous posting of the list.
+///
+/// # Returns
+/// The `Posting`, or an `anyhow::Result` error if the list is corrupt.
+fn decode_posting(buf: &[u8], pos: &mut usize, prev_doc: u64) -> anyhow::Result<Posting> {
+    let doc_id = prev_doc
+        .checked_add(read_varint(buf, pos)?)
+        .ok_or_else(|| anyhow!("document id overflows"))?;
+    let tf = u32::try_from(read_varint(buf, pos)?).context("term frequency")?;
+    let generation = read_varint(buf, pos)?;
+    let count = read_varint(buf, pos)? as usize;
+    // A corrupt count must not allocate more than the list could hold
+    let mut positions = Vec::with_capacity(count.min(buf.len() - *pos));
+    let mut prev_pos = 0u32;
+    for _ in 0..count {
+        let delta = u32::try_from(read_varint(buf, pos)?).context("position")?;
+        prev_pos = prev_pos
+            .checked_add(delta)
+            .ok_or_else(|| anyhow!("position overflows"))?;
+        positions.push(prev_pos);
+    }
+    Ok(Posting {
+        doc_id,
+        tf,
+        positions,
+        generation,
+    })
+}
+
+impl Iterator for PostingsIter {
+    type Item = anyhow::Result<Posting>;
+
+    /// Decodes the next posting. A corrupt list yields one error and ends.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        match decode_posting(&self.buf, &mut self.pos, self.prev_doc) {
+            Ok(posting) => {
+                self.prev_doc = posting.doc_id;
+                Some(Ok(posting))
+            }
+            Err(err) => {
+                // Nothing after a corrupt posting can be trusted
+                self.remaining = 0;
+                Some(Err(err.context(CorruptIndexError(
+                    "corrupt postings list".to_string(),
+                ))))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn posting(doc_id: u64, positions: Vec<u32>) -> Posting {
+        Posting {
+            doc_id,
+            tf: positions.len() as u32,
+            positions,
+            generation: doc_id + 7,
+        }
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        let values = [
+            0,
+            1,
+            0x7f,
+            0x80,
+            0x3fff,
+            0x4000,
+            u64::from(u32::MAX),
+            u64::MAX,
+        ];
+        let mut buf = Vec::new();
+        for value in values {
+            write_varint(&mut buf, value);
+        }
+        let mut pos = 0;
+        for value in values {
+            assert_eq!(read_varint(&buf, &mut pos).unwrap(), value);
+        }
+        assert_eq!(pos, buf.len());
+        assert!(read_varint(&buf, &mut pos).is_err());
+    }
+
+    #[test]
+    fn overlong_varints_are_rejected() {
+        let mut pos = 0;
+        assert!(read_varint(&[0xff; 10], &mut pos).is_err());
+    }
+
+    #[test]
+    fn postings_round_trip() {
+        let postings = vec![
+            posting(0, vec![0, 5, 9]),
+            posting(3, vec![]),
+            posting(1000, vec![2, 130_000]),
+            posting(u64::from(u32::MAX) + 1, vec![u32::MAX]),
+        ];
+        let buf = encode_postings(&postings);
+        let iter = PostingsIter::new(buf.clone()).unwrap();
+        assert_eq!(iter.remaining(), postings.len());
+        let decoded: Vec<Posting> = iter.collect::<anyhow::Result<_>>().unwrap();
+        assert_eq!(decoded, postings);
+
+        let mut pos = 0;
+        read_varint(&buf, &mut pos).unwrap();
+        assert_eq!(decode_posting(&buf, &mut pos, 0).unwrap(), postings[0]);
+        assert_eq!(decode_posting(&buf, &mut pos, 0).unwrap(), postings[1]);
+    }
+
+    #[test]
+    fn truncated_lists_end_with_one_error() {
+        let buf = encode_postings(&[posting(1, vec![1, 2]), posting(2, vec![3])]);
+        let mut iter = PostingsIter::new(buf[..buf.len() - 1].to_vec()).unwrap();
+        assert!(iter.next().unwrap().is_ok());
+        let err = iter.next().unwrap().unwrap_err();
+        assert!(err.downcast_ref::<CorruptIndexError>().is_some());
+        assert!(iter.next().is_none());
+    }
+}
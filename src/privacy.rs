@@ -0,0 +1,54 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::path::Path;
+use std::sync::{LazyLock, OnceLock};
+
+/// How document paths are written to logs, for deployments where the log
+/// file must not reveal which documents exist.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Redaction {
+    /// Replaced by a keyed hash. The key is drawn at startup, so the lines
+    /// of a document can be followed within a run, but hashes cannot be
+    /// matched against guessed paths or across runs.
+    Hash,
+    /// Cut down to their extension.
+    Truncate,
+}
+
+/// The redaction of the process, set once from the command line.
+static REDACTION: OnceLock<Redaction> = OnceLock::new();
+
+/// The random key of `Redaction::Hash`.
+static HASH_KEY: LazyLock<RandomState> = LazyLock::new(RandomState::new);
+
+/// Sets the redaction applied to every log line of the process. Only the
+/// first call has an effect.
+///
+/// # Arguments
+/// * `redaction` - The `Redaction` to apply.
+pub fn set_redaction(redaction: Redaction) {
+    let _ = REDACTION.set(redaction);
+}
+
+/// Returns the redaction applied to log lines, if any.
+pub fn redaction() -> Option<Redaction> {
+    REDACTION.get().copied()
+}
+
+/// Formats a document path for a log line.
+///
+/// # Arguments
+/// * `path` - The path or URI of the document.
+///
+/// # Returns
+/// The quoted path, or its hash or extension if logs are redacted.
+pub fn log_path(path: &Path) -> String {
+    match redaction() {
+        None => format!("{path:?}"),
+        Some(Redaction::Hash) => format!("<doc {:016x}>", HASH_KEY.hash_one(path)),
+        Some(Redaction::Truncate) => match path.extension() {
+            Some(ext) => format!("<doc .{}>", ext.to_string_lossy()),
+            None => "<doc>".to_string(),
+        },
+    }
+}
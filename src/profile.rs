@@ -0,0 +1,70 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// The number of documents held in memory before a segment is flushed by
+/// the low-memory profile.
+pub const LOW_MEMORY_SEGMENT_DOCS: u64 = 20;
+
+/// The number of worker threads used by the low-memory profile.
+pub const LOW_MEMORY_THREADS: usize = 2;
+
+/// The size of the chunks text documents are read and analyzed in by the
+/// low-memory profile.
+pub const STREAM_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Resource profiles tuning how the index is built and served, recorded in
+/// the manifest so that every run on the index uses the same one.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Profile {
+    /// Uses every core and keeps whole documents in memory while parsing.
+    #[default]
+    Default,
+    /// For single board computers with around 1GB of memory: small
+    /// segments, few threads, text documents analyzed in chunks and no
+    /// preloaded index or postings cache when serving.
+    LowMemory,
+}
+
+impl Profile {
+    /// Returns the number of documents held in memory before a segment is
+    /// flushed, or `None` for the index default.
+    pub fn segment_docs(self) -> Option<u64> {
+        match self {
+            Self::Default => None,
+            Self::LowMemory => Some(LOW_MEMORY_SEGMENT_DOCS),
+        }
+    }
+
+    /// Returns the size of the chunks text documents are analyzed in, or
+    /// `None` to analyze them whole.
+    pub fn stream_chunk(self) -> Option<usize> {
+        match self {
+            Self::Default => None,
+            Self::LowMemory => Some(STREAM_CHUNK_BYTES),
+        }
+    }
+
+    /// Returns `true` if the server may keep the index preloaded in memory
+    /// and cache postings.
+    pub fn allows_preload(self) -> bool {
+        self == Self::Default
+    }
+
+    /// Builds the thread pool indexing runs use with this profile.
+    ///
+    /// # Returns
+    /// A `rayon::ThreadPool` bounded to `LOW_MEMORY_THREADS` threads, `None`
+    /// to use the global pool, or an `anyhow::Result` error if it cannot be
+    /// built.
+    pub fn thread_pool(self) -> anyhow::Result<Option<rayon::ThreadPool>> {
+        match self {
+            Self::Default => Ok(None),
+            Self::LowMemory => rayon::ThreadPoolBuilder::new()
+                .num_threads(LOW_MEMORY_THREADS)
+                .build()
+                .map(Some)
+                .context("build low-memory thread pool"),
+        }
+    }
+}
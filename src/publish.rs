@@ -0,0 +1,84 @@
+use anyhow::{Context, anyhow};
+
+use crate::checkpoint::Checkpoint;
+use crate::tree::MainIndex;
+
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+/// The symlink within an index directory pointing at the index actually
+/// searched, so that a rebuilt index can replace it atomically.
+pub const CURRENT_LINK: &str = "current";
+
+/// Returns the directory holding the index files of `index_dir`: the target
+/// of its `CURRENT_LINK` if it has one, otherwise `index_dir` itself.
+/// Readers and writers resolve it once when they open the index, so that a
+/// switch never mixes the files of two indexes.
+///
+/// # Arguments
+/// * `index_dir` - The index directory given by the user.
+///
+/// # Returns
+/// The resolved directory.
+pub fn resolve_index(index_dir: &Path) -> PathBuf {
+    let link = index_dir.join(CURRENT_LINK);
+    let is_link = fs::symlink_metadata(&link).is_ok_and(|metadata| metadata.is_symlink());
+    match fs::canonicalize(&link) {
+        Ok(target) if is_link => target,
+        _ => index_dir.to_path_buf(),
+    }
+}
+
+/// Makes the index built in `build` the one searched through `index_dir`,
+/// by pointing the `CURRENT_LINK` of `index_dir` at it. The link is replaced
+/// by a rename, so servers see either the previous index or the new one,
+/// and pick the new one up at their next request.
+///
+/// The link is relative if `build` is inside `index_dir`, e.g. in
+/// `~/.indexer/builds/2024-05-01`, so that the directory can be moved.
+///
+/// # Arguments
+/// * `index_dir` - The index directory searched by servers and commands.
+/// * `build` - The directory of the complete index to publish.
+/// * `key_file` - The key file of the index, if it is encrypted.
+///
+/// # Returns
+/// The previously published index, `None` if `index_dir` held its own
+/// index, or an `anyhow::Result` error if `build` isn't a complete index.
+pub fn publish_index(
+    index_dir: &Path,
+    build: &Path,
+    key_file: Option<&Path>,
+) -> anyhow::Result<Option<PathBuf>> {
+    let root = fs::canonicalize(index_dir).with_context(|| format!("resolve {index_dir:?}"))?;
+    let build = fs::canonicalize(build).with_context(|| format!("resolve {build:?}"))?;
+    if build == root {
+        return Err(anyhow!("cannot publish {build:?} into itself"));
+    }
+    if Checkpoint::exists(&build) {
+        return Err(anyhow!(
+            "{build:?} holds an unfinished indexing run; resume it before publishing"
+        ));
+    }
+    // Refuse directories that servers couldn't open
+    let main_index = MainIndex::open_read_only(&build, key_file)
+        .with_context(|| format!("open index {build:?}"))?;
+    if main_index.doc_count() == 0 {
+        return Err(anyhow!("{build:?} holds no indexed documents"));
+    }
+
+    let previous = resolve_index(&root);
+    let previous = (previous != root).then_some(previous);
+    if previous.as_ref() == Some(&build) {
+        return Err(anyhow!("{build:?} is already published"));
+    }
+
+    let target = build.strip_prefix(&root).unwrap_or(&build);
+    let link = root.join(CURRENT_LINK);
+    let staged = root.join(format!("{CURRENT_LINK}.tmp"));
+    let _ = fs::remove_file(&staged);
+    symlink(target, &staged).with_context(|| format!("link {staged:?}"))?;
+    fs::rename(&staged, &link).with_context(|| format!("replace {link:?}"))?;
+    Ok(previous)
+}
@@ -0,0 +1,800 @@
+use std::fmt;
+
+use crate::analyzer::{LANGUAGE_FILTER, TAG_FILTER};
+use crate::lexer::EXACT_PREFIX;
+use crate::tags::validate_tag;
+
+/// One element of a parsed query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Clause {
+    /// A word, matched after stemming.
+    Word(String),
+    /// A word prefixed with `=`, matched exactly.
+    Exact(String),
+    /// The words between a pair of double quotes, matched exactly.
+    Phrase(String),
+    /// A `lang:<code>` filter restricting the search to one language.
+    Language(String),
+    /// A `tag:<tag>` filter restricting the search to documents tagged
+    /// with it.
+    Tag(String),
+}
+
+impl Clause {
+    /// Returns `true` for the clauses filtering the results of the whole
+    /// query rather than matching terms: `lang:` and `tag:` filters.
+    pub fn is_filter(&self) -> bool {
+        matches!(self, Self::Language(_) | Self::Tag(_))
+    }
+}
+
+/// A boolean combination of query clauses, or of their analyzed terms.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BoolExpr<T> {
+    /// A single clause.
+    Leaf(T),
+    /// Matches if every expression matches.
+    And(Vec<BoolExpr<T>>),
+    /// Matches if any expression matches.
+    Or(Vec<BoolExpr<T>>),
+    /// Matches if the expression does not match.
+    Not(Box<BoolExpr<T>>),
+}
+
+impl<T> BoolExpr<T> {
+    /// Transforms the leaves of the expression, dropping the ones `f` maps to
+    /// `None` along with the operators left without operands.
+    ///
+    /// # Arguments
+    /// * `f` - Maps a leaf to its replacement.
+    ///
+    /// # Returns
+    /// The transformed expression, or `None` if no leaf is left.
+    pub fn filter_map<U>(&self, f: &impl Fn(&T) -> Option<U>) -> Option<BoolExpr<U>> {
+        match self {
+            Self::Leaf(leaf) => f(leaf).map(BoolExpr::Leaf),
+            Self::And(exprs) => combine(exprs.iter().map(|e| e.filter_map(f)), BoolExpr::And),
+            Self::Or(exprs) => combine(exprs.iter().map(|e| e.filter_map(f)), BoolExpr::Or),
+            Self::Not(expr) => expr.filter_map(f).map(|e| BoolExpr::Not(Box::new(e))),
+        }
+    }
+
+    /// Returns every leaf of the expression, in order.
+    pub fn leaves(&self) -> Vec<&T> {
+        match self {
+            Self::Leaf(leaf) => vec![leaf],
+            Self::And(exprs) | Self::Or(exprs) => exprs.iter().flat_map(|e| e.leaves()).collect(),
+            Self::Not(expr) => expr.leaves(),
+        }
+    }
+
+    /// Returns every leaf of the expression, in order, with whether it is
+    /// negated: under an odd number of `NOT`s, so `NOT NOT a` is not.
+    pub fn polarized_leaves(&self) -> Vec<(&T, bool)> {
+        let mut leaves = Vec::new();
+        self.collect_polarized(false, &mut leaves);
+        leaves
+    }
+
+    /// Adds the leaves of the expression to `leaves`, negated if `negated`
+    /// flips an odd number of times on the way down.
+    fn collect_polarized<'a>(&'a self, negated: bool, leaves: &mut Vec<(&'a T, bool)>) {
+        match self {
+            Self::Leaf(leaf) => leaves.push((leaf, negated)),
+            Self::And(exprs) | Self::Or(exprs) => {
+                for expr in exprs {
+                    expr.collect_polarized(negated, leaves);
+                }
+            }
+            Self::Not(expr) => expr.collect_polarized(!negated, leaves),
+        }
+    }
+
+    /// Returns the leaves of the expression that are not negated, in order.
+    pub fn positive_leaves(&self) -> Vec<&T> {
+        self.polarized_leaves()
+            .into_iter()
+            .filter(|(_, negated)| !negated)
+            .map(|(leaf, _)| leaf)
+            .collect()
+    }
+
+    /// Evaluates the expression.
+    ///
+    /// # Arguments
+    /// * `leaf_matches` - Tells whether a leaf matches.
+    ///
+    /// # Returns
+    /// `true` if the expression matches.
+    pub fn matches(&self, leaf_matches: &impl Fn(&T) -> bool) -> bool {
+        match self {
+            Self::Leaf(leaf) => leaf_matches(leaf),
+            Self::And(exprs) => exprs.iter().all(|e| e.matches(leaf_matches)),
+            Self::Or(exprs) => exprs.iter().any(|e| e.matches(leaf_matches)),
+            Self::Not(expr) => !expr.matches(leaf_matches),
+        }
+    }
+}
+
+/// Joins the operands left of an operator with `op`, or returns the single
+/// one left as is.
+fn combine<T>(
+    operands: impl Iterator<Item = Option<BoolExpr<T>>>,
+    op: fn(Vec<BoolExpr<T>>) -> BoolExpr<T>,
+) -> Option<BoolExpr<T>> {
+    let mut operands = operands.flatten().collect::<Vec<BoolExpr<T>>>();
+    match operands.len() {
+        0 | 1 => operands.pop(),
+        _ => Some(op(operands)),
+    }
+}
+
+/// A parsed query.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Query {
+    /// The clauses of the query searched for and scored, in the order they
+    /// appear: every clause but the ones negated with `NOT`.
+    pub clauses: Vec<Clause>,
+    /// The boolean combination the matching documents must satisfy, if the
+    /// query uses operators. `lang:` filters are not part of it.
+    pub filter: Option<BoolExpr<Clause>>,
+}
+
+/// An error in the syntax of a query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuerySyntaxError {
+    /// The query that failed to parse.
+    pub query: String,
+    /// The position, in characters, at which the error was found.
+    pub position: usize,
+    /// What is wrong.
+    pub message: String,
+    /// What the parser expected at `position`.
+    pub expected: Vec<&'static str>,
+}
+
+impl fmt::Display for QuerySyntaxError {
+    /// Shows the error message, what was expected and a caret under the
+    /// position of the error.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.position + 1)?;
+        if !self.expected.is_empty() {
+            write!(f, ", expected {}", self.expected.join(" or "))?;
+        }
+        write!(f, "\n  {}\n  {}^", self.query, " ".repeat(self.position))
+    }
+}
+
+impl std::error::Error for QuerySyntaxError {}
+
+/// A query left without anything to search for once analyzed, e.g. an empty
+/// one, one made of stop words and punctuation only, or one only excluding
+/// terms.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EmptyQueryError {
+    /// The query as given.
+    pub query: String,
+    /// The terms the query was analyzed into, none of them holding a letter
+    /// or a digit.
+    pub tokens: Vec<String>,
+}
+
+impl EmptyQueryError {
+    /// Checks that the analyzed terms of a query leave something to search
+    /// for: at least one term holding a letter or a digit.
+    ///
+    /// # Arguments
+    /// * `query` - The query as given.
+    /// * `tokens` - The analyzed terms of the query.
+    ///
+    /// # Returns
+    /// `Ok(())` if the query can be searched, otherwise the `EmptyQueryError`.
+    pub fn check(query: &str, tokens: &[String]) -> Result<(), Self> {
+        if tokens
+            .iter()
+            .any(|token| token.chars().any(char::is_alphanumeric))
+        {
+            return Ok(());
+        }
+        Err(Self {
+            query: query.to_string(),
+            tokens: tokens.to_vec(),
+        })
+    }
+}
+
+impl fmt::Display for EmptyQueryError {
+    /// Shows the query and the terms it was analyzed into.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "query `{}` was reduced to nothing after analysis, analyzed tokens: ",
+            self.query
+        )?;
+        if self.tokens.is_empty() {
+            write!(f, "none")?;
+        } else {
+            write!(f, "{:?}", self.tokens)?;
+        }
+        write!(
+            f,
+            " (stop words, punctuation and terms excluded with `NOT` are not searched)"
+        )
+    }
+}
+
+impl std::error::Error for EmptyQueryError {}
+
+/// Characters reserved for query operators, which cannot appear in words.
+const RESERVED: [char; 2] = ['(', ')'];
+
+/// The operators of the query language. They are only recognized in
+/// uppercase; in lowercase they are plain words.
+const AND: &str = "AND";
+const OR: &str = "OR";
+const NOT: &str = "NOT";
+
+/// How deeply parentheses and `NOT` operators may nest, so that a hostile
+/// query cannot exhaust the stack of the parser.
+pub const MAX_QUERY_DEPTH: usize = 64;
+
+/// What the parser expects where an operand is missing.
+const OPERAND: [&str; 3] = ["a word", "a quoted phrase", "a `(`"];
+
+/// One token of a query: an operator, a parenthesis or a clause.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Clause(Clause),
+}
+
+impl Token {
+    /// Returns whether the token is an operator or a parenthesis.
+    fn is_operator(&self) -> bool {
+        !matches!(self, Self::Clause(_))
+    }
+
+    /// Returns how the token is written, for error messages.
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Open => "(",
+            Self::Close => ")",
+            Self::And => AND,
+            Self::Or => OR,
+            Self::Not => NOT,
+            Self::Clause(_) => "clause",
+        }
+    }
+}
+
+/// Prefixes the query term standing for a phrase of several words, which
+/// only matches documents containing the words next to each other.
+pub const PHRASE_PREFIX: &str = "\"";
+
+/// Separates the words of a phrase term; index terms never contain it.
+const PHRASE_SEPARATOR: char = ' ';
+
+/// Builds the query term of a phrase from the index terms of its words.
+///
+/// # Arguments
+/// * `terms` - The index terms of the words, in the order they appear.
+///
+/// # Returns
+/// The phrase term.
+pub fn phrase_term(terms: &[String]) -> String {
+    format!(
+        "{PHRASE_PREFIX}{}",
+        terms.join(&PHRASE_SEPARATOR.to_string())
+    )
+}
+
+/// Returns the index terms of the words of a phrase term, in order, or
+/// `None` if `term` is not a phrase term.
+pub fn phrase_parts(term: &str) -> Option<Vec<&str>> {
+    term.strip_prefix(PHRASE_PREFIX)
+        .filter(|words| !words.is_empty())
+        .map(|words| words.split(PHRASE_SEPARATOR).collect())
+}
+
+/// Returns the index terms a query term matches on: the words of a phrase
+/// term, or the term itself.
+pub fn term_parts(term: &str) -> Vec<&str> {
+    phrase_parts(term).unwrap_or_else(|| vec![term])
+}
+
+impl Query {
+    /// Parses a query: words, `=exact` words, `"quoted phrases"`,
+    /// `lang:<code>` and `tag:<tag>` filters separated by whitespace,
+    /// optionally combined with the `AND`, `OR` and `NOT` operators and
+    /// parentheses. Clauses next to each other are alternatives, as if
+    /// joined by `OR`; `AND` binds tighter than `OR`, and `a NOT b` means
+    /// `a AND NOT b`. `lang:` and `tag:` filters always apply to the whole
+    /// query.
+    ///
+    /// # Arguments
+    /// * `query` - The query string.
+    ///
+    /// # Returns
+    /// The parsed `Query`, or a `QuerySyntaxError` locating the first error.
+    pub fn parse(query: &str) -> Result<Self, QuerySyntaxError> {
+        let error = |position: usize, message: &str, expected: &[&'static str]| QuerySyntaxError {
+            query: query.to_string(),
+            position,
+            message: message.to_string(),
+            expected: expected.to_vec(),
+        };
+        let tokens = tokenize(query, &error)?;
+
+        if !tokens.iter().any(|(_, token)| token.is_operator()) {
+            let clauses = tokens
+                .into_iter()
+                .filter_map(|(_, token)| match token {
+                    Token::Clause(clause) => Some(clause),
+                    _ => None,
+                })
+                .collect();
+            return Ok(Self {
+                clauses,
+                filter: None,
+            });
+        }
+
+        let mut parser = ExprParser {
+            tokens: &tokens,
+            pos: 0,
+            end: query.chars().count(),
+            depth: 0,
+            error: &error,
+        };
+        let filter = parser.or_expr()?;
+        if let Some((position, token)) = tokens.get(parser.pos) {
+            return Err(error(
+                *position,
+                &format!("unexpected `{}`", token.name()),
+                &[AND, OR, NOT],
+            ));
+        }
+
+        let mut clauses = tokens
+            .iter()
+            .filter_map(|(_, token)| match token {
+                Token::Clause(clause) if clause.is_filter() => Some(clause.clone()),
+                _ => None,
+            })
+            .collect::<Vec<Clause>>();
+        if let Some(filter) = &filter {
+            clauses.extend(filter.positive_leaves().into_iter().cloned());
+        }
+        Ok(Self { clauses, filter })
+    }
+
+    /// Returns the language of the last `lang:` filter of the query, if any.
+    pub fn language(&self) -> Option<&str> {
+        self.clauses.iter().rev().find_map(|clause| match clause {
+            Clause::Language(code) => Some(code.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Returns the tags of the `tag:` filters of the query, all of which a
+    /// document must carry.
+    pub fn tags(&self) -> Vec<&str> {
+        self.clauses
+            .iter()
+            .filter_map(|clause| match clause {
+                Clause::Tag(tag) => Some(tag.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Splits a query into tokens, each with its position in characters.
+///
+/// # Arguments
+/// * `query` - The query string.
+/// * `error` - Builds the `QuerySyntaxError` of a malformed clause.
+///
+/// # Returns
+/// The tokens, or a `QuerySyntaxError` locating the first malformed clause.
+fn tokenize(
+    query: &str,
+    error: &dyn Fn(usize, &str, &[&'static str]) -> QuerySyntaxError,
+) -> Result<Vec<(usize, Token)>, QuerySyntaxError> {
+    let chars = query.chars().collect::<Vec<char>>();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < chars.len() {
+        let c = chars[pos];
+        if c.is_whitespace() {
+            pos += 1;
+            continue;
+        }
+        if RESERVED.contains(&c) {
+            let token = if c == '(' { Token::Open } else { Token::Close };
+            tokens.push((pos, token));
+            pos += 1;
+            continue;
+        }
+
+        if c == '"' {
+            let Some(len) = chars[pos + 1..].iter().position(|&c| c == '"') else {
+                return Err(error(pos, "unterminated phrase", &["a closing `\"`"]));
+            };
+            let phrase = chars[pos + 1..pos + 1 + len].iter().collect::<String>();
+            if !phrase.trim().is_empty() {
+                tokens.push((pos, Token::Clause(Clause::Phrase(phrase))));
+            }
+            pos += len + 2;
+            continue;
+        }
+
+        let start = pos;
+        while pos < chars.len()
+            && !chars[pos].is_whitespace()
+            && chars[pos] != '"'
+            && !RESERVED.contains(&chars[pos])
+        {
+            pos += 1;
+        }
+        let word = chars[start..pos].iter().collect::<String>();
+
+        let token = if word == AND {
+            Token::And
+        } else if word == OR {
+            Token::Or
+        } else if word == NOT {
+            Token::Not
+        } else if let Some(exact) = word.strip_prefix(EXACT_PREFIX) {
+            if exact.is_empty() {
+                return Err(error(
+                    start + 1,
+                    &format!("empty `{EXACT_PREFIX}` term"),
+                    &["a word"],
+                ));
+            }
+            Token::Clause(Clause::Exact(exact.to_string()))
+        } else if let Some(code) = word.strip_prefix(LANGUAGE_FILTER) {
+            if code.is_empty() || !code.chars().all(|c| c.is_ascii_alphabetic()) {
+                return Err(error(
+                    start + LANGUAGE_FILTER.len(),
+                    "invalid language filter",
+                    &["a language code like `de`"],
+                ));
+            }
+            Token::Clause(Clause::Language(code.to_lowercase()))
+        } else if let Some(tag) = word.strip_prefix(TAG_FILTER) {
+            if let Err(err) = validate_tag(tag) {
+                return Err(error(
+                    start + TAG_FILTER.len(),
+                    &format!("invalid tag filter: {err}"),
+                    &["a tag like `project=alpha`"],
+                ));
+            }
+            Token::Clause(Clause::Tag(tag.to_string()))
+        } else {
+            Token::Clause(Clause::Word(word))
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+/// A recursive descent parser of the tokens of a query using operators.
+/// Each level returns `None` for an operand made of `lang:` and `tag:`
+/// filters only, which are not part of the boolean expression.
+struct ExprParser<'a> {
+    tokens: &'a [(usize, Token)],
+    /// The index of the next token.
+    pos: usize,
+    /// The length of the query in characters, where a missing operand at
+    /// its end is reported.
+    end: usize,
+    /// The number of parentheses and `NOT` operators the next token is
+    /// nested in.
+    depth: usize,
+    error: &'a dyn Fn(usize, &str, &[&'static str]) -> QuerySyntaxError,
+}
+
+impl ExprParser<'_> {
+    /// Returns the next token, if any.
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    /// Returns the position of the next token, or the end of the query.
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.end, |(position, _)| *position)
+    }
+
+    /// Enters one more level of nesting at the next token.
+    ///
+    /// # Returns
+    /// A `QuerySyntaxError` if the query nests deeper than
+    /// `MAX_QUERY_DEPTH`.
+    fn descend(&mut self) -> Result<(), QuerySyntaxError> {
+        if self.depth >= MAX_QUERY_DEPTH {
+            return Err((self.error)(
+                self.position(),
+                &format!("query nested deeper than {MAX_QUERY_DEPTH} levels"),
+                &[],
+            ));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Parses alternatives: operands joined by `OR` or just next to each
+    /// other.
+    fn or_expr(&mut self) -> Result<Option<BoolExpr<Clause>>, QuerySyntaxError> {
+        let mut operands = vec![self.and_expr()?];
+        loop {
+            match self.peek() {
+                None | Some(Token::Close) => break,
+                Some(Token::Or) => {
+                    self.pos += 1;
+                    operands.push(self.and_expr()?);
+                }
+                Some(_) => operands.push(self.and_expr()?),
+            }
+        }
+        Ok(combine(operands.into_iter(), BoolExpr::Or))
+    }
+
+    /// Parses operands joined by `AND`, or by `NOT` excluding the right one.
+    fn and_expr(&mut self) -> Result<Option<BoolExpr<Clause>>, QuerySyntaxError> {
+        let mut operands = vec![self.unary()?];
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.pos += 1;
+                    operands.push(self.unary()?);
+                }
+                Some(Token::Not) => {
+                    self.descend()?;
+                    self.pos += 1;
+                    operands.push(self.unary()?.map(|e| BoolExpr::Not(Box::new(e))));
+                    self.depth -= 1;
+                }
+                _ => break,
+            }
+        }
+        Ok(combine(operands.into_iter(), BoolExpr::And))
+    }
+
+    /// Parses an operand, negated if it starts with `NOT`.
+    fn unary(&mut self) -> Result<Option<BoolExpr<Clause>>, QuerySyntaxError> {
+        if self.peek() == Some(&Token::Not) {
+            self.descend()?;
+            self.pos += 1;
+            let operand = self.unary()?;
+            self.depth -= 1;
+            return Ok(operand.map(|e| BoolExpr::Not(Box::new(e))));
+        }
+        self.primary()
+    }
+
+    /// Parses a clause or a parenthesized expression.
+    fn primary(&mut self) -> Result<Option<BoolExpr<Clause>>, QuerySyntaxError> {
+        let position = self.position();
+        let tokens = self.tokens;
+        let Some((_, token)) = tokens.get(self.pos) else {
+            return Err((self.error)(position, "missing operand", &OPERAND));
+        };
+        if *token == Token::Open {
+            self.descend()?;
+        }
+        self.pos += 1;
+        match token {
+            Token::Clause(clause) if clause.is_filter() => Ok(None),
+            Token::Clause(clause) => Ok(Some(BoolExpr::Leaf(clause.clone()))),
+            Token::Open => {
+                let expr = self.or_expr()?;
+                self.depth -= 1;
+                if self.peek() != Some(&Token::Close) {
+                    return Err((self.error)(
+                        self.position(),
+                        "unclosed parenthesis",
+                        &["a closing `)`"],
+                    ));
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            _ => Err((self.error)(
+                position,
+                &format!("unexpected `{}`", token.name()),
+                &OPERAND,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(word: &str) -> BoolExpr<Clause> {
+        BoolExpr::Leaf(Clause::Word(word.to_string()))
+    }
+
+    fn filter(query: &str) -> BoolExpr<Clause> {
+        Query::parse(query).unwrap().filter.unwrap()
+    }
+
+    fn clauses(query: &str) -> Vec<Clause> {
+        Query::parse(query).unwrap().clauses
+    }
+
+    #[test]
+    fn field_prefixes_parse_into_their_clauses() {
+        assert_eq!(
+            clauses("rust =Tokio lang:DE tag:project=alpha"),
+            vec![
+                Clause::Word("rust".to_string()),
+                Clause::Exact("Tokio".to_string()),
+                Clause::Language("de".to_string()),
+                Clause::Tag("project=alpha".to_string()),
+            ]
+        );
+        let query = Query::parse("lang:fr tag:a tag:b x lang:de").unwrap();
+        assert_eq!(query.language(), Some("de"));
+        assert_eq!(query.tags(), vec!["a", "b"]);
+        // Filters apply to the whole query, outside of the boolean expression
+        let query = Query::parse("tag:a AND (b OR lang:de)").unwrap();
+        assert_eq!(query.filter, Some(word("b")));
+        assert_eq!(query.tags(), vec!["a"]);
+        assert_eq!(query.language(), Some("de"));
+    }
+
+    #[test]
+    fn quotes_delimit_phrases() {
+        assert_eq!(
+            clauses("rust\"search engine\"local"),
+            vec![
+                Clause::Word("rust".to_string()),
+                Clause::Phrase("search engine".to_string()),
+                Clause::Word("local".to_string()),
+            ]
+        );
+        assert_eq!(
+            clauses("\"a (b) AND c\""),
+            vec![Clause::Phrase("a (b) AND c".to_string())]
+        );
+        assert_eq!(clauses("\"  \" x"), vec![Clause::Word("x".to_string())]);
+        assert_eq!(
+            filter("\"a b\" NOT c"),
+            BoolExpr::And(vec![
+                BoolExpr::Leaf(Clause::Phrase("a b".to_string())),
+                BoolExpr::Not(Box::new(word("c"))),
+            ])
+        );
+    }
+
+    #[test]
+    fn operators_are_only_recognized_in_uppercase() {
+        assert_eq!(
+            Query::parse("a and not b").unwrap(),
+            Query {
+                clauses: ["a", "and", "not", "b"]
+                    .map(|w| Clause::Word(w.to_string()))
+                    .to_vec(),
+                filter: None,
+            }
+        );
+    }
+
+    #[test]
+    fn syntax_errors_locate_the_faulty_clause() {
+        let error = |query: &str| Query::parse(query).unwrap_err();
+
+        let err = error("a \"b c");
+        assert_eq!(
+            (err.position, err.message.as_str()),
+            (2, "unterminated phrase")
+        );
+        let err = error("a = b");
+        assert_eq!((err.position, err.message.as_str()), (3, "empty `=` term"));
+        let err = error("x lang:d3");
+        assert_eq!(
+            (err.position, err.message.as_str()),
+            (7, "invalid language filter")
+        );
+        let err = error("tag:=alpha");
+        assert_eq!(err.position, 4);
+        assert!(err.message.starts_with("invalid tag filter"), "{err}");
+
+        let err = error("a AND");
+        assert_eq!((err.position, err.message.as_str()), (5, "missing operand"));
+        assert_eq!(err.expected, OPERAND);
+        let err = error("(a OR b");
+        assert_eq!(
+            (err.position, err.message.as_str()),
+            (7, "unclosed parenthesis")
+        );
+        let err = error("a OR b) c");
+        assert_eq!((err.position, err.message.as_str()), (6, "unexpected `)`"));
+        let err = error("a AND OR b");
+        assert_eq!((err.position, err.message.as_str()), (6, "unexpected `OR`"));
+        assert!(err.to_string().contains("at column 7"), "{err}");
+    }
+
+    #[test]
+    fn parentheses_group_operands() {
+        assert_eq!(
+            filter("(a OR b) AND c"),
+            BoolExpr::And(vec![BoolExpr::Or(vec![word("a"), word("b")]), word("c")])
+        );
+        assert_eq!(filter("((a))"), word("a"));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            filter("a OR b AND c"),
+            BoolExpr::Or(vec![word("a"), BoolExpr::And(vec![word("b"), word("c")])])
+        );
+        assert_eq!(
+            filter("a b AND c"),
+            BoolExpr::Or(vec![word("a"), BoolExpr::And(vec![word("b"), word("c")])])
+        );
+    }
+
+    #[test]
+    fn binary_not_excludes_the_right_operand() {
+        assert_eq!(
+            filter("a NOT b"),
+            BoolExpr::And(vec![word("a"), BoolExpr::Not(Box::new(word("b")))])
+        );
+        assert_eq!(
+            filter("NOT NOT a"),
+            BoolExpr::Not(Box::new(BoolExpr::Not(Box::new(word("a")))))
+        );
+    }
+
+    #[test]
+    fn negations_cancel_out_in_pairs() {
+        let negated = |query: &str| {
+            filter(query)
+                .polarized_leaves()
+                .into_iter()
+                .map(|(clause, negated)| (clause.clone(), negated))
+                .collect::<Vec<_>>()
+        };
+        let leaf = |w: &str| Clause::Word(w.to_string());
+        assert_eq!(negated("NOT NOT a"), vec![(leaf("a"), false)]);
+        assert_eq!(
+            negated("a NOT (b NOT c)"),
+            vec![(leaf("a"), false), (leaf("b"), true), (leaf("c"), false)]
+        );
+        assert_eq!(clauses("x NOT NOT y"), vec![leaf("x"), leaf("y")]);
+    }
+
+    #[test]
+    fn nesting_up_to_the_limit_parses() {
+        let depth = MAX_QUERY_DEPTH;
+        let query = format!("{}a{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(filter(&query), word("a"));
+        let query = format!("{}a", "NOT ".repeat(depth));
+        assert!(Query::parse(&query).is_ok());
+    }
+
+    #[test]
+    fn nesting_past_the_limit_is_a_syntax_error() {
+        let depth = 3000;
+        let query = format!("{}a{}", "(".repeat(depth), ")".repeat(depth));
+        let err = Query::parse(&query).unwrap_err();
+        assert!(err.message.contains("nested deeper"), "{err}");
+        assert_eq!(err.position, MAX_QUERY_DEPTH);
+
+        let query = format!("{}a", "NOT ".repeat(depth));
+        let err = Query::parse(&query).unwrap_err();
+        assert!(err.message.contains("nested deeper"), "{err}");
+    }
+}
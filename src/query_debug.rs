@@ -0,0 +1,240 @@
+use std::fmt;
+
+use crate::analyzer::{Analyzer, LANGUAGE_FILTER, TAG_FILTER};
+use crate::lexer::{EXACT_PREFIX, exact_word};
+use crate::query::{Clause, Query, term_parts};
+use crate::scoring::Ranker;
+use crate::tree::{MainIndex, TermSpread};
+
+/// How one clause of a query was analyzed.
+#[derive(Clone, Debug)]
+pub struct ClauseDebug {
+    /// The clause as written in the query.
+    pub clause: String,
+    /// Whether the clause is excluded with `NOT`, so only filters the
+    /// results without being scored.
+    pub negated: bool,
+    /// The stop words dropped from the clause.
+    pub stop_words: Vec<String>,
+    /// The words of the clause changed by stemming, with their stem.
+    pub stemmed: Vec<(String, String)>,
+    /// The index terms the clause was analyzed into.
+    pub terms: Vec<String>,
+}
+
+/// The statistics of one index term of a query.
+#[derive(Clone, Debug)]
+pub struct TermDebug {
+    /// The index term.
+    pub term: String,
+    /// How the term is spread over the segments of the index.
+    pub spread: TermSpread,
+    /// The inverse document frequency the ranker weights the term by, if
+    /// known.
+    pub idf: Option<f64>,
+}
+
+/// Everything `search --debug-query` shows about how a query is analyzed
+/// and matched against the index.
+#[derive(Clone, Debug)]
+pub struct QueryDebug {
+    /// The query as given.
+    pub query: String,
+    /// The ranker scoring the results.
+    pub ranker: Ranker,
+    /// The number of documents in the index.
+    pub total_docs: u64,
+    /// The number of active segments searched.
+    pub segments: usize,
+    /// The analysis of each clause, in the order they appear.
+    pub clauses: Vec<ClauseDebug>,
+    /// The analyzed tokens that are searched for and scored.
+    pub tokens: Vec<String>,
+    /// The statistics of every index term of the query, phrases split into
+    /// their words.
+    pub terms: Vec<TermDebug>,
+}
+
+impl QueryDebug {
+    /// Analyzes `query` against an opened index.
+    ///
+    /// # Arguments
+    /// * `main_index` - The opened `MainIndex`.
+    /// * `query` - The query string.
+    /// * `parsed` - The parsed query.
+    /// * `ranker` - The ranker scoring the results.
+    ///
+    /// # Returns
+    /// The `QueryDebug` of the query.
+    pub fn new(main_index: &MainIndex, query: &str, parsed: &Query, ranker: Ranker) -> Self {
+        let analyzer = main_index.analyzer();
+
+        let mut clauses: Vec<ClauseDebug> = match &parsed.filter {
+            Some(filter) => filter
+                .polarized_leaves()
+                .into_iter()
+                .map(|(clause, negated)| clause_debug(&analyzer, clause, negated))
+                .collect(),
+            None => parsed
+                .clauses
+                .iter()
+                .filter(|clause| !clause.is_filter())
+                .map(|clause| clause_debug(&analyzer, clause, false))
+                .collect(),
+        };
+        let tokens = analyzer.analyze_query(parsed);
+
+        let mut words: Vec<&str> = Vec::new();
+        for term in clauses
+            .iter()
+            .flat_map(|clause| &clause.terms)
+            .flat_map(|term| term_parts(term))
+        {
+            if !words.contains(&term) {
+                words.push(term);
+            }
+        }
+        let spread = main_index.term_spread(&words);
+        let total_docs = main_index.doc_count();
+        let terms = words
+            .iter()
+            .map(|&term| {
+                let spread = spread.get(term).copied().unwrap_or_default();
+                TermDebug {
+                    term: main_index.display_term(term),
+                    spread,
+                    idf: if spread.df > 0 {
+                        ranker.idf(spread.df, total_docs)
+                    } else {
+                        None
+                    },
+                }
+            })
+            .collect();
+
+        // Exact forms are shown in their original casing, once their
+        // statistics are looked up under the normalized words
+        for clause in &mut clauses {
+            for term in &mut clause.terms {
+                *term = main_index.display_term(term);
+            }
+        }
+
+        Self {
+            query: query.to_string(),
+            ranker,
+            total_docs,
+            segments: main_index.active_segments.len(),
+            clauses,
+            tokens,
+            terms,
+        }
+    }
+}
+
+/// Analyzes one clause, recording the stop words dropped and the words
+/// stemmed on the way.
+fn clause_debug(analyzer: &Analyzer, clause: &Clause, negated: bool) -> ClauseDebug {
+    let text = match clause {
+        Clause::Word(word) => word.clone(),
+        Clause::Exact(word) => format!("{EXACT_PREFIX}{word}"),
+        Clause::Phrase(phrase) => format!("\"{phrase}\""),
+        Clause::Language(code) => format!("{LANGUAGE_FILTER}{code}"),
+        Clause::Tag(tag) => format!("{TAG_FILTER}{tag}"),
+    };
+
+    let mut stop_words = Vec::new();
+    let mut stemmed = Vec::new();
+    let (Clause::Word(words) | Clause::Exact(words) | Clause::Phrase(words)) = clause else {
+        return ClauseDebug {
+            clause: text,
+            negated,
+            stop_words,
+            stemmed,
+            terms: Vec::new(),
+        };
+    };
+    let normalized = analyzer.normalize(words);
+    for word in normalized
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        if analyzer.stop_words.contains(word) {
+            stop_words.push(word.to_string());
+        } else if matches!(clause, Clause::Word(_))
+            && let Some(stem) = analyzer
+                .word_terms(word)
+                .into_iter()
+                .find(|term| exact_word(term).is_none())
+            && stem != word
+        {
+            stemmed.push((word.to_string(), stem));
+        }
+    }
+
+    ClauseDebug {
+        clause: text,
+        negated,
+        stop_words,
+        stemmed,
+        terms: analyzer.analyze_clause(clause),
+    }
+}
+
+impl fmt::Display for QueryDebug {
+    /// Shows the analysis of each clause, the analyzed tokens and a table
+    /// of the statistics of each term.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Query: {}", self.query)?;
+        writeln!(
+            f,
+            "Ranker: {:?}, {} documents in {} segments",
+            self.ranker, self.total_docs, self.segments
+        )?;
+
+        writeln!(f, "Clauses:")?;
+        for clause in &self.clauses {
+            let not = if clause.negated { "NOT " } else { "" };
+            if clause.terms.is_empty() {
+                writeln!(f, "  {not}{} -> nothing", clause.clause)?;
+            } else {
+                writeln!(f, "  {not}{} -> {}", clause.clause, clause.terms.join(" "))?;
+            }
+            if !clause.stop_words.is_empty() {
+                writeln!(
+                    f,
+                    "    stop words dropped: {}",
+                    clause.stop_words.join(", ")
+                )?;
+            }
+            for (word, stem) in &clause.stemmed {
+                writeln!(f, "    stemmed: {word} -> {stem}")?;
+            }
+        }
+        writeln!(f, "Analyzed tokens: {:?}", self.tokens)?;
+
+        writeln!(f, "Terms:")?;
+        let width = self
+            .terms
+            .iter()
+            .map(|term| term.term.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("term".len());
+        writeln!(
+            f,
+            "  {:width$}  {:>8}  {:>8}  {:>8}",
+            "term", "segments", "df", "idf"
+        )?;
+        for term in &self.terms {
+            let segments = format!("{}/{}", term.spread.segments, self.segments);
+            let idf = term.idf.map_or("-".to_string(), |idf| format!("{idf:.4}"));
+            writeln!(
+                f,
+                "  {:width$}  {:>8}  {:>8}  {:>8}",
+                term.term, segments, term.spread.df, idf
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+
+/// What indexing runs do with an index over one of its quotas.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum QuotaAction {
+    /// Runs on an index over a quota fail, and new documents past the
+    /// document quota are skipped.
+    #[default]
+    Refuse,
+    /// Runs index everything and only warn.
+    Warn,
+}
+
+/// Limits on what an index may hold, e.g. for each user of a shared host
+/// with an index directory of their own. Unlike a `SizeBudget`, nothing is
+/// evicted to stay within them.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quota {
+    /// The maximum number of documents, if any.
+    pub max_documents: Option<u64>,
+    /// The maximum size of the index directory in bytes, if any.
+    pub max_bytes: Option<u64>,
+    /// What indexing runs do once a quota is exceeded.
+    pub action: QuotaAction,
+}
+
+impl Quota {
+    /// Measures how much of the quota an index uses.
+    ///
+    /// # Arguments
+    /// * `documents` - The number of documents in the index.
+    /// * `bytes` - The size of the index directory.
+    ///
+    /// # Returns
+    /// The `QuotaUsage` of the index.
+    pub fn usage(&self, documents: u64, bytes: u64) -> QuotaUsage {
+        QuotaUsage {
+            documents,
+            max_documents: self.max_documents,
+            bytes,
+            max_bytes: self.max_bytes,
+        }
+    }
+}
+
+/// How much of its quota an index uses.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuotaUsage {
+    /// The number of documents in the index.
+    pub documents: u64,
+    /// The maximum number of documents, if any.
+    pub max_documents: Option<u64>,
+    /// The size of the index directory in bytes.
+    pub bytes: u64,
+    /// The maximum size of the index directory in bytes, if any.
+    pub max_bytes: Option<u64>,
+}
+
+impl QuotaUsage {
+    /// Returns `true` if the index holds more documents than allowed.
+    pub fn documents_exceeded(&self) -> bool {
+        self.max_documents.is_some_and(|max| self.documents > max)
+    }
+
+    /// Returns `true` if the index directory is larger than allowed.
+    pub fn bytes_exceeded(&self) -> bool {
+        self.max_bytes.is_some_and(|max| self.bytes > max)
+    }
+
+    /// Returns `true` if either quota is exceeded.
+    pub fn exceeded(&self) -> bool {
+        self.documents_exceeded() || self.bytes_exceeded()
+    }
+
+    /// Describes the usage, e.g. `120 of 1000 documents (12%), 3 MiB of
+    /// 10 MiB (30%)`, leaving out the quotas that are not set.
+    ///
+    /// # Arguments
+    /// * `format_bytes` - Formats a size for display.
+    pub fn describe(&self, format_bytes: impl Fn(u64) -> String) -> String {
+        let mut parts = Vec::new();
+        if let Some(max) = self.max_documents {
+            parts.push(format!(
+                "{} of {max} documents ({}%)",
+                self.documents,
+                percent(self.documents, max)
+            ));
+        }
+        if let Some(max) = self.max_bytes {
+            parts.push(format!(
+                "{} of {} ({}%)",
+                format_bytes(self.bytes),
+                format_bytes(max),
+                percent(self.bytes, max)
+            ));
+        }
+        parts.join(", ")
+    }
+}
+
+/// Returns `used` as a rounded percentage of `max`.
+fn percent(used: u64, max: u64) -> u64 {
+    if max == 0 {
+        return 100;
+    }
+    (used as f64 * 100.0 / max as f64).round() as u64
+}
@@ -0,0 +1,113 @@
+use anyhow::Context;
+
+use crate::manifest::{Manifest, install_file, staged_path, sync_dir};
+use crate::stats::STATS_FILE;
+
+use std::fs;
+use std::path::Path;
+
+/// The files a commit stages and renames into place once the manifest
+/// lists its segments.
+const COMMITTED_FILES: [&str; 3] = ["docstore.bin", "display.bin", STATS_FILE];
+
+/// What `recover_index` did to the index.
+#[derive(Debug, Default)]
+pub struct Recovery {
+    /// Whether a commit that died after saving its manifest was finished.
+    pub finished_commit: bool,
+    /// The number of partial or uncommitted segment directories removed.
+    pub removed_segments: usize,
+}
+
+impl Recovery {
+    /// Describes what was done to the index in `index_dir`, one message per
+    /// repair, e.g. to warn the user about an interrupted run.
+    ///
+    /// # Arguments
+    /// * `index_dir` - The directory of the recovered index.
+    ///
+    /// # Returns
+    /// The messages, empty if the index needed no recovery.
+    pub fn messages(&self, index_dir: &Path) -> Vec<String> {
+        let mut messages = Vec::new();
+        if self.finished_commit {
+            messages.push(format!("Finished the commit interrupted in {index_dir:?}"));
+        }
+        if self.removed_segments > 0 {
+            messages.push(format!(
+                "Removed {} partial segment directories left in {index_dir:?}",
+                self.removed_segments
+            ));
+        }
+        messages
+    }
+}
+
+/// Brings the index in `index_dir` back to its last commit after a crash or
+/// an interrupted run, before a writer opens it: a commit that died after
+/// saving its manifest is finished by installing its staged files, the
+/// files staged by a commit that died before are removed, and so are the
+/// segment directories being written and those the manifest doesn't list.
+/// Quarantined segments are kept for `indexer verify --repair`, and retired
+/// ones for the readers of older generations.
+///
+/// Only one writer may use an index at a time: the segments of another
+/// running writer are removed as uncommitted.
+///
+/// # Arguments
+/// * `index_dir` - The directory containing the index files.
+///
+/// # Returns
+/// The `Recovery`, or an `anyhow::Result` error if the index cannot be
+/// repaired.
+pub fn recover_index(index_dir: &Path) -> anyhow::Result<Recovery> {
+    let mut recovery = Recovery::default();
+    let mut manifest = Manifest::load(index_dir)?;
+    for name in COMMITTED_FILES {
+        let path = index_dir.join(name);
+        if !staged_path(&path).exists() {
+            continue;
+        }
+        if manifest.committing {
+            install_file(&path)?;
+        } else {
+            fs::remove_file(staged_path(&path)).context("remove staged file")?;
+        }
+    }
+    if manifest.committing {
+        manifest.committing = false;
+        manifest.save(index_dir).context("save manifest")?;
+        recovery.finished_commit = true;
+    }
+
+    let entries = match fs::read_dir(index_dir) {
+        Ok(entries) => entries,
+        // A new index
+        Err(_) => return Ok(recovery),
+    };
+    for entry in entries {
+        let entry = entry.context("read index entry")?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some(rest) = name.strip_prefix("segment_") else {
+            continue;
+        };
+        let partial = match rest.parse::<u64>() {
+            Ok(seg_id) => manifest.segments.as_ref().is_some_and(|segments| {
+                !segments.contains(&seg_id)
+                    && !manifest.quarantined.contains(&seg_id)
+                    && !manifest.retired.contains_key(&seg_id)
+            }),
+            // Staging directories of segments being flushed
+            Err(_) => rest.ends_with(".tmp"),
+        };
+        if partial && entry.path().is_dir() {
+            fs::remove_dir_all(entry.path())
+                .with_context(|| format!("remove partial segment {name}"))?;
+            recovery.removed_segments += 1;
+        }
+    }
+    if recovery.removed_segments > 0 {
+        sync_dir(index_dir)?;
+    }
+    Ok(recovery)
+}
@@ -0,0 +1,52 @@
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+/// How many times an operation failing with a transient error is retried.
+pub const REMOTE_RETRIES: u32 = 3;
+
+/// The pause before the first retry, doubled after each further attempt.
+pub const REMOTE_BACKOFF: Duration = Duration::from_millis(200);
+
+// Linux error codes returned by network filesystems whose server is briefly
+// unreachable.
+const ESTALE: i32 = 116;
+const EHOSTDOWN: i32 = 112;
+const EHOSTUNREACH: i32 = 113;
+
+/// Returns whether an IO error may go away on its own, as happens when a
+/// network mount is briefly unavailable.
+fn is_transient(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::TimedOut
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+    ) || matches!(err.raw_os_error(), Some(ESTALE | EHOSTDOWN | EHOSTUNREACH))
+}
+
+/// Runs an IO operation, retrying it with a growing pause while it fails with
+/// a transient error.
+///
+/// # Arguments
+/// * `op` - The operation to run.
+///
+/// # Returns
+/// The result of the last attempt.
+pub fn retry<T>(mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut backoff = REMOTE_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        match op() {
+            Err(err) if attempt < REMOTE_RETRIES && is_transient(&err) => {
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::{self, Cipher, index_cipher};
+use crate::parsers::{ParseError, ParseErrorClass, ParseOutcome};
+use crate::publish::resolve_index;
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicU64;
+
+/// The directory within an index directory holding the run reports.
+const REPORTS_DIR: &str = "reports";
+
+/// The number of run reports kept per index; older ones are removed.
+const MAX_REPORTS: usize = 20;
+
+/// The kinds of reasons a discovered file is not indexed for.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkipKind {
+    /// A hidden file or directory, and hidden entries are not indexed.
+    Hidden,
+    /// Matched by a skip rule.
+    Excluded,
+    /// An executable file.
+    Executable,
+    /// A file without an extension.
+    NoExtension,
+    /// A file whose extension has no parser.
+    UnsupportedExtension,
+    /// Unchanged since it was last indexed.
+    UpToDate,
+    /// Its content is already indexed under another path.
+    Duplicate,
+    /// Evicted to keep the index within its size budget.
+    Evicted,
+    /// Left for the next run as the time budget of the run expired.
+    TimeBudget,
+    /// A new document past the document quota of the index.
+    Quota,
+    /// Nothing could be parsed out of the file.
+    ParseError,
+    /// The file could not be read or added to the index.
+    Failed,
+    /// Larger than the maximum file size of the run.
+    TooLarge,
+    /// A file of a text format holding binary content.
+    Binary,
+    /// A symbolic link not followed, or leading to a file or directory
+    /// already reached.
+    Symlink,
+    /// Recorded by a version without kinds.
+    #[default]
+    Other,
+}
+
+/// A file that was discovered but not indexed, and why.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SkippedFile {
+    /// The path of the file.
+    pub path: PathBuf,
+    /// The kind of reason the file was not indexed for.
+    #[serde(default)]
+    pub kind: SkipKind,
+    /// Why the file was not indexed.
+    pub reason: String,
+}
+
+/// An indexed file found at a new path, whose document moved without being
+/// indexed again.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RenamedFile {
+    /// The path the file was indexed at.
+    pub from: PathBuf,
+    /// The path the file moved to.
+    pub to: PathBuf,
+}
+
+/// A file that could not be indexed because of an error.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FailedFile {
+    /// The path of the file.
+    pub path: PathBuf,
+    /// The error preventing the file from being indexed.
+    pub error: String,
+}
+
+/// The parse errors met in one document.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DocumentParseErrors {
+    /// The path of the document.
+    pub path: PathBuf,
+    /// The worst class of the errors: `recoverable` documents were partially
+    /// indexed, `fatal` ones not at all.
+    pub class: ParseErrorClass,
+    /// The errors met.
+    pub errors: Vec<ParseError>,
+    /// The number of further recoverable errors that were only counted.
+    pub suppressed: usize,
+}
+
+/// A machine readable summary of one indexing run, written as JSON into the
+/// `reports` directory of the index.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RunReport {
+    /// When the run started, in RFC 3339 format.
+    pub started_at: String,
+    /// The file or directory that was indexed.
+    pub source: PathBuf,
+    /// The number of files found while walking `source`.
+    pub discovered: u64,
+    /// The files that were (re-)indexed.
+    pub indexed: Vec<PathBuf>,
+    /// The files that were left out, with the reason.
+    pub skipped: Vec<SkippedFile>,
+    /// The files that failed to be indexed, with the error.
+    pub failures: Vec<FailedFile>,
+    /// The parse errors of every document that did not parse cleanly.
+    #[serde(default)]
+    pub parse_errors: Vec<DocumentParseErrors>,
+    /// The ids of the segments written by the run.
+    pub segments_created: Vec<u64>,
+    /// The documents evicted to keep the index within its size budget.
+    #[serde(default)]
+    pub evicted: Vec<PathBuf>,
+    /// The indexed files found at a new path.
+    #[serde(default)]
+    pub renamed: Vec<RenamedFile>,
+    /// The documents deleted because their file no longer exists.
+    #[serde(default)]
+    pub deleted: Vec<PathBuf>,
+    /// Milliseconds spent discovering files.
+    pub discovery_ms: u64,
+    /// Milliseconds spent parsing and indexing files.
+    pub indexing_ms: u64,
+    /// Milliseconds spent on the whole run.
+    pub total_ms: u64,
+}
+
+impl RunReport {
+    /// Creates an empty report for a run indexing `source`.
+    pub fn new(source: &Path) -> Self {
+        Self {
+            started_at: chrono::Local::now().to_rfc3339(),
+            source: source.to_path_buf(),
+            ..Default::default()
+        }
+    }
+
+    /// Records a file that was left out.
+    pub fn skip(&mut self, path: &Path, kind: SkipKind, reason: impl Into<String>) {
+        self.skipped.push(SkippedFile {
+            path: path.to_path_buf(),
+            kind,
+            reason: reason.into(),
+        });
+    }
+
+    /// Returns every file of the run that did not make it into the index:
+    /// the skipped files, then the failed ones as `SkipKind::ParseError` if
+    /// nothing could be parsed out of them, `SkipKind::Failed` otherwise.
+    pub fn not_indexed(&self) -> Vec<SkippedFile> {
+        let unparsable: HashSet<&PathBuf> = self
+            .parse_errors
+            .iter()
+            .filter(|doc| doc.class == ParseErrorClass::Fatal)
+            .map(|doc| &doc.path)
+            .collect();
+        let failed = self.failures.iter().map(|failure| SkippedFile {
+            path: failure.path.clone(),
+            kind: if unparsable.contains(&failure.path) {
+                SkipKind::ParseError
+            } else {
+                SkipKind::Failed
+            },
+            reason: failure.error.clone(),
+        });
+        self.skipped.iter().cloned().chain(failed).collect()
+    }
+
+    /// Records a file that failed to be indexed.
+    pub fn fail(&mut self, path: &Path, error: impl Into<String>) {
+        self.failures.push(FailedFile {
+            path: path.to_path_buf(),
+            error: error.into(),
+        });
+    }
+
+    /// Records the parse errors of a document, if any.
+    pub fn parse_outcome(&mut self, path: &Path, outcome: &ParseOutcome) {
+        if let Some(class) = outcome.class() {
+            self.parse_errors.push(DocumentParseErrors {
+                path: path.to_path_buf(),
+                class,
+                errors: outcome.errors.clone(),
+                suppressed: outcome.suppressed,
+            });
+        }
+    }
+
+    /// Writes the report into the `reports` directory of the index in
+    /// `index_dir`, removing the oldest reports beyond `MAX_REPORTS`. The
+    /// report of an encrypted index is encrypted too, as it lists the paths
+    /// of the run.
+    ///
+    /// # Arguments
+    /// * `index_dir` - The directory containing the index files.
+    /// * `cipher` - The cipher of an encrypted index, if any.
+    ///
+    /// # Returns
+    /// The path of the written report, or an `anyhow::Result` error.
+    pub fn save(&self, index_dir: &Path, cipher: Option<&Cipher>) -> anyhow::Result<PathBuf> {
+        let reports_dir = index_dir.join(REPORTS_DIR);
+        fs::create_dir_all(&reports_dir).context("create reports dir")?;
+
+        let name = format!(
+            "run-{}.json",
+            chrono::Local::now().format("%Y%m%dT%H%M%S%.3f")
+        );
+        let path = reports_dir.join(name);
+        let content = serde_json::to_vec_pretty(self).context("serialize run report")?;
+        let content = crypto::seal(cipher, content).context("encrypt run report")?;
+        fs::write(&path, content).context("write run report")?;
+
+        let reports = list_reports(index_dir)?;
+        if reports.len() > MAX_REPORTS {
+            for old in &reports[..reports.len() - MAX_REPORTS] {
+                let _ = fs::remove_file(old);
+            }
+        }
+        Ok(path)
+    }
+
+    /// Loads the report of the latest indexing run of the index in
+    /// `index_dir`.
+    ///
+    /// # Arguments
+    /// * `index_dir` - The directory containing the index files.
+    /// * `key_file` - The key file of an encrypted index, if any.
+    ///
+    /// # Returns
+    /// The latest `RunReport`, `None` if the index has no reports, or an
+    /// `anyhow::Result` error.
+    pub fn load_last(index_dir: &Path, key_file: Option<&Path>) -> anyhow::Result<Option<Self>> {
+        let Some(path) = list_reports(index_dir)?.pop() else {
+            return Ok(None);
+        };
+        let cipher = index_cipher(index_dir, key_file)?;
+        Self::load(&path, cipher.as_ref()).map(Some)
+    }
+
+    /// Loads the report in the file at `path`, decrypting it with `cipher`
+    /// if the index is encrypted.
+    ///
+    /// # Returns
+    /// The `RunReport`, or an `anyhow::Result` error if it cannot be read.
+    fn load(path: &Path, cipher: Option<&Cipher>) -> anyhow::Result<Self> {
+        let content = fs::read(path).with_context(|| format!("read run report {path:?}"))?;
+        let content = crypto::open(cipher, content)
+            .with_context(|| format!("decrypt run report {path:?}"))?;
+        serde_json::from_slice(&content).with_context(|| format!("parse run report {path:?}"))
+    }
+}
+
+/// The progress of an indexing run, counted while it runs so that another
+/// thread can follow it, e.g. the server answering `GET /api/index/status`.
+#[derive(Debug, Default)]
+pub struct IndexProgress {
+    /// The number of documents the run (re-)indexes, known once the files
+    /// are discovered.
+    pub total: AtomicU64,
+    /// The number of documents processed so far, indexed or not.
+    pub processed: AtomicU64,
+    /// The number of documents indexed so far.
+    pub indexed: AtomicU64,
+    /// The size of the documents indexed so far, in bytes.
+    pub bytes: AtomicU64,
+    /// The number of documents that failed to be indexed so far.
+    pub errors: AtomicU64,
+}
+
+/// Lists the files the indexing runs of the index in `index_dir` did not
+/// index, and why, from their reports. A file left out by several runs is
+/// listed once, with the reason of the latest run.
+///
+/// # Arguments
+/// * `index_dir` - The directory containing the index files.
+/// * `last_run` - Only read the report of the latest run.
+/// * `key_file` - The key file of an encrypted index, if any.
+///
+/// # Returns
+/// The files sorted by path, or an `anyhow::Result` error if a report
+/// cannot be read.
+pub fn skipped_files(
+    index_dir: &Path,
+    last_run: bool,
+    key_file: Option<&Path>,
+) -> anyhow::Result<Vec<SkippedFile>> {
+    let cipher = index_cipher(index_dir, key_file)?;
+    let mut reports = list_reports(index_dir)?;
+    if last_run && reports.len() > 1 {
+        reports.drain(..reports.len() - 1);
+    }
+    // A later run's outcome replaces the earlier ones, including indexing
+    let mut files: BTreeMap<PathBuf, Option<SkippedFile>> = BTreeMap::new();
+    for path in reports {
+        let report = RunReport::load(&path, cipher.as_ref())?;
+        for doc in &report.indexed {
+            files.insert(doc.clone(), None);
+        }
+        for skipped in report.not_indexed() {
+            files.insert(skipped.path.clone(), Some(skipped));
+        }
+    }
+    Ok(files.into_values().flatten().collect())
+}
+
+/// Lists the run reports of the index in `index_dir`, or of the index
+/// published in it, oldest first.
+///
+/// # Arguments
+/// * `index_dir` - The directory containing the index files.
+///
+/// # Returns
+/// The paths of the reports, or an `anyhow::Result` error.
+pub fn list_reports(index_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let reports_dir = resolve_index(index_dir).join(REPORTS_DIR);
+    if !reports_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut reports = fs::read_dir(&reports_dir)
+        .context("read reports dir")?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect::<Vec<PathBuf>>();
+    // The timestamped names sort chronologically
+    reports.sort();
+    Ok(reports)
+}
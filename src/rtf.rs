@@ -0,0 +1,227 @@
+/// The destinations whose content is not text of the document: tables of
+/// fonts, colors and styles, document properties, pictures, embedded
+/// objects and field instructions. Their groups are skipped entirely.
+const SKIPPED_DESTINATIONS: [&str; 19] = [
+    "fonttbl",
+    "colortbl",
+    "stylesheet",
+    "listtable",
+    "listoverridetable",
+    "revtbl",
+    "rsidtbl",
+    "filetbl",
+    "info",
+    "pict",
+    "object",
+    "objdata",
+    "fldinst",
+    "themedata",
+    "colorschememapping",
+    "datastore",
+    "latentstyles",
+    "xmlnstbl",
+    "generator",
+];
+
+/// The characters of bytes 0x80 to 0x9F in the Windows-1252 code page, the
+/// ANSI code page of most RTF documents. The other bytes map to the same
+/// code points as in Latin-1.
+const CP1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+/// The plain text of an RTF document.
+#[derive(Debug, Default)]
+pub struct RtfText {
+    /// The text of the body, paragraphs separated by newlines.
+    pub text: String,
+    /// The title from the document properties, if any.
+    pub title: Option<String>,
+    /// Whether the groups of the document are not balanced, e.g. because
+    /// the file was cut short. The text read is kept.
+    pub unbalanced: bool,
+}
+
+/// Where the characters of a group go.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Destination {
+    Text,
+    Title,
+    Skipped,
+}
+
+/// The state of a group, restored when the group ends.
+#[derive(Clone, Copy)]
+struct Group {
+    destination: Destination,
+    /// The number of fallback characters following a `\u` character.
+    unicode_skip: usize,
+}
+
+/// Returns `true` if `raw` starts like an RTF document.
+pub fn is_rtf(raw: &[u8]) -> bool {
+    raw.trim_ascii_start().starts_with(b"{\\rtf")
+}
+
+/// Extracts the plain text of an RTF document by dropping its control
+/// words and the groups that hold no text. Paragraph, line and page breaks
+/// become newlines, tabs and table cells spaces, and escaped characters
+/// (`\'e9`, `\u233`) are decoded, 8-bit ones as Windows-1252.
+///
+/// # Arguments
+/// * `raw` - The content of the RTF file.
+///
+/// # Returns
+/// The `RtfText` of the document.
+pub fn rtf_to_text(raw: &[u8]) -> RtfText {
+    let mut out = RtfText::default();
+    let mut title = String::new();
+    let mut stack: Vec<Group> = Vec::new();
+    let mut group = Group {
+        destination: Destination::Text,
+        unicode_skip: 1,
+    };
+    // The fallback characters left to skip after a `\u` character
+    let mut skipping = 0;
+    // The high half of a surrogate pair written as two `\u` characters
+    let mut high_surrogate: Option<u16> = None;
+
+    let mut push = |group: &Group, skipping: &mut usize, c: char| {
+        if *skipping > 0 {
+            *skipping -= 1;
+            return;
+        }
+        match group.destination {
+            Destination::Text => out.text.push(c),
+            Destination::Title => title.push(c),
+            Destination::Skipped => {}
+        }
+    };
+
+    let mut i = 0;
+    while i < raw.len() {
+        match raw[i] {
+            b'{' => {
+                stack.push(group);
+                skipping = 0;
+                i += 1;
+            }
+            b'}' => {
+                match stack.pop() {
+                    Some(outer) => group = outer,
+                    None => out.unbalanced = true,
+                }
+                skipping = 0;
+                i += 1;
+            }
+            b'\r' | b'\n' => i += 1,
+            b'\\' => {
+                let Some(&c) = raw.get(i + 1) else {
+                    break;
+                };
+                i += 2;
+                match c {
+                    b'\'' => {
+                        let byte = raw
+                            .get(i..i + 2)
+                            .and_then(|hex| std::str::from_utf8(hex).ok())
+                            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                        if let Some(byte) = byte {
+                            push(&group, &mut skipping, cp1252_char(byte));
+                            i += 2;
+                        }
+                    }
+                    b'*' => group.destination = Destination::Skipped,
+                    b'\\' | b'{' | b'}' => push(&group, &mut skipping, c as char),
+                    b'~' => push(&group, &mut skipping, ' '),
+                    b'_' => push(&group, &mut skipping, '-'),
+                    b'\r' | b'\n' => push(&group, &mut skipping, '\n'),
+                    c if c.is_ascii_alphabetic() => {
+                        let start = i - 1;
+                        while raw.get(i).is_some_and(u8::is_ascii_alphabetic) {
+                            i += 1;
+                        }
+                        let word = std::str::from_utf8(&raw[start..i]).unwrap_or_default();
+                        let param_start = i;
+                        if raw.get(i) == Some(&b'-') {
+                            i += 1;
+                        }
+                        while raw.get(i).is_some_and(u8::is_ascii_digit) {
+                            i += 1;
+                        }
+                        let param = std::str::from_utf8(&raw[param_start..i])
+                            .ok()
+                            .and_then(|param| param.parse::<i32>().ok());
+                        // A space ending a control word belongs to it
+                        if raw.get(i) == Some(&b' ') {
+                            i += 1;
+                        }
+                        match word {
+                            "par" | "line" | "sect" | "page" | "row" => {
+                                push(&group, &mut skipping, '\n')
+                            }
+                            "tab" | "cell" => push(&group, &mut skipping, ' '),
+                            "emdash" => push(&group, &mut skipping, '—'),
+                            "endash" => push(&group, &mut skipping, '–'),
+                            "bullet" => push(&group, &mut skipping, '•'),
+                            "lquote" => push(&group, &mut skipping, '‘'),
+                            "rquote" => push(&group, &mut skipping, '’'),
+                            "ldblquote" => push(&group, &mut skipping, '“'),
+                            "rdblquote" => push(&group, &mut skipping, '”'),
+                            "title" if group.destination != Destination::Text => {
+                                group.destination = Destination::Title
+                            }
+                            "uc" => {
+                                group.unicode_skip = param.unwrap_or(1).max(0) as usize;
+                            }
+                            "u" => {
+                                // Code points past 32767 are written negative
+                                let unit = param.unwrap_or_default() as u16;
+                                let c = match (high_surrogate.take(), unit) {
+                                    (_, 0xD800..=0xDBFF) => {
+                                        high_surrogate = Some(unit);
+                                        None
+                                    }
+                                    (Some(high), 0xDC00..=0xDFFF) => {
+                                        char::decode_utf16([high, unit]).next().and_then(Result::ok)
+                                    }
+                                    (_, unit) => char::from_u32(u32::from(unit)),
+                                };
+                                if let Some(c) = c {
+                                    push(&group, &mut skipping, c);
+                                }
+                                skipping = group.unicode_skip;
+                            }
+                            "bin" => {
+                                // Binary data, skipped whatever it holds
+                                i = i.saturating_add(param.unwrap_or_default().max(0) as usize);
+                            }
+                            word if SKIPPED_DESTINATIONS.contains(&word) => {
+                                group.destination = Destination::Skipped
+                            }
+                            _ => {}
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            byte => {
+                push(&group, &mut skipping, cp1252_char(byte));
+                i += 1;
+            }
+        }
+    }
+    out.unbalanced |= !stack.is_empty();
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    out.title = (!title.is_empty()).then_some(title);
+    out
+}
+
+/// Decodes a byte of the Windows-1252 code page.
+fn cp1252_char(byte: u8) -> char {
+    match byte {
+        0x80..=0x9F => CP1252_HIGH[usize::from(byte - 0x80)],
+        byte => char::from(byte),
+    }
+}
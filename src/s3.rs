@@ -0,0 +1,216 @@
+use anyhow::{Context, anyhow};
+use sha2::{Digest, Sha256};
+
+use std::io::Read;
+use std::time::Duration;
+
+/// How long each request to the object store may take.
+const S3_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The payload hash of requests whose body is streamed without being hashed
+/// first.
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// The SHA-256 block size, in bytes, used by HMAC.
+const HMAC_BLOCK: usize = 64;
+
+/// A bucket of an S3 compatible object store, e.g. AWS S3, MinIO or Garage,
+/// with the credentials to access it. Requests are signed with AWS
+/// Signature Version 4.
+pub struct S3Bucket {
+    /// The name of the bucket.
+    bucket: String,
+    /// The region of the bucket, `us-east-1` unless configured.
+    region: String,
+    /// The endpoint of a self-hosted store, e.g. `http://nas:9000`, whose
+    /// buckets are addressed by path. `None` for AWS, whose buckets are
+    /// addressed by host name.
+    endpoint: Option<String>,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl S3Bucket {
+    /// Configures the bucket from the usual AWS environment variables:
+    /// `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`, and optionally
+    /// `AWS_SESSION_TOKEN`, `AWS_REGION` (or `AWS_DEFAULT_REGION`) and
+    /// `AWS_ENDPOINT_URL` for self-hosted stores.
+    ///
+    /// # Arguments
+    /// * `bucket` - The name of the bucket.
+    ///
+    /// # Returns
+    /// The `S3Bucket`, or an `anyhow::Result` error if no credentials are
+    /// set.
+    pub fn from_env(bucket: &str) -> anyhow::Result<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        Ok(Self {
+            bucket: bucket.to_string(),
+            region: var("AWS_REGION")
+                .or_else(|| var("AWS_DEFAULT_REGION"))
+                .unwrap_or_else(|| "us-east-1".to_string()),
+            endpoint: var("AWS_ENDPOINT_URL").map(|url| url.trim_end_matches('/').to_string()),
+            access_key: var("AWS_ACCESS_KEY_ID")
+                .ok_or_else(|| anyhow!("AWS_ACCESS_KEY_ID is not set"))?,
+            secret_key: var("AWS_SECRET_ACCESS_KEY")
+                .ok_or_else(|| anyhow!("AWS_SECRET_ACCESS_KEY is not set"))?,
+            session_token: var("AWS_SESSION_TOKEN"),
+            agent: ureq::AgentBuilder::new()
+                .timeout(S3_TIMEOUT)
+                .user_agent(concat!("indexer/", env!("CARGO_PKG_VERSION")))
+                .build(),
+        })
+    }
+
+    /// Returns the name of the bucket.
+    pub fn name(&self) -> &str {
+        &self.bucket
+    }
+
+    /// Returns `true` if the bucket holds an object named `key`.
+    pub fn exists(&self, key: &str) -> anyhow::Result<bool> {
+        match self.request("HEAD", key).call() {
+            Ok(_) => Ok(true),
+            Err(ureq::Error::Status(404, _)) => Ok(false),
+            Err(err) => Err(err).with_context(|| format!("look up s3://{}/{key}", self.bucket)),
+        }
+    }
+
+    /// Streams the content of the object named `key`.
+    pub fn get(&self, key: &str) -> anyhow::Result<Box<dyn Read + Send>> {
+        let response = self
+            .request("GET", key)
+            .call()
+            .with_context(|| format!("download s3://{}/{key}", self.bucket))?;
+        Ok(response.into_reader())
+    }
+
+    /// Uploads `body` as the object named `key`, replacing any previous
+    /// one. The body is streamed and its payload left unsigned, so that
+    /// large segment files are not read twice.
+    ///
+    /// # Arguments
+    /// * `key` - The name of the object.
+    /// * `body` - The content of the object.
+    /// * `size` - The length of the content in bytes.
+    pub fn put(&self, key: &str, body: impl Read, size: u64) -> anyhow::Result<()> {
+        self.request("PUT", key)
+            .set("Content-Length", &size.to_string())
+            .send(body)
+            .with_context(|| format!("upload s3://{}/{key}", self.bucket))?;
+        Ok(())
+    }
+
+    /// Builds a signed request for the object named `key`.
+    fn request(&self, method: &str, key: &str) -> ureq::Request {
+        let path = key
+            .split('/')
+            .map(uri_encode)
+            .collect::<Vec<String>>()
+            .join("/");
+        let (url, host, canonical_uri) = match &self.endpoint {
+            Some(endpoint) => {
+                let authority = endpoint
+                    .split_once("://")
+                    .map_or(endpoint.as_str(), |(_, rest)| rest);
+                let host = authority.split('/').next().unwrap_or_default().to_string();
+                let uri = format!("/{}/{path}", uri_encode(&self.bucket));
+                (format!("{endpoint}{uri}"), host, uri)
+            }
+            None => {
+                let host = format!("{}.s3.{}.amazonaws.com", self.bucket, self.region);
+                (format!("https://{host}/{path}"), host, format!("/{path}"))
+            }
+        };
+
+        let now = chrono::Utc::now();
+        let date_time = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let mut headers = vec![
+            ("host", host),
+            ("x-amz-content-sha256", UNSIGNED_PAYLOAD.to_string()),
+            ("x-amz-date", date_time.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let canonical_headers = headers
+            .iter()
+            .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+            .collect::<String>();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<&str>>()
+            .join(";");
+        let canonical_request = format!(
+            "{method}\n{canonical_uri}\n\n{canonical_headers}\n{signed_headers}\n{UNSIGNED_PAYLOAD}"
+        );
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{date_time}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let key = [date.as_str(), &self.region, "s3", "aws4_request"]
+            .iter()
+            .fold(
+                format!("AWS4{}", self.secret_key).into_bytes(),
+                |key, part| hmac_sha256(&key, part.as_bytes()).to_vec(),
+            );
+        let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+
+        let mut request = self.agent.request(method, &url).set(
+            "Authorization",
+            &format!(
+                "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+                self.access_key
+            ),
+        );
+        // The host header is set by the agent from the URL
+        for (name, value) in headers.iter().skip(1) {
+            request = request.set(name, value);
+        }
+        request
+    }
+}
+
+/// Percent-encodes a path segment as AWS signatures expect: every byte but
+/// the unreserved characters.
+fn uri_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Computes the HMAC-SHA256 of `message` under `key`.
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; HMAC_BLOCK];
+    if key.len() > HMAC_BLOCK {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.iter().map(|b| b ^ byte).collect::<Vec<u8>>();
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+/// Formats bytes as lowercase hex.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
@@ -0,0 +1,233 @@
+use anyhow::{Context, anyhow};
+
+use crate::budget::index_size;
+use crate::report::RunReport;
+use crate::stats::IndexStats;
+use crate::{Config, get_docs, get_extensions_map, index_paths};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+/// The exponent of Heaps' law, `terms ∝ tokens^β`, used to project the
+/// number of distinct terms of the full corpus from the sample: the
+/// vocabulary grows much slower than the corpus.
+const HEAPS_EXPONENT: f64 = 0.5;
+
+/// What indexing the sampled documents of one extension cost.
+#[derive(Debug, Clone)]
+pub struct ExtensionCost {
+    /// The file extension, without the dot.
+    pub extension: String,
+    /// The number of files with the extension in the corpus.
+    pub files: u64,
+    /// The number of them that were sampled.
+    pub sampled: u64,
+    /// The size in bytes of every file with the extension in the corpus.
+    pub input_bytes: u64,
+    /// How much the index grew with the sampled files, in bytes.
+    pub index_bytes: u64,
+    /// How long indexing the sampled files took.
+    pub duration: Duration,
+}
+
+impl ExtensionCost {
+    /// Returns how many times larger the corpus is than the sample.
+    fn scale(&self) -> f64 {
+        if self.sampled == 0 {
+            0.0
+        } else {
+            self.files as f64 / self.sampled as f64
+        }
+    }
+
+    /// Returns the projected index size in bytes of every file with the
+    /// extension.
+    pub fn projected_index_bytes(&self) -> u64 {
+        (self.index_bytes as f64 * self.scale()) as u64
+    }
+
+    /// Returns the projected time to index every file with the extension.
+    pub fn projected_duration(&self) -> Duration {
+        self.duration.mul_f64(self.scale())
+    }
+}
+
+/// The outcome of indexing a sample of a corpus, with the projected cost of
+/// indexing all of it.
+#[derive(Debug, Clone)]
+pub struct SampleReport {
+    /// The number of indexable files in the corpus.
+    pub files: u64,
+    /// The number of them that were sampled.
+    pub sampled: u64,
+    /// The size in bytes of the sample index.
+    pub index_bytes: u64,
+    /// The number of distinct terms in the sample index.
+    pub term_count: u64,
+    /// The number of tokens indexed from the sample.
+    pub total_tokens: u64,
+    /// The cost of each extension, sorted by extension.
+    pub extensions: Vec<ExtensionCost>,
+}
+
+impl SampleReport {
+    /// Returns the projected size in bytes of the full index.
+    pub fn projected_index_bytes(&self) -> u64 {
+        self.extensions
+            .iter()
+            .map(ExtensionCost::projected_index_bytes)
+            .sum()
+    }
+
+    /// Returns the projected time to index the full corpus.
+    pub fn projected_duration(&self) -> Duration {
+        self.extensions
+            .iter()
+            .map(ExtensionCost::projected_duration)
+            .sum()
+    }
+
+    /// Returns the projected number of distinct terms in the full index,
+    /// following Heaps' law.
+    pub fn projected_term_count(&self) -> u64 {
+        if self.sampled == 0 {
+            return 0;
+        }
+        let scale = self.files as f64 / self.sampled as f64;
+        (self.term_count as f64 * scale.powf(HEAPS_EXPONENT)) as u64
+    }
+}
+
+/// A xorshift pseudo random number generator, good enough to pick samples.
+pub(crate) struct XorShift(u64);
+
+impl XorShift {
+    /// Creates a generator from `seed`; a zero seed is replaced as xorshift
+    /// would only ever return zero.
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(if seed == 0 {
+            0x9e37_79b9_7f4a_7c15
+        } else {
+            seed
+        })
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Moves a random selection of `count` items to the front of `items`.
+    fn pick<T>(&mut self, items: &mut [T], count: usize) {
+        for i in 0..count.min(items.len()) {
+            let j = i + (self.next() % (items.len() - i) as u64) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+/// Indexes a random sample of the documents under `cfg.filepath` into the
+/// index at `cfg.index_path`, normally a temporary one, measuring what each
+/// extension costs, so that the size and duration of indexing the whole
+/// corpus can be projected. At least one file of every supported extension
+/// is sampled.
+///
+/// # Arguments
+/// * `cfg` - The indexing `Config`, whose `index_path` receives the sample.
+/// * `percent` - The share of the files of each extension to sample, in
+///   percent.
+/// * `seed` - The seed of the random selection, or `None` for a random one.
+///
+/// # Returns
+/// The `SampleReport`, or an `anyhow::Result` error.
+pub fn sample_corpus(
+    cfg: &Config,
+    percent: f64,
+    seed: Option<u64>,
+) -> anyhow::Result<SampleReport> {
+    if !(percent > 0.0 && percent <= 100.0) {
+        return Err(anyhow!(
+            "sample percent must be within (0, 100], not {percent}"
+        ));
+    }
+    let started = Instant::now();
+    let mut report = RunReport::new(&cfg.filepath);
+    let (docs, modified) = get_docs(
+        cfg.filepath.clone(),
+        cfg.hidden,
+        cfg.follow_symlinks,
+        &cfg.skip,
+        cfg.discovery,
+        &mut report,
+    )?;
+
+    let extensions_map = get_extensions_map();
+    let mut by_extension: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for doc in docs {
+        if let Some(ext) = doc.extension().map(|ext| ext.to_string_lossy().to_string())
+            && extensions_map.contains_key(&ext)
+        {
+            by_extension.entry(ext).or_default().push(doc);
+        }
+    }
+
+    let seed = seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default()
+    });
+    let mut rng = XorShift::new(seed);
+    let mut sample = SampleReport {
+        files: 0,
+        sampled: 0,
+        index_bytes: 0,
+        term_count: 0,
+        total_tokens: 0,
+        extensions: Vec::new(),
+    };
+    for (extension, mut docs) in by_extension {
+        let count = ((docs.len() as f64 * percent / 100.0).ceil() as usize).max(1);
+        rng.pick(&mut docs, count);
+        let input_bytes = docs
+            .iter()
+            .filter_map(|doc| fs::metadata(doc).ok())
+            .map(|m| m.len())
+            .sum();
+        let files = docs.len() as u64;
+        docs.truncate(count);
+
+        let size_before = index_size(&cfg.index_path).unwrap_or_default();
+        let indexing_started = Instant::now();
+        let mut run = RunReport::new(&cfg.filepath);
+        run.discovered = docs.len() as u64;
+        index_paths(cfg, docs, &modified, None, run, started)
+            .with_context(|| format!("index sampled .{extension} files"))?;
+        let duration = indexing_started.elapsed();
+        let index_bytes = index_size(&cfg.index_path)
+            .context("measure sample index")?
+            .saturating_sub(size_before);
+
+        sample.files += files;
+        sample.sampled += count as u64;
+        sample.extensions.push(ExtensionCost {
+            extension,
+            files,
+            sampled: count as u64,
+            input_bytes,
+            index_bytes,
+            duration,
+        });
+    }
+
+    sample.index_bytes = index_size(&cfg.index_path).context("measure sample index")?;
+    if let Some(stats) = IndexStats::load(&cfg.index_path, None).context("load sample stats")? {
+        sample.term_count = stats.term_count;
+        sample.total_tokens = stats.total_tokens;
+    }
+    Ok(sample)
+}
@@ -0,0 +1,300 @@
+use anyhow::{Context, anyhow};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::analyzer::{Analyzer, AnalyzerSettings, Language};
+use crate::logger::Logger;
+use crate::parsers::{ParseError, ParseOutcome};
+use crate::privacy::redaction;
+use crate::tree::TermPositions;
+use crate::{Message, get_extensions_map};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::io::{self, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The hidden subcommand running a single parser in a child process.
+pub const PARSE_WORKER_COMMAND: &str = "parse-worker";
+
+/// The memory a sandboxed parser may allocate unless told otherwise.
+pub const DEFAULT_SANDBOX_MEMORY: u64 = 512 * 1024 * 1024;
+
+/// The CPU time a sandboxed parser may use unless told otherwise, in seconds.
+pub const DEFAULT_SANDBOX_CPU_SECONDS: u64 = 30;
+
+/// Parsers still running after this many times their CPU time limit are
+/// killed, catching the ones stuck without using the CPU.
+const WALL_CLOCK_FACTOR: u32 = 2;
+
+/// How often a running worker is checked for completion.
+const WORKER_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The resource limits of parsers run in a child process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SandboxLimits {
+    /// The address space a parser may use, in bytes.
+    pub memory_bytes: u64,
+    /// The CPU time a parser may use, in seconds.
+    pub cpu_seconds: u64,
+}
+
+impl Default for SandboxLimits {
+    fn default() -> Self {
+        Self {
+            memory_bytes: DEFAULT_SANDBOX_MEMORY,
+            cpu_seconds: DEFAULT_SANDBOX_CPU_SECONDS,
+        }
+    }
+}
+
+/// Returns `true` if documents with the extension `ext` are parsed by a
+/// parser risky enough to be sandboxed: large, complex formats read by
+/// third party libraries.
+pub fn is_risky(ext: &str) -> bool {
+    matches!(ext, "pdf" | "html" | "xhtml")
+}
+
+/// What the indexing process sends a worker over its `stdin`: the parts of
+/// the analyzer too large to pass as arguments.
+#[derive(Serialize, Deserialize, Default)]
+struct WorkerInput {
+    /// The abbreviation expansions of the index.
+    expansions: BTreeMap<String, String>,
+    /// The stemming exceptions of the index.
+    stem_exceptions: BTreeMap<String, String>,
+    /// The stop words of the index, if they override those of the analyzer
+    /// language.
+    stop_words: Option<HashSet<String>>,
+}
+
+/// What a worker sends back to the indexing process over its `stdout`.
+#[derive(Serialize, Deserialize)]
+struct WorkerOutput {
+    terms: TermPositions,
+    errors: Vec<ParseError>,
+    suppressed: usize,
+    title: Option<String>,
+    language: Option<Language>,
+    /// The display forms of the words of the document.
+    display_forms: HashMap<String, String>,
+}
+
+/// Parses a document in a child process limited to `limits`, so that a
+/// crashing or looping parser only fails this document. The worker is the
+/// current executable run with `PARSE_WORKER_COMMAND`, limited with
+/// `setrlimit` before it starts.
+///
+/// # Arguments
+/// * `doc` - The file to parse.
+/// * `ext` - The extension picking the parser.
+/// * `analyzer` - The analyzer of the index.
+/// * `limits` - The resource limits of the worker.
+///
+/// # Returns
+/// The `ParseOutcome` of the worker, or a fatal one if it crashed, ran out
+/// of time or could not be started.
+pub fn parse_sandboxed(
+    doc: &Path,
+    ext: &str,
+    analyzer: &Analyzer,
+    limits: &SandboxLimits,
+) -> ParseOutcome {
+    match run_worker(doc, ext, analyzer, limits) {
+        Ok(output) => ParseOutcome {
+            terms: output.terms,
+            errors: output.errors,
+            suppressed: output.suppressed,
+            title: output.title,
+            language: output.language,
+            display_forms: output.display_forms,
+            ..Default::default()
+        },
+        Err(err) => ParseOutcome::fatal(format!("sandboxed parser: {err:#}")),
+    }
+}
+
+/// Starts a worker on `doc` and waits for its output.
+fn run_worker(
+    doc: &Path,
+    ext: &str,
+    analyzer: &Analyzer,
+    limits: &SandboxLimits,
+) -> anyhow::Result<WorkerOutput> {
+    let exe = std::env::current_exe().context("locate indexer executable")?;
+    let settings = serde_json::to_string(&analyzer.settings).context("serialize analyzer")?;
+    let mut args: Vec<OsString> = Vec::new();
+    // Global flags go before the subcommand
+    if let Some(redaction) = redaction()
+        && let Some(value) = redaction.to_possible_value()
+    {
+        args.push("--redact-logs".into());
+        args.push(value.get_name().into());
+    }
+    args.extend([
+        PARSE_WORKER_COMMAND.into(),
+        "--ext".into(),
+        ext.into(),
+        "--analyzer".into(),
+        settings.into(),
+    ]);
+    if let Some(chunk) = analyzer.stream_chunk {
+        args.push("--stream-chunk".into());
+        args.push(chunk.to_string().into());
+    }
+    // Non UTF-8 file names are passed as they are
+    args.push("--".into());
+    args.push(doc.into());
+
+    let input = WorkerInput {
+        expansions: analyzer.expansions.clone().into_iter().collect(),
+        stem_exceptions: analyzer.stem_exceptions.clone().into_iter().collect(),
+        stop_words: (!Arc::ptr_eq(&analyzer.stop_words, &analyzer.settings.stop_words()))
+            .then(|| (*analyzer.stop_words).clone()),
+    };
+    let input = serde_json::to_vec(&input).context("serialize analyzer")?;
+
+    let mut command = Command::new(&exe);
+    command
+        .args(&args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let (memory, cpu) = (
+        limits.memory_bytes as libc::rlim_t,
+        limits.cpu_seconds as libc::rlim_t,
+    );
+    // SAFETY: the closure runs in the child between fork and exec, where
+    // it only calls `setrlimit`, which is async-signal-safe
+    unsafe {
+        command.pre_exec(move || {
+            for (resource, limit) in [(libc::RLIMIT_AS, memory), (libc::RLIMIT_CPU, cpu)] {
+                let limit = libc::rlimit {
+                    rlim_cur: limit,
+                    rlim_max: limit,
+                };
+                if libc::setrlimit(resource, &limit) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+    let mut child = command.spawn().context("start parse worker")?;
+    // Written from another thread, so that a worker failing before it
+    // reads its input cannot block the indexing process
+    if let Some(mut stdin) = child.stdin.take() {
+        thread::spawn(move || {
+            let _ = stdin.write_all(&input);
+        });
+    }
+
+    let timeout = Duration::from_secs(limits.cpu_seconds) * WALL_CLOCK_FACTOR;
+    let (status, stdout, stderr) = wait_with_timeout(child, timeout)?;
+    if !status.success() {
+        let stderr = String::from_utf8_lossy(&stderr);
+        let last_line = stderr.trim().lines().last().unwrap_or_default();
+        return Err(anyhow!("parser crashed ({status}): {last_line}"));
+    }
+    serde_json::from_slice(&stdout).context("read parse worker output")
+}
+
+/// Waits for `child` to exit while draining its output, killing it once
+/// `timeout` is over.
+///
+/// # Returns
+/// The exit status, `stdout` and `stderr` of the child, or an
+/// `anyhow::Result` error if it timed out.
+fn wait_with_timeout(
+    mut child: Child,
+    timeout: Duration,
+) -> anyhow::Result<(ExitStatus, Vec<u8>, Vec<u8>)> {
+    let drain = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut buf = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buf);
+            }
+            buf
+        })
+    };
+    let stdout = drain(child.stdout.take().map(|p| Box::new(p) as _));
+    let stderr = drain(child.stderr.take().map(|p| Box::new(p) as _));
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().context("wait for parse worker")? {
+            break status;
+        }
+        if started.elapsed() > timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow!("parser timed out after {}s", timeout.as_secs()));
+        }
+        thread::sleep(WORKER_POLL_INTERVAL);
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    Ok((status, stdout, stderr))
+}
+
+/// Runs one parser and writes its outcome to `stdout` as JSON: the body of
+/// the `PARSE_WORKER_COMMAND` subcommand. The expansions, stemming
+/// exceptions and stop words of the index are read from `stdin`. Messages
+/// sent by the parser are printed to `stderr`.
+///
+/// # Arguments
+/// * `doc` - The file to parse.
+/// * `ext` - The extension picking the parser.
+/// * `settings` - The analyzer settings of the index, as JSON.
+/// * `stream_chunk` - The size of the chunks text is analyzed in, if any.
+///
+/// # Returns
+/// `Ok(())` once the outcome is written, otherwise an `anyhow::Result` error.
+pub fn parse_worker(
+    doc: &Path,
+    ext: &str,
+    settings: &str,
+    stream_chunk: Option<usize>,
+) -> anyhow::Result<()> {
+    let settings: AnalyzerSettings =
+        serde_json::from_str(settings).context("read analyzer settings")?;
+    let input: WorkerInput =
+        serde_json::from_reader(io::stdin().lock()).context("read worker input")?;
+    let stop_words = match input.stop_words {
+        Some(stop_words) => Arc::new(stop_words),
+        None => settings.stop_words(),
+    };
+    let analyzer = Analyzer::new(settings, stop_words)
+        .with_stream_chunk(stream_chunk)
+        .with_expansions(&input.expansions)
+        .with_stem_exceptions(&input.stem_exceptions);
+    let parser = *get_extensions_map()
+        .get(ext)
+        .ok_or_else(|| anyhow!("no parser for .{ext} documents"))?;
+
+    let (logger, receiver) = Logger::channel();
+    let outcome = parser(doc, &logger, &analyzer);
+    for message in receiver.try_iter() {
+        if let Message::Error(msg) | Message::Info(msg) | Message::Debug(msg) = message {
+            eprintln!("{msg}");
+        }
+    }
+
+    let output = WorkerOutput {
+        terms: outcome.terms,
+        errors: outcome.errors,
+        suppressed: outcome.suppressed,
+        title: outcome.title,
+        language: outcome.language,
+        display_forms: outcome.display_forms,
+    };
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer(&mut stdout, &output).context("write parse outcome")?;
+    stdout.flush().context("write parse outcome")
+}
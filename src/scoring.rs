@@ -0,0 +1,413 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::analyzer::{HEADING_PREFIX, PATH_PREFIX, TITLE_PREFIX};
+use crate::tree::DocInfo;
+
+/// The ranking algorithms that can be selected to score search results.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Ranker {
+    /// Term frequency, normalized by document length with
+    /// `TFIDF_LENGTH_SLOPE`, multiplied by inverse document frequency.
+    #[default]
+    Tfidf,
+    /// Okapi BM25 with document length normalization.
+    Bm25,
+    /// The scorer registered on the `MainIndex` by a library user.
+    Custom,
+}
+
+/// The orders search results can be sorted in. Results equal in the chosen
+/// order are sorted by score.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// Best scored first.
+    #[default]
+    Score,
+    /// Most recently modified first. Documents without a known modification
+    /// time, e.g. virtual ones, count as modified when they were indexed.
+    Mtime,
+    /// By path, in alphabetical order.
+    Path,
+    /// Largest file first. Documents of unknown size come last.
+    Size,
+}
+
+/// The statistics of one query term within one document, handed to a
+/// `Scorer` to compute that term's contribution to the document score.
+pub struct TermStats {
+    /// How many times the term appears in the document.
+    pub tf: u32,
+    /// How many documents of the index contain the term.
+    pub df: u64,
+    /// The total number of documents in the index.
+    pub total_docs: u64,
+    /// The number of tokens in the document.
+    pub doc_len: u64,
+    /// The average number of tokens per document in the index.
+    pub avg_doc_len: f64,
+}
+
+impl TermStats {
+    /// Returns the length of the document relative to the average document
+    /// length, or 1 if the average is unknown.
+    pub fn length_ratio(&self) -> f64 {
+        if self.avg_doc_len > 0.0 {
+            self.doc_len as f64 / self.avg_doc_len
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Computes the score contribution of a query term for a document.
+/// The final score of a document is the sum of the contributions of every
+/// query term it contains.
+///
+/// Implementations can use the document metadata to rank some documents
+/// above others, e.g. to boost `docs/` over `archive/`:
+///
+/// ```
+/// use indexer::scoring::{Scorer, TermStats, TfIdf};
+/// use indexer::tree::DocInfo;
+///
+/// struct PreferDocs;
+///
+/// impl Scorer for PreferDocs {
+///     fn score(&self, stats: &TermStats, doc: &DocInfo) -> f64 {
+///         let base = TfIdf.score(stats, doc);
+///         if doc.path.starts_with("docs") { base * 2.0 } else { base }
+///     }
+/// }
+/// ```
+pub trait Scorer: Send + Sync {
+    /// Returns the score contribution of the term described by `stats`
+    /// within the document described by `doc`.
+    fn score(&self, stats: &TermStats, doc: &DocInfo) -> f64;
+
+    /// Returns how strongly the proximity of the query terms in a document
+    /// raises its summed score, see `proximity_score`. Custom scorers are
+    /// not boosted unless they return a weight, e.g. `PROXIMITY_WEIGHT`.
+    fn proximity_weight(&self) -> f64 {
+        0.0
+    }
+}
+
+/// Any thread safe closure taking the term statistics and document metadata
+/// can be used as a `Scorer`.
+impl<F> Scorer for F
+where
+    F: Fn(&TermStats, &DocInfo) -> f64 + Send + Sync,
+{
+    fn score(&self, stats: &TermStats, doc: &DocInfo) -> f64 {
+        self(stats, doc)
+    }
+}
+
+impl Ranker {
+    /// Returns the inverse document frequency the ranker weights a term by.
+    ///
+    /// # Arguments
+    /// * `df` - The number of documents containing the term.
+    /// * `total_docs` - The number of documents in the index.
+    ///
+    /// # Returns
+    /// The IDF, or `None` for the custom ranker, whose weighting is unknown.
+    pub fn idf(&self, df: u64, total_docs: u64) -> Option<f64> {
+        match self {
+            Self::Tfidf => Some(tfidf_idf(df, total_docs)),
+            Self::Bm25 => Some(bm25_idf(df, total_docs)),
+            Self::Custom => None,
+        }
+    }
+
+    /// Returns the highest score contribution a term can make to a document
+    /// holding it at most `max_tf` times: its contribution to an empty
+    /// document, as shorter documents score higher. Searches wanting only
+    /// the best results skip the documents these bounds keep out of them.
+    ///
+    /// # Arguments
+    /// * `bm25` - The parameters of the BM25 ranker.
+    /// * `max_tf` - The highest frequency of the term in a document.
+    /// * `df` - The number of documents containing the term.
+    /// * `total_docs` - The number of documents in the index.
+    ///
+    /// # Returns
+    /// The bound, or `None` for the custom ranker, whose scores are unknown.
+    pub fn max_score(&self, bm25: &Bm25, max_tf: u32, df: u64, total_docs: u64) -> Option<f64> {
+        let stats = TermStats {
+            tf: max_tf,
+            df,
+            total_docs,
+            doc_len: 0,
+            avg_doc_len: 1.0,
+        };
+        match self {
+            Self::Tfidf => Some(TfIdf.term_score(&stats)),
+            Self::Bm25 => Some(bm25.term_score(&stats)),
+            Self::Custom => None,
+        }
+    }
+}
+
+/// The IDF of TF-IDF: `ln(total_docs / df)`.
+fn tfidf_idf(df: u64, total_docs: u64) -> f64 {
+    (total_docs as f64 / df as f64).ln().abs()
+}
+
+/// The IDF of BM25: `ln(1 + (total_docs - df + 0.5) / (df + 0.5))`.
+fn bm25_idf(df: u64, total_docs: u64) -> f64 {
+    let total_docs = total_docs as f64;
+    let df = df as f64;
+    (1.0 + (total_docs - df + 0.5) / (df + 0.5)).ln()
+}
+
+/// How strongly TF-IDF normalizes term frequencies by document length,
+/// from 0 (raw counts) to 1 (counts relative to the length of the document
+/// over the average one).
+pub const TFIDF_LENGTH_SLOPE: f64 = 0.75;
+
+/// The TF-IDF scorer: `tf / (1 - s + s * doc_len / avg_doc_len) *
+/// ln(total_docs / df)`, `s` being `TFIDF_LENGTH_SLOPE`. Normalizing the term
+/// frequency by the document length keeps long documents from outranking
+/// short ones for mentioning a term as often relative to their length.
+pub struct TfIdf;
+
+impl TfIdf {
+    /// Returns the score contribution of the term described by `stats`.
+    fn term_score(&self, stats: &TermStats) -> f64 {
+        let norm = 1.0 - TFIDF_LENGTH_SLOPE + TFIDF_LENGTH_SLOPE * stats.length_ratio();
+        stats.tf as f64 / norm * tfidf_idf(stats.df, stats.total_docs)
+    }
+}
+
+impl Scorer for TfIdf {
+    fn score(&self, stats: &TermStats, _doc: &DocInfo) -> f64 {
+        self.term_score(stats)
+    }
+
+    fn proximity_weight(&self) -> f64 {
+        PROXIMITY_WEIGHT
+    }
+}
+
+/// The Okapi BM25 scorer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bm25 {
+    /// Term frequency saturation parameter.
+    pub k1: f64,
+    /// Document length normalization parameter, between 0 and 1.
+    pub b: f64,
+}
+
+impl Default for Bm25 {
+    /// Returns the commonly used parameters `k1 = 1.2` and `b = 0.75`.
+    fn default() -> Self {
+        Self { k1: 1.2, b: 0.75 }
+    }
+}
+
+impl Bm25 {
+    /// Creates a BM25 scorer with the given parameters.
+    ///
+    /// # Arguments
+    /// * `k1` - The term frequency saturation, at least 0.
+    /// * `b` - The document length normalization, between 0 and 1.
+    ///
+    /// # Returns
+    /// The `Bm25` scorer, or an `anyhow::Result` error if a parameter is out
+    /// of range.
+    pub fn new(k1: f64, b: f64) -> anyhow::Result<Self> {
+        if !(k1 >= 0.0 && k1.is_finite()) {
+            return Err(anyhow!("BM25 k1 must be a non-negative number, got {k1}"));
+        }
+        if !(0.0..=1.0).contains(&b) {
+            return Err(anyhow!("BM25 b must be between 0 and 1, got {b}"));
+        }
+        Ok(Self { k1, b })
+    }
+
+    /// Returns the score contribution of the term described by `stats`.
+    fn term_score(&self, stats: &TermStats) -> f64 {
+        let idf = bm25_idf(stats.df, stats.total_docs);
+
+        let tf = stats.tf as f64;
+        let norm = self.k1 * (1.0 - self.b + self.b * stats.length_ratio());
+
+        idf * (tf * (self.k1 + 1.0)) / (tf + norm)
+    }
+}
+
+impl Scorer for Bm25 {
+    fn score(&self, stats: &TermStats, _doc: &DocInfo) -> f64 {
+        self.term_score(stats)
+    }
+
+    fn proximity_weight(&self) -> f64 {
+        PROXIMITY_WEIGHT
+    }
+}
+
+/// The factor applied to the score contribution of a query term matching
+/// the path of a document rather than its content, as a file or directory
+/// name usually says what the document is about.
+pub const PATH_FIELD_BOOST: f64 = 2.0;
+
+/// The factor applied to the score contribution of a query term matching
+/// the title of a document, which names what the whole document is about.
+pub const TITLE_FIELD_BOOST: f64 = 5.0;
+
+/// The factor applied to the score contribution of a query term matching
+/// a heading of a document, naming what one of its sections is about.
+pub const HEADING_FIELD_BOOST: f64 = 2.0;
+
+/// The fields query terms are matched against, as named in field weights.
+const FIELDS: [&str; 4] = ["body", "title", "heading", "path"];
+
+/// The factors applied to the score contributions of query terms by the
+/// field they match, chosen per query to tune relevance without rebuilding
+/// the index.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FieldWeights {
+    /// The weight of terms matching the content of a document.
+    pub body: f64,
+    /// The weight of terms matching the path of a document.
+    pub path: f64,
+    /// The weight of terms matching the title of a document.
+    pub title: f64,
+    /// The weight of terms matching a heading of a document.
+    pub heading: f64,
+}
+
+impl Default for FieldWeights {
+    /// Returns a weight of 1 for the body, `PATH_FIELD_BOOST` for the path,
+    /// `TITLE_FIELD_BOOST` for the title and `HEADING_FIELD_BOOST` for the
+    /// headings.
+    fn default() -> Self {
+        Self {
+            body: 1.0,
+            path: PATH_FIELD_BOOST,
+            title: TITLE_FIELD_BOOST,
+            heading: HEADING_FIELD_BOOST,
+        }
+    }
+}
+
+impl FieldWeights {
+    /// Returns the weight of the field of an index term, told by its prefix.
+    pub fn of_term(&self, term: &str) -> f64 {
+        if term.starts_with(PATH_PREFIX) {
+            self.path
+        } else if term.starts_with(TITLE_PREFIX) {
+            self.title
+        } else if term.starts_with(HEADING_PREFIX) {
+            self.heading
+        } else {
+            self.body
+        }
+    }
+
+    /// Parses field weights written as `field^weight`, separated by spaces
+    /// or commas, e.g. `body^1 title^8`. Fields left out keep their default
+    /// weight.
+    ///
+    /// # Arguments
+    /// * `spec` - The field weights.
+    ///
+    /// # Returns
+    /// The `FieldWeights`, or an `anyhow::Result` error naming the unknown
+    /// field or invalid weight.
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let mut weights = Self::default();
+        for item in spec
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|item| !item.is_empty())
+        {
+            let (field, weight) = item
+                .split_once('^')
+                .ok_or_else(|| anyhow!("expected field^weight, got {item:?}"))?;
+            let weight = weight
+                .parse::<f64>()
+                .ok()
+                .filter(|weight| weight.is_finite() && *weight >= 0.0)
+                .ok_or_else(|| {
+                    anyhow!("weight of {field} must be a non-negative number, got {weight:?}")
+                })?;
+            match field {
+                "body" => weights.body = weight,
+                "path" => weights.path = weight,
+                "title" => weights.title = weight,
+                "heading" => weights.heading = weight,
+                _ => {
+                    return Err(anyhow!(
+                        "unknown field {field:?}, expected one of {}",
+                        FIELDS.join(", ")
+                    ));
+                }
+            }
+        }
+        Ok(weights)
+    }
+}
+
+/// How strongly the proximity of query terms boosts a document score with
+/// the built-in rankers. A document containing all query terms next to each
+/// other has its score multiplied by `1 + PROXIMITY_WEIGHT`.
+pub const PROXIMITY_WEIGHT: f64 = 0.5;
+
+/// Computes how close together the query terms occur in a document, from
+/// the smallest window of positions containing every term.
+///
+/// # Arguments
+/// * `positions` - The ascending positions of each matched query term.
+///
+/// # Returns
+/// `terms / window`, i.e. `1.0` if the terms occur next to each other,
+/// approaching `0.0` as they drift apart, and `0.0` if fewer than two terms
+/// matched.
+pub fn proximity_score(positions: &[&[u32]]) -> f64 {
+    let positions: Vec<&[u32]> = positions
+        .iter()
+        .filter(|p| !p.is_empty())
+        .copied()
+        .collect();
+    if positions.len() < 2 {
+        return 0.0;
+    }
+
+    let mut events: Vec<(u32, usize)> = positions
+        .iter()
+        .enumerate()
+        .flat_map(|(term, list)| list.iter().map(move |&pos| (pos, term)))
+        .collect();
+    events.sort_unstable();
+
+    // Slide a window over the sorted positions, shrinking it from the left
+    // while it still holds every term
+    let mut counts = vec![0usize; positions.len()];
+    let mut covered = 0;
+    let mut start = 0;
+    let mut min_window = u32::MAX;
+    for &(pos, term) in &events {
+        if counts[term] == 0 {
+            covered += 1;
+        }
+        counts[term] += 1;
+
+        while covered == positions.len() {
+            let (start_pos, start_term) = events[start];
+            min_window = min_window.min(pos - start_pos + 1);
+            counts[start_term] -= 1;
+            if counts[start_term] == 0 {
+                covered -= 1;
+            }
+            start += 1;
+        }
+    }
+
+    // Terms sharing a position (e.g. an exact form and its stem) can make
+    // the window smaller than the number of terms
+    (positions.len() as f64 / min_window as f64).min(1.0)
+}
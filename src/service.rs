@@ -0,0 +1,280 @@
+use anyhow::Context;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+
+use crate::cache::{DEFAULT_POSTINGS_CACHE_BYTES, DEFAULT_RESULTS_CACHE_ENTRIES, PostingsCache};
+use crate::logger::Logger;
+use crate::observer::LoggingObserver;
+use crate::privacy::log_path;
+use crate::server::{DEFAULT_BIND_ADDRESS, ServedIndex, default_workers};
+use crate::settings::Settings;
+use crate::skip::SkipRules;
+use crate::sniff::Sniffing;
+use crate::walker::{Discovery, IndexOrder};
+use crate::{Config, ErrorHandler, SearchOptions, detect_moves, index_documents};
+
+use std::fs;
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+#[cfg(target_os = "linux")]
+use std::os::unix::net::SocketAddr;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the service checks for signals and due re-indexing runs.
+const SERVICE_TICK: Duration = Duration::from_secs(1);
+
+/// The file in the index directory holding the pid of the running service.
+const PID_FILE: &str = "indexer.pid";
+
+/// Options of `indexer service`.
+pub struct ServiceOptions {
+    /// The directory of the index kept up to date and served.
+    pub index_dir: PathBuf,
+    /// The port the search server listens on.
+    pub port: u16,
+    /// The configuration file, read again on `SIGHUP`.
+    pub config_file: PathBuf,
+    /// Where errors and informational messages are written.
+    pub error_handler: ErrorHandler,
+    /// The key file of an encrypted index, if any.
+    pub key_file: Option<PathBuf>,
+    /// The rules excluding paths from the re-indexing runs.
+    pub skip: SkipRules,
+}
+
+/// Sends a state notification (e.g. `READY=1`) to the service manager over
+/// the socket named by `NOTIFY_SOCKET`, as `sd_notify(3)` does. Does nothing
+/// when not started by systemd. Sockets in the abstract namespace only exist
+/// on Linux; elsewhere only path sockets are supported.
+///
+/// # Arguments
+/// * `state` - The newline separated `KEY=VALUE` assignments to send.
+///
+/// # Returns
+/// An `anyhow::Result` error if the notification cannot be sent.
+pub fn sd_notify(state: &str) -> anyhow::Result<()> {
+    let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+    let socket = socket.to_string_lossy();
+    let datagram = UnixDatagram::unbound().context("create notify socket")?;
+    // A leading `@` names a socket in the abstract namespace
+    match socket.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            let addr = SocketAddr::from_abstract_name(name).context("notify socket address")?;
+            datagram.send_to_addr(state.as_bytes(), &addr)
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {
+            return Err(anyhow::anyhow!(
+                "notify socket {socket} is in the abstract namespace, which only Linux has"
+            ));
+        }
+        None => datagram.send_to(state.as_bytes(), socket.as_ref()),
+    }
+    .context("send notification")?;
+    Ok(())
+}
+
+/// Removes the pid file when the service stops.
+struct PidFile(PathBuf);
+
+impl PidFile {
+    /// Writes the pid of the current process to `path`.
+    fn create(path: PathBuf) -> anyhow::Result<Self> {
+        fs::write(&path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("write pid file {path:?}"))?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Returns the indexing `Config` of one watched path.
+///
+/// # Arguments
+/// * `path` - The watched file or directory.
+/// * `settings` - The current settings.
+/// * `options` - The service options.
+/// * `logger` - The log of the service.
+fn watch_config(
+    path: &Path,
+    settings: &Settings,
+    options: &ServiceOptions,
+    logger: &Logger,
+) -> Config {
+    Config {
+        hidden: false,
+        error_handler: options.error_handler.clone(),
+        filepath: path.to_path_buf(),
+        index_path: options.index_dir.clone(),
+        logger: logger.clone(),
+        skip: options.skip.clone(),
+        ranker: None,
+        auto_compact: settings.auto_compact,
+        analyzer: None,
+        key_file: options.key_file.clone(),
+        raw_key: false,
+        throttle: true,
+        discovery: Discovery::default(),
+        keep_history: false,
+        keep_duplicates: false,
+        budget: None,
+        quota: None,
+        profile: None,
+        segment_budget: None,
+        sandbox: None,
+        resume: false,
+        policies: settings.policies.clone(),
+        tags: Vec::new(),
+        tag_rules: settings.tags.clone(),
+        expansions: None,
+        stem_exceptions: None,
+        stop_words: None,
+        noise: None,
+        time_budget: None,
+        order: IndexOrder::default(),
+        priorities: settings.priorities.clone(),
+        sniffing: Sniffing::default(),
+        max_file_size: None,
+        follow_symlinks: false,
+        progress: None,
+        observer: Some(Arc::new(LoggingObserver::new(logger.clone()))),
+    }
+}
+
+/// Runs the indexer as a long lived service: serves the index over HTTP and
+/// re-indexes the paths listed by `watch` directives every `watch_interval`.
+/// The service writes its pid to `indexer.pid` in the index directory,
+/// notifies systemd once it is ready, reloads the configuration file on
+/// `SIGHUP` and stops on `SIGTERM` or `SIGINT`.
+///
+/// The search options (boosts) are read once when the server starts; a
+/// reload updates the watched paths, the interval and `auto_compact`.
+///
+/// # Arguments
+/// * `options` - The `ServiceOptions`.
+///
+/// # Returns
+/// `Ok(())` once the service was asked to stop, or an `anyhow::Result`
+/// error if it cannot start.
+pub fn run_service(options: ServiceOptions) -> anyhow::Result<()> {
+    let mut settings = Settings::load(&options.config_file)?;
+    fs::create_dir_all(&options.index_dir).context("create index dir")?;
+    let _pid_file = PidFile::create(options.index_dir.join(PID_FILE))?;
+
+    let reload = Arc::new(AtomicBool::new(false));
+    let stop = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(SIGHUP, Arc::clone(&reload)).context("handle SIGHUP")?;
+    for signal in [SIGTERM, SIGINT] {
+        signal_hook::flag::register(signal, Arc::clone(&stop)).context("handle signal")?;
+    }
+
+    // The server and the indexing runs log to the same place for as long
+    // as the service runs
+    let (logger, logs_handler) = Logger::spawn(options.error_handler.clone());
+    let search_options = SearchOptions {
+        boosts: settings.boosts.clone(),
+        bm25: settings.bm25,
+        key_file: options.key_file.clone(),
+        postings_cache: Some(Arc::new(PostingsCache::new(DEFAULT_POSTINGS_CACHE_BYTES))),
+        ..Default::default()
+    };
+    let served = ServedIndex {
+        prefix: String::new(),
+        index_file: options.index_dir.clone(),
+        options: search_options,
+        preload: None,
+        results_cache: DEFAULT_RESULTS_CACHE_ENTRIES,
+        token: None,
+        indexing: None,
+        allow_shutdown: false,
+        cors_origins: Vec::new(),
+    };
+    let port = options.port;
+    let server_logger = logger.clone();
+    thread::spawn(move || {
+        if let Err(err) = crate::server::run_server(
+            vec![served],
+            DEFAULT_BIND_ADDRESS,
+            port,
+            None,
+            default_workers(),
+            server_logger.clone(),
+            |_| {},
+        ) {
+            server_logger.error(format!("Search server stopped: {err}"));
+        }
+    });
+
+    sd_notify(&format!(
+        "READY=1\nSTATUS=Watching {} paths\nMAINPID={}",
+        settings.watch.len(),
+        std::process::id()
+    ))?;
+    logger.info(format!(
+        "Service started, watching {} paths every {}s",
+        settings.watch.len(),
+        settings.watch_interval.as_secs()
+    ));
+
+    let mut next_run = Instant::now();
+    while !stop.load(Ordering::Relaxed) {
+        if reload.swap(false, Ordering::Relaxed) {
+            sd_notify("RELOADING=1")?;
+            match Settings::load(&options.config_file) {
+                Ok(reloaded) => {
+                    settings = reloaded;
+                    next_run = Instant::now();
+                    logger.info(format!("Reloaded {:?}", options.config_file));
+                }
+                Err(err) => logger.error(format!("Keeping the previous configuration: {err:#}")),
+            }
+            sd_notify(&format!(
+                "READY=1\nSTATUS=Watching {} paths",
+                settings.watch.len()
+            ))?;
+        }
+
+        if Instant::now() >= next_run {
+            // Files moved from one watched path to another keep their
+            // documents rather than being deleted by the run of the first
+            if let Some(first) = settings.watch.first() {
+                let cfg = watch_config(first, &settings, &options, &logger);
+                match detect_moves(&cfg, &settings.watch) {
+                    Ok(moves) if !moves.is_empty() => {
+                        logger.info(format!("{} documents moved", moves.len()))
+                    }
+                    Ok(_) => {}
+                    Err(err) => logger.error(format!("Failed to detect moved files: {err:#}")),
+                }
+            }
+            for path in &settings.watch {
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                let cfg = watch_config(path, &settings, &options, &logger);
+                if let Err(err) = index_documents(&cfg) {
+                    logger.error(format!("Failed to index {}: {err:#}", log_path(path)));
+                }
+            }
+            next_run = Instant::now() + settings.watch_interval;
+        }
+        thread::sleep(SERVICE_TICK);
+    }
+
+    sd_notify("STOPPING=1")?;
+    logger.info("Service stopped");
+    logger.stop();
+    logs_handler.join().unwrap();
+    Ok(())
+}
@@ -0,0 +1,297 @@
+use anyhow::{Context, anyhow};
+
+use crate::glob::glob_match_path;
+use crate::policy::{IndexPolicy, PolicyRule};
+use crate::scoring::Bm25;
+use crate::tags::{TagRule, validate_tag};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// A rule multiplying the score of documents whose path matches `pattern`
+/// by `factor`.
+#[derive(Clone, Debug)]
+pub struct BoostRule {
+    /// The glob pattern matched against document paths.
+    pub pattern: String,
+    /// The factor applied to the score of matching documents.
+    pub factor: f64,
+}
+
+impl BoostRule {
+    /// Returns `true` if the rule applies to the document at `path`.
+    pub fn matches(&self, path: &Path) -> bool {
+        glob_match_path(&self.pattern, path)
+    }
+}
+
+/// An index hosted by `indexer serve` under a URL prefix.
+#[derive(Clone, Debug)]
+pub struct IndexRoute {
+    /// The URL prefix of the routes of the index, e.g. `notes` for
+    /// `/notes/query`.
+    pub prefix: String,
+    /// The directory of the index.
+    pub path: PathBuf,
+    /// The token requests must present to search the index, if any.
+    pub token: Option<String>,
+}
+
+/// The number of small segments tolerated before they are merged
+/// automatically, unless configured otherwise.
+const DEFAULT_AUTO_COMPACT: usize = 16;
+
+/// How often the service re-indexes the watched paths, unless configured
+/// otherwise.
+const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(300);
+
+/// User settings read from the configuration file.
+///
+/// The file contains one directive per line; empty lines and lines starting
+/// with `#` are ignored. Supported directives:
+///
+/// ```text
+/// # Rank READMEs higher and archived documents lower
+/// boost "**/README*" 2.0
+/// boost "**/archive/**" 0.3
+/// # Index only the names of archived files, keep the text of notes for
+/// # snippets and leave the words of source code unstemmed
+/// policy "archive/" filenames
+/// policy "notes/" store_text
+/// policy "code/" no_stemming
+/// # Tag the documents of a project, for `tag:project=alpha` filters
+/// tag "work/alpha/" project=alpha
+/// # With `--order priority`, index the documents under `inbox` first,
+/// # then the PDFs
+/// priority "**/inbox/**"
+/// priority "*.pdf"
+/// # BM25 parameters k1 and b
+/// bm25 1.5 0.6
+/// # Merge small segments once there are more than 32 of them (or `off`)
+/// auto_compact 32
+/// # Paths re-indexed by `indexer service`, every 10 minutes
+/// watch "/home/me/Documents"
+/// watch_interval 600
+/// # Indexes hosted by `indexer serve` under /notes/ and /code/, the second
+/// # one only for requests presenting its token
+/// serve "notes" "/home/me/.indexer-notes"
+/// serve "code" "/home/me/.indexer-code" "s3cret"
+/// ```
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Score boost rules applied to matching document paths.
+    pub boosts: Vec<BoostRule>,
+    /// Rules changing how the documents under some directories are indexed.
+    pub policies: Vec<PolicyRule>,
+    /// Rules tagging the documents under some directories.
+    pub tags: Vec<TagRule>,
+    /// The glob patterns of the documents indexed first with
+    /// `--order priority`, most important first.
+    pub priorities: Vec<String>,
+    /// The parameters of the BM25 ranker.
+    pub bm25: Bm25,
+    /// The number of small segments tolerated before they are merged on
+    /// startup, or `None` to disable automatic compaction.
+    pub auto_compact: Option<usize>,
+    /// The paths the service keeps indexed.
+    pub watch: Vec<PathBuf>,
+    /// How often the service re-indexes the watched paths.
+    pub watch_interval: Duration,
+    /// The indexes hosted by `indexer serve`, each under its own prefix.
+    pub routes: Vec<IndexRoute>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            boosts: Vec::new(),
+            policies: Vec::new(),
+            tags: Vec::new(),
+            priorities: Vec::new(),
+            bm25: Bm25::default(),
+            auto_compact: Some(DEFAULT_AUTO_COMPACT),
+            watch: Vec::new(),
+            watch_interval: DEFAULT_WATCH_INTERVAL,
+            routes: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Loads the settings from the configuration file at `path`, returning the
+    /// default settings if the file does not exist.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the configuration file.
+    ///
+    /// # Returns
+    /// The parsed `Settings`, or an `anyhow::Result` error naming the
+    /// offending line.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path).context("read config file")?;
+        Self::parse(&content).with_context(|| format!("parse config file {path:?}"))
+    }
+
+    /// Parses the content of a configuration file.
+    ///
+    /// # Arguments
+    /// * `content` - The configuration file content.
+    ///
+    /// # Returns
+    /// The parsed `Settings`, or an `anyhow::Result` error.
+    pub fn parse(content: &str) -> anyhow::Result<Self> {
+        let mut settings = Self::default();
+
+        for (line_no, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let args = split_args(line).map_err(|err| anyhow!("line {}: {err}", line_no + 1))?;
+            match args.as_slice() {
+                [directive, pattern, factor] if directive == "boost" => {
+                    let factor = factor.parse::<f64>().map_err(|err| {
+                        anyhow!("line {}: invalid boost factor {factor:?}: {err}", line_no + 1)
+                    })?;
+                    settings.boosts.push(BoostRule {
+                        pattern: pattern.to_string(),
+                        factor,
+                    });
+                }
+                [directive, pattern, options @ ..] if directive == "policy" => {
+                    let policy = IndexPolicy::parse(options)
+                        .map_err(|err| anyhow!("line {}: {err}", line_no + 1))?;
+                    settings.policies.push(PolicyRule {
+                        pattern: pattern.to_string(),
+                        policy,
+                    });
+                }
+                [directive, pattern, tags @ ..] if directive == "tag" && !tags.is_empty() => {
+                    for tag in tags {
+                        validate_tag(tag).map_err(|err| anyhow!("line {}: {err}", line_no + 1))?;
+                    }
+                    settings.tags.push(TagRule {
+                        pattern: pattern.to_string(),
+                        tags: tags.to_vec(),
+                    });
+                }
+                [directive, pattern] if directive == "priority" => {
+                    settings.priorities.push(pattern.to_string());
+                }
+                [directive, k1, b] if directive == "bm25" => {
+                    let parse = |value: &String| {
+                        value.parse::<f64>().map_err(|err| {
+                            anyhow!(
+                                "line {}: invalid bm25 parameter {value:?}: {err}",
+                                line_no + 1
+                            )
+                        })
+                    };
+                    settings.bm25 = Bm25::new(parse(k1)?, parse(b)?)
+                        .map_err(|err| anyhow!("line {}: {err}", line_no + 1))?;
+                }
+                [directive, value] if directive == "auto_compact" => {
+                    settings.auto_compact = if value == "off" {
+                        None
+                    } else {
+                        Some(value.parse::<usize>().map_err(|err| {
+                            anyhow!("line {}: invalid auto_compact {value:?}: {err}", line_no + 1)
+                        })?)
+                    };
+                }
+                [directive, path] if directive == "watch" => {
+                    settings.watch.push(PathBuf::from(path));
+                }
+                [directive, seconds] if directive == "watch_interval" => {
+                    let seconds = seconds.parse::<u64>().map_err(|err| {
+                        anyhow!(
+                            "line {}: invalid watch_interval {seconds:?}: {err}",
+                            line_no + 1
+                        )
+                    })?;
+                    if seconds == 0 {
+                        return Err(anyhow!(
+                            "line {}: watch_interval must be positive",
+                            line_no + 1
+                        ));
+                    }
+                    settings.watch_interval = Duration::from_secs(seconds);
+                }
+                [directive, prefix, path, token @ ..]
+                    if directive == "serve" && token.len() <= 1 =>
+                {
+                    let valid = !prefix.is_empty()
+                        && prefix
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+                    if !valid {
+                        return Err(anyhow!(
+                            "line {}: invalid serve prefix {prefix:?}, expected letters, digits, '-' or '_'",
+                            line_no + 1
+                        ));
+                    }
+                    if settings.routes.iter().any(|route| &route.prefix == prefix) {
+                        return Err(anyhow!(
+                            "line {}: prefix {prefix:?} is already served",
+                            line_no + 1
+                        ));
+                    }
+                    settings.routes.push(IndexRoute {
+                        prefix: prefix.to_string(),
+                        path: PathBuf::from(path),
+                        token: token.first().cloned(),
+                    });
+                }
+                _ => return Err(anyhow!("line {}: unknown directive {line:?}", line_no + 1)),
+            }
+        }
+
+        Ok(settings)
+    }
+}
+
+/// Splits a directive line into whitespace separated arguments, keeping
+/// double quoted arguments together.
+///
+/// # Arguments
+/// * `line` - The line to split.
+///
+/// # Returns
+/// The arguments, or an error message if a quote is left open.
+fn split_args(line: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut has_arg = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                has_arg = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if has_arg {
+                    args.push(std::mem::take(&mut current));
+                    has_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                has_arg = true;
+            }
+        }
+    }
+
+    if in_quotes {
+        return Err("unterminated quote".to_string());
+    }
+    if has_arg {
+        args.push(current);
+    }
+    Ok(args)
+}
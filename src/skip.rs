@@ -0,0 +1,368 @@
+use crate::glob::glob_match_path;
+
+use std::fmt;
+use std::fs::Metadata;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// The rules excluding files and directories from indexing.
+///
+/// A path is skipped if any rule matches it. The rules are checked in the
+/// following order, which only matters for the reported `SkipReason`:
+/// 1. `paths`: the exact file or directory, relative to the current
+///    directory or absolute.
+/// 2. `names`: any file or directory with this base name, wherever it lives.
+/// 3. `globs`: glob patterns as supported by `glob_match_path`; patterns
+///    without a `/` match the base name, others the path, at any depth
+///    unless they start with `/`. `build/**` also skips `build` itself.
+/// 4. `include`: when given, files matching none of these patterns or
+///    extensions are skipped. Directories are still descended into.
+///
+/// Files with an execute bit set are only skipped with `executables`, as
+/// scripts are worth indexing and files copied from FAT or NTFS drives are
+/// all executable; binaries are left out by their content anyway.
+///
+/// A skipped directory is not descended into.
+#[derive(Clone, Debug, Default)]
+pub struct SkipRules {
+    /// Exact paths to skip, made absolute.
+    pub paths: Vec<PathBuf>,
+    /// Base names to skip.
+    pub names: Vec<String>,
+    /// Glob patterns to skip.
+    pub globs: Vec<String>,
+    /// Glob patterns, or extensions such as `rs` or `.rs`, of the only files
+    /// to index, if any.
+    pub include: Vec<String>,
+    /// Skip the files with an execute bit set.
+    pub executables: bool,
+}
+
+/// The rule that excluded a path from indexing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// Matched an exact skip path.
+    Path(PathBuf),
+    /// Matched a skipped base name.
+    Name(String),
+    /// Matched a skip glob pattern.
+    Glob(String),
+    /// A file matching none of the include patterns.
+    NotIncluded,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Path(path) => write!(f, "--skip-path {path:?}"),
+            Self::Name(name) => write!(f, "--skip-name {name:?}"),
+            Self::Glob(glob) => write!(f, "--skip-glob {glob:?}"),
+            Self::NotIncluded => write!(f, "--include"),
+        }
+    }
+}
+
+impl SkipRules {
+    /// Creates the skip rules from the command line flags.
+    ///
+    /// # Arguments
+    /// * `paths` - Exact paths to skip.
+    /// * `names` - Base names to skip.
+    /// * `globs` - Glob patterns to skip.
+    pub fn new(paths: Vec<PathBuf>, names: Vec<String>, globs: Vec<String>) -> Self {
+        Self {
+            paths: paths.iter().map(|p| absolute(p)).collect(),
+            names,
+            globs,
+            include: Vec::new(),
+            executables: false,
+        }
+    }
+
+    /// Adds the entries of the legacy `--skip-paths` flag: entries with `*`
+    /// or `?` are glob patterns, those with more than one component exact
+    /// paths, the others base names.
+    ///
+    /// # Arguments
+    /// * `entries` - The `--skip-paths` entries.
+    pub fn add_legacy(&mut self, entries: Vec<PathBuf>) {
+        for entry in entries {
+            let text = entry.to_string_lossy();
+            if text.contains(['*', '?']) {
+                self.globs.push(text.to_string());
+            } else if entry.components().count() > 1 {
+                self.paths.push(absolute(&entry));
+            } else {
+                self.names.push(entry.to_string_lossy().to_string());
+            }
+        }
+    }
+
+    /// Restricts indexing to the files matching one of `include`: glob
+    /// patterns, matched as the skip globs are, or extensions such as `rs`
+    /// or `.rs`.
+    ///
+    /// # Arguments
+    /// * `include` - The `--include` entries.
+    pub fn with_include(mut self, include: Vec<String>) -> Self {
+        self.include = include;
+        self
+    }
+
+    /// Skips the files with an execute bit set, as `--skip-executables` does.
+    pub fn with_executables(mut self, executables: bool) -> Self {
+        self.executables = executables;
+        self
+    }
+
+    /// Returns `true` if the file of `metadata` is skipped as an executable.
+    pub fn skips_executable(&self, metadata: &Metadata) -> bool {
+        self.executables && metadata.permissions().mode() & 0o111 != 0
+    }
+
+    /// Returns `true` if no rule is configured.
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+            && self.names.is_empty()
+            && self.globs.is_empty()
+            && self.include.is_empty()
+    }
+
+    /// Finds the first rule excluding `path`, in the documented order.
+    ///
+    /// # Arguments
+    /// * `path` - The discovered file or directory.
+    ///
+    /// # Returns
+    /// The `SkipReason` of the matching rule, or `None` if the path is not
+    /// skipped.
+    pub fn matches(&self, path: &Path) -> Option<SkipReason> {
+        if self.is_empty() {
+            return None;
+        }
+
+        if !self.paths.is_empty() {
+            let absolute = absolute(path);
+            if let Some(skip) = self.paths.iter().find(|p| **p == absolute) {
+                return Some(SkipReason::Path(skip.clone()));
+            }
+        }
+
+        let basename = path
+            .file_name()
+            .map(|v| v.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if let Some(name) = self.names.iter().find(|n| **n == basename) {
+            return Some(SkipReason::Name(name.clone()));
+        }
+
+        if let Some(glob) = self.globs.iter().find(|g| glob_matches(g, path)) {
+            return Some(SkipReason::Glob(glob.clone()));
+        }
+
+        let included = |entry: &String| match extension_entry(entry) {
+            Some(ext) => path
+                .extension()
+                .is_some_and(|actual| actual.eq_ignore_ascii_case(ext)),
+            None => glob_matches(entry, path),
+        };
+        if !self.include.is_empty() && !path.is_dir() && !self.include.iter().any(included) {
+            return Some(SkipReason::NotIncluded);
+        }
+        None
+    }
+}
+
+/// Matches `path` against a skip or include glob: patterns with a `/` not
+/// starting with `/` or `**` match at any depth, as if prefixed with `**/`,
+/// and patterns ending with `/**` also match the directory itself.
+fn glob_matches(pattern: &str, path: &Path) -> bool {
+    let pattern = pattern.strip_prefix("./").unwrap_or(pattern);
+    let floating;
+    let pattern = if pattern.contains('/') && !pattern.starts_with(['/', '*']) {
+        floating = format!("**/{pattern}");
+        floating.as_str()
+    } else {
+        pattern
+    };
+    glob_match_path(pattern, path)
+        || pattern
+            .strip_suffix("/**")
+            .is_some_and(|dir| glob_match_path(dir, path))
+}
+
+/// Returns the extension an include entry stands for, e.g. `rs` for `rs`
+/// or `.rs`, or `None` for a glob pattern or a file name such as
+/// `Makefile.am`.
+fn extension_entry(entry: &str) -> Option<&str> {
+    let ext = entry.strip_prefix('.').unwrap_or(entry);
+    let plain = !ext.is_empty()
+        && ext
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    plain.then_some(ext)
+}
+
+/// Makes `path` absolute without touching the file system, falling back to
+/// the path itself.
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_docs;
+    use crate::report::RunReport;
+    use crate::temp::TempIndex;
+    use crate::walker::Discovery;
+
+    fn rules(paths: &[&str], names: &[&str], globs: &[&str]) -> SkipRules {
+        SkipRules::new(
+            paths.iter().map(PathBuf::from).collect(),
+            names.iter().map(|n| n.to_string()).collect(),
+            globs.iter().map(|g| g.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn names_match_the_base_name_anywhere() {
+        let skip = rules(&[], &["target"], &[]);
+        assert_eq!(
+            skip.matches(Path::new("/work/project/target")),
+            Some(SkipReason::Name("target".to_string()))
+        );
+        assert!(skip.matches(Path::new("/target/src")).is_none());
+        assert!(skip.matches(Path::new("/work/targets")).is_none());
+    }
+
+    #[test]
+    fn paths_match_only_the_exact_path() {
+        let skip = rules(&["/work/project/build"], &[], &[]);
+        assert_eq!(
+            skip.matches(Path::new("/work/project/build")),
+            Some(SkipReason::Path(PathBuf::from("/work/project/build")))
+        );
+        assert!(skip.matches(Path::new("/work/other/build")).is_none());
+        assert!(
+            skip.matches(Path::new("/work/project/build/out.o"))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn relative_paths_are_made_absolute() {
+        let skip = rules(&["vendor/cache"], &[], &[]);
+        let cwd = std::env::current_dir().unwrap();
+        assert!(skip.matches(&cwd.join("vendor/cache")).is_some());
+        assert!(skip.matches(Path::new("vendor/cache")).is_some());
+        assert!(skip.matches(Path::new("/elsewhere/vendor/cache")).is_none());
+    }
+
+    #[test]
+    fn globs_without_a_slash_match_the_base_name() {
+        let skip = rules(&[], &[], &["*.log"]);
+        assert_eq!(
+            skip.matches(Path::new("/var/app/server.log")),
+            Some(SkipReason::Glob("*.log".to_string()))
+        );
+        assert!(skip.matches(Path::new("/var/app.log/notes.txt")).is_none());
+    }
+
+    #[test]
+    fn globs_with_a_slash_match_at_any_depth_unless_anchored() {
+        let floating = rules(&[], &[], &["docs/*.md"]);
+        assert!(floating.matches(Path::new("/repo/docs/intro.md")).is_some());
+        assert!(
+            floating
+                .matches(Path::new("/repo/docs/api/intro.md"))
+                .is_none()
+        );
+
+        let anchored = rules(&[], &[], &["/repo/docs/*.md"]);
+        assert!(anchored.matches(Path::new("/repo/docs/intro.md")).is_some());
+        assert!(
+            anchored
+                .matches(Path::new("/other/repo/docs/intro.md"))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn double_star_globs_also_match_the_directory() {
+        let skip = rules(&[], &[], &["build/**"]);
+        assert!(skip.matches(Path::new("/repo/build")).is_some());
+        assert!(skip.matches(Path::new("/repo/build/debug/out.o")).is_some());
+        assert!(skip.matches(Path::new("/repo/builds")).is_none());
+    }
+
+    #[test]
+    fn rules_are_reported_in_the_documented_order() {
+        let path = Path::new("/work/cache");
+        let all = rules(&["/work/cache"], &["cache"], &["cach*"]);
+        assert_eq!(
+            all.matches(path),
+            Some(SkipReason::Path(PathBuf::from("/work/cache")))
+        );
+        let name_and_glob = rules(&[], &["cache"], &["cach*"]);
+        assert_eq!(
+            name_and_glob.matches(path),
+            Some(SkipReason::Name("cache".to_string()))
+        );
+    }
+
+    #[test]
+    fn include_keeps_only_matching_files() {
+        let skip = SkipRules::default().with_include(vec![".rs".to_string(), "*.toml".to_string()]);
+        assert!(skip.matches(Path::new("/repo/src/main.rs")).is_none());
+        assert!(skip.matches(Path::new("/repo/src/MAIN.RS")).is_none());
+        assert!(skip.matches(Path::new("/repo/Cargo.toml")).is_none());
+        assert_eq!(
+            skip.matches(Path::new("/repo/readme.md")),
+            Some(SkipReason::NotIncluded)
+        );
+    }
+
+    #[test]
+    fn include_still_descends_into_directories() {
+        let dir = TempIndex::new().unwrap();
+        let skip = SkipRules::default().with_include(vec!["rs".to_string()]);
+        assert!(skip.matches(dir.path()).is_none());
+    }
+
+    #[test]
+    fn legacy_entries_are_sorted_into_rules() {
+        let mut skip = SkipRules::default();
+        skip.add_legacy(vec![
+            PathBuf::from("*.tmp"),
+            PathBuf::from("/work/out"),
+            PathBuf::from("node_modules"),
+        ]);
+        assert_eq!(skip.globs, vec!["*.tmp".to_string()]);
+        assert_eq!(skip.paths, vec![PathBuf::from("/work/out")]);
+        assert_eq!(skip.names, vec!["node_modules".to_string()]);
+    }
+
+    #[test]
+    fn skipping_the_indexed_root_is_an_error() {
+        let dir = TempIndex::new().unwrap();
+        let root = dir.path().to_path_buf();
+        let name = root.file_name().unwrap().to_string_lossy().to_string();
+        let skip = rules(&[], &[&name], &[]);
+        let mut report = RunReport::new(&root);
+        let err = get_docs(
+            root.clone(),
+            false,
+            false,
+            &skip,
+            Discovery::Builtin,
+            &mut report,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("is itself excluded by --skip-name"),
+            "{err}"
+        );
+    }
+}
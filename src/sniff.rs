@@ -0,0 +1,229 @@
+use crate::rtf::is_rtf;
+use crate::tar::{is_gzip, is_tar};
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// The number of bytes read from the start of a file to tell its type.
+const SNIFF_BYTES: u64 = 8 * 1024;
+
+/// The build and configuration files without an extension indexed as
+/// source code, with the extension of the code they hold.
+const CODE_FILE_NAMES: [(&str, &str); 9] = [
+    ("Makefile", "sh"),
+    ("makefile", "sh"),
+    ("GNUmakefile", "sh"),
+    ("Dockerfile", "sh"),
+    ("Containerfile", "sh"),
+    ("PKGBUILD", "sh"),
+    ("Rakefile", "rb"),
+    ("Gemfile", "rb"),
+    ("Vagrantfile", "rb"),
+];
+
+/// How documents with no extension, or an unknown one, are typed.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Sniffing {
+    /// Tell their format from their content: PDF, RTF, Office, zip, tar
+    /// and gzip files, markup, mails and subtitles, and index other text files as
+    /// source code or plain text.
+    #[default]
+    All,
+    /// Only index text files, as source code or plain text.
+    Text,
+    /// Skip them.
+    Off,
+}
+
+/// Tells the type of a document from its name and the first bytes of its
+/// content, for documents whose extension does not: `README`, `LICENSE`,
+/// `Makefile`, scripts starting with a `#!` line, ...
+///
+/// # Arguments
+/// * `path` - The document.
+/// * `sniffing` - Which types may be told.
+///
+/// # Returns
+/// The extension of the parser of the document, or `None` if its type is
+/// not recognized, it cannot be read or `sniffing` is `Sniffing::Off`.
+pub fn sniff_type(path: &Path, sniffing: Sniffing) -> Option<&'static str> {
+    if sniffing == Sniffing::Off {
+        return None;
+    }
+    let mut head = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(SNIFF_BYTES)
+        .read_to_end(&mut head)
+        .ok()?;
+    if let Some(ext) = binary_type(path, &head) {
+        // Binary formats are not read as text, whatever their bytes
+        return (sniffing == Sniffing::All).then_some(ext);
+    }
+    let text = text_head(&head)?;
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    if let Some((_, ext)) = CODE_FILE_NAMES.iter().find(|(file, _)| *file == name) {
+        return Some(ext);
+    }
+    if let Some(ext) = text.strip_prefix("#!").and_then(interpreter_type) {
+        return Some(ext);
+    }
+    if sniffing == Sniffing::All
+        && let Some(ext) = markup_type(text)
+    {
+        return Some(ext);
+    }
+    Some("txt")
+}
+
+/// Returns `true` if the start of the file at `path` holds NUL bytes and no
+/// known binary format, e.g. an executable, an image or a database named
+/// like a text file. Such files are not parsed as text.
+pub fn is_binary(path: &Path) -> bool {
+    let mut head = Vec::new();
+    let read = File::open(path).and_then(|file| file.take(SNIFF_BYTES).read_to_end(&mut head));
+    read.is_ok() && head.contains(&0) && binary_type(path, &head).is_none()
+}
+
+/// Recognizes the binary formats by their magic bytes.
+fn binary_type(path: &Path, head: &[u8]) -> Option<&'static str> {
+    if head.starts_with(b"%PDF-") {
+        Some("pdf")
+    } else if head.starts_with(b"PK\x03\x04") {
+        Some(zip_type(path))
+    } else if is_gzip(head) {
+        Some("gz")
+    } else if is_tar(head) {
+        Some("tar")
+    } else if is_rtf(head) {
+        Some("rtf")
+    } else {
+        None
+    }
+}
+
+/// Tells Word and PowerPoint documents from other zip archives by the
+/// parts they hold.
+fn zip_type(path: &Path) -> &'static str {
+    let Some(archive) = File::open(path)
+        .ok()
+        .and_then(|file| zip::ZipArchive::new(file).ok())
+    else {
+        return "zip";
+    };
+    let names = archive.file_names().collect::<Vec<&str>>();
+    if names.contains(&"word/document.xml") {
+        "docx"
+    } else if names.iter().any(|name| name.starts_with("ppt/slides/")) {
+        "pptx"
+    } else {
+        "zip"
+    }
+}
+
+/// Returns the start of a file as text, or `None` if it holds NUL bytes or
+/// is not valid UTF-8. A character cut at the end of `head` is dropped.
+fn text_head(head: &[u8]) -> Option<&str> {
+    if head.contains(&0) {
+        return None;
+    }
+    match std::str::from_utf8(head) {
+        Ok(text) => Some(text),
+        // An incomplete character at the end of the bytes read
+        Err(err) if err.error_len().is_none() => {
+            std::str::from_utf8(&head[..err.valid_up_to()]).ok()
+        }
+        Err(_) => None,
+    }
+}
+
+/// Tells the language of a script from the interpreter of its `#!` line,
+/// e.g. `/bin/bash` or `/usr/bin/env python3`.
+fn interpreter_type(shebang: &str) -> Option<&'static str> {
+    let line = shebang.lines().next().unwrap_or_default();
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+    }
+    let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    Some(match program {
+        "sh" | "bash" | "zsh" | "dash" | "ksh" | "ash" | "fish" => "sh",
+        "python" | "pypy" => "py",
+        "ruby" => "rb",
+        "node" | "nodejs" | "deno" | "bun" => "js",
+        "php" => "php",
+        "lua" | "luajit" => "lua",
+        _ => return None,
+    })
+}
+
+/// Recognizes the text formats by their first lines.
+fn markup_type(text: &str) -> Option<&'static str> {
+    let start = text.trim_start_matches('\u{feff}').trim_start();
+    let lower = start
+        .get(..start.len().min(64))
+        .unwrap_or(start)
+        .to_ascii_lowercase();
+    if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+        return Some("html");
+    }
+    if lower.starts_with("<?xml") {
+        return Some("xml");
+    }
+    if start.starts_with("WEBVTT") {
+        return Some("vtt");
+    }
+    if lower.starts_with("#+title:") {
+        return Some("org");
+    }
+    if start.starts_with("From ") {
+        return Some("mbox");
+    }
+    if is_mail(start) {
+        return Some("eml");
+    }
+    let mut lines = start.lines();
+    if lines
+        .next()
+        .is_some_and(|line| line.trim().parse::<u32>().is_ok())
+        && lines.next().is_some_and(|line| line.contains("-->"))
+    {
+        return Some("srt");
+    }
+    let first = start
+        .lines()
+        .find(|line| !line.trim().is_empty() && !line.trim_start().starts_with('%'));
+    if first.is_some_and(|line| line.trim_start().starts_with("\\documentclass")) {
+        return Some("tex");
+    }
+    None
+}
+
+/// Returns `true` if `text` starts with the header block of a mail: header
+/// lines up to a blank line, among which `From:` and `Subject:` or `Date:`.
+fn is_mail(text: &str) -> bool {
+    let mut names = Vec::new();
+    for line in text.lines() {
+        if line.is_empty() {
+            break;
+        }
+        // Folded header values go on indented lines
+        if line.starts_with([' ', '\t']) && !names.is_empty() {
+            continue;
+        }
+        let Some((name, _)) = line.split_once(':') else {
+            return false;
+        };
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return false;
+        }
+        names.push(name.to_ascii_lowercase());
+    }
+    let has = |header: &str| names.iter().any(|name| name == header);
+    has("from") && (has("subject") || has("date"))
+}
@@ -0,0 +1,171 @@
+use crate::clipboard::CLIP_URI_PREFIX;
+use crate::embedded::{EMBEDDED_URI_PREFIX, container_file};
+use crate::tree::DocInfo;
+use crate::walker::ModifiedTimes;
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The prefix of `file://` URIs, which name files like bare paths do.
+pub const FILE_URI_PREFIX: &str = "file://";
+
+/// A kind of source documents are indexed from. Documents are keyed in the
+/// `DocumentStore` by a URI: a filesystem path for files, or `<scheme>:<id>`
+/// for virtual documents such as commits, mails and web pages. The scheme
+/// of the URI decides how the document is kept up to date.
+pub trait SourceScheme: Sync {
+    /// Returns the name of the scheme, e.g. `git`.
+    fn name(&self) -> &'static str;
+
+    /// Returns `true` if the document at `uri` belongs to this scheme.
+    fn matches(&self, uri: &str) -> bool;
+
+    /// Returns `true` if the indexed version of the document is out of date
+    /// and the document should be indexed again.
+    ///
+    /// # Arguments
+    /// * `uri` - The URI of the document.
+    /// * `info` - The `DocInfo` recorded when it was last indexed.
+    /// * `modified` - The modification times read during discovery.
+    fn is_expired(&self, uri: &Path, info: &DocInfo, modified: &ModifiedTimes) -> bool;
+
+    /// Returns the file holding the document, if it can be read back from
+    /// the filesystem (e.g. to highlight it).
+    fn local_path(&self, uri: &Path) -> Option<PathBuf>;
+}
+
+/// Files on the local filesystem, named by their path or a `file://` URI.
+/// They expire when modified after they were indexed.
+struct FileScheme;
+
+impl SourceScheme for FileScheme {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn matches(&self, _uri: &str) -> bool {
+        true
+    }
+
+    fn is_expired(&self, uri: &Path, info: &DocInfo, modified: &ModifiedTimes) -> bool {
+        let Some(path) = self.local_path(uri) else {
+            return true;
+        };
+        let modified_at = match modified.get(&path) {
+            Some(&time) => time,
+            None => match path.metadata().and_then(|m| m.modified()) {
+                Ok(time) => time,
+                Err(_) => return true,
+            },
+        };
+        let now = SystemTime::now();
+        let elapsed_since_modified = now.duration_since(modified_at).unwrap_or_default();
+        let elapsed_since_indexed = now.duration_since(info.indexed_at).unwrap_or_default();
+        elapsed_since_indexed > elapsed_since_modified
+    }
+
+    fn local_path(&self, uri: &Path) -> Option<PathBuf> {
+        let uri = uri.to_string_lossy();
+        Some(PathBuf::from(
+            uri.strip_prefix(FILE_URI_PREFIX).unwrap_or(&uri),
+        ))
+    }
+}
+
+/// Documents embedded in a file, such as mail attachments and zip entries,
+/// named `embedded:<file>!/<name>`. They expire with the file holding them
+/// and cannot be read back on their own.
+struct EmbeddedScheme;
+
+impl SourceScheme for EmbeddedScheme {
+    fn name(&self) -> &'static str {
+        "embedded"
+    }
+
+    fn matches(&self, uri: &str) -> bool {
+        uri.starts_with(EMBEDDED_URI_PREFIX)
+    }
+
+    fn is_expired(&self, uri: &Path, info: &DocInfo, modified: &ModifiedTimes) -> bool {
+        match container_file(uri) {
+            Some(file) => FileScheme.is_expired(&file, info, modified),
+            None => true,
+        }
+    }
+
+    fn local_path(&self, _uri: &Path) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Virtual documents whose content never changes once indexed: commits
+/// (`git:`), mails (`mid:`, `maildir:`), web pages (`http://`, `https://`)
+/// and clipboard snippets (`clip:`). They are indexed once.
+struct ImmutableScheme {
+    /// The name of the scheme.
+    name: &'static str,
+    /// The URI prefix of its documents.
+    prefix: &'static str,
+}
+
+impl SourceScheme for ImmutableScheme {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn matches(&self, uri: &str) -> bool {
+        uri.starts_with(self.prefix)
+    }
+
+    fn is_expired(&self, _uri: &Path, _info: &DocInfo, _modified: &ModifiedTimes) -> bool {
+        false
+    }
+
+    fn local_path(&self, _uri: &Path) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// The schemes of virtual documents, matched by prefix before falling back
+/// to files.
+static VIRTUAL_SCHEMES: [ImmutableScheme; 6] = [
+    ImmutableScheme {
+        name: "git",
+        prefix: "git:",
+    },
+    ImmutableScheme {
+        name: "mail",
+        prefix: "mid:",
+    },
+    ImmutableScheme {
+        name: "maildir",
+        prefix: "maildir:",
+    },
+    ImmutableScheme {
+        name: "http",
+        prefix: "http://",
+    },
+    ImmutableScheme {
+        name: "https",
+        prefix: "https://",
+    },
+    ImmutableScheme {
+        name: "clip",
+        prefix: CLIP_URI_PREFIX,
+    },
+];
+
+/// Returns the scheme of the document at `uri`.
+///
+/// # Arguments
+/// * `uri` - The URI the document is keyed by in the `DocumentStore`.
+pub fn scheme_of(uri: &Path) -> &'static dyn SourceScheme {
+    let uri_str = uri.to_string_lossy();
+    if EmbeddedScheme.matches(&uri_str) {
+        return &EmbeddedScheme;
+    }
+    VIRTUAL_SCHEMES
+        .iter()
+        .find(|scheme| scheme.matches(&uri_str))
+        .map_or(&FileScheme as &dyn SourceScheme, |scheme| scheme)
+}
@@ -0,0 +1,148 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::{self, Cipher};
+use crate::tree::DocumentStore;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The name of the statistics file within an index directory.
+pub(crate) const STATS_FILE: &str = "stats.bin";
+
+/// Corpus statistics written at commit time, so that ranking and reporting
+/// don't have to scan the segments or the document store.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct IndexStats {
+    /// The number of documents in the index.
+    pub total_docs: u64,
+    /// The number of tokens indexed across all documents.
+    pub total_tokens: u64,
+    /// The average number of tokens per document.
+    pub avg_doc_len: f64,
+    /// The number of distinct (stemmed) terms in the index.
+    pub term_count: u64,
+    /// The number of tokens indexed for each document, keyed by document id.
+    pub doc_lengths: HashMap<u64, u64>,
+    /// The number of live documents whose current version contains each
+    /// term. Unlike the document frequencies of the segment dictionaries,
+    /// these don't count the postings re-indexing leaves in older segments
+    /// nor those of deleted documents. `None` until a commit records them.
+    pub doc_freqs: Option<HashMap<String, u64>>,
+    /// The generation of the current version of each live document as
+    /// counted in `doc_freqs`, `None` for documents indexed before versions
+    /// were recorded, whose every posting is current.
+    #[serde(default)]
+    pub live_versions: HashMap<u64, Option<u64>>,
+    /// The documents with postings in each segment counted in `doc_freqs`,
+    /// so that a commit only reads again the segments of the documents it
+    /// changed.
+    #[serde(default)]
+    pub segment_docs: HashMap<u64, Vec<u64>>,
+}
+
+impl IndexStats {
+    /// Computes the statistics of a document store.
+    ///
+    /// # Arguments
+    /// * `doc_store` - The document store of the index.
+    /// * `term_count` - The number of distinct terms in the index.
+    ///
+    /// # Returns
+    /// The computed `IndexStats`.
+    pub fn compute(doc_store: &DocumentStore, term_count: u64) -> Self {
+        let doc_lengths: HashMap<u64, u64> = doc_store
+            .id_to_doc_info
+            .iter()
+            .map(|(id, info)| (*id, info.token_count))
+            .collect();
+        let total_tokens = doc_lengths.values().sum();
+        let avg_doc_len = if doc_lengths.is_empty() {
+            0.0
+        } else {
+            total_tokens as f64 / doc_lengths.len() as f64
+        };
+
+        Self {
+            total_docs: doc_store.doc_count,
+            total_tokens,
+            avg_doc_len,
+            term_count,
+            doc_lengths,
+            doc_freqs: None,
+            live_versions: HashMap::new(),
+            segment_docs: HashMap::new(),
+        }
+    }
+
+    /// Loads the statistics of the index in `index_dir`.
+    ///
+    /// # Arguments
+    /// * `index_dir` - The directory containing the index files.
+    /// * `cipher` - The cipher of an encrypted index, if any.
+    ///
+    /// # Returns
+    /// The loaded `IndexStats`, `None` if the index has none yet or they
+    /// were written in an older layout, or an `anyhow::Result` error if the
+    /// file cannot be read.
+    pub fn load(index_dir: &Path, cipher: Option<&Cipher>) -> anyhow::Result<Option<Self>> {
+        let path = index_dir.join(STATS_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let buf = fs::read(&path).context("read stats")?;
+        Ok(Self::decode(buf, cipher))
+    }
+
+    /// Decodes the statistics read from the statistics file.
+    ///
+    /// # Arguments
+    /// * `buf` - The content of the file.
+    /// * `cipher` - The cipher of an encrypted index, if any.
+    ///
+    /// # Returns
+    /// The `IndexStats`, or `None` if they were written in an older layout.
+    pub(crate) fn decode(buf: Vec<u8>, cipher: Option<&Cipher>) -> Option<Self> {
+        // Older statistics lack the fields added since, or were written
+        // unencrypted; they are rewritten at the next commit
+        let buf = crypto::open(cipher, buf).ok()?;
+        bincode2::deserialize(&buf).ok()
+    }
+
+    /// Encodes the statistics for the statistics file, encrypted with
+    /// `cipher` if given.
+    ///
+    /// # Returns
+    /// The content of the file, or an `anyhow::Result` error.
+    pub(crate) fn encode(&self, cipher: Option<&Cipher>) -> anyhow::Result<Vec<u8>> {
+        let buf = bincode2::serialize(self).context("serialize stats")?;
+        crypto::seal(cipher, buf).context("encrypt stats")
+    }
+
+    /// Returns the number of live documents containing `term`, or `None` if
+    /// the corrected document frequencies were not recorded.
+    pub fn doc_freq(&self, term: &str) -> Option<u64> {
+        self.doc_freqs
+            .as_ref()
+            .map(|dfs| dfs.get(term).copied().unwrap_or(0))
+    }
+
+    /// Writes the statistics into `index_dir`.
+    ///
+    /// # Arguments
+    /// * `index_dir` - The directory containing the index files.
+    /// * `cipher` - The cipher of an encrypted index, if any.
+    ///
+    /// # Returns
+    /// `Ok(())` if the statistics were written, otherwise an
+    /// `anyhow::Result` error.
+    pub fn save(&self, index_dir: &Path, cipher: Option<&Cipher>) -> anyhow::Result<()> {
+        fs::write(index_dir.join(STATS_FILE), self.encode(cipher)?).context("write stats file")
+    }
+
+    /// Returns the number of tokens indexed for a document.
+    pub fn doc_len(&self, doc_id: u64) -> Option<u64> {
+        self.doc_lengths.get(&doc_id).copied()
+    }
+}
@@ -0,0 +1,59 @@
+use anyhow::{Context, anyhow};
+
+use crate::analyzer::fold_case;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Reads a stemming exceptions list: one word per line, optionally followed
+/// by `=` and the stem it is indexed under instead of the one the stemmer
+/// would give it. A word alone is kept as is, e.g. `news` or `universal`
+/// so that it no longer collides with `new` or `universe`; `mice = mouse`
+/// joins words the stemmer cannot. Empty lines and lines starting with `#`
+/// are ignored.
+///
+/// # Arguments
+/// * `path` - The exceptions file.
+///
+/// # Returns
+/// The stems keyed by their case folded word, or an `anyhow::Result` error
+/// naming the offending line.
+pub fn load_stem_exceptions(path: &Path) -> anyhow::Result<BTreeMap<String, String>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("read stem exceptions file {path:?}"))?;
+    parse_stem_exceptions(&content).with_context(|| format!("parse stem exceptions file {path:?}"))
+}
+
+/// Parses the content of a stemming exceptions list as described in
+/// `load_stem_exceptions`.
+///
+/// # Arguments
+/// * `content` - The exceptions list content.
+///
+/// # Returns
+/// The stems keyed by their case folded word, or an `anyhow::Result` error.
+pub fn parse_stem_exceptions(content: &str) -> anyhow::Result<BTreeMap<String, String>> {
+    let mut exceptions = BTreeMap::new();
+    for (line_no, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (word, stem) = match line.split_once('=') {
+            Some((word, stem)) => (fold_case(word.trim()), fold_case(stem.trim())),
+            None => (fold_case(line), fold_case(line)),
+        };
+        for value in [&word, &stem] {
+            if value.is_empty() || !value.chars().all(char::is_alphanumeric) {
+                return Err(anyhow!(
+                    "line {}: expected `<word>` or `<word> = <stem>` with single words, \
+                     got {line:?}",
+                    line_no + 1
+                ));
+            }
+        }
+        exceptions.insert(word, stem);
+    }
+    Ok(exceptions)
+}
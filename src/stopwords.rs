@@ -0,0 +1,80 @@
+use anyhow::Context;
+
+use crate::analyzer::{Language, fold_case};
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// A set of stop words, shared by every analyzer using it.
+pub type StopWords = Arc<HashSet<String>>;
+
+/// The stop words of the languages used so far. The lists are compiled into
+/// the binary by the `stop_words` crate, and each is only turned into a set
+/// once per process.
+static LANGUAGE_STOP_WORDS: LazyLock<Mutex<HashMap<Language, StopWords>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// The stop words of an index overriding those of its language, kept so
+/// that opening the index again reuses the set.
+static CUSTOM_STOP_WORDS: Mutex<Option<(BTreeSet<String>, StopWords)>> = Mutex::new(None);
+
+/// The empty set used when stop words are disabled.
+static NO_STOP_WORDS: LazyLock<StopWords> = LazyLock::new(StopWords::default);
+
+/// Returns the stop words of `language`.
+pub fn language_stop_words(language: Language) -> StopWords {
+    let mut sets = LANGUAGE_STOP_WORDS.lock().unwrap();
+    let set = sets.entry(language).or_insert_with(|| {
+        Arc::new(
+            language
+                .stop_word_list()
+                .iter()
+                .map(|word| fold_case(word))
+                .collect(),
+        )
+    });
+    Arc::clone(set)
+}
+
+/// Returns the empty set of stop words.
+pub fn no_stop_words() -> StopWords {
+    Arc::clone(&NO_STOP_WORDS)
+}
+
+/// Returns the set of the stop words overriding those of a language.
+///
+/// # Arguments
+/// * `words` - The stop words, as recorded in the manifest of the index.
+pub fn custom_stop_words(words: &BTreeSet<String>) -> StopWords {
+    let mut custom = CUSTOM_STOP_WORDS.lock().unwrap();
+    match custom.as_ref() {
+        Some((cached, set)) if cached == words => Arc::clone(set),
+        _ => {
+            let set: StopWords = Arc::new(words.iter().cloned().collect());
+            *custom = Some((words.clone(), Arc::clone(&set)));
+            set
+        }
+    }
+}
+
+/// Reads a stop word list: one word per line. Empty lines and lines
+/// starting with `#` are ignored.
+///
+/// # Arguments
+/// * `path` - The stop word file.
+///
+/// # Returns
+/// The case folded stop words, or an `anyhow::Result` error if the file
+/// cannot be read.
+pub fn load_stop_words(path: &Path) -> anyhow::Result<BTreeSet<String>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("read stop words file {path:?}"))?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(fold_case)
+        .collect())
+}
@@ -0,0 +1,197 @@
+use anyhow::{Context, anyhow};
+
+use crate::manifest::{MANIFEST_FILE, Manifest};
+use crate::publish::resolve_index;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The file of the store holding the name of the default index.
+pub const DEFAULT_INDEX_FILE: &str = "default-index";
+
+/// The names of the files and directories the store keeps next to the
+/// named indexes, or that an unnamed index keeps in it, which no index can
+/// be named after.
+const RESERVED_NAMES: [&str; 7] = [
+    "builds",
+    "clipboard",
+    "config",
+    "current",
+    DEFAULT_INDEX_FILE,
+    "logs",
+    "reports",
+];
+
+/// An index of the store, as listed by `indexer list-indexes`.
+pub struct NamedIndex {
+    /// The name of the index, `None` for the unnamed index kept in the store
+    /// directory itself.
+    pub name: Option<String>,
+    /// The directory containing the index files.
+    pub path: PathBuf,
+    /// The directories and files the index was built from.
+    pub roots: Vec<PathBuf>,
+    /// Whether commands without `--index` or `--name` use this index.
+    pub is_default: bool,
+}
+
+/// The index store, e.g. `~/.indexer`: the unnamed index kept in it from
+/// before indexes had names, and the named indexes in its subdirectories,
+/// e.g. `~/.indexer/work` for `--name work`.
+pub struct IndexStore {
+    /// The store directory.
+    root: PathBuf,
+}
+
+impl IndexStore {
+    /// Opens the store in `root`.
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// Returns the directory of the index named `name`, which may not exist
+    /// yet: indexing into it creates it.
+    ///
+    /// # Returns
+    /// The directory, or an `anyhow::Result` error if `name` is not a valid
+    /// index name.
+    pub fn index_path(&self, name: &str) -> anyhow::Result<PathBuf> {
+        validate_name(name)?;
+        Ok(self.root.join(name))
+    }
+
+    /// Returns the directory of the index used by commands given neither
+    /// `--index` nor `--name`: the default index if one was chosen with
+    /// `indexer default-index`, otherwise the unnamed index of the store.
+    ///
+    /// # Returns
+    /// The directory, or an `anyhow::Result` error if the default index
+    /// file cannot be read.
+    pub fn default_path(&self) -> anyhow::Result<PathBuf> {
+        match self.default_name()? {
+            Some(name) => self.index_path(&name),
+            None => Ok(self.root.clone()),
+        }
+    }
+
+    /// Returns the name of the default index, if one was chosen.
+    ///
+    /// # Returns
+    /// The name, or an `anyhow::Result` error if the default index file
+    /// cannot be read.
+    pub fn default_name(&self) -> anyhow::Result<Option<String>> {
+        let path = self.root.join(DEFAULT_INDEX_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let name = fs::read_to_string(&path).context("read default index file")?;
+        let name = name.trim();
+        Ok((!name.is_empty()).then(|| name.to_string()))
+    }
+
+    /// Makes the index named `name` the one used by commands given neither
+    /// `--index` nor `--name`, or the unnamed index again with `None`.
+    ///
+    /// # Returns
+    /// `Ok(())` once saved, otherwise an `anyhow::Result` error, e.g. if no
+    /// index is named `name`.
+    pub fn set_default(&self, name: Option<&str>) -> anyhow::Result<()> {
+        let path = self.root.join(DEFAULT_INDEX_FILE);
+        let Some(name) = name else {
+            if path.exists() {
+                fs::remove_file(&path).context("remove default index file")?;
+            }
+            return Ok(());
+        };
+        if !is_index(&self.index_path(name)?) {
+            return Err(anyhow!(
+                "No index is named {name:?}; create it with `indexer index --name {name}`"
+            ));
+        }
+        fs::write(&path, format!("{name}\n")).context("write default index file")
+    }
+
+    /// Lists the unnamed index, if there is one, and the named indexes of
+    /// the store, sorted by name.
+    ///
+    /// # Returns
+    /// The indexes, or an `anyhow::Result` error if the store cannot be
+    /// read.
+    pub fn list(&self) -> anyhow::Result<Vec<NamedIndex>> {
+        let default_name = self.default_name()?;
+        let mut indexes = Vec::new();
+        if is_index(&self.root) {
+            indexes.push(NamedIndex {
+                name: None,
+                path: self.root.clone(),
+                roots: Manifest::load(&self.root)?.roots,
+                is_default: default_name.is_none(),
+            });
+        }
+        let entries = fs::read_dir(&self.root)
+            .with_context(|| format!("read index store {:?}", self.root))?;
+        let mut named = Vec::new();
+        for entry in entries {
+            let path = entry.context("read index store entry")?.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if validate_name(name).is_err() || !is_index(&path) {
+                continue;
+            }
+            named.push(NamedIndex {
+                name: Some(name.to_string()),
+                roots: Manifest::load(&path)?.roots,
+                is_default: default_name.as_deref() == Some(name),
+                path,
+            });
+        }
+        named.sort_by(|a, b| a.name.cmp(&b.name));
+        indexes.extend(named);
+        Ok(indexes)
+    }
+
+    /// Deletes the index named `name` with all its files. If it was the
+    /// default index, the unnamed index becomes the default again.
+    ///
+    /// # Returns
+    /// The directory removed, or an `anyhow::Result` error, e.g. if no
+    /// index is named `name`.
+    pub fn drop_index(&self, name: &str) -> anyhow::Result<PathBuf> {
+        let path = self.index_path(name)?;
+        if !is_index(&path) {
+            return Err(anyhow!("No index is named {name:?}"));
+        }
+        fs::remove_dir_all(&path).with_context(|| format!("remove index {path:?}"))?;
+        if self.default_name()?.as_deref() == Some(name) {
+            self.set_default(None)?;
+        }
+        Ok(path)
+    }
+}
+
+/// Checks that `name` can name an index: letters, digits, `-`, `_` and `.`,
+/// not starting with `.` nor taken by the files of the store.
+///
+/// # Returns
+/// `Ok(())` if it can, otherwise an `anyhow::Result` error saying why not.
+pub fn validate_name(name: &str) -> anyhow::Result<()> {
+    let allowed = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.');
+    if name.is_empty() || name.starts_with('.') || !name.chars().all(allowed) {
+        return Err(anyhow!(
+            "Invalid index name {name:?}: use letters, digits, '-', '_' and '.', \
+             not starting with '.'"
+        ));
+    }
+    if RESERVED_NAMES.contains(&name) || name.starts_with("segment_") {
+        return Err(anyhow!(
+            "Invalid index name {name:?}: the name is used by the files of the index store"
+        ));
+    }
+    Ok(())
+}
+
+/// Returns `true` if `dir` holds an index.
+pub(crate) fn is_index(dir: &Path) -> bool {
+    dir.is_dir() && resolve_index(dir).join(MANIFEST_FILE).exists()
+}
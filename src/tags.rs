@@ -0,0 +1,109 @@
+use anyhow::anyhow;
+
+use crate::analyzer::fold_case;
+use crate::policy::rule_matches;
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Separates the name of a tag from its value, e.g. `project=alpha`.
+pub const TAG_VALUE_SEPARATOR: char = '=';
+
+/// A rule tagging the documents whose path matches `pattern`, written in
+/// the configuration file as `tag "work/alpha/" project=alpha`. Patterns
+/// are those of `PolicyRule`.
+#[derive(Clone, Debug)]
+pub struct TagRule {
+    /// The pattern as written in the configuration file.
+    pub pattern: String,
+    /// The tags given to matching documents.
+    pub tags: Vec<String>,
+}
+
+impl TagRule {
+    /// Returns `true` if the rule applies to the document at `path`.
+    pub fn matches(&self, path: &Path) -> bool {
+        rule_matches(&self.pattern, path)
+    }
+}
+
+/// Checks that a tag can be attached to documents and searched for: a
+/// name, optionally followed by `=` and a value, without whitespace, quotes
+/// or parentheses.
+///
+/// # Arguments
+/// * `tag` - The tag, e.g. `project=alpha` or `urgent`.
+///
+/// # Returns
+/// `Ok(())` if the tag is valid, otherwise an `anyhow::Result` error
+/// telling why not.
+pub fn validate_tag(tag: &str) -> anyhow::Result<()> {
+    if tag.is_empty() {
+        return Err(anyhow!("empty tag"));
+    }
+    if tag.starts_with(TAG_VALUE_SEPARATOR) {
+        return Err(anyhow!("tag {tag:?} has no name"));
+    }
+    if tag
+        .chars()
+        .any(|c| c.is_whitespace() || matches!(c, '"' | '(' | ')'))
+    {
+        return Err(anyhow!(
+            "tag {tag:?} contains whitespace, quotes or parentheses"
+        ));
+    }
+    Ok(())
+}
+
+/// Parses a tag given on the command line, e.g. `--tag project=alpha`.
+///
+/// # Arguments
+/// * `tag` - The tag as written.
+///
+/// # Returns
+/// The tag, or an `anyhow::Result` error if it is not valid.
+pub fn parse_tag(tag: &str) -> anyhow::Result<String> {
+    validate_tag(tag)?;
+    Ok(tag.to_string())
+}
+
+/// Returns the tags of the document at `path`: those given to every
+/// document of the run and those of the rules matching it.
+///
+/// # Arguments
+/// * `tags` - The tags of every document.
+/// * `rules` - The tag rules of the configuration file.
+/// * `path` - The path of the document.
+///
+/// # Returns
+/// The tags of the document, each once.
+pub fn tags_for(tags: &[String], rules: &[TagRule], path: &Path) -> BTreeSet<String> {
+    tags.iter()
+        .chain(
+            rules
+                .iter()
+                .filter(|rule| rule.matches(path))
+                .flat_map(|rule| &rule.tags),
+        )
+        .cloned()
+        .collect()
+}
+
+/// Returns `true` if a document carries a tag matching `filter`, ignoring
+/// case: the tag itself or, for a filter without value, any value of it.
+/// `tag:project` matches `project=alpha`, `tag:project=alpha` only that.
+///
+/// # Arguments
+/// * `tags` - The tags of the document.
+/// * `filter` - The tag of a `tag:` filter.
+pub fn has_tag(tags: &BTreeSet<String>, filter: &str) -> bool {
+    let filter = fold_case(filter);
+    tags.iter().any(|tag| {
+        let tag = fold_case(tag);
+        tag == filter
+            || (!filter.contains(TAG_VALUE_SEPARATOR)
+                && tag
+                    .strip_prefix(filter.as_str())
+                    .is_some_and(|rest| rest.starts_with(TAG_VALUE_SEPARATOR)))
+    })
+}
@@ -0,0 +1,301 @@
+use anyhow::{Context, anyhow};
+use flate2::read::MultiGzDecoder;
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+/// The size of tar headers and of the blocks entries are padded to.
+const BLOCK: usize = 512;
+
+/// The magic bytes starting gzip files.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The content of a tar or gzip file.
+pub enum ArchiveContent {
+    /// A tar archive, possibly decompressed.
+    Tar(Box<dyn Read>),
+    /// A single gzip compressed file, e.g. `notes.txt.gz`.
+    File {
+        /// The name of the compressed file, that of the archive without its
+        /// `.gz` extension.
+        name: String,
+        /// The decompressed content.
+        reader: Box<dyn Read>,
+    },
+}
+
+/// Returns `true` if `head`, the start of a file, is a tar header with the
+/// ustar (or GNU) magic.
+pub fn is_tar(head: &[u8]) -> bool {
+    head.get(257..262) == Some(b"ustar")
+}
+
+/// Returns `true` if `head`, the start of a file, is that of a gzip file.
+pub fn is_gzip(head: &[u8]) -> bool {
+    head.starts_with(&GZIP_MAGIC)
+}
+
+/// Opens a tar archive, a gzip compressed one (`.tar.gz`, `.tgz`) or a
+/// single gzip compressed file, telling them by their magic bytes rather
+/// than their extension.
+///
+/// # Arguments
+/// * `path` - The archive.
+///
+/// # Returns
+/// The `ArchiveContent`, or an `anyhow::Result` error if the file cannot be
+/// read.
+pub fn open_archive(path: &Path) -> anyhow::Result<ArchiveContent> {
+    let mut file = BufReader::new(File::open(path).context("open archive")?);
+    let mut magic = [0u8; 2];
+    let read = read_full(&mut file, &mut magic).context("read archive")?;
+    let file = io::Cursor::new(magic[..read].to_vec()).chain(file);
+    if !is_gzip(&magic[..read]) {
+        return Ok(ArchiveContent::Tar(Box::new(file)));
+    }
+
+    let mut decoder = MultiGzDecoder::new(file);
+    let mut head = vec![0u8; BLOCK];
+    let read = read_full(&mut decoder, &mut head).context("decompress archive")?;
+    head.truncate(read);
+    let is_tar = is_tar(&head);
+    let reader = Box::new(io::Cursor::new(head).chain(decoder));
+    if is_tar {
+        return Ok(ArchiveContent::Tar(reader));
+    }
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    Ok(ArchiveContent::File { name, reader })
+}
+
+/// Walks the regular files of a tar archive, in order. Directories, links
+/// and other special entries are skipped. GNU long names and pax `path`
+/// records are followed.
+///
+/// # Arguments
+/// * `reader` - The content of the archive.
+/// * `visit` - Called with the path, the size and the content of each file.
+///   The content is skipped past if `visit` does not read it.
+///
+/// # Returns
+/// An `anyhow::Result` error if the archive is malformed or `visit` fails.
+pub fn for_each_entry(
+    mut reader: impl Read,
+    mut visit: impl FnMut(&str, u64, &mut dyn Read) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut header = [0u8; BLOCK];
+    let mut long_name: Option<String> = None;
+    loop {
+        let read = read_full(&mut reader, &mut header).context("read tar header")?;
+        // The archive ends with zero blocks, often cut short
+        if read < BLOCK || header.iter().all(|byte| *byte == 0) {
+            return Ok(());
+        }
+        let size = header_size(&header)?;
+        let padded = size.div_ceil(BLOCK as u64) * BLOCK as u64;
+        let mut content = (&mut reader).take(size);
+        match header[156] {
+            b'0' | b'\0' | b'7' => {
+                let name = match long_name.take() {
+                    Some(name) => name,
+                    None => header_name(&header),
+                };
+                let name = name.trim_start_matches("./");
+                if !name.is_empty() && !name.ends_with('/') {
+                    visit(name, size, &mut content)?;
+                }
+            }
+            b'L' => {
+                let mut name = Vec::new();
+                content.read_to_end(&mut name).context("read tar entry")?;
+                long_name = Some(field(&name));
+            }
+            b'x' => {
+                let mut records = Vec::new();
+                content
+                    .read_to_end(&mut records)
+                    .context("read tar entry")?;
+                if let Some(path) = pax_path(&records) {
+                    long_name = Some(path);
+                }
+            }
+            _ => {}
+        }
+        // Skip what `visit` left unread and the padding
+        io::copy(&mut content, &mut io::sink()).context("read tar entry")?;
+        io::copy(&mut (&mut reader).take(padded - size), &mut io::sink())
+            .context("read tar entry")?;
+    }
+}
+
+/// Writes a ustar archive of regular files, as read by `for_each_entry`.
+/// Names longer than the header allows are written as GNU long names.
+pub struct TarWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> TarWriter<W> {
+    /// Starts an archive written to `writer`.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Appends a regular file.
+    ///
+    /// # Arguments
+    /// * `name` - The path of the file within the archive.
+    /// * `size` - The size of the file in bytes.
+    /// * `content` - The content of the file, of exactly `size` bytes.
+    ///
+    /// # Returns
+    /// `Ok(())` once written, otherwise an `anyhow::Result` error, e.g. if
+    /// `content` is not `size` bytes long.
+    pub fn append(&mut self, name: &str, size: u64, content: impl Read) -> anyhow::Result<()> {
+        if name.len() > 100 {
+            let mut long_name = name.as_bytes().to_vec();
+            long_name.push(0);
+            let header = entry_header("././@LongLink", long_name.len() as u64, b'L');
+            self.writer.write_all(&header).context("write tar header")?;
+            self.write_content(long_name.len() as u64, long_name.as_slice())?;
+        }
+        let header = entry_header(name, size, b'0');
+        self.writer.write_all(&header).context("write tar header")?;
+        self.write_content(size, content)
+    }
+
+    /// Ends the archive with its two zero blocks.
+    ///
+    /// # Returns
+    /// The writer, or an `anyhow::Result` error.
+    pub fn finish(mut self) -> anyhow::Result<W> {
+        self.writer
+            .write_all(&[0u8; 2 * BLOCK])
+            .context("write tar trailer")?;
+        Ok(self.writer)
+    }
+
+    /// Writes the content of an entry and pads it to a whole block.
+    fn write_content(&mut self, size: u64, content: impl Read) -> anyhow::Result<()> {
+        let written =
+            io::copy(&mut content.take(size), &mut self.writer).context("write tar entry")?;
+        if written != size {
+            return Err(anyhow!(
+                "tar entry is {written} bytes long, expected {size}"
+            ));
+        }
+        let padding = (BLOCK - (size % BLOCK as u64) as usize) % BLOCK;
+        self.writer
+            .write_all(&vec![0u8; padding])
+            .context("write tar entry")
+    }
+}
+
+/// Builds the ustar header of an entry, its name cut to the 100 bytes of the
+/// name field.
+fn entry_header(name: &str, size: u64, kind: u8) -> [u8; BLOCK] {
+    let mut header = [0u8; BLOCK];
+    let name = name.as_bytes();
+    let length = name.len().min(100);
+    header[..length].copy_from_slice(&name[..length]);
+    header[100..108].copy_from_slice(b"0000644\0");
+    header[108..116].copy_from_slice(b"0000000\0");
+    header[116..124].copy_from_slice(b"0000000\0");
+    if size < 0o77777777777 {
+        header[124..136].copy_from_slice(format!("{size:011o}\0").as_bytes());
+    } else {
+        // Base-256, for entries of 8 GiB and more
+        header[124] = 0x80;
+        header[128..136].copy_from_slice(&size.to_be_bytes());
+    }
+    header[136..148].copy_from_slice(b"00000000000\0");
+    header[156] = kind;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    // The checksum is computed with its own field filled with spaces
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = header.iter().map(|byte| u32::from(*byte)).sum();
+    header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+    header
+}
+
+/// Reads the path of an entry from its header: the name field, after the
+/// ustar prefix if any.
+fn header_name(header: &[u8; BLOCK]) -> String {
+    let name = field(&header[..100]);
+    // GNU archives use the prefix field for other purposes
+    let prefix = if &header[257..263] == b"ustar\0" {
+        field(&header[345..500])
+    } else {
+        String::new()
+    };
+    if prefix.is_empty() {
+        name
+    } else {
+        format!("{prefix}/{name}")
+    }
+}
+
+/// Reads the size of an entry from its header, written in octal or, for
+/// large entries, in base-256.
+fn header_size(header: &[u8; BLOCK]) -> anyhow::Result<u64> {
+    let size = &header[124..136];
+    if size[0] & 0x80 != 0 {
+        return Ok(size[1..]
+            .iter()
+            .fold(u64::from(size[0] & 0x7f), |acc, byte| {
+                (acc << 8) | u64::from(*byte)
+            }));
+    }
+    let octal = field(size);
+    let octal = octal.trim();
+    if octal.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(octal, 8).map_err(|_| anyhow!("invalid tar entry size {octal:?}"))
+}
+
+/// Reads a NUL terminated header field.
+fn field(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// Finds the `path` of a pax extended header, made of `<length> <key>=<value>\n`
+/// records.
+fn pax_path(records: &[u8]) -> Option<String> {
+    let records = String::from_utf8_lossy(records);
+    let mut rest = records.as_ref();
+    while !rest.is_empty() {
+        let (length, _) = rest.split_once(' ')?;
+        let record = rest.get(..length.parse::<usize>().ok()?)?;
+        rest = &rest[record.len()..];
+        let (_, pair) = record.split_once(' ')?;
+        if let Some(path) = pair.trim_end_matches('\n').strip_prefix("path=") {
+            return Some(path.to_string());
+        }
+    }
+    None
+}
+
+/// Fills `buf` from `reader`, short only at the end of the input.
+///
+/// # Returns
+/// The number of bytes read.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(read)
+}
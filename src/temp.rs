@@ -0,0 +1,61 @@
+use anyhow::Context;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+/// The index path selecting a temporary index, e.g. `--index tmp:`.
+pub const TEMP_INDEX_SPEC: &str = "tmp:";
+
+/// Distinguishes the temporary indexes created by one process.
+static TEMP_INDEX_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// An index directory created in the system temp directory and removed with
+/// everything in it when dropped. Useful for one-shot searches and for tests
+/// of tools built on the indexer.
+pub struct TempIndex {
+    /// The directory holding the index files.
+    path: PathBuf,
+}
+
+impl TempIndex {
+    /// Creates a new, empty temporary index directory.
+    ///
+    /// # Returns
+    /// The `TempIndex`, or an `anyhow::Result` error if the directory cannot
+    /// be created.
+    pub fn new() -> anyhow::Result<Self> {
+        let path = temp_path();
+        fs::create_dir_all(&path).context("create temporary index dir")?;
+        Ok(Self { path })
+    }
+
+    /// Returns the directory holding the index files.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempIndex {
+    /// Removes the temporary index directory.
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Returns a path in the system temp directory no other call returns, in
+/// this process or another one.
+pub(crate) fn temp_path() -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let count = TEMP_INDEX_COUNTER.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("indexer-{}-{nanos}-{count}", std::process::id()))
+}
+
+/// Returns `true` if `path` selects a temporary index.
+pub fn is_temp_index(path: &Path) -> bool {
+    path.as_os_str() == TEMP_INDEX_SPEC
+}
@@ -0,0 +1,190 @@
+use anyhow::anyhow;
+
+use std::path::Path;
+
+/// A field of a search hit that can be placed in an output template.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    /// The 1-based rank of the hit.
+    Rank,
+    /// The score of the hit.
+    Score,
+    /// The path of the document.
+    Path,
+    /// The title of the document, empty if it has none.
+    Title,
+    /// The text around the first match in the document, empty if it cannot
+    /// be read back.
+    Snippet,
+}
+
+impl Field {
+    /// The fields, with the name they are written with in a template.
+    const NAMES: [(&'static str, Field); 5] = [
+        ("rank", Field::Rank),
+        ("score", Field::Score),
+        ("path", Field::Path),
+        ("title", Field::Title),
+        ("snippet", Field::Snippet),
+    ];
+}
+
+/// One piece of a template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    /// Text copied as is.
+    Literal(String),
+    /// A field, with the number of decimals of a `score`.
+    Field(Field, Option<usize>),
+}
+
+/// One search hit rendered by a `Template`.
+pub struct TemplateHit<'a> {
+    /// The 1-based rank of the hit.
+    pub rank: usize,
+    /// The score of the hit.
+    pub score: f64,
+    /// The path of the document.
+    pub path: &'a Path,
+    /// The title of the document, if any.
+    pub title: Option<&'a str>,
+    /// The text around the first match in the document, if any.
+    pub snippet: Option<&'a str>,
+}
+
+/// A template shaping each search hit into one line of plain text, e.g.
+/// `{score:.2}\t{path}\t{snippet}`.
+///
+/// Fields are written in braces: `{rank}`, `{score}`, `{path}`, `{title}`
+/// and `{snippet}`; `{score:.N}` rounds the score to `N` decimals. `{{` and
+/// `}}` stand for literal braces, and `\t`, `\n` and `\\` for a tab, a line
+/// break and a backslash, so templates can be written without shell quoting
+/// tricks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Parses a template.
+    ///
+    /// # Arguments
+    /// * `template` - The template string.
+    ///
+    /// # Returns
+    /// The parsed `Template`, or an `anyhow::Result` error naming the
+    /// unknown field or unbalanced brace.
+    pub fn parse(template: &str) -> anyhow::Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => {
+                        literal.push('\\');
+                        literal.push(other);
+                    }
+                    None => literal.push('\\'),
+                },
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => return Err(anyhow!("unmatched `}}` in template {template:?}")),
+                '{' => {
+                    let mut spec = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => spec.push(c),
+                            None => {
+                                return Err(anyhow!("unclosed `{{` in template {template:?}"));
+                            }
+                        }
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(parse_field(&spec)?);
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Self { parts })
+    }
+
+    /// Returns `true` if the template shows snippets, which have to be read
+    /// from the documents.
+    pub fn needs_snippets(&self) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, Part::Field(Field::Snippet, _)))
+    }
+
+    /// Renders one hit.
+    ///
+    /// # Arguments
+    /// * `hit` - The hit to render.
+    ///
+    /// # Returns
+    /// The rendered text.
+    pub fn render(&self, hit: &TemplateHit) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => out.push_str(text),
+                Part::Field(Field::Rank, _) => out.push_str(&hit.rank.to_string()),
+                Part::Field(Field::Score, Some(decimals)) => {
+                    out.push_str(&format!("{:.*}", decimals, hit.score));
+                }
+                Part::Field(Field::Score, None) => out.push_str(&hit.score.to_string()),
+                Part::Field(Field::Path, _) => out.push_str(&hit.path.to_string_lossy()),
+                Part::Field(Field::Title, _) => out.push_str(hit.title.unwrap_or_default()),
+                Part::Field(Field::Snippet, _) => out.push_str(hit.snippet.unwrap_or_default()),
+            }
+        }
+        out
+    }
+}
+
+/// Parses the inside of a `{...}` placeholder: a field name, optionally
+/// followed by `:.N` for a score.
+fn parse_field(spec: &str) -> anyhow::Result<Part> {
+    let (name, format) = match spec.split_once(':') {
+        Some((name, format)) => (name, Some(format)),
+        None => (spec, None),
+    };
+    let Some(&(_, field)) = Field::NAMES.iter().find(|(n, _)| *n == name) else {
+        let names = Field::NAMES
+            .iter()
+            .map(|(n, _)| format!("{{{n}}}"))
+            .collect::<Vec<_>>();
+        return Err(anyhow!(
+            "unknown template field {{{spec}}}, expected one of {}",
+            names.join(", ")
+        ));
+    };
+    let decimals = match format {
+        None => None,
+        Some(format) if field == Field::Score => Some(
+            format
+                .strip_prefix('.')
+                .and_then(|decimals| decimals.parse::<usize>().ok())
+                .ok_or_else(|| anyhow!("invalid score format {{{spec}}}, expected {{score:.N}}"))?,
+        ),
+        Some(_) => return Err(anyhow!("template field {{{name}}} takes no format")),
+    };
+    Ok(Part::Field(field, decimals))
+}
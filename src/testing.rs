@@ -0,0 +1,478 @@
+use anyhow::{Context, anyhow};
+use tiny_http::Server;
+
+use crate::cache::DEFAULT_RESULTS_CACHE_ENTRIES;
+use crate::logger::Logger;
+use crate::server::{ServedIndex, default_workers, serve};
+use crate::sniff::Sniffing;
+use crate::temp::temp_path;
+use crate::walker::{Discovery, IndexOrder};
+use crate::{
+    Config, ErrorHandler, Message, SearchOptions, SearchResults, handle_messages, index_documents,
+    search_term,
+};
+
+use std::fs;
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How long requests of a `TestServer` wait for the server to answer.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The name of the log file of a `Harness`, in its directory.
+const LOG_FILE: &str = "harness.log";
+
+/// A directory created in the system temp directory and removed with
+/// everything in it when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates a new, empty temporary directory.
+    fn new() -> anyhow::Result<Self> {
+        let path = temp_path();
+        fs::create_dir_all(&path).context("create temporary dir")?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A directory of documents that tests write, rename and remove between
+/// indexing runs, as a user or a synced folder would.
+pub struct ScriptedSource {
+    /// The directory holding the documents.
+    dir: PathBuf,
+}
+
+impl ScriptedSource {
+    /// Returns the directory holding the documents, the path indexing runs
+    /// are given.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the path of the document `name`, as it is indexed and
+    /// returned by searches.
+    ///
+    /// # Arguments
+    /// * `name` - The path of the document relative to the source, e.g.
+    ///   `notes/todo.txt`.
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// Creates or replaces the document `name`, creating its parent
+    /// directories.
+    ///
+    /// # Arguments
+    /// * `name` - The path of the document relative to the source.
+    /// * `content` - The content of the document.
+    ///
+    /// # Returns
+    /// The path of the document, or an `anyhow::Result` error if it cannot
+    /// be written.
+    pub fn write(&self, name: &str, content: impl AsRef<[u8]>) -> anyhow::Result<PathBuf> {
+        let path = self.path(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("create {parent:?}"))?;
+        }
+        fs::write(&path, content).with_context(|| format!("write {path:?}"))?;
+        Ok(path)
+    }
+
+    /// Renames the document `from` to `to`, creating the parent directories
+    /// of `to`.
+    ///
+    /// # Arguments
+    /// * `from` - The path of the document relative to the source.
+    /// * `to` - Its new path relative to the source.
+    ///
+    /// # Returns
+    /// The new path of the document, or an `anyhow::Result` error if it
+    /// cannot be renamed.
+    pub fn rename(&self, from: &str, to: &str) -> anyhow::Result<PathBuf> {
+        let (from, to) = (self.path(from), self.path(to));
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("create {parent:?}"))?;
+        }
+        fs::rename(&from, &to).with_context(|| format!("rename {from:?} to {to:?}"))?;
+        Ok(to)
+    }
+
+    /// Removes the document or directory `name`.
+    ///
+    /// # Arguments
+    /// * `name` - The path relative to the source.
+    ///
+    /// # Returns
+    /// An `anyhow::Result` error if it cannot be removed.
+    pub fn remove(&self, name: &str) -> anyhow::Result<()> {
+        let path = self.path(name);
+        if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        }
+        .with_context(|| format!("remove {path:?}"))
+    }
+}
+
+/// An in-process index for the tests of this crate and of tools built on
+/// it: an ephemeral index, a `ScriptedSource` indexed into it like the
+/// watched paths of the service, and servers hosting it on a free port.
+/// Everything lives in a temporary directory removed when the harness is
+/// dropped, so harnesses of tests running in parallel never share files or
+/// ports.
+///
+/// ```no_run
+/// use indexer::testing::Harness;
+///
+/// let harness = Harness::new()?;
+/// harness.source().write("notes.txt", "rust search engine")?;
+/// harness.source().write("todo.txt", "write the tests")?;
+/// harness.index()?;
+/// let server = harness.serve()?;
+/// assert_eq!(server.query("engine")?, vec![harness.source().path("notes.txt")]);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct Harness {
+    /// The directory holding the index, the documents and the log.
+    root: TempDir,
+    /// The documents indexed by `index`.
+    source: ScriptedSource,
+    /// The options of searches and of the indexes hosted by `serve`.
+    options: SearchOptions,
+    /// Held by indexing runs, so that runs started from several threads
+    /// are applied one after the other as the service does.
+    indexing: Mutex<()>,
+}
+
+impl Harness {
+    /// Creates a harness with an empty source and no index yet.
+    ///
+    /// # Returns
+    /// The `Harness`, or an `anyhow::Result` error if its directory cannot
+    /// be created.
+    pub fn new() -> anyhow::Result<Self> {
+        let root = TempDir::new()?;
+        let source = ScriptedSource {
+            dir: root.0.join("source"),
+        };
+        fs::create_dir_all(&source.dir).context("create source dir")?;
+        let harness = Self {
+            root,
+            source,
+            options: SearchOptions::default(),
+            indexing: Mutex::new(()),
+        };
+        fs::create_dir_all(harness.index_dir()).context("create index dir")?;
+        Ok(harness)
+    }
+
+    /// Sets the options of searches and of the indexes hosted by `serve`,
+    /// e.g. boosts or a sort order.
+    pub fn with_options(mut self, options: SearchOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Returns the documents indexed by `index`.
+    pub fn source(&self) -> &ScriptedSource {
+        &self.source
+    }
+
+    /// Returns the directory of the index.
+    pub fn index_dir(&self) -> PathBuf {
+        self.root.0.join("index")
+    }
+
+    /// Returns the log file the messages of indexing runs and servers are
+    /// written to.
+    pub fn log_file(&self) -> PathBuf {
+        self.root.0.join(LOG_FILE)
+    }
+
+    /// Indexes the source as it is now: new and modified documents are
+    /// indexed and removed ones dropped from the index, which is committed
+    /// before returning.
+    ///
+    /// # Returns
+    /// An `anyhow::Result` error if the indexing run failed.
+    pub fn index(&self) -> anyhow::Result<()> {
+        self.index_with(|_| {})
+    }
+
+    /// Indexes the source like `index`, with the indexing `Config` changed
+    /// by `configure` first, e.g. to set analyzer settings or tags.
+    ///
+    /// # Arguments
+    /// * `configure` - Changes the `Config` of the run.
+    ///
+    /// # Returns
+    /// An `anyhow::Result` error if the indexing run failed.
+    pub fn index_with(&self, configure: impl FnOnce(&mut Config)) -> anyhow::Result<()> {
+        let _indexing = self.indexing.lock().unwrap();
+        // Every run logs where `configure` leaves its error handler
+        let (logger, receiver) = Logger::channel();
+        let mut cfg = Config {
+            hidden: false,
+            error_handler: ErrorHandler::File(self.log_file()),
+            filepath: self.source.dir.clone(),
+            index_path: self.index_dir(),
+            logger,
+            skip: Default::default(),
+            ranker: None,
+            auto_compact: None,
+            analyzer: None,
+            key_file: self.options.key_file.clone(),
+            raw_key: false,
+            throttle: false,
+            discovery: Discovery::default(),
+            keep_history: false,
+            keep_duplicates: false,
+            budget: None,
+            quota: None,
+            profile: None,
+            segment_budget: None,
+            sandbox: None,
+            resume: false,
+            policies: Vec::new(),
+            tags: Vec::new(),
+            tag_rules: Vec::new(),
+            expansions: None,
+            stem_exceptions: None,
+            stop_words: None,
+            noise: None,
+            time_budget: None,
+            order: IndexOrder::default(),
+            priorities: Vec::new(),
+            sniffing: Sniffing::default(),
+            max_file_size: None,
+            follow_symlinks: false,
+            progress: None,
+            observer: None,
+        };
+        configure(&mut cfg);
+        let error_handler = cfg.error_handler.clone();
+        let logs_handler = thread::spawn(move || {
+            let _ = handle_messages(&receiver, error_handler);
+        });
+
+        let result = index_documents(&cfg);
+        cfg.logger.stop();
+        logs_handler.join().unwrap();
+        result
+    }
+
+    /// Searches the index in process.
+    ///
+    /// # Arguments
+    /// * `query` - The query, e.g. `rust AND engine`.
+    ///
+    /// # Returns
+    /// The `SearchResults`, or an `anyhow::Result` error if the index
+    /// cannot be searched.
+    pub fn search(&self, query: &str) -> anyhow::Result<SearchResults> {
+        search_term(query, &self.index_dir(), &self.options)
+    }
+
+    /// Searches the index in process and returns the paths of the hits, in
+    /// order of rank.
+    ///
+    /// # Arguments
+    /// * `query` - The query.
+    pub fn search_paths(&self, query: &str) -> anyhow::Result<Vec<PathBuf>> {
+        Ok(self
+            .search(query)?
+            .hits
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect())
+    }
+
+    /// Hosts the index at the root of a server listening on a free port of
+    /// the loopback interface. The index may be indexed again while it is
+    /// served; every query reads the last commit.
+    ///
+    /// # Returns
+    /// The `TestServer`, stopped when dropped, or an `anyhow::Result` error
+    /// if no port can be bound.
+    pub fn serve(&self) -> anyhow::Result<TestServer> {
+        TestServer::start(vec![ServedIndex {
+            prefix: String::new(),
+            index_file: self.index_dir(),
+            options: self.options.clone(),
+            preload: None,
+            results_cache: DEFAULT_RESULTS_CACHE_ENTRIES,
+            token: None,
+            indexing: None,
+            allow_shutdown: false,
+            cors_origins: Vec::new(),
+        }])
+        .map(|server| server.with_log(self.log_file()))
+    }
+}
+
+/// A search server answering on a free port of the loopback interface from
+/// a background thread, stopped when dropped.
+pub struct TestServer {
+    /// Set to stop the server.
+    shutdown: Arc<AtomicBool>,
+    /// The address the server listens on.
+    addr: SocketAddr,
+    /// The thread answering the requests.
+    thread: Option<JoinHandle<()>>,
+    /// The log of the server, told to stop when the server is.
+    logger: Logger,
+    /// The receiver of the messages of the server until `with_log` or
+    /// `start` hands it to a logging thread.
+    receiver: Option<mpsc::Receiver<Message>>,
+    /// The HTTP agent sending the requests of the tests.
+    agent: ureq::Agent,
+}
+
+impl TestServer {
+    /// Starts a server hosting `indexes`, as `run_server` does, on a free
+    /// port. Its messages are dropped unless `with_log` is called.
+    ///
+    /// # Arguments
+    /// * `indexes` - The `ServedIndex`es to host.
+    ///
+    /// # Returns
+    /// The `TestServer`, or an `anyhow::Result` error if no port can be
+    /// bound.
+    pub fn start(indexes: Vec<ServedIndex>) -> anyhow::Result<Self> {
+        // Port 0 lets the system pick a port no other server listens on
+        let listener = TcpListener::bind("127.0.0.1:0").context("bind test server")?;
+        let addr = listener.local_addr().context("test server address")?;
+        let server = Server::from_listener(listener, None)
+            .map_err(|err| anyhow!("start test server: {err}"))?;
+        let (logger, receiver) = Logger::channel();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let shutdown = Arc::clone(&shutdown);
+            let logger = logger.clone();
+            thread::spawn(move || serve(&server, indexes, default_workers(), shutdown, logger))
+        };
+        Ok(Self {
+            shutdown,
+            addr,
+            thread: Some(thread),
+            logger,
+            receiver: Some(receiver),
+            agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+        })
+    }
+
+    /// Writes the messages of the server, such as the requests it answers,
+    /// to `log_file`.
+    pub fn with_log(mut self, log_file: PathBuf) -> Self {
+        if let Some(receiver) = self.receiver.take() {
+            thread::spawn(move || {
+                let _ = handle_messages(&receiver, ErrorHandler::File(log_file));
+            });
+        }
+        self
+    }
+
+    /// Returns the address the server listens on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the URL of a route of the server, e.g. `/healthz`.
+    pub fn url(&self, route: &str) -> String {
+        format!("http://{}{route}", self.addr)
+    }
+
+    /// Sends a `GET` request to a route of the server.
+    ///
+    /// # Arguments
+    /// * `route` - The path and query string, e.g. `/api/stats`.
+    ///
+    /// # Returns
+    /// The status code and the body of the response, or an `anyhow::Result`
+    /// error if the server cannot be reached.
+    pub fn get(&self, route: &str) -> anyhow::Result<(u16, String)> {
+        response(self.agent.get(&self.url(route)).call())
+    }
+
+    /// Sends a `POST` request to a route of the server.
+    ///
+    /// # Arguments
+    /// * `route` - The path and query string, e.g. `/query?k=5`.
+    /// * `content_type` - The media type of `body`.
+    /// * `body` - The body of the request.
+    ///
+    /// # Returns
+    /// The status code and the body of the response, or an `anyhow::Result`
+    /// error if the server cannot be reached.
+    pub fn post(
+        &self,
+        route: &str,
+        content_type: &str,
+        body: &str,
+    ) -> anyhow::Result<(u16, String)> {
+        response(
+            self.agent
+                .post(&self.url(route))
+                .set("Content-Type", content_type)
+                .send_string(body),
+        )
+    }
+
+    /// Searches the hosted index through `POST /query`.
+    ///
+    /// # Arguments
+    /// * `query` - The query.
+    ///
+    /// # Returns
+    /// The paths of the hits, in order of rank, or an `anyhow::Result` error
+    /// if the server refused the query.
+    pub fn query(&self, query: &str) -> anyhow::Result<Vec<PathBuf>> {
+        let (status, body) = self.post("/query", "text/plain", query)?;
+        if status != 200 {
+            return Err(anyhow!("query {query:?} failed with {status}: {body}"));
+        }
+        if body == "Zero matches!" {
+            return Ok(Vec::new());
+        }
+        Ok(body
+            .lines()
+            .filter_map(|line| line.split('\t').next())
+            .map(PathBuf::from)
+            .collect())
+    }
+}
+
+impl Drop for TestServer {
+    /// Stops the server and waits for the requests being answered.
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        self.logger.stop();
+    }
+}
+
+/// Reads the status code and the body of a response, error statuses
+/// included.
+fn response(result: Result<ureq::Response, ureq::Error>) -> anyhow::Result<(u16, String)> {
+    let response = match result {
+        Ok(response) => response,
+        Err(ureq::Error::Status(_, response)) => response,
+        Err(err) => return Err(anyhow!(err).context("send request")),
+    };
+    let status = response.status();
+    let body = response.into_string().context("read response")?;
+    Ok((status, body))
+}
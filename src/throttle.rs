@@ -0,0 +1,51 @@
+use anyhow::Context;
+
+use std::process::Command;
+use std::time::Duration;
+
+/// The number of worker threads used by a throttled run.
+pub const THROTTLE_THREADS: usize = 2;
+
+/// The pause taken after each document in a throttled run.
+pub const THROTTLE_PAUSE: Duration = Duration::from_millis(25);
+
+/// Lowers the CPU and IO priority of the current process so that a
+/// background indexing run yields to foreground work.
+///
+/// Uses `renice` and `ionice` (idle IO class) when they are available.
+///
+/// # Returns
+/// `Ok(())` if the priority was lowered, or an `anyhow::Result` error if a
+/// tool is missing or fails.
+pub fn lower_priority() -> anyhow::Result<()> {
+    let pid = std::process::id().to_string();
+    let status = Command::new("renice")
+        .args(["-n", "19", "-p", &pid])
+        .output()
+        .context("run renice")?
+        .status;
+    if !status.success() {
+        anyhow::bail!("renice exited with {status}");
+    }
+    let status = Command::new("ionice")
+        .args(["-c", "3", "-p", &pid])
+        .output()
+        .context("run ionice")?
+        .status;
+    if !status.success() {
+        anyhow::bail!("ionice exited with {status}");
+    }
+    Ok(())
+}
+
+/// Builds the thread pool of a throttled run.
+///
+/// # Returns
+/// A `rayon::ThreadPool` bounded to `THROTTLE_THREADS` threads, or an
+/// `anyhow::Result` error if it cannot be built.
+pub fn thread_pool() -> anyhow::Result<rayon::ThreadPool> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(THROTTLE_THREADS)
+        .build()
+        .context("build throttled thread pool")
+}
@@ -1,21 +1,63 @@
-use anyhow::Context;
+use anyhow::{Context, anyhow};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    ffi::OsStr,
+    fmt,
     fs::{self, File},
     io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
-    path::{Path, PathBuf},
-    sync::atomic::AtomicU64,
-    time::SystemTime,
+    ops::Bound,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{FileExt, MetadataExt},
+    },
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex, atomic::AtomicU64},
+    thread,
+    time::{Duration, SystemTime},
 };
 
 use serde::{Deserialize, Serialize};
 
+use crate::analyzer::{Analyzer, Language, is_field_term};
+use crate::bloom::{BLOOM_FILE, BloomFilter};
+use crate::boost::ScoreBoost;
+use crate::budget::{
+    EvictionPolicy, SizeBudget, index_size, load_matches, record_evictions, select_evictions,
+};
+use crate::cache::{DICT_CACHE_BYTES, DictCache, PostingsCache};
+use crate::crypto::{self, Cipher};
+use crate::embedded::{EMBEDDED_SEPARATOR, EMBEDDED_URI_PREFIX, container_file};
+use crate::fuzzy::{MAX_FUZZY_EXPANSIONS, edit_distance, is_fuzzy_candidate};
+use crate::highlight::{Fragment, highlight_text};
+use crate::lease::{ReaderLease, oldest_read_generation};
+use crate::lexer::{EXACT_PREFIX, exact_word};
+use crate::manifest::{INDEX_FORMAT, Manifest, install_file, stage_file, staged_path, sync_dir};
+use crate::metadata_filter::{MetadataFilter, extension_of};
+use crate::observer::IndexingObserver;
+use crate::parsers::extract_text;
+use crate::postings::{PostingsIter, VARBYTE_POSTINGS_FILE, encode_postings};
+use crate::publish::resolve_index;
+use crate::query::{BoolExpr, phrase_parts, term_parts};
+use crate::quota::QuotaUsage;
+use crate::recovery::{Recovery, recover_index};
+use crate::scoring::{
+    Bm25, FieldWeights, Ranker, Scorer, SortOrder, TermStats, TfIdf, proximity_score,
+};
+use crate::settings::BoostRule;
+use crate::source::scheme_of;
+use crate::stats::{IndexStats, STATS_FILE};
+use crate::stopwords::custom_stop_words;
+use crate::tags::has_tag;
+use crate::walker::ModifiedTimes;
+
 /// Type alias for Document ID.
 type DocId = u64;
 /// Type alias for Term Frequency.
 type TermFrequency = u32;
 /// Type alias for a search Term.
 type Term = String;
+/// Type alias for the position of a token within a document.
+type Position = u32;
 
 /// Stores metadata about documents, mapping paths to IDs and vice-versa.
 #[derive(Serialize, Deserialize, Default)]
@@ -24,10 +66,19 @@ pub struct DocumentStore {
     pub doc_count: u64,
     /// The next available document ID.
     pub next_id: AtomicU64,
-    /// Maps document paths to their unique IDs.
-    pub doc_to_id: HashMap<PathBuf, DocId>,
+    /// Maps document URIs to their unique IDs: filesystem paths for files,
+    /// `<scheme>:<id>` for virtual documents (see `source::SourceScheme`).
+    /// Ordered by path components, so that the documents under a directory
+    /// are contiguous (see `find_by_prefix`).
+    pub doc_to_id: BTreeMap<PathBuf, DocId>,
     /// Maps document IDs to `DocInfo` containing path and indexed time.
     pub id_to_doc_info: HashMap<DocId, DocInfo>,
+    /// Maps content hashes to the ID of the document indexed with that
+    /// content, so that identical files are only indexed once.
+    pub content_to_id: HashMap<String, DocId>,
+    /// The IDs of deleted documents whose postings are still in segments.
+    /// Their postings are never searched and are left out of merges.
+    pub tombstones: HashSet<DocId>,
 }
 
 /// Contains information about a document, including its path and the time it
@@ -36,8 +87,58 @@ pub struct DocumentStore {
 pub struct DocInfo {
     /// The `SystemTime` when the document was indexed.
     pub indexed_at: SystemTime,
-    /// The file path of the document.
+    /// The URI of the document: its file path, or `<scheme>:<id>` for
+    /// virtual documents.
     pub path: PathBuf,
+    /// The number of tokens indexed for the document.
+    pub token_count: u64,
+    /// The hex encoded SHA-256 hash of the document content.
+    pub content_hash: String,
+    /// Other paths with identical content, sharing this document's postings.
+    pub aliases: Vec<PathBuf>,
+    /// The ISO 639-1 code of the document's language, if it was detected.
+    pub language: Option<String>,
+    /// The indexed versions of the document, oldest first. Only the current
+    /// one is kept unless the index keeps history.
+    pub versions: Vec<DocVersion>,
+    /// The URI of the document this one is embedded in, e.g. the mail of an
+    /// attachment.
+    pub parent: Option<PathBuf>,
+    /// The title of the document, if it has one, shown in results instead
+    /// of its path.
+    pub title: Option<String>,
+    /// The device and inode numbers of the file, recognising it once moved.
+    pub inode: Option<(u64, u64)>,
+    /// When the file was last modified, as of its indexing.
+    pub modified: Option<SystemTime>,
+    /// The size of the file in bytes, as of its indexing.
+    pub size: Option<u64>,
+    /// The extension of the document, lowercase, matched by `--ext`.
+    pub extension: Option<String>,
+    /// The extracted text of the document, kept if its indexing policy asks
+    /// for it so that snippets don't depend on the file.
+    pub text: Option<String>,
+    /// The tags attached to the document when it was indexed, e.g.
+    /// `project=alpha`, matched by `tag:` filters.
+    pub tags: BTreeSet<String>,
+}
+
+/// One indexed version of a document.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DocVersion {
+    /// The generation of the index commit that added this version; its
+    /// postings are tagged with it.
+    pub generation: u64,
+    /// When this version was indexed.
+    pub indexed_at: SystemTime,
+}
+
+impl DocInfo {
+    /// Returns the version of the document that was current at `time`, or
+    /// `None` if it was not indexed yet.
+    pub fn version_at(&self, time: SystemTime) -> Option<&DocVersion> {
+        self.versions.iter().rev().find(|v| v.indexed_at <= time)
+    }
 }
 
 impl Default for DocInfo {
@@ -47,6 +148,19 @@ impl Default for DocInfo {
         Self {
             path: Default::default(),
             indexed_at: SystemTime::UNIX_EPOCH,
+            token_count: 0,
+            content_hash: String::new(),
+            aliases: Vec::new(),
+            language: None,
+            versions: Vec::new(),
+            parent: None,
+            title: None,
+            inode: None,
+            modified: None,
+            size: None,
+            extension: None,
+            text: None,
+            tags: BTreeSet::new(),
         }
     }
 }
@@ -60,6 +174,16 @@ impl DocumentStore {
     ///
     /// # Returns
     /// The `DocId` for the given document path.
+    /// Returns the generation of the current version of every live
+    /// document, `None` for documents indexed before versions were recorded.
+    pub fn live_versions(&self) -> HashMap<DocId, Option<u64>> {
+        self.id_to_doc_info
+            .iter()
+            .filter(|(id, _)| !self.tombstones.contains(id))
+            .map(|(id, info)| (*id, info.versions.last().map(|v| v.generation)))
+            .collect()
+    }
+
     pub fn get_id(&mut self, path: &Path) -> DocId {
         if let Some(id) = self.doc_to_id.get(path) {
             *id
@@ -73,7 +197,7 @@ impl DocumentStore {
                 id,
                 DocInfo {
                     path: doc,
-                    indexed_at: SystemTime::UNIX_EPOCH,
+                    ..Default::default()
                 },
             );
             self.doc_count += 1;
@@ -81,6 +205,543 @@ impl DocumentStore {
         }
     }
 
+    /// Maps `path` to an already indexed document with identical content, so
+    /// that the content is only indexed once. A path whose content diverged
+    /// from the document it was mapped to is given its own document again.
+    /// Otherwise the content is claimed for `path`, so that identical
+    /// documents indexed in parallel are mapped to it rather than indexed
+    /// too; the claim is dropped by `release_content` if indexing it fails.
+    ///
+    /// # Arguments
+    /// * `path` - The `Path` of the document about to be indexed.
+    /// * `hash` - The content hash of the document.
+    ///
+    /// # Returns
+    /// `true` if `path` now shares the postings of another document and
+    /// doesn't need to be indexed, `false` otherwise.
+    pub fn dedup(&mut self, path: &Path, hash: &str) -> bool {
+        let id = self.get_id(path);
+        if let Some(info) = self.id_to_doc_info.get(&id)
+            && info.path != path
+            && info.content_hash == hash
+        {
+            return true;
+        }
+        self.unalias(path);
+        let id = self.get_id(path);
+
+        let original = self.content_to_id.get(hash).copied();
+        let Some(original) = original.filter(|&original| {
+            original != id
+                && self
+                    .id_to_doc_info
+                    .get(&original)
+                    .is_some_and(|info| info.path.exists())
+        }) else {
+            self.set_content_hash(id, hash);
+            return false;
+        };
+
+        // Fold this document into the original. Paths that shared its old
+        // content are unmapped so that they are indexed on their own again.
+        if let Some(info) = self.id_to_doc_info.remove(&id) {
+            self.doc_count -= 1;
+            if self.content_to_id.get(&info.content_hash) == Some(&id) {
+                self.content_to_id.remove(&info.content_hash);
+            }
+            for alias in &info.aliases {
+                self.doc_to_id.remove(alias);
+            }
+            self.doc_to_id.insert(info.path.clone(), original);
+            if let Some(original_info) = self.id_to_doc_info.get_mut(&original) {
+                original_info.aliases.push(info.path);
+            }
+        }
+        true
+    }
+
+    /// Drops the claim of the document at `path` on its content made by
+    /// `dedup`, once indexing it failed. The paths mapped to it are
+    /// unmapped, so that they are indexed on their own again.
+    ///
+    /// # Arguments
+    /// * `path` - The `Path` of the document that failed to be indexed.
+    pub fn release_content(&mut self, path: &Path) {
+        let Some(&id) = self.doc_to_id.get(path) else {
+            return;
+        };
+        let Some(info) = self.id_to_doc_info.get_mut(&id) else {
+            return;
+        };
+        let hash = std::mem::take(&mut info.content_hash);
+        for alias in std::mem::take(&mut info.aliases) {
+            self.doc_to_id.remove(&alias);
+        }
+        if self.content_to_id.get(&hash) == Some(&id) {
+            self.content_to_id.remove(&hash);
+        }
+    }
+
+    /// Gives `path` its own document again if it was mapped to another one
+    /// with identical content, e.g. before indexing it in an index keeping
+    /// duplicates.
+    ///
+    /// # Arguments
+    /// * `path` - The `Path` of the document about to be indexed.
+    pub fn unalias(&mut self, path: &Path) {
+        let Some(&id) = self.doc_to_id.get(path) else {
+            return;
+        };
+        if let Some(info) = self.id_to_doc_info.get_mut(&id)
+            && info.path != path
+        {
+            info.aliases.retain(|alias| alias != path);
+            self.doc_to_id.remove(path);
+        }
+    }
+
+    /// Returns `true` if the document at `uri` is not indexed yet, or its
+    /// indexed version expired according to the scheme of its URI.
+    ///
+    /// # Arguments
+    /// * `uri` - The URI of the document.
+    /// * `modified` - The modification times read during discovery.
+    pub fn needs_indexing(&self, uri: &Path, modified: &ModifiedTimes) -> bool {
+        match self
+            .doc_to_id
+            .get(uri)
+            .and_then(|id| self.id_to_doc_info.get(id))
+        {
+            Some(info) => scheme_of(uri).is_expired(uri, info, modified),
+            None => true,
+        }
+    }
+
+    /// Returns when the document at `uri` was last indexed, or `None` if it
+    /// is not indexed.
+    pub fn indexed_at(&self, uri: &Path) -> Option<SystemTime> {
+        self.doc_to_id
+            .get(uri)
+            .and_then(|id| self.id_to_doc_info.get(id))
+            .map(|info| info.indexed_at)
+    }
+
+    /// Drops the documents that are not in `live`, e.g. files that failed to
+    /// parse or whose postings were merged away, and optionally remaps the
+    /// remaining ids to `0..n` so that the id space doesn't grow forever.
+    ///
+    /// # Arguments
+    /// * `live` - The ids of the documents that still have postings.
+    /// * `remap` - Whether to assign new, dense ids to the remaining documents.
+    ///
+    /// # Returns
+    /// A `HashMap` from old to new ids of the remaining documents, empty if
+    /// `remap` is `false`.
+    pub fn compact(&mut self, live: &HashSet<DocId>, remap: bool) -> HashMap<DocId, DocId> {
+        self.id_to_doc_info.retain(|id, _| live.contains(id));
+        self.doc_to_id
+            .retain(|_, id| self.id_to_doc_info.contains_key(id));
+        self.content_to_id
+            .retain(|_, id| self.id_to_doc_info.contains_key(id));
+        self.doc_count = self.id_to_doc_info.len() as u64;
+        if !remap {
+            return HashMap::new();
+        }
+
+        let mut ids: Vec<DocId> = self.id_to_doc_info.keys().copied().collect();
+        ids.sort_unstable();
+        let mapping: HashMap<DocId, DocId> = ids
+            .into_iter()
+            .enumerate()
+            .map(|(new_id, old_id)| (old_id, new_id as DocId))
+            .collect();
+
+        self.id_to_doc_info = std::mem::take(&mut self.id_to_doc_info)
+            .into_iter()
+            .map(|(id, info)| (mapping[&id], info))
+            .collect();
+        for id in self
+            .doc_to_id
+            .values_mut()
+            .chain(self.content_to_id.values_mut())
+        {
+            *id = mapping[id];
+        }
+        self.next_id = AtomicU64::new(mapping.len() as u64);
+        mapping
+    }
+
+    /// Finds the documents at `path_prefix`, or under it if it is a
+    /// directory, along with the documents embedded in them, without
+    /// scanning the other documents.
+    ///
+    /// # Arguments
+    /// * `path_prefix` - The file or directory to look under.
+    ///
+    /// # Returns
+    /// The URIs and IDs of the documents found, in URI order.
+    pub fn find_by_prefix(&self, path_prefix: &Path) -> Vec<(&Path, DocId)> {
+        // Documents embedded in the files under a directory are under
+        // `embedded:<dir>`, those embedded in a file under `embedded:<file>!`
+        let embedded = format!("{EMBEDDED_URI_PREFIX}{}", path_prefix.to_string_lossy());
+        let container = format!("{embedded}{}", EMBEDDED_SEPARATOR.trim_end_matches('/'));
+        let roots = [
+            path_prefix.to_path_buf(),
+            PathBuf::from(embedded),
+            PathBuf::from(container),
+        ];
+        let mut found: Vec<(&Path, DocId)> = roots
+            .iter()
+            .flat_map(|root| {
+                self.doc_to_id
+                    .range::<Path, _>((Bound::Included(root.as_path()), Bound::Unbounded))
+                    .take_while(move |(uri, _)| uri.starts_with(root))
+            })
+            .filter(|(uri, _)| renamed_uri(uri, path_prefix, path_prefix).is_some())
+            .map(|(uri, id)| (uri.as_path(), *id))
+            .collect();
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+
+    /// Deletes the documents at `path`, or under it if it is a directory,
+    /// along with the documents embedded in them. A deleted path sharing its
+    /// content with other paths only unmaps it; the last path of a document
+    /// tombstones its ID until a merge purges its postings.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the documents to delete.
+    ///
+    /// # Returns
+    /// The URIs of the deleted documents.
+    pub fn delete(&mut self, path: &Path) -> Vec<PathBuf> {
+        let uris: Vec<PathBuf> = self
+            .find_by_prefix(path)
+            .into_iter()
+            .map(|(uri, _)| uri.to_path_buf())
+            .collect();
+        let deleted: HashSet<&PathBuf> = uris.iter().collect();
+
+        for uri in &uris {
+            let Some(id) = self.doc_to_id.remove(uri) else {
+                continue;
+            };
+            let Some(info) = self.id_to_doc_info.get_mut(&id) else {
+                continue;
+            };
+            info.aliases.retain(|alias| alias != uri);
+            if info.path != *uri {
+                continue;
+            }
+            // Another path with the same content takes the document over
+            if let Some(pos) = info.aliases.iter().position(|a| !deleted.contains(a)) {
+                info.path = info.aliases.remove(pos);
+                let new_path = info.path.clone();
+                self.set_file_metadata(id, &new_path);
+                continue;
+            }
+
+            if let Some(info) = self.id_to_doc_info.remove(&id) {
+                for alias in &info.aliases {
+                    self.doc_to_id.remove(alias);
+                }
+                if self.content_to_id.get(&info.content_hash) == Some(&id) {
+                    self.content_to_id.remove(&info.content_hash);
+                }
+            }
+            self.tombstones.insert(id);
+            self.doc_count = self.doc_count.saturating_sub(1);
+        }
+        uris
+    }
+
+    /// Records the document an indexed document is embedded in, whose tags
+    /// and modification time it takes, and the size of its content.
+    ///
+    /// # Arguments
+    /// * `id` - The `DocId` of the embedded document.
+    /// * `parent` - The URI of the document it is embedded in.
+    /// * `size` - The size of the embedded document in bytes.
+    pub fn set_parent(&mut self, id: DocId, parent: &Path, size: u64) {
+        let (tags, modified) = self
+            .doc_to_id
+            .get(parent)
+            .and_then(|parent_id| self.id_to_doc_info.get(parent_id))
+            .map(|info| (info.tags.clone(), info.modified))
+            .unwrap_or_default();
+        if let Some(info) = self.id_to_doc_info.get_mut(&id) {
+            info.parent = Some(parent.to_path_buf());
+            info.tags = tags;
+            info.modified = modified;
+            info.size = Some(size);
+            info.extension = extension_of(&info.path);
+        }
+    }
+
+    /// Records the detected language of an indexed document, replacing that
+    /// of its previous version.
+    ///
+    /// # Arguments
+    /// * `id` - The `DocId` of the indexed document.
+    /// * `language` - The language detected by its parser, if any.
+    pub fn set_language(&mut self, id: DocId, language: Option<Language>) {
+        if let Some(info) = self.id_to_doc_info.get_mut(&id) {
+            info.language = language.map(|language| language.code().to_string());
+        }
+    }
+
+    /// Records the title of an indexed document, replacing the title of its
+    /// previous version.
+    ///
+    /// # Arguments
+    /// * `id` - The `DocId` of the indexed document.
+    /// * `title` - The title extracted by its parser, if any.
+    pub fn set_title(&mut self, id: DocId, title: Option<String>) {
+        if let Some(info) = self.id_to_doc_info.get_mut(&id) {
+            info.title = title;
+        }
+    }
+
+    /// Records the text kept for an indexed document, replacing the text of
+    /// its previous version.
+    ///
+    /// # Arguments
+    /// * `id` - The `DocId` of the indexed document.
+    /// * `text` - The extracted text, or `None` not to keep any.
+    pub fn set_text(&mut self, id: DocId, text: Option<String>) {
+        if let Some(info) = self.id_to_doc_info.get_mut(&id) {
+            info.text = text;
+        }
+    }
+
+    /// Returns the text kept for the document at `path`, if any.
+    pub fn text(&self, path: &Path) -> Option<&str> {
+        self.doc_to_id
+            .get(path)
+            .and_then(|id| self.id_to_doc_info.get(id))
+            .and_then(|info| info.text.as_deref())
+    }
+
+    /// Records the tags of an indexed document, replacing those of its
+    /// previous version.
+    ///
+    /// # Arguments
+    /// * `id` - The `DocId` of the indexed document.
+    /// * `tags` - The tags of the document.
+    pub fn set_tags(&mut self, id: DocId, tags: BTreeSet<String>) {
+        if let Some(info) = self.id_to_doc_info.get_mut(&id) {
+            info.tags = tags;
+        }
+    }
+
+    /// Returns the tags of the document at `path`, if it is indexed.
+    pub fn tags(&self, path: &Path) -> Option<&BTreeSet<String>> {
+        self.doc_to_id
+            .get(path)
+            .and_then(|id| self.id_to_doc_info.get(id))
+            .map(|info| &info.tags)
+    }
+
+    /// Returns the title of the document at `path`, if it has one.
+    pub fn title(&self, path: &Path) -> Option<&str> {
+        self.doc_to_id
+            .get(path)
+            .and_then(|id| self.id_to_doc_info.get(id))
+            .and_then(|info| info.title.as_deref())
+    }
+
+    /// Records the device and inode numbers of the file of an indexed
+    /// document, so that it is recognised once moved, along with its
+    /// modification time, size and extension for sorting and filtering
+    /// results.
+    ///
+    /// # Arguments
+    /// * `id` - The `DocId` of the indexed document.
+    /// * `path` - The file of the document.
+    pub fn set_file_metadata(&mut self, id: DocId, path: &Path) {
+        if let Some(info) = self.id_to_doc_info.get_mut(&id) {
+            let metadata = fs::metadata(path).ok();
+            info.inode = metadata.as_ref().map(|m| (m.dev(), m.ino()));
+            info.modified = metadata.as_ref().and_then(|m| m.modified().ok());
+            info.size = metadata.map(|m| m.len());
+            info.extension = extension_of(path);
+        }
+    }
+
+    /// Moves the documents at `from`, or under it if it is a directory, to
+    /// `to` without re-indexing them. The documents embedded in them move
+    /// with them.
+    ///
+    /// # Arguments
+    /// * `from` - The old path of the documents.
+    /// * `to` - The new path of the documents.
+    ///
+    /// # Returns
+    /// The old and new URIs of the moved documents, or an `anyhow::Result`
+    /// error if a new URI is already indexed.
+    pub fn rename(&mut self, from: &Path, to: &Path) -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
+        let moves: Vec<(PathBuf, PathBuf)> = self
+            .find_by_prefix(from)
+            .into_iter()
+            .filter_map(|(uri, _)| Some((uri.to_path_buf(), renamed_uri(uri, from, to)?)))
+            .collect();
+        let moving: HashSet<&PathBuf> = moves.iter().map(|(old, _)| old).collect();
+        if let Some((_, taken)) = moves
+            .iter()
+            .find(|(_, new)| self.doc_to_id.contains_key(new) && !moving.contains(new))
+        {
+            return Err(anyhow!("{taken:?} is already indexed"));
+        }
+
+        let ids: Vec<DocId> = moves
+            .iter()
+            .filter_map(|(old, _)| self.doc_to_id.remove(old))
+            .collect();
+        for ((old, new), &id) in moves.iter().zip(&ids) {
+            self.doc_to_id.insert(new.clone(), id);
+            if let Some(info) = self.id_to_doc_info.get_mut(&id) {
+                if &info.path == old {
+                    info.path = new.clone();
+                }
+                for alias in &mut info.aliases {
+                    if alias == old {
+                        *alias = new.clone();
+                    }
+                }
+            }
+        }
+        // The documents embedded in a moved document are moved with it
+        for id in ids {
+            if let Some(info) = self.id_to_doc_info.get_mut(&id)
+                && let Some(parent) = &info.parent
+                && let Some(new) = renamed_uri(parent, from, to)
+            {
+                info.parent = Some(new);
+            }
+        }
+        Ok(moves)
+    }
+
+    /// Returns the indexed files that no longer exist, keyed by their
+    /// device and inode numbers, with their size and modification time as
+    /// of their indexing.
+    fn missing_files(&self) -> HashMap<(u64, u64), MissingFile> {
+        self.doc_to_id
+            .iter()
+            .filter_map(|(uri, id)| {
+                let info = self.id_to_doc_info.get(id)?;
+                let inode = info.inode?;
+                let file = scheme_of(uri).local_path(uri)?;
+                (info.path == *uri && fs::symlink_metadata(&file).is_err()).then(|| {
+                    let missing = MissingFile {
+                        uri: uri.clone(),
+                        size: info.size,
+                        modified: info.modified,
+                    };
+                    (inode, missing)
+                })
+            })
+            .collect()
+    }
+
+    /// Returns `true` if an indexed file no longer exists, so that it may
+    /// have been moved.
+    pub fn has_missing_files(&self) -> bool {
+        !self.missing_files().is_empty()
+    }
+
+    /// Detects the indexed files that were moved to one of the `discovered`
+    /// paths, by their device and inode numbers, size and modification
+    /// time, and moves their documents there without re-indexing them.
+    /// Renaming a file keeps all of them, while a new file reusing the inode
+    /// of a deleted one is unlikely to match its size and modification time.
+    ///
+    /// # Arguments
+    /// * `discovered` - The paths found by the discovery of an indexing run.
+    ///
+    /// # Returns
+    /// The old and new URIs of the moved documents.
+    pub fn detect_renames(&mut self, discovered: &[PathBuf]) -> Vec<(PathBuf, PathBuf)> {
+        let missing = self.missing_files();
+        if missing.is_empty() {
+            return Vec::new();
+        }
+
+        let mut renamed = Vec::new();
+        for doc in discovered {
+            if self.doc_to_id.contains_key(doc) {
+                continue;
+            }
+            let Ok(metadata) = fs::metadata(doc) else {
+                continue;
+            };
+            if let Some(old) = missing.get(&(metadata.dev(), metadata.ino()))
+                && old.size == Some(metadata.len())
+                && old.modified.is_some()
+                && old.modified == metadata.modified().ok()
+                && let Ok(mut moved) = self.rename(&old.uri, doc)
+            {
+                renamed.append(&mut moved);
+            }
+        }
+        renamed
+    }
+
+    /// Deletes the documents of the files under `root` that no longer exist,
+    /// so that they stop showing up in search results. Run after
+    /// `detect_renames`, so that moved files keep their documents.
+    ///
+    /// # Arguments
+    /// * `root` - The file or directory being indexed.
+    ///
+    /// # Returns
+    /// The URIs of the deleted documents.
+    pub fn purge_missing(&mut self, root: &Path) -> Vec<PathBuf> {
+        let missing: Vec<PathBuf> = self
+            .find_by_prefix(root)
+            .into_iter()
+            .filter(|(uri, _)| {
+                scheme_of(uri)
+                    .local_path(uri)
+                    .is_some_and(|file| fs::symlink_metadata(file).is_err())
+            })
+            .map(|(uri, _)| uri.to_path_buf())
+            .collect();
+
+        let mut deleted = Vec::new();
+        for uri in missing {
+            // Already deleted along with a missing directory or container
+            if self.doc_to_id.contains_key(&uri) {
+                deleted.append(&mut self.delete(&uri));
+            }
+        }
+        deleted
+    }
+
+    /// Records the content hash of an indexed document. If the content of
+    /// the document changed, the paths that shared its old content are
+    /// unmapped so that they are indexed on their own again.
+    ///
+    /// # Arguments
+    /// * `id` - The `DocId` of the indexed document.
+    /// * `hash` - The content hash of the document.
+    pub fn set_content_hash(&mut self, id: DocId, hash: &str) {
+        let Some(info) = self.id_to_doc_info.get_mut(&id) else {
+            return;
+        };
+        if info.content_hash != hash {
+            if self.content_to_id.get(&info.content_hash) == Some(&id) {
+                self.content_to_id.remove(&info.content_hash);
+            }
+            for alias in std::mem::take(&mut info.aliases) {
+                self.doc_to_id.remove(&alias);
+            }
+            info.content_hash = hash.to_string();
+        }
+        self.content_to_id.insert(hash.to_string(), id);
+    }
+
     /// Retrieves the `PathBuf` associated with a given `DocId`.
     ///
     /// # Arguments
@@ -102,19 +763,101 @@ impl DocumentStore {
     }
 }
 
+/// The files of an index hold data that cannot be read back, e.g. a
+/// truncated postings list or a garbled document store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptIndexError(pub String);
+
+impl fmt::Display for CorruptIndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for CorruptIndexError {}
+
 /// Represents a posting in an inverted index, linking a document ID
-/// to the term's frequency within that document.
-#[derive(Serialize, Deserialize)]
+/// to the term's frequency and positions within that document.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct Posting {
     /// The ID of a document containing the term.
     pub doc_id: DocId,
     /// How many times the term appears in that document.
     pub tf: TermFrequency,
+    /// The positions of the term within the document, in ascending order.
+    pub positions: Vec<Position>,
+    /// The generation of the document version this posting belongs to.
+    pub generation: u64,
+}
+
+/// The terms of a document folded into the positions of each distinct term,
+/// so that a document is held as one entry per term of its vocabulary rather
+/// than as one string per token. Exact forms precede their stem and share its
+/// position.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TermPositions {
+    /// The positions of each term, in ascending order.
+    positions: HashMap<Term, Vec<Position>>,
+    /// The position of the next token, i.e. the number of tokens so far.
+    next: Position,
+}
+
+impl TermPositions {
+    /// Adds a term after the terms added so far.
+    ///
+    /// # Arguments
+    /// * `term` - The term to add.
+    pub fn push(&mut self, term: Term) {
+        let position = self.next;
+        if exact_word(&term).is_none() {
+            self.next += 1;
+        }
+        match self.positions.get_mut(&term) {
+            Some(positions) => positions.push(position),
+            None => {
+                self.positions.insert(term, vec![position]);
+            }
+        }
+    }
+
+    /// Returns whether no term was added.
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Returns the number of tokens of the document, exact forms excluded.
+    pub fn token_count(&self) -> u64 {
+        self.next as u64
+    }
+
+    /// Returns the distinct terms with their positions, in no particular
+    /// order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Term, &[Position])> {
+        self.positions
+            .iter()
+            .map(|(term, positions)| (term, positions.as_slice()))
+    }
+}
+
+impl Extend<Term> for TermPositions {
+    fn extend<I: IntoIterator<Item = Term>>(&mut self, terms: I) {
+        for term in terms {
+            self.push(term);
+        }
+    }
+}
+
+impl FromIterator<Term> for TermPositions {
+    fn from_iter<I: IntoIterator<Item = Term>>(terms: I) -> Self {
+        let mut positions = Self::default();
+        positions.extend(terms);
+        positions
+    }
 }
 
 /// Metadata for a term within a specific segment's dictionary.
 #[derive(Serialize, Deserialize, Clone, Copy)]
-struct TermInfo {
+pub(crate) struct TermInfo {
     /// How many documents contain this term within the segment.
     df: u32,
     /// Byte offset to the start position of the postings list for this term in
@@ -125,7 +868,11 @@ struct TermInfo {
 }
 
 /// Type alias for a segment's term information, mapping terms to `TermInfo`.
-type SegmentTermInfo = HashMap<Term, TermInfo>;
+pub(crate) type SegmentTermInfo = HashMap<Term, TermInfo>;
+
+/// Type alias for the documents of an index with their terms and term
+/// frequencies, as listed by `MainIndex::document_terms`.
+type DocumentTerms = Vec<(PathBuf, Vec<(Term, TermFrequency)>)>;
 
 /// Represents an in-memory segment of the index, holding postings before
 /// flushing to disk.
@@ -135,6 +882,8 @@ pub struct InMemorySegment {
     pub doc_count: u64,
     /// Maps terms to a list of postings for documents added to *this segment*.
     pub postings: HashMap<Term, Vec<Posting>>,
+    /// The approximate memory held by the postings, in bytes.
+    pub bytes: u64,
 }
 
 impl InMemorySegment {
@@ -142,20 +891,43 @@ impl InMemorySegment {
     ///
     /// # Arguments
     /// * `doc_id` - The ID of the document.
-    /// * `terms` - A slice of terms found in the document.
-    fn add_doc(&mut self, doc_id: DocId, terms: &[Term]) {
+    /// * `generation` - The generation of the document version.
+    /// * `terms` - The terms found in the document with their positions.
+    fn add_doc(&mut self, doc_id: DocId, generation: u64, terms: TermPositions) {
         self.doc_count += 1;
-        let mut term_counts = HashMap::new();
-
-        for term in terms {
-            *term_counts.entry(term).or_insert(0) += 1;
+        for (term, positions) in terms.positions {
+            self.bytes += (size_of::<Posting>() + positions.len() * size_of::<Position>()) as u64;
+            let postings = self.postings.entry(term).or_insert_with_key(|term| {
+                // The key, its list and the slot of the map
+                self.bytes += (term.len() + size_of::<Term>() + size_of::<Vec<Posting>>()) as u64;
+                Vec::new()
+            });
+            postings.push(Posting {
+                doc_id,
+                tf: positions.len() as TermFrequency,
+                positions,
+                generation,
+            });
         }
+    }
 
-        for (term, count) in term_counts {
-            self.postings
-                .entry((&term).to_string())
-                .or_default()
-                .push(Posting { doc_id, tf: count });
+    /// Returns the number of documents containing each term of the segment
+    /// and the versions of the documents it holds, so that the document
+    /// frequencies of the index can be updated without reading the segment
+    /// back once it is flushed.
+    fn document_counts(&self) -> SegmentCounts {
+        let mut versions = BTreeSet::new();
+        let dfs = self
+            .postings
+            .iter()
+            .map(|(term, postings)| {
+                versions.extend(postings.iter().map(|p| (p.doc_id, p.generation)));
+                (term.clone(), postings.len() as i64)
+            })
+            .collect();
+        SegmentCounts {
+            dfs,
+            versions: versions.into_iter().collect(),
         }
     }
 
@@ -163,23 +935,110 @@ impl InMemorySegment {
     ///
     /// # Arguments
     /// * `max_docs` - The maximum number of documents allowed in this segment
-    ///   before flushing.
+    ///   before flushing, used if no memory budget is set.
+    /// * `budget` - The approximate memory in bytes the segment may hold
+    ///   before flushing, if any.
     ///
     /// # Returns
-    /// `true` if the segment's document count meets or exceeds `max_docs`,
-    /// `false` otherwise.
-    fn should_flush(&self, max_docs: u64) -> bool {
-        self.doc_count >= max_docs
+    /// `true` if the segment's memory meets or exceeds `budget`, or without
+    /// a budget, if its document count meets or exceeds `max_docs`; `false`
+    /// otherwise.
+    fn should_flush(&self, max_docs: u64, budget: Option<u64>) -> bool {
+        match budget {
+            Some(budget) => self.bytes >= budget,
+            None => self.doc_count >= max_docs,
+        }
+    }
+}
+
+/// Returns the URI of a document once the file or directory `from` moved to
+/// `to`: its new path if it is `from` or under it, or its new URI if it is
+/// embedded in such a file.
+///
+/// # Returns
+/// The new URI, or `None` if the document doesn't move.
+fn renamed_uri(uri: &Path, from: &Path, to: &Path) -> Option<PathBuf> {
+    if let Some(file) = container_file(uri) {
+        let new_file = renamed_uri(&file, from, to)?;
+        let uri = uri.to_string_lossy();
+        let (_, inner) = uri.split_once(EMBEDDED_SEPARATOR)?;
+        return Some(PathBuf::from(format!(
+            "{EMBEDDED_URI_PREFIX}{}{EMBEDDED_SEPARATOR}{inner}",
+            new_file.to_string_lossy()
+        )));
+    }
+    let rest = uri.strip_prefix(from).ok()?;
+    if rest.as_os_str().is_empty() {
+        Some(to.to_path_buf())
+    } else {
+        Some(to.join(rest))
+    }
+}
+
+/// An indexed file that no longer exists, see `DocumentStore::detect_renames`.
+struct MissingFile {
+    /// The URI of its document.
+    uri: PathBuf,
+    /// Its size in bytes, as of its indexing.
+    size: Option<u64>,
+    /// When it was last modified, as of its indexing.
+    modified: Option<SystemTime>,
+}
+
+/// The document frequencies of a segment flushed since the last commit,
+/// counted from memory before it was written.
+struct SegmentCounts {
+    /// The number of documents containing each term of the segment.
+    dfs: HashMap<Term, i64>,
+    /// The document versions with postings in the segment.
+    versions: Vec<(DocId, u64)>,
+}
+
+/// The document frequencies of the live documents, and the documents with
+/// postings in each segment they were counted from.
+type LiveCounts = (HashMap<Term, u64>, HashMap<u64, Vec<DocId>>);
+
+/// Returns whether `posting` belongs to the current version of a live
+/// document.
+///
+/// # Arguments
+/// * `live_versions` - The current version of every live document, see
+///   `DocumentStore::live_versions`.
+/// * `posting` - The posting.
+fn is_live(live_versions: &HashMap<DocId, Option<u64>>, posting: &Posting) -> bool {
+    live_versions
+        .get(&posting.doc_id)
+        .is_some_and(|generation| generation.is_none_or(|g| g == posting.generation))
+}
+
+/// Adds the counts of a segment to the document frequencies of the index.
+fn add_counts(dfs: &mut HashMap<Term, i64>, counts: HashMap<Term, i64>) {
+    for (term, count) in counts {
+        *dfs.entry(term).or_insert(0) += count;
     }
 }
 
+/// Returns the terms of `dfs` with live documents and their counts.
+fn into_counts(dfs: HashMap<Term, i64>) -> HashMap<Term, u64> {
+    dfs.into_iter()
+        .filter(|(_, df)| *df > 0)
+        .map(|(term, df)| (term, df as u64))
+        .collect()
+}
+
 /// Flushes the contents of an `InMemorySegment` to disk, creating segment files
-/// for the term dictionary and postings lists.
+/// for the term dictionary, the bloom filter of its terms and the postings
+/// lists. The files are written and
+/// synced into a staging directory renamed to the segment directory once
+/// complete, so that a crash never leaves a partial `segment_{id}` behind.
 ///
 /// # Arguments
 /// * `segment_id` - The unique ID of the segment being flushed.
 /// * `segment` - A mutable reference to the `InMemorySegment` to flush.
 /// * `index_dir` - The base directory where index segments are stored.
+/// * `cipher` - The `Cipher` encrypting the segment files, if the index is
+///   encrypted.
+/// * `observer` - Told once the segment is written, if any.
 ///
 /// # Returns
 /// `Ok(())` if the flush was successful, otherwise an `anyhow::Result` error.
@@ -187,17 +1046,24 @@ fn flush_segment(
     segment_id: u64,
     segment: &mut InMemorySegment,
     index_dir: &Path,
+    cipher: Option<&Cipher>,
+    observer: Option<&dyn IndexingObserver>,
 ) -> anyhow::Result<()> {
     if segment.postings.is_empty() {
         return Ok(());
     }
 
     let segment_dir = index_dir.join(format!("segment_{segment_id}"));
-    fs::create_dir_all(&segment_dir).context("create segment dir")?;
-    let dict_path = segment_dir.join("term.dict");
-    let postings_path = segment_dir.join("postings.bin");
+    let staging_dir = staged_path(&segment_dir);
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir).context("remove stale segment staging dir")?;
+    }
+    fs::create_dir_all(&staging_dir).context("create segment dir")?;
+    let dict_path = staging_dir.join("term.dict");
+    let postings_path = staging_dir.join(VARBYTE_POSTINGS_FILE);
 
     let mut segment_dict = SegmentTermInfo::new();
+    let mut bloom = BloomFilter::with_capacity(segment.postings.len());
     let mut post_writer =
         BufWriter::new(File::create(postings_path).context("create postings file")?);
     let mut current_offset: u64 = 0;
@@ -211,9 +1077,10 @@ fn flush_segment(
             postings.sort_unstable_by_key(|p| p.doc_id);
             let doc_freq = postings.len() as u32;
 
-            // serialization
-            // TODO: apply delta + variable-byte encoding here before writing
-            let serialised = bincode2::serialize(postings).context("serialize postings")?;
+            let serialised = encode_postings(postings);
+            // Every list is sealed on its own so that it can still be read
+            // by offset
+            let serialised = crypto::seal(cipher, serialised).context("encrypt postings")?;
 
             let postings_len_bytes = serialised.len() as u64;
             post_writer
@@ -229,31 +1096,159 @@ fn flush_segment(
                 },
             );
 
+            bloom.insert(&term);
             current_offset += postings_len_bytes;
         }
     }
 
-    post_writer.flush().context("flush postings writer")?;
-    let mut dict_writer = BufWriter::new(File::create(dict_path).context("create dict path")?);
-    bincode2::serialize_into(&mut dict_writer, &segment_dict)
+    post_writer
+        .into_inner()
+        .map_err(|err| err.into_error())
+        .and_then(|file| file.sync_all())
+        .context("flush postings writer")?;
+    let serialised = bincode2::serialize(&segment_dict).context("serialize segment dict")?;
+    let serialised = crypto::seal(cipher, serialised).context("encrypt segment dict")?;
+    File::create(dict_path)
+        .and_then(|mut file| {
+            file.write_all(&serialised)?;
+            file.sync_all()
+        })
         .context("write segment dict into file")?;
-    dict_writer.flush().context("flush dict writer")?;
+    // The filter is sealed too, as it tells which terms a segment holds
+    let serialised = bincode2::serialize(&bloom).context("serialize bloom filter")?;
+    let serialised = crypto::seal(cipher, serialised).context("encrypt bloom filter")?;
+    File::create(staging_dir.join(BLOOM_FILE))
+        .and_then(|mut file| {
+            file.write_all(&serialised)?;
+            file.sync_all()
+        })
+        .context("write bloom filter into file")?;
+    sync_dir(&staging_dir)?;
+    fs::rename(&staging_dir, &segment_dir).context("rename segment dir")?;
+    sync_dir(index_dir)?;
 
     segment.postings.clear();
     segment.doc_count = 0;
+    segment.bytes = 0;
 
-    println!("Flushed segment_{segment_id}");
+    if let Some(observer) = observer {
+        observer.on_flush(segment_id);
+    }
     Ok(())
 }
 
+/// The best results of a search, found by `MainIndex::search_top`.
+#[derive(Debug, Clone)]
+pub struct TopResults {
+    /// The results in descending order of score. Every document among the
+    /// best ones asked for is there, along with others scored on the way.
+    pub results: Vec<(PathBuf, f64)>,
+    /// Whether matching documents were skipped for not ranking among the
+    /// best ones, so that `results` are not every match.
+    pub pruned: bool,
+}
+
+/// One page of search results.
+#[derive(Debug, Clone)]
+pub struct SearchPage {
+    /// The matching documents and their scores in descending order of score.
+    pub results: Vec<(PathBuf, f64)>,
+    /// The opaque cursor fetching the next page, or `None` if this is the
+    /// last page.
+    pub next_cursor: Option<String>,
+}
+
+/// The longest path a page cursor holds, `PATH_MAX` on Linux.
+const MAX_CURSOR_PATH: usize = 4096;
+
+/// A page cursor given to `MainIndex::search_page` cannot be used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorError {
+    /// The cursor was not produced by `search_page`.
+    Malformed,
+    /// The index was committed since the cursor was issued.
+    Stale,
+}
+
+impl fmt::Display for CursorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed => f.write_str("malformed cursor"),
+            Self::Stale => f.write_str("stale cursor: the index changed since it was issued"),
+        }
+    }
+}
+
+impl std::error::Error for CursorError {}
+
+/// The last result of a page, from which the next page continues. Only
+/// valid for the index generation it was issued for.
+struct PageCursor {
+    /// The generation of the index the cursor was issued for.
+    generation: u64,
+    /// The score of the last result of the page.
+    score: f64,
+    /// The path of the last result of the page, breaking ties of scores.
+    path: PathBuf,
+}
+
+impl PageCursor {
+    /// Encodes the cursor as an opaque hex string: the generation and the
+    /// score, little endian, followed by the bytes of the path.
+    fn encode(&self) -> String {
+        let mut bytes = self.generation.to_le_bytes().to_vec();
+        bytes.extend(self.score.to_le_bytes());
+        bytes.extend(self.path.as_os_str().as_bytes());
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /// Decodes a cursor produced by `encode`, refusing cursors longer than
+    /// one holding a path of `MAX_CURSOR_PATH` bytes.
+    fn decode(cursor: &str) -> Result<Self, CursorError> {
+        let malformed = CursorError::Malformed;
+        if cursor.len() > 2 * (16 + MAX_CURSOR_PATH)
+            || !cursor.len().is_multiple_of(2)
+            || !cursor.is_ascii()
+        {
+            return Err(malformed);
+        }
+        let bytes = (0..cursor.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&cursor[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| malformed)?;
+        let (generation, rest) = bytes.split_first_chunk::<8>().ok_or(malformed)?;
+        let (score, path) = rest.split_first_chunk::<8>().ok_or(malformed)?;
+        let score = f64::from_le_bytes(*score);
+        if path.is_empty() || !score.is_finite() {
+            return Err(malformed);
+        }
+        Ok(Self {
+            generation: u64::from_le_bytes(*generation),
+            score,
+            path: PathBuf::from(OsStr::from_bytes(path)),
+        })
+    }
+}
+
+/// Orders search results by descending score, breaking ties by path.
+fn compare_results(a: &(PathBuf, f64), b: &(PathBuf, f64)) -> std::cmp::Ordering {
+    b.1.partial_cmp(&a.1)
+        .unwrap_or(std::cmp::Ordering::Equal)
+        .then_with(|| a.0.cmp(&b.0))
+}
+
 /// Represents the main inverted index, managing document storage, segments,
 /// and search operations.
 pub struct MainIndex {
     /// The ID for the next segment to be created.
     pub next_segment: u64,
     /// The maximum number of documents an in-memory segment can hold before
-    /// being flushed.
+    /// being flushed, unless `segment_budget` is set.
     pub max_segment_docs: u64,
+    /// The approximate memory in bytes an in-memory segment can hold before
+    /// being flushed, if any.
+    pub segment_budget: Option<u64>,
     /// The base directory where all index files and segments are stored.
     pub index_dir: PathBuf,
     /// A list of active segment IDs.
@@ -262,15 +1257,227 @@ pub struct MainIndex {
     pub doc_store: DocumentStore,
     /// The current in-memory segment being built.
     pub current_segment: InMemorySegment,
+    /// The persisted index wide settings.
+    pub manifest: Manifest,
+    /// The scorer used when searching with `Ranker::Custom`.
+    pub custom_scorer: Option<Box<dyn Scorer>>,
+    /// Rules multiplying the scores of documents with matching paths.
+    pub boosts: Vec<BoostRule>,
+    /// Hooks multiplying the score of each document by a factor of its own.
+    pub score_boosts: Vec<Arc<dyn ScoreBoost>>,
+    /// The parameters of the BM25 scorer used with `Ranker::Bm25`.
+    pub bm25: Bm25,
+    /// The weights of the score contributions of query terms by the field
+    /// they match.
+    pub field_weights: FieldWeights,
+    /// Search the index as it was at this time instead of its current state.
+    /// Past versions of documents are only found if the index keeps history.
+    pub as_of: Option<SystemTime>,
+    /// The original casing of indexed words, keyed by their normalized form.
+    /// Only recorded if the index preserves case.
+    pub display_forms: HashMap<String, String>,
+    /// The corpus statistics written at the last commit.
+    pub stats: IndexStats,
+    /// The cache of decoded postings lists shared with other readers of the
+    /// index, e.g. by the server.
+    pub postings_cache: Option<Arc<PostingsCache>>,
+    /// The cipher encrypting the index files, if the index is encrypted.
+    cipher: Option<Cipher>,
+    /// Whether the index was opened read-only and refuses every write.
+    read_only: bool,
+    /// The segments that failed to load since the index was opened. They are
+    /// recorded as quarantined in the manifest at the next commit.
+    failed_segments: Mutex<BTreeSet<u64>>,
+    /// The dictionaries of the active segments, if the index was preloaded.
+    preloaded_dicts: HashMap<u64, Arc<SegmentTermInfo>>,
+    /// The recently loaded dictionaries of segments, when not preloaded.
+    dict_cache: DictCache,
+    /// The bloom filters of the terms of segments loaded so far, `None` for
+    /// segments written without one.
+    segment_blooms: Mutex<HashMap<u64, Option<Arc<BloomFilter>>>>,
+    /// The postings lists kept in memory by `preload`, keyed by segment id
+    /// and offset in the postings file.
+    pinned_postings: HashMap<(u64, u64), Vec<Posting>>,
+    /// The segments replaced by a merge or a repair since the last commit,
+    /// which records them as retired in the manifest.
+    retired_segments: Vec<u64>,
+    /// The document frequencies of the segments documents were flushed into
+    /// since the last commit, so that the commit adds them to those of the
+    /// index instead of reading the segments back.
+    flushed_counts: HashMap<u64, SegmentCounts>,
+    /// The files of the committed segments, held open by read-only indexes
+    /// so that segments a concurrent merge removes stay readable until the
+    /// index is opened again.
+    segment_files: HashMap<u64, SegmentFiles>,
+    /// The lease of a read-only index on the generation it reads, keeping
+    /// writers from removing its segments. `None` for writers and for
+    /// indexes on read-only media, which no writer changes.
+    lease: Option<ReaderLease>,
+    /// Told about the segments written while indexing and the segments
+    /// that fail to load, if any.
+    pub observer: Option<Arc<dyn IndexingObserver>>,
+    /// What recovering the index did when it was opened for writing.
+    pub recovery: Recovery,
 }
 
-/// Constant defining the maximum number of documents allowed in an in-memory
-/// segment before flushing.
-const MAX_SEGMENT_DOCS: u64 = 100;
+/// The open files of a segment.
+struct SegmentFiles {
+    /// The term dictionary.
+    dict: File,
+    /// The postings lists.
+    postings: File,
+}
 
-impl MainIndex {
-    /// Creates a new `MainIndex` instance. It loads existing document store
-    /// and segments
+/// Reads `len` bytes of `file` starting at `offset`, without moving the
+/// cursor shared with other readers of the file.
+fn read_at(file: &File, offset: u64, len: u64) -> std::io::Result<Vec<u8>> {
+    let mut buf = vec![0; len as usize];
+    file.read_exact_at(&mut buf, offset)?;
+    Ok(buf)
+}
+
+/// What `MainIndex::preload` loaded into memory.
+#[derive(Debug, Default)]
+pub struct PreloadStats {
+    /// The number of segment dictionaries loaded.
+    pub segments: usize,
+    /// The number of terms in the loaded dictionaries.
+    pub terms: usize,
+    /// The number of postings lists pinned in memory.
+    pub pinned_lists: usize,
+}
+
+/// How a term is spread over the active segments, as reported by
+/// `MainIndex::term_spread`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TermSpread {
+    /// The number of segments whose dictionary holds the term.
+    pub segments: usize,
+    /// The number of documents containing the term: the corrected count
+    /// recorded at the last commit if any, otherwise summed over the
+    /// segments.
+    pub df: u64,
+}
+
+/// The outcome of checking one segment with `MainIndex::verify`.
+#[derive(Debug)]
+pub struct SegmentCheck {
+    /// The id of the checked segment.
+    pub seg_id: u64,
+    /// Whether the segment was already quarantined.
+    pub quarantined: bool,
+    /// Why the segment cannot be read, or `None` if it is healthy.
+    pub error: Option<String>,
+}
+
+/// What `MainIndex::repair` did to the broken segments.
+#[derive(Debug, Default)]
+pub struct RepairSummary {
+    /// The segments whose readable postings were rewritten into a new
+    /// segment.
+    pub rebuilt: Vec<u64>,
+    /// The segments dropped because nothing could be salvaged from them.
+    pub dropped: Vec<u64>,
+    /// The number of documents left without postings, removed from the
+    /// document store so that the next indexing run adds them again.
+    pub removed_docs: usize,
+}
+
+/// What `MainIndex::compact` did to the segments.
+#[derive(Debug, Default)]
+pub struct CompactSummary {
+    /// The segments merged and removed.
+    pub merged: Vec<u64>,
+    /// The segment they were merged into, `None` if no merge happened or no
+    /// live postings remained.
+    pub new_segment: Option<u64>,
+    /// The number of active segments left.
+    pub segments: usize,
+    /// The number of documents without postings left, dropped from the
+    /// document store.
+    pub removed_docs: usize,
+}
+
+/// What an index contains, as reported by `indexer stats`.
+#[derive(Debug, Default)]
+pub struct IndexSummary {
+    /// The number of documents in the index.
+    pub documents: u64,
+    /// The number of distinct (stemmed) terms in the segment dictionaries.
+    pub terms: u64,
+    /// The number of active segments.
+    pub segments: usize,
+    /// The total size of the postings files of the active segments.
+    pub postings_bytes: u64,
+    /// The total size of the index directory.
+    pub index_bytes: u64,
+    /// The documents with the most indexed tokens and their token count,
+    /// largest first.
+    pub largest: Vec<(PathBuf, u64)>,
+    /// When the document indexed the longest ago was indexed, if any.
+    pub oldest: Option<SystemTime>,
+    /// When the document indexed last was indexed, if any.
+    pub newest: Option<SystemTime>,
+    /// How much of its quotas the index uses, if it has any.
+    pub quota: Option<QuotaUsage>,
+}
+
+/// Constant defining the maximum number of documents allowed in an in-memory
+/// segment before flushing.
+pub(crate) const MAX_SEGMENT_DOCS: u64 = 100;
+
+/// Segments whose postings file is smaller than this many bytes are
+/// considered small and are candidates for automatic compaction.
+const SMALL_SEGMENT_BYTES: u64 = 1024 * 1024;
+
+/// How many times a reader retries loading the index files while a commit
+/// rewrites them, and how long it waits between tries.
+const SNAPSHOT_RETRIES: u32 = 100;
+const SNAPSHOT_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// The files of an index as of one committed generation.
+struct Snapshot {
+    manifest: Manifest,
+    doc_store: Option<Vec<u8>>,
+    display_forms: Option<Vec<u8>>,
+    stats: Option<Vec<u8>>,
+}
+
+impl Snapshot {
+    /// Reads the manifest, document store, display forms and statistics of
+    /// the index in `index_dir`, starting over if a commit ran meanwhile, so
+    /// that they all belong to the same generation. If commits keep running
+    /// for `SNAPSHOT_RETRIES` tries, or a commit was interrupted, the last
+    /// read files are used.
+    fn read(index_dir: &Path) -> anyhow::Result<Self> {
+        let mut tries = 0;
+        loop {
+            tries += 1;
+            let manifest = Manifest::load(index_dir).context("load manifest")?;
+            if manifest.committing && tries < SNAPSHOT_RETRIES {
+                thread::sleep(SNAPSHOT_RETRY_DELAY);
+                continue;
+            }
+            let snapshot = Self {
+                doc_store: fs::read(index_dir.join("docstore.bin")).ok(),
+                display_forms: fs::read(index_dir.join("display.bin")).ok(),
+                stats: fs::read(index_dir.join(STATS_FILE)).ok(),
+                manifest,
+            };
+            let current = Manifest::load(index_dir).context("load manifest")?;
+            if tries >= SNAPSHOT_RETRIES
+                || (!current.committing && current.generation == snapshot.manifest.generation)
+            {
+                return Ok(snapshot);
+            }
+            thread::sleep(SNAPSHOT_RETRY_DELAY);
+        }
+    }
+}
+
+impl MainIndex {
+    /// Creates a new `MainIndex` instance. It loads existing document store
+    /// and segments
     /// from the `index_dir` if available, or initializes a new index.
     ///
     /// # Arguments
@@ -280,33 +1487,133 @@ impl MainIndex {
     /// # Returns
     /// `Ok(Self)` if successful, otherwise an `anyhow::Result` error.
     pub fn new(index_dir: &Path) -> anyhow::Result<Self> {
-        let docstore_filepath = index_dir.join("docstore.bin");
+        Self::open(index_dir, None)
+    }
+
+    /// Opens the index in `index_dir`, decrypting its files with the key
+    /// read from `key_file`. A new index opened with a key file is encrypted.
+    /// If `index_dir` has a published index, that index is opened. The index
+    /// is opened for writing: a commit interrupted by a crash is finished and
+    /// partial segments are removed first, see `recover_index`. What was
+    /// repaired is kept in `recovery` for the caller to report.
+    ///
+    /// # Arguments
+    /// * `index_dir` - The directory where index files are located or will be
+    ///   stored.
+    /// * `key_file` - The key file of an encrypted index, if any.
+    ///
+    /// # Returns
+    /// `Ok(Self)` if successful, otherwise an `anyhow::Result` error, e.g. if
+    /// the index is encrypted but no key file was given.
+    pub fn open(index_dir: &Path, key_file: Option<&Path>) -> anyhow::Result<Self> {
+        let index_dir = &resolve_index(index_dir);
+        let recovery = recover_index(index_dir).context("recover index")?;
+        let mut index = Self::load(index_dir, key_file)?;
+        index.recovery = recovery;
+        Ok(index)
+    }
+
+    /// Loads the index in `index_dir` as it was last committed.
+    ///
+    /// # Arguments
+    /// * `index_dir` - The directory where index files are located, resolved
+    ///   to the published index if any.
+    /// * `key_file` - The key file of an encrypted index, if any.
+    ///
+    /// # Returns
+    /// `Ok(Self)` if successful, otherwise an `anyhow::Result` error.
+    fn load(index_dir: &Path, key_file: Option<&Path>) -> anyhow::Result<Self> {
+        let snapshot = Snapshot::read(index_dir)?;
+        let mut manifest = snapshot.manifest;
+        let cipher = match key_file {
+            Some(key_file) => Some(crypto::writer_cipher(
+                index_dir,
+                &mut manifest,
+                snapshot.doc_store.is_some(),
+                key_file,
+                false,
+            )?),
+            None if manifest.encrypted => {
+                return Err(crypto::encryption_error(format!(
+                    "index {index_dir:?} is encrypted; a key file is required"
+                )));
+            }
+            None => None,
+        };
 
-        let buf = fs::read(&docstore_filepath).unwrap_or_default();
-        let doc_store = bincode2::deserialize(&buf).unwrap_or_default();
+        manifest.check_format(index_dir)?;
+        let doc_store = match snapshot.doc_store {
+            Some(buf) => {
+                let buf = crypto::open(cipher.as_ref(), buf).context("decrypt docstore")?;
+                bincode2::deserialize(&buf).map_err(|err| {
+                    let advice =
+                        "re-index required: remove the index and run `indexer index` again";
+                    if manifest.format < INDEX_FORMAT {
+                        anyhow!(
+                            "cannot read the document store of index {index_dir:?}: it was \
+                             written in format {} by an older indexer; {advice}",
+                            manifest.format
+                        )
+                    } else {
+                        anyhow::Error::new(CorruptIndexError(format!(
+                            "cannot read the document store of index {index_dir:?}: it is \
+                             corrupt ({err}); {advice}"
+                        )))
+                    }
+                })?
+            }
+            None => DocumentStore::default(),
+        };
+        // Older indexes whose document store reads in the current layout are
+        // migrated as they are: the format is recorded at the next commit
+        manifest.format = INDEX_FORMAT;
 
         let paths: Vec<PathBuf> = match fs::read_dir(index_dir) {
             Ok(values) => values.map(|e| e.unwrap().path().to_path_buf()).collect(),
             Err(_) => Vec::new(),
         };
 
+        // Staging directories of segments being flushed have no id
         let mut segments = Vec::new();
         for path in paths {
-            if path.is_dir()
-                && path.to_string_lossy().to_string().contains("segment_")
-                && let Some(prefix) = path.file_stem()
+            if let Some(seg_id) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("segment_"))
+                .and_then(|id| id.parse::<u64>().ok())
+                && path.is_dir()
             {
-                let name = prefix.to_string_lossy().to_string();
-                let (_, seg_id) = name.split_once("segment_").unwrap();
-                let seg_id = seg_id
-                    .to_string()
-                    .parse::<u64>()
-                    .context("parsing segment id")?;
                 segments.push(seg_id);
             }
         }
 
-        let next_segment = segments.iter().max().cloned().unwrap_or(0) + 1;
+        // Segment directories left by an unfinished run still take an id,
+        // but only the committed segments are searched
+        let next_segment = segments
+            .iter()
+            .chain(manifest.segments.iter().flatten())
+            .max()
+            .cloned()
+            .unwrap_or(0)
+            + 1;
+        if let Some(committed) = &manifest.segments {
+            segments = committed.clone();
+        }
+        segments.retain(|seg_id| !manifest.quarantined.contains(seg_id));
+        let display_forms = match snapshot.display_forms {
+            Some(buf) if manifest.analyzer.preserve_case => {
+                let buf = crypto::open(cipher.as_ref(), buf).context("decrypt display forms")?;
+                bincode2::deserialize(&buf).unwrap_or_default()
+            }
+            _ => HashMap::new(),
+        };
+        let stats = match snapshot
+            .stats
+            .and_then(|buf| IndexStats::decode(buf, cipher.as_ref()))
+        {
+            Some(stats) => stats,
+            None => IndexStats::compute(&doc_store, 0),
+        };
 
         Ok(Self {
             index_dir: index_dir.to_path_buf(),
@@ -314,13 +1621,209 @@ impl MainIndex {
             active_segments: segments,
             current_segment: InMemorySegment::default(),
             next_segment,
-            max_segment_docs: MAX_SEGMENT_DOCS,
+            max_segment_docs: manifest.profile.segment_docs().unwrap_or(MAX_SEGMENT_DOCS),
+            segment_budget: manifest.segment_budget,
+            manifest,
+            custom_scorer: None,
+            boosts: Vec::new(),
+            score_boosts: Vec::new(),
+            bm25: Bm25::default(),
+            field_weights: FieldWeights::default(),
+            as_of: None,
+            display_forms,
+            stats,
+            postings_cache: None,
+            cipher,
+            read_only: false,
+            failed_segments: Mutex::new(BTreeSet::new()),
+            preloaded_dicts: HashMap::new(),
+            dict_cache: DictCache::new(DICT_CACHE_BYTES),
+            segment_blooms: Mutex::new(HashMap::new()),
+            pinned_postings: HashMap::new(),
+            retired_segments: Vec::new(),
+            flushed_counts: HashMap::new(),
+            segment_files: HashMap::new(),
+            lease: None,
+            observer: None,
+            recovery: Recovery::default(),
         })
     }
 
+    /// Opens the index in `index_dir` for searching only. The index is never
+    /// modified: every method writing to it returns an error instead.
+    ///
+    /// # Arguments
+    /// * `index_dir` - The directory where index files are located.
+    /// * `key_file` - The key file of an encrypted index, if any.
+    ///
+    /// # Returns
+    /// `Ok(Self)` if successful, otherwise an `anyhow::Result` error.
+    pub fn open_read_only(index_dir: &Path, key_file: Option<&Path>) -> anyhow::Result<Self> {
+        let index_dir = &resolve_index(index_dir);
+        let mut tries = 0;
+        loop {
+            tries += 1;
+            let mut index = Self::load(index_dir, key_file)?;
+            index.read_only = true;
+            index.lease = ReaderLease::acquire(index_dir, index.manifest.generation).ok();
+            // A segment missing by now was removed by a merge committed since
+            // the snapshot was read: the next snapshot no longer lists it
+            if index.open_segment_files()
+                || tries >= SNAPSHOT_RETRIES
+                || Manifest::load(index_dir)?.generation == index.manifest.generation
+            {
+                return Ok(index);
+            }
+        }
+    }
+
+    /// Opens the files of every active segment and holds them, so that the
+    /// index keeps reading the segments of its snapshot when a writer
+    /// removes them. Segments that cannot be opened are left to fail, and be
+    /// skipped, when read.
+    ///
+    /// # Returns
+    /// `true` if every active segment was opened.
+    fn open_segment_files(&mut self) -> bool {
+        let mut complete = true;
+        for &seg_id in &self.active_segments {
+            let dict = File::open(self.segment_dir(seg_id).join("term.dict"));
+            match (dict, File::open(self.postings_file(seg_id))) {
+                (Ok(dict), Ok(postings)) => {
+                    let files = SegmentFiles { dict, postings };
+                    self.segment_files.insert(seg_id, files);
+                }
+                _ => complete = false,
+            }
+        }
+        complete
+    }
+
+    /// Loads the dictionaries of every active segment into memory, and the
+    /// postings lists of the `pin_terms` terms with the highest document
+    /// frequency, so that queries don't wait for disk reads. The document
+    /// store is already loaded when the index is opened. Meant for read-only
+    /// indexes serving queries: writes don't update the preloaded data.
+    ///
+    /// # Arguments
+    /// * `pin_terms` - The number of most frequent terms whose postings are
+    ///   kept in memory.
+    ///
+    /// # Returns
+    /// The `PreloadStats`, or an `anyhow::Result` error if a segment cannot
+    /// be read.
+    pub fn preload(&mut self, pin_terms: usize) -> anyhow::Result<PreloadStats> {
+        let mut stats = PreloadStats::default();
+        for &seg_id in &self.active_segments {
+            let seg_dict = self.load_segment_dict(seg_id)?;
+            stats.segments += 1;
+            stats.terms += seg_dict.len();
+            self.preloaded_dicts.insert(seg_id, seg_dict);
+        }
+
+        let mut dfs = self
+            .document_frequencies()
+            .context("document frequencies")?
+            .into_iter()
+            .collect::<Vec<(Term, u64)>>();
+        dfs.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let mut pinned = HashMap::new();
+        for (term, _) in dfs.into_iter().take(pin_terms) {
+            for (&seg_id, seg_dict) in &self.preloaded_dicts {
+                if let Some(metadata) = seg_dict.get(&term) {
+                    let postings = self.read_postings(seg_id, metadata)?;
+                    pinned.insert((seg_id, metadata.postings_offset), postings);
+                }
+            }
+        }
+        stats.pinned_lists = pinned.len();
+        self.pinned_postings = pinned;
+        Ok(stats)
+    }
+
+    /// Returns the cipher encrypting the files of the index, if it is
+    /// encrypted.
+    pub(crate) fn cipher(&self) -> Option<&Cipher> {
+        self.cipher.as_ref()
+    }
+
+    /// Returns `true` if the index was opened read-only.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Fails if the index was opened read-only.
+    ///
+    /// # Arguments
+    /// * `operation` - The attempted write, named in the error.
+    fn ensure_writable(&self, operation: &str) -> anyhow::Result<()> {
+        if self.read_only {
+            return Err(anyhow!(
+                "cannot {operation}: index {:?} is opened read-only",
+                self.index_dir
+            ));
+        }
+        Ok(())
+    }
+
+    /// Creates the `Analyzer` matching the tokenizer options of the index, so
+    /// that documents and queries are analyzed identically.
+    pub fn analyzer(&self) -> Analyzer {
+        let stop_words = match &self.manifest.stop_words {
+            Some(words) if self.manifest.analyzer.stop_words => custom_stop_words(words),
+            _ => self.manifest.analyzer.stop_words(),
+        };
+        Analyzer::new(self.manifest.analyzer, stop_words)
+            .with_stream_chunk(self.manifest.profile.stream_chunk())
+            .with_expansions(&self.manifest.expansions)
+            .with_stem_exceptions(&self.manifest.stem_exceptions)
+    }
+
+    /// Records the original casing of words, keeping the forms already known.
+    ///
+    /// # Arguments
+    /// * `forms` - The display forms keyed by the normalized word.
+    pub fn add_display_forms(&mut self, forms: HashMap<String, String>) {
+        for (word, form) in forms {
+            self.display_forms.entry(word).or_insert(form);
+        }
+    }
+
+    /// Returns the original casing of a normalized word, or the word itself
+    /// if none was recorded.
+    pub fn display_form<'a>(&'a self, word: &'a str) -> &'a str {
+        self.display_forms
+            .get(word)
+            .map(|form| form.as_str())
+            .unwrap_or(word)
+    }
+
+    /// Returns an index term as it is shown to users: the exact form of a
+    /// word in its original casing, any other term as it is.
+    ///
+    /// # Arguments
+    /// * `term` - The index term.
+    ///
+    /// # Returns
+    /// The term to show.
+    pub fn display_term(&self, term: &str) -> String {
+        match exact_word(term) {
+            Some(word) => format!("{EXACT_PREFIX}{}", self.display_form(word)),
+            None => term.to_string(),
+        }
+    }
+
+    /// Registers the scorer used when searching with `Ranker::Custom`.
+    ///
+    /// # Arguments
+    /// * `scorer` - The `Scorer` implementing the custom ranking.
+    pub fn set_custom_scorer(&mut self, scorer: impl Scorer + 'static) {
+        self.custom_scorer = Some(Box::new(scorer));
+    }
+
     /// Adds a document to the index. It tokenizes the document, adds it to the
     /// current in-memory segment, and flushes the segment to disk if it exceeds
-    /// `max_segment_docs`.
+    /// `segment_budget`, or `max_segment_docs` without a budget.
     ///
     /// # Arguments
     /// * `doc_path` - The path to the document to add.
@@ -330,137 +1833,2711 @@ impl MainIndex {
     /// `Ok(())` if the document was added successfully, otherwise an
     /// `anyhow::Result` error.
     pub fn add_document(&mut self, doc_path: &Path, terms: &[Term]) -> anyhow::Result<()> {
+        self.add_document_positions(doc_path, terms.iter().cloned().collect())
+    }
+
+    /// Adds a document to the index from its terms already folded into their
+    /// positions, like `add_document`.
+    ///
+    /// # Arguments
+    /// * `doc_path` - The path to the document to add.
+    /// * `terms` - The terms extracted from the document with their positions.
+    ///
+    /// # Returns
+    /// `Ok(())` if the document was added successfully, otherwise an
+    /// `anyhow::Result` error.
+    pub fn add_document_positions(
+        &mut self,
+        doc_path: &Path,
+        terms: TermPositions,
+    ) -> anyhow::Result<()> {
+        self.ensure_writable("add document")?;
         if terms.is_empty() {
             return Ok(());
         }
 
+        // The version becomes visible with the next commit
+        let generation = self.manifest.generation + 1;
         let doc_id = self.doc_store.get_id(doc_path);
-        self.current_segment.add_doc(doc_id, terms);
+        let token_count = terms.token_count();
+        self.current_segment.add_doc(doc_id, generation, terms);
         if let Some(doc_info) = self.doc_store.id_to_doc_info.get_mut(&doc_id) {
             doc_info.indexed_at = SystemTime::now();
+            doc_info.token_count = token_count;
+            if !self.manifest.keep_history {
+                doc_info.versions.clear();
+            }
+            doc_info.versions.retain(|v| v.generation != generation);
+            doc_info.versions.push(DocVersion {
+                generation,
+                indexed_at: doc_info.indexed_at,
+            });
         }
 
-        if self.current_segment.should_flush(self.max_segment_docs) {
-            let seg_id = self.next_segment;
-            flush_segment(seg_id, &mut self.current_segment, &self.index_dir)
-                .context("flush segment")?;
-            self.next_segment += 1;
-            self.active_segments.push(seg_id);
+        if self
+            .current_segment
+            .should_flush(self.max_segment_docs, self.segment_budget)
+        {
+            self.flush_current_segment().context("flush segment")?;
         }
 
         Ok(())
     }
 
     /// Commits the current state of the index, flushing any partially filled
-    /// in-memory segment to disk and saving the `DocumentStore`.
+    /// in-memory segment to disk and saving the `DocumentStore` and the corpus
+    /// statistics, whose document frequencies are updated with the counts of
+    /// the segments flushed since the last commit.
     ///
     /// # Returns
     /// `Ok(())` if the commit was successful, otherwise an `anyhow::Result`
     /// error.
     pub fn commit(&mut self) -> anyhow::Result<()> {
+        self.ensure_writable("commit")?;
+        let failed = std::mem::take(&mut *self.failed_segments.lock().unwrap());
+        for seg_id in failed {
+            self.active_segments.retain(|id| *id != seg_id);
+            if !self.manifest.quarantined.contains(&seg_id) {
+                self.manifest.quarantined.push(seg_id);
+            }
+        }
         if self.current_segment.doc_count > 0 {
-            let seg_id = self.next_segment;
-            flush_segment(seg_id, &mut self.current_segment, &self.index_dir)
+            self.flush_current_segment()
                 .context("flush partially filled")?;
-            self.active_segments.push(seg_id);
-            self.next_segment += 1;
         }
 
-        let mut writer = BufWriter::new(
-            File::create(self.index_dir.join("docstore.bin")).context("create docstore")?,
-        );
-        bincode2::serialize_into(&mut writer, &self.doc_store)
-            .context("serialize doc store into file")?;
+        // The files are staged first and renamed into place once the manifest
+        // lists the new segments: readers keep searching the previous
+        // generation until then, and a crash before it leaves the previous
+        // generation intact
+        let mut staged = Vec::new();
+        let serialised = bincode2::serialize(&self.doc_store).context("serialize doc store")?;
+        let serialised =
+            crypto::seal(self.cipher.as_ref(), serialised).context("encrypt doc store")?;
+        let path = self.index_dir.join("docstore.bin");
+        stage_file(&path, &serialised).context("write doc store into file")?;
+        staged.push(path);
+        if !self.display_forms.is_empty() {
+            let serialised =
+                bincode2::serialize(&self.display_forms).context("serialize display forms")?;
+            let serialised =
+                crypto::seal(self.cipher.as_ref(), serialised).context("encrypt display forms")?;
+            let path = self.index_dir.join("display.bin");
+            stage_file(&path, &serialised).context("write display forms into file")?;
+            staged.push(path);
+        }
+        let live_versions = self.doc_store.live_versions();
+        let (doc_freqs, segment_docs) = self
+            .live_document_frequencies(&live_versions)
+            .context("count terms")?;
+        self.flushed_counts.clear();
+        let term_count = doc_freqs
+            .keys()
+            .filter(|term| exact_word(term).is_none())
+            .count() as u64;
+        self.stats = IndexStats {
+            doc_freqs: Some(doc_freqs),
+            live_versions,
+            segment_docs,
+            ..IndexStats::compute(&self.doc_store, term_count)
+        };
+        let serialised = self.stats.encode(self.cipher.as_ref())?;
+        let path = self.index_dir.join(STATS_FILE);
+        stage_file(&path, &serialised).context("save stats")?;
+        staged.push(path);
+
+        // Saving the manifest commits: from then on, a writer opening the
+        // index after a crash finishes installing the staged files
+        self.manifest.generation += 1;
+        self.manifest.segments = Some(self.active_segments.clone());
+        for seg_id in std::mem::take(&mut self.retired_segments) {
+            self.dict_cache.remove(seg_id);
+            self.segment_blooms.lock().unwrap().remove(&seg_id);
+            self.manifest
+                .retired
+                .insert(seg_id, self.manifest.generation);
+        }
+        self.manifest.committing = true;
+        self.manifest
+            .save(&self.index_dir)
+            .context("save manifest")?;
+        for path in staged {
+            install_file(&path)?;
+        }
+        self.manifest.committing = false;
+        self.manifest
+            .save(&self.index_dir)
+            .context("save manifest")?;
+        self.remove_retired_segments()
+    }
+
+    /// Removes the directories of the retired segments no reader may still
+    /// read: a segment retired by the commit of generation `g` is kept as
+    /// long as a `ReaderLease` holds a generation before `g`.
+    ///
+    /// # Returns
+    /// `Ok(())` once the unread segments are removed, otherwise an
+    /// `anyhow::Result` error.
+    fn remove_retired_segments(&mut self) -> anyhow::Result<()> {
+        if self.manifest.retired.is_empty() {
+            return Ok(());
+        }
+        let oldest_read = oldest_read_generation(&self.index_dir).context("check readers")?;
+        let unread: Vec<u64> = self
+            .manifest
+            .retired
+            .iter()
+            .filter(|&(_, &retired_at)| oldest_read.is_none_or(|read| read >= retired_at))
+            .map(|(&seg_id, _)| seg_id)
+            .collect();
+        if unread.is_empty() {
+            return Ok(());
+        }
+        for seg_id in unread {
+            match fs::remove_dir_all(self.segment_dir(seg_id)) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err).context("remove retired segment"),
+            }
+            self.manifest.retired.remove(&seg_id);
+        }
+        self.manifest.save(&self.index_dir).context("save manifest")
+    }
+
+    /// Flushes the in-memory segment into a new active segment, keeping
+    /// its document frequencies for the next commit.
+    ///
+    /// # Returns
+    /// `Ok(())` once the segment is written, otherwise an `anyhow::Result`
+    /// error.
+    fn flush_current_segment(&mut self) -> anyhow::Result<()> {
+        let seg_id = self.next_segment;
+        let counts = self.current_segment.document_counts();
+        flush_segment(
+            seg_id,
+            &mut self.current_segment,
+            &self.index_dir,
+            self.cipher.as_ref(),
+            self.observer.as_deref(),
+        )?;
+        self.flushed_counts.insert(seg_id, counts);
+        self.active_segments.push(seg_id);
+        self.next_segment += 1;
         Ok(())
     }
 
-    /// Searches the index for documents matching the given query tokens.
-    /// It calculates TF-IDF scores for each matching document across all active
-    /// segments.
+    /// Returns the directory of the segment with the given id.
+    fn segment_dir(&self, seg_id: u64) -> PathBuf {
+        self.index_dir.join(format!("segment_{seg_id}"))
+    }
+
+    /// Records that a segment failed to load, so that it is skipped from now
+    /// on and quarantined at the next commit.
     ///
     /// # Arguments
-    /// * `q_tokens` - A slice of terms representing the search query.
+    /// * `seg_id` - The id of the broken segment.
+    /// * `err` - Why the segment failed to load.
+    fn quarantine(&self, seg_id: u64, err: &anyhow::Error) {
+        if self.failed_segments.lock().unwrap().insert(seg_id)
+            && let Some(observer) = &self.observer
+        {
+            observer.on_warning(&format!("Skipping broken segment {seg_id}: {err:#}"));
+        }
+    }
+
+    /// Returns whether a segment failed to load since the index was opened.
+    fn has_failed(&self, seg_id: u64) -> bool {
+        self.failed_segments.lock().unwrap().contains(&seg_id)
+    }
+
+    /// Returns the ids of the segments left out of searches, because they
+    /// are quarantined or failed to load. Results are partial if any.
+    pub fn skipped_segments(&self) -> Vec<u64> {
+        let mut skipped = self.manifest.quarantined.clone();
+        skipped.extend(self.failed_segments.lock().unwrap().iter());
+        skipped.sort_unstable();
+        skipped.dedup();
+        skipped
+    }
+
+    /// Reads the dictionary and every postings list of a segment.
+    ///
+    /// # Arguments
+    /// * `seg_id` - The id of the segment to check.
     ///
     /// # Returns
-    /// A `Vec` of tuples, where each tuple contains the `PathBuf` of a matching
-    /// document and its calculated TF-IDF score, sorted in descending order of
-    /// score.
-    pub fn search(&self, q_tokens: &[Term]) -> anyhow::Result<Vec<(PathBuf, f64)>> {
-        let mut scores: HashMap<DocId, f64> = HashMap::new();
-        let total_docs = self.doc_store.total_docs();
+    /// `Ok(())` if the whole segment is readable, otherwise the first error.
+    fn check_segment(&self, seg_id: u64) -> anyhow::Result<()> {
+        for metadata in self.load_segment_dict(seg_id)?.values() {
+            self.read_postings(seg_id, metadata)?;
+        }
+        Ok(())
+    }
 
-        let mut terms_info_cache: HashMap<Term, Vec<(DocId, TermInfo)>> = HashMap::new();
-        let mut global_dfs: HashMap<Term, u32> = HashMap::new();
+    /// Counts the terms and the documents of a segment by reading its
+    /// dictionary and postings.
+    ///
+    /// # Arguments
+    /// * `seg_id` - The id of the segment.
+    ///
+    /// # Returns
+    /// The number of terms and of distinct documents with postings in the
+    /// segment, or an `anyhow::Result` error if it cannot be read.
+    pub fn segment_counts(&self, seg_id: u64) -> anyhow::Result<(u64, u64)> {
+        let seg_dict = self.load_segment_dict(seg_id)?;
+        let mut docs = HashSet::new();
+        for metadata in seg_dict.values() {
+            for posting in self.postings_iter(seg_id, metadata)? {
+                docs.insert(posting?.doc_id);
+            }
+        }
+        Ok((seg_dict.len() as u64, docs.len() as u64))
+    }
 
-        // Pass 1: Load dictionaries and calculate global DFs
-        for &seg_id in &self.active_segments {
-            let dict_path = self
-                .index_dir
-                .join(format!("segment_{seg_id}"))
-                .join("term.dict");
-            let mut reader = BufReader::new(File::open(dict_path).context("open dict path")?);
+    /// Checks that every segment of the index, quarantined ones included, can
+    /// be read completely.
+    ///
+    /// # Returns
+    /// The `SegmentCheck` of every segment, ordered by id.
+    pub fn verify(&self) -> Vec<SegmentCheck> {
+        let mut seg_ids = self.active_segments.clone();
+        seg_ids.extend(&self.manifest.quarantined);
+        seg_ids.sort_unstable();
+        seg_ids.dedup();
+        seg_ids
+            .into_iter()
+            .map(|seg_id| SegmentCheck {
+                seg_id,
+                quarantined: self.manifest.quarantined.contains(&seg_id),
+                error: self
+                    .check_segment(seg_id)
+                    .err()
+                    .map(|err| format!("{err:#}")),
+            })
+            .collect()
+    }
 
-            let seg_dict: SegmentTermInfo =
-                bincode2::deserialize_from(&mut reader).context("deserialise seg dict")?;
+    /// Repairs the segments failing `verify`: the postings lists that can
+    /// still be read are rewritten into a new segment and the broken segment
+    /// is removed. Documents left without postings are removed from the
+    /// document store, so that the next indexing run adds them again.
+    ///
+    /// # Returns
+    /// The `RepairSummary`, or an `anyhow::Result` error.
+    pub fn repair(&mut self) -> anyhow::Result<RepairSummary> {
+        self.ensure_writable("repair")?;
+        let mut summary = RepairSummary::default();
 
-            for token in q_tokens {
-                if let Some(metadata) = seg_dict.get(token) {
-                    terms_info_cache
-                        .entry(token.to_string())
-                        .or_default()
-                        .push((seg_id, *metadata));
+        for check in self.verify() {
+            if check.error.is_none() {
+                if check.quarantined {
+                    // Readable again, e.g. after the disk was remounted
+                    self.manifest.quarantined.retain(|id| *id != check.seg_id);
+                    self.active_segments.push(check.seg_id);
+                }
+                continue;
+            }
+            let seg_id = check.seg_id;
 
-                    *global_dfs.entry(token.to_string()).or_insert(0) += metadata.df;
+            let mut salvaged = InMemorySegment::default();
+            let mut docs = HashSet::new();
+            if let Ok(seg_dict) = self.load_segment_dict(seg_id) {
+                for (term, metadata) in seg_dict.iter() {
+                    let Ok(postings) = self.read_postings(seg_id, metadata) else {
+                        continue;
+                    };
+                    for posting in postings {
+                        // Keep the current versions only: the new segment is
+                        // newer than the segments that replaced older ones
+                        let current = self
+                            .doc_store
+                            .id_to_doc_info
+                            .get(&posting.doc_id)
+                            .is_some_and(|info| {
+                                info.versions
+                                    .last()
+                                    .is_none_or(|v| v.generation == posting.generation)
+                            });
+                        if current {
+                            docs.insert(posting.doc_id);
+                            salvaged
+                                .postings
+                                .entry(term.clone())
+                                .or_default()
+                                .push(posting);
+                        }
+                    }
                 }
             }
+            salvaged.doc_count = docs.len() as u64;
+
+            if salvaged.postings.is_empty() {
+                summary.dropped.push(seg_id);
+            } else {
+                let new_id = self.next_segment;
+                flush_segment(
+                    new_id,
+                    &mut salvaged,
+                    &self.index_dir,
+                    self.cipher.as_ref(),
+                    self.observer.as_deref(),
+                )
+                .context("flush rebuilt segment")?;
+                self.next_segment += 1;
+                self.active_segments.push(new_id);
+                summary.rebuilt.push(seg_id);
+            }
+            self.retired_segments.push(seg_id);
+            self.active_segments.retain(|id| *id != seg_id);
+            self.manifest.quarantined.retain(|id| *id != seg_id);
+            self.failed_segments.lock().unwrap().remove(&seg_id);
         }
 
-        // Pass 2: Read postings and calculate scores
-        for token in q_tokens {
-            let global_df = global_dfs.get(token).cloned().unwrap_or(0) as f64;
-            if global_df == 0.0 {
-                continue;
+        let live: HashSet<DocId> = self
+            .latest_segments()
+            .context("find latest segments")?
+            .into_keys()
+            .collect();
+        let before = self.doc_store.id_to_doc_info.len();
+        self.doc_store.compact(&live, false);
+        summary.removed_docs = before - self.doc_store.id_to_doc_info.len();
+
+        self.commit().context("commit repaired index")?;
+        Ok(summary)
+    }
+
+    /// Loads the term dictionary of the segment with the given id.
+    ///
+    /// # Arguments
+    /// * `seg_id` - The id of the segment whose dictionary is loaded.
+    ///
+    /// # Returns
+    /// The deserialised `SegmentTermInfo`, shared with the preloaded
+    /// dictionaries if the index was preloaded or with the dictionary cache,
+    /// or an `anyhow::Result` error.
+    fn load_segment_dict(&self, seg_id: u64) -> anyhow::Result<Arc<SegmentTermInfo>> {
+        if let Some(seg_dict) = self.preloaded_dicts.get(&seg_id) {
+            return Ok(Arc::clone(seg_dict));
+        }
+        if let Some(seg_dict) = self.dict_cache.get(seg_id) {
+            return Ok(seg_dict);
+        }
+        let buf = match self.segment_files.get(&seg_id) {
+            Some(files) => files
+                .dict
+                .metadata()
+                .and_then(|metadata| read_at(&files.dict, 0, metadata.len())),
+            None => fs::read(self.segment_dir(seg_id).join("term.dict")),
+        }
+        .context("read dict path")?;
+        let bytes = buf.len();
+        let buf = crypto::open(self.cipher.as_ref(), buf).context("decrypt seg dict")?;
+        let seg_dict: SegmentTermInfo =
+            bincode2::deserialize(&buf).context("deserialise seg dict")?;
+        let seg_dict = Arc::new(seg_dict);
+        self.dict_cache.insert(seg_id, &seg_dict, bytes);
+        Ok(seg_dict)
+    }
+
+    /// Returns whether the segment with the given id may hold one of `terms`,
+    /// according to the bloom filter of its terms. Segments whose dictionary
+    /// is in memory, or without a readable filter, may hold any term.
+    ///
+    /// # Arguments
+    /// * `seg_id` - The id of the segment.
+    /// * `terms` - The terms looked up.
+    ///
+    /// # Returns
+    /// `false` if the segment holds none of `terms`, otherwise `true`.
+    fn segment_may_contain<'a>(
+        &self,
+        seg_id: u64,
+        terms: impl IntoIterator<Item = &'a str>,
+    ) -> bool {
+        if self.preloaded_dicts.contains_key(&seg_id) {
+            return true;
+        }
+        let cached = self.segment_blooms.lock().unwrap().get(&seg_id).cloned();
+        let bloom = match cached {
+            Some(bloom) => bloom,
+            None => {
+                let bloom = self.load_segment_bloom(seg_id).map(Arc::new);
+                self.segment_blooms
+                    .lock()
+                    .unwrap()
+                    .insert(seg_id, bloom.clone());
+                bloom
             }
+        };
+        bloom.is_none_or(|bloom| terms.into_iter().any(|term| bloom.may_contain(term)))
+    }
 
-            // Calculate Inverse Document Frequency (IDF)
-            let idf = (total_docs as f64 / global_df).ln().abs();
+    /// Loads the bloom filter of the terms of the segment with the given id.
+    ///
+    /// # Returns
+    /// The filter, or `None` if the segment was written without one or it
+    /// cannot be read.
+    fn load_segment_bloom(&self, seg_id: u64) -> Option<BloomFilter> {
+        let buf = fs::read(self.segment_dir(seg_id).join(BLOOM_FILE)).ok()?;
+        let buf = crypto::open(self.cipher.as_ref(), buf).ok()?;
+        bincode2::deserialize(&buf).ok()
+    }
 
-            if let Some(postings_hit) = terms_info_cache.get(token) {
-                for (seg_id, metadata) in postings_hit {
-                    let posting_path = self
-                        .index_dir
-                        .join(format!("segment_{seg_id}"))
-                        .join("postings.bin");
-                    let mut reader =
-                        BufReader::new(File::open(&posting_path).context("open postings path")?);
+    /// Reads the postings list described by `metadata` from the postings file
+    /// of the segment with the given id, unless it is pinned or cached. The
+    /// whole list is decoded, so that it can be cached and scored.
+    ///
+    /// # Arguments
+    /// * `seg_id` - The id of the segment holding the postings.
+    /// * `metadata` - The `TermInfo` locating the postings list.
+    ///
+    /// # Returns
+    /// The deserialised postings, or an `anyhow::Result` error.
+    fn read_postings(&self, seg_id: u64, metadata: &TermInfo) -> anyhow::Result<Vec<Posting>> {
+        if let Some(postings) = self
+            .pinned_postings
+            .get(&(seg_id, metadata.postings_offset))
+        {
+            return Ok(postings.clone());
+        }
+        let generation = self.manifest.generation;
+        let offset = metadata.postings_offset;
+        if let Some(cache) = &self.postings_cache
+            && let Some(postings) = cache.get(&self.index_dir, generation, seg_id, offset)
+        {
+            return Ok(postings);
+        }
+        let postings = self
+            .postings_iter(seg_id, metadata)?
+            .collect::<anyhow::Result<Vec<Posting>>>()?;
+        if let Some(cache) = &self.postings_cache {
+            cache.insert(&self.index_dir, generation, seg_id, offset, &postings);
+        }
+        Ok(postings)
+    }
 
-                    reader
-                        .seek(SeekFrom::Start(metadata.postings_offset))
-                        .context("seek to postings offset")?;
-                    let mut reader = reader.take(metadata.postings_len);
+    /// Returns the postings file of a segment.
+    fn postings_file(&self, seg_id: u64) -> PathBuf {
+        self.segment_dir(seg_id).join(VARBYTE_POSTINGS_FILE)
+    }
 
-                    let deserialised: Vec<Posting> = bincode2::deserialize_from(&mut reader)
-                        .context("deserialise from post reader")?;
+    /// Reads the postings list described by `metadata` and returns an
+    /// iterator decoding its postings one at a time, in ascending order of
+    /// document id.
+    ///
+    /// # Arguments
+    /// * `seg_id` - The id of the segment holding the postings.
+    /// * `metadata` - The `TermInfo` locating the postings list.
+    ///
+    /// # Returns
+    /// The `PostingsIter`, or an `anyhow::Result` error if the list cannot be
+    /// read.
+    fn postings_iter(&self, seg_id: u64, metadata: &TermInfo) -> anyhow::Result<PostingsIter> {
+        let buf = match self.segment_files.get(&seg_id) {
+            Some(files) => read_at(
+                &files.postings,
+                metadata.postings_offset,
+                metadata.postings_len,
+            )
+            .context("read postings")?,
+            None => {
+                let posting_path = self.postings_file(seg_id);
+                let mut reader =
+                    BufReader::new(File::open(&posting_path).context("open postings path")?);
+                reader
+                    .seek(SeekFrom::Start(metadata.postings_offset))
+                    .context("seek to postings offset")?;
+                let mut buf = vec![0; metadata.postings_len as usize];
+                reader.read_exact(&mut buf).context("read postings")?;
+                buf
+            }
+        };
+        let buf = crypto::open(self.cipher.as_ref(), buf).context("decrypt postings")?;
+        PostingsIter::new(buf)
+    }
 
-                    for posting in deserialised {
-                        let tf = posting.tf as f64;
-                        let tf_idf = tf * idf;
-                        *scores.entry(posting.doc_id).or_insert(0.0) += tf_idf;
-                    }
+    /// Finds the newest segment holding postings for each document. Postings
+    /// of a document in older segments are stale leftovers of re-indexing.
+    ///
+    /// # Returns
+    /// A `HashMap` mapping document ids to segment ids, or an
+    /// `anyhow::Result` error.
+    fn latest_segments(&self) -> anyhow::Result<HashMap<DocId, u64>> {
+        let mut latest: HashMap<DocId, u64> = HashMap::new();
+        for &seg_id in &self.active_segments {
+            for metadata in self.load_segment_dict(seg_id)?.values() {
+                for posting in self.read_postings(seg_id, metadata)? {
+                    let entry = latest.entry(posting.doc_id).or_insert(seg_id);
+                    *entry = (*entry).max(seg_id);
                 }
             }
         }
+        Ok(latest)
+    }
 
-        let mut results: Vec<(PathBuf, f64)> = Vec::new();
-        for (doc_id, score) in scores {
-            let path = self.doc_store.get_path(doc_id).unwrap();
-            if score != 0.0 {
-                results.push((path.clone(), score));
-            }
+    /// Returns whether a merge keeps a posting of segment `seg_id`: postings
+    /// of deleted documents are purged, postings of live documents are kept if they belong to one of the versions the
+    /// document remembers, or to its latest segment for postings written
+    /// before versions were recorded.
+    fn keeps_version(&self, posting: &Posting, seg_id: u64, latest: &HashMap<DocId, u64>) -> bool {
+        if !latest.contains_key(&posting.doc_id)
+            || self.doc_store.tombstones.contains(&posting.doc_id)
+        {
+            return false;
         }
+        match self.doc_store.id_to_doc_info.get(&posting.doc_id) {
+            Some(info) if !info.versions.is_empty() => info
+                .versions
+                .iter()
+                .any(|v| v.generation == posting.generation),
+            _ => latest.get(&posting.doc_id) == Some(&seg_id),
+        }
+    }
+
+    /// Merges the given segments into a single new segment. Postings of
+    /// deleted documents and of documents that were re-indexed into a newer
+    /// segment are dropped, and
+    /// the directories of the merged segments are removed at the next commit,
+    /// once readers no longer see them. Documents without postings are
+    /// dropped from the `DocumentStore`, whose ids are remapped when all
+    /// segments are merged.
+    ///
+    /// # Arguments
+    /// * `seg_ids` - The ids of the active segments to merge.
+    ///
+    /// # Returns
+    /// The id of the new segment, `None` if fewer than two segments were given
+    /// or no live postings remained, or an `anyhow::Result` error.
+    pub fn merge_segments(&mut self, seg_ids: &[u64]) -> anyhow::Result<Option<u64>> {
+        if seg_ids.len() < 2 {
+            return Ok(None);
+        }
+        self.merge_segments_evicting(seg_ids, &HashSet::new())
+    }
+
+    /// Merges the given segments like `merge_segments`, leaving out the
+    /// postings of the `evicted` documents, which are dropped from the
+    /// `DocumentStore` if they have no postings left.
+    fn merge_segments_evicting(
+        &mut self,
+        seg_ids: &[u64],
+        evicted: &HashSet<DocId>,
+    ) -> anyhow::Result<Option<u64>> {
+        self.ensure_writable("merge segments")?;
+
+        let latest = self.latest_segments().context("find latest segments")?;
+        let mut merged = InMemorySegment::default();
+        let mut docs = HashSet::new();
+
+        for &seg_id in seg_ids {
+            for (term, metadata) in self.load_segment_dict(seg_id)?.iter() {
+                for posting in self.read_postings(seg_id, metadata)? {
+                    if !self.keeps_version(&posting, seg_id, &latest)
+                        || evicted.contains(&posting.doc_id)
+                    {
+                        continue;
+                    }
+                    docs.insert(posting.doc_id);
+                    merged.postings.entry(term.clone()).or_default().push(posting);
+                }
+            }
+        }
+        merged.doc_count = docs.len() as u64;
+
+        // Terms looking like noise are dropped if no other document has them
+        if let Some(noise) = &self.manifest.noise {
+            let dfs = self.document_frequencies().context("count terms")?;
+            merged
+                .postings
+                .retain(|term, _| dfs.get(term).is_some_and(|&df| df > 1) || !noise.is_noise(term));
+        }
+
+        // Drop the documents without postings left. Ids can only be remapped
+        // when every posting of the index is rewritten by this merge. The
+        // documents added since the last flush only have postings in memory.
+        let pending = self
+            .current_segment
+            .postings
+            .values()
+            .flatten()
+            .map(|posting| posting.doc_id);
+        let live: HashSet<DocId> = latest
+            .keys()
+            .copied()
+            .chain(pending)
+            .filter(|id| !evicted.contains(id) && !self.doc_store.tombstones.contains(id))
+            .collect();
+        let full_merge = self.current_segment.doc_count == 0
+            && self.active_segments.iter().all(|id| seg_ids.contains(id));
+        let mapping = self.doc_store.compact(&live, full_merge);
+        // Once every segment is rewritten, no postings of deleted documents
+        // are left
+        if full_merge {
+            self.doc_store.tombstones.clear();
+        }
+        if full_merge {
+            for postings in merged.postings.values_mut() {
+                postings.retain(|p| mapping.contains_key(&p.doc_id));
+                for posting in postings.iter_mut() {
+                    posting.doc_id = mapping[&posting.doc_id];
+                }
+            }
+            merged.postings.retain(|_, postings| !postings.is_empty());
+        }
+
+        let new_id = if merged.postings.is_empty() {
+            None
+        } else {
+            let seg_id = self.next_segment;
+            flush_segment(
+                seg_id,
+                &mut merged,
+                &self.index_dir,
+                self.cipher.as_ref(),
+                self.observer.as_deref(),
+            )
+            .context("flush merged segment")?;
+            self.next_segment += 1;
+            Some(seg_id)
+        };
+
+        self.retired_segments.extend_from_slice(seg_ids);
+        self.active_segments.retain(|id| !seg_ids.contains(id));
+        if let Some(seg_id) = new_id {
+            self.active_segments.push(seg_id);
+        }
+
+        Ok(new_id)
+    }
+
+    /// Evicts documents, chosen by the policy of `budget`, if the index is
+    /// larger than `budget` allows. The evicted documents are left out of a
+    /// merge of the segments holding their postings, as counted by the last
+    /// commit, so the pending documents must be committed first, and are
+    /// recorded so that indexing runs skip them until they are modified.
+    ///
+    /// # Arguments
+    /// * `budget` - The size budget of the index.
+    ///
+    /// # Returns
+    /// The URIs of the evicted documents, or an `anyhow::Result` error.
+    pub fn enforce_budget(&mut self, budget: &SizeBudget) -> anyhow::Result<Vec<PathBuf>> {
+        self.ensure_writable("evict documents")?;
+        let size = self.live_size()?;
+        let matches = match budget.policy {
+            EvictionPolicy::LeastMatched => load_matches(&self.index_dir, self.cipher.as_ref()),
+            _ => HashMap::new(),
+        };
+        let evicted: HashSet<DocId> = select_evictions(&self.doc_store, budget, size, &matches)
+            .into_iter()
+            .collect();
+        if evicted.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut paths: Vec<PathBuf> = evicted
+            .iter()
+            .filter_map(|id| self.doc_store.id_to_doc_info.get(id))
+            .map(|info| info.path.clone())
+            .collect();
+        paths.sort();
+        // Segments flushed since the last commit were not counted, and may
+        // hold them too
+        let seg_ids: Vec<u64> = self
+            .active_segments
+            .iter()
+            .copied()
+            .filter(|seg_id| {
+                self.stats
+                    .segment_docs
+                    .get(seg_id)
+                    .is_none_or(|docs| docs.iter().any(|id| evicted.contains(id)))
+            })
+            .collect();
+        self.merge_segments_evicting(&seg_ids, &evicted)
+            .context("merge segments without evicted documents")?;
+        record_evictions(&self.index_dir, self.cipher.as_ref(), &paths)
+            .context("record evictions")?;
+        Ok(paths)
+    }
+
+    /// Returns the ids of the segments whose postings file is smaller than
+    /// `max_bytes`.
+    pub fn small_segments(&self, max_bytes: u64) -> Vec<u64> {
+        self.active_segments
+            .iter()
+            .filter(|&&seg_id| {
+                fs::metadata(self.postings_file(seg_id))
+                    .map(|m| m.len() < max_bytes)
+                    .unwrap_or(false)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Summarizes what the index contains by reading its document store and
+    /// segment dictionaries.
+    ///
+    /// # Arguments
+    /// * `largest` - The number of largest documents to list.
+    ///
+    /// # Returns
+    /// The `IndexSummary` of the index, or an `anyhow::Result` error if a
+    /// segment dictionary cannot be read.
+    pub fn summary(&self, largest: usize) -> anyhow::Result<IndexSummary> {
+        let terms = self
+            .document_frequencies()
+            .context("count terms")?
+            .keys()
+            .filter(|term| exact_word(term).is_none())
+            .count() as u64;
+        let postings_bytes = self
+            .active_segments
+            .iter()
+            .filter_map(|&seg_id| fs::metadata(self.postings_file(seg_id)).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+
+        let infos: Vec<&DocInfo> = self.doc_store.id_to_doc_info.values().collect();
+        let mut by_size: Vec<(PathBuf, u64)> = infos
+            .iter()
+            .map(|info| (info.path.clone(), info.token_count))
+            .collect();
+        by_size.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        by_size.truncate(largest);
+
+        let index_bytes = self.live_size()?;
+        Ok(IndexSummary {
+            documents: self.doc_store.doc_count,
+            terms,
+            segments: self.active_segments.len(),
+            postings_bytes,
+            index_bytes,
+            largest: by_size,
+            oldest: infos.iter().map(|info| info.indexed_at).min(),
+            newest: infos.iter().map(|info| info.indexed_at).max(),
+            quota: self
+                .manifest
+                .quota
+                .map(|quota| quota.usage(self.doc_store.doc_count, index_bytes)),
+        })
+    }
+
+    /// Measures how much of its quotas the index uses.
+    ///
+    /// # Returns
+    /// The `QuotaUsage`, `None` if the index has no quota, or an
+    /// `anyhow::Result` error if the index directory cannot be measured.
+    pub fn quota_usage(&self) -> anyhow::Result<Option<QuotaUsage>> {
+        let Some(quota) = self.manifest.quota else {
+            return Ok(None);
+        };
+        let bytes = self.live_size()?;
+        Ok(Some(quota.usage(self.doc_count(), bytes)))
+    }
+
+    /// Measures the index without the retired segments kept for the
+    /// readers of older generations, which the next commits remove.
+    ///
+    /// # Returns
+    /// The size in bytes, or an `anyhow::Result` error if the index
+    /// directory cannot be measured.
+    fn live_size(&self) -> anyhow::Result<u64> {
+        let size = index_size(&self.index_dir).context("measure index size")?;
+        let retired: u64 = self
+            .manifest
+            .retired
+            .keys()
+            .filter_map(|&seg_id| index_size(&self.segment_dir(seg_id)).ok())
+            .sum();
+        Ok(size.saturating_sub(retired))
+    }
+
+    /// Merges the small segments of the index if there are more than
+    /// `max_small_segments` of them, so that long-lived indexes don't degrade
+    /// into hundreds of tiny segments.
+    ///
+    /// # Arguments
+    /// * `max_small_segments` - The number of small segments tolerated
+    ///   before merging.
+    ///
+    /// # Returns
+    /// The id of the merged segment if a merge happened, or an
+    /// `anyhow::Result` error.
+    pub fn compact_if_needed(&mut self, max_small_segments: usize) -> anyhow::Result<Option<u64>> {
+        let small = self.small_segments(SMALL_SEGMENT_BYTES);
+        if small.len() <= max_small_segments {
+            return Ok(None);
+        }
+        self.merge_segments(&small)
+    }
+
+    /// Deletes the documents at `path`, or under it if it is a directory,
+    /// from the index. Their IDs are tombstoned so that searches skip their
+    /// postings until a merge purges them.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the documents to delete.
+    ///
+    /// # Returns
+    /// The URIs of the deleted documents, or an `anyhow::Result` error if the
+    /// index is read-only.
+    pub fn delete_document(&mut self, path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        self.ensure_writable("delete documents")?;
+        Ok(self.doc_store.delete(path))
+    }
+
+    /// Merges the small segments of the index, or all of them if `all` is
+    /// set, whatever their number. Postings of re-indexed documents are
+    /// deduplicated and the merged segment directories are removed at the
+    /// next commit.
+    ///
+    /// # Arguments
+    /// * `all` - Merge every segment into one instead of only the small ones.
+    ///
+    /// # Returns
+    /// The `CompactSummary` of the merge, or an `anyhow::Result` error.
+    pub fn compact(&mut self, all: bool) -> anyhow::Result<CompactSummary> {
+        let seg_ids = if all {
+            self.active_segments.clone()
+        } else {
+            self.small_segments(SMALL_SEGMENT_BYTES)
+        };
+        if seg_ids.len() < 2 {
+            return Ok(CompactSummary {
+                segments: self.active_segments.len(),
+                ..Default::default()
+            });
+        }
+        let docs_before = self.doc_store.doc_to_id.len();
+        let new_segment = self.merge_segments(&seg_ids)?;
+        Ok(CompactSummary {
+            merged: seg_ids,
+            new_segment,
+            segments: self.active_segments.len(),
+            removed_docs: docs_before.saturating_sub(self.doc_store.doc_to_id.len()),
+        })
+    }
+
+    /// Reconstructs the terms of every indexed document from the segment
+    /// postings. When a document was re-indexed into several segments, the
+    /// terms from the newest segment win.
+    ///
+    /// # Returns
+    /// A `Vec` of `(PathBuf, Vec<(Term, TermFrequency)>)` tuples sorted by
+    /// path, or an `anyhow::Result` error.
+    pub fn document_terms(&self) -> anyhow::Result<DocumentTerms> {
+        let mut segments = self.active_segments.clone();
+        segments.sort_unstable();
+
+        let mut docs: HashMap<DocId, (u64, Vec<(Term, TermFrequency)>)> = HashMap::new();
+        for seg_id in segments {
+            let seg_dict = self.load_segment_dict(seg_id)?;
+            for (term, metadata) in seg_dict.iter() {
+                for posting in self.read_postings(seg_id, metadata)? {
+                    let entry = docs
+                        .entry(posting.doc_id)
+                        .or_insert_with(|| (seg_id, Vec::new()));
+                    if entry.0 < seg_id {
+                        *entry = (seg_id, Vec::new());
+                    }
+                    entry.1.push((term.clone(), posting.tf));
+                }
+            }
+        }
+
+        let mut results = Vec::with_capacity(docs.len());
+        for (doc_id, (_, mut terms)) in docs {
+            if let Some(path) = self.doc_store.get_path(doc_id) {
+                terms.sort_unstable();
+                results.push((path.clone(), terms));
+            }
+        }
+        results.sort_by(|a, b| a.0.cmp(&b.0));
 
-        results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-
         Ok(results)
     }
+
+    /// Returns the postings of `term` merged across all active segments and
+    /// ordered by document id. When a document was re-indexed into several
+    /// segments, only its posting from the newest segment is yielded.
+    ///
+    /// # Arguments
+    /// * `term` - The (analyzed) term to look up.
+    ///
+    /// # Returns
+    /// An iterator over the postings of the term, or an `anyhow::Result`
+    /// error if a segment cannot be read.
+    pub fn postings(&self, term: &str) -> anyhow::Result<impl Iterator<Item = Posting> + use<>> {
+        let mut segments = self.active_segments.clone();
+        segments.sort_unstable();
+
+        let mut merged: BTreeMap<DocId, Posting> = BTreeMap::new();
+        for seg_id in segments {
+            if !self.segment_may_contain(seg_id, [term]) {
+                continue;
+            }
+            let seg_dict = self.load_segment_dict(seg_id)?;
+            if let Some(metadata) = seg_dict.get(term) {
+                for posting in self.read_postings(seg_id, metadata)? {
+                    merged.insert(posting.doc_id, posting);
+                }
+            }
+        }
+
+        Ok(merged.into_values())
+    }
+
+    /// Aggregates the document frequency of every term across all active
+    /// segment dictionaries.
+    ///
+    /// # Returns
+    /// A `HashMap` mapping each term to the number of documents containing
+    /// it, or an `anyhow::Result` error.
+    pub fn document_frequencies(&self) -> anyhow::Result<HashMap<Term, u64>> {
+        let mut dfs: HashMap<Term, u64> = HashMap::new();
+        for &seg_id in &self.active_segments {
+            let seg_dict = match self.load_segment_dict(seg_id) {
+                Ok(seg_dict) => seg_dict,
+                Err(err) => {
+                    self.quarantine(seg_id, &err);
+                    continue;
+                }
+            };
+            for (term, metadata) in seg_dict.iter() {
+                *dfs.entry(term.clone()).or_insert(0) += metadata.df as u64;
+            }
+        }
+        Ok(dfs)
+    }
+
+    /// Counts, for every term, the live documents whose current version
+    /// contains it. Unlike `document_frequencies`, a document re-indexed into
+    /// several segments is counted once and deleted documents are not
+    /// counted. The counts of the last commit are updated with the segments
+    /// added and removed since and the segments holding the documents whose
+    /// current version changed, so that committing a few documents doesn't
+    /// read every segment; they are counted afresh if there are none. The
+    /// segments flushed since the last commit are counted from the postings
+    /// kept when they were flushed rather than read back.
+    ///
+    /// # Arguments
+    /// * `live_versions` - The current version of every live document, see
+    ///   `DocumentStore::live_versions`.
+    ///
+    /// # Returns
+    /// A `HashMap` mapping each term with live postings to the number of
+    /// documents containing it and the documents with postings in each
+    /// active segment, or an `anyhow::Result` error.
+    fn live_document_frequencies(
+        &self,
+        live_versions: &HashMap<DocId, Option<u64>>,
+    ) -> anyhow::Result<LiveCounts> {
+        // A segment that can no longer be read is quarantined by the full
+        // count
+        if self.stats.doc_freqs.is_some()
+            && let Ok(counted) = self.update_document_frequencies(live_versions)
+        {
+            return Ok(counted);
+        }
+
+        let mut dfs: HashMap<Term, i64> = HashMap::new();
+        let mut segment_docs = HashMap::new();
+        for &seg_id in &self.active_segments {
+            match self.count_segment(seg_id, |p| is_live(live_versions, p) as i64) {
+                Ok((seg_dfs, docs)) => {
+                    add_counts(&mut dfs, seg_dfs);
+                    segment_docs.insert(seg_id, docs);
+                }
+                Err(err) => self.quarantine(seg_id, &err),
+            }
+        }
+        Ok((into_counts(dfs), segment_docs))
+    }
+
+    /// Updates the document frequencies recorded at the last commit, see
+    /// `live_document_frequencies`.
+    ///
+    /// # Returns
+    /// The document frequencies and the documents of each active segment,
+    /// or an `anyhow::Result` error if a segment cannot be read.
+    fn update_document_frequencies(
+        &self,
+        live_versions: &HashMap<DocId, Option<u64>>,
+    ) -> anyhow::Result<LiveCounts> {
+        let counted = &self.stats.segment_docs;
+        let was_live = &self.stats.live_versions;
+        let changed: HashSet<DocId> = live_versions
+            .keys()
+            .chain(was_live.keys())
+            .filter(|id| live_versions.get(id) != was_live.get(id))
+            .copied()
+            .collect();
+
+        let mut dfs: HashMap<Term, i64> = self
+            .stats
+            .doc_freqs
+            .iter()
+            .flatten()
+            .map(|(term, df)| (term.clone(), *df as i64))
+            .collect();
+        let mut segment_docs = HashMap::new();
+        // The segments merged away or quarantined since no longer count
+        for &seg_id in counted.keys() {
+            if !self.active_segments.contains(&seg_id) {
+                let (seg_dfs, _) =
+                    self.count_segment(seg_id, |p| -(is_live(was_live, p) as i64))?;
+                add_counts(&mut dfs, seg_dfs);
+            }
+        }
+        for &seg_id in &self.active_segments {
+            match counted.get(&seg_id) {
+                Some(docs) if !docs.iter().any(|id| changed.contains(id)) => {
+                    segment_docs.insert(seg_id, docs.clone());
+                }
+                Some(_) => {
+                    let (seg_dfs, docs) = self.count_segment(seg_id, |p| {
+                        if changed.contains(&p.doc_id) {
+                            is_live(live_versions, p) as i64 - is_live(was_live, p) as i64
+                        } else {
+                            0
+                        }
+                    })?;
+                    add_counts(&mut dfs, seg_dfs);
+                    segment_docs.insert(seg_id, docs);
+                }
+                None => {
+                    // A segment flushed since the last commit whose documents
+                    // are all still current is counted from memory
+                    if let Some(flushed) = self.flushed_counts.get(&seg_id)
+                        && flushed.versions.iter().all(|&(doc_id, generation)| {
+                            live_versions
+                                .get(&doc_id)
+                                .is_some_and(|g| g.is_none_or(|g| g == generation))
+                        })
+                    {
+                        add_counts(&mut dfs, flushed.dfs.clone());
+                        let mut docs: Vec<DocId> =
+                            flushed.versions.iter().map(|&(doc_id, _)| doc_id).collect();
+                        docs.dedup();
+                        segment_docs.insert(seg_id, docs);
+                        continue;
+                    }
+                    let (seg_dfs, docs) =
+                        self.count_segment(seg_id, |p| is_live(live_versions, p) as i64)?;
+                    add_counts(&mut dfs, seg_dfs);
+                    segment_docs.insert(seg_id, docs);
+                }
+            }
+        }
+        Ok((into_counts(dfs), segment_docs))
+    }
+
+    /// Sums `count` over the postings of every term of a segment.
+    ///
+    /// # Arguments
+    /// * `seg_id` - The segment to read.
+    /// * `count` - What a posting adds to the count of its term.
+    ///
+    /// # Returns
+    /// The non-zero sums keyed by term and the documents with postings in
+    /// the segment, or an `anyhow::Result` error if it cannot be read.
+    fn count_segment(
+        &self,
+        seg_id: u64,
+        count: impl Fn(&Posting) -> i64,
+    ) -> anyhow::Result<(HashMap<Term, i64>, Vec<DocId>)> {
+        let mut dfs = HashMap::new();
+        let mut docs = BTreeSet::new();
+        for (term, metadata) in self.load_segment_dict(seg_id)?.iter() {
+            let mut sum = 0;
+            for posting in self.read_postings(seg_id, metadata)? {
+                sum += count(&posting);
+                docs.insert(posting.doc_id);
+            }
+            if sum != 0 {
+                dfs.insert(term.clone(), sum);
+            }
+        }
+        Ok((dfs, docs.into_iter().collect()))
+    }
+
+    /// Returns the document frequency of every term: the corrected counts
+    /// recorded at the last commit, or the counts summed over the segment
+    /// dictionaries for indexes committed before they were recorded.
+    ///
+    /// # Returns
+    /// A `HashMap` mapping each term to the number of documents containing
+    /// it, or an `anyhow::Result` error.
+    pub fn global_document_frequencies(&self) -> anyhow::Result<HashMap<Term, u64>> {
+        match &self.stats.doc_freqs {
+            Some(dfs) => Ok(dfs.clone()),
+            None => self.document_frequencies(),
+        }
+    }
+
+    /// Counts the segments and documents containing each of `terms`. Terms
+    /// found in no segment are reported with zero counts.
+    ///
+    /// # Arguments
+    /// * `terms` - The terms to look up.
+    ///
+    /// # Returns
+    /// A `HashMap` mapping each term to its `TermSpread`.
+    pub fn term_spread(&self, terms: &[&str]) -> HashMap<Term, TermSpread> {
+        let mut spread: HashMap<Term, TermSpread> = terms
+            .iter()
+            .map(|term| (term.to_string(), TermSpread::default()))
+            .collect();
+        for &seg_id in &self.active_segments {
+            if !self.segment_may_contain(seg_id, terms.iter().copied()) {
+                continue;
+            }
+            let seg_dict = match self.load_segment_dict(seg_id) {
+                Ok(seg_dict) => seg_dict,
+                Err(err) => {
+                    self.quarantine(seg_id, &err);
+                    continue;
+                }
+            };
+            for (term, term_spread) in spread.iter_mut() {
+                if let Some(metadata) = seg_dict.get(term) {
+                    term_spread.segments += 1;
+                    term_spread.df += metadata.df as u64;
+                }
+            }
+        }
+        if self.as_of.is_none() {
+            for (term, term_spread) in spread.iter_mut() {
+                if let Some(df) = self.stats.doc_freq(term) {
+                    term_spread.df = df;
+                }
+            }
+        }
+        spread
+    }
+
+    /// Adds to `terms` the index terms within `max_distance` edits of each
+    /// of them, found by scanning the dictionaries of the active segments,
+    /// so that misspelled queries still match. Only plain word terms are
+    /// expanded, each to at most `MAX_FUZZY_EXPANSIONS` terms.
+    ///
+    /// # Arguments
+    /// * `terms` - The analyzed query terms.
+    /// * `max_distance` - The largest edit distance accepted.
+    ///
+    /// # Returns
+    /// The query terms followed by the terms they were expanded to.
+    pub fn fuzzy_expand(&self, terms: &[Term], max_distance: usize) -> Vec<Term> {
+        let candidates = terms
+            .iter()
+            .filter(|term| is_fuzzy_candidate(term, max_distance))
+            .collect::<Vec<&Term>>();
+        if candidates.is_empty() || max_distance == 0 {
+            return terms.to_vec();
+        }
+
+        let mut matches: Vec<Vec<(usize, Term)>> = vec![Vec::new(); candidates.len()];
+        for &seg_id in &self.active_segments {
+            let seg_dict = match self.load_segment_dict(seg_id) {
+                Ok(seg_dict) => seg_dict,
+                Err(err) => {
+                    self.quarantine(seg_id, &err);
+                    continue;
+                }
+            };
+            for indexed in seg_dict.keys() {
+                if !is_fuzzy_candidate(indexed, max_distance) {
+                    continue;
+                }
+                for (candidate, found) in candidates.iter().zip(matches.iter_mut()) {
+                    if indexed != *candidate
+                        && let Some(distance) = edit_distance(candidate, indexed, max_distance)
+                        && !found.iter().any(|(_, term)| term == indexed)
+                    {
+                        found.push((distance, indexed.clone()));
+                    }
+                }
+            }
+        }
+
+        let mut expanded = terms.to_vec();
+        for mut found in matches {
+            found.sort();
+            found.truncate(MAX_FUZZY_EXPANSIONS);
+            for (_, term) in found {
+                if !expanded.contains(&term) {
+                    expanded.push(term);
+                }
+            }
+        }
+        expanded
+    }
+
+    /// Completes `prefix` into the words of the index starting with it, e.g.
+    /// for typeahead, by scanning the exact forms of the words in the
+    /// dictionaries of the active segments.
+    ///
+    /// # Arguments
+    /// * `prefix` - The beginning of a word, normalized as queries are.
+    /// * `limit` - The largest number of words returned.
+    ///
+    /// # Returns
+    /// The words in their original casing if the index preserves it, with
+    /// the number of documents containing them, most frequent first.
+    pub fn complete_word(&self, prefix: &str, limit: usize) -> Vec<(String, u64)> {
+        let prefix = self.analyzer().normalize(prefix);
+        if prefix.is_empty() || limit == 0 {
+            return Vec::new();
+        }
+        let prefix = format!("{EXACT_PREFIX}{prefix}");
+        let mut words: HashMap<Term, u64> = HashMap::new();
+        for &seg_id in &self.active_segments {
+            let seg_dict = match self.load_segment_dict(seg_id) {
+                Ok(seg_dict) => seg_dict,
+                Err(err) => {
+                    self.quarantine(seg_id, &err);
+                    continue;
+                }
+            };
+            for (term, metadata) in seg_dict.iter() {
+                if term.starts_with(&prefix) {
+                    *words.entry(term.clone()).or_default() += metadata.df as u64;
+                }
+            }
+        }
+        if self.as_of.is_none() {
+            for (term, df) in words.iter_mut() {
+                if let Some(global) = self.stats.doc_freq(term) {
+                    *df = global;
+                }
+            }
+        }
+
+        let mut words = words
+            .into_iter()
+            .filter(|(_, df)| *df > 0)
+            .collect::<Vec<(Term, u64)>>();
+        words.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        words.truncate(limit);
+        words
+            .into_iter()
+            .map(|(term, df)| {
+                let word = &term[EXACT_PREFIX.len()..];
+                (self.display_form(word).to_string(), df)
+            })
+            .collect()
+    }
+
+    /// Returns the number of documents in the index.
+    pub fn doc_count(&self) -> u64 {
+        self.doc_store.total_docs()
+    }
+
+    /// Searches the index for documents matching the given query tokens,
+    /// ranking them with the default ranker of the index manifest.
+    ///
+    /// # Arguments
+    /// * `q_tokens` - A slice of terms representing the search query.
+    ///
+    /// # Returns
+    /// A `Vec` of tuples, where each tuple contains the `PathBuf` of a matching
+    /// document and its score, sorted in descending order of score.
+    pub fn search(&self, q_tokens: &[Term]) -> anyhow::Result<Vec<(PathBuf, f64)>> {
+        self.search_ranked(q_tokens, None)
+    }
+
+    /// Highlights the matches of a query in a document. Unless its indexing
+    /// policy kept its text in the index, the document is read and parsed
+    /// again.
+    ///
+    /// # Arguments
+    /// * `doc` - The path of the document, as returned by a search.
+    /// * `tokens` - The analyzed query terms.
+    ///
+    /// # Returns
+    /// The fragments of the document around the matches, or an
+    /// `anyhow::Result` error if the document cannot be read.
+    pub fn highlight(&self, doc: &Path, tokens: &[Term]) -> anyhow::Result<Vec<Fragment>> {
+        if let Some(text) = self.doc_store.text(doc) {
+            return Ok(highlight_text(text, tokens, &self.analyzer()));
+        }
+        let scheme = scheme_of(doc);
+        let Some(path) = scheme.local_path(doc) else {
+            return Err(anyhow!(
+                "{doc:?} is a {} document and cannot be read back",
+                scheme.name()
+            ));
+        };
+        let text = extract_text(&path).with_context(|| format!("extract text of {doc:?}"))?;
+        Ok(highlight_text(&text, tokens, &self.analyzer()))
+    }
+
+    /// Resolves the file of an indexed document so that it can be served,
+    /// e.g. by a preview endpoint of the server, refusing every path that
+    /// could reach another file: paths with `..` components, paths that are
+    /// not live indexed documents, virtual documents, and files whose real
+    /// path, once symlinks are followed, is outside the roots indexing runs
+    /// were given.
+    ///
+    /// # Arguments
+    /// * `requested` - The path of the document, as returned by a search.
+    ///
+    /// # Returns
+    /// The canonical path of the file to serve, or an `anyhow::Result` error
+    /// if it must not be served.
+    pub fn servable_path(&self, requested: &Path) -> anyhow::Result<PathBuf> {
+        if requested.components().any(|c| c == Component::ParentDir) {
+            return Err(anyhow!("{requested:?} contains `..` components"));
+        }
+        let indexed = self
+            .doc_store
+            .doc_to_id
+            .get(requested)
+            .is_some_and(|id| !self.doc_store.tombstones.contains(id));
+        if !indexed {
+            return Err(anyhow!("{requested:?} is not an indexed document"));
+        }
+        let Some(file) = scheme_of(requested).local_path(requested) else {
+            return Err(anyhow!("{requested:?} is not a file"));
+        };
+        // Symlinks are resolved first, so that a link inside a root cannot
+        // expose a file outside of it
+        let real = fs::canonicalize(&file).with_context(|| format!("resolve {file:?}"))?;
+        let inside = self
+            .manifest
+            .roots
+            .iter()
+            .filter_map(|root| fs::canonicalize(root).ok())
+            .any(|root| real.starts_with(root));
+        if !inside {
+            return Err(anyhow!("{requested:?} resolves outside the indexed roots"));
+        }
+        Ok(real)
+    }
+
+    /// Keeps only the results whose document is in `language`. Documents
+    /// without a detected language never match.
+    ///
+    /// # Arguments
+    /// * `results` - The search results to filter.
+    /// * `language` - The ISO 639-1 code of the language to keep.
+    pub fn retain_language(&self, results: &mut Vec<(PathBuf, f64)>, language: &str) {
+        results.retain(|(path, _)| {
+            self.doc_store
+                .doc_to_id
+                .get(path)
+                .and_then(|id| self.doc_store.id_to_doc_info.get(id))
+                .and_then(|info| info.language.as_deref())
+                .is_some_and(|lang| lang.eq_ignore_ascii_case(language))
+        });
+    }
+
+    /// Keeps only the results whose document carries every one of `tags`.
+    ///
+    /// # Arguments
+    /// * `results` - The search results to filter.
+    /// * `tags` - The tags of the `tag:` filters, see `has_tag`.
+    pub fn retain_tags(&self, results: &mut Vec<(PathBuf, f64)>, tags: &[&str]) {
+        if tags.is_empty() {
+            return;
+        }
+        results.retain(|(path, _)| {
+            self.doc_store
+                .tags(path)
+                .is_some_and(|doc_tags| tags.iter().all(|tag| has_tag(doc_tags, tag)))
+        });
+    }
+
+    /// Keeps only the results whose document matches the metadata
+    /// `filter`.
+    ///
+    /// # Arguments
+    /// * `results` - The search results to filter.
+    /// * `filter` - The `MetadataFilter` of the search.
+    pub fn retain_metadata(&self, results: &mut Vec<(PathBuf, f64)>, filter: &MetadataFilter) {
+        if filter.is_empty() {
+            return;
+        }
+        results.retain(|(path, _)| {
+            self.doc_store
+                .doc_to_id
+                .get(path)
+                .and_then(|id| self.doc_store.id_to_doc_info.get(id))
+                .is_some_and(|info| filter.matches(info))
+        });
+    }
+
+    /// Sorts search results in `order`, best scored first among equal ones,
+    /// using the metadata recorded when the documents were indexed.
+    ///
+    /// # Arguments
+    /// * `results` - The search results, in descending order of score.
+    /// * `order` - The order to sort them in.
+    pub fn sort_results(&self, results: &mut [(PathBuf, f64)], order: SortOrder) {
+        let info = |path: &Path| {
+            self.doc_store
+                .doc_to_id
+                .get(path)
+                .and_then(|id| self.doc_store.id_to_doc_info.get(id))
+        };
+        // Sorts are stable, so ties keep their order of score
+        match order {
+            SortOrder::Score => {}
+            SortOrder::Mtime => results.sort_by_cached_key(|(path, _)| {
+                std::cmp::Reverse(info(path).map(|info| info.modified.unwrap_or(info.indexed_at)))
+            }),
+            SortOrder::Path => results.sort_by(|(a, _), (b, _)| a.cmp(b)),
+            SortOrder::Size => results.sort_by_cached_key(|(path, _)| {
+                std::cmp::Reverse(info(path).and_then(|info| info.size))
+            }),
+        }
+    }
+
+    /// Keeps only the results satisfying the boolean filter of a query. A
+    /// clause of the filter matches the documents found when searching for
+    /// its terms alone.
+    ///
+    /// # Arguments
+    /// * `results` - The search results to filter.
+    /// * `filter` - The analyzed filter of the query.
+    ///
+    /// # Returns
+    /// `Ok(())` once filtered, or an `anyhow::Result` error if the clauses
+    /// could not be searched.
+    pub fn retain_matching(
+        &self,
+        results: &mut Vec<(PathBuf, f64)>,
+        filter: &BoolExpr<Vec<Term>>,
+    ) -> anyhow::Result<()> {
+        let leaves = filter.leaves();
+        let queries = leaves
+            .iter()
+            .map(|terms| terms.to_vec())
+            .collect::<Vec<_>>();
+        // Only which documents match counts, not their score
+        let hits = self.search_batch_with(&queries, &TfIdf)?;
+        let matching: HashMap<&Vec<Term>, HashSet<PathBuf>> = leaves
+            .into_iter()
+            .zip(hits)
+            .map(|(terms, hits)| (terms, hits.into_iter().map(|(path, _)| path).collect()))
+            .collect();
+        results.retain(|(path, _)| {
+            filter.matches(&|terms| {
+                matching
+                    .get(terms)
+                    .is_some_and(|paths| paths.contains(path))
+            })
+        });
+        Ok(())
+    }
+
+    /// Collapses the results pointing at identical content, e.g. the same
+    /// page of mirrored documentation trees, into the best ranked of them.
+    /// Results without a recorded content hash are never collapsed, nor are
+    /// any in indexes keeping duplicates.
+    ///
+    /// # Arguments
+    /// * `results` - The search results, in descending order of score.
+    ///
+    /// # Returns
+    /// The other paths of every kept result that had duplicates, keyed by the
+    /// path of the kept result.
+    pub fn collapse_duplicates(
+        &self,
+        results: &mut Vec<(PathBuf, f64)>,
+    ) -> HashMap<PathBuf, Vec<PathBuf>> {
+        let mut kept_by_hash: HashMap<&str, PathBuf> = HashMap::new();
+        let mut duplicates: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+        if self.manifest.keep_duplicates {
+            return duplicates;
+        }
+        results.retain(|(path, _)| {
+            let hash = self
+                .doc_store
+                .doc_to_id
+                .get(path)
+                .and_then(|id| self.doc_store.id_to_doc_info.get(id))
+                .map(|info| info.content_hash.as_str())
+                .filter(|hash| !hash.is_empty());
+            let Some(hash) = hash else {
+                return true;
+            };
+            match kept_by_hash.get(hash) {
+                Some(kept) => {
+                    duplicates
+                        .entry(kept.clone())
+                        .or_default()
+                        .push(path.clone());
+                    false
+                }
+                None => {
+                    kept_by_hash.insert(hash, path.clone());
+                    true
+                }
+            }
+        });
+        duplicates
+    }
+
+    /// Searches the index for documents matching the given query tokens,
+    /// ranking them with `ranker`, or the manifest default if `None`.
+    ///
+    /// # Arguments
+    /// * `q_tokens` - A slice of terms representing the search query.
+    /// * `ranker` - The ranking algorithm to score the documents with.
+    ///
+    /// # Returns
+    /// A `Vec` of tuples, where each tuple contains the `PathBuf` of a matching
+    /// document and its score, sorted in descending order of score.
+    pub fn search_ranked(
+        &self,
+        q_tokens: &[Term],
+        ranker: Option<Ranker>,
+    ) -> anyhow::Result<Vec<(PathBuf, f64)>> {
+        match ranker.unwrap_or(self.manifest.ranker) {
+            Ranker::Tfidf => self.search_with(q_tokens, &TfIdf),
+            Ranker::Bm25 => self.search_with(q_tokens, &self.bm25),
+            Ranker::Custom => match &self.custom_scorer {
+                Some(scorer) => self.search_with(q_tokens, scorer.as_ref()),
+                None => Err(anyhow!("no custom scorer registered on the index")),
+            },
+        }
+    }
+
+    /// Searches the index for documents matching the given query tokens.
+    /// Every matching document is scored with `scorer` across all active
+    /// segments, allowing library users to plug in domain specific ranking.
+    /// The summed score is then multiplied by the factors of the boost rules
+    /// matching the document path.
+    ///
+    /// # Arguments
+    /// * `q_tokens` - A slice of terms representing the search query.
+    /// * `scorer` - The `Scorer` computing each term's score contribution.
+    ///
+    /// # Returns
+    /// A `Vec` of tuples, where each tuple contains the `PathBuf` of a matching
+    /// document and its score, sorted in descending order of score.
+    pub fn search_with(
+        &self,
+        q_tokens: &[Term],
+        scorer: &dyn Scorer,
+    ) -> anyhow::Result<Vec<(PathBuf, f64)>> {
+        let mut results = self.search_batch_with(&[q_tokens.to_vec()], scorer)?;
+        Ok(results.pop().unwrap_or_default())
+    }
+
+    /// Searches the index for one page of the documents matching the given
+    /// query tokens. Results are ordered by descending score, ties broken by
+    /// path, so that consecutive pages neither repeat nor skip documents.
+    /// The cursor holds the score and path of the last result of the previous
+    /// page: the documents ranking before it are left out and only those
+    /// that can rank up to the end of the page are scored, as by
+    /// `search_top`, so a deep page costs about as much as the first one.
+    ///
+    /// # Arguments
+    /// * `q_tokens` - A slice of terms representing the search query.
+    /// * `cursor` - The cursor returned with the previous page, or `None` for
+    ///   the first page.
+    /// * `page_size` - The maximum number of results on the page.
+    ///
+    /// # Returns
+    /// The `SearchPage`, or an `anyhow::Result` error, a `CursorError` if
+    /// the cursor is malformed or was issued for another generation of the
+    /// index.
+    pub fn search_page(
+        &self,
+        q_tokens: &[Term],
+        cursor: Option<&str>,
+        page_size: usize,
+    ) -> anyhow::Result<SearchPage> {
+        let after = cursor.map(PageCursor::decode).transpose()?;
+        if let Some(after) = &after
+            && after.generation != self.manifest.generation
+        {
+            return Err(CursorError::Stale.into());
+        }
+        let last = after.map(|after| (after.path, after.score));
+
+        // One result past the page tells whether another page follows
+        let ranker = self.manifest.ranker;
+        let limit = page_size + 1;
+        let top = (!q_tokens.iter().any(|token| phrase_parts(token).is_some()))
+            .then(|| {
+                let term_postings = self.query_postings(&[q_tokens.to_vec()]);
+                self.score_query_top(q_tokens, &term_postings, ranker, limit, last.as_ref())
+            })
+            .flatten();
+        let mut results = match top {
+            Some(top) => top.results,
+            None => self.search_ranked(q_tokens, Some(ranker))?,
+        };
+        if let Some(last) = &last {
+            results.retain(|result| compare_results(result, last).is_gt());
+        }
+        results.sort_by(compare_results);
+
+        let end = page_size.min(results.len());
+        let next_cursor = match results[..end].last() {
+            Some((path, score)) if end < results.len() => Some(
+                PageCursor {
+                    generation: self.manifest.generation,
+                    score: *score,
+                    path: path.clone(),
+                }
+                .encode(),
+            ),
+            _ => None,
+        };
+        results.truncate(end);
+
+        Ok(SearchPage {
+            results,
+            next_cursor,
+        })
+    }
+
+    /// Searches the index for several queries at once, ranking them with
+    /// `ranker`, or the manifest default if `None`.
+    ///
+    /// # Arguments
+    /// * `queries` - The analyzed tokens of each query.
+    /// * `ranker` - The ranking algorithm to score the documents with.
+    ///
+    /// # Returns
+    /// The results of each query, in the order of `queries`.
+    pub fn search_batch(
+        &self,
+        queries: &[Vec<Term>],
+        ranker: Option<Ranker>,
+    ) -> anyhow::Result<Vec<Vec<(PathBuf, f64)>>> {
+        match ranker.unwrap_or(self.manifest.ranker) {
+            Ranker::Tfidf => self.search_batch_with(queries, &TfIdf),
+            Ranker::Bm25 => self.search_batch_with(queries, &self.bm25),
+            Ranker::Custom => match &self.custom_scorer {
+                Some(scorer) => self.search_batch_with(queries, scorer.as_ref()),
+                None => Err(anyhow!("no custom scorer registered on the index")),
+            },
+        }
+    }
+
+    /// Searches the index for several queries at once. Every segment
+    /// dictionary is loaded once and the postings of every distinct term are
+    /// read once, however many queries use them. Segments that fail to load
+    /// are skipped and reported by `skipped_segments`.
+    ///
+    /// # Arguments
+    /// * `queries` - The analyzed tokens of each query.
+    /// * `scorer` - The `Scorer` computing each term's score contribution.
+    ///
+    /// # Returns
+    /// The results of each query, in the order of `queries`, each sorted in
+    /// descending order of score.
+    pub fn search_batch_with(
+        &self,
+        queries: &[Vec<Term>],
+        scorer: &dyn Scorer,
+    ) -> anyhow::Result<Vec<Vec<(PathBuf, f64)>>> {
+        let term_postings = self.query_postings(queries);
+
+        // Pass 3: Score each query
+        Ok(queries
+            .iter()
+            .map(|q_tokens| self.score_query(q_tokens, &term_postings, scorer))
+            .collect())
+    }
+
+    /// Searches the index for the `limit` best documents matching the given
+    /// query tokens, ranking them with `ranker`, or the manifest default if
+    /// `None`. Documents that cannot rank among them are skipped rather than
+    /// scored, which makes searches of large indexes for few results much
+    /// cheaper. Queries with phrases, or ranked by a custom scorer, are
+    /// searched in full.
+    ///
+    /// # Arguments
+    /// * `q_tokens` - A slice of terms representing the search query.
+    /// * `ranker` - The ranking algorithm to score the documents with.
+    /// * `limit` - The number of best documents wanted.
+    ///
+    /// # Returns
+    /// The `TopResults`, or an `anyhow::Result` error.
+    pub fn search_top(
+        &self,
+        q_tokens: &[Term],
+        ranker: Option<Ranker>,
+        limit: usize,
+    ) -> anyhow::Result<TopResults> {
+        let ranker = ranker.unwrap_or(self.manifest.ranker);
+        if limit > 0 && !q_tokens.iter().any(|token| phrase_parts(token).is_some()) {
+            let term_postings = self.query_postings(&[q_tokens.to_vec()]);
+            if let Some(top) = self.score_query_top(q_tokens, &term_postings, ranker, limit, None) {
+                return Ok(top);
+            }
+        }
+        Ok(TopResults {
+            results: self.search_ranked(q_tokens, Some(ranker))?,
+            pruned: false,
+        })
+    }
+
+    /// Counts the documents matching the given query tokens without scoring,
+    /// resolving or sorting them, which is much cheaper than a search when
+    /// only the number of hits is needed.
+    ///
+    /// # Arguments
+    /// * `q_tokens` - A slice of terms representing the search query.
+    ///
+    /// # Returns
+    /// The number of documents containing any of the terms or phrases.
+    pub fn search_count(&self, q_tokens: &[Term]) -> u64 {
+        let term_postings = self.query_postings(&[q_tokens.to_vec()]);
+        let mut matching: HashMap<DocId, f64> = HashMap::new();
+        for token in q_tokens {
+            if let Some(parts) = phrase_parts(token) {
+                // Only which documents match counts, not their score
+                self.score_phrase(&parts, &term_postings, &TfIdf, &mut matching);
+                continue;
+            }
+            let Some((_, postings)) = term_postings.get(token) else {
+                continue;
+            };
+            for posting in postings {
+                let visible = self
+                    .doc_store
+                    .id_to_doc_info
+                    .get(&posting.doc_id)
+                    .is_some_and(|doc_info| self.is_visible(posting, doc_info));
+                if visible {
+                    matching.entry(posting.doc_id).or_default();
+                }
+            }
+        }
+        matching.len() as u64
+    }
+
+    /// Reads the postings of every distinct term of the queries once, loading
+    /// every segment dictionary once. Segments that fail to load are skipped
+    /// and reported by `skipped_segments`.
+    ///
+    /// # Arguments
+    /// * `queries` - The analyzed tokens of each query.
+    ///
+    /// # Returns
+    /// The global DF and the postings of live documents of each term, keyed
+    /// by term.
+    fn query_postings(&self, queries: &[Vec<Term>]) -> HashMap<Term, (u64, Vec<Posting>)> {
+        // Phrase terms are matched on the postings of their words
+        let query_terms: HashSet<&str> = queries
+            .iter()
+            .flatten()
+            .flat_map(|term| term_parts(term))
+            .collect();
+        let mut terms_info_cache: HashMap<Term, Vec<(u64, TermInfo)>> = HashMap::new();
+
+        // Pass 1: Load dictionaries once for all queries, unless the bloom
+        // filter of the segment rules out every term. Broken segments are
+        // skipped, so that the healthy ones still answer.
+        for &seg_id in &self.active_segments {
+            if !self.segment_may_contain(seg_id, query_terms.iter().copied()) {
+                continue;
+            }
+            let seg_dict = match self.load_segment_dict(seg_id) {
+                Ok(seg_dict) => seg_dict,
+                Err(err) => {
+                    self.quarantine(seg_id, &err);
+                    continue;
+                }
+            };
+
+            for &token in &query_terms {
+                if let Some(metadata) = seg_dict.get(token) {
+                    terms_info_cache
+                        .entry(token.to_string())
+                        .or_default()
+                        .push((seg_id, *metadata));
+                }
+            }
+        }
+
+        // Pass 2: Read the postings of every term once and calculate global DFs
+        let mut term_postings: HashMap<Term, (u64, Vec<Posting>)> = HashMap::new();
+        for (term, postings_hit) in &terms_info_cache {
+            let mut postings = Vec::new();
+            for (seg_id, metadata) in postings_hit {
+                if self.has_failed(*seg_id) {
+                    continue;
+                }
+                match self.read_postings(*seg_id, metadata) {
+                    Ok(mut seg_postings) => {
+                        seg_postings.retain(|p| !self.doc_store.tombstones.contains(&p.doc_id));
+                        postings.append(&mut seg_postings);
+                    }
+                    Err(err) => self.quarantine(*seg_id, &err),
+                }
+            }
+            let global_df = match self.stats.doc_freq(term) {
+                Some(df) if self.as_of.is_none() => df,
+                // Searches as of an earlier time, and indexes committed
+                // before corrected frequencies were recorded, count the
+                // documents whose searched version contains the term
+                _ => self.visible_docs(&postings),
+            };
+            term_postings.insert(term.clone(), (global_df, postings));
+        }
+        term_postings
+    }
+
+    /// Counts the distinct documents whose searched version the postings of
+    /// a term belong to.
+    fn visible_docs(&self, postings: &[Posting]) -> u64 {
+        postings
+            .iter()
+            .filter(|posting| {
+                self.doc_store
+                    .id_to_doc_info
+                    .get(&posting.doc_id)
+                    .is_some_and(|doc_info| self.is_visible(posting, doc_info))
+            })
+            .map(|posting| posting.doc_id)
+            .collect::<HashSet<DocId>>()
+            .len() as u64
+    }
+
+    /// Returns whether a posting belongs to the version of its document that
+    /// is searched: the current one, or the one current at `as_of`.
+    fn is_visible(&self, posting: &Posting, doc_info: &DocInfo) -> bool {
+        let version = match self.as_of {
+            Some(time) => doc_info.version_at(time),
+            None => doc_info.versions.last(),
+        };
+        match version {
+            Some(version) => version.generation == posting.generation,
+            // Documents indexed before versions were recorded
+            None => {
+                doc_info.versions.is_empty()
+                    && self.as_of.is_none_or(|time| doc_info.indexed_at <= time)
+            }
+        }
+    }
+
+    /// Adds the scores of the documents containing the words of a phrase
+    /// term at consecutive positions to `scores`. Each word is scored as if
+    /// it occurred only as often as the whole phrase, weighted as a body
+    /// term.
+    ///
+    /// # Arguments
+    /// * `parts` - The index terms of the words of the phrase, in order.
+    /// * `term_postings` - The global DF and postings of each query term.
+    /// * `scorer` - The `Scorer` computing each term's score contribution.
+    /// * `scores` - The scores of the documents matching the query so far.
+    fn score_phrase(
+        &self,
+        parts: &[&str],
+        term_postings: &HashMap<Term, (u64, Vec<Posting>)>,
+        scorer: &dyn Scorer,
+        scores: &mut HashMap<DocId, f64>,
+    ) {
+        let mut part_postings: Vec<(u64, HashMap<DocId, &Posting>)> = Vec::new();
+        for &part in parts {
+            let Some((global_df, postings)) = term_postings.get(part) else {
+                return;
+            };
+            let visible = postings
+                .iter()
+                .filter(|posting| {
+                    self.doc_store
+                        .id_to_doc_info
+                        .get(&posting.doc_id)
+                        .is_some_and(|doc_info| self.is_visible(posting, doc_info))
+                })
+                .map(|posting| (posting.doc_id, posting))
+                .collect::<HashMap<DocId, &Posting>>();
+            part_postings.push((*global_df, visible));
+        }
+        let Some(((_, first), rest)) = part_postings.split_first() else {
+            return;
+        };
+
+        let total_docs = self.doc_store.total_docs();
+        let avg_doc_len = self.stats.avg_doc_len;
+        for (doc_id, posting) in first {
+            // Positions are stored in ascending order
+            let occurrences = posting
+                .positions
+                .iter()
+                .filter(|&&start| {
+                    rest.iter().enumerate().all(|(offset, (_, postings))| {
+                        postings.get(doc_id).is_some_and(|next| {
+                            next.positions
+                                .binary_search(&(start + offset as Position + 1))
+                                .is_ok()
+                        })
+                    })
+                })
+                .count();
+            if occurrences == 0 {
+                continue;
+            }
+            let Some(doc_info) = self.doc_store.id_to_doc_info.get(doc_id) else {
+                continue;
+            };
+            let doc_len = self.stats.doc_len(*doc_id).unwrap_or(doc_info.token_count);
+            let score: f64 = part_postings
+                .iter()
+                .map(|(global_df, _)| {
+                    let stats = TermStats {
+                        tf: occurrences as TermFrequency,
+                        df: *global_df,
+                        total_docs,
+                        doc_len,
+                        avg_doc_len,
+                    };
+                    scorer.score(&stats, doc_info)
+                })
+                .sum();
+            *scores.entry(*doc_id).or_insert(0.0) += self.field_weights.body * score;
+        }
+    }
+
+    /// Scores the documents matching one query from the preloaded postings.
+    /// Documents containing several query terms close together are boosted
+    /// by their proximity score. Phrase terms are scored by `score_phrase`,
+    /// and every term is weighted by the `field_weights` of the field it
+    /// matches.
+    ///
+    /// # Arguments
+    /// * `q_tokens` - The analyzed tokens of the query.
+    /// * `term_postings` - The global DF and postings of each query term.
+    /// * `scorer` - The `Scorer` computing each term's score contribution.
+    ///
+    /// # Returns
+    /// The matching documents and their scores in descending order of score.
+    fn score_query(
+        &self,
+        q_tokens: &[Term],
+        term_postings: &HashMap<Term, (u64, Vec<Posting>)>,
+        scorer: &dyn Scorer,
+    ) -> Vec<(PathBuf, f64)> {
+        let mut scores: HashMap<DocId, f64> = HashMap::new();
+        let mut doc_positions: HashMap<DocId, HashMap<&Term, &[Position]>> = HashMap::new();
+        let total_docs = self.doc_store.total_docs();
+        let avg_doc_len = self.stats.avg_doc_len;
+
+        for token in q_tokens {
+            if let Some(parts) = phrase_parts(token) {
+                self.score_phrase(&parts, term_postings, scorer, &mut scores);
+                continue;
+            }
+            let Some((global_df, postings)) = term_postings.get(token) else {
+                continue;
+            };
+            if *global_df == 0 {
+                continue;
+            }
+            let in_field = is_field_term(token);
+            let boost = self.field_weights.of_term(token);
+
+            for posting in postings {
+                let Some(doc_info) = self.doc_store.id_to_doc_info.get(&posting.doc_id) else {
+                    continue;
+                };
+                if !self.is_visible(posting, doc_info) {
+                    continue;
+                }
+                let stats = TermStats {
+                    tf: posting.tf,
+                    df: *global_df,
+                    total_docs,
+                    doc_len: self
+                        .stats
+                        .doc_len(posting.doc_id)
+                        .unwrap_or(doc_info.token_count),
+                    avg_doc_len,
+                };
+                *scores.entry(posting.doc_id).or_insert(0.0) +=
+                    boost * scorer.score(&stats, doc_info);
+                // Field terms are not positioned within the content
+                if !in_field {
+                    doc_positions
+                        .entry(posting.doc_id)
+                        .or_default()
+                        .insert(token, &posting.positions);
+                }
+            }
+        }
+
+        self.rank_scores(scores, &doc_positions, scorer.proximity_weight())
+    }
+
+    /// Scores the documents matching a query like `score_query`, but only
+    /// as far as needed to find the `limit` best of them: query terms are
+    /// scored from the one that can add the most to a document score down,
+    /// and once the terms left cannot lift a document to the `limit` best,
+    /// documents not matched yet are no longer scored and those left behind
+    /// are dropped. Phrase terms are not supported.
+    ///
+    /// With `after`, the best documents wanted are those ranking after it:
+    /// documents sure to rank before are dropped, and only those sure to
+    /// rank after it count towards the `limit` best.
+    ///
+    /// # Arguments
+    /// * `q_tokens` - The analyzed tokens of the query, without phrases.
+    /// * `term_postings` - The global DF and postings of each query term.
+    /// * `ranker` - The ranker scoring the documents, bounding the scores.
+    /// * `limit` - The number of best documents wanted.
+    /// * `after` - The path and score of the result the documents wanted
+    ///   rank after, if any.
+    ///
+    /// # Returns
+    /// The `TopResults`, or `None` if the scores of the query cannot be
+    /// bounded, e.g. with a custom ranker or negative weights.
+    fn score_query_top(
+        &self,
+        q_tokens: &[Term],
+        term_postings: &HashMap<Term, (u64, Vec<Posting>)>,
+        ranker: Ranker,
+        limit: usize,
+        after: Option<&(PathBuf, f64)>,
+    ) -> Option<TopResults> {
+        let scorer: &dyn Scorer = match ranker {
+            Ranker::Tfidf => &TfIdf,
+            Ranker::Bm25 => &self.bm25,
+            Ranker::Custom => return None,
+        };
+        let total_docs = self.doc_store.total_docs();
+        let avg_doc_len = self.stats.avg_doc_len;
+
+        // The highest contribution of each term, the most contributing first
+        let mut terms = Vec::new();
+        for (index, token) in q_tokens.iter().enumerate() {
+            let Some((global_df, postings)) = term_postings.get(token) else {
+                continue;
+            };
+            let Some(max_tf) = postings.iter().map(|posting| posting.tf).max() else {
+                continue;
+            };
+            if *global_df == 0 {
+                continue;
+            }
+            let weight = self.field_weights.of_term(token);
+            if weight < 0.0 {
+                return None;
+            }
+            let bound = weight * ranker.max_score(&self.bm25, max_tf, *global_df, total_docs)?;
+            terms.push((index, token, bound));
+        }
+        terms.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+        // The factors applied to the summed scores: the proximity bonus, the
+        // boost rules matching the path and the range of each boost hook
+        let factors = || self.boosts.iter().map(|rule| rule.factor);
+        if factors().any(|factor| factor < 0.0) {
+            return None;
+        }
+        let mut max_factor =
+            (1.0 + scorer.proximity_weight()) * factors().filter(|f| *f > 1.0).product::<f64>();
+        let mut min_factor = factors().filter(|f| *f < 1.0).product::<f64>();
+        for boost in &self.score_boosts {
+            let (low, high) = boost.range()?;
+            if low < 0.0 {
+                return None;
+            }
+            min_factor *= low;
+            max_factor *= high;
+        }
+
+        let mut scores: HashMap<DocId, f64> = HashMap::new();
+        // The documents sure to rank before `after`
+        let mut before_after: HashSet<DocId> = HashSet::new();
+        let last_score = after.map(|(_, score)| *score);
+        // The score of each term in each document, summed in query order at
+        // the end so that scores are those of a full search
+        let mut term_scores: HashMap<DocId, Vec<(usize, f64)>> = HashMap::new();
+        let mut doc_positions: HashMap<DocId, HashMap<&Term, &[Position]>> = HashMap::new();
+        let mut remaining: f64 = terms.iter().map(|(_, _, bound)| bound).sum();
+        // The lowest final score the `limit` best documents have so far
+        let mut threshold = 0.0;
+        let mut pruned = false;
+        for (index, token, bound) in terms {
+            // Documents first matched by this term get at most this term and
+            // the ones left
+            let admits_new = remaining * max_factor >= threshold;
+            remaining -= bound;
+            let (global_df, postings) = &term_postings[token];
+            let in_field = is_field_term(token);
+            let boost = self.field_weights.of_term(token);
+            for posting in postings {
+                if !admits_new && !scores.contains_key(&posting.doc_id) {
+                    pruned = true;
+                    continue;
+                }
+                if before_after.contains(&posting.doc_id) {
+                    continue;
+                }
+                let Some(doc_info) = self.doc_store.id_to_doc_info.get(&posting.doc_id) else {
+                    continue;
+                };
+                if !self.is_visible(posting, doc_info) {
+                    continue;
+                }
+                let stats = TermStats {
+                    tf: posting.tf,
+                    df: *global_df,
+                    total_docs,
+                    doc_len: self
+                        .stats
+                        .doc_len(posting.doc_id)
+                        .unwrap_or(doc_info.token_count),
+                    avg_doc_len,
+                };
+                let score = boost * scorer.score(&stats, doc_info);
+                *scores.entry(posting.doc_id).or_insert(0.0) += score;
+                term_scores
+                    .entry(posting.doc_id)
+                    .or_default()
+                    .push((index, score));
+                if !in_field {
+                    doc_positions
+                        .entry(posting.doc_id)
+                        .or_default()
+                        .insert(token, &posting.positions);
+                }
+            }
+
+            if let Some(last_score) = last_score {
+                scores.retain(|doc_id, score| {
+                    let before = *score * min_factor > last_score;
+                    if before {
+                        before_after.insert(*doc_id);
+                    }
+                    !before
+                });
+                term_scores.retain(|doc_id, _| scores.contains_key(doc_id));
+                doc_positions.retain(|doc_id, _| scores.contains_key(doc_id));
+            }
+            let mut partial: Vec<f64> = scores
+                .values()
+                .copied()
+                .filter(|score| {
+                    last_score.is_none_or(|last| (score + remaining) * max_factor < last)
+                })
+                .collect();
+            if partial.len() < limit {
+                continue;
+            }
+            partial.select_nth_unstable_by(limit - 1, |a, b| b.total_cmp(a));
+            threshold = partial[limit - 1] * min_factor;
+            let matched = scores.len();
+            scores.retain(|_, score| (*score + remaining) * max_factor >= threshold);
+            if scores.len() < matched {
+                pruned = true;
+                term_scores.retain(|doc_id, _| scores.contains_key(doc_id));
+                doc_positions.retain(|doc_id, _| scores.contains_key(doc_id));
+            }
+        }
+
+        let scores = term_scores
+            .into_iter()
+            .map(|(doc_id, mut doc_scores)| {
+                doc_scores.sort_by_key(|(index, _)| *index);
+                (doc_id, doc_scores.iter().map(|(_, score)| score).sum())
+            })
+            .collect();
+
+        Some(TopResults {
+            results: self.rank_scores(scores, &doc_positions, scorer.proximity_weight()),
+            pruned,
+        })
+    }
+
+    /// Turns the summed term scores of documents into ranked results: each
+    /// score is raised by the proximity of the query terms in the document,
+    /// as strongly as the scorer asks, and multiplied by the boost rules matching each path of the document
+    /// and the factors of the boost hooks.
+    ///
+    /// # Arguments
+    /// * `scores` - The summed term scores, keyed by document id.
+    /// * `doc_positions` - The positions of the query terms in each document.
+    /// * `proximity_weight` - The `Scorer::proximity_weight` of the scorer.
+    ///
+    /// # Returns
+    /// The results in descending order of score.
+    fn rank_scores(
+        &self,
+        scores: HashMap<DocId, f64>,
+        doc_positions: &HashMap<DocId, HashMap<&Term, &[Position]>>,
+        proximity_weight: f64,
+    ) -> Vec<(PathBuf, f64)> {
+        let mut results: Vec<(PathBuf, f64)> = Vec::new();
+        for (doc_id, score) in scores {
+            let Some(doc_info) = self.doc_store.id_to_doc_info.get(&doc_id) else {
+                continue;
+            };
+            if score == 0.0 {
+                continue;
+            }
+            let proximity = doc_positions
+                .get(&doc_id)
+                .map(|positions| proximity_score(&positions.values().copied().collect::<Vec<_>>()))
+                .unwrap_or(0.0);
+            let score = score * (1.0 + proximity_weight * proximity);
+
+            // Paths with identical content share the document's postings
+            for path in std::iter::once(&doc_info.path).chain(&doc_info.aliases) {
+                let boost: f64 = self
+                    .boosts
+                    .iter()
+                    .filter(|rule| rule.matches(path))
+                    .map(|rule| rule.factor)
+                    .product::<f64>()
+                    * self
+                        .score_boosts
+                        .iter()
+                        .map(|hook| hook.factor(path, doc_info))
+                        .product::<f64>();
+                results.push((path.clone(), score * boost));
+            }
+        }
+
+        results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::phrase_term;
+    use crate::temp::TempIndex;
+
+    /// An index of the files written to a root directory, whose documents
+    /// are added without a parser.
+    struct Served {
+        root: TempIndex,
+        _index_dir: TempIndex,
+        index: MainIndex,
+    }
+
+    impl Served {
+        fn new() -> Self {
+            let root = TempIndex::new().unwrap();
+            let index_dir = TempIndex::new().unwrap();
+            let mut index = MainIndex::new(index_dir.path()).unwrap();
+            index
+                .manifest
+                .roots
+                .push(fs::canonicalize(root.path()).unwrap());
+            Self {
+                root,
+                _index_dir: index_dir,
+                index,
+            }
+        }
+
+        fn path(&self, name: &str) -> PathBuf {
+            fs::canonicalize(self.root.path()).unwrap().join(name)
+        }
+
+        fn add(&mut self, path: &Path) {
+            self.index
+                .add_document(path, &["servable".to_string()])
+                .unwrap();
+            self.index.commit().unwrap();
+        }
+
+        fn write(&mut self, name: &str) -> PathBuf {
+            let path = self.path(name);
+            fs::write(&path, "servable").unwrap();
+            self.add(&path);
+            path
+        }
+    }
+
+    #[test]
+    fn phrases_match_adjacent_words_in_order() {
+        let index_dir = TempIndex::new().unwrap();
+        let mut index = MainIndex::new(index_dir.path()).unwrap();
+        let docs = [
+            ("adjacent", "the quick fox"),
+            ("twice", "quick fox and quick fox"),
+            ("gap", "quick brown fox"),
+            ("reversed", "fox quick"),
+            ("apart", "quick dog then fox"),
+            ("other", "nothing here"),
+        ];
+        for (name, text) in docs {
+            let terms = text.split(' ').map(str::to_string).collect::<Vec<_>>();
+            index
+                .add_document(Path::new(&format!("/docs/{name}.txt")), &terms)
+                .unwrap();
+        }
+        index.commit().unwrap();
+
+        let phrase = phrase_term(&["quick".to_string(), "fox".to_string()]);
+        let results = index.search(&[phrase]).unwrap();
+        let paths = results
+            .iter()
+            .map(|(path, _)| path.to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["/docs/twice.txt", "/docs/adjacent.txt"]);
+        assert!(results[0].1 > results[1].1);
+
+        let phrase = phrase_term(&["fox".to_string(), "quick".to_string()]);
+        let results = index.search(&[phrase]).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, Path::new("/docs/reversed.txt"));
+    }
+
+    #[test]
+    fn merged_segments_outlive_their_readers() {
+        let index_dir = TempIndex::new().unwrap();
+        let mut index = MainIndex::new(index_dir.path()).unwrap();
+        for name in ["first", "second"] {
+            let path = format!("/docs/{name}.txt");
+            index
+                .add_document(Path::new(&path), &[name.to_string()])
+                .unwrap();
+            index.commit().unwrap();
+        }
+        let merged = index.active_segments.clone();
+        assert_eq!(merged.len(), 2);
+
+        let reader = MainIndex::open_read_only(index_dir.path(), None).unwrap();
+        index.merge_segments(&merged).unwrap();
+        index.commit().unwrap();
+        assert!(
+            merged
+                .iter()
+                .all(|&seg_id| index.segment_dir(seg_id).exists())
+        );
+        assert_eq!(reader.search(&["first".to_string()]).unwrap().len(), 1);
+
+        drop(reader);
+        index.commit().unwrap();
+        assert!(
+            merged
+                .iter()
+                .all(|&seg_id| !index.segment_dir(seg_id).exists())
+        );
+        assert!(index.manifest.retired.is_empty());
+    }
+
+    #[test]
+    fn evictions_only_rewrite_the_segments_of_evicted_documents() {
+        let index_dir = TempIndex::new().unwrap();
+        let mut index = MainIndex::new(index_dir.path()).unwrap();
+        let big = (0..200).map(|i| format!("word{i}")).collect::<Vec<_>>();
+        for (name, terms) in [
+            ("small", vec!["tiny".to_string()]),
+            ("big", big),
+            ("other", vec!["little".to_string()]),
+        ] {
+            index
+                .add_document(Path::new(&format!("/docs/{name}.txt")), &terms)
+                .unwrap();
+            index.commit().unwrap();
+        }
+        let segments = index.active_segments.clone();
+        assert_eq!(segments.len(), 3);
+
+        let budget = SizeBudget {
+            max_bytes: index.live_size().unwrap() / 2,
+            policy: EvictionPolicy::Largest,
+        };
+        let evicted = index.enforce_budget(&budget).unwrap();
+        assert_eq!(evicted, vec![PathBuf::from("/docs/big.txt")]);
+        assert!(!index.active_segments.contains(&segments[1]));
+        assert!(index.active_segments.contains(&segments[0]));
+        assert!(index.active_segments.contains(&segments[2]));
+    }
+
+    #[test]
+    fn only_built_in_rankers_boost_close_terms() {
+        let index_dir = TempIndex::new().unwrap();
+        let mut index = MainIndex::new(index_dir.path()).unwrap();
+        for (name, text) in [
+            ("close", "quick fox dog dog"),
+            ("apart", "quick dog dog fox"),
+        ] {
+            let terms = text.split(' ').map(str::to_string).collect::<Vec<_>>();
+            index
+                .add_document(Path::new(&format!("/docs/{name}.txt")), &terms)
+                .unwrap();
+        }
+        index
+            .add_document(Path::new("/docs/other.txt"), &["cat".to_string()])
+            .unwrap();
+        index.commit().unwrap();
+        index.set_custom_scorer(|_: &TermStats, _: &DocInfo| 1.0);
+
+        let query = ["quick".to_string(), "fox".to_string()];
+        let results = index.search_ranked(&query, Some(Ranker::Custom)).unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|(_, score)| *score == 2.0));
+
+        let results = index.search_ranked(&query, Some(Ranker::Bm25)).unwrap();
+        assert_eq!(results[0].0, Path::new("/docs/close.txt"));
+        assert!(results[0].1 > results[1].1);
+    }
+
+    #[test]
+    fn exact_forms_keep_phrase_words_adjacent() {
+        let index_dir = TempIndex::new().unwrap();
+        let mut index = MainIndex::new(index_dir.path()).unwrap();
+        // Exact forms share the position of the stem they precede
+        let terms = [
+            format!("{EXACT_PREFIX}Quick"),
+            "quick".to_string(),
+            format!("{EXACT_PREFIX}Foxes"),
+            "fox".to_string(),
+        ];
+        index
+            .add_document(Path::new("/docs/exact.txt"), &terms)
+            .unwrap();
+        index
+            .add_document(Path::new("/docs/other.txt"), &["other".to_string()])
+            .unwrap();
+        index.commit().unwrap();
+
+        let phrase = phrase_term(&["quick".to_string(), "fox".to_string()]);
+        let results = index.search(&[phrase]).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, Path::new("/docs/exact.txt"));
+    }
+
+    #[test]
+    fn the_equals_sign_is_not_an_exact_form() {
+        let index_dir = TempIndex::new().unwrap();
+        let mut index = MainIndex::new(index_dir.path()).unwrap();
+        index.manifest.analyzer.punctuation = true;
+        let terms = index.analyzer().analyze("x=y");
+        assert!(terms.contains(&EXACT_PREFIX.to_string()), "{terms:?}");
+        index
+            .add_document(Path::new("/docs/equation.txt"), &terms)
+            .unwrap();
+        index
+            .add_document(Path::new("/docs/other.txt"), &["other".to_string()])
+            .unwrap();
+        index.commit().unwrap();
+
+        // The sign takes a position of its own between the words
+        let parts = ["x", EXACT_PREFIX, "y"].map(str::to_string);
+        let results = index.search(&[phrase_term(&parts)]).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(exact_word(EXACT_PREFIX), None);
+        assert_eq!(exact_word(&format!("{EXACT_PREFIX}b")), Some("b"));
+    }
+
+    #[test]
+    fn pages_follow_the_full_ranking() {
+        let index_dir = TempIndex::new().unwrap();
+        let mut index = MainIndex::new(index_dir.path()).unwrap();
+        for doc in 0..12 {
+            // Repeated counts make ties that only the path breaks
+            let mut terms = vec!["page".to_string(); doc % 4 + 1];
+            terms.push(format!("doc{doc}"));
+            index
+                .add_document(Path::new(&format!("/docs/{doc}.txt")), &terms)
+                .unwrap();
+        }
+        index
+            .add_document(Path::new("/docs/other.txt"), &["other".to_string()])
+            .unwrap();
+        index.commit().unwrap();
+
+        let query = ["page".to_string()];
+        let mut expected = index.search(&query).unwrap();
+        expected.sort_by(compare_results);
+        let mut paged = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = index.search_page(&query, cursor.as_deref(), 5).unwrap();
+            assert!(page.results.len() <= 5);
+            paged.extend(page.results);
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(paged, expected);
+    }
+
+    #[test]
+    fn commits_count_flushed_segments_like_a_full_recount() {
+        let index_dir = TempIndex::new().unwrap();
+        let mut index = MainIndex::new(index_dir.path()).unwrap();
+        let terms = |terms: &[&str]| terms.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        index
+            .add_document(Path::new("/a"), &terms(&["x", "y"]))
+            .unwrap();
+        index.add_document(Path::new("/b"), &terms(&["x"])).unwrap();
+        index.commit().unwrap();
+        index.add_document(Path::new("/a"), &terms(&["y"])).unwrap();
+        index
+            .add_document(Path::new("/c"), &terms(&["x", "z"]))
+            .unwrap();
+        index.add_document(Path::new("/d"), &terms(&["q"])).unwrap();
+        index.commit().unwrap();
+        // Deleted before the commit, so its segment is read back
+        index.add_document(Path::new("/e"), &terms(&["w"])).unwrap();
+        index.delete_document(Path::new("/e")).unwrap();
+        index.commit().unwrap();
+
+        let counted = index.global_document_frequencies().unwrap();
+        let live_versions = index.doc_store.live_versions();
+        index.stats.doc_freqs = None;
+        let (recounted, _) = index.live_document_frequencies(&live_versions).unwrap();
+        assert_eq!(counted, recounted);
+        let expected: HashMap<Term, u64> = [("x", 2), ("y", 1), ("z", 1), ("q", 1)]
+            .into_iter()
+            .map(|(term, df)| (term.to_string(), df))
+            .collect();
+        assert_eq!(counted, expected);
+    }
+
+    #[test]
+    fn detects_renames_by_inode_size_and_modification_time() {
+        let mut served = Served::new();
+        let mut moved = Vec::new();
+        for name in ["moved.txt", "resized.txt"] {
+            let path = served.write(name);
+            let id = served.index.doc_store.doc_to_id[&path];
+            served.index.doc_store.set_file_metadata(id, &path);
+            let new = served.path(&format!("new-{name}"));
+            fs::rename(&path, &new).unwrap();
+            moved.push(new);
+        }
+        // As if the inode was reused by a file of another size
+        let id = served.index.doc_store.doc_to_id[&served.path("resized.txt")];
+        served
+            .index
+            .doc_store
+            .id_to_doc_info
+            .get_mut(&id)
+            .unwrap()
+            .size = Some(1);
+
+        let renamed = served.index.doc_store.detect_renames(&moved);
+        assert_eq!(renamed, vec![(served.path("moved.txt"), moved[0].clone())]);
+        assert!(served.index.doc_store.doc_to_id.contains_key(&moved[0]));
+        assert!(!served.index.doc_store.doc_to_id.contains_key(&moved[1]));
+    }
+
+    #[test]
+    fn claims_content_before_it_is_indexed() {
+        let mut served = Served::new();
+        let first = served.path("first.txt");
+        let copy = served.path("copy.txt");
+        for path in [&first, &copy] {
+            fs::write(path, "same").unwrap();
+        }
+        let store = &mut served.index.doc_store;
+        assert!(!store.dedup(&first, "hash"));
+        // Indexed in parallel before the first one is added
+        assert!(store.dedup(&copy, "hash"));
+        assert_eq!(store.doc_to_id[&copy], store.doc_to_id[&first]);
+
+        store.release_content(&first);
+        assert!(!store.doc_to_id.contains_key(&copy));
+        assert!(!store.dedup(&copy, "hash"));
+    }
+
+    #[test]
+    fn rejects_malformed_and_stale_cursors() {
+        let index_dir = TempIndex::new().unwrap();
+        let mut index = MainIndex::new(index_dir.path()).unwrap();
+        for doc in 0..3 {
+            index
+                .add_document(
+                    Path::new(&format!("/docs/{doc}.txt")),
+                    &["page".to_string()],
+                )
+                .unwrap();
+        }
+        // A term in every document scores nothing
+        index
+            .add_document(Path::new("/docs/other.txt"), &["other".to_string()])
+            .unwrap();
+        index.commit().unwrap();
+        let query = ["page".to_string()];
+        let cursor_error = |index: &MainIndex, cursor: &str| {
+            let err = index.search_page(&query, Some(cursor), 1).unwrap_err();
+            *err.downcast_ref::<CursorError>().unwrap()
+        };
+        assert_eq!(cursor_error(&index, "zz"), CursorError::Malformed);
+        assert_eq!(cursor_error(&index, "00ff"), CursorError::Malformed);
+        let too_long = "00".repeat(16 + MAX_CURSOR_PATH + 1);
+        assert_eq!(cursor_error(&index, &too_long), CursorError::Malformed);
+
+        let cursor = index
+            .search_page(&query, None, 1)
+            .unwrap()
+            .next_cursor
+            .unwrap();
+        index
+            .add_document(Path::new("/docs/new.txt"), &["page".to_string()])
+            .unwrap();
+        index.commit().unwrap();
+        assert_eq!(cursor_error(&index, &cursor), CursorError::Stale);
+    }
+
+    #[test]
+    fn serves_an_indexed_file() {
+        let mut served = Served::new();
+        let path = served.write("notes.txt");
+        assert_eq!(served.index.servable_path(&path).unwrap(), path);
+    }
+
+    #[test]
+    fn rejects_parent_dir_components() {
+        let mut served = Served::new();
+        served.write("notes.txt");
+        let escaping = served.path("sub/../notes.txt");
+        let err = served.index.servable_path(&escaping).unwrap_err();
+        assert!(err.to_string().contains("`..` components"), "{err}");
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_indexed() {
+        let mut served = Served::new();
+        served.write("notes.txt");
+        let other = served.path("secret.txt");
+        fs::write(&other, "not indexed").unwrap();
+        let err = served.index.servable_path(&other).unwrap_err();
+        assert!(err.to_string().contains("not an indexed document"), "{err}");
+    }
+
+    #[test]
+    fn rejects_deleted_documents() {
+        let mut served = Served::new();
+        let path = served.write("notes.txt");
+        served.index.delete_document(&path).unwrap();
+        served.index.commit().unwrap();
+        let err = served.index.servable_path(&path).unwrap_err();
+        assert!(err.to_string().contains("not an indexed document"), "{err}");
+    }
+
+    #[test]
+    fn rejects_tombstoned_documents() {
+        let mut served = Served::new();
+        let path = served.write("notes.txt");
+        let id = served.index.doc_store.doc_to_id[&path];
+        served.index.doc_store.tombstones.insert(id);
+        let err = served.index.servable_path(&path).unwrap_err();
+        assert!(err.to_string().contains("not an indexed document"), "{err}");
+    }
+
+    #[test]
+    fn rejects_symlinks_leaving_the_roots() {
+        let mut served = Served::new();
+        let link = served.path("passwd");
+        std::os::unix::fs::symlink("/etc/passwd", &link).unwrap();
+        served.add(&link);
+        let err = served.index.servable_path(&link).unwrap_err();
+        assert!(
+            err.to_string().contains("outside the indexed roots"),
+            "{err}"
+        );
+    }
 }
@@ -0,0 +1,300 @@
+use anyhow::{Context, anyhow};
+use minisign_verify::{PublicKey, Signature};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use std::fs;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The GitHub API endpoint describing the latest release.
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/juanmilkah/indexer/releases/latest";
+
+/// The release asset listing the SHA-256 checksums of the other assets, one
+/// `<hex digest>  <asset name>` line each.
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+
+/// The release asset holding the minisign signature of `CHECKSUMS_ASSET`.
+const SIGNATURE_ASSET: &str = "SHA256SUMS.minisig";
+
+/// The base64 minisign public key releases are signed with, pinned into the
+/// binary when it is built. Builds without one refuse to install updates, as
+/// they could not tell a genuine release from a tampered one.
+const RELEASE_PUBLIC_KEY: Option<&str> = option_env!("INDEXER_RELEASE_PUBLIC_KEY");
+
+/// How long each request to GitHub may take.
+const UPDATE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What `self_update` found or did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateStatus {
+    /// The running version is the latest.
+    UpToDate,
+    /// A newer version is available, but only checking was requested.
+    Available(String),
+    /// A newer version was installed.
+    Installed {
+        /// The version installed.
+        version: String,
+        /// The path of the installed binary.
+        target: PathBuf,
+    },
+}
+
+/// A file attached to a release.
+#[derive(Deserialize, Debug)]
+pub struct Asset {
+    /// The file name of the asset.
+    pub name: String,
+    /// The URL the asset is downloaded from.
+    pub browser_download_url: String,
+}
+
+/// A GitHub release.
+#[derive(Deserialize, Debug)]
+pub struct Release {
+    /// The tag of the release, e.g. `v0.3.0`.
+    pub tag_name: String,
+    /// The files attached to the release.
+    pub assets: Vec<Asset>,
+}
+
+impl Release {
+    /// Returns the version of the release, without the `v` prefix of its tag.
+    pub fn version(&self) -> &str {
+        self.tag_name.trim_start_matches('v')
+    }
+
+    /// Returns the asset named `name`, if the release has one.
+    fn asset(&self, name: &str) -> anyhow::Result<&Asset> {
+        self.assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .ok_or_else(|| anyhow!("release {} has no {name} asset", self.tag_name))
+    }
+}
+
+/// Returns the name of the release artifact built for this platform, e.g.
+/// `indexer-x86_64-linux`.
+pub fn artifact_name() -> String {
+    format!(
+        "indexer-{}-{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS
+    )
+}
+
+/// Returns `true` if `candidate` is a later version than `current`, comparing
+/// the dot separated numbers of both.
+///
+/// # Arguments
+/// * `candidate` - The version of a release.
+/// * `current` - The version of the running binary.
+pub fn is_newer(candidate: &str, current: &str) -> bool {
+    let numbers = |version: &str| {
+        version
+            .split(['.', '-', '+'])
+            .map_while(|part| part.parse::<u64>().ok())
+            .collect::<Vec<u64>>()
+    };
+    numbers(candidate) > numbers(current)
+}
+
+/// Creates the HTTP agent used to talk to GitHub.
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout(UPDATE_TIMEOUT)
+        .user_agent(concat!("indexer/", env!("CARGO_PKG_VERSION")))
+        .build()
+}
+
+/// Fetches the description of the latest release.
+///
+/// # Returns
+/// The latest `Release`, or an `anyhow::Result` error.
+pub fn latest_release() -> anyhow::Result<Release> {
+    let body = agent()
+        .get(LATEST_RELEASE_URL)
+        .call()
+        .context("fetch latest release")?
+        .into_string()
+        .context("read latest release")?;
+    serde_json::from_str(&body).context("parse latest release")
+}
+
+/// Downloads an asset into memory.
+fn download(agent: &ureq::Agent, asset: &Asset) -> anyhow::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    agent
+        .get(&asset.browser_download_url)
+        .call()
+        .with_context(|| format!("download {}", asset.name))?
+        .into_reader()
+        .read_to_end(&mut body)
+        .with_context(|| format!("read {}", asset.name))?;
+    Ok(body)
+}
+
+/// Checks the minisign signature of a release file.
+///
+/// # Arguments
+/// * `public_key` - The base64 minisign public key the release is signed
+///   with.
+/// * `content` - The signed file.
+/// * `signature` - The content of the `.minisig` signature file.
+///
+/// # Returns
+/// `Ok(())` if `content` was signed with the key, otherwise an
+/// `anyhow::Result` error.
+pub fn verify_signature(public_key: &str, content: &[u8], signature: &str) -> anyhow::Result<()> {
+    let public_key = PublicKey::from_base64(public_key).context("decode release public key")?;
+    let signature = Signature::decode(signature).context("decode release signature")?;
+    public_key
+        .verify(content, &signature, false)
+        .context("the release is not signed with the release key")
+}
+
+/// Checks `content` against its entry in a `SHA256SUMS` file.
+///
+/// # Arguments
+/// * `checksums` - The content of the checksums file.
+/// * `name` - The asset name looked up in the checksums file.
+/// * `content` - The downloaded asset.
+///
+/// # Returns
+/// `Ok(())` if the digests match, otherwise an `anyhow::Result` error.
+pub fn verify_checksum(checksums: &str, name: &str, content: &[u8]) -> anyhow::Result<()> {
+    let expected = checksums
+        .lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, file)| file.trim().trim_start_matches('*') == name)
+        .map(|(digest, _)| digest.to_ascii_lowercase())
+        .ok_or_else(|| anyhow!("{CHECKSUMS_ASSET} has no entry for {name}"))?;
+    let actual = Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    if actual != expected {
+        return Err(anyhow!(
+            "checksum mismatch for {name}: expected {expected}, got {actual}"
+        ));
+    }
+    Ok(())
+}
+
+/// Replaces the binary at `target` with `content`. The new binary is written
+/// next to the target and renamed over it, so an interrupted update never
+/// leaves a truncated binary behind.
+///
+/// # Arguments
+/// * `target` - The path of the binary to replace or create.
+/// * `content` - The new binary.
+///
+/// # Returns
+/// An `anyhow::Result` error if the directory of `target` is not writable.
+fn install(target: &Path, content: &[u8]) -> anyhow::Result<()> {
+    let dir = target
+        .parent()
+        .ok_or_else(|| anyhow!("{target:?} has no parent directory"))?;
+    fs::create_dir_all(dir).with_context(|| format!("create {dir:?}"))?;
+    let staged = dir.join(format!(".indexer-update-{}", std::process::id()));
+    fs::write(&staged, content)
+        .with_context(|| format!("write {staged:?} (is {dir:?} writable?)"))?;
+    fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))
+        .context("make the new binary executable")?;
+    if let Err(err) = fs::rename(&staged, target) {
+        let _ = fs::remove_file(&staged);
+        return Err(anyhow!("replace {target:?}: {err}"));
+    }
+    Ok(())
+}
+
+/// Updates the indexer to the latest release: downloads the artifact built
+/// for this platform, verifies the signature of the `SHA256SUMS` of the
+/// release with `RELEASE_PUBLIC_KEY`, verifies the artifact against it and
+/// replaces the running binary, or installs it in `install_dir`.
+///
+/// # Arguments
+/// * `install_dir` - Install into this directory (e.g. `~/.local/bin`)
+///   instead of replacing the running binary.
+/// * `check_only` - Only report whether an update is available.
+///
+/// # Returns
+/// The `UpdateStatus`, or an `anyhow::Result` error.
+pub fn self_update(install_dir: Option<&Path>, check_only: bool) -> anyhow::Result<UpdateStatus> {
+    let current = env!("CARGO_PKG_VERSION");
+    let release = latest_release()?;
+    if !is_newer(release.version(), current) {
+        return Ok(UpdateStatus::UpToDate);
+    }
+    if check_only {
+        return Ok(UpdateStatus::Available(release.version().to_string()));
+    }
+
+    let public_key = RELEASE_PUBLIC_KEY.ok_or_else(|| {
+        anyhow!(
+            "this build has no pinned release key (INDEXER_RELEASE_PUBLIC_KEY) \
+             to verify updates with; install the release by hand"
+        )
+    })?;
+    let name = artifact_name();
+    let artifact = release.asset(&name)?;
+    let agent = agent();
+    let checksums = download(&agent, release.asset(CHECKSUMS_ASSET)?)?;
+    let signature = download(&agent, release.asset(SIGNATURE_ASSET)?)?;
+    let signature = String::from_utf8(signature).context("read signature")?;
+    verify_signature(public_key, &checksums, &signature)?;
+    let checksums = String::from_utf8(checksums).context("read checksums")?;
+    let binary = download(&agent, artifact)?;
+    verify_checksum(&checksums, &name, &binary)?;
+
+    let target = match install_dir {
+        Some(dir) => dir.join("indexer"),
+        None => std::env::current_exe()
+            .and_then(fs::canonicalize)
+            .context("locate the running binary")?,
+    };
+    install(&target, &binary).with_context(|| match install_dir {
+        Some(_) => String::from("install update"),
+        None => String::from("install update, or use --install-dir ~/.local/bin"),
+    })?;
+    Ok(UpdateStatus::Installed {
+        version: release.version().to_string(),
+        target,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The key and signature of the test vector of `minisign-verify`.
+    const PUBLIC_KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+    const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1556193335\tfile:test
+y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==";
+
+    #[test]
+    fn signed_checksums_verify() {
+        verify_signature(PUBLIC_KEY, b"test", SIGNATURE).unwrap();
+    }
+
+    #[test]
+    fn tampered_checksums_are_rejected() {
+        assert!(verify_signature(PUBLIC_KEY, b"tested", SIGNATURE).is_err());
+        let other_key = "RWTAPRW2qy9FjsBiMoqbYmNk/lSnRMWJdb1LG4Ka6Gv4djkFUy6D3Glr";
+        assert!(verify_signature(other_key, b"test", SIGNATURE).is_err());
+    }
+
+    #[test]
+    fn checksums_are_matched_by_asset_name() {
+        let digest = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+        let checksums = format!("{digest}  indexer-x86_64-linux\n00  other\n");
+        verify_checksum(&checksums, "indexer-x86_64-linux", b"test").unwrap();
+        assert!(verify_checksum(&checksums, "indexer-x86_64-linux", b"tested").is_err());
+        assert!(verify_checksum(&checksums, "missing", b"test").is_err());
+    }
+}
@@ -0,0 +1,325 @@
+use ignore::{WalkBuilder, WalkState};
+
+use crate::glob::glob_match_path;
+use crate::remote;
+use crate::report::{RunReport, SkipKind};
+use crate::skip::SkipRules;
+use crate::skip_reason;
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// The modification times of discovered files, when the discovery backend
+/// already read them.
+pub type ModifiedTimes = HashMap<PathBuf, SystemTime>;
+
+/// The ways documents can be discovered under the indexed directory.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Discovery {
+    /// The built-in recursive walk.
+    #[default]
+    Builtin,
+    /// The parallel walker used by ripgrep, honouring `.gitignore`,
+    /// `.ignore` and the global git excludes.
+    Ignore,
+    /// A walk tuned for NFS/SMB mounts: one metadata call per file, whose
+    /// modification time is reused by the re-indexing check, and retries of
+    /// transient errors instead of aborting.
+    Remote,
+}
+
+/// The order in which the documents needing (re-)indexing are processed.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndexOrder {
+    /// The most recently modified documents first.
+    #[default]
+    Newest,
+    /// The smallest documents first, so that the most documents become
+    /// searchable early.
+    Smallest,
+    /// The documents matching the earliest `priority` pattern of the
+    /// configuration file first.
+    Priority,
+    /// The order of discovery.
+    Walk,
+}
+
+/// Sorts the documents of an indexing run by `order`. Documents comparing
+/// equal, e.g. those matching no `priority` pattern, keep the order of
+/// discovery.
+///
+/// # Arguments
+/// * `docs` - The documents to sort.
+/// * `order` - The order to sort them in.
+/// * `priorities` - The glob patterns of the `priority` directives, most
+///   important first.
+/// * `modified` - The modification times already read by the discovery.
+pub fn order_documents(
+    docs: &mut [PathBuf],
+    order: IndexOrder,
+    priorities: &[String],
+    modified: &ModifiedTimes,
+) {
+    match order {
+        IndexOrder::Newest => docs.sort_by_cached_key(|doc| {
+            let time = modified.get(doc).copied().or_else(|| {
+                fs::metadata(doc)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+            });
+            std::cmp::Reverse(time)
+        }),
+        IndexOrder::Smallest => docs.sort_by_cached_key(|doc| {
+            fs::metadata(doc).map_or(u64::MAX, |metadata| metadata.len())
+        }),
+        IndexOrder::Priority => docs.sort_by_cached_key(|doc| {
+            priorities
+                .iter()
+                .position(|pattern| glob_match_path(pattern, doc))
+                .unwrap_or(priorities.len())
+        }),
+        IndexOrder::Walk => {}
+    }
+}
+
+/// Decides which symbolic links a walk follows. Without `follow`, links are
+/// skipped. With it, they are followed unless they lead to a file or a
+/// directory the walk already reached, by its device and inode, so that
+/// cycles end and content is not discovered twice.
+pub(crate) struct Symlinks {
+    follow: bool,
+    /// The devices and inodes reached so far, only tracked with `follow`.
+    visited: HashSet<(u64, u64)>,
+}
+
+impl Symlinks {
+    /// Creates the policy of a walk, `follow`ing links or not.
+    pub(crate) fn new(follow: bool) -> Self {
+        Self {
+            follow,
+            visited: HashSet::new(),
+        }
+    }
+
+    /// Records an entry reached by a walk, before it is descended into or
+    /// listed.
+    ///
+    /// # Arguments
+    /// * `path` - The entry.
+    /// * `is_link` - Whether the entry itself is a symbolic link.
+    ///
+    /// # Returns
+    /// The kind of reason and the reason the entry is skipped for, or
+    /// `None` if the walk goes on with it.
+    pub(crate) fn admit(&mut self, path: &Path, is_link: bool) -> Option<(SkipKind, String)> {
+        if !self.follow {
+            return is_link.then(|| {
+                (
+                    SkipKind::Symlink,
+                    "symbolic link, not followed without --follow-symlinks".to_string(),
+                )
+            });
+        }
+        let data = match fs::metadata(path) {
+            Ok(data) => data,
+            Err(err) if is_link => {
+                return Some((SkipKind::Symlink, format!("broken symbolic link: {err}")));
+            }
+            // Reported by the walk when it reads the entry
+            Err(_) => return None,
+        };
+        if self.visited.insert((data.dev(), data.ino())) {
+            return None;
+        }
+        if !is_link {
+            return Some((
+                SkipKind::Symlink,
+                "already reached through a symbolic link".to_string(),
+            ));
+        }
+        let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let reason = if data.is_dir() {
+            format!("symbolic link cycle, {target:?} was already walked")
+        } else {
+            format!("{target:?} was already reached through another path")
+        };
+        Some((SkipKind::Symlink, reason))
+    }
+}
+
+/// Discovers the files under a directory with the `ignore` crate's parallel
+/// walker. Hidden entries follow `scan_hidden`, entries ignored by the
+/// ignore files of the tree are left out, and the skip rules, executables
+/// included, apply as with the built-in walk. Symbolic links are
+/// followed with `follow_symlinks`, the walker ending the cycles they make.
+///
+/// # Arguments
+/// * `root` - The directory to read files from.
+/// * `scan_hidden` - If `true`, hidden files and directories will be included.
+/// * `follow_symlinks` - If `true`, symbolic links are followed.
+/// * `skip` - The `SkipRules` excluding paths.
+/// * `report` - The `RunReport` recording the skipped paths.
+///
+/// # Returns
+/// The discovered files, sorted by path.
+pub fn walk_ignore(
+    root: &Path,
+    scan_hidden: bool,
+    follow_symlinks: bool,
+    skip: &SkipRules,
+    report: &mut RunReport,
+) -> Vec<PathBuf> {
+    let files = Arc::new(Mutex::new(Vec::new()));
+    let skipped = Arc::new(Mutex::new(Vec::new()));
+
+    let filter_skip = skip.clone();
+    let filter_skipped = Arc::clone(&skipped);
+    let walker = WalkBuilder::new(root)
+        .hidden(!scan_hidden)
+        .require_git(false)
+        .follow_links(follow_symlinks)
+        .filter_entry(move |entry| match filter_skip.matches(entry.path()) {
+            Some(reason) => {
+                filter_skipped.lock().unwrap().push((
+                    entry.path().to_path_buf(),
+                    SkipKind::Excluded,
+                    format!("excluded by {reason}"),
+                ));
+                false
+            }
+            None => true,
+        })
+        .build_parallel();
+
+    walker.run(|| {
+        let files = Arc::clone(&files);
+        let skipped = Arc::clone(&skipped);
+        Box::new(move |entry| {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(_) => return WalkState::Continue,
+            };
+            if !follow_symlinks && entry.path_is_symlink() {
+                skipped.lock().unwrap().push((
+                    entry.into_path(),
+                    SkipKind::Symlink,
+                    "symbolic link, not followed without --follow-symlinks".to_string(),
+                ));
+                return WalkState::Continue;
+            }
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                return WalkState::Continue;
+            }
+            let path = entry.into_path();
+            match path.metadata() {
+                Ok(data) if skip.skips_executable(&data) => {
+                    skipped.lock().unwrap().push((
+                        path,
+                        SkipKind::Executable,
+                        "executable".to_string(),
+                    ));
+                }
+                Ok(_) => files.lock().unwrap().push(path),
+                Err(_) => {}
+            }
+            WalkState::Continue
+        })
+    });
+
+    for (path, kind, reason) in skipped.lock().unwrap().drain(..) {
+        report.skip(&path, kind, reason);
+    }
+    let mut files = std::mem::take(&mut *files.lock().unwrap());
+    files.sort_unstable();
+    files
+}
+
+/// Discovers the files under a directory mounted over the network. Each file
+/// costs a single metadata call, directories that stay unavailable after the
+/// retries are recorded as failures and the walk goes on.
+///
+/// # Arguments
+/// * `root` - The directory to read files from.
+/// * `scan_hidden` - If `true`, hidden files and directories will be included.
+/// * `follow_symlinks` - If `true`, symbolic links are followed.
+/// * `skip` - The `SkipRules` excluding paths.
+/// * `report` - The `RunReport` recording the skipped and failed paths.
+///
+/// # Returns
+/// The discovered files and their modification times.
+pub fn walk_remote(
+    root: &Path,
+    scan_hidden: bool,
+    follow_symlinks: bool,
+    skip: &SkipRules,
+    report: &mut RunReport,
+) -> (Vec<PathBuf>, ModifiedTimes) {
+    let mut files = Vec::new();
+    let mut modified = ModifiedTimes::new();
+    let mut dirs = vec![root.to_path_buf()];
+    let mut symlinks = Symlinks::new(follow_symlinks);
+    symlinks.admit(root, false);
+
+    while let Some(dir) = dirs.pop() {
+        let entries = match remote::retry(|| fs::read_dir(&dir)) {
+            Ok(entries) => entries,
+            Err(err) => {
+                report.fail(&dir, format!("read dir: {err}"));
+                continue;
+            }
+        };
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    report.fail(&dir, format!("read dir entry: {err}"));
+                    continue;
+                }
+            };
+            let path = entry.path();
+            let basename = entry.file_name().to_string_lossy().to_string();
+            if let Some((kind, reason)) = skip_reason(&path, &basename, scan_hidden, skip) {
+                report.skip(&path, kind, reason);
+                continue;
+            }
+
+            // The entry type comes with the directory listing; only symbolic
+            // links need a metadata call to be resolved
+            let is_link = entry.file_type().is_ok_and(|t| t.is_symlink());
+            if let Some((kind, reason)) = symlinks.admit(&path, is_link) {
+                report.skip(&path, kind, reason);
+                continue;
+            }
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                dirs.push(path);
+                continue;
+            }
+            let data = match remote::retry(|| fs::metadata(&path)) {
+                Ok(data) => data,
+                Err(err) => {
+                    report.fail(&path, format!("metadata: {err}"));
+                    continue;
+                }
+            };
+            if data.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            if skip.skips_executable(&data) {
+                report.skip(&path, SkipKind::Executable, "executable");
+                continue;
+            }
+            if let Ok(time) = data.modified() {
+                modified.insert(path.clone(), time);
+            }
+            files.push(path);
+        }
+    }
+
+    files.sort_unstable();
+    (files, modified)
+}
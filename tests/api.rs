@@ -0,0 +1,46 @@
+use indexer::api::{Error, Indexer};
+use indexer::testing::Harness;
+
+use std::error::Error as _;
+use std::fs;
+
+#[test]
+fn a_key_file_on_a_plain_index_is_an_encryption_error() -> anyhow::Result<()> {
+    let harness = Harness::new()?;
+    harness.source().write("notes.txt", "plain text notes")?;
+    harness.source().write("todo.txt", "water the plants")?;
+    harness.index()?;
+    let key_file = harness.source().write("key", "passphrase")?;
+
+    let indexer = Indexer::builder()
+        .index_dir(harness.index_dir())
+        .key_file(key_file)
+        .build()?;
+    match indexer.reader()?.search("notes") {
+        Err(err @ Error::Encryption(_)) => {
+            // The message is the top one, its causes the source chain
+            let cause = err.source().expect("the cause is kept").to_string();
+            assert!(cause.contains("not encrypted"), "{cause}");
+            assert!(!err.to_string().contains(&cause), "{err}");
+        }
+        Err(err) => panic!("expected an encryption error, got {err:?}"),
+        Ok(_) => panic!("searched a plain index with a key file"),
+    }
+    Ok(())
+}
+
+#[test]
+fn a_garbled_document_store_is_a_corrupt_index() -> anyhow::Result<()> {
+    let harness = Harness::new()?;
+    harness.source().write("notes.txt", "plain text notes")?;
+    harness.index()?;
+    fs::write(harness.index_dir().join("docstore.bin"), b"\xffgarbled")?;
+
+    let indexer = Indexer::builder().index_dir(harness.index_dir()).build()?;
+    match indexer.reader()?.search("notes") {
+        Err(Error::CorruptIndex(_)) => {}
+        Err(err) => panic!("expected a corrupt index, got {err:?}"),
+        Ok(_) => panic!("searched a garbled index"),
+    }
+    Ok(())
+}
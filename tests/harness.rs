@@ -0,0 +1,121 @@
+use indexer::analyzer::AnalyzerSettings;
+use indexer::query::EmptyQueryError;
+use indexer::testing::Harness;
+use indexer::{SearchOptions, hit_snippets, list_terms, search_terms};
+
+#[test]
+fn indexed_documents_are_searched_and_served() -> anyhow::Result<()> {
+    let harness = Harness::new()?;
+    harness
+        .source()
+        .write("engine.txt", "a search engine for local directories")?;
+    harness
+        .source()
+        .write("garden.txt", "tomatoes and basil in the garden")?;
+    harness.index()?;
+
+    assert_eq!(
+        harness.search_paths("engine")?,
+        vec![harness.source().path("engine.txt")]
+    );
+    let server = harness.serve()?;
+    assert_eq!(
+        server.query("basil")?,
+        vec![harness.source().path("garden.txt")]
+    );
+    assert!(server.query("volcano")?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn the_server_answers_from_the_last_indexing_run() -> anyhow::Result<()> {
+    let harness = Harness::new()?;
+    // A term found in every document has no weight, so another one is kept
+    harness.source().write("todo.txt", "water the plants")?;
+    harness.source().write("notes.txt", "the first draft")?;
+    harness.index()?;
+    let server = harness.serve()?;
+    assert_eq!(
+        server.query("draft")?,
+        vec![harness.source().path("notes.txt")]
+    );
+
+    harness.source().write("notes.txt", "the final version")?;
+    harness.source().rename("notes.txt", "final.txt")?;
+    harness.index()?;
+    assert!(server.query("draft")?.is_empty());
+    assert_eq!(
+        server.query("version")?,
+        vec![harness.source().path("final.txt")]
+    );
+
+    harness.source().remove("final.txt")?;
+    harness.index()?;
+    assert!(server.query("version")?.is_empty());
+    assert!(harness.search_paths("version")?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn an_empty_query_only_fails_its_own_batch_entry() -> anyhow::Result<()> {
+    let harness = Harness::new()?;
+    harness.source().write("engine.txt", "a search engine")?;
+    harness.source().write("garden.txt", "tomatoes and basil")?;
+    harness.index()?;
+
+    let queries = ["engine".to_string(), "the".to_string(), "basil".to_string()];
+    let results = search_terms(&queries, &harness.index_dir(), &SearchOptions::default())?;
+    let paths = |index: usize| -> Vec<_> {
+        let hits = &results[index].as_ref().unwrap().hits;
+        hits.iter().map(|(path, _)| path.clone()).collect()
+    };
+    assert_eq!(paths(0), vec![harness.source().path("engine.txt")]);
+    assert!(matches!(&results[1], Err(err) if err.is::<EmptyQueryError>()));
+    assert_eq!(paths(2), vec![harness.source().path("garden.txt")]);
+    Ok(())
+}
+
+#[test]
+fn words_are_shown_in_their_original_casing() -> anyhow::Result<()> {
+    let harness = Harness::new()?;
+    harness
+        .source()
+        .write("launch.txt", "NASA launched the probe")?;
+    harness.source().write("garden.txt", "tomatoes and basil")?;
+    harness.index_with(|cfg| {
+        cfg.analyzer = Some(AnalyzerSettings {
+            preserve_case: true,
+            ..Default::default()
+        })
+    })?;
+
+    let terms = list_terms(&harness.index_dir(), Some("=nasa"), None, None)?;
+    assert_eq!(terms, vec![("=NASA".to_string(), 1)]);
+    let launch = harness.source().path("launch.txt");
+    let snippets = hit_snippets(
+        "nasa",
+        &harness.index_dir(),
+        &SearchOptions::default(),
+        &[&launch],
+    )?;
+    assert_eq!(
+        snippets[&launch].marked("[", "]"),
+        "[NASA] launched the probe"
+    );
+    Ok(())
+}
+
+#[test]
+fn reloads_are_only_accepted_over_post() -> anyhow::Result<()> {
+    let harness = Harness::new()?;
+    harness.source().write("engine.txt", "a search engine")?;
+    harness.index()?;
+    let server = harness.serve()?;
+
+    let (status, _) = server.get("/api/reload")?;
+    assert_eq!(status, 404);
+    // The test server reads the index from disk, so there is nothing to reload
+    let (status, _) = server.post("/api/reload", "text/plain", "")?;
+    assert_eq!(status, 409);
+    Ok(())
+}